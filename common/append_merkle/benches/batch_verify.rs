@@ -0,0 +1,50 @@
+//! Compares the serial and parallel verification of batches of data proofs, of which the
+//! crossover point is the batch size from which the parallel verification is faster, i.e. a
+//! proper `parallel_threshold` of `BatchVerifier`.
+//!
+//! Run with `cargo bench -p append_merkle --features parallel-verify --bench batch_verify`.
+
+use append_merkle::{
+    Algorithm, AppendMerkleTree, BatchVerifier, MerkleTreeRead, OptionalHash, Proof, Sha3Algorithm,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const NUM_LEAVES: usize = 1 << 16;
+const BATCH_SIZES: [usize; 8] = [1, 2, 4, 8, 16, 32, 64, 256];
+
+fn batch_verify(c: &mut Criterion) {
+    let data: Vec<Vec<u8>> = (0..NUM_LEAVES)
+        .map(|i| (i as u64).to_be_bytes().repeat(32))
+        .collect();
+    let leaves = data.iter().map(|d| Sha3Algorithm::leaf(d)).collect();
+    let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves, 0, None);
+    let root = merkle.root();
+
+    let mut group = c.benchmark_group("batch verify");
+    for size in BATCH_SIZES {
+        // spread over the tree
+        let batch: Vec<(&[u8], usize, Proof<OptionalHash>)> = (0..size)
+            .map(|i| {
+                let index = i * (NUM_LEAVES / size);
+                (
+                    data[index].as_slice(),
+                    index,
+                    merkle.gen_proof(index).unwrap(),
+                )
+            })
+            .collect();
+
+        for (name, verifier) in [
+            ("serial", BatchVerifier::new(usize::MAX)),
+            ("parallel", BatchVerifier::new(0)),
+        ] {
+            group.bench_with_input(BenchmarkId::new(name, size), &batch, |b, batch| {
+                b.iter(|| verifier.verify::<_, Sha3Algorithm, _>(&root, batch))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, batch_verify);
+criterion_main!(benches);
@@ -0,0 +1,78 @@
+//! Commitments over a batch of files, e.g. many small files uploaded together, of which the
+//! leaves are the roots of the files in order. A file is proved to belong to the batch by a
+//! single proof of its root into the batch root, without the data of the other files.
+//!
+//! The batch tree is built by `AppendMerkleTree`, so it's padded the same way as the file
+//! trees, while the verification only needs the proof and works without `std`.
+
+use crate::{Algorithm, HashElement, Proof};
+use anyhow::{bail, Result};
+
+#[cfg(feature = "std")]
+use crate::{AppendMerkleTree, MerkleTreeRead};
+
+/// Return the depth of the proofs of a batch of `num_files` files.
+pub fn batch_proof_depth(num_files: usize) -> usize {
+    num_files.next_power_of_two().trailing_zeros() as usize
+}
+
+/// Verifies that `file_root` is the file at `index` of the batch of `num_files` files with
+/// `batch_root`. The proof depth must match the batch size, so that an internal node of the
+/// batch tree cannot be passed off as a file root with a shorter proof.
+pub fn verify_batch_inclusion<E: HashElement, A: Algorithm<E>>(
+    batch_root: &E,
+    num_files: usize,
+    file_root: &E,
+    index: usize,
+    proof: &Proof<E>,
+) -> Result<()> {
+    if index >= num_files {
+        bail!(
+            "file index out of bound: index={} num_files={}",
+            index,
+            num_files
+        );
+    }
+    if proof.depth() != batch_proof_depth(num_files) {
+        bail!(
+            "Batch proof depth mismatch: depth={} num_files={}",
+            proof.depth(),
+            num_files
+        );
+    }
+    proof.validate_with_root::<A>(file_root, index, batch_root)
+}
+
+/// The merkle tree of a batch of files, of which the leaves are the file roots.
+#[cfg(feature = "std")]
+pub struct BatchTree<E: HashElement, A: Algorithm<E>> {
+    tree: AppendMerkleTree<E, A>,
+}
+
+#[cfg(feature = "std")]
+impl<E: HashElement, A: Algorithm<E>> BatchTree<E, A> {
+    pub fn new(file_roots: Vec<E>) -> Result<Self> {
+        if file_roots.is_empty() {
+            bail!("empty batch");
+        }
+        if let Some(index) = file_roots.iter().position(|root| root.is_null()) {
+            bail!("null file root in batch: index={}", index);
+        }
+        Ok(Self {
+            tree: AppendMerkleTree::new(file_roots, 0, None),
+        })
+    }
+
+    pub fn root(&self) -> E {
+        self.tree.root()
+    }
+
+    pub fn num_files(&self) -> usize {
+        self.tree.leaves()
+    }
+
+    /// Generates the proof of the root of the file at `index` into the batch root.
+    pub fn gen_inclusion_proof(&self, index: usize) -> Result<Proof<E>> {
+        self.tree.gen_proof(index)
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt::{self, Debug, Display};
+
+/// An internal node of which the stored value does not match the value recomputed from its
+/// children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeMismatch<E> {
+    /// Height of the node, where the leaves are at 0.
+    pub layer: usize,
+    pub index: usize,
+    pub stored: E,
+    /// Null if the node has no children, i.e. the layer is longer than the layer below allows.
+    pub computed: E,
+}
+
+/// All the mismatched internal nodes of a tree, ordered from the bottom layer and by index
+/// within a layer. A corrupt node also mismatches its ancestors, so the first mismatch is the
+/// lowest node to repair.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InconsistencyReport<E> {
+    pub mismatches: Vec<NodeMismatch<E>>,
+}
+
+impl<E> InconsistencyReport<E> {
+    pub fn first(&self) -> Option<&NodeMismatch<E>> {
+        self.mismatches.first()
+    }
+
+    /// `(layer, index)` of all the mismatched nodes.
+    pub fn positions(&self) -> Vec<(usize, usize)> {
+        self.mismatches
+            .iter()
+            .map(|mismatch| (mismatch.layer, mismatch.index))
+            .collect()
+    }
+}
+
+impl<E: Debug> Display for InconsistencyReport<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} inconsistent internal nodes", self.mismatches.len())?;
+        if let Some(first) = self.first() {
+            write!(
+                f,
+                ", first at layer={} index={} stored={:?} computed={:?}",
+                first.layer, first.index, first.stored, first.computed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: Debug> std::error::Error for InconsistencyReport<E> {}
@@ -1,30 +1,93 @@
+//! Append-only merkle trees of the flow, and the proofs of their leaves.
+//!
+//! Without the default `std` feature, only the proofs and their verification are built, on top
+//! of `alloc`, e.g. to verify the proofs of the storage in a light client. The trees, which keep
+//! their nodes in a database, require `std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod batch;
+#[cfg(feature = "std")]
+mod cancel;
+#[cfg(feature = "std")]
+mod consistency;
 mod merkle_tree;
+#[cfg(feature = "std")]
 mod metrics;
+#[cfg(feature = "std")]
 mod node_manager;
 mod proof;
 mod sha3;
+#[cfg(feature = "std")]
+pub mod test_vectors;
+#[cfg(feature = "std")]
+mod wal;
 
+#[cfg(feature = "std")]
 use anyhow::{anyhow, bail, Result};
+#[cfg(feature = "std")]
 use itertools::Itertools;
+#[cfg(feature = "std")]
 use std::cmp::Ordering;
+#[cfg(feature = "std")]
 use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "std")]
 use std::fmt::Debug;
+#[cfg(feature = "std")]
 use std::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::ops::Range;
+#[cfg(feature = "std")]
 use std::sync::Arc;
+#[cfg(feature = "std")]
 use std::time::Instant;
+#[cfg(feature = "std")]
 use tracing::{trace, warn};
 
-use crate::merkle_tree::MerkleTreeWrite;
+#[cfg(feature = "std")]
+use crate::cancel::yield_now;
 pub use crate::merkle_tree::{
-    Algorithm, HashElement, MerkleTreeInitialData, MerkleTreeRead, OptionalHash, ZERO_HASHES,
+    Algorithm, HashElement, MaybeSsz, MerkleTreeInitialData, MerkleTreeRead, MissingProofNodes,
+    OptionalHash, OptionalHashEncoding, ZeroHashes, DOMAIN_SEPARATED_SHA3_ALGORITHM_ID,
+    SHA3_ALGORITHM_ID, ZERO_HASHES,
+};
+#[cfg(feature = "std")]
+use crate::merkle_tree::{MerkleTreeWrite, ProofBuilder};
+#[cfg(feature = "std")]
+pub use crate::node_manager::{
+    EmptyNodeDatabase, NodeCacheFootprint, NodeDatabase, NodeManager, NodeTransaction,
 };
-pub use crate::node_manager::{EmptyNodeDatabase, NodeDatabase, NodeManager, NodeTransaction};
-pub use proof::{Proof, RangeProof};
-pub use sha3::Sha3Algorithm;
+#[cfg(feature = "std")]
+pub use batch::BatchTree;
+pub use batch::{batch_proof_depth, verify_batch_inclusion};
+#[cfg(feature = "std")]
+pub use cancel::CancelToken;
+#[cfg(feature = "std")]
+pub use consistency::{InconsistencyReport, NodeMismatch};
+pub use proof::{
+    verify_data_proof, verify_data_proof_with_max_depth, BatchVerifier, OrderedProof, Proof,
+    ProofDivergence, ProofOrder, RangeProof, RangeProofError, SegmentProof, SolidityCalldata,
+    VerifyOutcome, ZeroLeafPolicy, DEFAULT_MAX_PROOF_DEPTH, DEFAULT_PARALLEL_VERIFY_THRESHOLD,
+};
+pub use sha3::{DomainSeparatedSha3Algorithm, Sha3Algorithm};
+#[cfg(feature = "std")]
+pub use wal::{AppendWal, MemoryWal, WalOp, WalRecord};
+
+/// Number of leaves in a segment, which is the unit to download and verify data.
+pub const LEAVES_PER_SEGMENT: usize = 1024;
+
+/// Maximum height of a tree reconstructed by `AppendMerkleTree::import_and_verify`, i.e. at most
+/// 2^31 leaves, which bounds the nodes allocated for the subtrees of untrusted initial data.
+#[cfg(feature = "std")]
+pub const MAX_IMPORT_TREE_HEIGHT: usize = 32;
 
 // Helper functions for converting between H256 and OptionalHash types
+#[cfg(feature = "std")]
 use ethereum_types::H256;
 
+#[cfg(feature = "std")]
 impl AppendMerkleTree<OptionalHash, Sha3Algorithm> {
     /// Convert a proof of OptionalHash to a proof of H256
     pub fn convert_proof_to_h256(proof: Proof<OptionalHash>) -> Result<Proof<H256>, anyhow::Error> {
@@ -36,7 +99,7 @@ impl AppendMerkleTree<OptionalHash, Sha3Algorithm> {
             })
             .collect();
 
-        Proof::new(lemma?, proof.path().to_vec())
+        Ok(Proof::new(lemma?, proof.path().to_vec())?.with_algorithm_tag(proof.algorithm_tag()))
     }
 
     /// Convert a range proof of OptionalHash to a range proof of H256  
@@ -59,7 +122,7 @@ impl AppendMerkleTree<OptionalHash, Sha3Algorithm> {
             .map(|h| OptionalHash::some(*h))
             .collect();
         let path = proof.path().to_vec();
-        Proof::new(lemma, path)
+        Ok(Proof::new(lemma, path)?.with_algorithm_tag(proof.algorithm_tag()))
     }
 
     /// Convert a RangeProof<H256> to RangeProof<OptionalHash>
@@ -94,6 +157,20 @@ impl AppendMerkleTree<OptionalHash, Sha3Algorithm> {
     }
 }
 
+/// The state of a leaf position in a tree that may be partially filled.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LeafState {
+    /// The leaf hash is known.
+    Real,
+    /// The position is beyond the real leaf count, so it is only covered by padding.
+    Padding,
+    /// The position is within the tree bounds but the leaf hash is not known yet,
+    /// e.g. the leaf is in an appended subtree and not downloaded.
+    Unknown,
+}
+
+#[cfg(feature = "std")]
 pub struct AppendMerkleTree<E: HashElement, A: Algorithm<E>> {
     /// Keep all the nodes in the latest version. `layers[0]` is the layer of leaves.
     node_manager: NodeManager<E>,
@@ -108,9 +185,12 @@ pub struct AppendMerkleTree<E: HashElement, A: Algorithm<E>> {
     /// Used to compute the correct padding hash.
     /// 0 for `pora_chunk_merkle` and 10 for not-first `last_chunk_merkle`.
     leaf_height: usize,
+    /// Records the appends of `append_batch` before they are applied, if set.
+    wal: Option<Arc<dyn AppendWal<E>>>,
     _a: PhantomData<A>,
 }
 
+#[cfg(feature = "std")]
 impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
     pub fn new(leaves: Vec<E>, leaf_height: usize, start_tx_seq: Option<u64>) -> Self {
         let mut merkle = Self {
@@ -119,6 +199,7 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
             root_to_tx_seq_map: HashMap::new(),
             min_depth: None,
             leaf_height,
+            wal: None,
             _a: Default::default(),
         };
         merkle.node_manager.start_transaction();
@@ -148,14 +229,16 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
     pub fn new_with_subtrees(
         node_db: Arc<dyn NodeDatabase<E>>,
         node_cache_capacity: usize,
+        pinned_layers: usize,
         leaf_height: usize,
     ) -> Result<Self> {
         let mut merkle = Self {
-            node_manager: NodeManager::new(node_db, node_cache_capacity)?,
+            node_manager: NodeManager::new(node_db, node_cache_capacity, pinned_layers)?,
             delta_nodes_map: BTreeMap::new(),
             root_to_tx_seq_map: HashMap::new(),
             min_depth: None,
             leaf_height,
+            wal: None,
             _a: Default::default(),
         };
         if merkle.height() == 0 {
@@ -166,6 +249,223 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         Ok(merkle)
     }
 
+    /// Reconstruct a tree from `data` and check that it is consistent with `expected_root`.
+    ///
+    /// Unlike inserting the initial data directly, this never panics on conflicting input.
+    /// The subtree depths are checked to be aligned and within `MAX_IMPORT_TREE_HEIGHT` before
+    /// anything is appended. The complete subtrees covered by `known_leaves` and all `extra_mpt_nodes` are checked
+    /// against the nodes computed from `subtree_list` before they are inserted, and the error
+    /// names the first subtree or node that diverges.
+    pub fn import_and_verify(
+        data: MerkleTreeInitialData<E>,
+        expected_root: &E,
+        leaf_height: usize,
+    ) -> Result<Self> {
+        let max_leaves = 1usize << (MAX_IMPORT_TREE_HEIGHT - 1);
+        let mut leaves = 0usize;
+        for (subtree_index, (subtree_depth, _)) in data.subtree_list.iter().enumerate() {
+            if *subtree_depth == 0 || *subtree_depth > MAX_IMPORT_TREE_HEIGHT {
+                bail!(
+                    "invalid subtree depth: subtree_index={} depth={}",
+                    subtree_index,
+                    subtree_depth
+                );
+            }
+            let subtree_size = 1usize << (subtree_depth - 1);
+            if leaves % subtree_size != 0 {
+                bail!(
+                    "subtree not aligned: subtree_index={} depth={} start_index={}",
+                    subtree_index,
+                    subtree_depth,
+                    leaves
+                );
+            }
+            leaves = match leaves.checked_add(subtree_size) {
+                Some(leaves) if leaves <= max_leaves => leaves,
+                _ => bail!(
+                    "too many leaves: subtree_index={} depth={} start_index={}",
+                    subtree_index,
+                    subtree_depth,
+                    leaves
+                ),
+            };
+        }
+
+        let mut merkle = Self::new(vec![], leaf_height, None);
+        merkle.append_subtree_list(data.subtree_list.clone())?;
+
+        let mut known_leaves = BTreeMap::new();
+        for (index, leaf) in data.known_leaves {
+            if index >= merkle.leaves() {
+                bail!(
+                    "known leaf out of range: index={} leaves={}",
+                    index,
+                    merkle.leaves()
+                );
+            }
+            if leaf.is_null() {
+                bail!("known leaf is null: index={}", index);
+            }
+            match known_leaves.get(&index) {
+                Some(existing) if *existing != leaf => bail!(
+                    "conflicting known leaves: index={} first={:?} second={:?}",
+                    index,
+                    existing,
+                    leaf
+                ),
+                _ => {
+                    known_leaves.insert(index, leaf);
+                }
+            }
+        }
+
+        // Check the known leaves against the subtree roots that they fully cover.
+        let mut subtree_start = 0;
+        for (subtree_index, (subtree_depth, subtree_root)) in data.subtree_list.iter().enumerate() {
+            let subtree_size = 1 << (subtree_depth - 1);
+            let subtree_leaves: Vec<E> = known_leaves
+                .range(subtree_start..subtree_start + subtree_size)
+                .map(|(_, leaf)| leaf.clone())
+                .collect();
+            if subtree_leaves.len() == subtree_size {
+                let computed = Self::root_of_full_subtree(subtree_leaves);
+                if computed != *subtree_root {
+                    bail!(
+                        "subtree root mismatch: subtree_index={} depth={} start_index={} \
+                        given={:?} computed_from_leaves={:?}",
+                        subtree_index,
+                        subtree_depth,
+                        subtree_start,
+                        subtree_root,
+                        computed
+                    );
+                }
+            }
+            subtree_start += subtree_size;
+        }
+        for (index, leaf) in known_leaves {
+            merkle.fill_leaf(index, leaf);
+        }
+
+        merkle.insert_extra_mpt_nodes(data.extra_mpt_nodes, true)?;
+
+        if merkle.root() != *expected_root {
+            bail!(
+                "root mismatch after import: expected={:?} computed={:?}",
+                expected_root,
+                merkle.root()
+            );
+        }
+        Ok(merkle)
+    }
+
+    /// Insert `MerkleTreeInitialData::extra_mpt_nodes` after the tree is constructed.
+    ///
+    /// If `verify` is false, the nodes are trusted and inserted as is. Otherwise, each node is
+    /// checked against the nodes computable from its children and its sibling before it's
+    /// inserted, and the first contradicting node is rejected. The nodes before it are kept.
+    pub fn insert_extra_mpt_nodes(
+        &mut self,
+        nodes: Vec<(usize, usize, E)>,
+        verify: bool,
+    ) -> Result<()> {
+        self.node_manager.start_transaction();
+        for (layer, position, node) in nodes {
+            if verify {
+                if let Err(e) = self.check_extra_node(layer, position, &node) {
+                    self.node_manager.commit();
+                    return Err(e);
+                }
+                if !self.node(layer, position).is_null() {
+                    continue;
+                }
+            }
+            self.update_node(layer, position, node);
+        }
+        self.node_manager.commit();
+        Ok(())
+    }
+
+    /// Fill the `null` nodes with the known nodes of `other`, which is another reconstruction of
+    /// the same tree, e.g. with the data downloaded from different peers.
+    ///
+    /// The known nodes of both trees must agree, and so must the nodes computed from the merged
+    /// children. On conflict, the error reports the first conflicting node and `self` is not
+    /// changed.
+    pub fn merge(&mut self, other: &impl MerkleTreeRead<E = E>) -> Result<()> {
+        if self.height() != other.height() || self.leaves() != other.leaves() {
+            bail!(
+                "cannot merge trees of different sizes: leaves={} other_leaves={}",
+                self.leaves(),
+                other.leaves()
+            );
+        }
+
+        // The nodes that are `null` in `self` but known after merging.
+        let mut new_nodes: BTreeMap<(usize, usize), E> = BTreeMap::new();
+        for layer in 0..self.height() {
+            for position in 0..self.layer_len(layer) {
+                let node = self.node(layer, position);
+                let other_node = other.node(layer, position);
+                if !node.is_null() && !other_node.is_null() && node != other_node {
+                    bail!(
+                        "conflicting node: layer={} position={} self={:?} other={:?}",
+                        layer,
+                        position,
+                        node,
+                        other_node
+                    );
+                }
+                let mut merged = if node.is_null() {
+                    other_node
+                } else {
+                    node.clone()
+                };
+
+                if layer > 0 {
+                    let child = |pos: usize| match new_nodes.get(&(layer - 1, pos)) {
+                        Some(node) => node.clone(),
+                        None => self.node(layer - 1, pos),
+                    };
+                    let left = child(position * 2);
+                    let computed = if left.is_null() {
+                        None
+                    } else if position * 2 + 1 == self.layer_len(layer - 1) {
+                        Some(A::parent_single(&left, layer - 1 + self.leaf_height))
+                    } else {
+                        let right = child(position * 2 + 1);
+                        (!right.is_null()).then(|| A::parent(&left, &right))
+                    };
+                    if let Some(computed) = computed {
+                        if merged.is_null() {
+                            merged = computed;
+                        } else if merged != computed {
+                            bail!(
+                                "merged node mismatches its children: layer={} position={} \
+                                merged={:?} computed={:?}",
+                                layer,
+                                position,
+                                merged,
+                                computed
+                            );
+                        }
+                    }
+                }
+
+                if node.is_null() && !merged.is_null() {
+                    new_nodes.insert((layer, position), merged);
+                }
+            }
+        }
+
+        self.node_manager.start_transaction();
+        for ((layer, position), node) in new_nodes {
+            self.update_node(layer, position, node);
+        }
+        self.node_manager.commit();
+        Ok(())
+    }
+
     /// This is only used for the last chunk, so `leaf_height` is always 0 so far.
     pub fn new_with_depth(leaves: Vec<E>, depth: usize, start_tx_seq: Option<u64>) -> Self {
         let mut node_manager = NodeManager::new_dummy();
@@ -179,6 +479,7 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
                 root_to_tx_seq_map: HashMap::new(),
                 min_depth: Some(depth),
                 leaf_height: 0,
+                wal: None,
                 _a: Default::default(),
             };
             for _ in 0..depth {
@@ -202,6 +503,7 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
                 root_to_tx_seq_map: HashMap::new(),
                 min_depth: Some(depth),
                 leaf_height: 0,
+                wal: None,
                 _a: Default::default(),
             };
             merkle.node_manager.add_layer();
@@ -230,6 +532,8 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
 
         self.node_manager.commit();
         metrics::APPEND.update_since(start_time);
+        #[cfg(any(test, feature = "verify-appends"))]
+        self.verify_appended_leaves(self.leaves() - 1);
     }
 
     pub fn append_list(&mut self, leaf_list: Vec<E>) {
@@ -244,6 +548,8 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         self.recompute_after_append_leaves(start_index);
         self.node_manager.commit();
         metrics::APPEND_LIST.update_since(start_time);
+        #[cfg(any(test, feature = "verify-appends"))]
+        self.verify_appended_leaves(start_index);
     }
 
     /// Append a leaf list by providing their intermediate node hash.
@@ -263,6 +569,8 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         self.recompute_after_append_subtree(start_index, subtree_depth - 1);
         self.node_manager.commit();
         metrics::APPEND_SUBTREE.update_since(start_time);
+        #[cfg(any(test, feature = "verify-appends"))]
+        self.verify_appended_subtree(start_index, subtree_depth);
 
         Ok(())
     }
@@ -274,13 +582,21 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
             bail!("subtree_list contains null");
         }
         self.node_manager.start_transaction();
+        #[cfg(any(test, feature = "verify-appends"))]
+        let mut appended = vec![];
         for (subtree_depth, subtree_root) in subtree_list {
             let start_index = self.leaves();
             self.append_subtree_inner(subtree_depth, subtree_root)?;
             self.recompute_after_append_subtree(start_index, subtree_depth - 1);
+            #[cfg(any(test, feature = "verify-appends"))]
+            appended.push((start_index, subtree_depth));
         }
         self.node_manager.commit();
         metrics::APPEND_SUBTREE_LIST.update_since(start_time);
+        #[cfg(any(test, feature = "verify-appends"))]
+        for (start_index, subtree_depth) in appended {
+            self.verify_appended_subtree(start_index, subtree_depth);
+        }
 
         Ok(())
     }
@@ -437,6 +753,210 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         }
     }
 
+    /// Generate the proof of the leaf computed from `data` with `A::leaf`.
+    ///
+    /// There is no index from leaves to positions, so this scans all the leaves and costs
+    /// O(n) for a tree of n leaves. It fails if the leaf is not found or appears more than once.
+    pub fn gen_proof_for_data(&self, data: &[u8]) -> Result<Proof<E>> {
+        let leaf = A::leaf(data);
+        let mut found = None;
+        for index in 0..self.leaves() {
+            if self.node(0, index) == leaf {
+                if let Some(first) = found {
+                    bail!(
+                        "ambiguous leaf for data: leaf={:?} first={} second={}",
+                        leaf,
+                        first,
+                        index
+                    );
+                }
+                found = Some(index);
+            }
+        }
+        match found {
+            Some(index) => self.gen_proof(index),
+            None => bail!("leaf not found for data: leaf={:?}", leaf),
+        }
+    }
+
+    /// Generate the proof of the last known leaf, which is the current tip of the tree.
+    /// The position of the leaf is given by `Proof::position`.
+    pub fn gen_tip_proof(&self) -> Result<Proof<E>> {
+        match (0..self.leaves())
+            .rev()
+            .find(|index| !self.node(0, *index).is_null())
+        {
+            Some(index) => self.gen_proof(index),
+            None => bail!("no known leaf to generate the tip proof"),
+        }
+    }
+
+    /// Generate a single proof of all the leaves in the segment, which covers
+    /// `[segment_index * LEAVES_PER_SEGMENT, end_index)` and is truncated at the last leaf.
+    pub fn gen_segment_proof(&self, segment_index: usize) -> Result<SegmentProof<E>> {
+        let start_index = segment_index * LEAVES_PER_SEGMENT;
+        if start_index >= self.leaves() {
+            bail!(
+                "segment index out of bound: segment_index={} total_leaves={}",
+                segment_index,
+                self.leaves()
+            );
+        }
+        let end_index = (start_index + LEAVES_PER_SEGMENT).min(self.leaves());
+        Ok(SegmentProof {
+            segment_index,
+            start_index,
+            end_index,
+            proof: self.gen_range_proof(start_index, end_index)?,
+        })
+    }
+
+    /// Generate the proof of the leaf against the current tree, and locate where it diverges
+    /// from the caller supplied `expected_root`, e.g. the root on chain.
+    ///
+    /// If `expected_root` is the root of a history version, the divergence is the first layer
+    /// at which the derived node differs from the node at that version. Otherwise, only the
+    /// root layer is known to diverge. The divergence is `None` if the roots are the same.
+    pub fn gen_proof_with_root(
+        &self,
+        leaf_index: usize,
+        expected_root: &E,
+    ) -> Result<(Proof<E>, Option<ProofDivergence<E>>)> {
+        let proof = self.gen_proof(leaf_index)?;
+        if proof.root() == *expected_root {
+            return Ok((proof, None));
+        }
+
+        let reference = self
+            .tx_seq_at_root(expected_root)
+            .and_then(|tx_seq| self.at_version(tx_seq)?.gen_proof(leaf_index));
+        let divergence = match reference {
+            Ok(reference) => proof.first_divergence::<A>(&reference),
+            Err(_) => Some(ProofDivergence {
+                layer: proof.path().len(),
+                derived: Some(proof.root()),
+                expected: Some(expected_root.clone()),
+            }),
+        };
+        Ok((proof, divergence))
+    }
+
+    /// Generate the proof of a leaf like `gen_proof`, but yield to the executor at every layer
+    /// boundary and fail once `cancel` is cancelled, so that a proof request abandoned by the
+    /// client does not keep the CPU busy on a huge tree. The tree is never modified, so
+    /// cancelling or dropping the future leaves no partial state.
+    pub async fn gen_proof_cancellable(
+        &self,
+        leaf_index: usize,
+        cancel: &CancelToken,
+    ) -> Result<Proof<E>> {
+        let mut builder = ProofBuilder::new(self, leaf_index)?;
+        while !builder.is_done(self) {
+            if cancel.is_cancelled() {
+                bail!("Proof generation cancelled: leaf_index={}", leaf_index);
+            }
+            builder.step(self);
+            yield_now().await;
+        }
+        builder.finish(self)
+    }
+
+    pub fn leaf_state(&self, index: usize) -> LeafState {
+        if index >= self.leaves() {
+            LeafState::Padding
+        } else if self.node(0, index).is_null() {
+            LeafState::Unknown
+        } else {
+            LeafState::Real
+        }
+    }
+
+    /// Return the indices of the unknown leaves in `[start, end)`, e.g. to plan the download
+    /// or repair of a partially filled tree. The padding positions beyond the real leaf count
+    /// are excluded. Use `null_leaf_runs_in_range` to avoid a huge vector for a large range.
+    pub fn null_leaves_in_range(&self, start: usize, end: usize) -> Vec<usize> {
+        self.null_leaf_runs_in_range(start, end)
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Return the unknown leaves in `[start, end)` as the runs of consecutive indices, ordered
+    /// from left to right.
+    pub fn null_leaf_runs_in_range(&self, start: usize, end: usize) -> Vec<Range<usize>> {
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for index in start..end.min(self.leaves()) {
+            if !self.node(0, index).is_null() {
+                continue;
+            }
+            match runs.last_mut() {
+                Some(run) if run.end == index => run.end += 1,
+                _ => runs.push(index..index + 1),
+            }
+        }
+        runs
+    }
+
+    /// Return `true` if all the nodes of the tree are known, so `gen_proof` succeeds for every
+    /// leaf. Unlike counting the known leaves, this also detects the gaps left by appended
+    /// subtrees. An empty tree is not complete since it has no root.
+    pub fn is_complete(&self) -> bool {
+        if self.leaves() == 0 {
+            return false;
+        }
+        (0..self.height()).all(|height| {
+            (0..self.layer_len(height)).all(|index| !self.node(height, index).is_null())
+        })
+    }
+
+    /// Return `true` if all the nodes of the perfect subtrees within the leaves `[start, end)`
+    /// are known, with the same layer-wise null scan as `is_complete` limited to the range.
+    pub fn is_range_complete(&self, start: usize, end: usize) -> bool {
+        if start >= end || end > self.leaves() {
+            return false;
+        }
+        (0..self.height()).all(|height| {
+            // The nodes of which all the leaves are in the range.
+            let first = (start + (1 << height) - 1) >> height;
+            let last = end >> height;
+            (first..last).all(|index| !self.node(height, index).is_null())
+        })
+    }
+
+    /// Return the `(height, root)` of the perfect subtrees (Merkle Mountain Range peaks) that
+    /// cover all the leaves, ordered from left to right with decreasing heights. A leaf is at
+    /// height 0, so the subtree depth as in `subtree_list` is `height + 1`. A peak root is
+    /// `null` if it's unknown.
+    ///
+    /// The tree root is computed from the peaks from right to left: extend the accumulated
+    /// node with `A::parent_single` to the height of the next peak and combine them with
+    /// `A::parent`, and finally extend it with `A::parent_single` to the root height.
+    pub fn peaks(&self) -> Vec<(usize, E)> {
+        let leaves = self.leaves();
+        let mut peaks = Vec::new();
+        let mut start_index = 0;
+        for height in (0..self.height()).rev() {
+            if leaves & (1 << height) != 0 {
+                peaks.push((height, self.node(height, start_index >> height)));
+                start_index += 1 << height;
+            }
+        }
+        peaks
+    }
+
+    /// Return the number of leaves to append before the right-most peak merges with its left
+    /// neighbour into a larger perfect subtree, which could be flushed as a whole. It's the
+    /// lowest set bit of the leaf count, so for a perfect tree of `2^k` leaves it's `2^k` to
+    /// reach the next power of two, and for an empty tree it's 1.
+    pub fn leaves_until_next_flush(&self) -> usize {
+        let leaves = self.leaves();
+        if leaves == 0 {
+            1
+        } else {
+            1 << leaves.trailing_zeros()
+        }
+    }
+
     /// Return a list of subtrees that can be used to rebuild the tree.
     pub fn get_subtrees(&self) -> Vec<(usize, E)> {
         let mut next_index = 0;
@@ -461,8 +981,99 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         }
         subtree_list
     }
+
+    /// Memory used by the cached nodes, including the pinned top layers.
+    pub fn node_cache_footprint(&self) -> NodeCacheFootprint {
+        self.node_manager.footprint()
+    }
+
+    /// Keep only the right-most spine of the tree in memory, and load the nodes in completed
+    /// subtrees from db, so that memory does not grow with appended leaves.
+    pub fn set_flush_completed_subtrees(&mut self, enabled: bool) {
+        self.node_manager.set_flush_completed_subtrees(enabled);
+    }
+
+    /// Record the appends of `append_batch` into `wal` before they are applied, so that the
+    /// appends of a batch interrupted by a crash are recovered by `replay_wal` on startup.
+    pub fn set_wal(&mut self, wal: Arc<dyn AppendWal<E>>) {
+        self.wal = Some(wal);
+    }
+
+    /// Append a batch of leaf lists and subtrees. The whole batch is recorded in the WAL first
+    /// if set, and the batch is durable once the WAL is written.
+    pub fn append_batch(&mut self, ops: Vec<WalOp<E>>) -> Result<()> {
+        let mut leaves_before = self.leaves();
+        let mut records = Vec::with_capacity(ops.len());
+        for op in ops {
+            op.check(leaves_before)?;
+            let leaves = op.leaves();
+            records.push(WalRecord { leaves_before, op });
+            leaves_before += leaves;
+        }
+
+        if let Some(wal) = &self.wal {
+            wal.write(&records)?;
+        }
+        for record in records {
+            self.apply_wal_op(record.op)?;
+        }
+        Ok(())
+    }
+
+    /// Commit the version of `tx_seq` and truncate the WAL, since the recorded appends have
+    /// all been applied.
+    pub fn checkpoint(&mut self, tx_seq: Option<u64>) -> Result<()> {
+        self.commit(tx_seq);
+        if let Some(wal) = &self.wal {
+            wal.truncate()?;
+        }
+        Ok(())
+    }
+
+    /// Apply the appends recorded in the WAL but missing in the tree, e.g. after a crash in the
+    /// middle of a batch, and return the number of the applied appends. The appends already in
+    /// the tree are skipped, so the replay is idempotent.
+    pub fn replay_wal(&mut self) -> Result<usize> {
+        let wal = match &self.wal {
+            Some(wal) => wal.clone(),
+            None => return Ok(0),
+        };
+        let mut applied = 0;
+        for record in wal.read()? {
+            let leaves = self.leaves();
+            record.op.check(record.leaves_before)?;
+            let applied_before = match record.op.leaves() {
+                // An update is idempotent, so it's applied again unless followed by appends.
+                0 => record.leaves_before < leaves,
+                n => record.leaves_before + n <= leaves,
+            };
+            if applied_before {
+                continue;
+            }
+            if record.leaves_before != leaves {
+                bail!(
+                    "WAL record does not follow the tree: leaves_before={} leaves={}",
+                    record.leaves_before,
+                    leaves
+                );
+            }
+            self.apply_wal_op(record.op)?;
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    fn apply_wal_op(&mut self, op: WalOp<E>) -> Result<()> {
+        match op {
+            WalOp::AppendList(leaves) => self.append_list(leaves),
+            WalOp::AppendSubtree { depth, root } => self.append_subtree(depth, root)?,
+            WalOp::UpdateLast(leaf) => self.update_last(leaf),
+        }
+        Ok(())
+    }
 }
 
+#[cfg(feature = "std")]
 impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
     pub fn commit(&mut self, tx_seq: Option<u64>) {
         if let Some(tx_seq) = tx_seq {
@@ -489,6 +1100,173 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         }
     }
 
+    /// Only for debugging: check the proofs of the leaves from `start_index` against the
+    /// current root. The leaves without enough known nodes to generate proofs are skipped.
+    #[cfg(any(test, feature = "verify-appends"))]
+    fn verify_appended_leaves(&self, start_index: usize) {
+        let root = self.root();
+        for index in start_index..self.leaves() {
+            if self.has_unknown_proof_nodes(index) {
+                continue;
+            }
+            let leaf = self.node(0, index);
+            let proof = match self.gen_proof(index) {
+                Ok(proof) => proof,
+                Err(e) => panic!("Failed to generate proof: index={} err={:?}", index, e),
+            };
+            if let Err(e) = proof.validate::<A>(&leaf, index) {
+                panic!(
+                    "Invalid proof of appended leaf: index={} err={:?}",
+                    index, e
+                );
+            }
+            assert_eq!(proof.root(), root, "Proof root mismatch: index={}", index);
+        }
+    }
+
+    /// Only for debugging: check that the root of the subtree appended at `start_index` hashes
+    /// up to the current root through the stored nodes. The check stops at an unknown sibling,
+    /// as the nodes above it are unknown too.
+    #[cfg(any(test, feature = "verify-appends"))]
+    fn verify_appended_subtree(&self, start_index: usize, subtree_depth: usize) {
+        let mut height = subtree_depth - 1;
+        let mut index = start_index >> height;
+        let mut node = self.node(height, index);
+        while height < self.height() - 1 {
+            let sibling = index ^ 1;
+            node = if sibling >= self.layer_len(height) {
+                A::parent_single(&node, height + self.leaf_height)
+            } else {
+                let sibling_node = self.node(height, sibling);
+                if sibling_node.is_null() {
+                    return;
+                }
+                if index % 2 == 0 {
+                    A::parent(&node, &sibling_node)
+                } else {
+                    A::parent(&sibling_node, &node)
+                }
+            };
+            height += 1;
+            index >>= 1;
+            assert_eq!(
+                node,
+                self.node(height, index),
+                "Invalid node above appended subtree: start_index={} height={}",
+                start_index,
+                height
+            );
+        }
+    }
+
+    #[cfg(any(test, feature = "verify-appends"))]
+    fn has_unknown_proof_nodes(&self, leaf_index: usize) -> bool {
+        let mut index_in_layer = leaf_index;
+        for height in 0..self.height() {
+            let sibling = index_in_layer ^ 1;
+            if self.node(height, index_in_layer).is_null()
+                || (sibling < self.layer_len(height) && self.node(height, sibling).is_null())
+            {
+                return true;
+            }
+            index_in_layer >>= 1;
+        }
+        false
+    }
+
+    /// Recompute all the internal nodes from the leaves and overwrite the stored ones, e.g. to
+    /// recover from corrupt internal nodes without downloading the data again, and return the
+    /// rebuilt root. All the leaves must be known.
+    pub fn rebuild_internal_nodes(&mut self) -> Result<E> {
+        if self.leaves() == 0 {
+            bail!("Cannot rebuild an empty tree");
+        }
+        let mut layer: Vec<E> = self.node_manager.get_nodes(0, 0, self.leaves()).collect();
+        if let Some(index) = layer.iter().position(|leaf| leaf.is_null()) {
+            bail!("Cannot rebuild with an unknown leaf: index={}", index);
+        }
+
+        self.node_manager.start_transaction();
+        for height in 1..self.height() {
+            layer = layer
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => A::parent(left, right),
+                    [single] => A::parent_single(single, height - 1 + self.leaf_height),
+                    _ => unreachable!(),
+                })
+                .collect();
+            assert_eq!(layer.len(), self.layer_len(height));
+            for (index, node) in layer.iter().enumerate() {
+                self.update_node(height, index, node.clone());
+            }
+        }
+        self.node_manager.commit();
+        Ok(self.root())
+    }
+
+    /// Recompute every internal node from its children and compare with the stored one, e.g.
+    /// to detect disk corruption or bugs in updating the tree, and report all the mismatched
+    /// nodes. The nodes that are unknown or have an unknown child are skipped, and the padding
+    /// positions out of the layers are never stored, so not checked.
+    ///
+    /// With the `parallel-verify` feature, the nodes of each layer are recomputed in parallel.
+    pub fn verify_tree_consistency(&self) -> std::result::Result<(), InconsistencyReport<E>> {
+        if self.height() == 0 {
+            return Ok(());
+        }
+        let mut mismatches = vec![];
+        let mut children: Vec<E> = self
+            .node_manager
+            .get_nodes(0, 0, self.layer_len(0))
+            .collect();
+        for height in 1..self.height() {
+            let parents: Vec<E> = self
+                .node_manager
+                .get_nodes(height, 0, self.layer_len(height))
+                .collect();
+            let padding_height = height - 1 + self.leaf_height;
+            let check = |index: usize| {
+                let stored = &parents[index];
+                if stored.is_null() {
+                    return None;
+                }
+                let computed = match (children.get(2 * index), children.get(2 * index + 1)) {
+                    (Some(left), Some(right)) if left.is_null() || right.is_null() => return None,
+                    (Some(left), Some(right)) => A::parent(left, right),
+                    (Some(single), None) if single.is_null() => return None,
+                    (Some(single), None) => A::parent_single(single, padding_height),
+                    (None, _) => E::null(),
+                };
+                (computed != *stored).then(|| NodeMismatch {
+                    layer: height,
+                    index,
+                    stored: stored.clone(),
+                    computed,
+                })
+            };
+
+            #[cfg(feature = "parallel-verify")]
+            mismatches.extend({
+                use rayon::prelude::*;
+                (0..parents.len())
+                    .into_par_iter()
+                    .filter_map(check)
+                    .collect::<Vec<_>>()
+            });
+            #[cfg(not(feature = "parallel-verify"))]
+            mismatches.extend((0..parents.len()).filter_map(check));
+
+            children = parents;
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(InconsistencyReport { mismatches })
+        }
+    }
+
     fn before_extend_layer(&mut self, height: usize) {
         if height == self.height() {
             self.node_manager.add_layer()
@@ -677,6 +1455,90 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         Ok(())
     }
 
+    /// Discard the leaves at or above `leaf_count`, so the tree is the same as a tree that
+    /// only ever had `leaf_count` leaves. The removed nodes are also deleted from the database,
+    /// and the versions that include the removed leaves are dropped.
+    ///
+    /// This fails without changing the tree if a node on the new right edge cannot be
+    /// computed, e.g. when truncating into an appended subtree whose leaves are unknown.
+    pub fn truncate_to(&mut self, leaf_count: usize) -> Result<()> {
+        if leaf_count > self.leaves() {
+            bail!(
+                "truncate beyond the tree size: leaf_count={} leaves={}",
+                leaf_count,
+                self.leaves()
+            );
+        }
+        if leaf_count == self.leaves() {
+            return Ok(());
+        }
+        if leaf_count == 0 {
+            self.reset();
+        } else {
+            let needed_height = leaf_count.next_power_of_two().trailing_zeros() as usize + 1;
+            let new_height = needed_height.max(self.min_depth.unwrap_or(0));
+
+            // Compute the new right most nodes before changing the tree.
+            let mut right_most_nodes = Vec::with_capacity(new_height);
+            for height in 0..new_height {
+                let layer_len = (leaf_count - 1) / (1 << height) + 1;
+                let pos = layer_len - 1;
+                let node = if (pos + 1) << height <= leaf_count {
+                    // A full subtree that is not changed.
+                    self.node(height, pos)
+                } else {
+                    let child_len = right_most_nodes.len();
+                    let (_, last_child): &(usize, E) = &right_most_nodes[child_len - 1];
+                    if 2 * pos + 1 < (leaf_count - 1) / (1 << (height - 1)) + 1 {
+                        let left = self.node(height - 1, 2 * pos);
+                        if left.is_null() || last_child.is_null() {
+                            E::null()
+                        } else {
+                            A::parent(&left, last_child)
+                        }
+                    } else if last_child.is_null() {
+                        E::null()
+                    } else {
+                        A::parent_single(last_child, height - 1 + self.leaf_height)
+                    }
+                };
+                right_most_nodes.push((pos, node));
+            }
+            if right_most_nodes.last().expect("non-empty").1.is_null() {
+                bail!(
+                    "unknown nodes to truncate the tree: leaf_count={}",
+                    leaf_count
+                );
+            }
+
+            self.node_manager.start_transaction();
+            for height in (new_height..self.height()).rev() {
+                self.node_manager.truncate_layer(height);
+            }
+            for (height, (pos, node)) in right_most_nodes.into_iter().enumerate() {
+                self.node_manager.truncate_nodes(height, pos + 1);
+                if self.node(height, pos) != node {
+                    self.update_node(height, pos, node);
+                }
+            }
+            self.node_manager.commit();
+        }
+
+        // Drop the versions that include the removed leaves.
+        let removed_versions: Vec<u64> = self
+            .delta_nodes_map
+            .iter()
+            .filter(|(_, nodes)| nodes.height() != 0 && nodes.layer_len(0) > leaf_count)
+            .map(|(tx_seq, _)| *tx_seq)
+            .collect();
+        for tx_seq in removed_versions {
+            if let Some(nodes) = self.delta_nodes_map.remove(&tx_seq) {
+                self.root_to_tx_seq_map.remove(nodes.root());
+            }
+        }
+        Ok(())
+    }
+
     pub fn tx_seq_at_root(&self, root_hash: &E) -> Result<u64> {
         self.root_to_tx_seq_map
             .get(root_hash)
@@ -696,6 +1558,7 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
             node_manager: &self.node_manager,
             delta_nodes,
             leaf_height: self.leaf_height,
+            algorithm_tag: self.algorithm_tag(),
         })
     }
 
@@ -741,15 +1604,113 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         }
         unreachable!("root is always available")
     }
-}
 
-#[derive(Clone, Debug)]
-struct DeltaNodes<E: HashElement> {
-    /// The right most nodes in a layer and its position.
-    right_most_nodes: Vec<(usize, E)>,
-}
+    /// Compute the root of a subtree whose leaf count is a power of two.
+    fn root_of_full_subtree(mut layer: Vec<E>) -> E {
+        while layer.len() > 1 {
+            layer = layer
+                .chunks(2)
+                .map(|pair| A::parent(&pair[0], &pair[1]))
+                .collect();
+        }
+        layer.remove(0)
+    }
 
-impl<E: HashElement> DeltaNodes<E> {
+    /// Check that a node from `MerkleTreeInitialData::extra_mpt_nodes` agrees with the nodes
+    /// that are already known around its position.
+    fn check_extra_node(&self, layer: usize, position: usize, node: &E) -> Result<()> {
+        if layer >= self.height() || position >= self.layer_len(layer) {
+            bail!(
+                "extra node out of range: layer={} position={} height={}",
+                layer,
+                position,
+                self.height()
+            );
+        }
+        if node.is_null() {
+            bail!("extra node is null: layer={} position={}", layer, position);
+        }
+        let existing = self.node(layer, position);
+        if !existing.is_null() && existing != *node {
+            bail!(
+                "extra node conflicts with the tree: layer={} position={} given={:?} computed={:?}",
+                layer,
+                position,
+                node,
+                existing
+            );
+        }
+        // Compare with the parent computed from its children.
+        if layer > 0 {
+            if let Some(computed) = self.known_parent(layer - 1, position * 2, None) {
+                if computed != *node {
+                    bail!(
+                        "extra node mismatches its children: layer={} position={} given={:?} \
+                        computed={:?}",
+                        layer,
+                        position,
+                        node,
+                        computed
+                    );
+                }
+            }
+        }
+        // Compare the parent computed with its sibling to the known parent.
+        if layer + 1 < self.height() {
+            let parent = self.node(layer + 1, position / 2);
+            if let Some(computed) = self.known_parent(layer, position, Some(node)) {
+                if !parent.is_null() && computed != parent {
+                    bail!(
+                        "extra node mismatches its parent: layer={} position={} given={:?} \
+                        parent={:?} computed_parent={:?}",
+                        layer,
+                        position,
+                        node,
+                        parent,
+                        computed
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compute the parent of the node at `(layer, position)` if both children are known.
+    /// `replaced` is used as the value at `(layer, position)` instead of the tree node.
+    fn known_parent(&self, layer: usize, position: usize, replaced: Option<&E>) -> Option<E> {
+        let left_position = position & !1;
+        if left_position >= self.layer_len(layer) {
+            return None;
+        }
+        let get = |pos: usize| match replaced {
+            Some(node) if pos == position => node.clone(),
+            _ => self.node(layer, pos),
+        };
+        let left = get(left_position);
+        if left.is_null() {
+            return None;
+        }
+        if left_position + 1 == self.layer_len(layer) {
+            return Some(A::parent_single(&left, layer + self.leaf_height));
+        }
+        let right = get(left_position + 1);
+        if right.is_null() {
+            None
+        } else {
+            Some(A::parent(&left, &right))
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+struct DeltaNodes<E: HashElement> {
+    /// The right most nodes in a layer and its position.
+    right_most_nodes: Vec<(usize, E)>,
+}
+
+#[cfg(feature = "std")]
+impl<E: HashElement> DeltaNodes<E> {
     fn new(right_most_nodes: Vec<(usize, E)>) -> Self {
         Self { right_most_nodes }
     }
@@ -777,6 +1738,7 @@ impl<E: HashElement> DeltaNodes<E> {
     }
 }
 
+#[cfg(feature = "std")]
 pub struct HistoryTree<'m, E: HashElement> {
     /// A reference to the global tree nodes.
     node_manager: &'m NodeManager<E>,
@@ -785,11 +1747,17 @@ pub struct HistoryTree<'m, E: HashElement> {
     delta_nodes: &'m DeltaNodes<E>,
 
     leaf_height: usize,
+    algorithm_tag: Option<u8>,
 }
 
+#[cfg(feature = "std")]
 impl<E: HashElement, A: Algorithm<E>> MerkleTreeRead for AppendMerkleTree<E, A> {
     type E = E;
 
+    fn algorithm_tag(&self) -> Option<u8> {
+        (A::ID != SHA3_ALGORITHM_ID).then_some(A::ID)
+    }
+
     fn node(&self, layer: usize, index: usize) -> Self::E {
         self.node_manager
             .get_node(layer, index)
@@ -809,6 +1777,7 @@ impl<E: HashElement, A: Algorithm<E>> MerkleTreeRead for AppendMerkleTree<E, A>
     }
 }
 
+#[cfg(feature = "std")]
 impl<'a, E: HashElement> MerkleTreeRead for HistoryTree<'a, E> {
     type E = E;
     fn node(&self, layer: usize, index: usize) -> Self::E {
@@ -829,11 +1798,16 @@ impl<'a, E: HashElement> MerkleTreeRead for HistoryTree<'a, E> {
         self.delta_nodes.layer_len(layer_height)
     }
 
+    fn algorithm_tag(&self) -> Option<u8> {
+        self.algorithm_tag
+    }
+
     fn padding_node(&self, height: usize) -> Self::E {
         E::end_pad(height + self.leaf_height)
     }
 }
 
+#[cfg(feature = "std")]
 impl<E: HashElement, A: Algorithm<E>> MerkleTreeWrite for AppendMerkleTree<E, A> {
     type E = E;
 
@@ -855,26 +1829,117 @@ macro_rules! ensure_eq {
     ($given:expr, $expected:expr) => {
         ensure!(
             $given == $expected,
-            format!(
-                "equal check fails! {}:{}: {}={:?}, {}={:?}",
-                file!(),
-                line!(),
-                stringify!($given),
-                $given,
-                stringify!($expected),
-                $expected,
-            )
+            "equal check fails! {}:{}: {}={:?}, {}={:?}",
+            file!(),
+            line!(),
+            stringify!($given),
+            $given,
+            stringify!($expected),
+            $expected,
         );
     };
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use crate::merkle_tree::{MerkleTreeRead, OptionalHash};
+    use crate::merkle_tree::{
+        Algorithm, HashElement, MerkleTreeInitialData, MerkleTreeRead, MerkleTreeWrite,
+        MissingProofNodes, OptionalHash, OptionalHashEncoding, SHA3_ALGORITHM_ID,
+    };
 
     use crate::sha3::Sha3Algorithm;
-    use crate::AppendMerkleTree;
-    use ethereum_types::H256;
+    use crate::{
+        batch_proof_depth, verify_batch_inclusion, verify_data_proof,
+        verify_data_proof_with_max_depth, AppendMerkleTree, AppendWal, BatchTree, BatchVerifier,
+        CancelToken, EmptyNodeDatabase, LeafState, MemoryWal, NodeDatabase, NodeManager,
+        NodeTransaction, Proof, ProofDivergence, ProofOrder, RangeProof, RangeProofError,
+        VerifyOutcome, WalOp, WalRecord, ZeroLeafPolicy, DEFAULT_MAX_PROOF_DEPTH,
+        DEFAULT_PARALLEL_VERIFY_THRESHOLD, LEAVES_PER_SEGMENT, MAX_IMPORT_TREE_HEIGHT,
+    };
+    use anyhow::Result;
+    use ethereum_types::{H256, U256};
+    use std::any::Any;
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Wake, Waker};
+
+    #[derive(Default)]
+    struct MemoryNodeDatabase {
+        nodes: Mutex<HashMap<(usize, usize), OptionalHash>>,
+        layer_size: Mutex<HashMap<usize, usize>>,
+    }
+
+    #[derive(Default)]
+    struct MemoryNodeTransaction {
+        nodes: Vec<((usize, usize), Option<OptionalHash>)>,
+        layer_size: Vec<(usize, Option<usize>)>,
+    }
+
+    impl NodeDatabase<OptionalHash> for MemoryNodeDatabase {
+        fn get_node(&self, layer: usize, pos: usize) -> Result<Option<OptionalHash>> {
+            Ok(self.nodes.lock().unwrap().get(&(layer, pos)).cloned())
+        }
+
+        fn get_layer_size(&self, layer: usize) -> Result<Option<usize>> {
+            Ok(self.layer_size.lock().unwrap().get(&layer).cloned())
+        }
+
+        fn start_transaction(&self) -> Box<dyn NodeTransaction<OptionalHash>> {
+            Box::<MemoryNodeTransaction>::default()
+        }
+
+        fn commit(&self, tx: Box<dyn NodeTransaction<OptionalHash>>) -> Result<()> {
+            let tx: Box<MemoryNodeTransaction> = tx.into_any().downcast().unwrap();
+            let mut nodes = self.nodes.lock().unwrap();
+            for (key, node) in tx.nodes {
+                match node {
+                    Some(node) => nodes.insert(key, node),
+                    None => nodes.remove(&key),
+                };
+            }
+            let mut layer_size = self.layer_size.lock().unwrap();
+            for (layer, size) in tx.layer_size {
+                match size {
+                    Some(size) => layer_size.insert(layer, size),
+                    None => layer_size.remove(&layer),
+                };
+            }
+            Ok(())
+        }
+    }
+
+    impl NodeTransaction<OptionalHash> for MemoryNodeTransaction {
+        fn save_node(&mut self, layer: usize, pos: usize, node: &OptionalHash) {
+            self.nodes.push(((layer, pos), Some(node.clone())));
+        }
+
+        fn save_node_list(&mut self, nodes: &[(usize, usize, &OptionalHash)]) {
+            for (layer, pos, node) in nodes {
+                self.save_node(*layer, *pos, node);
+            }
+        }
+
+        fn remove_node_list(&mut self, nodes: &[(usize, usize)]) {
+            for key in nodes {
+                self.nodes.push((*key, None));
+            }
+        }
+
+        fn save_layer_size(&mut self, layer: usize, size: usize) {
+            self.layer_size.push((layer, Some(size)));
+        }
+
+        fn remove_layer_size(&mut self, layer: usize) {
+            self.layer_size.push((layer, None));
+        }
+
+        fn into_any(self: Box<Self>) -> Box<dyn Any> {
+            self
+        }
+    }
 
     #[test]
     fn test_proof() {
@@ -913,6 +1978,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_proof_size_bytes() {
+        use ssz::Encode;
+
+        for leaves in [1, 2, 5, 13, 64, 1025] {
+            let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(
+                (0..leaves)
+                    .map(|_| OptionalHash::some(H256::random()))
+                    .collect(),
+                0,
+                None,
+            );
+            // the last even leaf may have a padding sibling
+            for leaf_index in [0, leaves / 2, (leaves - 1) & !1, leaves - 1] {
+                let proof = merkle.gen_proof(leaf_index).unwrap();
+                assert_eq!(
+                    merkle.proof_size_bytes(leaf_index),
+                    proof.as_ssz_bytes().len(),
+                    "leaves={} leaf_index={}",
+                    leaves,
+                    leaf_index
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_proof_against_modified_merkle() {
         let n = [1, 2, 6, 1025];
@@ -989,6 +2080,1490 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_import_and_verify() {
+        let leaves: Vec<OptionalHash> = (0..12)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let expected_root =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None).root();
+        let subtree_root = |range: std::ops::Range<usize>| {
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[range].to_vec(), 0, None)
+                .root()
+        };
+        let initial_data = || MerkleTreeInitialData {
+            subtree_list: vec![(4, subtree_root(0..8)), (3, subtree_root(8..12))],
+            known_leaves: (0..10).map(|i| (i, leaves[i].clone())).collect(),
+            extra_mpt_nodes: vec![(1, 5, Sha3Algorithm::parent(&leaves[10], &leaves[11]))],
+        };
+
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::import_and_verify(
+            initial_data(),
+            &expected_root,
+            0,
+        )
+        .unwrap();
+        assert_eq!(merkle.root(), expected_root);
+        assert_eq!(merkle.leaf_at(9).unwrap(), Some(leaves[9].clone()));
+
+        let r = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::import_and_verify(
+            initial_data(),
+            &OptionalHash::some(H256::random()),
+            0,
+        );
+        assert!(r.is_err());
+
+        let mut tampered_leaf = initial_data();
+        tampered_leaf.known_leaves[3].1 = OptionalHash::some(H256::random());
+        let e = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::import_and_verify(
+            tampered_leaf,
+            &expected_root,
+            0,
+        )
+        .err()
+        .unwrap();
+        assert!(e.to_string().contains("subtree_index=0"), "{:?}", e);
+
+        let mut tampered_node = initial_data();
+        tampered_node.extra_mpt_nodes[0].2 = OptionalHash::some(H256::random());
+        let e = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::import_and_verify(
+            tampered_node,
+            &expected_root,
+            0,
+        )
+        .err()
+        .unwrap();
+        assert!(e.to_string().contains("layer=1 position=5"), "{:?}", e);
+
+        // The depths are checked before any subtree is appended.
+        for (depth, error) in [
+            (0, "invalid subtree depth"),
+            (MAX_IMPORT_TREE_HEIGHT + 1, "invalid subtree depth"),
+            (64, "invalid subtree depth"),
+            (usize::MAX, "invalid subtree depth"),
+            (5, "subtree not aligned"),
+        ] {
+            let mut tampered_depth = initial_data();
+            tampered_depth.subtree_list[1].0 = depth;
+            let e = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::import_and_verify(
+                tampered_depth,
+                &expected_root,
+                0,
+            )
+            .err()
+            .unwrap();
+            assert!(e.to_string().contains(error), "depth={} {:?}", depth, e);
+        }
+        let mut too_many_leaves = initial_data();
+        too_many_leaves.subtree_list = vec![
+            (MAX_IMPORT_TREE_HEIGHT, subtree_root(0..8)),
+            (MAX_IMPORT_TREE_HEIGHT, subtree_root(8..12)),
+        ];
+        let e = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::import_and_verify(
+            too_many_leaves,
+            &expected_root,
+            0,
+        )
+        .err()
+        .unwrap();
+        assert!(e.to_string().contains("too many leaves"), "{:?}", e);
+    }
+
+    #[test]
+    fn test_insert_extra_mpt_nodes() {
+        let leaves: Vec<OptionalHash> = (0..12)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let expected_root =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None).root();
+        let subtree_root =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[8..12].to_vec(), 0, None)
+                .root();
+        let new_tree = || {
+            let mut merkle =
+                AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[..8].to_vec(), 0, None);
+            merkle.append_subtree(3, subtree_root.clone()).unwrap();
+            merkle.fill_leaf(8, leaves[8].clone());
+            merkle.fill_leaf(9, leaves[9].clone());
+            merkle
+        };
+        let consistent = (1, 5, Sha3Algorithm::parent(&leaves[10], &leaves[11]));
+
+        let mut merkle = new_tree();
+        merkle
+            .insert_extra_mpt_nodes(vec![consistent.clone()], true)
+            .unwrap();
+        assert_eq!(merkle.node(1, 5), consistent.2);
+        assert_eq!(merkle.root(), expected_root);
+
+        // Contradicts the subtree root computed from its sibling.
+        let inconsistent = (1, 5, OptionalHash::some(H256::random()));
+        let mut merkle = new_tree();
+        let e = merkle
+            .insert_extra_mpt_nodes(vec![inconsistent.clone()], true)
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("layer=1 position=5"), "{:?}", e);
+        assert!(merkle.node(1, 5).is_null());
+
+        // Contradicts a node computed from the known leaves.
+        let e = merkle
+            .insert_extra_mpt_nodes(vec![(1, 4, OptionalHash::some(H256::random()))], true)
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("layer=1 position=4"), "{:?}", e);
+
+        // Trusted nodes are inserted without verification.
+        merkle
+            .insert_extra_mpt_nodes(vec![inconsistent.clone()], false)
+            .unwrap();
+        assert_eq!(merkle.node(1, 5), inconsistent.2);
+    }
+
+    #[test]
+    fn test_merge() {
+        let leaves: Vec<OptionalHash> =
+            (0..7).map(|_| OptionalHash::some(H256::random())).collect();
+        let expected_root =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None).root();
+        let subtree_root = |range: std::ops::Range<usize>| {
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[range].to_vec(), 0, None)
+                .root()
+        };
+        let partial_tree = |range: std::ops::Range<usize>| {
+            let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+            merkle
+                .append_subtree_list(vec![
+                    (3, subtree_root(0..4)),
+                    (2, subtree_root(4..6)),
+                    (1, leaves[6].clone()),
+                ])
+                .unwrap();
+            for i in range {
+                merkle.fill_leaf(i, leaves[i].clone());
+            }
+            merkle
+        };
+
+        // non-overlapping
+        let mut merkle = partial_tree(0..4);
+        merkle.merge(&partial_tree(4..6)).unwrap();
+        assert_eq!(merkle.root(), expected_root);
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert_eq!(merkle.leaf_at(i).unwrap(), Some(leaf.clone()));
+        }
+        assert!(merkle.gen_proof(5).is_ok());
+
+        // overlapping and consistent
+        let mut merkle = partial_tree(0..3);
+        merkle.merge(&partial_tree(2..5)).unwrap();
+        assert_eq!(merkle.root(), expected_root);
+        for (i, leaf) in leaves.iter().enumerate().take(5) {
+            assert_eq!(merkle.leaf_at(i).unwrap(), Some(leaf.clone()));
+        }
+        assert_eq!(merkle.leaf_at(5).unwrap(), None);
+
+        // conflicting
+        let mut corrupted_leaves = leaves.clone();
+        corrupted_leaves[2] = OptionalHash::some(H256::random());
+        let corrupted =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(corrupted_leaves, 0, None);
+        let mut merkle = partial_tree(0..3);
+        let e = merkle.merge(&corrupted).err().unwrap();
+        assert!(e.to_string().contains("layer=0 position=2"), "{:?}", e);
+        assert_eq!(merkle.leaf_at(4).unwrap(), None);
+        assert_eq!(merkle.root(), expected_root);
+
+        let e = merkle
+            .merge(&AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(
+                leaves[..6].to_vec(),
+                0,
+                None,
+            ))
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("different sizes"), "{:?}", e);
+    }
+
+    #[test]
+    fn test_optional_hash_encoding() {
+        use ssz::{Decode, Encode};
+
+        let zero = OptionalHash::some(H256::zero());
+        let none = OptionalHash::none();
+        let random = OptionalHash::some(H256::random());
+
+        // flagged
+        let mode = OptionalHashEncoding::Flagged;
+        let zero_bytes = zero.encode_mode(mode).unwrap();
+        let none_bytes = none.encode_mode(mode).unwrap();
+        assert_ne!(zero_bytes, none_bytes);
+        assert_eq!(zero_bytes, zero.as_ssz_bytes());
+        assert_eq!(none_bytes, none.as_ssz_bytes());
+        assert!(!OptionalHash::is_null_encoding(&zero_bytes, mode));
+        assert!(OptionalHash::is_null_encoding(&none_bytes, mode));
+        for hash in [&zero, &none, &random] {
+            let bytes = hash.encode_mode(mode).unwrap();
+            assert_eq!(OptionalHash::decode_mode(&bytes, mode).unwrap(), *hash);
+            assert_eq!(OptionalHash::from_ssz_bytes(&bytes).unwrap(), *hash);
+        }
+        let mut non_canonical = none_bytes.clone();
+        non_canonical[32] = 1;
+        assert!(OptionalHash::decode_mode(&non_canonical, mode).is_err());
+        assert!(OptionalHash::decode_mode(&zero_bytes[1..], mode).is_err());
+
+        // compact
+        let mode = OptionalHashEncoding::Compact;
+        assert!(zero.is_ambiguous_compact());
+        assert!(zero.encode_mode(mode).is_err());
+        let none_bytes = none.encode_mode(mode).unwrap();
+        assert!(OptionalHash::is_null_encoding(&none_bytes, mode));
+        for hash in [&none, &random] {
+            let bytes = hash.encode_mode(mode).unwrap();
+            assert_eq!(OptionalHash::decode_mode(&bytes, mode).unwrap(), *hash);
+        }
+    }
+
+    #[test]
+    fn test_truncate_to() {
+        let leaves: Vec<OptionalHash> = (0..14)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let subtree_root = |start: usize, end: usize| {
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(
+                leaves[start..end].to_vec(),
+                0,
+                None,
+            )
+            .root()
+        };
+        let root_of = |n: usize| subtree_root(0, n);
+
+        // Truncate a tree with all leaves known.
+        for n in [13, 9, 8, 5, 2, 1] {
+            let mut merkle =
+                AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+            merkle.truncate_to(n).unwrap();
+            assert_eq!(merkle.leaves(), n);
+            assert_eq!(merkle.root(), root_of(n));
+            merkle.append_list(leaves[n..].to_vec());
+            assert_eq!(merkle.root(), root_of(leaves.len()));
+        }
+
+        // Truncate a tree built from subtrees with unknown leaves.
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        merkle
+            .append_subtree_list(vec![(4, subtree_root(0, 8)), (3, subtree_root(8, 12))])
+            .unwrap();
+        merkle.append_list(leaves[12..].to_vec());
+        merkle.commit(Some(0));
+        assert_eq!(merkle.root(), root_of(14));
+
+        merkle.truncate_to(12).unwrap();
+        assert_eq!(merkle.root(), root_of(12));
+        assert!(merkle.at_version(0).is_err());
+
+        // The leaves within the second subtree are unknown.
+        assert!(merkle.truncate_to(10).is_err());
+        assert_eq!(merkle.root(), root_of(12));
+        for (i, leaf) in leaves.iter().enumerate().take(12).skip(8) {
+            merkle.fill_leaf(i, leaf.clone());
+        }
+        merkle.truncate_to(10).unwrap();
+        assert_eq!(merkle.root(), root_of(10));
+
+        merkle.truncate_to(8).unwrap();
+        assert_eq!(merkle.root(), root_of(8));
+        assert!(merkle.truncate_to(9).is_err());
+        merkle.truncate_to(0).unwrap();
+        assert_eq!(merkle.leaves(), 0);
+    }
+
+    #[test]
+    fn test_leaf_state() {
+        let leaves: Vec<OptionalHash> =
+            (0..6).map(|_| OptionalHash::some(H256::random())).collect();
+        let subtree_root =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[..4].to_vec(), 0, None)
+                .root();
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        assert_eq!(merkle.leaf_state(0), LeafState::Padding);
+
+        merkle.append_subtree(3, subtree_root).unwrap();
+        merkle.append_list(leaves[4..].to_vec());
+        assert_eq!(merkle.leaf_state(0), LeafState::Unknown);
+        assert_eq!(merkle.leaf_state(3), LeafState::Unknown);
+        assert_eq!(merkle.leaf_state(4), LeafState::Real);
+        assert_eq!(merkle.leaf_state(5), LeafState::Real);
+        assert_eq!(merkle.leaf_state(6), LeafState::Padding);
+        assert_eq!(merkle.leaf_state(8), LeafState::Padding);
+
+        merkle.fill_leaf(3, leaves[3].clone());
+        assert_eq!(merkle.leaf_state(2), LeafState::Unknown);
+        assert_eq!(merkle.leaf_state(3), LeafState::Real);
+    }
+
+    #[test]
+    fn test_batch_tree() {
+        let file_roots: Vec<H256> = (0..5u8)
+            .map(|i| {
+                let data: Vec<u8> = vec![i; 256 * 3];
+                let leaves = data
+                    .chunks(256)
+                    .map(<Sha3Algorithm as Algorithm<H256>>::leaf)
+                    .collect();
+                AppendMerkleTree::<H256, Sha3Algorithm>::new(leaves, 0, None).root()
+            })
+            .collect();
+        let batch = BatchTree::<H256, Sha3Algorithm>::new(file_roots.clone()).unwrap();
+        assert_eq!(batch.num_files(), 5);
+        let batch_root = batch.root();
+
+        for (index, file_root) in file_roots.iter().enumerate() {
+            let proof = batch.gen_inclusion_proof(index).unwrap();
+            assert_eq!(proof.depth(), batch_proof_depth(5));
+            assert!(verify_batch_inclusion::<_, Sha3Algorithm>(
+                &batch_root,
+                5,
+                file_root,
+                index,
+                &proof
+            )
+            .is_ok());
+
+            // wrong file, position, batch size or batch root
+            let other = &file_roots[(index + 1) % 5];
+            assert!(verify_batch_inclusion::<_, Sha3Algorithm>(
+                &batch_root,
+                5,
+                other,
+                index,
+                &proof
+            )
+            .is_err());
+            assert!(verify_batch_inclusion::<_, Sha3Algorithm>(
+                &batch_root,
+                5,
+                file_root,
+                (index + 1) % 5,
+                &proof
+            )
+            .is_err());
+            assert!(verify_batch_inclusion::<_, Sha3Algorithm>(
+                &batch_root,
+                16,
+                file_root,
+                index,
+                &proof
+            )
+            .is_err());
+            assert!(verify_batch_inclusion::<_, Sha3Algorithm>(
+                &H256::random(),
+                5,
+                file_root,
+                index,
+                &proof
+            )
+            .is_err());
+        }
+
+        // an internal node of the batch tree is not a file root
+        let proof = batch.gen_inclusion_proof(0).unwrap();
+        let internal = Sha3Algorithm::parent(&file_roots[0], &file_roots[1]);
+        let lemma = [&[internal], &proof.lemma()[2..]].concat();
+        let shorter = Proof::new(lemma, proof.path()[1..].to_vec()).unwrap();
+        assert!(shorter
+            .validate_with_root::<Sha3Algorithm>(&internal, 0, &batch_root)
+            .is_ok());
+        assert!(
+            verify_batch_inclusion::<_, Sha3Algorithm>(&batch_root, 5, &internal, 0, &shorter)
+                .is_err()
+        );
+
+        // a single file is the batch root itself
+        let single = BatchTree::<H256, Sha3Algorithm>::new(file_roots[..1].to_vec()).unwrap();
+        assert_eq!(single.root(), file_roots[0]);
+        let proof = single.gen_inclusion_proof(0).unwrap();
+        assert!(verify_batch_inclusion::<_, Sha3Algorithm>(
+            &file_roots[0],
+            1,
+            &file_roots[0],
+            0,
+            &proof
+        )
+        .is_ok());
+
+        assert!(BatchTree::<H256, Sha3Algorithm>::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_gen_proof_missing_nodes() {
+        let leaves: Vec<OptionalHash> = (0..10)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let subtree_root =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[..8].to_vec(), 0, None)
+                .root();
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        merkle.append_subtree(4, subtree_root).unwrap();
+        merkle.append_list(leaves[8..].to_vec());
+        merkle.fill_leaf(5, leaves[5].clone());
+
+        let missing = |merkle: &AppendMerkleTree<OptionalHash, Sha3Algorithm>| {
+            let err = merkle.gen_proof(5).unwrap_err();
+            err.downcast_ref::<MissingProofNodes>().unwrap().clone()
+        };
+        let report = missing(&merkle);
+        assert_eq!(report.positions, vec![(0, 4), (1, 3), (2, 0)]);
+        assert_eq!(report.proof_nodes, 6);
+        assert!(report
+            .to_string()
+            .ends_with("3 of 6 nodes missing, first at layer=0 index=4"));
+
+        // the gaps shrink as the data is filled
+        merkle.fill_leaf(4, leaves[4].clone());
+        assert_eq!(missing(&merkle).positions, vec![(1, 3), (2, 0)]);
+        for index in [0, 1, 2, 3, 6, 7] {
+            merkle.fill_leaf(index, leaves[index].clone());
+        }
+        assert!(merkle.gen_proof(5).is_ok());
+    }
+
+    #[test]
+    fn test_null_leaves_in_range() {
+        let leaves: Vec<OptionalHash> = (0..16)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let subtree_root =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[..16].to_vec(), 0, None)
+                .root();
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        merkle.append_subtree(5, subtree_root).unwrap();
+        merkle.append_list((0..3).map(|_| OptionalHash::some(H256::random())).collect());
+        assert_eq!(merkle.null_leaf_runs_in_range(0, 32), vec![0..16]);
+
+        // scattered gaps
+        for index in [0, 1, 5, 9, 10, 11, 15] {
+            merkle.fill_leaf(index, leaves[index].clone());
+        }
+        assert_eq!(
+            merkle.null_leaf_runs_in_range(0, 19),
+            vec![2..5, 6..9, 12..15]
+        );
+        assert_eq!(merkle.null_leaves_in_range(3, 13), vec![3, 4, 6, 7, 8, 12]);
+        assert!(merkle.null_leaves_in_range(9, 12).is_empty());
+
+        // padding positions are excluded
+        assert!(merkle.null_leaves_in_range(16, 32).is_empty());
+        assert!(merkle.null_leaves_in_range(20, 10).is_empty());
+        assert_eq!(merkle.null_leaf_runs_in_range(14, 1000), vec![14..15]);
+    }
+
+    #[test]
+    fn test_proof_divergence() {
+        let leaves: Vec<OptionalHash> =
+            (0..8).map(|_| OptionalHash::some(H256::random())).collect();
+        let mut merkle =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+        merkle.commit(Some(0));
+        let old_root = merkle.root();
+        merkle.update_last(OptionalHash::some(H256::random()));
+        merkle.commit(Some(1));
+        let root = merkle.root();
+
+        let (proof, divergence) = merkle.gen_proof_with_root(6, &root).unwrap();
+        assert_eq!(divergence, None);
+        assert!(proof
+            .validate_with_root::<Sha3Algorithm>(&leaves[6], 6, &root)
+            .is_ok());
+        assert!(proof
+            .validate_with_root::<Sha3Algorithm>(&leaves[6], 6, &old_root)
+            .is_err());
+
+        // the sibling of leaf 6 is updated
+        let (_, divergence) = merkle.gen_proof_with_root(6, &old_root).unwrap();
+        let divergence = divergence.unwrap();
+        assert_eq!(divergence.layer, 1);
+        assert_eq!(divergence.derived, Some(merkle.node(1, 3)));
+        assert_eq!(
+            divergence.expected,
+            Some(merkle.at_version(0).unwrap().node(1, 3))
+        );
+        let (_, divergence) = merkle.gen_proof_with_root(0, &old_root).unwrap();
+        assert_eq!(divergence.unwrap().layer, 3);
+
+        // only the root layer is known to diverge from an unknown root
+        let wrong_root = OptionalHash::some(H256::random());
+        let (_, divergence) = merkle.gen_proof_with_root(6, &wrong_root).unwrap();
+        assert_eq!(
+            divergence,
+            Some(ProofDivergence {
+                layer: 3,
+                derived: Some(root),
+                expected: Some(wrong_root),
+            })
+        );
+    }
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn test_cancel_proof_generation() {
+        let leaves: Vec<OptionalHash> = (0..1024)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves, 0, None);
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+
+        // yields once per layer and returns the same proof as the sync API
+        let cancel = CancelToken::new();
+        let mut future = Box::pin(merkle.gen_proof_cancellable(100, &cancel));
+        let mut polls = 0;
+        let proof = loop {
+            polls += 1;
+            if let Poll::Ready(proof) = future.as_mut().poll(&mut cx) {
+                break proof.unwrap();
+            }
+        };
+        assert_eq!(proof, merkle.gen_proof(100).unwrap());
+        assert_eq!(polls, merkle.height());
+
+        // returns at the next layer boundary once cancelled
+        let cancel = CancelToken::new();
+        let mut future = Box::pin(merkle.gen_proof_cancellable(100, &cancel));
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        cancel.cancel();
+        assert!(matches!(future.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+    }
+
+    #[test]
+    fn test_is_complete() {
+        let leaves: Vec<OptionalHash> =
+            (0..6).map(|_| OptionalHash::some(H256::random())).collect();
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        assert!(!merkle.is_complete());
+
+        let complete =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+        assert!(complete.is_complete());
+
+        // the leaves of the appended subtree are unknown
+        let subtree_root =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[..4].to_vec(), 0, None)
+                .root();
+        merkle.append_subtree(3, subtree_root).unwrap();
+        merkle.append_list(leaves[4..].to_vec());
+        assert!(!merkle.is_complete());
+        assert!(!merkle.is_range_complete(0, 4));
+        assert!(merkle.is_range_complete(4, 6));
+        for (index, leaf) in leaves[..3].iter().enumerate() {
+            merkle.fill_leaf(index, leaf.clone());
+        }
+        assert!(!merkle.is_complete());
+        assert!(merkle.is_range_complete(0, 3));
+        assert!(!merkle.is_range_complete(2, 6));
+        assert!(!merkle.is_range_complete(0, 7));
+        merkle.fill_leaf(3, leaves[3].clone());
+        assert!(merkle.is_complete());
+        assert_eq!(merkle.root(), complete.root());
+        for index in 0..leaves.len() {
+            assert!(merkle.gen_proof(index).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_gen_proof_for_data() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 256]).collect();
+        let leaves: Vec<OptionalHash> = data.iter().map(|d| Sha3Algorithm::leaf(d)).collect();
+        let mut merkle =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+
+        for (i, d) in data.iter().enumerate() {
+            let proof = merkle.gen_proof_for_data(d).unwrap();
+            assert_eq!(proof, merkle.gen_proof(i).unwrap());
+            assert!(proof.validate::<Sha3Algorithm>(&leaves[i], i).is_ok());
+        }
+        assert!(merkle.gen_proof_for_data(&[9; 256]).is_err());
+
+        // duplicate leaves
+        merkle.append(leaves[2].clone());
+        assert!(merkle.gen_proof_for_data(&data[2]).is_err());
+        assert!(merkle.gen_proof_for_data(&data[3]).is_ok());
+    }
+
+    #[test]
+    fn test_pinned_layers() {
+        let node = |layer: usize, pos: usize| {
+            OptionalHash::some(H256::from_low_u64_be((layer * 1000 + pos) as u64))
+        };
+        // nodes not in the cache are lost with the empty db
+        let mut manager =
+            NodeManager::<OptionalHash>::new(Arc::new(EmptyNodeDatabase {}), 1, 2).unwrap();
+        manager.start_transaction();
+        let layer_sizes = [8, 4, 2];
+        for (layer, size) in layer_sizes.iter().enumerate() {
+            manager.add_layer();
+            for pos in 0..*size {
+                manager.push_node(layer, node(layer, pos));
+            }
+        }
+        manager.commit();
+
+        // the top 2 layers are never evicted
+        for (layer, size) in layer_sizes.iter().enumerate().skip(1) {
+            for pos in 0..*size {
+                assert_eq!(manager.get_node(layer, pos), Some(node(layer, pos)));
+            }
+        }
+        assert_eq!(manager.get_node(0, 0), None);
+        let footprint = manager.footprint();
+        assert_eq!(footprint.pinned_nodes, 6);
+        assert_eq!(footprint.cached_nodes, 1);
+        assert!(footprint.pinned_bytes > 0);
+
+        // pinned layers move up with the tree height
+        manager.start_transaction();
+        manager.add_layer();
+        manager.push_node(3, node(3, 0));
+        manager.push_node(0, node(0, 8));
+        manager.commit();
+        assert_eq!(manager.footprint().pinned_nodes, 3);
+        assert_eq!(manager.get_node(3, 0), Some(node(3, 0)));
+        assert_eq!(manager.get_node(2, 1), Some(node(2, 1)));
+        assert_eq!(manager.get_node(1, 0), None);
+
+        // and move down back after truncation
+        manager.start_transaction();
+        manager.truncate_layer(3);
+        manager.commit();
+        assert_eq!(manager.footprint().pinned_nodes, 2);
+        assert_eq!(manager.get_node(2, 0), Some(node(2, 0)));
+    }
+
+    #[test]
+    fn test_flush_completed_subtrees() {
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new_with_subtrees(
+            Arc::new(MemoryNodeDatabase::default()),
+            1 << 20,
+            0,
+            0,
+        )
+        .unwrap();
+        merkle.set_flush_completed_subtrees(true);
+        let mut expected = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new_with_subtrees(
+            Arc::new(EmptyNodeDatabase {}),
+            1 << 20,
+            0,
+            0,
+        )
+        .unwrap();
+
+        let leaves: Vec<OptionalHash> = (0..3000)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        for (i, leaf) in leaves.iter().enumerate() {
+            merkle.append(leaf.clone());
+            expected.append(leaf.clone());
+            if i % 100 == 0 {
+                assert_eq!(merkle.root(), expected.root());
+            }
+            // only the last 2 nodes of each layer are resident
+            assert!(merkle.node_cache_footprint().cached_nodes <= 2 * merkle.height());
+        }
+        merkle.append_list(leaves[..512].to_vec());
+        expected.append_list(leaves[..512].to_vec());
+        assert_eq!(merkle.root(), expected.root());
+        assert!(merkle.node_cache_footprint().cached_nodes <= 2 * merkle.height());
+
+        // flushed nodes are loaded from db
+        for i in [0, 1000, 2999] {
+            let proof = merkle.gen_proof(i).unwrap();
+            assert_eq!(proof, expected.gen_proof(i).unwrap());
+            assert!(proof.validate::<Sha3Algorithm>(&leaves[i], i).is_ok());
+        }
+        merkle.update_last(leaves[0].clone());
+        expected.update_last(leaves[0].clone());
+        assert_eq!(merkle.root(), expected.root());
+    }
+
+    #[test]
+    fn test_verify_data_proof() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 256]).collect();
+        let leaves: Vec<OptionalHash> = data.iter().map(|d| Sha3Algorithm::leaf(d)).collect();
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+        let root = merkle.root();
+        let proof = merkle.gen_proof(3).unwrap();
+        let verify = |data: &[u8], leaf_index, proof: &Proof<OptionalHash>| {
+            verify_data_proof::<_, Sha3Algorithm>(&root, data, leaf_index, proof)
+        };
+
+        assert_eq!(verify(&data[3], 3, &proof), VerifyOutcome::Valid);
+
+        // wrong data
+        assert_eq!(
+            verify(&data[2], 3, &proof),
+            VerifyOutcome::LeafHashMismatch {
+                computed: leaves[2].clone(),
+                claimed: leaves[3].clone(),
+            }
+        );
+
+        // bad proof
+        let mut lemma = proof.lemma().to_vec();
+        lemma[1] = OptionalHash::some(H256::random());
+        let tampered = Proof::new(lemma, proof.path().to_vec()).unwrap();
+        assert_eq!(verify(&data[3], 3, &tampered), VerifyOutcome::InvalidProof);
+        assert_eq!(
+            verify(&data[3], 3, &Proof::new_empty()),
+            VerifyOutcome::InvalidProof
+        );
+
+        // proof of another leaf index
+        assert_eq!(
+            verify(&data[3], 2, &proof),
+            VerifyOutcome::PositionMismatch {
+                expected: 2,
+                actual: 3,
+            }
+        );
+
+        // valid proof of another tree
+        let mut other_leaves = leaves.clone();
+        other_leaves.push(leaves[0].clone());
+        let other = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(other_leaves, 0, None);
+        let other_proof = other.gen_proof(3).unwrap();
+        assert_eq!(
+            verify(&data[3], 3, &other_proof),
+            VerifyOutcome::RootMismatch {
+                derived: other.root(),
+                expected: root.clone(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_batch_verifier() {
+        let data: Vec<Vec<u8>> = (0..100u8).map(|i| vec![i; 256]).collect();
+        let leaves: Vec<OptionalHash> = data.iter().map(|d| Sha3Algorithm::leaf(d)).collect();
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves, 0, None);
+        let root = merkle.root();
+
+        // every 7th item has the data of another leaf
+        let batch: Vec<(Vec<u8>, usize, Proof<OptionalHash>)> = (0..data.len())
+            .map(|i| {
+                let item = if i % 7 == 0 { (i + 1) % data.len() } else { i };
+                (data[item].clone(), i, merkle.gen_proof(i).unwrap())
+            })
+            .collect();
+
+        let serial = BatchVerifier::new(usize::MAX);
+        assert!(!serial.is_parallel(batch.len()));
+        let outcomes = serial.verify::<_, Sha3Algorithm, _>(&root, &batch);
+        assert_eq!(outcomes.len(), batch.len());
+        for (i, outcome) in outcomes.iter().enumerate() {
+            assert_eq!(outcome.is_valid(), i % 7 != 0, "index={}", i);
+        }
+
+        // the same outcomes whatever the threshold
+        for threshold in [0, 1, DEFAULT_PARALLEL_VERIFY_THRESHOLD, batch.len()] {
+            let verifier = BatchVerifier::new(threshold);
+            assert_eq!(
+                verifier.is_parallel(batch.len()),
+                cfg!(feature = "parallel-verify")
+            );
+            assert_eq!(
+                verifier.verify::<_, Sha3Algorithm, _>(&root, &batch),
+                outcomes
+            );
+            assert_eq!(
+                verifier.verify::<_, Sha3Algorithm, _>(&root, &batch[..1]),
+                outcomes[..1]
+            );
+        }
+        assert!(BatchVerifier::default()
+            .verify::<_, Sha3Algorithm, _>(&root, &batch[..0])
+            .is_empty());
+    }
+
+    #[test]
+    fn test_solidity_calldata() {
+        let leaves: Vec<OptionalHash> = (1..=3)
+            .map(|i| OptionalHash::some(H256::from_low_u64_be(i)))
+            .collect();
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves, 0, None);
+        let proof = merkle.gen_proof_h256(2).unwrap();
+        let calldata = proof.to_solidity_calldata().unwrap();
+
+        let hex = |s: &str| H256::from_str(s).unwrap();
+        let root = hex("899a0485d4625b3c7e16d2c9277860fc0845befeade7df7d636cd77cde81519a");
+        assert_eq!(
+            calldata.lemma,
+            vec![
+                H256::from_low_u64_be(3),
+                // padding of the last leaf
+                hex("d397b3b043d87fcd6fad1291ff0bfd16401c274896d8c63a923727f077b8e0b5"),
+                hex("e90b7bceb6e7df5418fb78d8ee546e97c83a08bbccc01a0644d599ccd2a7c2e0"),
+                root,
+            ]
+        );
+        assert_eq!(calldata.path, U256::from(2));
+
+        // verify in the same way as the on-chain verifier
+        let mut h = calldata.lemma[0];
+        for i in 0..calldata.lemma.len() - 2 {
+            h = if calldata.path.bit(i) {
+                Sha3Algorithm::parent_raw(&calldata.lemma[i + 1], &h)
+            } else {
+                Sha3Algorithm::parent_raw(&h, &calldata.lemma[i + 1])
+            };
+        }
+        assert_eq!(h, root);
+
+        let encoded = calldata.abi_encode();
+        assert_eq!(encoded.len(), 32 * 7);
+        assert_eq!(&encoded[..32], H256::from_low_u64_be(0x40).as_bytes());
+        assert_eq!(&encoded[32..64], H256::from_low_u64_be(2).as_bytes());
+        assert_eq!(&encoded[64..96], H256::from_low_u64_be(4).as_bytes());
+        assert_eq!(&encoded[192..], root.as_bytes());
+    }
+
+    #[test]
+    fn test_proof_order() {
+        let leaves: Vec<OptionalHash> = (0..13)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+
+        for (position, leaf) in leaves.iter().enumerate() {
+            let proof = merkle.gen_proof(position).unwrap();
+
+            // leaf-first verifier
+            let ordered = proof.to_ordered(ProofOrder::LeafFirst);
+            assert_eq!(ordered.lemma, proof.lemma());
+            let mut h = ordered.lemma[0].clone();
+            for (i, is_left) in ordered.path.iter().enumerate() {
+                h = if *is_left {
+                    Sha3Algorithm::parent(&h, &ordered.lemma[i + 1])
+                } else {
+                    Sha3Algorithm::parent(&ordered.lemma[i + 1], &h)
+                };
+            }
+            assert_eq!(h, merkle.root());
+
+            // root-first verifier
+            let ordered = proof.to_ordered(ProofOrder::RootFirst);
+            assert_eq!(ordered.lemma[0], merkle.root());
+            assert_eq!(ordered.lemma.last(), Some(leaf));
+            let n = ordered.lemma.len();
+            let mut h = ordered.lemma[n - 1].clone();
+            for i in (0..ordered.path.len()).rev() {
+                h = if ordered.path[i] {
+                    Sha3Algorithm::parent(&h, &ordered.lemma[i + 1])
+                } else {
+                    Sha3Algorithm::parent(&ordered.lemma[i + 1], &h)
+                };
+            }
+            assert_eq!(h, ordered.lemma[0]);
+
+            // the canonical form is unchanged
+            let converted = ordered.into_proof().unwrap();
+            assert_eq!(converted, proof);
+            assert!(converted.validate::<Sha3Algorithm>(leaf, position).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_gen_tip_proof() {
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        assert!(merkle.gen_tip_proof().is_err());
+
+        for n in 1..=6 {
+            let leaf = OptionalHash::some(H256::random());
+            merkle.append(leaf.clone());
+            // The last leaf is at an even index for odd `n`, so its sibling is padding.
+            let proof = merkle.gen_tip_proof().unwrap();
+            assert_eq!(proof.position(), n - 1);
+            assert_eq!(proof.root(), merkle.root());
+            assert!(proof.validate::<Sha3Algorithm>(&leaf, n - 1).is_ok());
+        }
+
+        // The leaves in the appended subtree are unknown.
+        let subtree_root = merkle.root();
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        merkle.append_subtree(4, subtree_root).unwrap();
+        assert!(merkle.gen_tip_proof().is_err());
+    }
+
+    #[test]
+    fn test_peaks() {
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        assert!(merkle.peaks().is_empty());
+
+        for n in 1..=33usize {
+            merkle.append(OptionalHash::some(H256::random()));
+            let peaks = merkle.peaks();
+            assert_eq!(peaks.len(), n.count_ones() as usize);
+            assert_eq!(
+                peaks.iter().map(|(height, _)| 1 << height).sum::<usize>(),
+                n
+            );
+
+            // bag the peaks from right to left
+            let mut iter = peaks.into_iter().rev();
+            let (mut height, mut root) = iter.next().unwrap();
+            for (peak_height, peak) in iter {
+                while height < peak_height {
+                    root = Sha3Algorithm::parent_single(&root, height);
+                    height += 1;
+                }
+                root = Sha3Algorithm::parent(&peak, &root);
+                height += 1;
+            }
+            while height < merkle.height() - 1 {
+                root = Sha3Algorithm::parent_single(&root, height);
+                height += 1;
+            }
+            assert_eq!(root, merkle.root(), "n={}", n);
+        }
+    }
+
+    #[test]
+    fn test_leaves_until_next_flush() {
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        assert_eq!(merkle.leaves_until_next_flush(), 1);
+
+        for (leaves, expected) in [
+            (1, 1),
+            (2, 2),
+            (3, 1),
+            (4, 4),
+            (5, 1),
+            (6, 2),
+            (12, 4),
+            (16, 16),
+            (24, 8),
+            (31, 1),
+            (32, 32),
+        ] {
+            while merkle.leaves() < leaves {
+                merkle.append(OptionalHash::some(H256::random()));
+            }
+            let remaining = merkle.leaves_until_next_flush();
+            assert_eq!(remaining, expected, "leaves={}", leaves);
+
+            // the right-most peak is merged only after appending the remaining leaves
+            let lowest_peak = |leaves: usize| 1usize << leaves.trailing_zeros();
+            for appended in 1..remaining {
+                assert!(lowest_peak(leaves + appended) < remaining);
+            }
+            assert!(lowest_peak(leaves + remaining) > remaining);
+        }
+    }
+
+    #[test]
+    fn test_subtree_proof() {
+        let leaves: Vec<OptionalHash> = (0..13)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+        let peaks = merkle.peaks();
+        assert_eq!(
+            peaks.iter().map(|(height, _)| *height).collect::<Vec<_>>(),
+            vec![3, 2, 0]
+        );
+
+        let mut start = 0;
+        for (peak_index, (height, peak)) in peaks.iter().enumerate() {
+            for position in start..start + (1 << height) {
+                let proof = merkle.gen_subtree_proof(position, *height).unwrap();
+                assert_eq!(proof.root(), *peak);
+                assert!(proof
+                    .validate_with_subtree_root::<Sha3Algorithm>(
+                        &leaves[position],
+                        position,
+                        peak,
+                        *height
+                    )
+                    .is_ok());
+
+                // rejected against other peaks
+                for (other_index, (other_height, other_peak)) in peaks.iter().enumerate() {
+                    if other_index != peak_index {
+                        assert!(proof
+                            .validate_with_subtree_root::<Sha3Algorithm>(
+                                &leaves[position],
+                                position,
+                                other_peak,
+                                *other_height
+                            )
+                            .is_err());
+                    }
+                }
+                assert!(proof
+                    .validate_with_subtree_root::<Sha3Algorithm>(
+                        &leaves[position],
+                        position,
+                        &merkle.root(),
+                        *height
+                    )
+                    .is_err());
+            }
+            start += 1 << height;
+        }
+
+        // the subtree must be complete
+        assert!(merkle.gen_subtree_proof(12, 1).is_err());
+        assert!(merkle.gen_subtree_proof(8, 3).is_err());
+        assert!(merkle.gen_subtree_proof(13, 0).is_err());
+    }
+
+    #[test]
+    fn test_rebuild_internal_nodes() {
+        for n in [1, 2, 13, 64] {
+            let leaves: Vec<OptionalHash> =
+                (0..n).map(|_| OptionalHash::some(H256::random())).collect();
+            let mut merkle =
+                AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+            let root = merkle.root();
+
+            // corrupt all the internal nodes
+            merkle.node_manager.start_transaction();
+            for height in 1..merkle.height() {
+                for index in 0..merkle.layer_len(height) {
+                    merkle.update_node(height, index, OptionalHash::some(H256::zero()));
+                }
+            }
+            merkle.node_manager.commit();
+            if n > 1 {
+                assert_ne!(merkle.root(), root);
+            }
+
+            assert_eq!(merkle.rebuild_internal_nodes().unwrap(), root);
+            assert_eq!(merkle.root(), root);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle.gen_proof(index).unwrap();
+                assert!(proof.validate::<Sha3Algorithm>(leaf, index).is_ok());
+                assert_eq!(proof.root(), root);
+            }
+        }
+
+        // all the leaves must be known
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        assert!(merkle.rebuild_internal_nodes().is_err());
+        merkle.append_list(vec![OptionalHash::some(H256::random()); 4]);
+        merkle
+            .append_subtree(3, OptionalHash::some(H256::random()))
+            .unwrap();
+        assert!(merkle.rebuild_internal_nodes().is_err());
+    }
+
+    #[test]
+    fn test_verify_tree_consistency() {
+        let leaves: Vec<OptionalHash> = (0..1000)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves, 0, None);
+        // the nodes of unknown children are skipped
+        merkle
+            .append_subtree(4, OptionalHash::some(H256::random()))
+            .unwrap();
+        assert!(merkle.verify_tree_consistency().is_ok());
+
+        let stored = merkle.node(3, 5);
+        let tampered = OptionalHash::some(H256::random());
+        merkle.node_manager.start_transaction();
+        merkle.update_node(3, 5, tampered.clone());
+        merkle.node_manager.commit();
+
+        let report = merkle.verify_tree_consistency().unwrap_err();
+        let first = report.first().unwrap();
+        assert_eq!((first.layer, first.index), (3, 5));
+        assert_eq!(first.stored, tampered);
+        assert_eq!(first.computed, stored);
+        // the ancestors are recomputed from the tampered node
+        assert_eq!(report.positions(), vec![(3, 5), (4, 2)]);
+
+        merkle.node_manager.start_transaction();
+        merkle.update_node(3, 5, stored);
+        merkle.node_manager.commit();
+        assert!(merkle.verify_tree_consistency().is_ok());
+    }
+
+    #[test]
+    fn test_append_wal() {
+        let leaves: Vec<OptionalHash> = (0..11)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let expected =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None).root();
+        let batch = vec![
+            WalOp::AppendList(leaves[4..6].to_vec()),
+            WalOp::AppendSubtree {
+                depth: 2,
+                root: Sha3Algorithm::parent(&leaves[6], &leaves[7]),
+            },
+            WalOp::AppendList(leaves[8..].to_vec()),
+        ];
+        let wal = Arc::new(MemoryWal::default());
+        let new_merkle = || {
+            let mut merkle =
+                AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[..4].to_vec(), 0, None);
+            merkle.set_wal(wal.clone());
+            merkle
+        };
+
+        let mut merkle = new_merkle();
+        merkle.append_batch(batch).unwrap();
+        assert_eq!(merkle.root(), expected);
+        assert_eq!(wal.read().unwrap().len(), 3);
+
+        // crash after the first append of the batch
+        let mut merkle = new_merkle();
+        merkle.append_list(leaves[4..6].to_vec());
+        assert_eq!(merkle.replay_wal().unwrap(), 2);
+        assert_eq!(merkle.root(), expected);
+        assert_eq!(merkle.replay_wal().unwrap(), 0);
+        assert_eq!(merkle.root(), expected);
+
+        // crash before any append of the batch
+        let mut merkle = new_merkle();
+        assert_eq!(merkle.replay_wal().unwrap(), 3);
+        assert_eq!(merkle.root(), expected);
+
+        // truncated after checkpoint
+        merkle.checkpoint(Some(0)).unwrap();
+        assert!(wal.read().unwrap().is_empty());
+        assert_eq!(merkle.replay_wal().unwrap(), 0);
+        assert_eq!(merkle.root(), expected);
+
+        // a record that does not follow the tree
+        wal.write(&[WalRecord {
+            leaves_before: 20,
+            op: WalOp::AppendList(leaves[..1].to_vec()),
+        }])
+        .unwrap();
+        assert!(merkle.replay_wal().is_err());
+        assert!(merkle
+            .append_batch(vec![WalOp::AppendList(vec![OptionalHash::none()])])
+            .is_err());
+        assert!(merkle
+            .append_batch(vec![WalOp::AppendSubtree {
+                depth: 0,
+                root: leaves[0].clone(),
+            }])
+            .is_err());
+    }
+
+    #[test]
+    fn test_append_wal_update_last() {
+        let leaves: Vec<OptionalHash> =
+            (0..6).map(|_| OptionalHash::some(H256::random())).collect();
+        let mut expected_leaves = leaves.clone();
+        expected_leaves[4] = leaves[5].clone();
+        expected_leaves.truncate(5);
+        let expected =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(expected_leaves, 0, None).root();
+        let batch = vec![
+            WalOp::AppendList(leaves[3..5].to_vec()),
+            WalOp::UpdateLast(leaves[5].clone()),
+        ];
+        let wal = Arc::new(MemoryWal::default());
+        let new_merkle = || {
+            let mut merkle =
+                AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves[..3].to_vec(), 0, None);
+            merkle.set_wal(wal.clone());
+            merkle
+        };
+
+        let mut merkle = new_merkle();
+        merkle.append_batch(batch).unwrap();
+        assert_eq!(merkle.root(), expected);
+        // the update is applied again without changing the tree
+        assert_eq!(merkle.replay_wal().unwrap(), 1);
+        assert_eq!(merkle.root(), expected);
+
+        // crash after the append of the batch
+        let mut merkle = new_merkle();
+        merkle.append_list(leaves[3..5].to_vec());
+        assert_eq!(merkle.replay_wal().unwrap(), 1);
+        assert_eq!(merkle.root(), expected);
+        wal.truncate().unwrap();
+
+        let mut empty = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        assert!(empty
+            .append_batch(vec![WalOp::UpdateLast(leaves[0].clone())])
+            .is_err());
+    }
+
+    #[test]
+    fn test_range_proof_verify() {
+        let data: Vec<H256> = (0..17).map(|_| H256::random()).collect();
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(
+            vec![OptionalHash::some(H256::zero())],
+            0,
+            None,
+        );
+        merkle.append_list(data.iter().copied().map(OptionalHash::some).collect());
+        merkle.commit(Some(0));
+        let root = merkle.root();
+
+        let range_proof = merkle.gen_range_proof(2, 11).unwrap();
+        assert_eq!(range_proof.verify::<Sha3Algorithm>(&root), Ok(()));
+        assert_eq!(
+            range_proof.verify::<Sha3Algorithm>(&OptionalHash::some(H256::random())),
+            Err(RangeProofError::InvalidLeftProof)
+        );
+
+        // tamper a sibling of the right proof
+        let mut lemma = range_proof.right_proof.lemma().to_vec();
+        lemma[1] = OptionalHash::some(H256::random());
+        let tampered = RangeProof {
+            left_proof: range_proof.left_proof.clone(),
+            right_proof: Proof::new(lemma, range_proof.right_proof.path().to_vec()).unwrap(),
+        };
+        assert_eq!(
+            tampered.verify::<Sha3Algorithm>(&root),
+            Err(RangeProofError::InvalidRightProof)
+        );
+
+        // endpoints from different trees
+        let mismatched = RangeProof {
+            left_proof: range_proof.left_proof.clone(),
+            right_proof: Proof::new(vec![OptionalHash::some(H256::random()), root], vec![])
+                .unwrap(),
+        };
+        assert_eq!(
+            mismatched.verify::<Sha3Algorithm>(&root),
+            Err(RangeProofError::HeightMismatch)
+        );
+    }
+
+    static COUNTED_PARENTS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Sha3 that counts the parent hashes, to check that a proof is rejected before hashing.
+    struct CountingAlgorithm;
+
+    impl Algorithm<H256> for CountingAlgorithm {
+        const ID: u8 = SHA3_ALGORITHM_ID;
+
+        fn parent(left: &H256, right: &H256) -> H256 {
+            COUNTED_PARENTS.fetch_add(1, Ordering::Relaxed);
+            Sha3Algorithm::parent(left, right)
+        }
+
+        fn leaf(data: &[u8]) -> H256 {
+            Sha3Algorithm::leaf(data)
+        }
+    }
+
+    #[test]
+    fn test_max_proof_depth() {
+        let depth = DEFAULT_MAX_PROOF_DEPTH + 1;
+        let path: Vec<bool> = (0..depth).map(|i| i % 2 == 0).collect();
+        let lemma: Vec<H256> = (0..depth + 2).map(|_| H256::random()).collect();
+        let proof = Proof::new(lemma, path).unwrap();
+        let position = proof.position();
+
+        let err = proof
+            .validate::<CountingAlgorithm>(&proof.item(), position)
+            .unwrap_err();
+        assert!(err.to_string().contains("too deep"));
+        assert_eq!(
+            verify_data_proof::<_, CountingAlgorithm>(&proof.root(), &[0; 256], position, &proof),
+            VerifyOutcome::TooDeep {
+                depth,
+                max_depth: DEFAULT_MAX_PROOF_DEPTH,
+            }
+        );
+        let range_proof = RangeProof {
+            left_proof: proof.clone(),
+            right_proof: proof.clone(),
+        };
+        assert_eq!(
+            range_proof.verify::<CountingAlgorithm>(&proof.root()),
+            Err(RangeProofError::TooDeep)
+        );
+        assert_eq!(COUNTED_PARENTS.load(Ordering::Relaxed), 0);
+
+        // a tighter limit for a smaller expected tree
+        let data: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i; 256]).collect();
+        let leaves: Vec<OptionalHash> = data.iter().map(|d| Sha3Algorithm::leaf(d)).collect();
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+        let root = merkle.root();
+        let proof = merkle.gen_proof(5).unwrap();
+        assert_eq!(proof.depth(), 4);
+        assert!(proof.validate::<Sha3Algorithm>(&leaves[5], 5).is_ok());
+        assert!(proof
+            .validate_with_max_depth::<Sha3Algorithm>(&leaves[5], 5, 4)
+            .is_ok());
+        assert!(proof
+            .validate_with_max_depth::<Sha3Algorithm>(&leaves[5], 5, 3)
+            .is_err());
+        assert_eq!(
+            verify_data_proof_with_max_depth::<_, Sha3Algorithm>(&root, &data[5], 5, &proof, 4),
+            VerifyOutcome::Valid
+        );
+        assert_eq!(
+            verify_data_proof_with_max_depth::<_, Sha3Algorithm>(&root, &data[5], 5, &proof, 3),
+            VerifyOutcome::TooDeep {
+                depth: 4,
+                max_depth: 3,
+            }
+        );
+        let batch = vec![(data[5].clone(), 5, proof)];
+        assert!(BatchVerifier::default()
+            .with_max_proof_depth(4)
+            .verify::<_, Sha3Algorithm, _>(&root, &batch)[0]
+            .is_valid());
+        assert!(!BatchVerifier::default()
+            .with_max_proof_depth(3)
+            .verify::<_, Sha3Algorithm, _>(&root, &batch)[0]
+            .is_valid());
+
+        let range_proof = merkle.gen_range_proof(2, 7).unwrap();
+        assert!(range_proof
+            .validate_with_max_depth::<Sha3Algorithm>(&leaves[2..7], 2, 4)
+            .is_ok());
+        assert!(range_proof
+            .validate_with_max_depth::<Sha3Algorithm>(&leaves[2..7], 2, 3)
+            .is_err());
+        assert_eq!(
+            range_proof.verify_with_max_depth::<Sha3Algorithm>(&root, 4),
+            Ok(())
+        );
+        assert_eq!(
+            range_proof.verify_with_max_depth::<Sha3Algorithm>(&root, 3),
+            Err(RangeProofError::TooDeep)
+        );
+    }
+
+    #[test]
+    fn test_validate_strict_zero_leaf() {
+        let mut leaves: Vec<H256> = (0..8).map(|_| H256::random()).collect();
+        leaves[3] = H256::zero();
+        let merkle = AppendMerkleTree::<H256, Sha3Algorithm>::new(leaves.clone(), 0, None);
+        let zero_proof = merkle.gen_proof(3).unwrap();
+
+        // a proof of the null leaf, which is not a real leaf of any tree
+        let null_leaf = <H256 as HashElement>::null();
+        let sibling = H256::random();
+        let null_proof = Proof::new(
+            vec![
+                null_leaf,
+                sibling,
+                Sha3Algorithm::parent(&null_leaf, &sibling),
+            ],
+            vec![true],
+        )
+        .unwrap();
+        leaves[0] = null_leaf;
+
+        for (index, proof) in [(3, zero_proof), (0, null_proof)] {
+            // the relaxed mode accepts any leaf
+            assert!(proof
+                .validate::<Sha3Algorithm>(&leaves[index], index)
+                .is_ok());
+            let err = proof
+                .validate_strict::<Sha3Algorithm>(&leaves[index], index, ZeroLeafPolicy::Reject)
+                .unwrap_err();
+            assert!(err.to_string().contains("null or zero leaf"));
+            assert!(proof
+                .validate_strict::<Sha3Algorithm>(&leaves[index], index, ZeroLeafPolicy::Allow)
+                .is_ok());
+        }
+
+        let proof = merkle.gen_proof(5).unwrap();
+        assert!(proof
+            .validate_strict::<Sha3Algorithm>(&leaves[5], 5, ZeroLeafPolicy::default())
+            .is_ok());
+        // the allowed zero leaf is still verified
+        assert!(proof
+            .validate_strict::<Sha3Algorithm>(&H256::zero(), 5, ZeroLeafPolicy::Allow)
+            .is_err());
+    }
+
+    #[test]
+    fn test_proof_algorithm_tag() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 256]).collect();
+        let leaves: Vec<OptionalHash> = data.iter().map(|d| Sha3Algorithm::leaf(d)).collect();
+        let merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+        let root = merkle.root();
+
+        // untagged proofs default to sha3
+        let proof = merkle.gen_proof(3).unwrap();
+        assert_eq!(proof.algorithm_tag(), None);
+        assert_eq!(proof.algorithm(), SHA3_ALGORITHM_ID);
+        assert!(proof.validate::<Sha3Algorithm>(&leaves[3], 3).is_ok());
+        let tagged = proof.clone().with_algorithm_tag(Some(SHA3_ALGORITHM_ID));
+        assert!(tagged.validate::<Sha3Algorithm>(&leaves[3], 3).is_ok());
+
+        // proof tagged for another algorithm
+        let other = proof.clone().with_algorithm_tag(Some(1));
+        let err = other.validate::<Sha3Algorithm>(&leaves[3], 3).unwrap_err();
+        assert!(err.to_string().contains("algorithm mismatch"));
+        assert_eq!(
+            verify_data_proof::<_, Sha3Algorithm>(&root, &data[3], 3, &other),
+            VerifyOutcome::AlgorithmMismatch {
+                proof: 1,
+                verifier: SHA3_ALGORITHM_ID,
+            }
+        );
+        let mut range_proof = merkle.gen_range_proof(1, 4).unwrap();
+        range_proof.right_proof = range_proof.right_proof.with_algorithm_tag(Some(1));
+        assert_eq!(
+            range_proof.verify::<Sha3Algorithm>(&root),
+            Err(RangeProofError::AlgorithmMismatch)
+        );
+        assert!(range_proof
+            .validate::<Sha3Algorithm>(&leaves[1..4], 1)
+            .is_err());
+
+        // the tag is kept in the conversion to H256
+        let converted =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::convert_proof_to_h256(other).unwrap();
+        assert_eq!(converted.algorithm_tag(), Some(1));
+    }
+
+    #[test]
+    fn test_segment_proof() {
+        let leaves: Vec<OptionalHash> = (0..2 * LEAVES_PER_SEGMENT + 100)
+            .map(|_| OptionalHash::some(H256::random()))
+            .collect();
+        let mut merkle =
+            AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(leaves.clone(), 0, None);
+        merkle.commit(Some(0));
+        let root = merkle.root();
+
+        let proof = merkle.gen_segment_proof(1).unwrap();
+        assert_eq!(proof.start_index, LEAVES_PER_SEGMENT);
+        assert_eq!(proof.end_index, 2 * LEAVES_PER_SEGMENT);
+        let segment = &leaves[proof.start_index..proof.end_index];
+        assert!(proof.validate::<Sha3Algorithm>(segment, &root).is_ok());
+        assert!(proof
+            .validate::<Sha3Algorithm>(segment, &OptionalHash::some(H256::random()))
+            .is_err());
+
+        // any tampered leaf fails the whole segment
+        let mut tampered = segment.to_vec();
+        tampered[LEAVES_PER_SEGMENT / 2] = OptionalHash::some(H256::random());
+        assert!(proof.validate::<Sha3Algorithm>(&tampered, &root).is_err());
+
+        // the last segment is truncated at the last leaf
+        let proof = merkle.gen_segment_proof(2).unwrap();
+        assert_eq!(proof.end_index, leaves.len());
+        assert!(proof
+            .validate::<Sha3Algorithm>(&leaves[proof.start_index..], &root)
+            .is_ok());
+
+        assert!(merkle.gen_segment_proof(3).is_err());
+    }
+
     fn verify(data: &[H256], merkle: &mut AppendMerkleTree<OptionalHash, Sha3Algorithm>) {
         for (i, item) in data.iter().enumerate() {
             let proof = merkle.gen_proof(i + 1).unwrap();
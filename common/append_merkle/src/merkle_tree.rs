@@ -1,17 +1,45 @@
 use crate::sha3::Sha3Algorithm;
 use crate::{Proof, RangeProof};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use anyhow::{bail, Result};
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::ops::Deref;
 use ethereum_types::H256;
-use once_cell::sync::Lazy;
+use once_cell::race::OnceBox;
+#[cfg(feature = "std")]
 use ssz::{Decode, Encode};
-use std::fmt::Debug;
-use std::hash::Hash;
 use tracing::trace;
 
 /// A wrapper around Option<H256> that properly handles null hashes
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct OptionalHash(pub Option<H256>);
 
+/// The byte encodings of `OptionalHash`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OptionalHashEncoding {
+    /// 33 bytes of a flag byte and the hash, where the flag is 1 for `Some` and 0 for `None`,
+    /// and the hash of `None` is all zeros. This is used by SSZ and the storage.
+    ///
+    /// `None` and `Some(H256::zero())` never collide, because their flag bytes differ.
+    #[default]
+    Flagged,
+    /// 32 bytes of the hash, where `None` is encoded as all zeros.
+    ///
+    /// `Some(H256::zero())` cannot be encoded since it would collide with `None`.
+    Compact,
+}
+
+impl OptionalHashEncoding {
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            OptionalHashEncoding::Flagged => 33,
+            OptionalHashEncoding::Compact => 32,
+        }
+    }
+}
+
 impl OptionalHash {
     pub fn some(hash: H256) -> Self {
         OptionalHash(Some(hash))
@@ -67,6 +95,48 @@ impl OptionalHash {
         bytes
     }
 
+    /// Returns true if it cannot be encoded with `OptionalHashEncoding::Compact`
+    /// without being decoded as `None`.
+    pub fn is_ambiguous_compact(&self) -> bool {
+        self.0 == Some(H256::zero())
+    }
+
+    /// Returns true if `bytes` is the encoding of `None` in `mode`.
+    pub fn is_null_encoding(bytes: &[u8], mode: OptionalHashEncoding) -> bool {
+        bytes.len() == mode.encoded_len() && bytes.iter().all(|b| *b == 0)
+    }
+
+    /// Encode with `mode`, which fails if the encoding is ambiguous.
+    pub fn encode_mode(&self, mode: OptionalHashEncoding) -> Result<Vec<u8>, &'static str> {
+        match mode {
+            OptionalHashEncoding::Flagged => Ok(self.as_bytes().to_vec()),
+            OptionalHashEncoding::Compact => {
+                if self.is_ambiguous_compact() {
+                    return Err("Zero hash cannot be encoded in compact mode");
+                }
+                Ok(self.unwrap_or(H256::zero()).as_bytes().to_vec())
+            }
+        }
+    }
+
+    /// Decode the bytes encoded with `mode`. Unlike `from_bytes`, this rejects `None` with
+    /// non-zero hash bytes, so each value has exactly one encoding.
+    pub fn decode_mode(bytes: &[u8], mode: OptionalHashEncoding) -> Result<Self, &'static str> {
+        if bytes.len() != mode.encoded_len() {
+            return Err("Invalid byte length for OptionalHash");
+        }
+        if Self::is_null_encoding(bytes, mode) {
+            return Ok(OptionalHash::none());
+        }
+        match mode {
+            OptionalHashEncoding::Flagged => match bytes[0] {
+                0 => Err("Non-zero hash bytes for None OptionalHash"),
+                _ => Self::from_bytes(bytes.try_into().expect("length checked")),
+            },
+            OptionalHashEncoding::Compact => Self::from_slice(bytes),
+        }
+    }
+
     /// Create OptionalHash from storage bytes (33 bytes)
     pub fn from_bytes(bytes: &[u8; 33]) -> Result<Self, &'static str> {
         match bytes[0] {
@@ -115,6 +185,7 @@ impl AsMut<[u8]> for OptionalHash {
     }
 }
 
+#[cfg(feature = "std")]
 impl Encode for OptionalHash {
     fn is_ssz_fixed_len() -> bool {
         true
@@ -142,6 +213,7 @@ impl Encode for OptionalHash {
     }
 }
 
+#[cfg(feature = "std")]
 impl Decode for OptionalHash {
     fn is_ssz_fixed_len() -> bool {
         true
@@ -175,8 +247,22 @@ impl Decode for OptionalHash {
 unsafe impl Send for OptionalHash {}
 unsafe impl Sync for OptionalHash {}
 
+/// The SSZ encoding required of the hash elements with `std`, since SSZ is not available in
+/// no_std.
+#[cfg(feature = "std")]
+pub trait MaybeSsz: Decode + Encode {}
+#[cfg(feature = "std")]
+impl<T: Decode + Encode> MaybeSsz for T {}
+
+/// The SSZ encoding required of the hash elements with `std`, since SSZ is not available in
+/// no_std.
+#[cfg(not(feature = "std"))]
+pub trait MaybeSsz {}
+#[cfg(not(feature = "std"))]
+impl<T> MaybeSsz for T {}
+
 pub trait HashElement:
-    Clone + Debug + Eq + Hash + AsRef<[u8]> + AsMut<[u8]> + Decode + Encode + Send + Sync
+    Clone + Debug + Eq + Hash + AsRef<[u8]> + AsMut<[u8]> + MaybeSsz + Send + Sync
 {
     fn end_pad(height: usize) -> Self;
     fn null() -> Self;
@@ -210,17 +296,39 @@ impl HashElement for H256 {
     }
 }
 
-pub static ZERO_HASHES: Lazy<[H256; 64]> = Lazy::new(|| {
-    let leaf_zero_hash: H256 = Sha3Algorithm::leaf_raw(&[0u8; 256]);
-    let mut list = [H256::zero(); 64];
-    list[0] = leaf_zero_hash;
-    for i in 1..list.len() {
-        list[i] = Sha3Algorithm::parent_raw(&list[i - 1], &list[i - 1]);
+/// The roots of the subtrees of zero leaves by height, which are computed at the first use.
+/// It's initialized without `std::sync`, so that it's also available in no_std.
+pub struct ZeroHashes(OnceBox<[H256; 64]>);
+
+impl Deref for ZeroHashes {
+    type Target = [H256; 64];
+
+    fn deref(&self) -> &Self::Target {
+        self.0.get_or_init(|| {
+            let leaf_zero_hash: H256 = Sha3Algorithm::leaf_raw(&[0u8; 256]);
+            let mut list = [H256::zero(); 64];
+            list[0] = leaf_zero_hash;
+            for i in 1..list.len() {
+                list[i] = Sha3Algorithm::parent_raw(&list[i - 1], &list[i - 1]);
+            }
+            Box::new(list)
+        })
     }
-    list
-});
+}
+
+pub static ZERO_HASHES: ZeroHashes = ZeroHashes(OnceBox::new());
+
+/// Identifier of the sha3 algorithm, which is also assumed for proofs without an identifier.
+pub const SHA3_ALGORITHM_ID: u8 = 0;
+
+/// Identifier of the sha3 algorithm with domain separation between leaf and parent hashes.
+pub const DOMAIN_SEPARATED_SHA3_ALGORITHM_ID: u8 = 1;
 
 pub trait Algorithm<E: HashElement> {
+    /// Identifier of the algorithm tagged in proofs, so that a verifier does not verify a
+    /// proof with a different algorithm.
+    const ID: u8;
+
     fn parent(left: &E, right: &E) -> E;
     fn parent_single(r: &E, height: usize) -> E {
         let right = E::end_pad(height);
@@ -234,6 +342,12 @@ pub trait MerkleTreeRead {
     fn node(&self, layer: usize, index: usize) -> Self::E;
     fn height(&self) -> usize;
     fn layer_len(&self, layer_height: usize) -> usize;
+
+    /// Algorithm identifier to tag the generated proofs with, which is `None` for the default
+    /// algorithm so that the proofs remain compatible with the verifiers without tags.
+    fn algorithm_tag(&self) -> Option<u8> {
+        None
+    }
     fn padding_node(&self, height: usize) -> Self::E;
 
     fn leaves(&self) -> usize {
@@ -244,54 +358,49 @@ pub trait MerkleTreeRead {
         self.node(self.height() - 1, 0)
     }
 
+    /// Fails with `MissingProofNodes` if any node of the proof is null, which could be
+    /// downcast from the error to request the missing nodes.
     fn gen_proof(&self, leaf_index: usize) -> Result<Proof<Self::E>> {
-        if leaf_index >= self.leaves() {
+        let mut builder = ProofBuilder::new(self, leaf_index)?;
+        while !builder.is_done(self) {
+            builder.step(self);
+        }
+        builder.finish(self)
+    }
+
+    /// Returns the length of the SSZ encoding of the proof of `leaf_index`, without generating
+    /// the proof.
+    ///
+    /// A proof always has `height + 1` nodes and `height - 1` directions whatever the leaf
+    /// position, because a missing sibling at the end of a layer is filled by the padding node
+    /// instead of being omitted, so the size only depends on the tree height. The algorithm tag
+    /// is not encoded.
+    fn proof_size_bytes(&self, _leaf_index: usize) -> usize {
+        let height = self.height();
+        // offsets of the 2 variable-length fields `lemma` and `path`
+        2 * ssz::BYTES_PER_LENGTH_OFFSET
+            + (height + 1) * <Self::E as Encode>::ssz_fixed_len()
+            + height.saturating_sub(1) * <bool as Encode>::ssz_fixed_len()
+    }
+
+    /// Generates the proof of a leaf up to the root of the perfect subtree at `height` that
+    /// contains it, e.g. a peak returned by `peaks()`, instead of the tree root. The proof
+    /// position is the leaf position within the subtree.
+    fn gen_subtree_proof(&self, leaf_index: usize, height: usize) -> Result<Proof<Self::E>> {
+        let subtree_index = leaf_index >> height;
+        if height >= self.height() || (subtree_index + 1) << height > self.leaves() {
             bail!(
-                "leaf index out of bound: leaf_index={} total_leaves={}",
+                "subtree is not complete: leaf_index={} height={} total_leaves={}",
                 leaf_index,
-                self.leaves()
-            );
-        }
-        if self.node(0, leaf_index).is_null() {
-            bail!("Not ready to generate proof for leaf_index={}", leaf_index);
-        }
-        if self.height() == 1 {
-            return Proof::new(vec![self.root(), self.root().clone()], vec![]);
-        }
-        let mut lemma: Vec<Self::E> = Vec::with_capacity(self.height()); // path + root
-        let mut path: Vec<bool> = Vec::with_capacity(self.height() - 2); // path - 1
-        let mut index_in_layer = leaf_index;
-        lemma.push(self.node(0, leaf_index));
-        for height in 0..(self.height() - 1) {
-            trace!(
-                "gen_proof: height={} index={} hash={:?}",
                 height,
-                index_in_layer,
-                self.node(height, index_in_layer)
+                self.leaves()
             );
-            if index_in_layer % 2 == 0 {
-                path.push(true);
-                if index_in_layer + 1 == self.layer_len(height) {
-                    // TODO: This can be skipped if the tree size is available in validation.
-                    lemma.push(self.padding_node(height));
-                } else {
-                    lemma.push(self.node(height, index_in_layer + 1));
-                }
-            } else {
-                path.push(false);
-                lemma.push(self.node(height, index_in_layer - 1));
-            }
-            index_in_layer >>= 1;
         }
-        lemma.push(self.root());
-        if lemma.iter().any(|e| e.is_null()) {
-            bail!(
-                "Not enough data to generate proof, lemma={:?} path={:?}",
-                lemma,
-                path
-            );
+        let mut builder = ProofBuilder::new(self, leaf_index)?;
+        for _ in 0..height {
+            builder.step(self);
         }
-        Proof::new(lemma, path)
+        builder.finish_at_subtree_root(self)
     }
 
     fn gen_range_proof(&self, start_index: usize, end_index: usize) -> Result<RangeProof<Self::E>> {
@@ -312,6 +421,143 @@ pub trait MerkleTreeRead {
     }
 }
 
+/// The nodes of a proof that are null in the tree, e.g. since the data is not downloaded yet,
+/// so the proof cannot be generated. The positions are ordered from the leaf to the root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingProofNodes {
+    /// `(layer, index)` of the null nodes, where the leaves are at layer 0.
+    pub positions: Vec<(usize, usize)>,
+    /// Number of the nodes of the proof, including the leaf and the root.
+    pub proof_nodes: usize,
+}
+
+impl core::fmt::Display for MissingProofNodes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Not enough data to generate proof, {} of {} nodes missing",
+            self.positions.len(),
+            self.proof_nodes
+        )?;
+        if let Some((layer, index)) = self.positions.first() {
+            write!(f, ", first at layer={} index={}", layer, index)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MissingProofNodes {}
+
+/// Builds the proof of a leaf one layer at a time, so that the generation can be suspended at
+/// layer boundaries.
+pub(crate) struct ProofBuilder<E: HashElement> {
+    lemma: Vec<E>,
+    /// `(layer, index)` of each node of `lemma`.
+    positions: Vec<(usize, usize)>,
+    path: Vec<bool>,
+    index_in_layer: usize,
+    height: usize,
+}
+
+impl<E: HashElement> ProofBuilder<E> {
+    pub(crate) fn new<T: MerkleTreeRead<E = E> + ?Sized>(
+        tree: &T,
+        leaf_index: usize,
+    ) -> Result<Self> {
+        if leaf_index >= tree.leaves() {
+            bail!(
+                "leaf index out of bound: leaf_index={} total_leaves={}",
+                leaf_index,
+                tree.leaves()
+            );
+        }
+        if tree.node(0, leaf_index).is_null() {
+            bail!("Not ready to generate proof for leaf_index={}", leaf_index);
+        }
+        let mut lemma: Vec<E> = Vec::with_capacity(tree.height() + 1); // path + root
+        lemma.push(tree.node(0, leaf_index));
+        let mut positions = Vec::with_capacity(tree.height() + 1);
+        positions.push((0, leaf_index));
+        Ok(Self {
+            lemma,
+            positions,
+            path: Vec::with_capacity(tree.height().saturating_sub(1)),
+            index_in_layer: leaf_index,
+            height: 0,
+        })
+    }
+
+    pub(crate) fn is_done<T: MerkleTreeRead<E = E> + ?Sized>(&self, tree: &T) -> bool {
+        self.height + 1 >= tree.height()
+    }
+
+    /// Pushes the sibling node of the current layer.
+    pub(crate) fn step<T: MerkleTreeRead<E = E> + ?Sized>(&mut self, tree: &T) {
+        let height = self.height;
+        let index_in_layer = self.index_in_layer;
+        trace!(
+            "gen_proof: height={} index={} hash={:?}",
+            height,
+            index_in_layer,
+            tree.node(height, index_in_layer)
+        );
+        if index_in_layer % 2 == 0 {
+            self.path.push(true);
+            self.positions.push((height, index_in_layer + 1));
+            if index_in_layer + 1 == tree.layer_len(height) {
+                // TODO: This can be skipped if the tree size is available in validation.
+                self.lemma.push(tree.padding_node(height));
+            } else {
+                self.lemma.push(tree.node(height, index_in_layer + 1));
+            }
+        } else {
+            self.path.push(false);
+            self.positions.push((height, index_in_layer - 1));
+            self.lemma.push(tree.node(height, index_in_layer - 1));
+        }
+        self.index_in_layer >>= 1;
+        self.height += 1;
+    }
+
+    pub(crate) fn finish<T: MerkleTreeRead<E = E> + ?Sized>(
+        mut self,
+        tree: &T,
+    ) -> Result<Proof<E>> {
+        // A single leaf is proved by itself as the root.
+        self.lemma.push(tree.root());
+        self.positions.push((tree.height().saturating_sub(1), 0));
+        self.build(tree)
+    }
+
+    /// Completes the proof with the node of the current layer as the root.
+    pub(crate) fn finish_at_subtree_root<T: MerkleTreeRead<E = E> + ?Sized>(
+        mut self,
+        tree: &T,
+    ) -> Result<Proof<E>> {
+        self.lemma.push(tree.node(self.height, self.index_in_layer));
+        self.positions.push((self.height, self.index_in_layer));
+        self.build(tree)
+    }
+
+    fn build<T: MerkleTreeRead<E = E> + ?Sized>(self, tree: &T) -> Result<Proof<E>> {
+        if self.lemma.iter().any(|e| e.is_null()) {
+            let positions = self
+                .lemma
+                .iter()
+                .zip(&self.positions)
+                .filter(|(e, _)| e.is_null())
+                .map(|(_, position)| *position)
+                .collect();
+            return Err(anyhow::Error::msg(MissingProofNodes {
+                positions,
+                proof_nodes: self.lemma.len(),
+            }));
+        }
+        Ok(Proof::new(self.lemma, self.path)?.with_algorithm_tag(tree.algorithm_tag()))
+    }
+}
+
 pub trait MerkleTreeWrite {
     type E: HashElement;
     fn push_node(&mut self, layer: usize, node: Self::E);
@@ -1,43 +1,96 @@
 use crate::HashElement;
 use anyhow::Result;
 use lru::LruCache;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
+use std::collections::HashMap;
+use std::mem::size_of;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
 use tracing::error;
 
+/// Memory used by the cached nodes of a tree.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeCacheFootprint {
+    /// Number of nodes in the LRU cache.
+    pub cached_nodes: usize,
+    /// Number of nodes in the pinned top layers, which are never evicted.
+    pub pinned_nodes: usize,
+    /// Estimated memory size in bytes of the pinned nodes.
+    pub pinned_bytes: usize,
+}
+
 pub struct NodeManager<E: HashElement> {
     cache: LruCache<(usize, usize), E>,
+    /// Nodes of the top `pinned_layers` layers, which are traversed by every proof.
+    pinned: HashMap<(usize, usize), E>,
+    pinned_layers: usize,
+    /// Whether to drop the persisted nodes out of the right-most spine from the cache.
+    flush_completed_subtrees: bool,
     layer_size: Vec<usize>,
     db: Arc<dyn NodeDatabase<E>>,
     db_tx: Option<Box<dyn NodeTransaction<E>>>,
 }
 
 impl<E: HashElement> NodeManager<E> {
-    pub fn new(db: Arc<dyn NodeDatabase<E>>, capacity: usize) -> Result<Self> {
+    /// Nodes of the top `pinned_layers` layers are kept in memory permanently, and nodes
+    /// below them are cached in a LRU cache of `capacity`.
+    pub fn new(
+        db: Arc<dyn NodeDatabase<E>>,
+        capacity: usize,
+        pinned_layers: usize,
+    ) -> Result<Self> {
         let mut layer = 0;
         let mut layer_size = Vec::new();
         while let Some(size) = db.get_layer_size(layer)? {
             layer_size.push(size);
             layer += 1;
         }
-        Ok(Self {
+        let mut manager = Self {
             cache: LruCache::new(NonZeroUsize::new(capacity).expect("capacity should be non-zero")),
+            pinned: HashMap::new(),
+            pinned_layers,
+            flush_completed_subtrees: false,
             layer_size,
             db,
             db_tx: None,
-        })
+        };
+        for layer in manager.num_layers().saturating_sub(pinned_layers)..manager.num_layers() {
+            manager.pin_layer(layer);
+        }
+        Ok(manager)
     }
 
     pub fn new_dummy() -> Self {
         Self {
             cache: LruCache::unbounded(),
+            pinned: HashMap::new(),
+            pinned_layers: 0,
+            flush_completed_subtrees: false,
             layer_size: vec![],
             db: Arc::new(EmptyNodeDatabase {}),
             db_tx: None,
         }
     }
 
+    /// Nodes in completed subtrees never change once appended, so they could be dropped from
+    /// the cache once committed to db, and only the right-most spine of the tree is kept in
+    /// memory. Dropped nodes are loaded from db when read.
+    ///
+    /// This should not be enabled for an in-memory tree without db.
+    pub fn set_flush_completed_subtrees(&mut self, enabled: bool) {
+        self.flush_completed_subtrees = enabled;
+    }
+
+    pub fn footprint(&self) -> NodeCacheFootprint {
+        NodeCacheFootprint {
+            cached_nodes: self.cache.len(),
+            pinned_nodes: self.pinned.len(),
+            pinned_bytes: self.pinned.len() * (size_of::<(usize, usize)>() + size_of::<E>()),
+        }
+    }
+
     pub fn push_node(&mut self, layer: usize, node: E) {
         self.add_node(layer, self.layer_size[layer], node);
         self.set_layer_size(layer, self.layer_size[layer] + 1);
@@ -47,7 +100,7 @@ impl<E: HashElement> NodeManager<E> {
         let mut pos = self.layer_size[layer];
         let mut saved_nodes = Vec::with_capacity(nodes.len());
         for node in nodes {
-            self.cache.put((layer, pos), node.clone());
+            self.put_node(layer, pos, node.clone());
             saved_nodes.push((layer, pos, node));
             pos += 1;
         }
@@ -56,6 +109,9 @@ impl<E: HashElement> NodeManager<E> {
     }
 
     pub fn get_node(&self, layer: usize, pos: usize) -> Option<E> {
+        if let Some(node) = self.pinned.get(&(layer, pos)) {
+            return Some(node.clone());
+        }
         match self.cache.peek(&(layer, pos)) {
             Some(node) => Some(node.clone()),
             None => self.db.get_node(layer, pos).unwrap_or_else(|e| {
@@ -76,9 +132,13 @@ impl<E: HashElement> NodeManager<E> {
 
     pub fn add_node(&mut self, layer: usize, pos: usize, node: E) {
         // No need to insert if the value is unchanged.
-        if self.cache.get(&(layer, pos)) != Some(&node) {
+        let unchanged = match self.pinned.get(&(layer, pos)) {
+            Some(pinned) => *pinned == node,
+            None => self.cache.get(&(layer, pos)) == Some(&node),
+        };
+        if !unchanged {
             self.db_tx().save_node(layer, pos, &node);
-            self.cache.put((layer, pos), node);
+            self.put_node(layer, pos, node);
         }
     }
 
@@ -86,6 +146,10 @@ impl<E: HashElement> NodeManager<E> {
         self.layer_size.push(0);
         let layer = self.layer_size.len() - 1;
         self.db_tx().save_layer_size(layer, 0);
+        // The lowest pinned layer is not in the top layers anymore.
+        if let Some(unpinned) = layer.checked_sub(self.pinned_layers) {
+            self.unpin_layer(unpinned);
+        }
     }
 
     pub fn layer_size(&self, layer: usize) -> usize {
@@ -100,6 +164,7 @@ impl<E: HashElement> NodeManager<E> {
         let mut removed_nodes = Vec::new();
         for pos in pos_end..self.layer_size[layer] {
             self.cache.pop(&(layer, pos));
+            self.pinned.remove(&(layer, pos));
             removed_nodes.push((layer, pos));
         }
         self.db_tx().remove_node_list(&removed_nodes);
@@ -111,6 +176,12 @@ impl<E: HashElement> NodeManager<E> {
         if layer == self.num_layers() - 1 {
             self.layer_size.pop();
             self.db_tx().remove_layer_size(layer);
+            // The layer below the pinned layers becomes one of the top layers.
+            if self.pinned_layers > 0 {
+                if let Some(pinned) = layer.checked_sub(self.pinned_layers) {
+                    self.pin_layer(pinned);
+                }
+            }
         }
     }
 
@@ -132,6 +203,28 @@ impl<E: HashElement> NodeManager<E> {
         };
         if let Err(e) = self.db.commit(tx) {
             error!("Failed to commit db transaction: {}", e);
+            // Keep the nodes not persisted in the cache.
+            return;
+        }
+        if self.flush_completed_subtrees {
+            self.flush_completed_nodes();
+        }
+    }
+
+    /// Drops the cached nodes except the last two of each layer, which are read to recompute
+    /// the parents of the appended or updated nodes.
+    fn flush_completed_nodes(&mut self) {
+        let completed: Vec<(usize, usize)> = self
+            .cache
+            .iter()
+            .map(|(key, _)| *key)
+            .filter(|(layer, pos)| match self.layer_size.get(*layer) {
+                Some(size) => pos + 2 < *size,
+                None => true,
+            })
+            .collect();
+        for key in completed {
+            self.cache.pop(&key);
         }
     }
 
@@ -139,6 +232,43 @@ impl<E: HashElement> NodeManager<E> {
         (*self.db_tx.as_mut().expect("tx checked")).as_mut()
     }
 
+    fn is_pinned(&self, layer: usize) -> bool {
+        layer + self.pinned_layers >= self.num_layers()
+    }
+
+    fn put_node(&mut self, layer: usize, pos: usize, node: E) {
+        if self.is_pinned(layer) {
+            self.pinned.insert((layer, pos), node);
+        } else {
+            self.cache.put((layer, pos), node);
+        }
+    }
+
+    /// Moves the nodes of `layer` from the LRU cache or db into the pinned nodes.
+    fn pin_layer(&mut self, layer: usize) {
+        for pos in 0..self.layer_size[layer] {
+            let node = match self.cache.pop(&(layer, pos)) {
+                Some(node) => Some(node),
+                None => self.db.get_node(layer, pos).unwrap_or_else(|e| {
+                    error!("Failed to get node: {}", e);
+                    None
+                }),
+            };
+            if let Some(node) = node {
+                self.pinned.insert((layer, pos), node);
+            }
+        }
+    }
+
+    /// Moves the pinned nodes of `layer` into the LRU cache.
+    fn unpin_layer(&mut self, layer: usize) {
+        for pos in 0..self.layer_size[layer] {
+            if let Some(node) = self.pinned.remove(&(layer, pos)) {
+                self.cache.put((layer, pos), node);
+            }
+        }
+    }
+
     fn set_layer_size(&mut self, layer: usize, size: usize) {
         self.layer_size[layer] = size;
         self.db_tx().save_layer_size(layer, size);
@@ -1,12 +1,40 @@
-use crate::{ensure_eq, Algorithm, HashElement};
+use crate::{ensure_eq, Algorithm, HashElement, LEAVES_PER_SEGMENT, SHA3_ALGORITHM_ID};
+use alloc::vec;
+use alloc::vec::Vec;
 use anyhow::{bail, ensure, Result};
+use ethereum_types::{H256, U256};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
 use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 
-#[derive(Clone, Debug, Eq, PartialEq, DeriveEncode, DeriveDecode, Deserialize, Serialize)]
+/// Whether `Proof::validate_strict` accepts a claimed leaf of `E::null()` or all zeros, which
+/// is ambiguous with a null or padding position since the null `OptionalHash` is encoded as
+/// zeros, and so is the padding leaf.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZeroLeafPolicy {
+    #[default]
+    Reject,
+    /// Accept a zero leaf, e.g. if the position is known to hold a real zero-valued leaf.
+    Allow,
+}
+
+/// Default maximum depth of a proof, i.e. the number of siblings between the leaf and the
+/// root, which is the maximum tree height supported by `ZERO_HASHES`. Deeper proofs are
+/// rejected before hashing, so that a proof from an untrusted source cannot cause excessive
+/// computation with an enormous lemma.
+pub const DEFAULT_MAX_PROOF_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "std", derive(DeriveEncode, DeriveDecode))]
 pub struct Proof<T: HashElement> {
     lemma: Vec<T>,
     path: Vec<bool>,
+    /// Identifier of the algorithm to verify this proof, which is sha3 if not tagged. It's
+    /// not included in the SSZ encoding, which is only used between nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "std", ssz(skip_serializing))]
+    #[cfg_attr(feature = "std", ssz(skip_deserializing))]
+    algorithm: Option<u8>,
 }
 
 impl<T: HashElement> Proof<T> {
@@ -19,14 +47,63 @@ impl<T: HashElement> Proof<T> {
                 path.len()
             );
         }
-        Ok(Proof { lemma: hash, path })
+        Ok(Proof {
+            lemma: hash,
+            path,
+            algorithm: None,
+        })
     }
 
     pub fn new_empty() -> Proof<T> {
         Proof {
             lemma: vec![],
             path: vec![],
+            algorithm: None,
+        }
+    }
+
+    /// Tags this proof with the algorithm identifier, or removes the tag if `None`.
+    pub fn with_algorithm_tag(mut self, algorithm: Option<u8>) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Return the algorithm identifier tagged in this proof, if any.
+    pub fn algorithm_tag(&self) -> Option<u8> {
+        self.algorithm
+    }
+
+    /// Return the algorithm to verify this proof, which is sha3 if not tagged.
+    pub fn algorithm(&self) -> u8 {
+        self.algorithm.unwrap_or(SHA3_ALGORITHM_ID)
+    }
+
+    /// Return the number of siblings between the leaf and the root.
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    fn check_depth(&self, max_depth: usize) -> Result<()> {
+        if self.lemma.len() > max_depth + 2 || self.path.len() > max_depth {
+            bail!(
+                "Proof too deep: lemma={} path={} max_depth={}",
+                self.lemma.len(),
+                self.path.len(),
+                max_depth
+            );
+        }
+        Ok(())
+    }
+
+    fn check_algorithm<A: Algorithm<T>>(&self) -> Result<()> {
+        if self.algorithm() != A::ID {
+            bail!(
+                "Proof algorithm mismatch: proof={} verifier={}",
+                self.algorithm(),
+                A::ID
+            );
         }
+        Ok(())
     }
 
     /// Return proof target leaf
@@ -39,11 +116,12 @@ impl<T: HashElement> Proof<T> {
         self.lemma.last().unwrap().clone()
     }
 
-    /// Verifies MT inclusion proof
-    fn validate_integrity<A: Algorithm<T>>(&self) -> bool {
+    /// Verifies MT inclusion proof, which fails without hashing if the proof is deeper than
+    /// `max_depth`.
+    fn validate_integrity<A: Algorithm<T>>(&self, max_depth: usize) -> bool {
         let size = self.lemma.len();
 
-        if size < 2 {
+        if size < 2 || self.algorithm() != A::ID || self.check_depth(max_depth).is_err() {
             return false;
         }
         let mut h = self.item();
@@ -60,7 +138,20 @@ impl<T: HashElement> Proof<T> {
     }
 
     pub fn validate<A: Algorithm<T>>(&self, item: &T, position: usize) -> Result<()> {
-        if !self.validate_integrity::<A>() {
+        self.validate_with_max_depth::<A>(item, position, DEFAULT_MAX_PROOF_DEPTH)
+    }
+
+    /// Validates the proof as `validate`, and rejects the proof deeper than `max_depth` before
+    /// any hashing, e.g. with the height of the largest expected tree.
+    pub fn validate_with_max_depth<A: Algorithm<T>>(
+        &self,
+        item: &T,
+        position: usize,
+        max_depth: usize,
+    ) -> Result<()> {
+        self.check_depth(max_depth)?;
+        self.check_algorithm::<A>()?;
+        if !self.validate_integrity::<A>(max_depth) {
             bail!("Invalid proof");
         }
         if *item != self.item() {
@@ -72,6 +163,103 @@ impl<T: HashElement> Proof<T> {
         Ok(())
     }
 
+    /// Validates the proof as `validate`, and rejects a claimed leaf of `T::null()` or all zeros
+    /// unless `zero_leaf` allows it, so that a null or padding position cannot be passed off as
+    /// a real leaf. `validate` is the relaxed mode, which accepts any leaf.
+    pub fn validate_strict<A: Algorithm<T>>(
+        &self,
+        item: &T,
+        position: usize,
+        zero_leaf: ZeroLeafPolicy,
+    ) -> Result<()> {
+        if zero_leaf == ZeroLeafPolicy::Reject && is_zero_leaf(item) {
+            bail!("Proof of a null or zero leaf: position={}", position);
+        }
+        self.validate::<A>(item, position)
+    }
+
+    /// Validates the proof structure as `validate`, and checks the root against the caller
+    /// supplied `expected_root` instead of trusting the root in the lemma.
+    pub fn validate_with_root<A: Algorithm<T>>(
+        &self,
+        item: &T,
+        position: usize,
+        expected_root: &T,
+    ) -> Result<()> {
+        self.validate::<A>(item, position)?;
+        if self.root() != *expected_root {
+            bail!(
+                "Proof root mismatch: root={:?} expected={:?}",
+                self.root(),
+                expected_root
+            );
+        }
+        Ok(())
+    }
+
+    /// Validates the proof generated by `gen_subtree_proof` of the leaf at `position` in the
+    /// tree against `subtree_root`, the root of the perfect subtree at `height` that contains
+    /// the leaf, e.g. a peak returned by `peaks()`.
+    pub fn validate_with_subtree_root<A: Algorithm<T>>(
+        &self,
+        item: &T,
+        position: usize,
+        subtree_root: &T,
+        height: usize,
+    ) -> Result<()> {
+        if self.depth() != height {
+            bail!(
+                "Proof height mismatch: depth={} height={}",
+                self.depth(),
+                height
+            );
+        }
+        let position_in_subtree = position
+            & 1usize
+                .checked_shl(height as u32)
+                .map_or(usize::MAX, |size| size - 1);
+        self.validate_with_root::<A>(item, position_in_subtree, subtree_root)
+    }
+
+    /// Returns the nodes on the path from the leaf to the root derived from the lemma, where
+    /// the node at index `i` is at layer `i`, i.e. the first one is the leaf and the last one
+    /// is the derived root.
+    pub fn derived_nodes<A: Algorithm<T>>(&self) -> Vec<T> {
+        let mut nodes = Vec::with_capacity(self.path.len() + 1);
+        if self.lemma.is_empty() {
+            return nodes;
+        }
+        let mut h = self.item();
+        nodes.push(h.clone());
+        for (i, is_left) in self.path.iter().enumerate() {
+            h = if *is_left {
+                A::parent(&h, &self.lemma[i + 1])
+            } else {
+                A::parent(&self.lemma[i + 1], &h)
+            };
+            nodes.push(h.clone());
+        }
+        nodes
+    }
+
+    /// Returns the first layer at which the derived node of this proof differs from that of
+    /// `reference`, the proof of the same position against another root, or `None` if all the
+    /// derived nodes are the same.
+    pub fn first_divergence<A: Algorithm<T>>(
+        &self,
+        reference: &Proof<T>,
+    ) -> Option<ProofDivergence<T>> {
+        let derived = self.derived_nodes::<A>();
+        let expected = reference.derived_nodes::<A>();
+        (0..derived.len().max(expected.len()))
+            .map(|layer| ProofDivergence {
+                layer,
+                derived: derived.get(layer).cloned(),
+                expected: expected.get(layer).cloned(),
+            })
+            .find(|divergence| divergence.derived != divergence.expected)
+    }
+
     /// Returns the path of this proof.
     pub fn path(&self) -> &[bool] {
         &self.path
@@ -157,7 +345,339 @@ impl<T: HashElement> Proof<T> {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, DeriveEncode, DeriveDecode, Deserialize, Serialize)]
+/// The first layer from the leaf at which the derived node of a proof differs from the node
+/// implied by the expected root. A node is `None` if the tree is lower than the layer.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct ProofDivergence<T: HashElement> {
+    pub layer: usize,
+    pub derived: Option<T>,
+    pub expected: Option<T>,
+}
+
+/// The outcome to verify a proof of the raw leaf data, which tells whether the client sent
+/// wrong data or the proof is bad.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum VerifyOutcome<T: HashElement> {
+    Valid,
+    /// The proof is deeper than the maximum depth, so it's rejected before hashing.
+    TooDeep {
+        depth: usize,
+        max_depth: usize,
+    },
+    /// The proof is tagged for an algorithm other than the one of the verifier.
+    AlgorithmMismatch {
+        proof: u8,
+        verifier: u8,
+    },
+    /// The hash of the data differs from the leaf claimed by the proof.
+    LeafHashMismatch {
+        computed: T,
+        claimed: T,
+    },
+    /// The proof does not reconstruct the root in its lemma from the leaf.
+    InvalidProof,
+    /// The proof is for another leaf index.
+    PositionMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The proof reconstructs a root different from the expected one.
+    RootMismatch {
+        derived: T,
+        expected: T,
+    },
+}
+
+impl<T: HashElement> VerifyOutcome<T> {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, VerifyOutcome::Valid)
+    }
+}
+
+fn is_zero_leaf<T: HashElement>(leaf: &T) -> bool {
+    leaf.is_null() || leaf.as_ref().iter().all(|byte| *byte == 0)
+}
+
+/// Verifies that `data` is the leaf at `leaf_index` of the tree with `root`. The leaf hash is
+/// checked before the proof itself, so that a mismatched leaf is reported even if the proof
+/// is built for the wrong data.
+pub fn verify_data_proof<T: HashElement, A: Algorithm<T>>(
+    root: &T,
+    data: &[u8],
+    leaf_index: usize,
+    proof: &Proof<T>,
+) -> VerifyOutcome<T> {
+    verify_data_proof_with_max_depth::<T, A>(root, data, leaf_index, proof, DEFAULT_MAX_PROOF_DEPTH)
+}
+
+/// Verifies the data proof as `verify_data_proof`, and rejects the proof deeper than
+/// `max_depth` before any hashing.
+pub fn verify_data_proof_with_max_depth<T: HashElement, A: Algorithm<T>>(
+    root: &T,
+    data: &[u8],
+    leaf_index: usize,
+    proof: &Proof<T>,
+    max_depth: usize,
+) -> VerifyOutcome<T> {
+    if proof.check_depth(max_depth).is_err() {
+        return VerifyOutcome::TooDeep {
+            depth: proof.depth(),
+            max_depth,
+        };
+    }
+    if proof.lemma.len() < 2 || proof.lemma.len() != proof.path.len() + 2 {
+        return VerifyOutcome::InvalidProof;
+    }
+    if proof.algorithm() != A::ID {
+        return VerifyOutcome::AlgorithmMismatch {
+            proof: proof.algorithm(),
+            verifier: A::ID,
+        };
+    }
+
+    let computed = A::leaf(data);
+    if computed != proof.item() {
+        return VerifyOutcome::LeafHashMismatch {
+            computed,
+            claimed: proof.item(),
+        };
+    }
+    if !proof.validate_integrity::<A>(max_depth) {
+        return VerifyOutcome::InvalidProof;
+    }
+    if proof.position() != leaf_index {
+        return VerifyOutcome::PositionMismatch {
+            expected: leaf_index,
+            actual: proof.position(),
+        };
+    }
+    if proof.root() != *root {
+        return VerifyOutcome::RootMismatch {
+            derived: proof.root(),
+            expected: root.clone(),
+        };
+    }
+    VerifyOutcome::Valid
+}
+
+/// Default number of proofs in a batch from which `BatchVerifier` verifies them in parallel.
+/// The crossover point of the serial and parallel verification depends on the machine, which
+/// could be measured by the `batch_verify` benchmark.
+pub const DEFAULT_PARALLEL_VERIFY_THRESHOLD: usize = 32;
+
+/// Verifies a batch of data proofs against the same root, e.g. to audit the stored data.
+///
+/// With the `parallel-verify` feature, a batch of at least `parallel_threshold` proofs is
+/// verified by the rayon thread pool, and a smaller batch is verified serially to avoid the
+/// overhead of spawning tasks. The outcomes are the same in either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchVerifier {
+    parallel_threshold: usize,
+    max_proof_depth: usize,
+}
+
+impl Default for BatchVerifier {
+    fn default() -> Self {
+        Self::new(DEFAULT_PARALLEL_VERIFY_THRESHOLD)
+    }
+}
+
+impl BatchVerifier {
+    /// Creates a verifier which verifies a batch in parallel if it has at least
+    /// `parallel_threshold` proofs, so `usize::MAX` always verifies serially.
+    pub fn new(parallel_threshold: usize) -> Self {
+        Self {
+            parallel_threshold,
+            max_proof_depth: DEFAULT_MAX_PROOF_DEPTH,
+        }
+    }
+
+    /// Rejects the proofs deeper than `max_proof_depth` instead of `DEFAULT_MAX_PROOF_DEPTH`,
+    /// e.g. with the height of the largest expected tree.
+    pub fn with_max_proof_depth(mut self, max_proof_depth: usize) -> Self {
+        self.max_proof_depth = max_proof_depth;
+        self
+    }
+
+    pub fn parallel_threshold(&self) -> usize {
+        self.parallel_threshold
+    }
+
+    pub fn max_proof_depth(&self) -> usize {
+        self.max_proof_depth
+    }
+
+    /// Whether a batch of `len` proofs is verified in parallel.
+    pub fn is_parallel(&self, len: usize) -> bool {
+        cfg!(feature = "parallel-verify") && len >= self.parallel_threshold
+    }
+
+    /// Verifies each `(data, leaf_index, proof)` in `batch` with `verify_data_proof`, and
+    /// returns the outcomes in the order of `batch`.
+    pub fn verify<T, A, D>(&self, root: &T, batch: &[(D, usize, Proof<T>)]) -> Vec<VerifyOutcome<T>>
+    where
+        T: HashElement,
+        A: Algorithm<T>,
+        D: AsRef<[u8]> + Sync,
+    {
+        let verify = |(data, leaf_index, proof): &(D, usize, Proof<T>)| {
+            verify_data_proof_with_max_depth::<T, A>(
+                root,
+                data.as_ref(),
+                *leaf_index,
+                proof,
+                self.max_proof_depth,
+            )
+        };
+
+        #[cfg(feature = "parallel-verify")]
+        if self.is_parallel(batch.len()) {
+            use rayon::prelude::*;
+            return batch.par_iter().map(verify).collect();
+        }
+
+        batch.iter().map(verify).collect()
+    }
+}
+
+impl Proof<H256> {
+    /// Convert to the layout of the on-chain verifier.
+    pub fn to_solidity_calldata(&self) -> Result<SolidityCalldata> {
+        if self.path.len() > 256 {
+            bail!("proof path too long: len={}", self.path.len());
+        }
+        let mut path = U256::zero();
+        for (i, is_left) in self.path.iter().enumerate() {
+            if !is_left {
+                path.0[i / 64] |= 1 << (i % 64);
+            }
+        }
+        Ok(SolidityCalldata {
+            lemma: self.lemma.clone(),
+            path,
+        })
+    }
+}
+
+/// A proof of `(bytes32[] lemma, uint256 path)` for the on-chain verifier.
+///
+/// `lemma` is the same as `Proof::lemma`, i.e. the leaf, the siblings from bottom
+/// to top (including the padding nodes) and the root. Bit `i` of `path` is set if the
+/// `i`-th sibling is the left node, so `path` equals the leaf position. The verifier
+/// computes `h = keccak256(abi.encodePacked(lemma[i + 1], h))` if bit `i` is set,
+/// or `h = keccak256(abi.encodePacked(h, lemma[i + 1]))` otherwise.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SolidityCalldata {
+    pub lemma: Vec<H256>,
+    pub path: U256,
+}
+
+impl SolidityCalldata {
+    /// ABI encoding of the arguments `(bytes32[] lemma, uint256 path)`.
+    pub fn abi_encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(32 * (3 + self.lemma.len()));
+        // offset of the dynamic array
+        encoded.extend_from_slice(H256::from_low_u64_be(64).as_bytes());
+        let mut word = [0u8; 32];
+        self.path.to_big_endian(&mut word);
+        encoded.extend_from_slice(&word);
+        encoded.extend_from_slice(H256::from_low_u64_be(self.lemma.len() as u64).as_bytes());
+        for node in &self.lemma {
+            encoded.extend_from_slice(node.as_bytes());
+        }
+        encoded
+    }
+}
+
+/// Order of the nodes in a proof exported to an external verifier.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProofOrder {
+    /// The canonical order of `Proof`: the lemma is the leaf, the siblings from bottom to top
+    /// and the root, and `path[i]` is the direction at the sibling `lemma[i + 1]`.
+    #[default]
+    LeafFirst,
+    /// The reversed order: the lemma is the root, the siblings from top to bottom and the
+    /// leaf, and `path[i]` is still the direction at the sibling `lemma[i + 1]`, i.e. the
+    /// path is reversed as well.
+    RootFirst,
+}
+
+/// A proof laid out in `order` for an external verifier, which could be converted back to
+/// the canonical `Proof`.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct OrderedProof<T: HashElement> {
+    pub order: ProofOrder,
+    pub lemma: Vec<T>,
+    pub path: Vec<bool>,
+    /// The algorithm tag of the proof, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<u8>,
+}
+
+impl<T: HashElement> Proof<T> {
+    /// Export the lemma and path in `order`, without changing this proof.
+    pub fn to_ordered(&self, order: ProofOrder) -> OrderedProof<T> {
+        let mut lemma = self.lemma.clone();
+        let mut path = self.path.clone();
+        if order == ProofOrder::RootFirst {
+            lemma.reverse();
+            path.reverse();
+        }
+        OrderedProof {
+            order,
+            lemma,
+            path,
+            algorithm: self.algorithm,
+        }
+    }
+}
+
+impl<T: HashElement> OrderedProof<T> {
+    /// Convert back to the canonical leaf-first `Proof`.
+    pub fn into_proof(self) -> Result<Proof<T>> {
+        let (mut lemma, mut path) = (self.lemma, self.path);
+        if self.order == ProofOrder::RootFirst {
+            lemma.reverse();
+            path.reverse();
+        }
+        Ok(Proof::new(lemma, path)?.with_algorithm_tag(self.algorithm))
+    }
+}
+
+/// The reason that a range proof fails the verification.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RangeProofError {
+    /// The endpoint proofs have different heights.
+    HeightMismatch,
+    InvalidLeftProof,
+    InvalidRightProof,
+    /// The left endpoint is after the right endpoint.
+    InvalidRange,
+    /// Any endpoint proof is tagged for an algorithm other than the one of the verifier.
+    AlgorithmMismatch,
+    /// Any endpoint proof is deeper than the maximum depth.
+    TooDeep,
+}
+
+impl core::fmt::Display for RangeProofError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RangeProofError::HeightMismatch => write!(f, "endpoint proofs height mismatch"),
+            RangeProofError::InvalidLeftProof => write!(f, "invalid left endpoint proof"),
+            RangeProofError::InvalidRightProof => write!(f, "invalid right endpoint proof"),
+            RangeProofError::InvalidRange => write!(f, "left endpoint after right endpoint"),
+            RangeProofError::AlgorithmMismatch => write!(f, "proof algorithm mismatch"),
+            RangeProofError::TooDeep => write!(f, "endpoint proof too deep"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RangeProofError {}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "std", derive(DeriveEncode, DeriveDecode))]
 pub struct RangeProof<E: HashElement> {
     pub left_proof: Proof<E>,
     pub right_proof: Proof<E>,
@@ -171,9 +691,9 @@ impl<E: HashElement> RangeProof<E> {
         }
     }
 
-    fn validate_integrity<A: Algorithm<E>>(&self) -> bool {
-        self.left_proof.validate_integrity::<A>()
-            && self.right_proof.validate_integrity::<A>()
+    fn validate_integrity<A: Algorithm<E>>(&self, max_depth: usize) -> bool {
+        self.left_proof.validate_integrity::<A>(max_depth)
+            && self.right_proof.validate_integrity::<A>(max_depth)
             && self.left_proof.root() == self.right_proof.root()
             && self.left_proof.path().len() == self.right_proof.path().len()
     }
@@ -182,12 +702,80 @@ impl<E: HashElement> RangeProof<E> {
         self.left_proof.root()
     }
 
+    /// Verifies both endpoint proofs against `root`, and returns which endpoint failed if any.
+    /// The endpoint proofs are verified in parallel with the `parallel-verify` feature.
+    pub fn verify<A: Algorithm<E>>(&self, root: &E) -> core::result::Result<(), RangeProofError> {
+        self.verify_with_max_depth::<A>(root, DEFAULT_MAX_PROOF_DEPTH)
+    }
+
+    /// Verifies the range proof as `verify`, and rejects the endpoint proofs deeper than
+    /// `max_depth` before any hashing.
+    pub fn verify_with_max_depth<A: Algorithm<E>>(
+        &self,
+        root: &E,
+        max_depth: usize,
+    ) -> core::result::Result<(), RangeProofError> {
+        if self.left_proof.check_depth(max_depth).is_err()
+            || self.right_proof.check_depth(max_depth).is_err()
+        {
+            return Err(RangeProofError::TooDeep);
+        }
+        if self.left_proof.algorithm() != A::ID || self.right_proof.algorithm() != A::ID {
+            return Err(RangeProofError::AlgorithmMismatch);
+        }
+        // Both endpoints should be in a single tree of the same height.
+        if self.left_proof.path().len() != self.right_proof.path().len() {
+            return Err(RangeProofError::HeightMismatch);
+        }
+
+        let verify_endpoint =
+            |proof: &Proof<E>| proof.validate_integrity::<A>(max_depth) && proof.root() == *root;
+
+        #[cfg(feature = "parallel-verify")]
+        let (left_valid, right_valid) = rayon::join(
+            || verify_endpoint(&self.left_proof),
+            || verify_endpoint(&self.right_proof),
+        );
+        #[cfg(not(feature = "parallel-verify"))]
+        let (left_valid, right_valid) = (
+            verify_endpoint(&self.left_proof),
+            verify_endpoint(&self.right_proof),
+        );
+
+        if !left_valid {
+            return Err(RangeProofError::InvalidLeftProof);
+        }
+        if !right_valid {
+            return Err(RangeProofError::InvalidRightProof);
+        }
+        if self.left_proof.position() > self.right_proof.position() {
+            return Err(RangeProofError::InvalidRange);
+        }
+
+        Ok(())
+    }
+
     pub fn validate<A: Algorithm<E>>(
         &self,
         range_leaves: &[E],
         start_position: usize,
     ) -> Result<()> {
-        if !self.validate_integrity::<A>() {
+        self.validate_with_max_depth::<A>(range_leaves, start_position, DEFAULT_MAX_PROOF_DEPTH)
+    }
+
+    /// Validates the range proof as `validate`, and rejects the endpoint proofs deeper than
+    /// `max_depth` before any hashing.
+    pub fn validate_with_max_depth<A: Algorithm<E>>(
+        &self,
+        range_leaves: &[E],
+        start_position: usize,
+        max_depth: usize,
+    ) -> Result<()> {
+        self.left_proof.check_depth(max_depth)?;
+        self.right_proof.check_depth(max_depth)?;
+        self.left_proof.check_algorithm::<A>()?;
+        self.right_proof.check_algorithm::<A>()?;
+        if !self.validate_integrity::<A>(max_depth) {
             bail!("Invalid range proof");
         }
         if range_leaves.is_empty() {
@@ -246,3 +834,34 @@ impl<E: HashElement> RangeProof<E> {
         Ok(())
     }
 }
+
+/// The proof of all the leaves in a segment, of which the sibling nodes are shared between the
+/// leaves, so that the whole segment is verified at once.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "std", derive(DeriveEncode, DeriveDecode))]
+pub struct SegmentProof<E: HashElement> {
+    pub segment_index: usize,
+    /// Index of the first leaf in the segment.
+    pub start_index: usize,
+    /// Index after the last leaf in the segment, which is less than a full segment for the
+    /// last segment of the tree.
+    pub end_index: usize,
+    pub proof: RangeProof<E>,
+}
+
+impl<E: HashElement> SegmentProof<E> {
+    /// Verifies that `leaves` are all the leaves of the segment in the tree of `root`.
+    pub fn validate<A: Algorithm<E>>(&self, leaves: &[E], root: &E) -> Result<()> {
+        ensure_eq!(self.start_index, self.segment_index * LEAVES_PER_SEGMENT);
+        ensure!(
+            self.end_index > self.start_index
+                && self.end_index - self.start_index <= LEAVES_PER_SEGMENT,
+            "invalid segment range: start={} end={}",
+            self.start_index,
+            self.end_index
+        );
+        ensure_eq!(leaves.len(), self.end_index - self.start_index);
+        ensure_eq!(self.proof.root(), *root);
+        self.proof.validate::<A>(leaves, self.start_index)
+    }
+}
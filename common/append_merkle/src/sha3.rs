@@ -1,19 +1,27 @@
-use crate::merkle_tree::{OptionalHash, ZERO_HASHES};
+use crate::merkle_tree::{
+    OptionalHash, DOMAIN_SEPARATED_SHA3_ALGORITHM_ID, SHA3_ALGORITHM_ID, ZERO_HASHES,
+};
 use crate::{Algorithm, HashElement};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use ethereum_types::H256;
-use once_cell::sync::Lazy;
-use std::collections::BTreeMap;
+use once_cell::race::OnceBox;
 use tiny_keccak::{Hasher, Keccak};
 
 pub struct Sha3Algorithm {}
 
-static ZERO_HASHES_MAP: Lazy<BTreeMap<H256, H256>> = Lazy::new(|| {
-    let mut map = BTreeMap::new();
-    for i in 1..ZERO_HASHES.len() {
-        map.insert(ZERO_HASHES[i - 1], ZERO_HASHES[i]);
-    }
-    map
-});
+/// The parent of two zero subtrees of the same height by their root.
+static ZERO_HASHES_MAP: OnceBox<BTreeMap<H256, H256>> = OnceBox::new();
+
+fn zero_hashes_map() -> &'static BTreeMap<H256, H256> {
+    ZERO_HASHES_MAP.get_or_init(|| {
+        let mut map = BTreeMap::new();
+        for i in 1..ZERO_HASHES.len() {
+            map.insert(ZERO_HASHES[i - 1], ZERO_HASHES[i]);
+        }
+        Box::new(map)
+    })
+}
 
 impl Sha3Algorithm {
     pub fn parent_raw(left: &H256, right: &H256) -> H256 {
@@ -34,9 +42,11 @@ impl Sha3Algorithm {
     }
 }
 impl Algorithm<H256> for Sha3Algorithm {
+    const ID: u8 = SHA3_ALGORITHM_ID;
+
     fn parent(left: &H256, right: &H256) -> H256 {
         if left == right {
-            if let Some(v) = ZERO_HASHES_MAP.get(left) {
+            if let Some(v) = zero_hashes_map().get(left) {
                 return *v;
             }
         }
@@ -52,6 +62,8 @@ impl Algorithm<H256> for Sha3Algorithm {
 }
 
 impl Algorithm<OptionalHash> for Sha3Algorithm {
+    const ID: u8 = SHA3_ALGORITHM_ID;
+
     fn parent(left: &OptionalHash, right: &OptionalHash) -> OptionalHash {
         match (&left.0, &right.0) {
             (Some(l), Some(r)) => {
@@ -69,3 +81,67 @@ impl Algorithm<OptionalHash> for Sha3Algorithm {
         OptionalHash::some(result)
     }
 }
+
+/// `Sha3Algorithm` with domain separation between leaf and parent hashes, i.e. a leaf hash is
+/// `keccak256(0x00 ++ data)` and a parent hash is `keccak256(0x01 ++ left ++ right)`, so that an
+/// internal node could never be presented as a leaf of the same tree.
+///
+/// It's selected by the algorithm type of a tree, and `Sha3Algorithm` remains the default.
+/// Since it changes all the roots, including the file roots submitted to the flow contract, it
+/// must be adopted by the whole network at once. Its proofs are tagged with a different
+/// algorithm and rejected by the verifiers of `Sha3Algorithm`.
+///
+/// The padding nodes are the same as `Sha3Algorithm`, since they are constants rather than
+/// hashes of the data.
+pub struct DomainSeparatedSha3Algorithm {}
+
+impl DomainSeparatedSha3Algorithm {
+    pub const LEAF_TAG: u8 = 0x00;
+    pub const PARENT_TAG: u8 = 0x01;
+
+    pub fn parent_raw(left: &H256, right: &H256) -> H256 {
+        let mut h = Keccak::v256();
+        let mut e = H256::null();
+        h.update(&[Self::PARENT_TAG]);
+        h.update(left.as_ref());
+        h.update(right.as_ref());
+        h.finalize(e.as_mut());
+        e
+    }
+
+    pub fn leaf_raw(data: &[u8]) -> H256 {
+        let mut h = Keccak::v256();
+        let mut e = H256::null();
+        h.update(&[Self::LEAF_TAG]);
+        h.update(data.as_ref());
+        h.finalize(e.as_mut());
+        e
+    }
+}
+
+impl Algorithm<H256> for DomainSeparatedSha3Algorithm {
+    const ID: u8 = DOMAIN_SEPARATED_SHA3_ALGORITHM_ID;
+
+    fn parent(left: &H256, right: &H256) -> H256 {
+        Self::parent_raw(left, right)
+    }
+
+    fn leaf(data: &[u8]) -> H256 {
+        Self::leaf_raw(data)
+    }
+}
+
+impl Algorithm<OptionalHash> for DomainSeparatedSha3Algorithm {
+    const ID: u8 = DOMAIN_SEPARATED_SHA3_ALGORITHM_ID;
+
+    fn parent(left: &OptionalHash, right: &OptionalHash) -> OptionalHash {
+        match (&left.0, &right.0) {
+            (Some(l), Some(r)) => OptionalHash::some(Self::parent_raw(l, r)),
+            _ => OptionalHash::none(),
+        }
+    }
+
+    fn leaf(data: &[u8]) -> OptionalHash {
+        OptionalHash::some(Self::leaf_raw(data))
+    }
+}
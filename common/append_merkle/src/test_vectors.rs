@@ -0,0 +1,214 @@
+//! Known hashes of `Sha3Algorithm` to validate the merkle tree implementations in other
+//! languages.
+//!
+//! A leaf hash is `keccak256(data)`, where `data` is usually a 256-byte chunk, and a parent hash
+//! is `keccak256(left ++ right)`. The padding leaf is the hash of a zero chunk, and
+//! `ZERO_HASHES[i]` is the root of a subtree of height `i` with only padding leaves.
+//!
+//! The vectors of `DomainSeparatedSha3Algorithm` are of the same inputs, where a leaf hash is
+//! `keccak256(0x00 ++ data)` and a parent hash is `keccak256(0x01 ++ left ++ right)`.
+//!
+//! The vectors are a stable API and never change. They are checked against the algorithms in
+//! the tests.
+
+use ethereum_types::H256;
+use std::str::FromStr;
+
+/// Leaf data and its hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeafVector {
+    pub data: Vec<u8>,
+    pub hash: H256,
+}
+
+/// Two child nodes and their parent hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParentVector {
+    pub left: H256,
+    pub right: H256,
+    pub parent: H256,
+}
+
+const EMPTY_LEAF: &str = "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470";
+const SEQUENCE_LEAF: &str = "dc924469b334aed2a19fac7252e9961aea41f8d91996366029dbe0884229bf36";
+const ONES_LEAF: &str = "10c18b0ffc7f89e6da317ea664365820b9f7f954fa9f03733072f5ccd0826818";
+
+const ZERO_HASHES_PREFIX: [&str; 8] = [
+    "d397b3b043d87fcd6fad1291ff0bfd16401c274896d8c63a923727f077b8e0b5",
+    "f73e6947d7d1628b9976a6e40d7b278a8a16405e96324a68df45b12a51b7cfde",
+    "a1520264ae93cac619e22e8718fc4fa7ebdd23f493cad602434d2a58ff4868fb",
+    "de5747106ac1194a1fa9071dbd6cf19dc2bc7964497ef0afec7e4bdbcf08c47e",
+    "09c7082879180d28c789c05fafe7030871c76cedbe82c948b165d6a1d66ac15b",
+    "aa7a02bcf29fba687f84123c808b5b48834ff5395abe98e622fadc14e4180c95",
+    "7608fd46b710b589e0f2ee5a13cd9c41d432858a30d524f84c6d5db37f66273a",
+    "a5d9a2f7f3573ac9a1366bc484688b4daf934b87ea9b3bf2e703da8fd9f09708",
+];
+
+fn h256(s: &str) -> H256 {
+    H256::from_str(s).expect("valid hash")
+}
+
+/// Returns the leaf hashes of an empty input, a zero chunk, the chunk of bytes `0..=255` and
+/// the chunk of `0x01` bytes.
+pub fn leaf_vectors() -> Vec<LeafVector> {
+    vec![
+        LeafVector {
+            data: vec![],
+            hash: h256(EMPTY_LEAF),
+        },
+        LeafVector {
+            data: vec![0; 256],
+            hash: h256(ZERO_HASHES_PREFIX[0]),
+        },
+        LeafVector {
+            data: (0..=255).collect(),
+            hash: h256(SEQUENCE_LEAF),
+        },
+        LeafVector {
+            data: vec![1; 256],
+            hash: h256(ONES_LEAF),
+        },
+    ]
+}
+
+/// Returns the parent hashes of some leaves in `leaf_vectors()`. The children are not
+/// interchangeable.
+pub fn parent_vectors() -> Vec<ParentVector> {
+    vec![
+        ParentVector {
+            left: H256::zero(),
+            right: H256::zero(),
+            parent: h256("ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5"),
+        },
+        ParentVector {
+            left: h256(ZERO_HASHES_PREFIX[0]),
+            right: h256(ZERO_HASHES_PREFIX[0]),
+            parent: h256(ZERO_HASHES_PREFIX[1]),
+        },
+        ParentVector {
+            left: h256(SEQUENCE_LEAF),
+            right: h256(ONES_LEAF),
+            parent: h256("25329f0925bf936c7c9aeeb639922e103210236dd7025f2191082e087dca1f03"),
+        },
+        ParentVector {
+            left: h256(ONES_LEAF),
+            right: h256(SEQUENCE_LEAF),
+            parent: h256("82c410d5c13cfa87c8dda6d6f789ac822f3a8cadf4c46e9dedd0d787942edda0"),
+        },
+    ]
+}
+
+/// Returns the leaf hashes of `DomainSeparatedSha3Algorithm` of the data in `leaf_vectors()`.
+pub fn domain_separated_leaf_vectors() -> Vec<LeafVector> {
+    let hashes = [
+        "bc36789e7a1e281436464229828f817d6612f7b477d66591ff96a9e064bcc98a",
+        "7a9efb4f2d5fc995fb0c798bd5426724a58aa7b05b62f646b6baed96f5dbfc87",
+        "6e2e82e8f4292b14dd63e1f87349129b30797ce28ad0ed83456535ff2eab88e8",
+        "3e9b8836bd72f81d366f0559bf76bb5ffa9c5188de3fbe5674ba48e76cc53b23",
+    ];
+    leaf_vectors()
+        .into_iter()
+        .zip(hashes)
+        .map(|(v, hash)| LeafVector {
+            data: v.data,
+            hash: h256(hash),
+        })
+        .collect()
+}
+
+/// Returns the parent hashes of `DomainSeparatedSha3Algorithm` of the children in
+/// `parent_vectors()`.
+pub fn domain_separated_parent_vectors() -> Vec<ParentVector> {
+    let hashes = [
+        "c07a1e8b7e0057673fdc2affe190d8a960c5fe615663f27b7ce84f3d93ef92a6",
+        "e2850a3ef8e814afbf36c94ce245078f427ddd7447d8e3b895e2fe0ddb0fafaa",
+        "5be4cbe0607b59700952cad157c4c51c2b700b8188739492e4e5b19dfff4cf8f",
+        "53c563ef49a385fc880b3eba0fc232f2b327ce6055499c7f6383db6aece6fb33",
+    ];
+    parent_vectors()
+        .into_iter()
+        .zip(hashes)
+        .map(|(v, parent)| ParentVector {
+            left: v.left,
+            right: v.right,
+            parent: h256(parent),
+        })
+        .collect()
+}
+
+/// Returns the first values of `ZERO_HASHES`.
+pub fn zero_hash_vectors() -> Vec<H256> {
+    ZERO_HASHES_PREFIX.iter().map(|s| h256(s)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        domain_separated_leaf_vectors, domain_separated_parent_vectors, leaf_vectors,
+        parent_vectors, zero_hash_vectors,
+    };
+    use crate::{
+        Algorithm, AppendMerkleTree, DomainSeparatedSha3Algorithm, MerkleTreeRead, Sha3Algorithm,
+        ZERO_HASHES,
+    };
+    use ethereum_types::H256;
+
+    #[test]
+    fn test_vectors() {
+        for v in leaf_vectors() {
+            assert_eq!(Sha3Algorithm::leaf_raw(&v.data), v.hash);
+            assert_eq!(<Sha3Algorithm as Algorithm<H256>>::leaf(&v.data), v.hash);
+        }
+        for v in parent_vectors() {
+            assert_eq!(Sha3Algorithm::parent_raw(&v.left, &v.right), v.parent);
+            assert_eq!(
+                <Sha3Algorithm as Algorithm<H256>>::parent(&v.left, &v.right),
+                v.parent
+            );
+        }
+        for (i, hash) in zero_hash_vectors().into_iter().enumerate() {
+            assert_eq!(ZERO_HASHES[i], hash);
+        }
+    }
+
+    #[test]
+    fn test_domain_separated_vectors() {
+        type Separated = DomainSeparatedSha3Algorithm;
+
+        for (v, default) in domain_separated_leaf_vectors()
+            .into_iter()
+            .zip(leaf_vectors())
+        {
+            assert_eq!(<Separated as Algorithm<H256>>::leaf(&v.data), v.hash);
+            assert_ne!(v.hash, default.hash);
+        }
+        for (v, default) in domain_separated_parent_vectors()
+            .into_iter()
+            .zip(parent_vectors())
+        {
+            assert_eq!(
+                <Separated as Algorithm<H256>>::parent(&v.left, &v.right),
+                v.parent
+            );
+            assert_ne!(v.parent, default.parent);
+        }
+
+        // the root of the same data differs from the default
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 256]).collect();
+        let default_leaves: Vec<H256> = data
+            .iter()
+            .map(|d| <Sha3Algorithm as Algorithm<H256>>::leaf(d))
+            .collect();
+        let leaves: Vec<H256> = data
+            .iter()
+            .map(|d| <Separated as Algorithm<H256>>::leaf(d))
+            .collect();
+        let default_merkle = AppendMerkleTree::<H256, Sha3Algorithm>::new(default_leaves, 0, None);
+        let merkle = AppendMerkleTree::<H256, Separated>::new(leaves.clone(), 0, None);
+        assert_ne!(merkle.root(), default_merkle.root());
+
+        let proof = merkle.gen_proof(3).unwrap();
+        assert!(proof.validate::<Separated>(&leaves[3], 3).is_ok());
+        assert!(proof.validate::<Sha3Algorithm>(&leaves[3], 3).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::HashElement;
+use anyhow::{bail, Result};
+use std::sync::Mutex;
+
+/// An append of a batch that is recorded in the write-ahead log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WalOp<E: HashElement> {
+    AppendList(Vec<E>),
+    /// An aligned subtree of which only the root is known.
+    AppendSubtree {
+        depth: usize,
+        root: E,
+    },
+    /// An update of the last leaf, which appends no leaf.
+    UpdateLast(E),
+}
+
+impl<E: HashElement> WalOp<E> {
+    /// Returns an error if the op cannot be applied to a tree of `leaves_before` leaves.
+    pub fn check(&self, leaves_before: usize) -> Result<()> {
+        let has_null = match self {
+            WalOp::AppendList(leaves) => leaves.iter().any(|leaf| leaf.is_null()),
+            WalOp::AppendSubtree { depth, root } => {
+                if *depth == 0 || *depth > usize::BITS as usize {
+                    bail!("invalid subtree depth: {}", depth);
+                }
+                root.is_null()
+            }
+            WalOp::UpdateLast(leaf) => {
+                if leaves_before == 0 {
+                    bail!("update the last leaf of an empty tree");
+                }
+                leaf.is_null()
+            }
+        };
+        if has_null {
+            // appending null is not allowed.
+            bail!("op contains null");
+        }
+        Ok(())
+    }
+
+    /// Number of the leaves appended. The op must have passed `check`.
+    pub fn leaves(&self) -> usize {
+        match self {
+            WalOp::AppendList(leaves) => leaves.len(),
+            WalOp::AppendSubtree { depth, .. } => 1 << (depth - 1),
+            WalOp::UpdateLast(_) => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalRecord<E: HashElement> {
+    /// Number of the leaves in the tree before the op is applied, which tells whether the op
+    /// has been applied when replayed.
+    pub leaves_before: usize,
+    pub op: WalOp<E>,
+}
+
+/// A write-ahead log of the pending appends of a tree, which are recorded before they are
+/// applied to the tree, and truncated once the tree is checkpointed.
+pub trait AppendWal<E: HashElement>: Send + Sync {
+    /// Durably appends the records of a batch to the log.
+    fn write(&self, records: &[WalRecord<E>]) -> Result<()>;
+    /// Returns all the records in the order written.
+    fn read(&self) -> Result<Vec<WalRecord<E>>>;
+    fn truncate(&self) -> Result<()>;
+}
+
+/// A write-ahead log in memory, which survives a tree in the same process only, e.g. for tests.
+pub struct MemoryWal<E: HashElement> {
+    records: Mutex<Vec<WalRecord<E>>>,
+}
+
+impl<E: HashElement> Default for MemoryWal<E> {
+    fn default() -> Self {
+        Self {
+            records: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl<E: HashElement> AppendWal<E> for MemoryWal<E> {
+    fn write(&self, records: &[WalRecord<E>]) -> Result<()> {
+        self.records.lock().unwrap().extend_from_slice(records);
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Vec<WalRecord<E>>> {
+        Ok(self.records.lock().unwrap().clone())
+    }
+
+    fn truncate(&self) -> Result<()> {
+        self.records.lock().unwrap().clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Deduplicates the identical warnings repeated by a polling loop, e.g. a miner that is not
+//! authorized or a sync loop that keeps failing for the same reason.
+//!
+//! The first occurrence of a message is logged at once, and the following identical ones are
+//! only counted and summarized as "repeated N times in the last M seconds" once per interval.
+//! A different message is logged at once, after the summary of the previous one if any.
+//!
+//! The summary is emitted along with an occurrence, so it's as periodic as the loop itself.
+
+use std::time::{Duration, Instant};
+
+pub use tracing;
+
+/// Default interval to summarize the repeated messages.
+pub const DEFAULT_SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Logs a formatted warning through a `LogDedup`, so that the identical warnings are
+/// summarized periodically instead of logged one by one.
+///
+/// ```ignore
+/// let mut dedup = LogDedup::default();
+/// loop {
+///     if let Err(e) = query().await {
+///         dedup_warn!(dedup, "Failed to query: {:?}", e);
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! dedup_warn {
+    ($dedup:expr, $($arg:tt)+) => {
+        for line in $dedup.record(format!($($arg)+)) {
+            $crate::tracing::warn!("{}", line);
+        }
+    };
+}
+
+/// Tracks the last logged message and the number of its repeats since the last summary.
+#[derive(Debug)]
+pub struct LogDedup {
+    interval: Duration,
+    last: Option<Repeated>,
+}
+
+#[derive(Debug)]
+struct Repeated {
+    message: String,
+    /// Number of the repeats since `since`, excluding the logged one.
+    count: usize,
+    since: Instant,
+}
+
+impl Repeated {
+    fn summary(&self, now: Instant) -> String {
+        format!(
+            "{} (repeated {} times in the last {} seconds)",
+            self.message,
+            self.count,
+            now.duration_since(self.since).as_secs()
+        )
+    }
+}
+
+impl Default for LogDedup {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUMMARY_INTERVAL)
+    }
+}
+
+impl LogDedup {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: None,
+        }
+    }
+
+    /// Records an occurrence of `message`, and returns the lines to log.
+    pub fn record(&mut self, message: String) -> Vec<String> {
+        self.record_at(message, Instant::now())
+    }
+
+    /// Forgets the last message once the condition is resolved, and returns the summary of
+    /// its repeats if any.
+    pub fn clear(&mut self) -> Option<String> {
+        self.clear_at(Instant::now())
+    }
+
+    fn record_at(&mut self, message: String, now: Instant) -> Vec<String> {
+        if let Some(last) = self.last.as_mut().filter(|last| last.message == message) {
+            last.count += 1;
+            if now.duration_since(last.since) < self.interval {
+                return vec![];
+            }
+
+            let summary = last.summary(now);
+            last.count = 0;
+            last.since = now;
+            return vec![summary];
+        }
+
+        let mut lines: Vec<String> = self.clear_at(now).into_iter().collect();
+        lines.push(message.clone());
+        self.last = Some(Repeated {
+            message,
+            count: 0,
+            since: now,
+        });
+        lines
+    }
+
+    fn clear_at(&mut self, now: Instant) -> Option<String> {
+        let last = self.last.take()?;
+        (last.count > 0).then(|| last.summary(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogDedup;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_log_dedup() {
+        let mut dedup = LogDedup::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let unauthorized = || "Miner is not authorized".to_string();
+
+        // the first occurrence is logged at once, and the repeats are only counted
+        assert_eq!(dedup.record_at(unauthorized(), at(0)), vec![unauthorized()]);
+        for secs in 1..60 {
+            assert!(dedup.record_at(unauthorized(), at(secs)).is_empty());
+        }
+
+        // summary once per interval
+        assert_eq!(
+            dedup.record_at(unauthorized(), at(60)),
+            vec!["Miner is not authorized (repeated 60 times in the last 60 seconds)"]
+        );
+        assert!(dedup.record_at(unauthorized(), at(61)).is_empty());
+        assert!(dedup.record_at(unauthorized(), at(62)).is_empty());
+
+        // a different message is logged at once after the pending summary
+        assert_eq!(
+            dedup.record_at("Failed to connect".into(), at(70)),
+            vec![
+                "Miner is not authorized (repeated 2 times in the last 10 seconds)".to_string(),
+                "Failed to connect".to_string(),
+            ]
+        );
+        assert_eq!(
+            dedup.record_at(unauthorized(), at(71)),
+            vec![unauthorized()]
+        );
+
+        // resolved condition
+        assert_eq!(dedup.clear_at(at(72)), None);
+        assert_eq!(
+            dedup.record_at(unauthorized(), at(73)),
+            vec![unauthorized()]
+        );
+        assert!(dedup.record_at(unauthorized(), at(74)).is_empty());
+        assert_eq!(
+            dedup.clear_at(at(75)),
+            Some("Miner is not authorized (repeated 1 times in the last 2 seconds)".into())
+        );
+        assert_eq!(
+            dedup.record_at(unauthorized(), at(76)),
+            vec![unauthorized()]
+        );
+    }
+}
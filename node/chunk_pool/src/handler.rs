@@ -107,6 +107,10 @@ impl ChunkPoolHandler {
     pub async fn run(mut self) {
         info!("Worker started to finalize transactions");
 
+        if let Err(e) = self.mem_pool.load_chunked_uploads().await {
+            warn!("Failed to load the staged chunked uploads, {:?}", e);
+        }
+
         loop {
             if let Err(e) = self.handle().await {
                 warn!("Failed to write chunks or finalize transaction, {:?}", e);
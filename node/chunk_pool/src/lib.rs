@@ -5,7 +5,7 @@ mod handler;
 mod mem_pool;
 
 pub use handler::{ChunkPoolHandler, ChunkPoolMessage};
-pub use mem_pool::{FileID, MemoryChunkPool, SegmentInfo};
+pub use mem_pool::{ChunkedUploadStatus, FileID, MemoryChunkPool, SegmentInfo};
 
 use std::sync::Arc;
 use std::time::Duration;
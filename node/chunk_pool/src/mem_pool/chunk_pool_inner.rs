@@ -1,5 +1,6 @@
 use super::chunk_cache::{ChunkPoolCache, MemoryCachedFile};
 use super::chunk_write_control::ChunkPoolWriteCtrl;
+use super::chunked_upload::{ChunkedUpload, ChunkedUploadStatus, ChunkedUploads};
 use super::FileID;
 use crate::handler::ChunkPoolMessage;
 use crate::Config;
@@ -10,6 +11,7 @@ use shared_types::{
     bytes_to_chunks, compute_segment_size, ChunkArray, DataRoot, FileProof, Transaction, CHUNK_SIZE,
 };
 use std::sync::Arc;
+use storage::log_store::log_manager::sub_merkle_tree;
 use storage_async::{ShardConfig, Store};
 use tokio::sync::broadcast::{error::RecvError, Receiver};
 use tokio::sync::mpsc::UnboundedSender;
@@ -82,6 +84,8 @@ impl From<SegmentInfo> for (ChunkArray, FileProof) {
 /// and data root verified on blockchain.
 pub struct MemoryChunkPool {
     inner: Mutex<Inner>,
+    /// Segments of the chunked uploads are staged in the store rather than cached in memory.
+    chunked_uploads: Mutex<ChunkedUploads>,
     log_store: Arc<Store>,
     sender: UnboundedSender<ChunkPoolMessage>,
 }
@@ -94,6 +98,7 @@ impl MemoryChunkPool {
     ) -> Self {
         MemoryChunkPool {
             inner: Mutex::new(Inner::new(config)),
+            chunked_uploads: Mutex::new(ChunkedUploads::new(config)),
             log_store,
             sender,
         }
@@ -262,9 +267,199 @@ impl MemoryChunkPool {
             "cached segments flushed to log store. data root: {}, tx_seq:{}",
             tx.data_merkle_root, tx.seq
         );
+
+        self.write_completed_chunked_upload(tx).await?;
+
         Ok(true)
     }
 
+    /// Stages a segment of a chunked upload in the store, which is uploaded without the proof
+    /// and resumable by querying the missing segments, even after a restart. Once all the
+    /// segments are received, the file root is verified against the declared `root`, and the
+    /// file is written into the flow if the log entry is retrieved already, or once retrieved
+    /// otherwise.
+    pub async fn upload_chunked_segment(
+        &self,
+        root: DataRoot,
+        file_size: usize,
+        chunks_per_segment: usize,
+        seg_index: usize,
+        seg_data: Vec<u8>,
+    ) -> Result<ChunkedUploadStatus> {
+        debug!(
+            "upload_chunked_segment, root={:?} index={}",
+            root, seg_index
+        );
+        self.validate_segment_size(&seg_data)?;
+        let seg_root = sub_merkle_tree(&seg_data)?.root();
+
+        // always GC at first
+        self.garbage_collect_chunked_uploads().await?;
+
+        let mut upload = match self.load_chunked_upload(&root).await? {
+            Some(upload) => upload,
+            None => ChunkedUpload::new(root, file_size, chunks_per_segment)?,
+        };
+        if upload.check_segment(file_size, chunks_per_segment, seg_index, seg_data.len())? {
+            self.log_store
+                .put_upload_segment(
+                    &root,
+                    file_size,
+                    chunks_per_segment,
+                    seg_index,
+                    seg_root.into(),
+                    seg_data,
+                )
+                .await?;
+            self.chunked_uploads.lock().await.touch(root);
+            // reload, since the other segments may be staged in the meantime
+            upload = self
+                .load_chunked_upload(&root)
+                .await?
+                .ok_or_else(|| anyhow!("chunked upload removed, please upload again"))?;
+        }
+
+        let status = upload.status();
+        if !upload.is_completed() {
+            return Ok(status);
+        }
+
+        self.verify_chunked_upload(&upload).await?;
+        if let Some(tx) = self.log_store.get_tx_by_data_root(&root, false).await? {
+            self.write_chunked_upload_and_finalize(&upload, &tx).await?;
+        }
+
+        Ok(status)
+    }
+
+    pub async fn get_chunked_upload_status(
+        &self,
+        root: &DataRoot,
+    ) -> Result<Option<ChunkedUploadStatus>> {
+        Ok(self
+            .load_chunked_upload(root)
+            .await?
+            .map(|upload| upload.status()))
+    }
+
+    /// Tracks the chunked uploads staged in the store before a restart for garbage collection,
+    /// and writes the completed ones of which the log entry is retrieved already.
+    pub async fn load_chunked_uploads(&self) -> Result<()> {
+        for root in self.log_store.get_uploads().await? {
+            self.chunked_uploads.lock().await.touch(root);
+            if let Some(tx) = self.log_store.get_tx_by_data_root(&root, false).await? {
+                if let Err(e) = self.write_completed_chunked_upload(&tx).await {
+                    warn!(%root, "Failed to write the staged chunked upload: {:?}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the chunked upload of the file of `tx` if all the segments are received.
+    async fn write_completed_chunked_upload(&self, tx: &Transaction) -> Result<()> {
+        let maybe_upload = self.load_chunked_upload(&tx.data_merkle_root).await?;
+        if let Some(upload) = maybe_upload.filter(|upload| upload.is_completed()) {
+            self.verify_chunked_upload(&upload).await?;
+            self.write_chunked_upload_and_finalize(&upload, tx).await?;
+        }
+        Ok(())
+    }
+
+    async fn load_chunked_upload(&self, root: &DataRoot) -> Result<Option<ChunkedUpload>> {
+        match self.log_store.get_upload(root).await? {
+            Some(staged) => Ok(Some(ChunkedUpload::from_staged(*root, staged)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Verifies the root of a completed upload, which is discarded on mismatch, since the
+    /// client has to upload the file again anyway.
+    async fn verify_chunked_upload(&self, upload: &ChunkedUpload) -> Result<()> {
+        if let Err(e) = upload.verify_root() {
+            self.remove_chunked_upload(&upload.root).await?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    async fn remove_chunked_upload(&self, root: &DataRoot) -> Result<bool> {
+        let removed = self.chunked_uploads.lock().await.remove(root);
+        self.log_store.remove_upload(root).await?;
+        Ok(removed)
+    }
+
+    /// Remove uploads that no new segment uploaded for a long time.
+    async fn garbage_collect_chunked_uploads(&self) -> Result<()> {
+        let expired = self.chunked_uploads.lock().await.garbage_collect();
+        for root in expired {
+            self.log_store.remove_upload(&root).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_chunked_upload_and_finalize(
+        &self,
+        upload: &ChunkedUpload,
+        tx: &Transaction,
+    ) -> Result<()> {
+        if tx.size != upload.file_size as u64 {
+            bail!(
+                "chunked upload file size {} not matched with tx file size {}",
+                upload.file_size,
+                tx.size
+            );
+        }
+
+        // Written once, e.g. if the last segment is received when the log entry is retrieved.
+        if !self.chunked_uploads.lock().await.start_writing(upload.root) {
+            debug!("Chunked upload {} is being written already", upload.root);
+            return Ok(());
+        }
+        let result = self.write_chunked_upload(upload, tx).await;
+        self.chunked_uploads
+            .lock()
+            .await
+            .finish_writing(&upload.root, result.is_ok());
+        result?;
+
+        self.send_finalize_file(FileID {
+            root: tx.data_merkle_root,
+            tx_id: tx.id(),
+        })
+        .await?;
+        debug!("Queue to finalize chunked upload {}", tx.data_merkle_root);
+
+        Ok(())
+    }
+
+    /// Moves the staged segments into the flow, and then removes them from the store.
+    async fn write_chunked_upload(&self, upload: &ChunkedUpload, tx: &Transaction) -> Result<()> {
+        for index in 0..upload.total_segments() {
+            let data = self
+                .log_store
+                .get_upload_segment(&upload.root, index)
+                .await?
+                .ok_or_else(|| anyhow!("staged segment {} missing", index))?;
+            let seg = ChunkArray {
+                data,
+                start_index: upload.segment_start_index(index),
+            };
+
+            // The data is verified against the file root already, so no proof is required.
+            if !self
+                .log_store
+                .put_chunks_with_tx_hash(tx.seq, tx.hash(), seg, None)
+                .await?
+            {
+                bail!("Transaction reverted, please upload again");
+            }
+        }
+
+        self.log_store.remove_upload(&upload.root).await
+    }
+
     pub async fn monitor_log_entry(chunk_pool: Arc<Self>, mut receiver: Receiver<LogSyncEvent>) {
         info!("Start to monitor log entry");
 
@@ -302,9 +497,18 @@ impl MemoryChunkPool {
     }
 
     pub(crate) async fn remove_file(&self, root: &DataRoot) -> bool {
-        let mut inner = self.inner.lock().await;
-        inner.segment_cache.remove_file(root).is_some()
-            || inner.write_control.remove_file(root).is_some()
+        let removed = {
+            let mut inner = self.inner.lock().await;
+            inner.segment_cache.remove_file(root).is_some()
+                || inner.write_control.remove_file(root).is_some()
+        };
+        match self.remove_chunked_upload(root).await {
+            Ok(removed_upload) => removed || removed_upload,
+            Err(e) => {
+                warn!(%root, "Failed to remove chunked upload: {:?}", e);
+                removed
+            }
+        }
     }
 
     pub async fn check_already_has_cache(&self, root: &DataRoot) -> bool {
@@ -389,3 +593,158 @@ impl MemoryChunkPool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkedUploadStatus, MemoryChunkPool};
+    use crate::Config;
+    use shared_types::{bytes_to_chunks, DataRoot, CHUNK_SIZE};
+    use std::sync::Arc;
+    use storage::log_store::log_manager::{sub_merkle_tree, LogConfig};
+    use storage::LogManager;
+    use storage_async::{ShardConfig, Store};
+    use task_executor::test_utils::TestRuntime;
+    use tokio::sync::mpsc;
+
+    const CHUNKS_PER_SEGMENT: usize = 4;
+
+    fn store(runtime: &TestRuntime) -> Arc<Store> {
+        Arc::new(Store::new(
+            Arc::new(LogManager::memorydb(LogConfig::default()).unwrap()),
+            runtime.task_executor.clone(),
+        ))
+    }
+
+    fn pool(store: &Arc<Store>) -> MemoryChunkPool {
+        let config = Config {
+            write_window_size: 4,
+            // chunked uploads are not bounded by the memory cache
+            max_cached_chunks_all: 1,
+            max_writings: 16,
+            expiration_time_secs: 300,
+            shard_config: ShardConfig::default(),
+        };
+        MemoryChunkPool::new(config, store.clone(), mpsc::unbounded_channel().0)
+    }
+
+    /// Returns the root and the segments of a file of `file_size` bytes.
+    fn file(file_size: usize) -> (DataRoot, Vec<Vec<u8>>) {
+        let mut data: Vec<u8> = (0..file_size).map(|i| (i % 251) as u8).collect();
+        // the last chunk is padded with zeros
+        data.resize(bytes_to_chunks(file_size) * CHUNK_SIZE, 0);
+        let root = sub_merkle_tree(&data).unwrap().root().into();
+        let segments = data
+            .chunks(CHUNKS_PER_SEGMENT * CHUNK_SIZE)
+            .map(|seg| seg.to_vec())
+            .collect();
+        (root, segments)
+    }
+
+    async fn upload(
+        chunk_pool: &MemoryChunkPool,
+        root: DataRoot,
+        file_size: usize,
+        index: usize,
+        seg: Vec<u8>,
+    ) -> anyhow::Result<ChunkedUploadStatus> {
+        chunk_pool
+            .upload_chunked_segment(root, file_size, CHUNKS_PER_SEGMENT, index, seg)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_chunked_upload_resumed_after_restart() {
+        let runtime = TestRuntime::default();
+        let store = store(&runtime);
+        let file_size = 10 * CHUNK_SIZE - 100;
+        let (root, segments) = file(file_size);
+        assert_eq!(segments.len(), 3);
+
+        let chunk_pool = pool(&store);
+        upload(&chunk_pool, root, file_size, 0, segments[0].clone())
+            .await
+            .unwrap();
+        upload(&chunk_pool, root, file_size, 2, segments[2].clone())
+            .await
+            .unwrap();
+        // a truncated segment is rejected without affecting the upload
+        assert!(upload(
+            &chunk_pool,
+            root,
+            file_size,
+            1,
+            segments[1][..CHUNK_SIZE].to_vec()
+        )
+        .await
+        .is_err());
+
+        // interrupted by a restart, and resumed from the missing segments in the store
+        drop(chunk_pool);
+        let chunk_pool = pool(&store);
+        chunk_pool.load_chunked_uploads().await.unwrap();
+        let status = chunk_pool
+            .get_chunked_upload_status(&root)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(status.total_segments, 3);
+        assert_eq!(status.missing_segments, vec![1]);
+        assert!(!status.verified);
+
+        // segments received already are ignored
+        upload(&chunk_pool, root, file_size, 0, segments[0].clone())
+            .await
+            .unwrap();
+        let status = upload(&chunk_pool, root, file_size, 1, segments[1].clone())
+            .await
+            .unwrap();
+        assert!(status.missing_segments.is_empty());
+        assert!(status.verified);
+
+        // staged until the log entry is retrieved
+        for (index, seg) in segments.iter().enumerate() {
+            assert_eq!(
+                store
+                    .get_upload_segment(&root, index)
+                    .await
+                    .unwrap()
+                    .as_ref(),
+                Some(seg)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chunked_upload_root_mismatch() {
+        let runtime = TestRuntime::default();
+        let store = store(&runtime);
+        let file_size = 9 * CHUNK_SIZE;
+        let (_, segments) = file(file_size);
+        let declared = DataRoot::repeat_byte(1);
+
+        let chunk_pool = pool(&store);
+        for (index, seg) in segments.iter().enumerate().take(2) {
+            upload(&chunk_pool, declared, file_size, index, seg.clone())
+                .await
+                .unwrap();
+        }
+        let status = chunk_pool
+            .get_chunked_upload_status(&declared)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(status.missing_segments, vec![2]);
+
+        let err = upload(&chunk_pool, declared, file_size, 2, segments[2].clone())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("file root mismatch"));
+        // discarded from the store to upload again
+        assert!(chunk_pool
+            .get_chunked_upload_status(&declared)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(store.get_uploads().await.unwrap().is_empty());
+    }
+}
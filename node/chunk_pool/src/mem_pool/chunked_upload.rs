@@ -0,0 +1,372 @@
+use crate::Config;
+use anyhow::{bail, Result};
+use hashlink::LinkedHashMap;
+use shared_types::{bytes_to_chunks, compute_segment_size, DataRoot, CHUNK_SIZE};
+use std::collections::HashSet;
+use std::ops::Add;
+use std::time::Instant;
+use storage::log_store::log_manager::FileMerkleTree;
+use storage::log_store::upload_store::StagedUpload;
+use storage::H256;
+
+/// Progress of a chunked upload, which a client queries to resume an interrupted upload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkedUploadStatus {
+    pub root: DataRoot,
+    pub file_size: usize,
+    pub total_segments: usize,
+    /// Indices of the segments not received yet in ascending order.
+    pub missing_segments: Vec<usize>,
+    /// Whether all the segments are received and the file root is verified, so the file is
+    /// written into store once the log entry is retrieved from blockchain.
+    pub verified: bool,
+}
+
+/// A file uploaded segment by segment without the proofs, which is verified against the root
+/// declared by the client once all the segments are received.
+///
+/// The received segments are staged in the store, and the upload is rebuilt from the roots of
+/// the staged segments, so it is neither bounded by the memory nor lost on restart.
+pub struct ChunkedUpload {
+    pub root: DataRoot,
+    pub file_size: usize,
+    pub chunks_per_segment: usize,
+    /// Sparse list of the roots of the received segments, which are computed on receipt.
+    segment_roots: Vec<Option<H256>>,
+}
+
+impl ChunkedUpload {
+    pub fn new(root: DataRoot, file_size: usize, chunks_per_segment: usize) -> Result<Self> {
+        if file_size == 0 {
+            bail!("file size is zero");
+        }
+        // the file root is bagged from the segment roots
+        if !chunks_per_segment.is_power_of_two() {
+            bail!(
+                "chunks per segment {} is not a power of two",
+                chunks_per_segment
+            );
+        }
+
+        let (total_segments, _) =
+            compute_segment_size(bytes_to_chunks(file_size), chunks_per_segment);
+        Ok(ChunkedUpload {
+            root,
+            file_size,
+            chunks_per_segment,
+            segment_roots: vec![None; total_segments],
+        })
+    }
+
+    /// Rebuilds the upload from the segments staged in the store.
+    pub fn from_staged(root: DataRoot, staged: StagedUpload) -> Result<Self> {
+        let mut upload = Self::new(root, staged.file_size, staged.chunks_per_segment)?;
+        for (index, segment_root) in staged.segment_roots {
+            if index >= upload.total_segments() {
+                bail!(
+                    "staged segment index {} out of bound, total segments {}",
+                    index,
+                    upload.total_segments()
+                );
+            }
+            upload.segment_roots[index] = Some(segment_root);
+        }
+        Ok(upload)
+    }
+
+    pub fn total_segments(&self) -> usize {
+        self.segment_roots.len()
+    }
+
+    fn segment_chunks(&self, index: usize) -> usize {
+        let (total_segments, last_segment_chunks) =
+            compute_segment_size(bytes_to_chunks(self.file_size), self.chunks_per_segment);
+        if index + 1 == total_segments {
+            last_segment_chunks
+        } else {
+            self.chunks_per_segment
+        }
+    }
+
+    /// Returns the index of the first chunk of the segment `index` in the file.
+    pub fn segment_start_index(&self, index: usize) -> u64 {
+        (index * self.chunks_per_segment) as u64
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.segment_roots.iter().all(Option::is_some)
+    }
+
+    pub fn status(&self) -> ChunkedUploadStatus {
+        ChunkedUploadStatus {
+            root: self.root,
+            file_size: self.file_size,
+            total_segments: self.total_segments(),
+            missing_segments: self
+                .segment_roots
+                .iter()
+                .enumerate()
+                .filter(|(_, seg)| seg.is_none())
+                .map(|(index, _)| index)
+                .collect(),
+            // a completed upload is discarded if the root mismatches
+            verified: self.is_completed(),
+        }
+    }
+
+    /// Checks the segment `index` of `data_len` bytes before it's staged, and returns `false`
+    /// if the segment is received already.
+    pub fn check_segment(
+        &self,
+        file_size: usize,
+        chunks_per_segment: usize,
+        index: usize,
+        data_len: usize,
+    ) -> Result<bool> {
+        if file_size != self.file_size {
+            bail!(
+                "file size mismatch, {} declared before, now {}",
+                self.file_size,
+                file_size
+            );
+        }
+        if chunks_per_segment != self.chunks_per_segment {
+            bail!(
+                "chunks per segment mismatch, {} before, now {}",
+                self.chunks_per_segment,
+                chunks_per_segment
+            );
+        }
+        if index >= self.total_segments() {
+            bail!(
+                "segment index {} out of bound, total segments {}",
+                index,
+                self.total_segments()
+            );
+        }
+
+        // Segment already received.
+        if self.segment_roots[index].is_some() {
+            return Ok(false);
+        }
+
+        let num_chunks = self.segment_chunks(index);
+        if data_len != num_chunks * CHUNK_SIZE {
+            bail!(
+                "invalid segment size {}, expected {}",
+                data_len,
+                num_chunks * CHUNK_SIZE
+            );
+        }
+
+        Ok(true)
+    }
+
+    /// Bags the segment roots into the file root and compares it with the declared one.
+    pub fn verify_root(&self) -> Result<()> {
+        if !self.is_completed() {
+            bail!("chunked upload not completed");
+        }
+
+        let computed: DataRoot = FileMerkleTree::new(
+            self.segment_roots
+                .iter()
+                .map(|seg_root| seg_root.expect("all segments received").0),
+        )
+        .root()
+        .into();
+
+        if computed != self.root {
+            bail!(
+                "file root mismatch, computed {:?}, declared {:?}",
+                computed,
+                self.root
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// ChunkedUploads tracks the chunked uploads staged in the store to garbage collect the
+/// abandoned ones, and the completed uploads being written into the flow.
+pub struct ChunkedUploads {
+    config: Config,
+    /// Expiration time of the uploads in ascending order, which is updated when a new segment
+    /// uploaded. Note, file root is used as key, since log entry may be not retrieved yet.
+    expirations: LinkedHashMap<DataRoot, Instant>,
+    /// Uploads being written into the flow, which are exempt from garbage collection.
+    writing: HashSet<DataRoot>,
+}
+
+impl ChunkedUploads {
+    pub fn new(config: Config) -> Self {
+        ChunkedUploads {
+            config,
+            expirations: LinkedHashMap::default(),
+            writing: HashSet::default(),
+        }
+    }
+
+    /// Postpones the expiration of the upload, e.g. when a segment is received.
+    pub fn touch(&mut self, root: DataRoot) {
+        // move to the back, so that uploads are ordered by the expiration time
+        self.expirations.remove(&root);
+        self.expirations
+            .insert(root, Instant::now().add(self.config.expiration_time()));
+    }
+
+    /// Returns `false` if the upload is being written already.
+    pub fn start_writing(&mut self, root: DataRoot) -> bool {
+        self.expirations.remove(&root);
+        self.writing.insert(root)
+    }
+
+    /// Tracks the upload for garbage collection again if the write failed, so that it is
+    /// written again on the next segment or log entry, or expires otherwise.
+    pub fn finish_writing(&mut self, root: &DataRoot, succeeded: bool) {
+        if self.writing.remove(root) && !succeeded {
+            self.touch(*root);
+        }
+    }
+
+    pub fn remove(&mut self, root: &DataRoot) -> bool {
+        self.expirations.remove(root).is_some()
+    }
+
+    /// Removes and returns the uploads that no new segment uploaded for a long time, of which
+    /// the staged segments are to remove from the store.
+    pub fn garbage_collect(&mut self) -> Vec<DataRoot> {
+        let now = Instant::now();
+        let mut expired = vec![];
+
+        while let Some((_, expired_at)) = self.expirations.front() {
+            if *expired_at > now {
+                break;
+            }
+
+            if let Some((root, _)) = self.expirations.pop_front() {
+                debug!("Garbage collected for chunked upload {}", root);
+                expired.push(root);
+            }
+        }
+
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkedUpload, ChunkedUploads};
+    use crate::Config;
+    use shared_types::{bytes_to_chunks, DataRoot, CHUNK_SIZE};
+    use storage::log_store::log_manager::sub_merkle_tree;
+    use storage::log_store::upload_store::StagedUpload;
+    use storage::H256;
+    use storage_async::ShardConfig;
+
+    const CHUNKS_PER_SEGMENT: usize = 4;
+
+    fn config(expiration_time_secs: u64) -> Config {
+        Config {
+            write_window_size: 4,
+            max_cached_chunks_all: 1024,
+            max_writings: 16,
+            expiration_time_secs,
+            shard_config: ShardConfig::default(),
+        }
+    }
+
+    /// Returns the root and the segment roots of a file of `file_size` bytes.
+    fn file(file_size: usize) -> (DataRoot, Vec<H256>) {
+        let mut data: Vec<u8> = (0..file_size).map(|i| (i % 251) as u8).collect();
+        // the last chunk is padded with zeros
+        data.resize(bytes_to_chunks(file_size) * CHUNK_SIZE, 0);
+        let root = sub_merkle_tree(&data).unwrap().root().into();
+        let segment_roots = data
+            .chunks(CHUNKS_PER_SEGMENT * CHUNK_SIZE)
+            .map(|seg| sub_merkle_tree(seg).unwrap().root().into())
+            .collect();
+        (root, segment_roots)
+    }
+
+    #[test]
+    fn test_chunked_upload_from_staged() {
+        let file_size = 10 * CHUNK_SIZE - 100;
+        let (root, segment_roots) = file(file_size);
+        assert_eq!(segment_roots.len(), 3);
+
+        let staged = |indices: &[usize]| StagedUpload {
+            file_size,
+            chunks_per_segment: CHUNKS_PER_SEGMENT,
+            segment_roots: indices.iter().map(|i| (*i, segment_roots[*i])).collect(),
+        };
+
+        let upload = ChunkedUpload::from_staged(root, staged(&[0, 2])).unwrap();
+        let status = upload.status();
+        assert_eq!(status.total_segments, 3);
+        assert_eq!(status.missing_segments, vec![1]);
+        assert!(!status.verified);
+        assert!(upload.verify_root().is_err());
+
+        // segments received already are not staged again
+        assert!(!upload
+            .check_segment(file_size, CHUNKS_PER_SEGMENT, 0, 4 * CHUNK_SIZE)
+            .unwrap());
+        // the last segment is of the remaining chunks
+        assert!(upload
+            .check_segment(file_size, CHUNKS_PER_SEGMENT, 1, 4 * CHUNK_SIZE)
+            .unwrap());
+        assert!(upload
+            .check_segment(file_size, CHUNKS_PER_SEGMENT, 1, CHUNK_SIZE)
+            .is_err());
+        assert!(upload
+            .check_segment(file_size, CHUNKS_PER_SEGMENT, 3, 4 * CHUNK_SIZE)
+            .is_err());
+        assert!(upload
+            .check_segment(file_size + 1, CHUNKS_PER_SEGMENT, 1, 4 * CHUNK_SIZE)
+            .is_err());
+        assert_eq!(upload.segment_start_index(2), 8);
+
+        let upload = ChunkedUpload::from_staged(root, staged(&[0, 1, 2])).unwrap();
+        assert!(upload.status().verified);
+        upload.verify_root().unwrap();
+
+        let err = ChunkedUpload::from_staged(DataRoot::repeat_byte(1), staged(&[0, 1, 2]))
+            .unwrap()
+            .verify_root()
+            .unwrap_err();
+        assert!(err.to_string().contains("file root mismatch"));
+
+        let mut out_of_bound = staged(&[0]);
+        out_of_bound.segment_roots.push((3, H256::zero()));
+        assert!(ChunkedUpload::from_staged(root, out_of_bound).is_err());
+    }
+
+    #[test]
+    fn test_chunked_uploads_garbage_collect() {
+        let (root1, root2) = (DataRoot::repeat_byte(1), DataRoot::repeat_byte(2));
+
+        let mut uploads = ChunkedUploads::new(config(0));
+        uploads.touch(root1);
+        uploads.touch(root2);
+        // uploads being written are not collected
+        assert!(uploads.start_writing(root2));
+        assert!(!uploads.start_writing(root2));
+        assert_eq!(uploads.garbage_collect(), vec![root1]);
+        assert!(uploads.garbage_collect().is_empty());
+
+        // tracked again once failed to write
+        uploads.finish_writing(&root2, false);
+        assert_eq!(uploads.garbage_collect(), vec![root2]);
+        assert!(uploads.start_writing(root2));
+        uploads.finish_writing(&root2, true);
+        assert!(uploads.garbage_collect().is_empty());
+
+        let mut uploads = ChunkedUploads::new(config(300));
+        uploads.touch(root1);
+        assert!(uploads.garbage_collect().is_empty());
+        assert!(uploads.remove(&root1));
+        assert!(!uploads.remove(&root1));
+    }
+}
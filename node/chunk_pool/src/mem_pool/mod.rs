@@ -1,9 +1,11 @@
 mod chunk_cache;
 mod chunk_pool_inner;
 mod chunk_write_control;
+mod chunked_upload;
 
 pub use chunk_pool_inner::MemoryChunkPool;
 pub use chunk_pool_inner::SegmentInfo;
+pub use chunked_upload::ChunkedUploadStatus;
 
 use shared_types::DataRoot;
 use shared_types::TxID;
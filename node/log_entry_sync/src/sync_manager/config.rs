@@ -1,5 +1,8 @@
+use std::collections::HashSet;
 use std::time::Duration;
 
+use ethereum_types::Address;
+
 use crate::ContractAddress;
 
 pub struct LogSyncConfig {
@@ -14,8 +17,13 @@ pub struct LogSyncConfig {
     /// This is used to rollback to a stable height if reorg happens during node restart.
     /// TODO(zz): Some blockchains have better confirmation/finalization mechanisms.
     pub confirmation_block_count: u64,
-    /// Maximum number of event logs to poll at a time.
+    /// Initial number of blocks to poll event logs at a time, which is adapted to the latency
+    /// of blockchain rpc within `[min_log_page_size, max_log_page_size]`.
     pub log_page_size: u64,
+    pub min_log_page_size: u64,
+    pub max_log_page_size: u64,
+    /// The page size is increased if polled faster than this, otherwise decreased.
+    pub log_page_target_latency: Duration,
 
     // blockchain provider retry params
     // the number of retries after a connection times out
@@ -39,6 +47,14 @@ pub struct LogSyncConfig {
 
     // the timeout for blockchain rpc connection
     pub blockchain_rpc_timeout: Duration,
+
+    /// Only download files submitted by these uploaders. Files from other uploaders are
+    /// recorded in the log but never downloaded. Empty means all files are downloaded.
+    pub uploader_allowlist: HashSet<Address>,
+    /// Skip the files with zero size rather than finalizing them as empty files.
+    pub skip_empty_files: bool,
+    /// Skip the files larger than this size in bytes, 0 for unlimited.
+    pub max_file_size: u64,
 }
 
 #[derive(Clone)]
@@ -58,6 +74,9 @@ impl LogSyncConfig {
         confirmation_block_count: u64,
         cache_config: CacheConfig,
         log_page_size: u64,
+        min_log_page_size: u64,
+        max_log_page_size: u64,
+        log_page_target_latency: Duration,
         rate_limit_retries: u32,
         timeout_retries: u32,
         initial_backoff: u64,
@@ -67,6 +86,9 @@ impl LogSyncConfig {
         watch_loop_wait_time_ms: u64,
         force_log_sync_from_start_block_number: bool,
         blockchain_rpc_timeout: Duration,
+        uploader_allowlist: HashSet<Address>,
+        skip_empty_files: bool,
+        max_file_size: u64,
     ) -> Self {
         Self {
             rpc_endpoint_url,
@@ -75,6 +97,9 @@ impl LogSyncConfig {
             start_block_number,
             confirmation_block_count,
             log_page_size,
+            min_log_page_size,
+            max_log_page_size,
+            log_page_target_latency,
             rate_limit_retries,
             timeout_retries,
             initial_backoff,
@@ -84,6 +109,13 @@ impl LogSyncConfig {
             watch_loop_wait_time_ms,
             force_log_sync_from_start_block_number,
             blockchain_rpc_timeout,
+            uploader_allowlist,
+            skip_empty_files,
+            max_file_size,
         }
     }
+
+    pub fn is_uploader_allowed(&self, uploader: &Address) -> bool {
+        self.uploader_allowlist.is_empty() || self.uploader_allowlist.contains(uploader)
+    }
 }
@@ -1,4 +1,5 @@
 use crate::sync_manager::log_query::LogQuery;
+use crate::sync_manager::page_size::{AdaptivePageSize, SharedPageSize};
 use crate::sync_manager::{metrics, RETRY_WAIT_MS};
 use crate::{ContractAddress, LogSyncConfig};
 use anyhow::{anyhow, bail, Result};
@@ -7,7 +8,7 @@ use contract_interface::{SubmissionNode, SubmitFilter, ZgsFlow};
 use ethers::abi::RawLog;
 use ethers::prelude::{BlockNumber, EthLogDecode, Http, Middleware, Provider};
 use ethers::providers::{HttpRateLimitRetryPolicy, RetryClient, RetryClientBuilder};
-use ethers::types::{Block, Log, H256};
+use ethers::types::{Address, Block, Log, H256, U256};
 use futures::StreamExt;
 use jsonrpsee::tracing::{debug, error, info, warn};
 use shared_types::{DataRoot, Transaction};
@@ -23,7 +24,7 @@ use tokio::sync::{
 
 pub struct LogEntryFetcher {
     contract_address: ContractAddress,
-    log_page_size: u64,
+    log_page_size: SharedPageSize,
     provider: Arc<Provider<RetryClient<Http>>>,
 
     confirmation_delay: u64,
@@ -47,11 +48,18 @@ impl LogEntryFetcher {
                     Box::new(HttpRateLimitRetryPolicy),
                 ),
         ));
+        let log_page_size = AdaptivePageSize::new(
+            config.log_page_size,
+            config.min_log_page_size,
+            config.max_log_page_size,
+            config.log_page_target_latency,
+        );
+        log_page_size.update_metrics();
         // TODO: `error` types are removed from the ABI json file.
         Ok(Self {
             contract_address: config.contract_address,
             provider,
-            log_page_size: config.log_page_size,
+            log_page_size: log_page_size.shared(),
             confirmation_delay: config.confirmation_block_count,
         })
     }
@@ -220,7 +228,7 @@ impl LogEntryFetcher {
         let provider = self.provider.clone();
         let (recover_tx, recover_rx) = tokio::sync::mpsc::unbounded_channel();
         let contract = self.flow_contract();
-        let log_page_size = self.log_page_size;
+        let log_page_size = self.log_page_size.clone();
 
         executor.spawn(
             async move {
@@ -232,7 +240,7 @@ impl LogEntryFetcher {
                     .address(contract.address().into())
                     .filter;
                 let mut stream = LogQuery::new(&provider, &filter, log_query_delay)
-                    .with_page_size(log_page_size);
+                    .with_page_size(log_page_size.clone());
                 info!(
                     "start_recover starts, start={} end={}",
                     start_block_number, end_block_number
@@ -295,7 +303,7 @@ impl LogEntryFetcher {
                             error!("log query error: e={:?}", e);
                             filter = filter.from_block(progress).address(contract.address());
                             stream = LogQuery::new(&provider, &filter, log_query_delay)
-                                .with_page_size(log_page_size);
+                                .with_page_size(log_page_size.clone());
                             tokio::time::sleep(Duration::from_millis(RETRY_WAIT_MS)).await;
                         }
                     }
@@ -322,7 +330,7 @@ impl LogEntryFetcher {
         let contract = self.flow_contract();
         let provider = self.provider.clone();
         let confirmation_delay = self.confirmation_delay;
-        let log_page_size = self.log_page_size;
+        let log_page_size = self.log_page_size.clone();
         let mut progress_reset_history = BTreeMap::new();
         executor.spawn(
             async move {
@@ -350,7 +358,7 @@ impl LogEntryFetcher {
                         confirmation_delay,
                         &contract,
                         &block_hash_cache,
-                        log_page_size,
+                        &log_page_size,
                     )
                     .await
                     {
@@ -386,7 +394,7 @@ impl LogEntryFetcher {
         confirmation_delay: u64,
         contract: &ZgsFlow<Provider<RetryClient<Http>>>,
         block_hash_cache: &Arc<RwLock<BTreeMap<u64, Option<BlockHashAndSubmissionIndex>>>>,
-        log_page_size: u64,
+        log_page_size: &SharedPageSize,
     ) -> Result<Option<(u64, H256, Option<Option<u64>>)>> {
         let latest_block_number = provider.get_block_number().await?.as_u64();
         debug!(
@@ -469,7 +477,7 @@ impl LogEntryFetcher {
             .address(contract.address().into())
             .filter;
         let mut stream = LogQuery::new(provider, &filter, Duration::from_millis(10))
-            .with_page_size(log_page_size);
+            .with_page_size(log_page_size.clone());
         let mut block_logs: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
         while let Some(maybe_log) = stream.next().await {
             let log = maybe_log?;
@@ -754,7 +762,8 @@ async fn revert_one_block(
 #[derive(Debug)]
 pub enum LogFetchProgress {
     SyncedBlock((u64, H256, Option<Option<u64>>)),
-    Transaction((Transaction, u64)),
+    /// The transaction, its block number and uploader.
+    Transaction((Transaction, u64, Address)),
     Reverted(u64),
 }
 
@@ -769,18 +778,37 @@ fn submission_event_to_transaction(e: SubmitFilter, block_number: u64) -> LogFet
                 .nodes
                 .iter()
                 // the submission height is the height of the root node starting from height 0.
-                .map(|SubmissionNode { root, height }| (height.as_usize() + 1, root.into()))
+                // An out of range height is mapped to the invalid depth 0, which is rejected
+                // before the tx is stored.
+                .map(|SubmissionNode { root, height }| {
+                    let depth = if *height < U256::from(u64::MAX) {
+                        height.as_usize() + 1
+                    } else {
+                        0
+                    };
+                    (depth, root.into())
+                })
                 .collect(),
             start_entry_index: e.start_pos.as_u64(),
-            size: e.submission.length.as_u64(),
+            // An absurd size is rejected before the tx is stored.
+            size: if e.submission.length > U256::from(u64::MAX) {
+                u64::MAX
+            } else {
+                e.submission.length.as_u64()
+            },
             seq: e.submission_index.as_u64(),
         },
         block_number,
+        e.sender,
     ))
 }
 
 fn nodes_to_root(node_list: &[SubmissionNode]) -> DataRoot {
-    let mut root: DataRoot = node_list.last().expect("not empty").root.into();
+    // The root of an empty file is defined as zero.
+    let Some(last) = node_list.last() else {
+        return DataRoot::zero();
+    };
+    let mut root: DataRoot = last.root.into();
     for next_node in node_list[..node_list.len() - 1].iter().rev() {
         root = Sha3Algorithm::parent(&next_node.root.into(), &root);
     }
@@ -1,8 +1,9 @@
+use crate::sync_manager::page_size::{AdaptivePageSize, SharedPageSize};
 use ethers::prelude::{Filter, JsonRpcClient, Log, Middleware, Provider, ProviderError, U64};
 use futures_core::stream::Stream;
 use jsonrpsee::tracing::trace;
 use std::future::Future;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{
     cmp::min,
     collections::VecDeque,
@@ -24,9 +25,11 @@ pub struct LogQuery<'a, P> {
     filter: Filter,
     from_block: Option<U64>,
 
-    expected_page_size: u64,
-    /// It may be smaller than `expected_page_size` if the server cannot return all the logs.
-    page_size: u64,
+    /// Adapted to the latency of the server, and shared between queries of the same server.
+    page_size: SharedPageSize,
+    /// The page size of the pending request, `None` if the request is not paginated.
+    request_page_size: Option<u64>,
+    request_start: Instant,
     current_logs: VecDeque<Log>,
     last_block: Option<U64>,
     state: LogQueryState<'a>,
@@ -51,8 +54,9 @@ where
             provider,
             filter: filter.clone(),
             from_block: filter.get_from_block(),
-            expected_page_size: 10000,
-            page_size: 10000,
+            page_size: AdaptivePageSize::new(10000, 10000, 10000, Duration::MAX).shared(),
+            request_page_size: None,
+            request_start: Instant::now(),
             current_logs: VecDeque::new(),
             last_block: None,
             state: LogQueryState::Initial,
@@ -61,11 +65,37 @@ where
     }
 
     /// set page size for pagination
-    pub fn with_page_size(mut self, page_size: u64) -> Self {
+    pub fn with_page_size(mut self, page_size: SharedPageSize) -> Self {
         self.page_size = page_size;
-        self.expected_page_size = page_size;
         self
     }
+
+    /// Returns the current page size, and starts timing the request of it.
+    fn start_request(&mut self) -> u64 {
+        let page_size = self.page_size.lock().unwrap().current();
+        self.request_page_size = Some(page_size);
+        self.request_start = Instant::now();
+        page_size
+    }
+
+    fn finish_request(&mut self, succeeded: bool) {
+        let request_page_size = match self.request_page_size.take() {
+            Some(request_page_size) => request_page_size,
+            None => return,
+        };
+        let mut page_size = self.page_size.lock().unwrap();
+        // ignore the stale result if the page size has been adapted by another query
+        if page_size.current() != request_page_size {
+            return;
+        }
+        // the delay before the request is not part of the server latency
+        let latency = self.request_start.elapsed().saturating_sub(self.delay);
+        if succeeded {
+            page_size.on_success(latency);
+        } else {
+            page_size.on_failure();
+        }
+    }
 }
 
 macro_rules! rewake_with_new_state {
@@ -127,7 +157,8 @@ where
                         // this is okay because we will only enter this state when the filter is
                         // paginatable i.e. from block is set
                         let from_block = self.filter.get_from_block().unwrap();
-                        let to_block = min(from_block + self.page_size - 1, last_block);
+                        let page_size = self.start_request();
+                        let to_block = min(from_block + page_size - 1, last_block);
                         self.from_block = Some(to_block + 1);
 
                         let filter = self
@@ -155,14 +186,15 @@ where
                 match futures_util::ready!(fut.as_mut().poll(ctx)) {
                     Ok(logs) => {
                         self.current_logs = VecDeque::from(logs);
-                        self.page_size = self.expected_page_size;
+                        self.finish_request(true);
                         rewake_with_new_state!(ctx, self, LogQueryState::Consume);
                     }
                     Err(err) => {
+                        let from_block = *from_block;
+                        self.finish_request(false);
                         for msg in TOO_MANY_LOGS_ERROR_MSG.iter() {
                             if err.to_string().contains(msg) {
-                                self.from_block = *from_block;
-                                self.page_size /= 2;
+                                self.from_block = from_block;
                                 rewake_with_new_state!(ctx, self, LogQueryState::Consume);
                             }
                         }
@@ -180,11 +212,12 @@ where
                         // load new logs if there are still more pages to go through
                         // can safely assume this will always be set in this state
                         let from_block = self.from_block.unwrap();
+                        let page_size = self.start_request();
                         let to_block = if let Some(l) = self.last_block {
                             // if last_block is not none, only getLogs from to_block to last_block
-                            min(from_block + self.page_size - 1, l)
+                            min(from_block + page_size - 1, l)
                         } else {
-                            from_block + self.page_size - 1
+                            from_block + page_size - 1
                         };
 
                         // no more pages to load, and everything is consumed
@@ -10,4 +10,6 @@ lazy_static::lazy_static! {
     pub static ref STORE_PUT_TX_SPEED_IN_BYTES: Arc<dyn Gauge<usize>> = GaugeUsize::register("log_entry_sync_manager_put_tx_speed_in_bytes");
 
     pub static ref RECOVER_LOG: Arc<dyn Timer> = register_timer("log_entry_sync_manager_recover_log");
+
+    pub static ref LOG_PAGE_SIZE: Arc<dyn Gauge<usize>> = GaugeUsize::register("log_entry_sync_manager_log_page_size");
 }
@@ -1,8 +1,9 @@
 use crate::sync_manager::config::LogSyncConfig;
 use crate::sync_manager::data_cache::DataCache;
 use crate::sync_manager::log_entry_fetcher::{LogEntryFetcher, LogFetchProgress};
+use crate::sync_manager::tx_check::{check_tx, TxCheck};
 use anyhow::{anyhow, bail, Result};
-use ethereum_types::H256;
+use ethereum_types::{Address, H256};
 use ethers::{prelude::Middleware, types::BlockNumber};
 use futures::FutureExt;
 use jsonrpsee::tracing::{debug, error, warn};
@@ -295,13 +296,13 @@ impl LogSyncManager {
         Ok((event_send_cloned, catch_up_end_receiver))
     }
 
-    async fn put_tx(&mut self, tx: Transaction) -> Option<bool> {
+    async fn put_tx(&mut self, tx: Transaction, uploader: Address) -> Option<bool> {
         // We call this after process chain reorg, so the sequence number should match.
         match tx.seq.cmp(&self.next_tx_seq) {
             std::cmp::Ordering::Less => Some(true),
             std::cmp::Ordering::Equal => {
                 debug!("log entry sync get entry: {:?}", tx);
-                Some(self.put_tx_inner(tx).await)
+                Some(self.put_tx_inner(tx, uploader).await)
             }
             std::cmp::Ordering::Greater => {
                 error!(
@@ -401,10 +402,10 @@ impl LogSyncManager {
                         }
                     }
                 }
-                LogFetchProgress::Transaction((tx, block_number)) => {
+                LogFetchProgress::Transaction((tx, block_number, uploader)) => {
                     let mut stop = false;
                     let start_time = Instant::now();
-                    match self.put_tx(tx.clone()).await {
+                    match self.put_tx(tx.clone(), uploader).await {
                         Some(false) => stop = true,
                         Some(true) => {
                             if let Err(e) = self.store.put_log_latest_block_number(block_number) {
@@ -448,15 +449,54 @@ impl LogSyncManager {
         Ok(())
     }
 
-    async fn put_tx_inner(&mut self, tx: Transaction) -> bool {
+    async fn put_tx_inner(&mut self, mut tx: Transaction, uploader: Address) -> bool {
         let start_time = Instant::now();
+        // The malformed tx is still stored to keep the tx seqs continuous, but skipped.
+        let skip_reason = match check_tx(&tx, self.config.max_file_size) {
+            TxCheck::Valid => None,
+            TxCheck::TooLarge { reason } => Some(reason),
+            TxCheck::Empty if self.config.skip_empty_files => Some("empty file".to_string()),
+            TxCheck::Empty => None,
+            TxCheck::Malformed {
+                reason,
+                invalid_nodes,
+            } => {
+                if invalid_nodes {
+                    // The entries are padded by the next tx instead.
+                    tx.merkle_nodes.clear();
+                }
+                Some(reason)
+            }
+        };
         let result = self.store.put_tx(tx.clone());
 
         if let Err(e) = result {
             error!("put_tx error: e={:?}", e);
             false
         } else {
-            if let Some(data) = self.data_cache.pop_data(&tx.data_merkle_root) {
+            if let Some(reason) = skip_reason {
+                warn!(tx_seq = tx.seq, size = tx.size, %reason, "skip malformed file");
+                if let Err(e) = self.store.skip_tx(tx.seq, &reason) {
+                    error!("skip malformed file: e={:?}", e);
+                    return false;
+                }
+            } else if tx.size == 0 {
+                // An empty file has no data to download.
+                if let Err(e) = self.store.finalize_tx_with_hash(tx.seq, tx.hash()) {
+                    error!("finalize empty file: e={:?}", e);
+                    return false;
+                }
+            } else if !self.config.is_uploader_allowed(&uploader) {
+                // Neither downloaded nor stored from the data uploaded to this node.
+                debug!(
+                    "skip file since uploader not allowlisted: tx_seq={} uploader={:?}",
+                    tx.seq, uploader
+                );
+                if let Err(e) = self.store.skip_tx(tx.seq, "uploader not allowlisted") {
+                    error!("skip file of uploader not allowlisted: e={:?}", e);
+                    return false;
+                }
+            } else if let Some(data) = self.data_cache.pop_data(&tx.data_merkle_root) {
                 let store = self.store.clone();
                 // We are holding a mutable reference of LogSyncManager, so no chain reorg is
                 // possible after put_tx.
@@ -546,8 +586,9 @@ impl LogSyncManager {
                 }
             }
 
+            let elapsed_micros = start_time.elapsed().as_micros().max(1);
             metrics::STORE_PUT_TX_SPEED_IN_BYTES
-                .update((tx.size * 1000 / start_time.elapsed().as_micros() as u64) as usize);
+                .update((tx.size as u128 * 1000 / elapsed_micros).min(usize::MAX as u128) as usize);
             metrics::STORE_PUT_TX.update_since(start_time);
 
             true
@@ -657,3 +698,206 @@ mod data_cache;
 mod log_entry_fetcher;
 mod log_query;
 mod metrics;
+mod page_size;
+mod tx_check;
+
+#[cfg(test)]
+mod tests {
+    use super::LogSyncManager;
+    use crate::sync_manager::config::{CacheConfig, LogSyncConfig};
+    use crate::sync_manager::data_cache::DataCache;
+    use crate::sync_manager::log_entry_fetcher::LogEntryFetcher;
+    use ethereum_types::Address;
+    use shared_types::{DataRoot, Transaction, CHUNK_SIZE};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use storage::log_store::log_manager::{
+        sub_merkle_tree, tx_subtree_root_list_padded, LogConfig,
+    };
+    use storage::log_store::Store;
+    use storage::LogManager;
+    use tokio::sync::broadcast;
+
+    fn config(
+        skip_empty_files: bool,
+        max_file_size: u64,
+        uploader_allowlist: HashSet<Address>,
+    ) -> LogSyncConfig {
+        LogSyncConfig::new(
+            "http://127.0.0.1:8545".into(),
+            Default::default(),
+            0,
+            0,
+            CacheConfig {
+                max_data_size: 1024,
+                tx_seq_ttl: 10,
+            },
+            100,
+            1,
+            100,
+            Duration::from_secs(1),
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            false,
+            Duration::from_secs(1),
+            uploader_allowlist,
+            skip_empty_files,
+            max_file_size,
+        )
+    }
+
+    async fn create_manager(config: LogSyncConfig) -> (LogSyncManager, Arc<dyn Store>) {
+        let store: Arc<dyn Store> = Arc::new(LogManager::memorydb(LogConfig::default()).unwrap());
+        let manager = LogSyncManager {
+            log_fetcher: LogEntryFetcher::new(&config).await.unwrap(),
+            data_cache: DataCache::new(config.cache_config.clone()),
+            config,
+            store: store.clone(),
+            next_tx_seq: 0,
+            event_send: broadcast::channel(1).0,
+            block_hash_cache: Default::default(),
+        };
+        (manager, store)
+    }
+
+    fn tx(
+        seq: u64,
+        size: u64,
+        start_entry_index: u64,
+        merkle_nodes: Vec<(usize, DataRoot)>,
+    ) -> Transaction {
+        Transaction {
+            stream_ids: vec![],
+            data: vec![],
+            data_merkle_root: DataRoot::repeat_byte(seq as u8 + 1),
+            merkle_nodes,
+            start_entry_index,
+            size,
+            seq,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put_malformed_tx() {
+        let (mut manager, store) = create_manager(config(false, 256 * 4, Default::default())).await;
+        let root = DataRoot::repeat_byte(1);
+        let txs = vec![
+            // empty file with a zero root
+            tx(0, 0, 0, vec![]),
+            // absurd size of valid nodes
+            tx(1, u64::MAX, 0, vec![(3, root)]),
+            // nodes beyond the flow height, which are not appended
+            tx(2, 1 << 60, 4, vec![(64, root)]),
+            // larger than the max file size, padded to align the node
+            tx(3, 256 * 8, 8, vec![(4, root)]),
+            tx(4, 256 * 4, 16, vec![(3, root)]),
+        ];
+        for tx in txs {
+            assert_eq!(manager.put_tx(tx, Address::zero()).await, Some(true));
+        }
+        assert_eq!(store.next_tx_seq(), 5);
+        assert_eq!(manager.next_tx_seq, 5);
+
+        assert!(store.check_tx_completed(0).unwrap());
+        assert_eq!(store.get_skip_reason(0).unwrap(), None);
+        assert_eq!(
+            store.get_skip_reason(1).unwrap(),
+            Some(format!("size {} exceeds the 4 submitted entries", u64::MAX))
+        );
+        assert_eq!(
+            store.get_skip_reason(2).unwrap().as_deref(),
+            Some("invalid submission node depth 64")
+        );
+        assert_eq!(
+            store.get_skip_reason(3).unwrap().as_deref(),
+            Some("size 2048 exceeds max file size 1024")
+        );
+        assert_eq!(store.get_skip_reason(4).unwrap(), None);
+
+        // the skipped files are never finalized
+        for tx_seq in 1..4 {
+            assert!(store.finalize_tx(tx_seq).is_err());
+            assert!(!store.check_tx_completed(tx_seq).unwrap());
+        }
+        let tx = store.get_tx_by_seq_number(2).unwrap().unwrap();
+        assert!(tx.merkle_nodes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_put_empty_tx_skipped() {
+        let (mut manager, store) = create_manager(config(true, 0, Default::default())).await;
+        assert_eq!(
+            manager.put_tx(tx(0, 0, 0, vec![]), Address::zero()).await,
+            Some(true)
+        );
+        assert_eq!(
+            store.get_skip_reason(0).unwrap().as_deref(),
+            Some("empty file")
+        );
+        assert!(!store.check_tx_completed(0).unwrap());
+
+        // unexpected tx seq
+        assert_eq!(
+            manager.put_tx(tx(2, 0, 0, vec![]), Address::zero()).await,
+            None
+        );
+    }
+
+    /// Returns a tx of 4 chunks with valid nodes and its data.
+    fn tx_with_data(seq: u64) -> (Transaction, Vec<u8>) {
+        let mut data = vec![0u8; CHUNK_SIZE * 4];
+        data[0] = seq as u8 + 1;
+        let tx = Transaction {
+            data_merkle_root: sub_merkle_tree(&data).unwrap().root().into(),
+            merkle_nodes: tx_subtree_root_list_padded(&data),
+            start_entry_index: 4 * (seq + 1),
+            size: data.len() as u64,
+            ..tx(seq, 0, 0, vec![])
+        };
+        (tx, data)
+    }
+
+    #[tokio::test]
+    async fn test_put_tx_uploader_allowlist() {
+        let allowed = Address::repeat_byte(1);
+        let other = Address::repeat_byte(2);
+        let (mut manager, store) = create_manager(config(false, 0, HashSet::from([allowed]))).await;
+
+        // the data of tx 2 and 3 is uploaded to this node before the tx is synced
+        let mut txs = vec![];
+        for (seq, uploader) in [(0, allowed), (1, other), (2, other), (3, allowed)] {
+            let (tx, data) = tx_with_data(seq);
+            if seq >= 2 {
+                assert!(manager.data_cache.add_data(tx.data_merkle_root, seq, data));
+            }
+            txs.push((tx, uploader));
+        }
+        for (tx, uploader) in txs {
+            assert_eq!(manager.put_tx(tx, uploader).await, Some(true));
+        }
+        assert_eq!(store.next_tx_seq(), 4);
+
+        // only the files of the allowlisted uploader are downloaded or stored
+        assert_eq!(store.get_skip_reason(0).unwrap(), None);
+        assert!(!store.check_tx_completed(0).unwrap());
+        for tx_seq in [1, 2] {
+            assert_eq!(
+                store.get_skip_reason(tx_seq).unwrap().as_deref(),
+                Some("uploader not allowlisted")
+            );
+            assert!(!store.check_tx_completed(tx_seq).unwrap());
+        }
+        assert!(store
+            .get_chunks_by_tx_and_index_range(2, 0, 4)
+            .unwrap()
+            .is_none());
+        assert_eq!(store.get_skip_reason(3).unwrap(), None);
+        assert!(store.check_tx_completed(3).unwrap());
+    }
+}
@@ -0,0 +1,114 @@
+//! Adapts the number of blocks to query logs in a single `eth_getLogs` call to the observed
+//! latency of the blockchain RPC, with additive increase and multiplicative decrease.
+//!
+//! The page size grows by a fixed step after every request faster than the target latency,
+//! and is halved after every slow or failed request, e.g. timeout or too many logs. So the
+//! page size converges to the largest one that the RPC server could serve in time.
+
+use crate::sync_manager::metrics;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The page size grows by `1 / INCREASE_STEP_DIVISOR` of the configured range at a time.
+const INCREASE_STEP_DIVISOR: u64 = 20;
+
+pub type SharedPageSize = Arc<Mutex<AdaptivePageSize>>;
+
+#[derive(Debug)]
+pub struct AdaptivePageSize {
+    current: u64,
+    min: u64,
+    max: u64,
+    increase_step: u64,
+    target_latency: Duration,
+}
+
+impl AdaptivePageSize {
+    pub fn new(initial: u64, min: u64, max: u64, target_latency: Duration) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self {
+            current: initial.clamp(min, max),
+            min,
+            max,
+            increase_step: ((max - min) / INCREASE_STEP_DIVISOR).max(1),
+            target_latency,
+        }
+    }
+
+    pub fn shared(self) -> SharedPageSize {
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    /// Called when a request of the current page size succeeded in `latency`.
+    pub fn on_success(&mut self, latency: Duration) {
+        if latency > self.target_latency {
+            self.decrease();
+        } else if self.current < self.max {
+            self.current = (self.current + self.increase_step).min(self.max);
+            self.update_metrics();
+        }
+    }
+
+    /// Called when a request of the current page size timed out or was rejected.
+    pub fn on_failure(&mut self) {
+        self.decrease();
+    }
+
+    fn decrease(&mut self) {
+        if self.current > self.min {
+            self.current = (self.current / 2).max(self.min);
+            self.update_metrics();
+        }
+    }
+
+    pub fn update_metrics(&self) {
+        metrics::LOG_PAGE_SIZE.update(self.current as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AdaptivePageSize;
+    use std::time::Duration;
+
+    /// Simulates an RPC server of which the latency grows linearly with the page size, and
+    /// requests time out after `timeout`.
+    fn simulate(page_size: &mut AdaptivePageSize, ms_per_block: u64, timeout: Duration) {
+        for _ in 0..200 {
+            let latency = Duration::from_millis(page_size.current() * ms_per_block);
+            if latency > timeout {
+                page_size.on_failure();
+            } else {
+                page_size.on_success(latency);
+            }
+        }
+    }
+
+    #[test]
+    fn test_page_size_converge() {
+        let target = Duration::from_secs(1);
+        let timeout = Duration::from_secs(5);
+        let mut page_size = AdaptivePageSize::new(100, 10, 2010, target);
+
+        // fast server, grows up to the max
+        simulate(&mut page_size, 0, timeout);
+        assert_eq!(page_size.current(), 2010);
+
+        // slow server, oscillates around the 1000 blocks that fit in the target latency
+        simulate(&mut page_size, 1, timeout);
+        assert!((500..=1100).contains(&page_size.current()));
+
+        // very slow server, 10 blocks time out
+        simulate(&mut page_size, 1000, timeout);
+        assert_eq!(page_size.current(), 10);
+
+        // recovers after the server is fast again
+        simulate(&mut page_size, 0, timeout);
+        assert_eq!(page_size.current(), 2010);
+    }
+}
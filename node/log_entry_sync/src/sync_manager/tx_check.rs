@@ -0,0 +1,177 @@
+//! Validation of the declared size of the txs submitted on chain before they're stored, so
+//! that a malformed submission is skipped with a recorded reason rather than building a
+//! degenerate tree or overflowing the size computations.
+
+use shared_types::{bytes_to_chunks, Transaction, CHUNK_SIZE};
+
+/// Maximum number of entries in the flow, so that the byte offset of any entry fits in `u64`.
+const MAX_FLOW_ENTRIES: u64 = 1 << (u64::BITS - CHUNK_SIZE.trailing_zeros());
+/// Maximum depth of a submission node, of which the subtree has `2^(depth - 1)` entries, i.e.
+/// the height of the flow of `MAX_FLOW_ENTRIES` entries.
+const MAX_NODE_DEPTH: usize = MAX_FLOW_ENTRIES.trailing_zeros() as usize + 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TxCheck {
+    Valid,
+    /// The file has no data and no submission nodes.
+    Empty,
+    /// The size or the submission nodes are malformed. If `invalid_nodes` is set, the
+    /// submission nodes cannot be appended to the flow.
+    Malformed {
+        reason: String,
+        invalid_nodes: bool,
+    },
+    /// The file is larger than the configured maximum file size.
+    TooLarge {
+        reason: String,
+    },
+}
+
+fn malformed(reason: String, invalid_nodes: bool) -> TxCheck {
+    TxCheck::Malformed {
+        reason,
+        invalid_nodes,
+    }
+}
+
+/// Checks the declared size of `tx` against its submission nodes, and against `max_file_size`
+/// unless it's 0.
+pub fn check_tx(tx: &Transaction, max_file_size: u64) -> TxCheck {
+    let mut num_entries: u64 = 0;
+    for (depth, _) in &tx.merkle_nodes {
+        if *depth == 0 || *depth > MAX_NODE_DEPTH {
+            return malformed(format!("invalid submission node depth {}", depth), true);
+        }
+        match num_entries.checked_add(1 << (depth - 1)) {
+            Some(n) => num_entries = n,
+            None => return malformed("submission nodes overflow".into(), true),
+        }
+    }
+    match tx.start_entry_index.checked_add(num_entries) {
+        Some(end) if end <= MAX_FLOW_ENTRIES => {}
+        _ => {
+            return malformed(
+                format!(
+                    "submission exceeds the flow: start_entry_index={} entries={}",
+                    tx.start_entry_index, num_entries
+                ),
+                true,
+            )
+        }
+    }
+
+    if tx.size == 0 {
+        return if num_entries == 0 {
+            TxCheck::Empty
+        } else {
+            malformed(
+                format!("zero size with {} submitted entries", num_entries),
+                false,
+            )
+        };
+    }
+    let chunks = bytes_to_chunks(tx.size as usize) as u64;
+    if chunks > num_entries {
+        return malformed(
+            format!(
+                "size {} exceeds the {} submitted entries",
+                tx.size, num_entries
+            ),
+            false,
+        );
+    }
+    if max_file_size > 0 && tx.size > max_file_size {
+        return TxCheck::TooLarge {
+            reason: format!("size {} exceeds max file size {}", tx.size, max_file_size),
+        };
+    }
+    TxCheck::Valid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_tx, TxCheck, MAX_NODE_DEPTH};
+    use shared_types::{DataRoot, Transaction};
+
+    fn tx(size: u64, merkle_nodes: Vec<(usize, DataRoot)>) -> Transaction {
+        Transaction {
+            stream_ids: vec![],
+            data: vec![],
+            data_merkle_root: DataRoot::zero(),
+            merkle_nodes,
+            start_entry_index: 0,
+            size,
+            seq: 0,
+        }
+    }
+
+    fn reason(check: TxCheck) -> String {
+        match check {
+            TxCheck::Malformed { reason, .. } | TxCheck::TooLarge { reason } => reason,
+            check => panic!("unexpected check result {:?}", check),
+        }
+    }
+
+    #[test]
+    fn test_check_tx_size() {
+        let root = DataRoot::repeat_byte(1);
+        assert_eq!(
+            check_tx(&tx(256 * 3, vec![(2, root), (1, root)]), 0),
+            TxCheck::Valid
+        );
+        assert_eq!(check_tx(&tx(1, vec![(1, root)]), 0), TxCheck::Valid);
+
+        // zero size
+        assert_eq!(check_tx(&tx(0, vec![]), 0), TxCheck::Empty);
+        assert_eq!(
+            reason(check_tx(&tx(0, vec![(1, root)]), 0)),
+            "zero size with 1 submitted entries"
+        );
+
+        // absurd size
+        assert_eq!(
+            reason(check_tx(&tx(u64::MAX, vec![(3, root)]), 0)),
+            format!("size {} exceeds the 4 submitted entries", u64::MAX)
+        );
+        assert_eq!(
+            reason(check_tx(&tx(256 * 3, vec![(2, root)]), 0)),
+            "size 768 exceeds the 2 submitted entries"
+        );
+
+        // max file size
+        assert_eq!(
+            check_tx(&tx(256 * 3, vec![(2, root), (1, root)]), 768),
+            TxCheck::Valid
+        );
+        assert_eq!(
+            reason(check_tx(&tx(256 * 3, vec![(2, root), (1, root)]), 767)),
+            "size 768 exceeds max file size 767"
+        );
+    }
+
+    #[test]
+    fn test_check_tx_nodes() {
+        let root = DataRoot::repeat_byte(1);
+        assert_eq!(MAX_NODE_DEPTH, 57);
+        // a node of the flow height may still match an absurd size, bounded by max file size
+        assert_eq!(
+            check_tx(&tx(1 << 60, vec![(MAX_NODE_DEPTH, root)]), 0),
+            TxCheck::Valid
+        );
+        for nodes in [
+            vec![(0, root)],
+            vec![(MAX_NODE_DEPTH + 1, root)],
+            vec![(64, root)],
+            vec![(MAX_NODE_DEPTH, root), (MAX_NODE_DEPTH, root)],
+        ] {
+            match check_tx(&tx(256, nodes), 0) {
+                TxCheck::Malformed { invalid_nodes, .. } => assert!(invalid_nodes),
+                check => panic!("unexpected check result {:?}", check),
+            }
+        }
+
+        let mut end_overflow = tx(256, vec![(1, root)]);
+        end_overflow.start_entry_index = u64::MAX;
+        assert!(reason(check_tx(&end_overflow, 0)).starts_with("submission exceeds the flow"));
+    }
+}
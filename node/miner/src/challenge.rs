@@ -0,0 +1,111 @@
+//! Computes the data to prove for a mining challenge and generates its proofs locally,
+//! so that operators could check whether the node holds the data before submitting answers.
+
+use crate::recall_range::RecallRange;
+use crate::PoraLoader;
+use ethereum_types::H256;
+use shared_types::FlowRangeProof;
+use storage_async::Store;
+use zgs_spec::{SECTORS_PER_LOAD, SECTORS_PER_SEAL};
+
+/// The proofs of the sealed data to answer a mining challenge.
+#[derive(Debug)]
+pub struct ChallengeProof {
+    /// The first sector of the loaded chunk.
+    pub recall_position: u64,
+    /// `(seal_offset, proof)` of the sealed data available in the loaded chunk.
+    pub proofs: Vec<(usize, FlowRangeProof)>,
+}
+
+/// Derives the first sector of the chunk to load for the challenge from `recall_seed`
+/// in the same way as mining, where the recall seed is derived from the nonce.
+pub fn challenge_position(range: &RecallRange, recall_seed: &H256) -> Result<u64, String> {
+    range
+        .load_position(recall_seed.0)
+        .ok_or_else(|| format!("Invalid recall range for challenge: {:?}", range))
+}
+
+/// Loads the chunk of the challenge and generates the proofs of its sealed data against
+/// `flow_root`, which fails if none of the sealed data is available.
+pub async fn prove_challenge(
+    store: &Store,
+    flow_root: H256,
+    range: &RecallRange,
+    recall_seed: &H256,
+) -> Result<ChallengeProof, String> {
+    let recall_position = challenge_position(range, recall_seed)?;
+
+    let chunk = PoraLoader::load_sealed_data(store, recall_position / SECTORS_PER_LOAD as u64)
+        .await
+        .ok_or_else(|| format!("Data not found for challenge: position={}", recall_position))?;
+
+    let mut proofs = vec![];
+    for (seal_offset, available) in chunk.availabilities.iter().enumerate() {
+        if !available {
+            continue;
+        }
+
+        let seal_position = recall_position + (seal_offset * SECTORS_PER_SEAL) as u64;
+        let proof = store
+            .get_proof_at_root(Some(flow_root), seal_position, SECTORS_PER_SEAL as u64)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Failed to generate proof for challenge: position={} err={:?}",
+                    seal_position, e
+                )
+            })?;
+        proofs.push((seal_offset, proof));
+    }
+
+    if proofs.is_empty() {
+        return Err(format!(
+            "Sealed data not available for challenge: position={}",
+            recall_position
+        ));
+    }
+
+    Ok(ChallengeProof {
+        recall_position,
+        proofs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::challenge_position;
+    use crate::recall_range::RecallRange;
+    use ethereum_types::H256;
+    use zgs_spec::SECTORS_PER_LOAD;
+
+    #[test]
+    fn test_challenge_position() {
+        let range = RecallRange {
+            start_position: 1024,
+            mining_length: 16 * SECTORS_PER_LOAD as u64,
+            shard_mask: u64::MAX,
+            shard_id: 0,
+        };
+        let seed = H256::from_low_u64_be(16 * 3 + 5);
+        let position = challenge_position(&range, &seed).unwrap();
+        assert_eq!(position, 1024 + 5 * SECTORS_PER_LOAD as u64);
+        assert_eq!(challenge_position(&range, &seed).unwrap(), position);
+
+        // only the loads in the shard are challenged
+        let sharded_range = RecallRange {
+            shard_mask: !1,
+            shard_id: 0,
+            ..range
+        };
+        assert_eq!(
+            challenge_position(&sharded_range, &seed).unwrap(),
+            1024 + 4 * SECTORS_PER_LOAD as u64
+        );
+
+        let empty_range = RecallRange {
+            mining_length: 0,
+            ..range
+        };
+        assert!(challenge_position(&empty_range, &seed).is_err());
+    }
+}
@@ -4,6 +4,7 @@ extern crate contract_interface;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod challenge;
 mod config;
 mod loader;
 mod metrics;
@@ -12,6 +13,7 @@ mod miner_id;
 mod monitor;
 pub mod pora;
 mod recall_range;
+mod reevaluate;
 mod sealer;
 mod service;
 mod submitter;
@@ -21,5 +23,7 @@ pub use config::MinerConfig;
 pub use loader::PoraLoader;
 pub use mine::MineRangeConfig;
 pub use miner_id::load_miner_id;
+pub use recall_range::RecallRange;
+pub use reevaluate::MinerState;
 pub use service::{MineService, MinerMessage};
 pub use storage::config::ShardConfig;
@@ -5,6 +5,10 @@ use storage_async::Store;
 #[async_trait]
 pub trait PoraLoader: Send + Sync {
     async fn load_sealed_data(&self, index: u64) -> Option<MineLoadChunk>;
+
+    /// Returns the sorted flow sector ranges `[start, end)` of the finalized data, or `None` if
+    /// it fails to tell.
+    async fn finalized_ranges(&self) -> Option<Vec<(u64, u64)>>;
 }
 
 #[async_trait]
@@ -15,4 +19,29 @@ impl PoraLoader for Store {
             _ => None,
         }
     }
+
+    async fn finalized_ranges(&self) -> Option<Vec<(u64, u64)>> {
+        let tx_ranges = match self.get_finalized_tx_ranges().await {
+            Ok(ranges) => ranges,
+            Err(e) => {
+                warn!(%e, "Failed to get the finalized files");
+                return None;
+            }
+        };
+
+        let mut ranges = Vec::with_capacity(tx_ranges.len());
+        for (start_tx_seq, end_tx_seq) in tx_ranges {
+            let first_tx = self.get_tx_by_seq_number(start_tx_seq).await;
+            let last_tx = self.get_tx_by_seq_number(end_tx_seq - 1).await;
+            match (first_tx, last_tx) {
+                (Ok(Some(first_tx)), Ok(Some(last_tx))) => ranges.push((
+                    first_tx.start_entry_index,
+                    last_tx.start_entry_index + last_tx.num_entries() as u64,
+                )),
+                // e.g. reverted in the meantime
+                _ => return None,
+            }
+        }
+        Some(ranges)
+    }
 }
@@ -12,6 +12,7 @@ use zgs_spec::{SECTORS_PER_LOAD, SECTORS_PER_MAX_MINING_RANGE, SECTORS_PER_PRICI
 use crate::recall_range::RecallRange;
 use crate::{
     pora::{AnswerWithoutProof, Miner},
+    reevaluate::{FinalizedRanges, MinerState},
     watcher::MineContextMessage,
     MinerConfig, MinerMessage, PoraLoader,
 };
@@ -32,6 +33,10 @@ pub struct PoraService {
 
     cpu_percentage: u64,
     iter_batch: usize,
+
+    state: Arc<MinerState>,
+    /// `None` if not evaluated yet.
+    finalized_ranges: Option<FinalizedRanges>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -125,6 +130,7 @@ impl PoraService {
         loader: Arc<dyn PoraLoader>,
         config: &MinerConfig,
         miner_id: H256,
+        state: Arc<MinerState>,
     ) -> mpsc::Receiver<AnswerWithoutProof> {
         let (mine_answer_sender, mine_answer_receiver) =
             mpsc::channel::<AnswerWithoutProof>(MINE_ANSWER_CHANNEL_CAPACITY);
@@ -143,6 +149,8 @@ impl PoraService {
             loader,
             cpu_percentage: config.cpu_percentage,
             iter_batch: config.iter_batch,
+            state,
+            finalized_ranges: None,
         };
         executor.spawn(async move { Box::pin(pora.start()).await }, "pora_master");
         mine_answer_receiver
@@ -156,6 +164,8 @@ impl PoraService {
         let diastole = sleep(Duration::from_secs(0));
         tokio::pin!(diastole);
 
+        self.reevaluate_finalized_ranges().await;
+
         loop {
             tokio::select! {
                 biased;
@@ -182,6 +192,10 @@ impl PoraService {
                             self.mine_range.shard_config = shard_config;
                             self.report_reason_if_mine_stop("update shard");
                         }
+                        Ok(MinerMessage::DataFinalized) => {
+                            self.reevaluate_finalized_ranges().await;
+                            self.report_reason_if_mine_stop("finalize data");
+                        }
                         Err(broadcast::error::RecvError::Closed) => {
                             warn!("Unexpected: Mine service config channel closed.");
                             channel_opened = false;
@@ -267,9 +281,27 @@ impl PoraService {
             subtask_digest: &puzzle.subtask_digest,
             pora_target: &puzzle.pora_target,
             loader: &*self.loader,
+            finalized_ranges: self.finalized_ranges.as_ref(),
         })
     }
 
+    /// Re-evaluates the ranges of the finalized data that can be proved.
+    async fn reevaluate_finalized_ranges(&mut self) {
+        self.finalized_ranges = self
+            .loader
+            .finalized_ranges()
+            .await
+            .map(FinalizedRanges::new);
+        debug!(
+            finalized_sectors = ?self
+                .finalized_ranges
+                .as_ref()
+                .map(FinalizedRanges::num_sectors),
+            "Re-evaluated the finalized data for mining"
+        );
+        self.state.record_reevaluation();
+    }
+
     fn report_reason_if_mine_stop(&self, event: &'static str) {
         if let Err(reason) = self.as_miner() {
             info!(reason, "Mine stopped on {}", event);
@@ -1,5 +1,6 @@
 use super::metrics::*;
 use crate::recall_range::RecallRange;
+use crate::reevaluate::FinalizedRanges;
 use crate::{MineRangeConfig, PoraLoader};
 use blake2::{Blake2b512, Digest};
 use contract_interface::pora_mine::MineContext;
@@ -29,6 +30,8 @@ pub(crate) struct Miner<'a> {
     pub pora_target: &'a U256,
     pub loader: &'a dyn PoraLoader,
     pub mine_range_config: &'a MineRangeConfig,
+    /// `None` if not evaluated yet.
+    pub finalized_ranges: Option<&'a FinalizedRanges>,
 }
 #[derive(Debug)]
 pub struct AnswerWithoutProof {
@@ -78,6 +81,17 @@ impl<'a> Miner<'a> {
             return None;
         }
 
+        if let Some(finalized_ranges) = self.finalized_ranges {
+            let end_position = recall_position + SECTORS_PER_LOAD as u64;
+            if !finalized_ranges.overlaps(recall_position, end_position) {
+                trace!(
+                    "recall offset not finalized: recall_offset={}",
+                    recall_position,
+                );
+                return None;
+            }
+        }
+
         inc_counter(&LOADING_COUNT);
         let MineLoadChunk {
             loaded_chunk,
@@ -0,0 +1,143 @@
+//! Notifies the miner when the finalized data grows, so that the newly finalized data is
+//! sealed and the mining eligibility is re-evaluated ahead of the challenges.
+
+use crate::MinerMessage;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use storage_async::Store;
+use task_executor::TaskExecutor;
+use tokio::sync::{broadcast, watch};
+use tokio::time::sleep;
+
+/// Delay to coalesce the finalization of many files, e.g. during bulk finalization.
+const REEVALUATE_DEBOUNCE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+pub struct MinerState {
+    /// Unix timestamp in seconds of the last re-evaluation, or 0 if not re-evaluated yet.
+    last_reevaluation: AtomicU64,
+}
+
+impl MinerState {
+    pub fn last_reevaluation_timestamp(&self) -> Option<u64> {
+        match self.last_reevaluation.load(Ordering::Relaxed) {
+            0 => None,
+            timestamp => Some(timestamp),
+        }
+    }
+
+    pub(crate) fn record_reevaluation(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.last_reevaluation.store(now, Ordering::Relaxed);
+    }
+}
+
+/// The flow sector ranges of the finalized data, which are re-evaluated when files are
+/// finalized so that the recall positions without data are skipped before loading.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FinalizedRanges {
+    /// Sorted ranges `[start, end)`.
+    ranges: Vec<(u64, u64)>,
+}
+
+impl FinalizedRanges {
+    pub fn new(ranges: Vec<(u64, u64)>) -> Self {
+        Self { ranges }
+    }
+
+    /// Returns whether any sector in `[start, end)` is finalized.
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        let i = self
+            .ranges
+            .partition_point(|(_, range_end)| *range_end <= start);
+        self.ranges
+            .get(i)
+            .map_or(false, |(range_start, _)| *range_start < end)
+    }
+
+    pub fn num_sectors(&self) -> u64 {
+        self.ranges.iter().map(|(start, end)| end - start).sum()
+    }
+}
+
+pub struct FinalizedWatcher;
+
+impl FinalizedWatcher {
+    pub fn spawn(executor: TaskExecutor, store: &Store, msg_send: broadcast::Sender<MinerMessage>) {
+        let finalized_recv = store.subscribe_finalized();
+        executor.spawn(
+            notify_on_finalized(finalized_recv, msg_send, REEVALUATE_DEBOUNCE),
+            "mine_finalized_watcher",
+        );
+    }
+}
+
+/// Sends `MinerMessage::DataFinalized` at most once per `debounce` when files are finalized.
+async fn notify_on_finalized(
+    mut finalized_recv: watch::Receiver<u64>,
+    msg_send: broadcast::Sender<MinerMessage>,
+    debounce: Duration,
+) {
+    while finalized_recv.changed().await.is_ok() {
+        sleep(debounce).await;
+        let num_finalized = *finalized_recv.borrow_and_update();
+        trace!(num_finalized, "Notify miner of finalized data");
+
+        if msg_send.send(MinerMessage::DataFinalized).is_err() {
+            warn!("Miner message channel closed");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{notify_on_finalized, FinalizedRanges};
+    use crate::MinerMessage;
+    use std::time::Duration;
+    use tokio::sync::{broadcast, watch};
+
+    #[test]
+    fn test_finalized_ranges() {
+        let ranges = FinalizedRanges::new(vec![(0, 10), (20, 30)]);
+        assert!(ranges.overlaps(0, 1));
+        assert!(ranges.overlaps(5, 25));
+        assert!(ranges.overlaps(29, 40));
+        assert!(!ranges.overlaps(10, 20));
+        assert!(!ranges.overlaps(30, 40));
+        assert_eq!(ranges.num_sectors(), 20);
+        assert!(!FinalizedRanges::default().overlaps(0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_notify_debounced() {
+        let (finalized_send, finalized_recv) = watch::channel(0);
+        let (msg_send, mut msg_recv) = broadcast::channel(16);
+        tokio::spawn(notify_on_finalized(
+            finalized_recv,
+            msg_send,
+            Duration::from_millis(100),
+        ));
+
+        // bulk finalization is notified only once
+        for _ in 0..10 {
+            finalized_send.send_modify(|num| *num += 1);
+        }
+        assert!(matches!(
+            msg_recv.recv().await,
+            Ok(MinerMessage::DataFinalized)
+        ));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(msg_recv.try_recv().is_err());
+
+        finalized_send.send_modify(|num| *num += 1);
+        assert!(matches!(
+            msg_recv.recv().await,
+            Ok(MinerMessage::DataFinalized)
+        ));
+    }
+}
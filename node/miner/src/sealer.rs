@@ -2,9 +2,11 @@ use std::{collections::BTreeMap, sync::Arc};
 
 use ethereum_types::H256;
 use ethers::prelude::{Http, Provider, RetryClient};
+use tokio::sync::broadcast;
 use tokio::time::{sleep, Duration, Instant};
 
 use contract_interface::{EpochRangeWithContextDigest, ZgsFlow};
+use log_dedup::{dedup_warn, LogDedup};
 use storage::{
     error::Result,
     log_store::{SealAnswer, SealTask},
@@ -14,6 +16,7 @@ use task_executor::TaskExecutor;
 use zgs_spec::SECTORS_PER_SEAL;
 
 use crate::config::MinerConfig;
+use crate::MinerMessage;
 
 const DB_QUERY_PERIOD_ON_NO_TASK: u64 = 1;
 const DB_QUERY_PERIOD_ON_ERROR: u64 = 5;
@@ -25,11 +28,13 @@ pub struct Sealer {
     context_cache: BTreeMap<u128, EpochRangeWithContextDigest>,
     last_context_flow_length: u64,
     miner_id: H256,
+    msg_recv: broadcast::Receiver<MinerMessage>,
 }
 
 impl Sealer {
     pub fn spawn(
         executor: TaskExecutor,
+        msg_recv: broadcast::Receiver<MinerMessage>,
         provider: Arc<Provider<RetryClient<Http>>>,
         store: Arc<Store>,
         config: &MinerConfig,
@@ -42,6 +47,7 @@ impl Sealer {
             context_cache: Default::default(),
             last_context_flow_length: 0,
             miner_id,
+            msg_recv,
         };
 
         executor.spawn(async move { Box::pin(sealer.start()).await }, "data_sealer");
@@ -54,10 +60,27 @@ impl Sealer {
         let contract_checker_throttle = sleep(Duration::from_secs(0));
         tokio::pin!(contract_checker_throttle);
 
+        let mut channel_opened = true;
+        let mut context_errors = LogDedup::default();
+        let mut seal_errors = LogDedup::default();
+
         loop {
             tokio::select! {
                 biased;
 
+                v = self.msg_recv.recv(), if channel_opened => {
+                    match v {
+                        // seal the newly finalized data without waiting for the next query
+                        Ok(MinerMessage::DataFinalized) => {
+                            db_checker_throttle.as_mut().reset(Instant::now());
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            channel_opened = false;
+                        }
+                        _ => {}
+                    }
+                }
+
                 () = &mut contract_checker_throttle, if !contract_checker_throttle.is_elapsed() => {
                 }
 
@@ -66,7 +89,7 @@ impl Sealer {
 
                 _ = async {}, if contract_checker_throttle.is_elapsed() => {
                     if let Err(err) = self.update_flow_length().await{
-                        warn!("Fetch onchain context failed {:?}", err);
+                        dedup_warn!(context_errors, "Fetch onchain context failed {:?}", err);
                     }
                     contract_checker_throttle.as_mut().reset(Instant::now() + Duration::from_secs(CHAIN_STATUS_QUERY_PERIOD));
                 }
@@ -76,7 +99,7 @@ impl Sealer {
                         Ok(true) => {},
                         Ok(false) => {db_checker_throttle.as_mut().reset(Instant::now() + Duration::from_secs(DB_QUERY_PERIOD_ON_NO_TASK));}
                         Err(err) => {
-                            warn!("Seal iteration failed {:?}", err);
+                            dedup_warn!(seal_errors, "Seal iteration failed {:?}", err);
                             db_checker_throttle.as_mut().reset(Instant::now() + Duration::from_secs(DB_QUERY_PERIOD_ON_ERROR));
                         }
                     }
@@ -1,5 +1,6 @@
 use crate::miner_id::check_and_request_miner_id;
 use crate::monitor::Monitor;
+use crate::reevaluate::{FinalizedWatcher, MinerState};
 use crate::sealer::Sealer;
 use crate::submitter::Submitter;
 use crate::{config::MinerConfig, mine::PoraService, watcher::MineContextWatcher};
@@ -21,6 +22,9 @@ pub enum MinerMessage {
 
     /// Change shard config
     SetShardConfig(ShardConfig),
+
+    /// Finalized data grew, so the sealable and provable data should be re-evaluated
+    DataFinalized,
 }
 
 pub struct MineService;
@@ -31,7 +35,7 @@ impl MineService {
         _network_send: NetworkSender,
         config: MinerConfig,
         store: Arc<Store>,
-    ) -> Result<broadcast::Sender<MinerMessage>, String> {
+    ) -> Result<(broadcast::Sender<MinerMessage>, Arc<MinerState>), String> {
         let provider = config.make_provider()?;
         let signing_provider = Arc::new(config.make_signing_provider().await?);
 
@@ -49,6 +53,8 @@ impl MineService {
             miner_id,
         );
 
+        let state = Arc::new(MinerState::default());
+
         let mine_answer_receiver = PoraService::spawn(
             executor.clone(),
             msg_recv.resubscribe(),
@@ -56,6 +62,7 @@ impl MineService {
             store.clone(),
             &config,
             miner_id,
+            state.clone(),
         );
 
         Submitter::spawn(
@@ -68,12 +75,21 @@ impl MineService {
             &config,
         );
 
-        Sealer::spawn(executor.clone(), provider, store, &config, miner_id);
+        FinalizedWatcher::spawn(executor.clone(), &store, msg_send.clone());
+
+        Sealer::spawn(
+            executor.clone(),
+            msg_recv.resubscribe(),
+            provider,
+            store,
+            &config,
+            miner_id,
+        );
 
         Monitor::spawn(executor, Duration::from_secs(5));
 
         debug!("Starting miner service");
 
-        Ok(msg_send)
+        Ok((msg_send, state))
     }
 }
@@ -16,6 +16,7 @@ use tokio::{
 
 use crate::{config::MineServiceMiddleware, mine::PoraPuzzle, MinerConfig, MinerMessage};
 use ethers::prelude::{Http, RetryClient};
+use log_dedup::{dedup_warn, LogDedup};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
@@ -80,6 +81,7 @@ impl MineContextWatcher {
     async fn start(mut self) {
         let mut mining_enabled = true;
         let mut channel_opened = true;
+        let mut query_errors = LogDedup::default();
 
         let mut mining_throttle = sleep(Duration::from_secs(0));
         tokio::pin!(mining_throttle);
@@ -105,8 +107,13 @@ impl MineContextWatcher {
 
                 _ = async {}, if mining_enabled && mining_throttle.is_elapsed() => {
                     mining_throttle.as_mut().reset(Instant::now() + self.query_interval);
-                    if let Err(err) = self.query_recent_context().await {
-                        warn!(err);
+                    match self.query_recent_context().await {
+                        Ok(()) => {
+                            if let Some(summary) = query_errors.clear() {
+                                warn!("{}", summary);
+                            }
+                        }
+                        Err(err) => dedup_warn!(query_errors, "{}", err),
                     }
                 }
             }
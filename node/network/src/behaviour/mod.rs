@@ -257,6 +257,10 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
             get_hash(GossipKind::AnnounceChunks),
             TopicScoreParams::default(),
         );
+        params.topics.insert(
+            get_hash(GossipKind::AnnounceFileRanges),
+            TopicScoreParams::default(),
+        );
 
         // Set up a scoring update interval
         let update_gossipsub_scores = tokio::time::interval(params.decay_interval);
@@ -91,6 +91,15 @@ pub struct Config {
     /// List of trusted libp2p nodes which are not scored.
     pub trusted_peers: Vec<PeerIdSerialized>,
 
+    /// List of libp2p nodes which are never dialed or accepted.
+    pub blacklist_peers: Vec<PeerIdSerialized>,
+
+    /// List of libp2p nodes which are only allowed to dial or accept in whitelist mode.
+    pub whitelist_peers: Vec<PeerIdSerialized>,
+
+    /// Whether to only dial or accept the whitelisted peers.
+    pub whitelist_mode: bool,
+
     /// Client version
     pub client_version: String,
 
@@ -203,6 +212,9 @@ impl Default for Config {
             boot_nodes_multiaddr: vec![],
             libp2p_nodes: vec![],
             trusted_peers: vec![],
+            blacklist_peers: vec![],
+            whitelist_peers: vec![],
+            whitelist_mode: false,
             client_version: zgs_version::version_with_platform(),
             disable_discovery: false,
             upnp_enabled: true,
@@ -0,0 +1,133 @@
+//! Health of the discovery routing table, which explains why a node cannot find peers to
+//! download data from, e.g. a sparse routing table or failing discovery queries.
+
+use discv5::{enr::NodeId, Key};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of the recent discovery queries to compute the success rate.
+const MAX_RECENT_QUERIES: usize = 32;
+
+/// A snapshot of the discovery state, which is updated whenever a discovery query completes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryHealth {
+    pub enabled: bool,
+    /// Number of nodes in the routing table.
+    pub table_size: usize,
+    /// Number of nodes in the routing table that are connected.
+    pub connected_nodes: usize,
+    /// Number of nodes in the occupied buckets, keyed by the log2 distance to the local node.
+    pub buckets: BTreeMap<u64, usize>,
+    /// Number of peers known by the peer manager, including the ones out of the routing table.
+    pub known_peers: usize,
+    /// Number of the recent discovery queries.
+    pub recent_queries: usize,
+    /// Number of the recent discovery queries that found any peer.
+    pub recent_successes: usize,
+    /// Success rate of the recent discovery queries, or `None` if no query completed yet.
+    pub success_rate: Option<f64>,
+    /// Unix timestamp in seconds of the last completed query.
+    pub last_query_at: Option<u64>,
+    /// Unix timestamp in seconds of the snapshot.
+    pub updated_at: u64,
+}
+
+/// Outcomes of the recent discovery queries.
+#[derive(Debug, Default)]
+pub(crate) struct QueryHistory {
+    /// Whether each recent query found any peer, from the oldest to the latest.
+    outcomes: VecDeque<bool>,
+    last_query_at: Option<u64>,
+}
+
+impl QueryHistory {
+    pub fn record(&mut self, success: bool) {
+        if self.outcomes.len() >= MAX_RECENT_QUERIES {
+            self.outcomes.pop_front();
+        }
+        self.outcomes.push_back(success);
+        self.last_query_at = Some(now_secs());
+    }
+
+    /// Builds the snapshot of the routing table with `entries` of `(node_id, connected)`.
+    pub fn health(
+        &self,
+        enabled: bool,
+        local_node_id: NodeId,
+        entries: impl IntoIterator<Item = (NodeId, bool)>,
+    ) -> DiscoveryHealth {
+        let local_key = Key::from(local_node_id);
+        let recent_queries = self.outcomes.len();
+        let recent_successes = self.outcomes.iter().filter(|success| **success).count();
+        let mut health = DiscoveryHealth {
+            enabled,
+            recent_queries,
+            recent_successes,
+            success_rate: (recent_queries > 0)
+                .then(|| recent_successes as f64 / recent_queries as f64),
+            last_query_at: self.last_query_at,
+            updated_at: now_secs(),
+            ..Default::default()
+        };
+
+        for (node_id, connected) in entries {
+            health.table_size += 1;
+            if connected {
+                health.connected_nodes += 1;
+            }
+            if let Some(distance) = Key::from(node_id).log2_distance(&local_key) {
+                *health.buckets.entry(distance).or_default() += 1;
+            }
+        }
+
+        health
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QueryHistory, MAX_RECENT_QUERIES};
+    use discv5::{enr::NodeId, Key};
+
+    #[test]
+    fn test_discovery_health() {
+        let mut history = QueryHistory::default();
+        let local_node_id = NodeId::random();
+        let health = history.health(true, local_node_id, vec![]);
+        assert_eq!(health.table_size, 0);
+        assert!(health.buckets.is_empty());
+        assert_eq!(health.success_rate, None);
+
+        // bucket occupancy
+        let entries: Vec<(NodeId, bool)> =
+            (0..20).map(|i| (NodeId::random(), i % 4 == 0)).collect();
+        let health = history.health(true, local_node_id, entries.clone());
+        assert_eq!(health.table_size, 20);
+        assert_eq!(health.connected_nodes, 5);
+        assert_eq!(health.buckets.values().sum::<usize>(), 20);
+        let local_key = Key::from(local_node_id);
+        let distance = Key::from(entries[0].0).log2_distance(&local_key).unwrap();
+        assert!(health.buckets[&distance] >= 1);
+
+        // success rate of the recent queries only
+        for _ in 0..MAX_RECENT_QUERIES {
+            history.record(false);
+        }
+        for i in 0..MAX_RECENT_QUERIES {
+            history.record(i % 2 == 0);
+        }
+        let health = history.health(true, local_node_id, vec![]);
+        assert_eq!(health.recent_queries, MAX_RECENT_QUERIES);
+        assert_eq!(health.success_rate, Some(0.5));
+        assert!(health.last_query_at.is_some());
+    }
+}
@@ -5,6 +5,7 @@
 
 pub(crate) mod enr;
 pub mod enr_ext;
+pub mod health;
 
 use crate::metrics;
 use crate::{error, Enr, NetworkConfig, NetworkGlobals};
@@ -13,6 +14,7 @@ pub use enr::{
     build_enr, create_enr_builder_from_config, load_enr_from_disk, use_or_load_enr, CombinedKey,
 };
 pub use enr_ext::{peer_id_to_node_id, CombinedKeyExt, EnrExt};
+use health::QueryHistory;
 pub use libp2p::core::identity::{Keypair, PublicKey};
 
 use futures::prelude::*;
@@ -32,12 +34,14 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
-    time::Instant,
+    time::{Duration, Instant},
 };
 use tokio::sync::mpsc;
 
 /// Local ENR storage filename.
 pub const ENR_FILENAME: &str = "enr.dat";
+/// The minimum interval between discovery refreshes requested by operators.
+pub const MIN_MANUAL_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
 /// The number of closest peers to search for when doing a regular peer search.
 ///
 /// We could reduce this constant to speed up queries however at the cost of security. It will
@@ -58,6 +62,8 @@ pub enum DiscoveryEvent {
 enum QueryType {
     /// We are searching for more peers without ENR or time constraints.
     FindPeers,
+    /// A random walk requested by operators, which is not limited by `find_peer_active`.
+    Refresh,
 }
 
 /// The result of a query.
@@ -109,6 +115,9 @@ pub struct Discovery {
     /// Active discovery queries.
     active_queries: FuturesUnordered<std::pin::Pin<Box<dyn Future<Output = QueryResult> + Send>>>,
 
+    /// Outcomes of the recent `FindPeers` queries.
+    query_history: QueryHistory,
+
     /// The discv5 event stream.
     event_stream: EventStream,
 
@@ -256,16 +265,20 @@ impl Discovery {
             }
         }
 
-        Ok(Self {
+        let discovery = Self {
             cached_enrs: LruCache::new(50),
             network_globals,
             find_peer_active: false,
             active_queries: FuturesUnordered::new(),
+            query_history: QueryHistory::default(),
             discv5,
             event_stream,
             started: !config.disable_discovery,
             enr_dir,
-        })
+        };
+        discovery.update_health();
+
+        Ok(discovery)
     }
 
     /// Return the nodes local ENR.
@@ -298,12 +311,24 @@ impl Discovery {
         self.start_query(QueryType::FindPeers, target_peers);
     }
 
+    /// Starts a discovery round immediately, even if a periodic `FindPeers` query is active.
+    pub fn refresh(&mut self) {
+        if !self.started {
+            warn!("Discovery is disabled, skip refreshing");
+            return;
+        }
+        info!("Refreshing discovery manually");
+        self.start_query(QueryType::Refresh, FIND_NODE_QUERY_CLOSEST_PEERS);
+    }
+
     /// Add an ENR to the routing table of the discovery mechanism.
     pub fn add_enr(&mut self, enr: Enr) {
         // add the enr to seen caches
         self.cached_enrs.put(enr.peer_id(), enr.clone());
 
-        if let Err(e) = self.discv5.add_enr(enr) {
+        let result = self.discv5.add_enr(enr);
+        self.update_health();
+        if let Err(e) = result {
             debug!(
                 error = %e,
                 "Could not add peer to the local routing table",
@@ -451,33 +476,68 @@ impl Discovery {
         &mut self,
         query: QueryResult,
     ) -> Option<HashMap<PeerId, Option<Instant>>> {
-        match query.query_type {
+        let manual = match query.query_type {
             QueryType::FindPeers => {
                 self.find_peer_active = false;
-                match query.result {
-                    Ok(r) if r.is_empty() => {
-                        debug!("Discovery query yielded no results.");
-                    }
-                    Ok(r) => {
-                        debug!(peers_found = r.len(), "Discovery query completed");
-                        let mut results: HashMap<_, Option<Instant>> = HashMap::new();
-                        r.iter().for_each(|enr| {
-                            // cache the found ENR's
-                            self.cached_enrs.put(enr.peer_id(), enr.clone());
-                            results.insert(enr.peer_id(), None);
-                        });
-                        return Some(results);
-                    }
-                    Err(e) => {
-                        warn!(error = %e, "Discovery query failed");
-                    }
+                false
+            }
+            QueryType::Refresh => true,
+        };
+        self.query_history
+            .record(matches!(&query.result, Ok(r) if !r.is_empty()));
+        self.update_health();
+
+        match query.result {
+            Ok(r) if r.is_empty() => {
+                debug!("Discovery query yielded no results.");
+                if manual {
+                    info!(new_peers = 0, "Manual discovery refresh completed");
+                }
+            }
+            Ok(r) => {
+                debug!(peers_found = r.len(), "Discovery query completed");
+                if manual {
+                    // peers are added into peer db after this query is returned
+                    let peers = self.network_globals.peers.read();
+                    let new_peers = r
+                        .iter()
+                        .filter(|enr| peers.peer_info(&enr.peer_id()).is_none())
+                        .count();
+                    info!(
+                        peers_found = r.len(),
+                        %new_peers,
+                        "Manual discovery refresh completed"
+                    );
                 }
+                let mut results: HashMap<_, Option<Instant>> = HashMap::new();
+                r.iter().for_each(|enr| {
+                    // cache the found ENR's
+                    self.cached_enrs.put(enr.peer_id(), enr.clone());
+                    results.insert(enr.peer_id(), None);
+                });
+                return Some(results);
+            }
+            Err(e) => {
+                warn!(error = %e, manual, "Discovery query failed");
             }
         }
 
         None
     }
 
+    /// Updates the routing table health in network globals, which is cheap to be queried by RPC.
+    fn update_health(&self) {
+        let entries = self
+            .discv5
+            .table_entries()
+            .into_iter()
+            .map(|(node_id, _, status)| (node_id, status.is_connected()));
+        let health =
+            self.query_history
+                .health(self.started, self.discv5.local_enr().node_id(), entries);
+        *self.network_globals.discovery_health.write() = health;
+    }
+
     /// Drives the queries returning any results from completed queries.
     fn poll_queries(&mut self, cx: &mut Context) -> Option<HashMap<PeerId, Option<Instant>>> {
         while let Poll::Ready(Some(query_result)) = self.active_queries.poll_next_unpin(cx) {
@@ -616,9 +676,8 @@ impl NetworkBehaviour for Discovery {
                                 DiscoveryEvent::SocketUpdated(socket),
                             ));
                         }
-                        Discv5Event::EnrAdded { .. }
-                        | Discv5Event::TalkRequest(_)
-                        | Discv5Event::NodeInserted { .. } => {} // Ignore all other discv5 server events
+                        Discv5Event::NodeInserted { .. } => self.update_health(),
+                        Discv5Event::EnrAdded { .. } | Discv5Event::TalkRequest(_) => {} // Ignore all other discv5 server events
                     }
                 }
             }
@@ -156,6 +156,10 @@ pub enum NetworkMessage {
     DialPeer { address: Multiaddr, peer_id: PeerId },
     /// Disconnect a peer.
     DisconnectPeer { peer_id: PeerId },
+    /// Clear the score and the reported actions of a peer.
+    ResetPeerState { peer_id: PeerId },
+    /// Start a discovery round immediately instead of waiting for the periodic schedule.
+    RefreshDiscovery,
     /// Notify that new file stored in db.
     AnnounceLocalFile { tx_id: TxID },
     /// Called if a known external TCP socket address has been updated.
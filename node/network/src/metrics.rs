@@ -1,4 +1,12 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
 pub use lighthouse_metrics::*;
+use parking_lot::Mutex;
+
+/// Time window to count the peers in `PEERS_DATA_AVAILABLE` and `PEERS_SERVED_SEGMENTS`.
+pub const PEER_ACTIVITY_WINDOW: Duration = Duration::from_secs(60);
 
 lazy_static! {
     pub static ref NAT_OPEN: Result<IntCounter> = try_create_int_counter(
@@ -11,8 +19,22 @@ lazy_static! {
     );
     pub static ref PEERS_CONNECTED: Result<IntGauge> = try_create_int_gauge(
         "libp2p_peers",
-        "Count of libp2p peers currently connected"
+        "Count of libp2p peers currently connected. This only means the peers are reachable \
+        at the network layer, not that they have any file data"
+    );
+    pub static ref PEERS_DATA_AVAILABLE: Result<IntGauge> = try_create_int_gauge(
+        "libp2p_peers_data_available",
+        "Count of peers that announced file data (AnnounceFile or AnnounceChunks, e.g. to answer \
+        a FindFile or FindChunks query) in the last minute. These peers may not be connected, \
+        and may still fail to serve the data"
+    );
+    pub static ref PEERS_SERVED_SEGMENTS: Result<IntGauge> = try_create_int_gauge(
+        "libp2p_peers_served_segments",
+        "Count of peers that served at least one valid file segment in the last minute. This is \
+        the number of peers that really provided data, which is at most the connected peers"
     );
+    pub static ref DATA_AVAILABLE_PEERS: RecentPeers = RecentPeers::new(&PEERS_DATA_AVAILABLE);
+    pub static ref SERVED_SEGMENTS_PEERS: RecentPeers = RecentPeers::new(&PEERS_SERVED_SEGMENTS);
 
     pub static ref PEER_CONNECT_EVENT_COUNT: Result<IntCounter> = try_create_int_counter(
         "libp2p_peer_connect_event_total",
@@ -153,6 +175,37 @@ pub fn check_nat() {
     }
 }
 
+/// Distinct peers seen in the last `PEER_ACTIVITY_WINDOW`, with the count exposed as a gauge.
+pub struct RecentPeers {
+    gauge: &'static Result<IntGauge>,
+    peers: Mutex<HashMap<PeerId, Instant>>,
+}
+
+impl RecentPeers {
+    fn new(gauge: &'static Result<IntGauge>) -> Self {
+        Self {
+            gauge,
+            peers: Default::default(),
+        }
+    }
+
+    pub fn mark(&self, peer_id: PeerId) {
+        let mut peers = self.peers.lock();
+        peers.insert(peer_id, Instant::now());
+        Self::update(self.gauge, &mut peers);
+    }
+
+    /// Removes the expired peers, which is required if no peer is marked for a while.
+    pub fn refresh(&self) {
+        Self::update(self.gauge, &mut self.peers.lock());
+    }
+
+    fn update(gauge: &Result<IntGauge>, peers: &mut HashMap<PeerId, Instant>) {
+        peers.retain(|_, since| since.elapsed() < PEER_ACTIVITY_WINDOW);
+        set_gauge(gauge, peers.len() as i64);
+    }
+}
+
 pub fn scrape_discovery_metrics() {
     let metrics = discv5::metrics::Metrics::from(discv5::Discv5::raw_metrics());
     set_float_gauge(&DISCOVERY_REQS, metrics.unsolicited_requests_per_second);
@@ -0,0 +1,289 @@
+//! Peers that are blocked or allowed by operators.
+//!
+//! Blacklisted peers are never dialed or accepted. In whitelist mode, only the whitelisted peers
+//! are dialed or accepted. The lists from config are merged with the runtime changes, which are
+//! persisted in the network directory apart from the config, so that removing a peer from config
+//! takes effect after a restart.
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// File name of the persisted lists in the network directory.
+pub const PEER_ACCESS_LIST_FILENAME: &str = "peer_access_list";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerAccessListInfo {
+    pub whitelist_mode: bool,
+    pub blacklist: Vec<String>,
+    pub whitelist: Vec<String>,
+}
+
+/// A list of peers from config, along with the peers added or removed at runtime.
+#[derive(Debug, Default)]
+struct PeerList {
+    configured: HashSet<PeerId>,
+    added: HashSet<PeerId>,
+    /// Configured peers that are removed at runtime.
+    removed: HashSet<PeerId>,
+}
+
+impl PeerList {
+    fn new(configured: Vec<PeerId>) -> Self {
+        Self {
+            configured: configured.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn contains(&self, peer_id: &PeerId) -> bool {
+        (self.configured.contains(peer_id) || self.added.contains(peer_id))
+            && !self.removed.contains(peer_id)
+    }
+
+    fn insert(&mut self, peer_id: PeerId) -> bool {
+        if self.contains(&peer_id) {
+            return false;
+        }
+        if !self.removed.remove(&peer_id) {
+            self.added.insert(peer_id);
+        }
+        true
+    }
+
+    fn remove(&mut self, peer_id: &PeerId) -> bool {
+        if !self.contains(peer_id) {
+            return false;
+        }
+        if !self.added.remove(peer_id) {
+            self.removed.insert(*peer_id);
+        }
+        true
+    }
+
+    fn peers(&self) -> Vec<String> {
+        let mut peers: Vec<String> = self
+            .configured
+            .iter()
+            .chain(self.added.iter())
+            .filter(|peer_id| self.contains(peer_id))
+            .map(|peer_id| peer_id.to_base58())
+            .collect();
+        peers.sort();
+        peers.dedup();
+        peers
+    }
+
+    /// Encodes the runtime changes as lines of `<name> <peer id>` for the added peers and
+    /// `un<name> <peer id>` for the removed ones.
+    fn encode_changes(&self, name: &str, content: &mut String) {
+        let sorted = |peers: &HashSet<PeerId>| {
+            let mut peers: Vec<String> = peers.iter().map(|p| p.to_base58()).collect();
+            peers.sort();
+            peers
+        };
+        for peer_id in sorted(&self.added) {
+            content.push_str(&format!("{} {}\n", name, peer_id));
+        }
+        for peer_id in sorted(&self.removed) {
+            content.push_str(&format!("un{} {}\n", name, peer_id));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PeerAccessList {
+    blacklist: PeerList,
+    whitelist: PeerList,
+    whitelist_mode: bool,
+    /// File to persist the lists, or `None` to keep the lists in memory only.
+    path: Option<PathBuf>,
+}
+
+impl PeerAccessList {
+    pub fn new(blacklist: Vec<PeerId>, whitelist: Vec<PeerId>, whitelist_mode: bool) -> Self {
+        Self {
+            blacklist: PeerList::new(blacklist),
+            whitelist: PeerList::new(whitelist),
+            whitelist_mode,
+            path: None,
+        }
+    }
+
+    /// Loads the runtime changes persisted in `path` and applies them to the configured lists.
+    pub fn load(
+        blacklist: Vec<PeerId>,
+        whitelist: Vec<PeerId>,
+        whitelist_mode: bool,
+        path: PathBuf,
+    ) -> io::Result<Self> {
+        let mut list = Self::new(blacklist, whitelist, whitelist_mode);
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    let invalid = || {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("invalid peer access list entry: {}", line),
+                        )
+                    };
+                    let (kind, peer_id) = line.split_once(' ').ok_or_else(invalid)?;
+                    let peer_id = PeerId::from_str(peer_id.trim()).map_err(|_| invalid())?;
+                    match kind {
+                        "blacklist" => list.blacklist.insert(peer_id),
+                        "unblacklist" => list.blacklist.remove(&peer_id),
+                        "whitelist" => list.whitelist.insert(peer_id),
+                        "unwhitelist" => list.whitelist.remove(&peer_id),
+                        _ => return Err(invalid()),
+                    };
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        list.path = Some(path);
+
+        Ok(list)
+    }
+
+    /// Returns whether the peer is allowed to dial or connect.
+    pub fn is_allowed(&self, peer_id: &PeerId) -> bool {
+        if self.blacklist.contains(peer_id) {
+            return false;
+        }
+
+        !self.whitelist_mode || self.whitelist.contains(peer_id)
+    }
+
+    pub fn is_blacklisted(&self, peer_id: &PeerId) -> bool {
+        self.blacklist.contains(peer_id)
+    }
+
+    /// Returns `false` if the peer has been blacklisted already.
+    pub fn blacklist(&mut self, peer_id: PeerId) -> io::Result<bool> {
+        let added = self.blacklist.insert(peer_id);
+        if added {
+            self.persist()?;
+        }
+        Ok(added)
+    }
+
+    /// Returns `false` if the peer is not blacklisted.
+    pub fn unblacklist(&mut self, peer_id: &PeerId) -> io::Result<bool> {
+        let removed = self.blacklist.remove(peer_id);
+        if removed {
+            self.persist()?;
+        }
+        Ok(removed)
+    }
+
+    /// Returns `false` if the peer has been whitelisted already.
+    pub fn whitelist(&mut self, peer_id: PeerId) -> io::Result<bool> {
+        let added = self.whitelist.insert(peer_id);
+        if added {
+            self.persist()?;
+        }
+        Ok(added)
+    }
+
+    /// Returns `false` if the peer is not whitelisted.
+    pub fn unwhitelist(&mut self, peer_id: &PeerId) -> io::Result<bool> {
+        let removed = self.whitelist.remove(peer_id);
+        if removed {
+            self.persist()?;
+        }
+        Ok(removed)
+    }
+
+    pub fn info(&self) -> PeerAccessListInfo {
+        PeerAccessListInfo {
+            whitelist_mode: self.whitelist_mode,
+            blacklist: self.blacklist.peers(),
+            whitelist: self.whitelist.peers(),
+        }
+    }
+
+    fn persist(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        // only the runtime changes are persisted, and the config is applied again on load
+        let mut content = String::new();
+        self.blacklist.encode_changes("blacklist", &mut content);
+        self.whitelist.encode_changes("whitelist", &mut content);
+
+        // write to a temporary file first to not corrupt the lists on crash
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PeerAccessList, PEER_ACCESS_LIST_FILENAME};
+    use libp2p::PeerId;
+
+    #[test]
+    fn test_access_list() {
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+
+        let mut list = PeerAccessList::new(vec![peer1], vec![], false);
+        assert!(!list.is_allowed(&peer1));
+        assert!(list.is_allowed(&peer2));
+
+        // blacklist takes precedence over whitelist
+        let mut whitelist_mode = PeerAccessList::new(vec![peer1], vec![peer1], true);
+        assert!(!whitelist_mode.is_allowed(&peer1));
+        assert!(!whitelist_mode.is_allowed(&peer2));
+        assert!(whitelist_mode.whitelist(peer2).unwrap());
+        assert!(whitelist_mode.is_allowed(&peer2));
+
+        assert!(list.unblacklist(&peer1).unwrap());
+        assert!(!list.unblacklist(&peer1).unwrap());
+        assert!(list.is_allowed(&peer1));
+    }
+
+    #[test]
+    fn test_access_list_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PEER_ACCESS_LIST_FILENAME);
+        let configured = PeerId::random();
+        let unblacklisted = PeerId::random();
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+
+        let mut list =
+            PeerAccessList::load(vec![configured, unblacklisted], vec![], false, path.clone())
+                .unwrap();
+        assert!(list.blacklist(peer1).unwrap());
+        assert!(!list.blacklist(peer1).unwrap());
+        assert!(list.whitelist(peer2).unwrap());
+        assert!(list.unblacklist(&unblacklisted).unwrap());
+
+        let list =
+            PeerAccessList::load(vec![configured, unblacklisted], vec![], true, path.clone())
+                .unwrap();
+        assert!(!list.is_allowed(&peer1));
+        assert!(list.is_allowed(&peer2));
+        assert!(list.is_blacklisted(&configured));
+        // the runtime removal of a configured peer is persisted
+        assert!(!list.is_blacklisted(&unblacklisted));
+
+        // configured peers are not persisted, so removing them from config takes effect
+        let list = PeerAccessList::load(vec![], vec![], false, path).unwrap();
+        assert!(!list.is_blacklisted(&configured));
+        assert!(list.is_blacklisted(&peer1));
+        assert_eq!(list.info().blacklist, vec![peer1.to_base58()]);
+    }
+}
@@ -41,6 +41,10 @@ pub struct Config {
     pub ping_interval_inbound: u64,
     /// Interval between PING events for peers dialed by us.
     pub ping_interval_outbound: u64,
+    /// Time in seconds to disconnect a peer since it's last known alive by a PING or PONG, which
+    /// frees the slot of an idle or unresponsive peer. 0 to keep peers connected however long
+    /// they are idle.
+    pub idle_timeout: u64,
 
     #[serde(skip)]
     pub filters: Filters,
@@ -56,6 +60,7 @@ impl Default for Config {
             status_interval: DEFAULT_STATUS_INTERVAL,
             ping_interval_inbound: DEFAULT_PING_INTERVAL_INBOUND,
             ping_interval_outbound: DEFAULT_PING_INTERVAL_OUTBOUND,
+            idle_timeout: 0,
             filters: Default::default(),
         }
     }
@@ -27,6 +27,7 @@ use peerdb::score::{PeerAction, ReportSource};
 pub use peerdb::sync_status::{SyncInfo, SyncStatus};
 use std::collections::HashMap;
 use std::net::IpAddr;
+pub mod access_list;
 pub mod config;
 mod network_behaviour;
 
@@ -61,6 +62,10 @@ pub struct PeerManager {
     outbound_ping_peers: HashSetDelay<PeerId>,
     /// A collection of peers awaiting to be Status'd.
     status_peers: HashSetDelay<PeerId>,
+    /// The time of the PINGs sent to peers and not answered yet.
+    pending_pings: HashMap<PeerId, Instant>,
+    /// Time to disconnect a peer since it's last known alive, disabled if zero.
+    idle_timeout: Duration,
     /// The target number of peers we would like to connect to.
     target_peers: usize,
     /// The heartbeat interval to perform routine maintenance.
@@ -110,6 +115,7 @@ impl PeerManager {
             status_interval,
             ping_interval_inbound,
             ping_interval_outbound,
+            idle_timeout,
             filters,
         } = cfg;
 
@@ -122,6 +128,8 @@ impl PeerManager {
             inbound_ping_peers: HashSetDelay::new(Duration::from_secs(ping_interval_inbound)),
             outbound_ping_peers: HashSetDelay::new(Duration::from_secs(ping_interval_outbound)),
             status_peers: HashSetDelay::new(Duration::from_secs(status_interval)),
+            pending_pings: HashMap::new(),
+            idle_timeout: Duration::from_secs(idle_timeout),
             target_peers: target_peer_count,
             heartbeat,
             discovery_enabled,
@@ -174,6 +182,20 @@ impl PeerManager {
         self.handle_score_action(peer_id, action, reason);
     }
 
+    /// Clears the score and the reported actions of a peer, e.g. after a transient issue
+    /// unfairly penalized a good peer. Returns `false` if the peer is unknown.
+    pub fn reset_peer_state(&mut self, peer_id: &PeerId) -> bool {
+        let action = self.network_globals.peers.write().reset_peer_state(peer_id);
+        match action {
+            Some(action) => {
+                info!(%peer_id, "Peer state reset");
+                self.handle_score_action(peer_id, action, None);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Upon adjusting a Peer's score, there are times the peer manager must pass messages up to
     /// libp2p. This function handles the conditional logic associated with each score update
     /// result.
@@ -285,6 +307,10 @@ impl PeerManager {
             to_dial_peers.retain(|peer_id| dial_peer_filter(peer_id));
         }
 
+        let peer_access = self.network_globals.peer_access.read();
+        to_dial_peers.retain(|peer_id| peer_access.is_allowed(peer_id));
+        drop(peer_access);
+
         // Queue another discovery if we need to
         self.maintain_peer_count(to_dial_peers.len());
 
@@ -338,6 +364,10 @@ impl PeerManager {
     ///
     /// This is used to determine if we should accept incoming connections.
     pub fn ban_status(&self, peer_id: &PeerId) -> BanResult {
+        if !self.network_globals.peer_access.read().is_allowed(peer_id) {
+            return BanResult::NotAllowed;
+        }
+
         self.network_globals.peers.read().ban_status(peer_id)
     }
 
@@ -515,7 +545,8 @@ impl PeerManager {
     /// A ping request has been received.
     // NOTE: The behaviour responds with a PONG automatically
     pub fn ping_request(&mut self, peer_id: &PeerId, seq: u64) {
-        if let Some(peer_info) = self.network_globals.peers.read().peer_info(peer_id) {
+        if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
+            peer_info.keep_alive(None);
             // received a ping
             // reset the to-ping timer for this peer
             trace!(%peer_id, seq_no = %seq, "Received a ping request");
@@ -537,13 +568,24 @@ impl PeerManager {
 
     /// A PONG has been returned from a peer.
     pub fn pong_response(&mut self, peer_id: &PeerId, _seq: u64) {
-        if let Some(_peer_info) = self.network_globals.peers.read().peer_info(peer_id) {
+        let ping_rtt = self
+            .pending_pings
+            .remove(peer_id)
+            .map(|sent| sent.elapsed());
+        if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
             // received a pong
+            peer_info.keep_alive(ping_rtt);
         } else {
             error!(%peer_id, "Received a PONG from an unknown peer");
         }
     }
 
+    /// Sends a PING to the peer, of which the round trip time is measured on the PONG.
+    fn send_ping(&mut self, peer_id: PeerId) {
+        self.pending_pings.insert(peer_id, Instant::now());
+        self.events.push(PeerManagerEvent::Ping(peer_id));
+    }
+
     /// Updates the gossipsub scores for all known peers in gossipsub.
     pub(crate) fn update_gossipsub_scores(&mut self, gossipsub: &Gossipsub) {
         let actions = self
@@ -644,6 +686,7 @@ impl PeerManager {
         self.inbound_ping_peers.remove(peer_id);
         self.outbound_ping_peers.remove(peer_id);
         self.status_peers.remove(peer_id);
+        self.pending_pings.remove(peer_id);
         self.events.extend(
             purged_peers
                 .into_iter()
@@ -1021,12 +1064,61 @@ impl PeerManager {
 
         // Update peer score metrics;
         self.update_peer_score_metrics();
+        metrics::DATA_AVAILABLE_PEERS.refresh();
+        metrics::SERVED_SEGMENTS_PEERS.refresh();
+
+        // Disconnect the peers that are blacklisted or not whitelisted at runtime.
+        self.disconnect_not_allowed_peers();
+
+        // Disconnect the peers idle for too long, e.g. failed to answer the PINGs.
+        self.disconnect_idle_peers();
 
         // Prune any excess peers back to our target in such a way that incentivises good scores and
         // a uniform distribution of subnets.
         self.prune_excess_peers();
     }
 
+    fn disconnect_not_allowed_peers(&mut self) {
+        let not_allowed: Vec<PeerId> = {
+            let peer_access = self.network_globals.peer_access.read();
+            self.network_globals
+                .peers
+                .read()
+                .connected_or_dialing_peers()
+                .filter(|peer_id| !peer_access.is_allowed(peer_id))
+                .cloned()
+                .collect()
+        };
+
+        for peer_id in not_allowed {
+            debug!(%peer_id, "Disconnecting peer not allowed by access list");
+            self.disconnect_peer(peer_id, GoodbyeReason::Banned);
+        }
+    }
+
+    fn disconnect_idle_peers(&mut self) {
+        if self.idle_timeout.is_zero() {
+            return;
+        }
+
+        let idle: Vec<PeerId> = self
+            .network_globals
+            .peers
+            .read()
+            .connected_peers()
+            .filter(|(_, info)| {
+                info.last_alive()
+                    .map_or(false, |alive| alive.elapsed() > self.idle_timeout)
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+
+        for peer_id in idle {
+            debug!(%peer_id, "Disconnecting idle peer");
+            self.disconnect_peer(peer_id, GoodbyeReason::Fault);
+        }
+    }
+
     // Update metrics related to peer scoring.
     fn update_peer_score_metrics(&self) {
         if !self.metrics_enabled {
@@ -1334,4 +1426,104 @@ mod tests {
         // the number of connected peers updates and we will not remove too many peers.
         assert_eq!(peer_manager.network_globals.connected_or_dialing_peers(), 3);
     }
+
+    #[tokio::test]
+    async fn test_peer_manager_rejects_blacklisted_peers() {
+        use libp2p::core::connection::ConnectionId;
+        use libp2p::core::ConnectedPoint;
+        use libp2p::swarm::NetworkBehaviour;
+
+        let mut peer_manager = build_peer_manager(10).await;
+        let blacklisted = PeerId::random();
+        let allowed = PeerId::random();
+        peer_manager
+            .network_globals
+            .peer_access
+            .write()
+            .blacklist(blacklisted)
+            .unwrap();
+
+        // outbound: blacklisted peers are never dialed
+        let discovered = HashMap::from([(blacklisted, None), (allowed, None)]);
+        assert_eq!(peer_manager.peers_discovered(discovered), vec![allowed]);
+
+        // inbound: blacklisted peers are disconnected once connected
+        let endpoint = ConnectedPoint::Listener {
+            local_addr: "/ip4/0.0.0.0".parse().unwrap(),
+            send_back_addr: "/ip4/0.0.0.0".parse().unwrap(),
+        };
+        for peer_id in [blacklisted, allowed] {
+            peer_manager.inject_connection_established(
+                &peer_id,
+                &ConnectionId::new(0),
+                &endpoint,
+                None,
+                0,
+            );
+        }
+        assert!(peer_manager.ban_status(&blacklisted).is_banned());
+        assert!(!peer_manager.is_connected(&blacklisted));
+        assert!(peer_manager.is_connected(&allowed));
+        assert!(peer_manager.events.iter().any(|e| matches!(
+            e,
+            PeerManagerEvent::DisconnectPeer(peer_id, GoodbyeReason::Banned) if *peer_id == blacklisted
+        )));
+
+        // connected peers are disconnected once blacklisted
+        peer_manager
+            .network_globals
+            .peer_access
+            .write()
+            .blacklist(allowed)
+            .unwrap();
+        peer_manager.heartbeat();
+        assert!(!peer_manager.is_connected(&allowed));
+    }
+
+    #[tokio::test]
+    async fn test_peer_manager_whitelist_mode() {
+        let mut peer_manager = build_peer_manager(10).await;
+        let whitelisted = PeerId::random();
+        let other = PeerId::random();
+        *peer_manager.network_globals.peer_access.write() =
+            access_list::PeerAccessList::new(vec![], vec![whitelisted], true);
+
+        let discovered = HashMap::from([(whitelisted, None), (other, None)]);
+        assert_eq!(peer_manager.peers_discovered(discovered), vec![whitelisted]);
+        assert!(!peer_manager.ban_status(&whitelisted).is_banned());
+        assert!(peer_manager.ban_status(&other).is_banned());
+    }
+
+    #[tokio::test]
+    async fn test_peer_manager_disconnects_idle_peers() {
+        let config = config::Config {
+            discovery_enabled: false,
+            idle_timeout: 1,
+            ..Default::default()
+        };
+        let globals = NetworkGlobals::new_test_globals();
+        let mut peer_manager = PeerManager::new(config, Arc::new(globals)).await.unwrap();
+
+        let idle = PeerId::random();
+        let pinging = PeerId::random();
+        let ponging = PeerId::random();
+        for peer_id in [idle, pinging, ponging] {
+            peer_manager.inject_connect_outgoing(&peer_id, "/ip4/0.0.0.0".parse().unwrap(), None);
+        }
+        peer_manager.send_ping(ponging);
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        peer_manager.ping_request(&pinging, 1);
+        peer_manager.pong_response(&ponging, 1);
+        peer_manager.heartbeat();
+
+        assert!(!peer_manager.is_connected(&idle));
+        assert!(peer_manager.is_connected(&pinging));
+        assert!(peer_manager.is_connected(&ponging));
+
+        let peers = peer_manager.network_globals.peers.read();
+        assert!(peers.peer_info(&pinging).unwrap().ping_rtt().is_none());
+        let ping_rtt = peers.peer_info(&ponging).unwrap().ping_rtt().unwrap();
+        assert!(ping_rtt >= Duration::from_millis(1100));
+    }
 }
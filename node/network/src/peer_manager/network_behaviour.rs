@@ -50,7 +50,7 @@ impl NetworkBehaviour for PeerManager {
             match self.inbound_ping_peers.poll_next_unpin(cx) {
                 Poll::Ready(Some(Ok(peer_id))) => {
                     self.inbound_ping_peers.insert(peer_id);
-                    self.events.push(PeerManagerEvent::Ping(peer_id));
+                    self.send_ping(peer_id);
                 }
                 Poll::Ready(Some(Err(e))) => {
                     error!(error = %e.to_string(), "Failed to check for inbound peers to ping")
@@ -63,7 +63,7 @@ impl NetworkBehaviour for PeerManager {
             match self.outbound_ping_peers.poll_next_unpin(cx) {
                 Poll::Ready(Some(Ok(peer_id))) => {
                     self.outbound_ping_peers.insert(peer_id);
-                    self.events.push(PeerManagerEvent::Ping(peer_id));
+                    self.send_ping(peer_id);
                 }
                 Poll::Ready(Some(Err(e))) => {
                     error!(error = %e.to_string(), "Failed to check for outbound peers to ping")
@@ -131,6 +131,11 @@ impl NetworkBehaviour for PeerManager {
                 self.goodbye_peer(peer_id, GoodbyeReason::BannedIP, ReportSource::PeerManager);
                 return;
             }
+            BanResult::NotAllowed => {
+                debug!(%peer_id, "Peer not allowed by access list. Disconnecting");
+                self.disconnect_peer(*peer_id, GoodbyeReason::Banned);
+                return;
+            }
             BanResult::NotBanned => {}
         }
 
@@ -579,6 +579,34 @@ impl PeerDB {
         }
     }
 
+    /// Clears the score and the reported actions of a known peer, which unbans the peer if it
+    /// was banned by the score. Returns `None` if the peer is unknown.
+    // VISIBILITY: Only the peer manager can reset the state, to lift the libp2p ban if any.
+    pub(super) fn reset_peer_state(&mut self, peer_id: &PeerId) -> Option<ScoreUpdateResult> {
+        let info = self.peers.get_mut(peer_id)?;
+        let previous_state = info.score_state();
+        info.reset_state();
+
+        if !matches!(
+            Self::handle_score_transition(previous_state, peer_id, info),
+            ScoreTransitionResult::Unbanned
+        ) {
+            return Some(ScoreUpdateResult::NoAction);
+        }
+
+        self.update_connection_state(peer_id, NewConnectionState::Unbanned);
+        let seen_ip_addresses = self
+            .peers
+            .get(peer_id)
+            .map(|info| {
+                info.seen_ip_addresses()
+                    .filter(|ip| !self.is_ip_banned(ip))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        Some(ScoreUpdateResult::Unbanned(seen_ip_addresses))
+    }
+
     /// Update min ttl of a peer.
     // VISIBILITY: Only the peer manager can update the min_ttl
     pub(super) fn update_min_ttl(&mut self, peer_id: &PeerId, min_ttl: Instant) {
@@ -1152,6 +1180,8 @@ pub enum BanResult {
     BadScore,
     /// The peer should be banned because it is connecting from a banned IP address.
     BannedIp(IpAddr),
+    /// The peer is blacklisted, or not whitelisted in whitelist mode.
+    NotAllowed,
     /// The peer is not banned.
     NotBanned,
 }
@@ -1955,4 +1985,48 @@ mod tests {
             Score::max_score().score()
         );
     }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_reset_peer_state() {
+        let mut pdb = get_db();
+        let peer = PeerId::random();
+        assert!(pdb.reset_peer_state(&peer).is_none());
+
+        pdb.connect_ingoing(&peer, "/ip4/0.0.0.0".parse().unwrap(), None);
+        for _ in 0..2 {
+            let _ = pdb.report_peer(
+                &peer,
+                PeerAction::HighToleranceError,
+                ReportSource::SyncService,
+                "",
+            );
+        }
+        let _ = pdb.report_peer(&peer, PeerAction::Fatal, ReportSource::SyncService, "");
+        pdb.inject_disconnect(&peer);
+        let info = pdb.peer_info(&peer).unwrap();
+        assert!(info.is_banned());
+        assert!(info.last_fault().is_some());
+        assert_eq!(info.fault_counts().get("high_tolerance_error"), Some(&2));
+        assert_eq!(info.fault_counts().get("fatal"), Some(&1));
+
+        // unbanned with a fresh start
+        assert!(matches!(
+            pdb.reset_peer_state(&peer),
+            Some(ScoreUpdateResult::Unbanned(_))
+        ));
+        let info = pdb.peer_info(&peer).unwrap();
+        assert!(!info.is_banned());
+        assert!(!info.score_is_banned());
+        assert_eq!(info.score().score(), Score::default().score());
+        assert!(info.fault_counts().is_empty());
+        assert!(info.last_fault().is_none());
+        assert_eq!(pdb.banned_peers().count(), 0);
+
+        // nothing to lift for a healthy peer
+        assert!(matches!(
+            pdb.reset_peer_state(&peer),
+            Some(ScoreUpdateResult::NoAction)
+        ));
+    }
 }
@@ -7,9 +7,9 @@ use serde::{
     ser::{SerializeStruct, Serializer},
     Serialize,
 };
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::net::{IpAddr, SocketAddr};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use strum::AsRefStr;
 use PeerConnectionStatus::*;
 
@@ -42,6 +42,17 @@ pub struct PeerInfo {
     connection_direction: Option<ConnectionDirection>,
     /// The enr of the peer, if known.
     enr: Option<Enr>,
+    /// Number of the reported actions by type since the peer is known or its state is reset.
+    fault_counts: BTreeMap<String, u64>,
+    /// The last time an action was reported.
+    #[serde(skip)]
+    last_fault: Option<Instant>,
+    /// Round trip time of the last PING answered by the peer.
+    #[serde(skip)]
+    ping_rtt: Option<Duration>,
+    /// The last time the peer is known alive, i.e. connected, PING'd us or answered our PING.
+    #[serde(skip)]
+    last_alive: Option<Instant>,
 }
 
 impl Default for PeerInfo {
@@ -57,6 +68,10 @@ impl Default for PeerInfo {
             is_trusted: false,
             connection_direction: None,
             enr: None,
+            fault_counts: BTreeMap::new(),
+            last_fault: None,
+            ping_rtt: None,
+            last_alive: None,
         }
     }
 }
@@ -144,6 +159,26 @@ impl PeerInfo {
         self.score.is_good_gossipsub_peer()
     }
 
+    /// Returns the number of the reported actions by type.
+    pub fn fault_counts(&self) -> &BTreeMap<String, u64> {
+        &self.fault_counts
+    }
+
+    /// Returns the last time an action was reported.
+    pub fn last_fault(&self) -> Option<&Instant> {
+        self.last_fault.as_ref()
+    }
+
+    /// Returns the round trip time of the last PING answered by the peer.
+    pub fn ping_rtt(&self) -> Option<Duration> {
+        self.ping_rtt
+    }
+
+    /// Returns the last time the peer is known alive.
+    pub fn last_alive(&self) -> Option<&Instant> {
+        self.last_alive.as_ref()
+    }
+
     /* Peer connection status API */
 
     /// Checks if the status is connected.
@@ -242,11 +277,38 @@ impl PeerInfo {
     /// Apply peer action to a non-trusted peer's score.
     // VISIBILITY: The peer manager is able to modify the score of a peer.
     pub(in crate::peer_manager) fn apply_peer_action_to_score(&mut self, peer_action: PeerAction) {
+        *self
+            .fault_counts
+            .entry(peer_action.as_ref().to_string())
+            .or_default() += 1;
+        self.last_fault = Some(Instant::now());
+
         if !self.is_trusted {
             self.score.apply_peer_action(peer_action)
         }
     }
 
+    /// Clears the penalties of the peer, i.e. the score and the reported actions, to give the
+    /// peer a fresh start.
+    pub(in crate::peer_manager) fn reset_state(&mut self) {
+        self.score = if self.is_trusted {
+            Score::max_score()
+        } else {
+            Score::default()
+        };
+        self.fault_counts.clear();
+        self.last_fault = None;
+    }
+
+    /// Marks the peer alive on a PING or PONG, with the round trip time if it answers our PING.
+    // VISIBILITY: The peer manager is able to track the keep-alive of a peer.
+    pub(in crate::peer_manager) fn keep_alive(&mut self, ping_rtt: Option<Duration>) {
+        self.last_alive = Some(Instant::now());
+        if ping_rtt.is_some() {
+            self.ping_rtt = ping_rtt;
+        }
+    }
+
     /// Updates the gossipsub score with a new score. Optionally ignore the gossipsub score.
     pub(super) fn update_gossipsub_score(&mut self, new_score: f64, ignore: bool) {
         self.score.update_gossipsub_score(new_score, ignore);
@@ -285,6 +347,8 @@ impl PeerInfo {
             | Unknown => {
                 self.connection_status = Connected { n_in: 1, n_out: 0 };
                 self.connection_direction = Some(ConnectionDirection::Incoming);
+                self.ping_rtt = None;
+                self.last_alive = Some(Instant::now());
             }
         }
 
@@ -305,6 +369,8 @@ impl PeerInfo {
             | Unknown => {
                 self.connection_status = Connected { n_in: 0, n_out: 1 };
                 self.connection_direction = Some(ConnectionDirection::Outgoing);
+                self.ping_rtt = None;
+                self.last_alive = Some(Instant::now());
             }
         }
         if let Some(ip_addr) = seen_address {
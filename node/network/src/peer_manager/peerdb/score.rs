@@ -63,6 +63,9 @@ pub enum PeerAction {
     /// kicked.
     /// NOTE: ~50 occurrences will get the peer banned
     HighToleranceError,
+    /// This peer served data that does not belong to the requested file, e.g. the segment of
+    /// another file. It's counted apart from other fatal actions, and gets the peer banned.
+    WrongFileData,
 }
 
 /// Service reporting a `PeerAction` for a peer.
@@ -94,6 +97,7 @@ impl std::fmt::Display for PeerAction {
             PeerAction::LowToleranceError => write!(f, "Low Tolerance Error"),
             PeerAction::MidToleranceError => write!(f, "Mid Tolerance Error"),
             PeerAction::HighToleranceError => write!(f, "High Tolerance Error"),
+            PeerAction::WrongFileData => write!(f, "Wrong File Data"),
         }
     }
 }
@@ -176,6 +180,7 @@ impl RealScore {
             PeerAction::LowToleranceError => self.add(-10.0),
             PeerAction::MidToleranceError => self.add(-5.0),
             PeerAction::HighToleranceError => self.add(-1.0),
+            PeerAction::WrongFileData => self.set_lighthouse_score(MIN_SCORE),
         }
     }
 
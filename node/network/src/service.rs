@@ -4,7 +4,7 @@ use crate::discovery::enr;
 use crate::multiaddr::Protocol;
 use crate::rpc::{GoodbyeReason, RPCResponseErrorCode, ReqId};
 use crate::types::{error, GossipKind};
-use crate::{EnrExt, NetworkSender};
+use crate::{EnrExt, NetworkSender, PeerIdSerialized};
 use crate::{NetworkConfig, NetworkGlobals, PeerAction, ReportSource};
 use futures::prelude::*;
 use libp2p::core::{
@@ -22,6 +22,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::peer_manager::access_list::{PeerAccessList, PEER_ACCESS_LIST_FILENAME};
 use crate::peer_manager::{MIN_OUTBOUND_ONLY_FACTOR, PEER_EXCESS_FACTOR, PRIORITY_PEER_EXCESS};
 
 pub const NETWORK_KEY_FILENAME: &str = "key";
@@ -87,6 +88,18 @@ impl<AppReqId: ReqId> Service<AppReqId> {
             config.network_id.clone(),
         ));
 
+        // load the peers blocked or allowed by operators
+        let to_peer_ids = |peers: &[PeerIdSerialized]| -> Vec<PeerId> {
+            peers.iter().map(|x| PeerId::from(x.clone())).collect()
+        };
+        *network_globals.peer_access.write() = PeerAccessList::load(
+            to_peer_ids(&config.blacklist_peers),
+            to_peer_ids(&config.whitelist_peers),
+            config.whitelist_mode,
+            config.network_dir.join(PEER_ACCESS_LIST_FILENAME),
+        )
+        .map_err(|e| format!("Failed to load peer access list: {:?}", e))?;
+
         // try and construct UPnP port mappings if required.
         if let Some(upnp_config) = crate::nat::UPnPConfig::from_config(config) {
             if config.upnp_enabled {
@@ -249,6 +262,7 @@ impl<AppReqId: ReqId> Service<AppReqId> {
             GossipKind::FindFile,
             GossipKind::AnnounceFile,
             GossipKind::AnnounceShardConfig,
+            GossipKind::AnnounceFileRanges,
         ];
         if config.find_chunks_enabled {
             topics.push(GossipKind::FindChunks);
@@ -310,6 +324,14 @@ impl<AppReqId: ReqId> Service<AppReqId> {
             .report_peer(peer_id, action, source, None, msg);
     }
 
+    /// Clears the score and the reported actions of a peer.
+    pub fn reset_peer_state(&mut self, peer_id: &PeerId) -> bool {
+        self.swarm
+            .behaviour_mut()
+            .peer_manager_mut()
+            .reset_peer_state(peer_id)
+    }
+
     /// Disconnect and ban a peer, providing a reason.
     pub fn goodbye_peer(&mut self, peer_id: &PeerId, reason: GoodbyeReason, source: ReportSource) {
         self.swarm
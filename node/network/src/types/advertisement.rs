@@ -0,0 +1,43 @@
+//! What a node advertises to the peers about the files it holds, which is either the finalized
+//! files only, or also the available chunk ranges of the partial files.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of the recent partial file advertisements to keep.
+const MAX_RECENT_PARTIAL_FILES: usize = 32;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialFileAdvertisement {
+    pub tx_seq: u64,
+    /// The available chunk ranges `[start, end)` of the file that are advertised.
+    pub chunk_ranges: Vec<(u64, u64)>,
+    /// Unix timestamp in seconds of the advertisement.
+    pub timestamp: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdvertisementStatus {
+    /// Whether the available chunk ranges of the partial files are advertised besides the
+    /// finalized files.
+    pub partial_files_enabled: bool,
+    /// Number of the finalized files announced.
+    pub finalized_files: u64,
+    /// Number of the partial files of which the chunk ranges are announced.
+    pub partial_files: u64,
+    /// The recent advertisements of the partial files, from the oldest.
+    pub recent_partial_files: VecDeque<PartialFileAdvertisement>,
+}
+
+impl AdvertisementStatus {
+    pub fn on_partial_file(&mut self, advertisement: PartialFileAdvertisement) {
+        self.partial_files += 1;
+        if self.recent_partial_files.len() >= MAX_RECENT_PARTIAL_FILES {
+            self.recent_partial_files.pop_front();
+        }
+        self.recent_partial_files.push_back(advertisement);
+    }
+}
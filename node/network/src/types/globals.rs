@@ -1,13 +1,17 @@
 //! A collection of variables that are accessible outside of the network thread itself.
+use crate::discovery::health::DiscoveryHealth;
+use crate::peer_manager::access_list::PeerAccessList;
 use crate::peer_manager::peerdb::PeerDB;
 use crate::peer_manager::peerdb::PeerDBConfig;
+use crate::types::{AdvertisementStatus, SeedStatus};
 use crate::Client;
 use crate::EnrExt;
 use crate::{Enr, GossipTopic, Multiaddr, PeerId};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use shared_types::NetworkIdentity;
 use std::collections::HashSet;
 use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
 
 pub struct NetworkGlobals {
     /// The current local ENR.
@@ -22,8 +26,18 @@ pub struct NetworkGlobals {
     pub listen_port_udp: AtomicU16,
     /// The collection of known peers.
     pub peers: RwLock<PeerDB>,
+    /// Peers that are blocked or allowed by operators.
+    pub peer_access: RwLock<PeerAccessList>,
     /// The current gossipsub topic subscriptions.
     pub gossipsub_subscriptions: RwLock<HashSet<GossipTopic>>,
+    /// The health of the discovery routing table, updated by the discovery service.
+    pub discovery_health: RwLock<DiscoveryHealth>,
+    /// The resolution status of the seed nodes, updated by the router.
+    pub seed_status: RwLock<Vec<SeedStatus>>,
+    /// What the node advertises about the files it holds, updated by the router.
+    pub advertisement: RwLock<AdvertisementStatus>,
+    /// The last time that operators refreshed the discovery manually.
+    last_discovery_refresh: Mutex<Option<Instant>>,
 
     /// The id of the storage network.
     pub network_id: RwLock<NetworkIdentity>,
@@ -45,7 +59,12 @@ impl NetworkGlobals {
             listen_port_tcp: AtomicU16::new(tcp_port),
             listen_port_udp: AtomicU16::new(udp_port),
             peers: RwLock::new(PeerDB::new(peer_db_config, trusted_peers)),
+            peer_access: RwLock::new(PeerAccessList::default()),
             gossipsub_subscriptions: RwLock::new(HashSet::new()),
+            discovery_health: RwLock::new(DiscoveryHealth::default()),
+            seed_status: RwLock::new(Vec::new()),
+            advertisement: RwLock::new(AdvertisementStatus::default()),
+            last_discovery_refresh: Mutex::new(None),
             network_id: RwLock::new(network_id),
         }
     }
@@ -95,6 +114,34 @@ impl NetworkGlobals {
         self.peers.read().connected_or_dialing_peers().count()
     }
 
+    /// Returns the health of the discovery routing table, together with the number of peers
+    /// known by the peer manager.
+    pub fn discovery_health(&self) -> DiscoveryHealth {
+        let mut health = self.discovery_health.read().clone();
+        health.known_peers = self.peers.read().peers().count();
+        health
+    }
+
+    pub fn seed_status(&self) -> Vec<SeedStatus> {
+        self.seed_status.read().clone()
+    }
+
+    pub fn advertisement(&self) -> AdvertisementStatus {
+        self.advertisement.read().clone()
+    }
+
+    /// Returns `false` if the discovery has been refreshed manually within `min_interval`,
+    /// otherwise, records the refresh and returns `true`.
+    pub fn try_refresh_discovery(&self, min_interval: Duration) -> bool {
+        let mut last = self.last_discovery_refresh.lock();
+        let now = Instant::now();
+        if matches!(*last, Some(t) if now.saturating_duration_since(t) < min_interval) {
+            return false;
+        }
+        *last = Some(now);
+        true
+    }
+
     /// Returns a `Client` type if one is known for the `PeerId`.
     pub fn client(&self, peer_id: &PeerId) -> Client {
         self.peers
@@ -1,13 +1,18 @@
+mod advertisement;
 pub mod error;
 mod globals;
 mod pubsub;
+mod seeds;
 mod topics;
 
 pub type Enr = discv5::enr::Enr<discv5::enr::CombinedKey>;
 
+pub use advertisement::{AdvertisementStatus, PartialFileAdvertisement};
 pub use globals::NetworkGlobals;
 pub use pubsub::{
-    AnnounceChunks, AnnounceFile, FindChunks, FindFile, HasSignature, PubsubMessage,
-    SignedAnnounceFile, SignedMessage, SnappyTransform, TimedMessage,
+    AnnounceChunks, AnnounceFile, AnnounceFileRanges, FindChunks, FindFile, HasSignature,
+    PubsubMessage, SignedAnnounceFile, SignedAnnounceFileRanges, SignedMessage, SnappyTransform,
+    TimedMessage, TxSeqRange,
 };
+pub use seeds::SeedStatus;
 pub use topics::{GossipEncoding, GossipKind, GossipTopic};
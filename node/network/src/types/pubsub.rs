@@ -2,6 +2,7 @@
 
 use crate::types::{GossipEncoding, GossipKind, GossipTopic};
 use crate::{Keypair, PublicKey, SigningError, TopicHash};
+use ethereum_types::H256;
 use libp2p::{
     gossipsub::{DataTransform, GossipsubMessage, RawGossipsubMessage},
     Multiaddr, PeerId,
@@ -144,6 +145,29 @@ pub struct AnnounceChunks {
     pub at: WrappedMultiaddr,
 }
 
+/// A range of tx seq numbers: `[start, end)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct TxSeqRange {
+    pub start: u64, // inclusive
+    pub end: u64,   // exclusive
+}
+
+/// Announces the finalized files of a peer as tx seq ranges, only including the
+/// differences from the previously announced version if possible.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct AnnounceFileRanges {
+    pub peer_id: WrappedPeerId,
+    pub shard_config: ShardConfig,
+    /// The version that `added` and `removed` are based on.
+    /// `None` means that `added` is the full set.
+    pub base_version: Option<u64>,
+    pub version: u64,
+    pub added: Vec<TxSeqRange>,
+    pub removed: Vec<TxSeqRange>,
+    /// Digest of the full range set at `version`.
+    pub digest: H256,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
 pub struct TimedMessage<T: Encode + Decode> {
     pub inner: T,
@@ -208,6 +232,7 @@ impl<T: Encode + Decode> HasSignature for SignedMessage<T> {
 
 pub type SignedAnnounceFile = SignedMessage<TimedMessage<AnnounceFile>>;
 type SignedAnnounceFiles = Vec<SignedAnnounceFile>;
+pub type SignedAnnounceFileRanges = SignedMessage<TimedMessage<AnnounceFileRanges>>;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PubsubMessage {
@@ -226,6 +251,8 @@ pub enum PubsubMessage {
     AnnounceShardConfig(TimedMessage<ShardConfig>),
     /// Published to network to announce chunks.
     AnnounceChunks(TimedMessage<AnnounceChunks>),
+    /// Published to network to reconcile the finalized files of a peer.
+    AnnounceFileRanges(SignedAnnounceFileRanges),
 }
 
 // Implements the `DataTransform` trait of gossipsub to employ snappy compression
@@ -306,6 +333,7 @@ impl PubsubMessage {
             PubsubMessage::AnnounceFile(_) => GossipKind::AnnounceFile,
             PubsubMessage::AnnounceChunks(_) => GossipKind::AnnounceChunks,
             PubsubMessage::AnnounceShardConfig(_) => GossipKind::AnnounceShardConfig,
+            PubsubMessage::AnnounceFileRanges(_) => GossipKind::AnnounceFileRanges,
         }
     }
 
@@ -355,6 +383,10 @@ impl PubsubMessage {
                         TimedMessage::<ShardConfig>::from_ssz_bytes(data)
                             .map_err(|e| format!("{:?}", e))?,
                     )),
+                    GossipKind::AnnounceFileRanges => Ok(PubsubMessage::AnnounceFileRanges(
+                        SignedAnnounceFileRanges::from_ssz_bytes(data)
+                            .map_err(|e| format!("{:?}", e))?,
+                    )),
                 }
             }
         }
@@ -376,6 +408,7 @@ impl PubsubMessage {
             PubsubMessage::AnnounceFile(data) => data.as_ssz_bytes(),
             PubsubMessage::AnnounceChunks(data) => data.as_ssz_bytes(),
             PubsubMessage::AnnounceShardConfig(data) => data.as_ssz_bytes(),
+            PubsubMessage::AnnounceFileRanges(data) => data.as_ssz_bytes(),
         }
     }
 }
@@ -407,6 +440,9 @@ impl std::fmt::Display for PubsubMessage {
             PubsubMessage::AnnounceShardConfig(msg) => {
                 write!(f, "AnnounceShardConfig message: {:?}", msg)
             }
+            PubsubMessage::AnnounceFileRanges(msg) => {
+                write!(f, "AnnounceFileRanges message: {:?}", msg)
+            }
         }
     }
 }
@@ -0,0 +1,19 @@
+//! Resolution status of the seed nodes, which are re-resolved and re-dialed by the router to
+//! keep a node connected when the discovery is not available, e.g. in private deployments.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedStatus {
+    /// The configured address, which may contain a DNS name.
+    pub address: String,
+    /// Addresses of the last successful resolution, which are dialed.
+    pub resolved_addresses: Vec<String>,
+    /// Unix timestamp in seconds of the last successful resolution.
+    pub last_resolved_at: Option<u64>,
+    /// Error of the last resolution if it failed.
+    pub last_error: Option<String>,
+    /// Number of the resolutions failed in a row, by which the retries are backed off.
+    pub consecutive_failures: u32,
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use network::types::{AnnounceFileRanges, TxSeqRange};
+use network::PeerId;
+use shared_types::ShardConfig;
+use storage::H256;
+use tiny_keccak::{Hasher, Keccak};
+
+/// SSZ encoded size of a `TxSeqRange`.
+const ENCODED_RANGE_SIZE: usize = 16;
+
+/// A set of tx seq numbers, kept as sorted and non-overlapping ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RangeSet {
+    ranges: Vec<TxSeqRange>,
+}
+
+impl RangeSet {
+    /// Builds a set from ranges that may be unsorted, overlapping or empty.
+    pub fn from_ranges(mut ranges: Vec<TxSeqRange>) -> Self {
+        ranges.retain(|r| r.start < r.end);
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<TxSeqRange> = Vec::with_capacity(ranges.len());
+        for r in ranges {
+            match merged.last_mut() {
+                Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+                _ => merged.push(r),
+            }
+        }
+
+        Self { ranges: merged }
+    }
+
+    pub fn contains(&self, seq: u64) -> bool {
+        let index = self.ranges.partition_point(|r| r.end <= seq);
+        self.ranges.get(index).map_or(false, |r| r.start <= seq)
+    }
+
+    pub fn insert(&mut self, seq: u64) {
+        self.union(&[TxSeqRange {
+            start: seq,
+            end: seq + 1,
+        }]);
+    }
+
+    pub fn union(&mut self, other: &[TxSeqRange]) {
+        let mut ranges = self.ranges.clone();
+        ranges.extend_from_slice(other);
+        *self = Self::from_ranges(ranges);
+    }
+
+    pub fn subtract(&mut self, other: &[TxSeqRange]) {
+        let other = Self::from_ranges(other.to_vec());
+        let mut result = Vec::with_capacity(self.ranges.len());
+        let mut j = 0;
+
+        for r in &self.ranges {
+            let mut start = r.start;
+            while j < other.ranges.len() && other.ranges[j].end <= start {
+                j += 1;
+            }
+
+            let mut k = j;
+            while start < r.end {
+                match other.ranges.get(k) {
+                    Some(o) if o.start < r.end => {
+                        if o.start > start {
+                            result.push(TxSeqRange {
+                                start,
+                                end: o.start,
+                            });
+                        }
+                        start = start.max(o.end);
+                        k += 1;
+                    }
+                    _ => {
+                        result.push(TxSeqRange { start, end: r.end });
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.ranges = result;
+    }
+
+    /// Returns the ranges `(added, removed)` to change `base` into `self`.
+    pub fn diff(&self, base: &RangeSet) -> (Vec<TxSeqRange>, Vec<TxSeqRange>) {
+        let mut added = self.clone();
+        added.subtract(&base.ranges);
+        let mut removed = base.clone();
+        removed.subtract(&self.ranges);
+        (added.ranges, removed.ranges)
+    }
+
+    pub fn digest(&self) -> H256 {
+        let mut hasher = Keccak::v256();
+        for r in &self.ranges {
+            hasher.update(&r.start.to_be_bytes());
+            hasher.update(&r.end.to_be_bytes());
+        }
+        let mut output = [0u8; 32];
+        hasher.finalize(&mut output);
+        H256(output)
+    }
+}
+
+/// Content of an `AnnounceFileRanges` message without the peer and shard info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RangeDelta {
+    pub base_version: Option<u64>,
+    pub version: u64,
+    pub added: Vec<TxSeqRange>,
+    pub removed: Vec<TxSeqRange>,
+    pub digest: H256,
+    /// Encoded size of the full set, to compute the bytes saved by sending a delta.
+    pub full_size: usize,
+}
+
+impl RangeDelta {
+    pub fn encoded_size(&self) -> usize {
+        (self.added.len() + self.removed.len()) * ENCODED_RANGE_SIZE
+    }
+}
+
+/// Result of applying an announcement to the view of a remote peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ApplyResult {
+    Applied,
+    /// The announcement is older than the current view, e.g. delivered out of order.
+    Stale,
+    /// Some previous delta is missing, so wait for the next full announcement.
+    Gap,
+    /// The digest mismatches after applying the delta, so the view is dropped.
+    DigestMismatch,
+}
+
+struct PeerView {
+    version: u64,
+    shard_config: ShardConfig,
+    ranges: RangeSet,
+}
+
+/// Reconciles the finalized files between peers by publishing range set deltas.
+///
+/// A full set is published every `full_interval` rounds, so that peers that missed
+/// some deltas or received them out of order could converge eventually.
+pub(crate) struct FileRangesReconciler {
+    local: RangeSet,
+    published: RangeSet,
+    version: u64,
+    rounds_since_full: Option<usize>,
+    full_interval: usize,
+    peers: HashMap<PeerId, PeerView>,
+}
+
+impl FileRangesReconciler {
+    pub fn new(full_interval: usize) -> Self {
+        Self {
+            local: Default::default(),
+            published: Default::default(),
+            version: 0,
+            rounds_since_full: None,
+            full_interval: full_interval.max(1),
+            peers: Default::default(),
+        }
+    }
+
+    pub fn insert_local(&mut self, tx_seq: u64) {
+        self.local.insert(tx_seq);
+    }
+
+    /// Replaces the local files, e.g. with the finalized files in the store at startup.
+    pub fn reset_local(&mut self, ranges: Vec<TxSeqRange>) {
+        self.local = RangeSet::from_ranges(ranges);
+    }
+
+    pub fn remove_local(&mut self, range: TxSeqRange) {
+        self.local.subtract(&[range]);
+    }
+
+    /// Returns the next delta to publish, or `None` if nothing changed since the last round.
+    pub fn next_delta(&mut self) -> Option<RangeDelta> {
+        let full_size = self.local.ranges.len() * ENCODED_RANGE_SIZE;
+        let is_full = match self.rounds_since_full {
+            None => true,
+            Some(rounds) => rounds + 1 >= self.full_interval,
+        };
+
+        let delta = if is_full {
+            self.rounds_since_full = Some(0);
+            RangeDelta {
+                base_version: None,
+                version: self.version + 1,
+                added: self.local.ranges.clone(),
+                removed: vec![],
+                digest: self.local.digest(),
+                full_size,
+            }
+        } else {
+            self.rounds_since_full = self.rounds_since_full.map(|r| r + 1);
+            let (added, removed) = self.local.diff(&self.published);
+            if added.is_empty() && removed.is_empty() {
+                return None;
+            }
+            RangeDelta {
+                base_version: Some(self.version),
+                version: self.version + 1,
+                added,
+                removed,
+                digest: self.local.digest(),
+                full_size,
+            }
+        };
+
+        self.version += 1;
+        self.published = self.local.clone();
+        Some(delta)
+    }
+
+    pub fn on_announcement(&mut self, peer_id: PeerId, msg: &AnnounceFileRanges) -> ApplyResult {
+        let ranges = match (msg.base_version, self.peers.get(&peer_id)) {
+            (_, Some(view)) if msg.version <= view.version => return ApplyResult::Stale,
+            (None, _) => RangeSet::from_ranges(msg.added.clone()),
+            (Some(base), Some(view)) if base == view.version => {
+                let mut ranges = view.ranges.clone();
+                ranges.subtract(&msg.removed);
+                ranges.union(&msg.added);
+                ranges
+            }
+            (Some(_), _) => return ApplyResult::Gap,
+        };
+
+        if ranges.digest() != msg.digest {
+            self.peers.remove(&peer_id);
+            return ApplyResult::DigestMismatch;
+        }
+
+        self.peers.insert(
+            peer_id,
+            PeerView {
+                version: msg.version,
+                shard_config: msg.shard_config,
+                ranges,
+            },
+        );
+        ApplyResult::Applied
+    }
+
+    /// Returns the peers that have the file of `tx_seq` and their shard configs.
+    pub fn peers_with_file(&self, tx_seq: u64) -> Vec<(PeerId, ShardConfig)> {
+        self.peers
+            .iter()
+            .filter(|(_, view)| view.ranges.contains(tx_seq))
+            .map(|(peer_id, view)| (*peer_id, view.shard_config))
+            .collect()
+    }
+
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use network::types::{AnnounceFileRanges, TxSeqRange};
+    use network::PeerId;
+    use shared_types::ShardConfig;
+
+    use super::{ApplyResult, FileRangesReconciler, RangeDelta, RangeSet};
+
+    fn range(start: u64, end: u64) -> TxSeqRange {
+        TxSeqRange { start, end }
+    }
+
+    fn to_msg(peer_id: PeerId, delta: RangeDelta) -> AnnounceFileRanges {
+        AnnounceFileRanges {
+            peer_id: peer_id.into(),
+            shard_config: ShardConfig::default(),
+            base_version: delta.base_version,
+            version: delta.version,
+            added: delta.added,
+            removed: delta.removed,
+            digest: delta.digest,
+        }
+    }
+
+    #[test]
+    fn test_range_set() {
+        let mut set = RangeSet::from_ranges(vec![range(5, 8), range(0, 2), range(1, 3)]);
+        assert_eq!(set.ranges, vec![range(0, 3), range(5, 8)]);
+
+        set.insert(3);
+        set.insert(4);
+        assert_eq!(set.ranges, vec![range(0, 8)]);
+
+        set.subtract(&[range(5, 6)]);
+        assert_eq!(set.ranges, vec![range(0, 5), range(6, 8)]);
+        assert!(set.contains(4));
+        assert!(!set.contains(5));
+
+        let base = RangeSet::from_ranges(vec![range(2, 7)]);
+        let (added, removed) = set.diff(&base);
+        assert_eq!(added, vec![range(0, 2), range(7, 8)]);
+        assert_eq!(removed, vec![range(5, 6)]);
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let peer_id = PeerId::random();
+        let mut local = FileRangesReconciler::new(4);
+        let mut remote = FileRangesReconciler::new(4);
+
+        local.reset_local(vec![range(0, 10)]);
+        let full = local.next_delta().unwrap();
+        assert_eq!(full.base_version, None);
+        let full_msg = to_msg(peer_id, full);
+        assert_eq!(
+            remote.on_announcement(peer_id, &full_msg),
+            ApplyResult::Applied
+        );
+
+        // nothing changed
+        assert!(local.next_delta().is_none());
+
+        // delta is dropped
+        local.insert_local(20);
+        let dropped = local.next_delta().unwrap();
+        assert_eq!(dropped.base_version, Some(1));
+        assert!(dropped.encoded_size() < dropped.full_size);
+
+        // the next delta cannot be applied, and the old one arrives later
+        local.remove_local(range(3, 4));
+        let delta = to_msg(peer_id, local.next_delta().unwrap());
+        assert_eq!(remote.on_announcement(peer_id, &delta), ApplyResult::Gap);
+        assert_eq!(
+            remote.peers_with_file(3),
+            vec![(peer_id, ShardConfig::default())]
+        );
+        assert_eq!(
+            remote.on_announcement(peer_id, &full_msg),
+            ApplyResult::Stale
+        );
+
+        // converge with the next full announcement
+        let full = local.next_delta().unwrap();
+        assert_eq!(full.base_version, None);
+        assert_eq!(
+            remote.on_announcement(peer_id, &to_msg(peer_id, full)),
+            ApplyResult::Applied
+        );
+        assert_eq!(remote.peers_with_file(20).len(), 1);
+        assert!(remote.peers_with_file(3).is_empty());
+    }
+}
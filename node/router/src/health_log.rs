@@ -0,0 +1,229 @@
+//! Periodic heartbeat log of the key health fields of the node, so that the operators of
+//! headless nodes could confirm the liveness and health at a glance without querying the RPC.
+//!
+//! Every heartbeat is a single log event with the fields as structured values, which is parsed
+//! cleanly in the JSON log format. The fields not available, e.g. the sync service does not
+//! respond in time, are omitted.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use file_location_cache::FileLocationCache;
+use network::NetworkGlobals;
+use storage::log_store::Store as LogStore;
+use sync::{SyncRequest, SyncResponse, SyncSender};
+use task_executor::TaskExecutor;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HealthStatus {
+    pub connected_peers: usize,
+    /// Connected peers that announced the shards of the data they store.
+    pub peers_with_data: usize,
+    pub log_sync_height: Option<u64>,
+    pub next_tx_seq: u64,
+    pub highest_finalized_seq: Option<u64>,
+    /// Files being synced or waiting to be synced.
+    pub pending_files: Option<usize>,
+    /// Files that failed to sync too many times and are not synced automatically anymore.
+    pub failed_files: Option<usize>,
+    pub miner_enabled: bool,
+}
+
+pub(crate) struct HealthLog {
+    network_globals: Arc<NetworkGlobals>,
+    file_location_cache: Arc<FileLocationCache>,
+    store: Arc<dyn LogStore>,
+    sync_send: SyncSender,
+    miner_enabled: bool,
+}
+
+impl HealthLog {
+    pub fn new(
+        network_globals: Arc<NetworkGlobals>,
+        file_location_cache: Arc<FileLocationCache>,
+        store: Arc<dyn LogStore>,
+        sync_send: SyncSender,
+        miner_enabled: bool,
+    ) -> Self {
+        Self {
+            network_globals,
+            file_location_cache,
+            store,
+            sync_send,
+            miner_enabled,
+        }
+    }
+
+    pub fn spawn(self, executor: &TaskExecutor, interval: Duration) {
+        executor.spawn(self.run(interval), "health_log");
+    }
+
+    async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.heartbeat().await;
+        }
+    }
+
+    /// Logs the health fields, and returns them.
+    pub async fn heartbeat(&self) -> HealthStatus {
+        let status = self.status().await;
+        info!(
+            connected_peers = status.connected_peers,
+            peers_with_data = status.peers_with_data,
+            log_sync_height = status.log_sync_height,
+            next_tx_seq = status.next_tx_seq,
+            highest_finalized_seq = status.highest_finalized_seq,
+            pending_files = status.pending_files,
+            failed_files = status.failed_files,
+            miner_enabled = status.miner_enabled,
+            "Heartbeat"
+        );
+        status
+    }
+
+    async fn status(&self) -> HealthStatus {
+        let (connected_peers, peers_with_data) = {
+            let peers = self.network_globals.peers.read();
+            let connected: Vec<_> = peers.connected_peer_ids().collect();
+            let with_data = connected
+                .iter()
+                .filter(|peer_id| self.file_location_cache.get_peer_config(peer_id).is_some())
+                .count();
+            (connected.len(), with_data)
+        };
+
+        let pending_files = match self.sync_send.request(SyncRequest::SyncState).await {
+            Ok(SyncResponse::SyncState { state }) => Some(
+                state.num_syncing
+                    + state
+                        .auto_sync_random
+                        .map_or(0, |random| random.pending_txs + random.ready_txs),
+            ),
+            _ => None,
+        };
+        let failed_files = match self.sync_send.request(SyncRequest::DeadLetterFiles).await {
+            Ok(SyncResponse::DeadLetterFiles { result }) => Some(result.len()),
+            _ => None,
+        };
+
+        HealthStatus {
+            connected_peers,
+            peers_with_data,
+            log_sync_height: self
+                .store
+                .get_sync_progress()
+                .ok()
+                .flatten()
+                .map(|(height, _)| height),
+            next_tx_seq: self.store.next_tx_seq(),
+            highest_finalized_seq: self.store.get_highest_finalized_seq().ok().flatten(),
+            pending_files,
+            failed_files,
+            miner_enabled: self.miner_enabled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HealthLog, HealthStatus};
+    use channel::Message;
+    use file_location_cache::FileLocationCache;
+    use network::NetworkGlobals;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use storage::log_store::{log_manager::LogConfig, Store};
+    use storage::{LogManager, H256};
+    use sync::auto_sync::batcher_random::RandomBatcherState;
+    use sync::disk_watchdog::DiskSpaceState;
+    use sync::{DeadLetterFile, SyncReceiver, SyncRequest, SyncResponse, SyncServiceState};
+
+    /// Responds to the sync state requests, and returns the number of the requests.
+    fn respond_sync_requests(mut sync_recv: SyncReceiver) -> Arc<AtomicUsize> {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = sync_recv.recv().await {
+                let Message::Request(request, sender) = msg else {
+                    continue;
+                };
+                let response = match request {
+                    SyncRequest::SyncState => {
+                        counter.fetch_add(1, Ordering::Relaxed);
+                        SyncResponse::SyncState {
+                            state: SyncServiceState {
+                                serve_only: false,
+                                paused: false,
+                                num_syncing: 2,
+                                catched_up: Some(true),
+                                auto_sync_serial: None,
+                                auto_sync_random: Some(RandomBatcherState {
+                                    name: "random".into(),
+                                    tasks: vec![],
+                                    pending_txs: 3,
+                                    ready_txs: 1,
+                                    cached_ready_txs: 0,
+                                    dead_txs: 0,
+                                }),
+                                disk_space: DiskSpaceState {
+                                    available_bytes: None,
+                                    min_free_bytes: 0,
+                                    download_paused: false,
+                                },
+                                checkpoint: Default::default(),
+                            },
+                        }
+                    }
+                    SyncRequest::DeadLetterFiles => SyncResponse::DeadLetterFiles {
+                        result: vec![DeadLetterFile {
+                            tx_seq: 7,
+                            attempts: 10,
+                            last_error: "timeout".into(),
+                        }],
+                    },
+                    _ => continue,
+                };
+                let _ = sender.send(response);
+            }
+        });
+        requests
+    }
+
+    #[tokio::test]
+    async fn test_health_heartbeat() {
+        let store: Arc<dyn Store> = Arc::new(LogManager::memorydb(LogConfig::default()).unwrap());
+        store.put_sync_progress((100, H256::zero(), None)).unwrap();
+        let (sync_send, sync_recv) = channel::Channel::unbounded("test_health_log");
+        let requests = respond_sync_requests(sync_recv);
+        let health_log = HealthLog::new(
+            Arc::new(NetworkGlobals::new_test_globals()),
+            Arc::new(FileLocationCache::default()),
+            store,
+            sync_send,
+            true,
+        );
+
+        assert_eq!(
+            health_log.heartbeat().await,
+            HealthStatus {
+                connected_peers: 0,
+                peers_with_data: 0,
+                log_sync_height: Some(100),
+                next_tx_seq: 0,
+                highest_finalized_seq: None,
+                pending_files: Some(6),
+                failed_files: Some(1),
+                miner_enabled: true,
+            }
+        );
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+
+        // fires periodically
+        tokio::spawn(health_log.run(Duration::from_millis(20)));
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(requests.load(Ordering::Relaxed) >= 4);
+    }
+}
@@ -2,10 +2,13 @@
 extern crate tracing;
 
 mod batcher;
+mod file_ranges;
+mod health_log;
 mod libp2p_event_handler;
 mod metrics;
 mod peer_manager;
 mod rate_limit;
+mod seeds;
 mod service;
 
 use duration_str::deserialize_duration;
@@ -37,6 +40,41 @@ pub struct Config {
     pub batcher_file_capacity: usize,
     /// Number of announcements in a pubsub message
     pub batcher_announcement_capacity: usize,
+
+    // file ranges
+    /// Whether to publish finalized files as tx seq ranges, so that peers only
+    /// exchange the changes since the last announcement.
+    pub file_ranges_enabled: bool,
+    /// Interval to publish the file ranges announcement.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub file_ranges_interval: Duration,
+    /// Publish the full file ranges every `file_ranges_full_rounds` announcements,
+    /// so that peers missing some deltas could converge.
+    pub file_ranges_full_rounds: usize,
+
+    /// Whether to also answer the file queries with the chunk ranges of the files that are not
+    /// finalized yet but downloaded by the file sync. Only the finalized files are advertised
+    /// if `false`.
+    pub advertise_partial_files: bool,
+
+    // seeds, i.e. `libp2p_nodes`, which are dialed periodically and whenever the connected
+    // peers are too few, and of which the DNS names are resolved again on every dial
+    /// Dial the seeds whenever the connected peers drop below it.
+    pub seed_min_peers: usize,
+    /// Interval to resolve and dial the seeds regardless of the connected peers.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub seed_refresh_interval: Duration,
+    /// Minimum interval to dial a seed when the connected peers are too few, which is also the
+    /// initial backoff to retry a failed resolution and doubled on every failure.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub seed_retry_backoff: Duration,
+
+    // health log
+    /// Whether to log the key health fields periodically, e.g. the connected peers and the
+    /// sync progress, as a single structured event.
+    pub health_log_enabled: bool,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub health_log_interval: Duration,
 }
 
 impl Default for Config {
@@ -54,6 +92,19 @@ impl Default for Config {
             batcher_timeout: Duration::from_secs(1),
             batcher_file_capacity: 1,
             batcher_announcement_capacity: 1,
+
+            file_ranges_enabled: false,
+            file_ranges_interval: Duration::from_secs(60),
+            file_ranges_full_rounds: 10,
+
+            advertise_partial_files: false,
+
+            seed_min_peers: 1,
+            seed_refresh_interval: Duration::from_secs(300),
+            seed_retry_backoff: Duration::from_secs(10),
+
+            health_log_enabled: false,
+            health_log_interval: Duration::from_secs(60),
         }
     }
 }
@@ -1,5 +1,6 @@
+use std::collections::HashMap;
 use std::net::IpAddr;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{ops::Neg, sync::Arc};
 
 use chunk_pool::ChunkPoolMessage;
@@ -9,8 +10,9 @@ use network::types::TimedMessage;
 use network::{
     rpc::StatusMessage,
     types::{
-        AnnounceChunks, AnnounceFile, FindChunks, FindFile, HasSignature, SignedAnnounceFile,
-        SignedMessage,
+        AnnounceChunks, AnnounceFile, AnnounceFileRanges, FindChunks, FindFile, HasSignature,
+        PartialFileAdvertisement, SignedAnnounceFile, SignedAnnounceFileRanges, SignedMessage,
+        TxSeqRange,
     },
     Keypair, MessageAcceptance, MessageId, NetworkGlobals, NetworkMessage, PeerId, PeerRequestId,
     PublicKey, PubsubMessage, Request, RequestId, Response,
@@ -18,12 +20,15 @@ use network::{
 use network::{Multiaddr, NetworkSender, PeerAction, ReportSource};
 use shared_types::{bytes_to_chunks, timestamp_now, NetworkIdentity, ShardedFile, TxID};
 use storage::config::ShardConfig;
+use storage::log_store::RemovedFiles;
 use storage_async::Store;
-use sync::{SyncMessage, SyncSender};
+use sync::{SyncMessage, SyncRequest, SyncResponse, SyncSender};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{mpsc, RwLock};
 
 use crate::batcher::Batcher;
+use crate::file_ranges::{ApplyResult, FileRangesReconciler};
 use crate::metrics::{self, PubsubMsgHandleMetrics};
 use crate::peer_manager::PeerManager;
 use crate::Config;
@@ -36,6 +41,11 @@ lazy_static::lazy_static! {
     pub static ref TOLERABLE_DRIFT: chrono::Duration = chrono::Duration::seconds(10);
 }
 
+/// Maximum number of the chunk ranges to advertise for a partial file.
+const MAX_PARTIAL_FILE_RANGES: usize = 16;
+/// Minimum interval to advertise the chunk ranges of the same partial file again.
+const PARTIAL_FILE_PUBLISH_INTERVAL: Duration = Duration::from_secs(60);
+
 fn duration_since(timestamp: u32, latency_ms: Arc<dyn ::metrics::Histogram>) -> chrono::Duration {
     let timestamp = i64::from(timestamp);
     let timestamp = chrono::DateTime::from_timestamp(timestamp, 0).expect("should fit");
@@ -132,6 +142,10 @@ pub struct Libp2pEventHandler {
     file_batcher: RwLock<Batcher<TxID>>,
     /// Announcements to publish in batch
     announcement_batcher: RwLock<Batcher<SignedAnnounceFile>>,
+    /// Finalized files of this node and other peers in tx seq ranges.
+    file_ranges: RwLock<FileRangesReconciler>,
+    /// Partial files of which the chunk ranges are advertised recently, by tx seq.
+    partial_files_published: RwLock<HashMap<u64, Instant>>,
 }
 
 impl Libp2pEventHandler {
@@ -159,6 +173,11 @@ impl Libp2pEventHandler {
             "announcement",
         ));
 
+        let file_ranges = RwLock::new(FileRangesReconciler::new(config.file_ranges_full_rounds));
+
+        network_globals.advertisement.write().partial_files_enabled =
+            config.advertise_partial_files;
+
         Self {
             config,
             network_globals,
@@ -171,6 +190,8 @@ impl Libp2pEventHandler {
             peers,
             file_batcher,
             announcement_batcher,
+            file_ranges,
+            partial_files_published: Default::default(),
         }
     }
 
@@ -229,6 +250,7 @@ impl Libp2pEventHandler {
 
     pub async fn on_peer_disconnected(&self, peer_id: PeerId) {
         self.peers.write().await.remove(&peer_id);
+        self.file_ranges.write().await.remove_peer(&peer_id);
         self.send_to_sync(SyncMessage::PeerDisconnected { peer_id });
         metrics::LIBP2P_HANDLE_PEER_DISCONNECTED.mark(1);
     }
@@ -394,6 +416,9 @@ impl Libp2pEventHandler {
             PubsubMessage::AnnounceShardConfig(msg) => {
                 self.on_announce_shard_config(propagation_source, source, msg)
             }
+            PubsubMessage::AnnounceFileRanges(msg) => {
+                self.on_announce_file_ranges(propagation_source, msg).await
+            }
         }
     }
 
@@ -631,6 +656,11 @@ impl Libp2pEventHandler {
             }
         }
 
+        // advertise the available chunks of a partial file, and still look for the whole file
+        if self.config.advertise_partial_files {
+            self.publish_partial_file(tx_id).await;
+        }
+
         // try from cache
         if let Some(mut msg) = self.file_location_cache.get_one(tx_id) {
             trace!(?tx_id, "Found file in cache, responding to FindFile query");
@@ -816,6 +846,7 @@ impl Libp2pEventHandler {
             metrics::LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_TIMEOUT.mark(1);
             return MessageAcceptance::Ignore;
         }
+        network::metrics::DATA_AVAILABLE_PEERS.mark(msg.peer_id.clone().into());
 
         // notify sync layer if shard config matches
         let my_shard_config = self.store.get_store().get_shard_config();
@@ -898,12 +929,64 @@ impl Libp2pEventHandler {
             return MessageAcceptance::Reject;
         }
 
+        network::metrics::DATA_AVAILABLE_PEERS.mark(msg.peer_id.clone().into());
+
         // notify sync layer
         self.send_to_sync(SyncMessage::AnnounceChunksGossip { msg: msg.inner });
 
         MessageAcceptance::Accept
     }
 
+    async fn on_announce_file_ranges(
+        &self,
+        propagation_source: PeerId,
+        msg: SignedAnnounceFileRanges,
+    ) -> MessageAcceptance {
+        // verify timestamp
+        if !metrics::LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_RANGES.verify_timestamp(
+            propagation_source,
+            msg.timestamp,
+            *PUBSUB_TIMEOUT_NETWORK,
+            None,
+        ) {
+            return MessageAcceptance::Ignore;
+        }
+
+        // verify message signature
+        if !verify_signature(&msg, &msg.peer_id, propagation_source) {
+            return MessageAcceptance::Reject;
+        }
+
+        // verify announced shard config
+        if ShardConfig::try_from(msg.shard_config).is_err() {
+            return MessageAcceptance::Reject;
+        }
+
+        let peer_id: PeerId = msg.peer_id.clone().into();
+        match self
+            .file_ranges
+            .write()
+            .await
+            .on_announcement(peer_id, &msg.inner.inner)
+        {
+            ApplyResult::Applied => MessageAcceptance::Accept,
+            ApplyResult::Stale => {
+                metrics::LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_RANGES_STALE.mark(1);
+                MessageAcceptance::Ignore
+            }
+            ApplyResult::Gap => {
+                // Still propagate it, other peers may have the base version.
+                metrics::LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_RANGES_GAP.mark(1);
+                MessageAcceptance::Accept
+            }
+            ApplyResult::DigestMismatch => {
+                debug!(%peer_id, version = msg.version, "Mismatched digest of AnnounceFileRanges message");
+                metrics::LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_RANGES_MISMATCH.mark(1);
+                MessageAcceptance::Ignore
+            }
+        }
+    }
+
     fn verify_status_message(
         &self,
         peer_id: PeerId,
@@ -955,6 +1038,7 @@ impl Libp2pEventHandler {
     }
 
     async fn publish_file(&self, tx_id: TxID) {
+        self.network_globals.advertisement.write().finalized_files += 1;
         if let Some(batch) = self.file_batcher.write().await.add(tx_id) {
             if let Some(announcement) = self.construct_announce_file_message(batch).await {
                 self.publish_announcement(announcement).await;
@@ -962,12 +1046,184 @@ impl Libp2pEventHandler {
         }
     }
 
+    /// Publish the available chunk ranges of a file that is not finalized yet, if any, so that
+    /// a partial file is never advertised as held.
+    async fn publish_partial_file(&self, tx_id: TxID) {
+        // the queries of the same file are answered at most once per interval
+        {
+            let now = Instant::now();
+            let mut published = self.partial_files_published.write().await;
+            published.retain(|_, at| now.duration_since(*at) < PARTIAL_FILE_PUBLISH_INTERVAL);
+            if published.contains_key(&tx_id.seq) {
+                return;
+            }
+            published.insert(tx_id.seq, now);
+        }
+
+        let tx = match self.store.get_tx_by_seq_number(tx_id.seq).await {
+            Ok(Some(tx)) if tx.id() == tx_id => tx,
+            _ => return,
+        };
+        // finalized, pruned or skipped
+        if !matches!(self.store.get_tx_status(tx.seq).await, Ok(None)) {
+            return;
+        }
+
+        let chunk_ranges = self.available_chunk_ranges(tx.seq).await;
+        if chunk_ranges.is_empty() {
+            return;
+        }
+
+        let mut messages = Vec::with_capacity(chunk_ranges.len());
+        for (index_start, index_end) in &chunk_ranges {
+            match self
+                .construct_announce_chunks_message(tx_id, *index_start, *index_end)
+                .await
+            {
+                Some(msg) => messages.push(msg),
+                None => return,
+            }
+        }
+
+        trace!(
+            ?tx_id,
+            ?chunk_ranges,
+            "Found partial file locally, responding to FindFile query"
+        );
+        self.send_to_network(NetworkMessage::Publish { messages });
+        self.network_globals
+            .advertisement
+            .write()
+            .on_partial_file(PartialFileAdvertisement {
+                tx_seq: tx.seq,
+                chunk_ranges,
+                timestamp: timestamp_now(),
+            });
+    }
+
+    /// Returns the chunk ranges `[start, end)` of a file that are stored, according to the
+    /// segment coverage of the file sync, so that no data is read from the store. Nothing is
+    /// returned if the file is not being synced.
+    async fn available_chunk_ranges(&self, tx_seq: u64) -> Vec<(u64, u64)> {
+        let coverage = match self
+            .sync_send
+            .request(SyncRequest::SegmentCoverage { tx_seq })
+            .await
+        {
+            Ok(SyncResponse::SegmentCoverage {
+                coverage: Some(coverage),
+            }) => coverage,
+            _ => return vec![],
+        };
+
+        let mut ranges = coverage.stored_chunk_ranges;
+        ranges.truncate(MAX_PARTIAL_FILE_RANGES);
+        ranges
+    }
+
     async fn publish_announcement(&self, announcement: SignedAnnounceFile) {
         if let Some(batch) = self.announcement_batcher.write().await.add(announcement) {
             self.publish(PubsubMessage::AnnounceFile(batch));
         }
     }
 
+    /// Track a finalized local file in the file ranges announcement.
+    pub async fn insert_local_file_range(&self, tx_seq: u64) {
+        self.file_ranges.write().await.insert_local(tx_seq);
+    }
+
+    /// Track all the finalized local files in the file ranges announcement, e.g. the files
+    /// finalized before startup.
+    pub async fn load_local_file_ranges(&self) {
+        let ranges = match self.store.get_finalized_tx_ranges().await {
+            Ok(ranges) => ranges,
+            Err(e) => {
+                error!(%e, "Failed to load the finalized files for file ranges");
+                return;
+            }
+        };
+
+        let num_ranges = ranges.len();
+        self.file_ranges.write().await.reset_local(
+            ranges
+                .into_iter()
+                .map(|(start, end)| TxSeqRange { start, end })
+                .collect(),
+        );
+        debug!(%num_ranges, "Loaded the finalized files for file ranges");
+    }
+
+    /// Withdraw the pruned or reverted local files from the file ranges announcement.
+    pub async fn on_local_files_removed(&self, result: Result<RemovedFiles, RecvError>) {
+        match result {
+            Ok(removed) => self.file_ranges.write().await.remove_local(TxSeqRange {
+                start: removed.start_tx_seq,
+                end: removed.end_tx_seq,
+            }),
+            // Some removals are missed, so reload all the finalized files.
+            Err(RecvError::Lagged(_)) => self.load_local_file_ranges().await,
+            // The store outlives the router.
+            Err(RecvError::Closed) => {}
+        }
+    }
+
+    /// Tell the sync layer the connected peers that have the file of `tx_id` according to
+    /// their file ranges announcements, so that they are requested for the file at once
+    /// instead of after answering the `FindFile` query.
+    pub async fn find_file_in_ranges(&self, tx_id: TxID) {
+        let peers = self.file_ranges.read().await.peers_with_file(tx_id.seq);
+        for (peer_id, shard_config) in peers {
+            if !self.network_globals.peers.read().is_connected(&peer_id) {
+                continue;
+            }
+            self.send_to_sync(SyncMessage::AnswerFile {
+                peer_id,
+                file: ShardedFile {
+                    tx_id,
+                    shard_config,
+                },
+            });
+            metrics::LIBP2P_FIND_FILE_IN_RANGES.mark(1);
+        }
+    }
+
+    /// Publish the changes of local file ranges since the last announcement.
+    pub async fn publish_file_ranges(&self) {
+        let delta = match self.file_ranges.write().await.next_delta() {
+            Some(delta) => delta,
+            None => return,
+        };
+
+        let saved_bytes = delta.full_size.saturating_sub(delta.encoded_size());
+        let timestamp = timestamp_now();
+        let msg = TimedMessage {
+            inner: AnnounceFileRanges {
+                peer_id: (*self.network_globals.peer_id.read()).into(),
+                shard_config: self.store.get_store().get_shard_config().into(),
+                base_version: delta.base_version,
+                version: delta.version,
+                added: delta.added,
+                removed: delta.removed,
+                digest: delta.digest,
+            },
+            timestamp,
+        };
+
+        let mut signed = match SignedMessage::sign_message(msg, &self.local_keypair) {
+            Ok(signed) => signed,
+            Err(e) => {
+                error!(%e, "Failed to sign AnnounceFileRanges message");
+                return;
+            }
+        };
+        signed.resend_timestamp = timestamp;
+
+        self.publish(PubsubMessage::AnnounceFileRanges(signed));
+
+        metrics::LIBP2P_PUBLISH_FILE_RANGES.mark(1);
+        metrics::LIBP2P_PUBLISH_FILE_RANGES_BYTES_SAVED.inc(saved_bytes);
+    }
+
     /// Publish expired file announcements.
     pub async fn expire_batcher(&self) {
         if let Some(batch) = self.file_batcher.write().await.expire() {
@@ -1000,10 +1256,16 @@ mod tests {
     };
     use shared_types::{timestamp_now, ChunkArray, ChunkArrayWithProof, FlowRangeProof, TxID};
     use storage::{
-        log_store::{log_manager::LogConfig, Store},
+        log_store::{
+            log_manager::{LogConfig, PORA_CHUNK_SIZE},
+            Store,
+        },
         LogManager,
     };
-    use sync::{test_util::create_2_store, SyncMessage, SyncReceiver, SyncSender};
+    use sync::{
+        test_util::create_2_store, SegmentCoverage, SyncMessage, SyncReceiver, SyncRequest,
+        SyncResponse, SyncSender,
+    };
     use task_executor::test_utils::TestRuntime;
     use tokio::sync::{
         mpsc::{self, error::TryRecvError},
@@ -1057,8 +1319,12 @@ mod tests {
 
     impl Context {
         fn new_handler(&self) -> Libp2pEventHandler {
+            self.new_handler_with_config(Config::default())
+        }
+
+        fn new_handler_with_config(&self, config: Config) -> Libp2pEventHandler {
             Libp2pEventHandler::new(
-                Config::default().with_private_ip_enabled(true),
+                config.with_private_ip_enabled(true),
                 self.network_globals.clone(),
                 self.network_send.clone(),
                 self.sync_send.clone(),
@@ -1376,6 +1642,90 @@ mod tests {
         ctx.assert_file_announcement_published(txs[0].id());
     }
 
+    #[tokio::test]
+    async fn test_on_pubsub_find_partial_file() {
+        let mut ctx = Context::default();
+
+        // prepare store with a file of 3 segments, of which the sync has stored the first one
+        let (store, _, txs, _) = create_2_store(vec![2 * PORA_CHUNK_SIZE + 100]);
+        ctx.store = store;
+        let stored_chunk_ranges = vec![(0, PORA_CHUNK_SIZE as u64)];
+
+        // only finalized files are advertised by default
+        let handler = ctx.new_handler();
+        let result = handle_find_file_msg(&handler, txs[0].id(), timestamp_now()).await;
+        assert!(matches!(result, MessageAcceptance::Accept));
+        assert!(matches!(
+            ctx.network_recv.try_recv(),
+            Err(TryRecvError::Empty)
+        ));
+        assert_eq!(ctx.network_globals.advertisement().partial_files, 0);
+
+        // advertise the available chunks, and still forward the query for the whole file
+        let config = Config {
+            advertise_partial_files: true,
+            ..Default::default()
+        };
+        let handler = ctx.new_handler_with_config(config);
+        let (result, _) = tokio::join!(
+            handle_find_file_msg(&handler, txs[0].id(), timestamp_now()),
+            respond_segment_coverage(&mut ctx.sync_recv, stored_chunk_ranges.clone()),
+        );
+        assert!(matches!(result, MessageAcceptance::Accept));
+        match ctx.network_recv.try_recv() {
+            Ok(NetworkMessage::Publish { messages }) => {
+                assert_eq!(messages.len(), 1);
+                assert!(matches!(
+                    &messages[0],
+                    PubsubMessage::AnnounceChunks(msg) if msg.tx_id == txs[0].id()
+                        && msg.index_start == 0
+                        && msg.index_end == PORA_CHUNK_SIZE as u64
+                ));
+            }
+            Ok(_) => panic!("Unexpected network message type received"),
+            Err(e) => panic!("No network message received: {:?}", e),
+        }
+
+        let advertisement = ctx.network_globals.advertisement();
+        assert!(advertisement.partial_files_enabled);
+        assert_eq!(advertisement.partial_files, 1);
+        assert_eq!(
+            advertisement.recent_partial_files[0].chunk_ranges,
+            stored_chunk_ranges
+        );
+
+        // the same file is not advertised again within the interval
+        let result = handle_find_file_msg(&handler, txs[0].id(), timestamp_now()).await;
+        assert!(matches!(result, MessageAcceptance::Accept));
+        assert!(matches!(ctx.sync_recv.try_recv(), Err(TryRecvError::Empty)));
+        assert!(matches!(
+            ctx.network_recv.try_recv(),
+            Err(TryRecvError::Empty)
+        ));
+        assert_eq!(ctx.network_globals.advertisement().partial_files, 1);
+    }
+
+    async fn respond_segment_coverage(
+        sync_recv: &mut SyncReceiver,
+        stored_chunk_ranges: Vec<(u64, u64)>,
+    ) {
+        match sync_recv.recv().await {
+            Some(channel::Message::Request(SyncRequest::SegmentCoverage { .. }, sender)) => {
+                let coverage = SegmentCoverage {
+                    stored_chunk_ranges,
+                    ..Default::default()
+                };
+                sender
+                    .send(SyncResponse::SegmentCoverage {
+                        coverage: Some(coverage),
+                    })
+                    .unwrap();
+            }
+            Some(_) => panic!("Unexpected sync message type received"),
+            None => panic!("No sync message received"),
+        }
+    }
+
     #[tokio::test]
     async fn test_on_pubsub_find_file_in_cache() {
         let mut ctx = Context::default();
@@ -1462,4 +1812,51 @@ mod tests {
         // ensure cache updated
         assert_eq!(ctx.file_location_cache.get_all(tx).len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_local_file_ranges() {
+        let mut ctx = Context::default();
+        let handler = ctx.new_handler();
+
+        // no finalized files in store
+        handler.load_local_file_ranges().await;
+        let delta = handler.file_ranges.write().await.next_delta().unwrap();
+        assert!(delta.added.is_empty());
+
+        for tx_seq in 0..3 {
+            handler.insert_local_file_range(tx_seq).await;
+        }
+        handler
+            .on_local_files_removed(Ok(RemovedFiles {
+                start_tx_seq: 1,
+                end_tx_seq: 2,
+            }))
+            .await;
+        let delta = handler.file_ranges.write().await.next_delta().unwrap();
+        assert_eq!(
+            delta.added,
+            vec![
+                TxSeqRange { start: 0, end: 1 },
+                TxSeqRange { start: 2, end: 3 }
+            ]
+        );
+
+        // reloaded from store if any removal missed
+        handler
+            .on_local_files_removed(Err(RecvError::Lagged(1)))
+            .await;
+        let delta = handler.file_ranges.write().await.next_delta().unwrap();
+        assert!(delta.added.is_empty());
+        assert_eq!(
+            delta.removed,
+            vec![
+                TxSeqRange { start: 0, end: 1 },
+                TxSeqRange { start: 2, end: 3 }
+            ]
+        );
+
+        // no peer announced the file
+        handler.find_file_in_ranges(TxID::random_hash(0)).await;
+        assert!(matches!(ctx.sync_recv.try_recv(), Err(TryRecvError::Empty)));
+    }
 }
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use metrics::{register_meter, register_meter_with_group, Histogram, Meter, Sample};
+use metrics::{
+    register_meter, register_meter_with_group, Counter, CounterUsize, Histogram, Meter, Sample,
+};
 
 pub struct PubsubMsgHandleMetrics {
     pub(crate) topic_name: &'static str,
@@ -85,6 +87,15 @@ lazy_static::lazy_static! {
     pub static ref LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_ANNOUNCEMENTS: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_pubsub_announce_file", "announcements");
     pub static ref LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_FILES: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_pubsub_announce_file", "files");
 
+    // libp2p_event_handler: file ranges
+    pub static ref LIBP2P_PUBLISH_FILE_RANGES: Arc<dyn Meter> = register_meter("router_libp2p_publish_file_ranges");
+    pub static ref LIBP2P_PUBLISH_FILE_RANGES_BYTES_SAVED: Arc<dyn Counter<usize>> = CounterUsize::register("router_libp2p_publish_file_ranges_bytes_saved");
+    pub static ref LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_RANGES: PubsubMsgHandleMetrics = PubsubMsgHandleMetrics::new("announce_file_ranges");
+    pub static ref LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_RANGES_STALE: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_pubsub_announce_file_ranges", "stale");
+    pub static ref LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_RANGES_GAP: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_pubsub_announce_file_ranges", "gap");
+    pub static ref LIBP2P_HANDLE_PUBSUB_ANNOUNCE_FILE_RANGES_MISMATCH: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_pubsub_announce_file_ranges", "mismatch");
+    pub static ref LIBP2P_FIND_FILE_IN_RANGES: Arc<dyn Meter> = register_meter("router_libp2p_find_file_in_ranges");
+
     // libp2p_event_handler: verify IP address
     pub static ref LIBP2P_VERIFY_ANNOUNCED_IP: Arc<dyn Meter> = register_meter("router_libp2p_verify_announced_ip");
     pub static ref LIBP2P_VERIFY_ANNOUNCED_IP_UNSEEN: Arc<dyn Meter> = register_meter("router_libp2p_verify_announced_ip_unseen");
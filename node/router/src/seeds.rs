@@ -0,0 +1,295 @@
+//! Dials the seed nodes, i.e. the configured `libp2p_nodes`, which keeps a node connected when
+//! it relies on a static seed list rather than the discovery, e.g. in private deployments.
+//!
+//! The seeds are resolved and dialed periodically, and whenever the connected peers drop below
+//! a threshold. The DNS names of the seeds are resolved again on every dial, so that a seed
+//! changing its IP is still reachable, and failed resolutions are retried with backoff.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use network::multiaddr::Protocol;
+use network::types::SeedStatus;
+use network::{Multiaddr, PeerId};
+
+use crate::Config;
+
+/// Timeout to resolve the DNS name of a seed.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A seed with the addresses it's resolved to, or the resolution error.
+pub(crate) type SeedResolution = (Multiaddr, Result<Vec<Multiaddr>, String>);
+
+struct Seed {
+    address: Multiaddr,
+    /// Addresses of the last successful resolution.
+    resolved: Vec<Multiaddr>,
+    resolving: bool,
+    /// The earliest time to dial the seed when the connected peers are below the threshold.
+    next_fallback_at: Instant,
+    /// The time to dial the seed regardless of the connected peers.
+    next_refresh_at: Instant,
+    failures: u32,
+    last_error: Option<String>,
+    last_resolved_at: Option<u64>,
+}
+
+pub(crate) struct SeedDialer {
+    seeds: Vec<Seed>,
+    /// Dial the seeds whenever the connected peers drop below it.
+    min_peers: usize,
+    refresh_interval: Duration,
+    retry_backoff: Duration,
+}
+
+impl SeedDialer {
+    pub fn new(config: &Config, now: Instant) -> Self {
+        let mut seeds: Vec<Seed> = vec![];
+        for address in &config.libp2p_nodes {
+            if seeds.iter().any(|seed| seed.address == *address) {
+                continue;
+            }
+            seeds.push(Seed {
+                address: address.clone(),
+                resolved: vec![],
+                resolving: false,
+                next_fallback_at: now,
+                next_refresh_at: now,
+                failures: 0,
+                last_error: None,
+                last_resolved_at: None,
+            });
+        }
+
+        Self {
+            seeds,
+            min_peers: config.seed_min_peers,
+            refresh_interval: config.seed_refresh_interval,
+            retry_backoff: config.seed_retry_backoff,
+        }
+    }
+
+    /// Returns the seeds to resolve and dial now, which are marked as resolving until
+    /// `on_resolved` is called.
+    pub fn due(&mut self, now: Instant, connected_peers: usize) -> Vec<Multiaddr> {
+        let below_threshold = connected_peers < self.min_peers;
+        self.seeds
+            .iter_mut()
+            .filter(|seed| {
+                !seed.resolving
+                    && (now >= seed.next_refresh_at
+                        || (below_threshold && now >= seed.next_fallback_at))
+            })
+            .map(|seed| {
+                seed.resolving = true;
+                seed.address.clone()
+            })
+            .collect()
+    }
+
+    /// Records the resolution of `address`, and returns the addresses to dial. If the
+    /// resolution failed, the addresses of the last successful resolution are still dialed,
+    /// which may have not changed.
+    pub fn on_resolved(
+        &mut self,
+        address: &Multiaddr,
+        result: Result<Vec<Multiaddr>, String>,
+        now: Instant,
+    ) -> Vec<Multiaddr> {
+        let Some(seed) = self.seeds.iter_mut().find(|seed| seed.address == *address) else {
+            return vec![];
+        };
+        seed.resolving = false;
+
+        match result {
+            Ok(resolved) => {
+                seed.resolved = resolved;
+                seed.failures = 0;
+                seed.last_error = None;
+                seed.last_resolved_at = Some(now_secs());
+                seed.next_fallback_at = now + self.retry_backoff;
+                seed.next_refresh_at = now + self.refresh_interval;
+            }
+            Err(e) => {
+                seed.failures += 1;
+                let backoff = self
+                    .retry_backoff
+                    .saturating_mul(1 << (seed.failures - 1).min(16))
+                    .min(self.refresh_interval);
+                warn!(%address, error = %e, failures = seed.failures, ?backoff, "Failed to resolve seed node");
+                seed.last_error = Some(e);
+                seed.next_fallback_at = now + backoff;
+                seed.next_refresh_at = now + backoff;
+            }
+        }
+
+        seed.resolved.clone()
+    }
+
+    pub fn status(&self) -> Vec<SeedStatus> {
+        self.seeds
+            .iter()
+            .map(|seed| SeedStatus {
+                address: seed.address.to_string(),
+                resolved_addresses: seed.resolved.iter().map(|addr| addr.to_string()).collect(),
+                last_resolved_at: seed.last_resolved_at,
+                last_error: seed.last_error.clone(),
+                consecutive_failures: seed.failures,
+            })
+            .collect()
+    }
+}
+
+/// Resolves the DNS name of `address` into the addresses to dial, or returns `address` as is
+/// if it has no DNS name.
+pub(crate) async fn resolve(address: &Multiaddr) -> Result<Vec<Multiaddr>, String> {
+    let (host, ipv4) = match address.iter().next() {
+        Some(Protocol::Dns(host)) => (host, None),
+        Some(Protocol::Dns4(host)) => (host, Some(true)),
+        Some(Protocol::Dns6(host)) => (host, Some(false)),
+        _ => return Ok(vec![address.clone()]),
+    };
+    let port = address
+        .iter()
+        .find_map(|protocol| match protocol {
+            Protocol::Tcp(port) => Some(port),
+            _ => None,
+        })
+        .ok_or_else(|| "no tcp port".to_string())?;
+
+    let socket_addrs = tokio::time::timeout(
+        RESOLVE_TIMEOUT,
+        tokio::net::lookup_host((host.as_ref(), port)),
+    )
+    .await
+    .map_err(|_| "timeout".to_string())?
+    .map_err(|e| e.to_string())?;
+
+    let mut resolved: Vec<Multiaddr> = vec![];
+    for socket_addr in socket_addrs {
+        if ipv4.is_some_and(|ipv4| ipv4 != socket_addr.is_ipv4()) {
+            continue;
+        }
+        let mut addr = Multiaddr::from(socket_addr.ip());
+        for protocol in address.iter().skip(1) {
+            addr.push(protocol);
+        }
+        if !resolved.contains(&addr) {
+            resolved.push(addr);
+        }
+    }
+
+    if resolved.is_empty() {
+        Err("no address resolved".to_string())
+    } else {
+        Ok(resolved)
+    }
+}
+
+/// Returns the peer id in `address` if any.
+pub(crate) fn peer_id(address: &Multiaddr) -> Option<PeerId> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::P2p(multihash) => PeerId::from_multihash(multihash).ok(),
+        _ => None,
+    })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, SeedDialer};
+    use crate::Config;
+    use network::Multiaddr;
+    use std::time::{Duration, Instant};
+
+    fn config() -> Config {
+        Config {
+            libp2p_nodes: vec![
+                "/ip4/10.0.0.1/tcp/1234".parse().unwrap(),
+                "/dns4/seed.example.com/tcp/1234".parse().unwrap(),
+            ],
+            seed_min_peers: 2,
+            seed_refresh_interval: Duration::from_secs(300),
+            seed_retry_backoff: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_seed_dialer_redials_below_peer_threshold() {
+        let config = config();
+        let now = Instant::now();
+        let mut dialer = SeedDialer::new(&config, now);
+
+        // all seeds are dialed at startup
+        let due = dialer.due(now, 0);
+        assert_eq!(due, config.libp2p_nodes);
+        // not again while resolving
+        assert!(dialer.due(now, 0).is_empty());
+        for seed in &due {
+            let dial = dialer.on_resolved(seed, Ok(vec![seed.clone()]), now);
+            assert_eq!(dial, vec![seed.clone()]);
+        }
+
+        // enough peers connected
+        let later = now + Duration::from_secs(60);
+        assert!(dialer.due(later, 2).is_empty());
+
+        // dropped below the threshold
+        assert_eq!(dialer.due(later, 1), config.libp2p_nodes);
+    }
+
+    #[test]
+    fn test_seed_dialer_backoff_on_failure() {
+        let config = config();
+        let now = Instant::now();
+        let mut dialer = SeedDialer::new(&config, now);
+        let seed = config.libp2p_nodes[1].clone();
+        let ip: Multiaddr = "/ip4/10.0.0.2/tcp/1234".parse().unwrap();
+
+        dialer.due(now, 0);
+        dialer.on_resolved(&seed, Ok(vec![ip.clone()]), now);
+        // periodic refresh regardless of the peers
+        let refresh_at = now + config.seed_refresh_interval;
+        assert!(dialer
+            .due(refresh_at - Duration::from_secs(1), 10)
+            .is_empty());
+        assert_eq!(dialer.due(refresh_at, 10), vec![seed.clone()]);
+
+        // the last resolved addresses are still dialed on failure
+        let dial = dialer.on_resolved(&seed, Err("timeout".into()), refresh_at);
+        assert_eq!(dial, vec![ip]);
+        assert!(!dialer
+            .due(refresh_at + Duration::from_secs(9), 10)
+            .contains(&seed));
+        assert!(dialer
+            .due(refresh_at + Duration::from_secs(10), 10)
+            .contains(&seed));
+        dialer.on_resolved(&seed, Err("timeout".into()), refresh_at);
+        assert!(!dialer
+            .due(refresh_at + Duration::from_secs(19), 10)
+            .contains(&seed));
+        assert!(dialer
+            .due(refresh_at + Duration::from_secs(20), 10)
+            .contains(&seed));
+
+        let status = &dialer.status()[1];
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.last_error.as_deref(), Some("timeout"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_seed() {
+        let ip: Multiaddr = "/ip4/10.0.0.1/tcp/1234".parse().unwrap();
+        assert_eq!(resolve(&ip).await.unwrap(), vec![ip]);
+
+        let dns: Multiaddr = "/dns4/localhost/tcp/1234".parse().unwrap();
+        let expected: Multiaddr = "/ip4/127.0.0.1/tcp/1234".parse().unwrap();
+        assert_eq!(resolve(&dns).await.unwrap(), vec![expected]);
+    }
+}
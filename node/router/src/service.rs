@@ -1,26 +1,29 @@
+use crate::health_log::HealthLog;
 use crate::metrics;
 use crate::rate_limit::PubsubRateLimiter;
+use crate::seeds::{self, SeedDialer, SeedResolution};
 use crate::Config;
 use crate::{libp2p_event_handler::Libp2pEventHandler, peer_manager::PeerManager};
 use chunk_pool::ChunkPoolMessage;
 use file_location_cache::FileLocationCache;
 use futures::{channel::mpsc::Sender, prelude::*};
 use miner::MinerMessage;
+use network::libp2p::swarm::DialError;
 use network::rpc::GoodbyeReason;
 use network::types::GossipKind;
 use network::{
     BehaviourEvent, Keypair, Libp2pEvent, NetworkGlobals, NetworkMessage, NetworkReceiver,
     NetworkSender, PubsubMessage, RequestId, Service as LibP2PService, Swarm,
 };
-use network::{MessageAcceptance, PeerAction, PeerId, ReportSource};
+use network::{MessageAcceptance, Multiaddr, PeerAction, PeerId, ReportSource};
 use pruner::PrunerMessage;
 use shared_types::ShardedFile;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use storage::log_store::Store as LogStore;
 use storage_async::Store;
 use sync::{SyncMessage, SyncSender};
-use task_executor::ShutdownReason;
+use task_executor::{ShutdownReason, TaskExecutor};
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{broadcast, mpsc, RwLock};
 use tokio::time::interval;
@@ -54,6 +57,13 @@ pub struct RouterService {
     store: Arc<dyn LogStore>,
 
     pubsub_rate_limiter: PubsubRateLimiter,
+
+    executor: TaskExecutor,
+
+    /// Dials the seed nodes, of which the resolutions are sent back to the main loop.
+    seeds: SeedDialer,
+    seed_resolved_send: mpsc::UnboundedSender<SeedResolution>,
+    seed_resolved_recv: mpsc::UnboundedReceiver<SeedResolution>,
 }
 
 impl RouterService {
@@ -65,7 +75,7 @@ impl RouterService {
         network_recv: NetworkReceiver,
         network_send: NetworkSender,
         sync_send: SyncSender,
-        _miner_send: Option<broadcast::Sender<MinerMessage>>,
+        miner_send: Option<broadcast::Sender<MinerMessage>>,
         chunk_pool_send: UnboundedSender<ChunkPoolMessage>,
         pruner_recv: Option<mpsc::UnboundedReceiver<PrunerMessage>>,
         store: Arc<dyn LogStore>,
@@ -83,7 +93,23 @@ impl RouterService {
             .limit_by_topic(GossipKind::AnnounceFile, 10, Duration::from_secs(10))?
             .limit_by_topic(GossipKind::FindChunks, 10, Duration::from_secs(10))?
             .limit_by_topic(GossipKind::AnnounceChunks, 10, Duration::from_secs(10))?
-            .limit_by_topic(GossipKind::AnnounceShardConfig, 50, Duration::from_secs(10))?;
+            .limit_by_topic(GossipKind::AnnounceShardConfig, 50, Duration::from_secs(10))?
+            .limit_by_topic(GossipKind::AnnounceFileRanges, 10, Duration::from_secs(10))?;
+
+        if config.health_log_enabled {
+            HealthLog::new(
+                network_globals.clone(),
+                file_location_cache.clone(),
+                store.clone(),
+                sync_send.clone(),
+                miner_send.is_some(),
+            )
+            .spawn(&executor, config.health_log_interval);
+        }
+
+        let seeds = SeedDialer::new(&config, Instant::now());
+        *network_globals.seed_status.write() = seeds.status();
+        let (seed_resolved_send, seed_resolved_recv) = mpsc::unbounded_channel();
 
         // create the network service and spawn the task
         let router = RouterService {
@@ -107,6 +133,10 @@ impl RouterService {
             upnp_mappings: (None, None),
             store,
             pubsub_rate_limiter,
+            executor: executor.clone(),
+            seeds,
+            seed_resolved_send,
+            seed_resolved_recv,
         };
 
         // spawn service
@@ -121,6 +151,13 @@ impl RouterService {
         let mut heartbeat_service = interval(self.config.heartbeat_interval);
         let mut heartbeat_batcher = interval(self.config.batcher_timeout);
         let mut heartbeat_rate_limiter = interval(Duration::from_secs(30));
+        let mut heartbeat_file_ranges = interval(self.config.file_ranges_interval);
+
+        // Subscribe before loading the finalized files, so that no removal is missed.
+        let mut removed_files = self.store.subscribe_removed_files();
+        if self.config.file_ranges_enabled {
+            self.libp2p_event_handler.load_local_file_ranges().await;
+        }
 
         loop {
             tokio::select! {
@@ -132,6 +169,8 @@ impl RouterService {
 
                 Some(msg) = Self::try_recv(&mut self.pruner_recv) => self.on_pruner_msg(msg).await,
 
+                Some((seed, result)) = self.seed_resolved_recv.recv() => self.on_seed_resolved(seed, result),
+
                 // heartbeat for service
                 _ = heartbeat_service.tick() => self.on_heartbeat().await,
 
@@ -139,6 +178,16 @@ impl RouterService {
                 _ = heartbeat_batcher.tick() => self.libp2p_event_handler.expire_batcher().await,
 
                 _ = heartbeat_rate_limiter.tick() => self.pubsub_rate_limiter.prune(),
+
+                // heartbeat for publishing file ranges
+                _ = heartbeat_file_ranges.tick(), if self.config.file_ranges_enabled => {
+                    self.libp2p_event_handler.publish_file_ranges().await
+                }
+
+                // withdraw the pruned or reverted files from the file ranges
+                result = removed_files.recv(), if self.config.file_ranges_enabled => {
+                    self.libp2p_event_handler.on_local_files_removed(result).await
+                }
             }
         }
     }
@@ -324,6 +373,16 @@ impl RouterService {
                     topics = ?topic_kinds,
                     "Sending pubsub messages",
                 );
+                if self.config.file_ranges_enabled {
+                    for message in &messages {
+                        if let PubsubMessage::FindFile(msg) = message {
+                            self.libp2p_event_handler
+                                .find_file_in_ranges(msg.tx_id)
+                                .await;
+                        }
+                    }
+                }
+
                 self.libp2p.swarm.behaviour_mut().publish(messages);
 
                 metrics::SERVICE_ROUTE_NETWORK_MESSAGE_PUBLISH.mark(1);
@@ -348,7 +407,14 @@ impl RouterService {
             NetworkMessage::DialPeer { address, peer_id } => {
                 metrics::SERVICE_ROUTE_NETWORK_MESSAGE_DIAL_PEER.mark(1);
 
-                if self.libp2p.swarm.is_connected(&peer_id) {
+                if !self.network_globals.peer_access.read().is_allowed(&peer_id) {
+                    debug!(%peer_id, "Not dialing peer disallowed by access list");
+                    self.libp2p_event_handler
+                        .send_to_sync(SyncMessage::DialFailed {
+                            peer_id,
+                            err: DialError::Banned,
+                        });
+                } else if self.libp2p.swarm.is_connected(&peer_id) {
                     self.libp2p_event_handler
                         .send_to_sync(SyncMessage::PeerConnected { peer_id });
                     metrics::SERVICE_ROUTE_NETWORK_MESSAGE_DIAL_PEER_ALREADY.mark(1);
@@ -370,7 +436,18 @@ impl RouterService {
             NetworkMessage::DisconnectPeer { peer_id } => {
                 self.disconnect_peer(peer_id);
             }
+            NetworkMessage::ResetPeerState { peer_id } => {
+                self.libp2p.reset_peer_state(&peer_id);
+            }
+            NetworkMessage::RefreshDiscovery => {
+                self.libp2p.swarm.behaviour_mut().discovery_mut().refresh();
+            }
             NetworkMessage::AnnounceLocalFile { tx_id } => {
+                if self.config.file_ranges_enabled {
+                    self.libp2p_event_handler
+                        .insert_local_file_range(tx_id.seq)
+                        .await;
+                }
                 let new_file = ShardedFile {
                     tx_id,
                     shard_config: self.store.get_shard_config().into(),
@@ -438,6 +515,44 @@ impl RouterService {
         for peer_id in expired_peers {
             self.disconnect_peer(peer_id);
         }
+
+        self.resolve_seeds();
+    }
+
+    /// Resolves the seeds due to dial in the background, since the DNS resolution may be slow.
+    fn resolve_seeds(&mut self) {
+        let due = self
+            .seeds
+            .due(Instant::now(), self.network_globals.connected_peers());
+        for seed in due {
+            let sender = self.seed_resolved_send.clone();
+            self.executor.spawn(
+                async move {
+                    let result = seeds::resolve(&seed).await;
+                    let _ = sender.send((seed, result));
+                },
+                "seed_resolve",
+            );
+        }
+    }
+
+    fn on_seed_resolved(&mut self, seed: Multiaddr, result: Result<Vec<Multiaddr>, String>) {
+        let addresses = self.seeds.on_resolved(&seed, result, Instant::now());
+        *self.network_globals.seed_status.write() = self.seeds.status();
+
+        for address in addresses {
+            if let Some(peer_id) = seeds::peer_id(&address) {
+                if self.libp2p.swarm.is_connected(&peer_id)
+                    || !self.network_globals.peer_access.read().is_allowed(&peer_id)
+                {
+                    continue;
+                }
+            }
+            match Swarm::dial(&mut self.libp2p.swarm, address.clone()) {
+                Ok(()) => debug!(%seed, %address, "Dialing seed node"),
+                Err(err) => debug!(%seed, %address, error = ?err, "Could not dial seed node"),
+            }
+        }
     }
 
     fn disconnect_peer(&mut self, peer_id: PeerId) {
@@ -455,3 +570,93 @@ impl Drop for RouterService {
         network::nat::remove_mappings(self.upnp_mappings.0, self.upnp_mappings.1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RouterService;
+    use crate::Config;
+    use file_location_cache::FileLocationCache;
+    use network::{new_network_channel, Multiaddr, NetworkConfig, Service as LibP2PService};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use storage::{log_store::log_manager::LogConfig, LogManager};
+    use task_executor::test_utils::TestRuntime;
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_router_redials_libp2p_nodes() {
+        let runtime = TestRuntime::default();
+        let executor = runtime.task_executor.clone();
+
+        // a node that accepts the connections but never completes the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let node: Multiaddr = format!(
+            "/ip4/127.0.0.1/tcp/{}",
+            listener.local_addr().unwrap().port()
+        )
+        .parse()
+        .unwrap();
+
+        let port = unused_port::unused_tcp_port().unwrap();
+        let network_config = NetworkConfig {
+            network_dir: tempfile::tempdir().unwrap().into_path(),
+            libp2p_port: port,
+            discovery_port: port,
+            enr_address: Some("127.0.0.1".parse().unwrap()),
+            disable_discovery: true,
+            upnp_enabled: false,
+            ..Default::default()
+        };
+        let (network_send, network_recv) = new_network_channel();
+        let (network_globals, keypair, libp2p) = LibP2PService::new(
+            executor.clone(),
+            network_send.clone(),
+            network::Context {
+                config: &network_config,
+            },
+        )
+        .await
+        .unwrap();
+        let (sync_send, _sync_recv) = channel::Channel::unbounded("test");
+        let (chunk_pool_send, _chunk_pool_recv) = tokio::sync::mpsc::unbounded_channel();
+
+        RouterService::spawn(
+            executor,
+            libp2p,
+            network_globals.clone(),
+            network_recv,
+            network_send,
+            sync_send,
+            None,
+            chunk_pool_send,
+            None,
+            Arc::new(LogManager::memorydb(LogConfig::default()).unwrap()),
+            Arc::new(FileLocationCache::default()),
+            keypair,
+            Config {
+                heartbeat_interval: Duration::from_millis(100),
+                libp2p_nodes: vec![node.clone()],
+                seed_min_peers: 1,
+                seed_retry_backoff: Duration::from_millis(100),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // dialed at startup, and dialed again after the handshake fails since no peer is
+        // connected
+        for _ in 0..2 {
+            let (stream, _) = timeout(Duration::from_secs(10), listener.accept())
+                .await
+                .expect("libp2p node not dialed")
+                .unwrap();
+            drop(stream);
+        }
+
+        let status = network_globals.seed_status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].address, node.to_string());
+        assert_eq!(status[0].resolved_addresses, vec![node.to_string()]);
+    }
+}
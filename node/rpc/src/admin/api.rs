@@ -1,8 +1,18 @@
-use crate::types::{LocationInfo, NetworkInfo, PeerInfo};
+use crate::types::{LocationInfo, NetworkInfo, PeerInfo, PeerState, RootVerification};
 use jsonrpsee::core::RpcResult;
 use jsonrpsee::proc_macros::rpc;
+use network::discovery::health::DiscoveryHealth;
+use network::peer_manager::access_list::PeerAccessListInfo;
+use shared_types::DataRoot;
 use std::collections::{BTreeMap, HashMap};
-use sync::{FileSyncInfo, SyncServiceState};
+use storage::log_store::file_download::FileDownloadInfo;
+use storage::log_store::gc::GcReport;
+use storage::log_store::replica::{ReplicaComparison, ReplicaDigest};
+use storage::log_store::state_dump::StateDumpInfo;
+use sync::auto_prune::AutoPruneState;
+use sync::integrity_scan::IntegrityScanState;
+use sync::worker_watchdog::WorkerHeartbeat;
+use sync::{DeadLetterFile, FileSyncInfo, SegmentCoverage, SyncServiceState};
 
 #[rpc(server, client, namespace = "admin")]
 pub trait Rpc {
@@ -27,6 +37,14 @@ pub trait Rpc {
     #[method(name = "terminateSync")]
     async fn terminate_sync(&self, tx_seq: u64) -> RpcResult<bool>;
 
+    /// Stop requesting new segments of all the files until resumed, even after restart. The
+    /// requests in flight are completed, and the stored files are still served.
+    #[method(name = "pauseSync")]
+    async fn pause_sync(&self) -> RpcResult<()>;
+
+    #[method(name = "resumeSync")]
+    async fn resume_sync(&self) -> RpcResult<()>;
+
     #[method(name = "getSyncServiceState")]
     async fn get_sync_service_state(&self) -> RpcResult<SyncServiceState>;
 
@@ -36,12 +54,73 @@ pub trait Rpc {
     #[method(name = "getSyncInfo")]
     async fn get_sync_info(&self, tx_seq: Option<u64>) -> RpcResult<HashMap<u64, FileSyncInfo>>;
 
+    /// Peers that cover the segments of a file in sync not downloaded yet, and the segments
+    /// that no known peer holds. Return `None` if the file is not in sync.
+    #[method(name = "getSegmentCoverage")]
+    async fn get_segment_coverage(&self, tx_seq: u64) -> RpcResult<Option<SegmentCoverage>>;
+
+    /// Files that failed to sync too many times and are not synced automatically anymore.
+    /// Only the files with `tag` are returned if specified.
+    #[method(name = "getDeadLetterFiles")]
+    async fn get_dead_letter_files(&self, tag: Option<String>) -> RpcResult<Vec<DeadLetterFile>>;
+
+    /// Move the file out of the dead letter queue to sync it again.
+    /// Return `false` if the file is not in the dead letter queue.
+    #[method(name = "retryDeadLetterFile")]
+    async fn retry_dead_letter_file(&self, tx_seq: u64) -> RpcResult<bool>;
+
+    /// Last heartbeats of the long-lived workers monitored by the watchdog, which is
+    /// available even if the sync service stalls.
+    #[method(name = "getWorkerHeartbeats")]
+    async fn get_worker_heartbeats(&self) -> RpcResult<BTreeMap<String, WorkerHeartbeat>>;
+
     #[method(name = "getNetworkInfo")]
     async fn get_network_info(&self) -> RpcResult<NetworkInfo>;
 
     #[method(name = "getPeers")]
     async fn get_peers(&self) -> RpcResult<HashMap<String, PeerInfo>>;
 
+    /// Score, reported actions by type and ban state of the peer, or `None` if the peer is
+    /// unknown.
+    #[method(name = "getPeerState")]
+    async fn get_peer_state(&self, peer_id: String) -> RpcResult<Option<PeerState>>;
+
+    /// Clear the score and the reported actions of the peer to give it a fresh start, which
+    /// also lifts the ban by score. Return `false` if the peer is unknown.
+    #[method(name = "resetPeerState")]
+    async fn reset_peer_state(&self, peer_id: String) -> RpcResult<bool>;
+
+    /// Occupancy of the discovery routing table buckets, and the success rate of the recent
+    /// discovery queries.
+    #[method(name = "getDiscoveryHealth")]
+    async fn get_discovery_health(&self) -> RpcResult<DiscoveryHealth>;
+
+    /// Start a discovery round immediately instead of waiting for the periodic schedule, e.g.
+    /// when the node cannot find peers. It's allowed once per 30 seconds at most.
+    #[method(name = "refreshDiscovery")]
+    async fn refresh_discovery(&self) -> RpcResult<()>;
+
+    #[method(name = "getPeerAccessList")]
+    async fn get_peer_access_list(&self) -> RpcResult<PeerAccessListInfo>;
+
+    /// Block the peer permanently, which disconnects the peer if connected.
+    /// Return `false` if the peer has been blacklisted already.
+    #[method(name = "blacklistPeer")]
+    async fn blacklist_peer(&self, peer_id: String) -> RpcResult<bool>;
+
+    /// Return `false` if the peer is not blacklisted.
+    #[method(name = "unblacklistPeer")]
+    async fn unblacklist_peer(&self, peer_id: String) -> RpcResult<bool>;
+
+    /// Allow the peer in whitelist mode.
+    /// Return `false` if the peer has been whitelisted already.
+    #[method(name = "whitelistPeer")]
+    async fn whitelist_peer(&self, peer_id: String) -> RpcResult<bool>;
+
+    /// Return `false` if the peer is not whitelisted.
+    #[method(name = "unwhitelistPeer")]
+    async fn unwhitelist_peer(&self, peer_id: String) -> RpcResult<bool>;
+
     #[method(name = "getFileLocation")]
     async fn get_file_location(
         &self,
@@ -54,4 +133,92 @@ pub trait Rpc {
         &self,
         maybe_prefix: Option<String>,
     ) -> RpcResult<BTreeMap<String, String>>;
+
+    /// Pin the file of specified tx_seq so that it is never pruned.
+    /// Return `false` if the file has been pinned already.
+    #[method(name = "pinFile")]
+    async fn pin_file(&self, tx_seq: u64) -> RpcResult<bool>;
+
+    /// Return `false` if the file is not pinned.
+    #[method(name = "unpinFile")]
+    async fn unpin_file(&self, tx_seq: u64) -> RpcResult<bool>;
+
+    /// Only the files with `tag` are returned if specified.
+    #[method(name = "getPinnedFiles")]
+    async fn get_pinned_files(&self, tag: Option<String>) -> RpcResult<Vec<u64>>;
+
+    /// Replace the tags of the file of specified tx_seq, or remove them if `tags` is empty.
+    /// A file has 16 tags at most, each of 1 to 64 bytes.
+    #[method(name = "tagFile")]
+    async fn tag_file(&self, tx_seq: u64, tags: Vec<String>) -> RpcResult<()>;
+
+    #[method(name = "getFileTags")]
+    async fn get_file_tags(&self, tx_seq: u64) -> RpcResult<Vec<String>>;
+
+    /// Return the sorted tx seqs of the files with `tag`.
+    #[method(name = "getTaggedFiles")]
+    async fn get_tagged_files(&self, tag: String) -> RpcResult<Vec<u64>>;
+
+    /// Prune the finalized files with `tag` that are not pinned, and return the pruned tx
+    /// seqs. The data of a pruned file is no longer served.
+    #[method(name = "pruneFiles")]
+    async fn prune_files(&self, tag: String) -> RpcResult<Vec<u64>>;
+
+    /// State of the policy to prune the oldest files once the storage exceeds the cap, and
+    /// the last auto pruning action.
+    #[method(name = "getAutoPruneState")]
+    async fn get_auto_prune_state(&self) -> RpcResult<AutoPruneState>;
+
+    /// Progress and result of the integrity scan of the finalized files at startup.
+    #[method(name = "getIntegrityScanState")]
+    async fn get_integrity_scan_state(&self) -> RpcResult<IntegrityScanState>;
+
+    /// Export a consistent dump of the node state to `path` on the node machine, which could
+    /// be imported by the `import-state` command into the empty db of another node.
+    #[method(name = "exportState")]
+    async fn export_state(&self, path: String) -> RpcResult<StateDumpInfo>;
+
+    /// Write the data of a stored file to `path` on the node machine after verifying it
+    /// against the tx root. Fail with the missing segments if the file is not complete.
+    #[method(name = "downloadFile")]
+    async fn download_file(&self, tx_seq: u64, path: String) -> RpcResult<FileDownloadInfo>;
+
+    /// Recompute the root of a finalized file from the stored data and compare it with
+    /// `expected_root`. The file is synced again on mismatch only if `repair` is `true`.
+    #[method(name = "verifyRoot")]
+    async fn verify_root(
+        &self,
+        tx_seq: u64,
+        expected_root: DataRoot,
+        repair: Option<bool>,
+    ) -> RpcResult<RootVerification>;
+
+    /// Reclaim the records of the store that nothing references anymore, e.g. left behind by
+    /// reorgs and prunes, and report the bytes freed. Only count them if `dry_run` is `true`.
+    #[method(name = "gcStore")]
+    async fn gc_store(&self, dry_run: Option<bool>) -> RpcResult<GcReport>;
+
+    /// Build the digest of the stored data of a tx, i.e. the root of the data and the leaves of
+    /// the chunks at `sample_indices`, which is up to 64 chunks. The root is the tx root if the
+    /// tx is finalized, or recomputed from all the stored chunks only if `recompute` is `true`.
+    /// Return `None` if the tx does not exist.
+    #[method(name = "getReplicaDigest")]
+    async fn get_replica_digest(
+        &self,
+        tx_seq: u64,
+        sample_indices: Vec<u64>,
+        recompute: Option<bool>,
+    ) -> RpcResult<Option<ReplicaDigest>>;
+
+    /// Compare the stored data of a tx with the replica of the node of which the admin RPC is
+    /// at `peer_rpc_url`, by the digests of both with `num_samples` random chunks (8 by
+    /// default), so that the file is not transferred. See `getReplicaDigest` for `recompute`.
+    #[method(name = "compareReplica")]
+    async fn compare_replica(
+        &self,
+        tx_seq: u64,
+        peer_rpc_url: String,
+        num_samples: Option<usize>,
+        recompute: Option<bool>,
+    ) -> RpcResult<ReplicaComparison>;
 }
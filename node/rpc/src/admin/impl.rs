@@ -1,17 +1,37 @@
-use super::api::RpcServer;
-use crate::types::{LocationInfo, NetworkInfo, PeerInfo};
+use super::api::{RpcClient, RpcServer};
+use crate::types::{LocationInfo, NetworkInfo, PeerInfo, PeerState, RootVerification};
 use crate::{error, Context};
 use futures::prelude::*;
 use jsonrpsee::core::async_trait;
 use jsonrpsee::core::RpcResult;
+use jsonrpsee::http_client::HttpClientBuilder;
 use metrics::{DEFAULT_GROUPING_REGISTRY, DEFAULT_REGISTRY};
-use network::{multiaddr::Protocol, Multiaddr};
+use network::discovery::{health::DiscoveryHealth, MIN_MANUAL_REFRESH_INTERVAL};
+use network::peer_manager::access_list::PeerAccessListInfo;
+use network::{multiaddr::Protocol, Multiaddr, NetworkMessage, PeerId};
+use shared_types::DataRoot;
 use std::collections::{BTreeMap, HashMap};
 use std::net::IpAddr;
+use std::str::FromStr;
 use storage::config::all_shards_available;
-use sync::{FileSyncInfo, SyncRequest, SyncResponse, SyncServiceState};
+use storage::log_store::file_download::FileDownloadInfo;
+use storage::log_store::gc::GcReport;
+use storage::log_store::replica::{
+    compare_replica_digests, sample_chunk_indices, ReplicaComparison, ReplicaDigest,
+};
+use storage::log_store::state_dump::StateDumpInfo;
+use storage::log_store::tx_store::TxStatus;
+use sync::auto_prune::AutoPruneState;
+use sync::integrity_scan::IntegrityScanState;
+use sync::worker_watchdog::WorkerHeartbeat;
+use sync::{
+    DeadLetterFile, FileSyncInfo, SegmentCoverage, SyncRequest, SyncResponse, SyncServiceState,
+};
 use task_executor::ShutdownReason;
 
+/// Number of the sampled chunks to compare with a replica by default.
+const DEFAULT_REPLICA_SAMPLES: usize = 8;
+
 pub struct RpcServerImpl {
     pub ctx: Context,
 }
@@ -115,6 +135,16 @@ impl RpcServer for RpcServerImpl {
         }
     }
 
+    async fn pause_sync(&self) -> RpcResult<()> {
+        info!("admin_pauseSync()");
+        self.set_sync_paused(true).await
+    }
+
+    async fn resume_sync(&self) -> RpcResult<()> {
+        info!("admin_resumeSync()");
+        self.set_sync_paused(false).await
+    }
+
     async fn get_sync_service_state(&self) -> RpcResult<SyncServiceState> {
         info!("admin_getSyncServiceState()");
 
@@ -135,9 +165,18 @@ impl RpcServer for RpcServerImpl {
             .await?;
 
         match response {
-            SyncResponse::SyncStatus { status } => Ok(status
-                .map(|x| format!("{:?}", x))
-                .unwrap_or_else(|| "unknown".into())),
+            SyncResponse::SyncStatus {
+                status: Some(status),
+            } => Ok(format!("{:?}", status)),
+            SyncResponse::SyncStatus { status: None } => {
+                match self.ctx.log_store.get_store().get_tx_status(tx_seq)? {
+                    Some(TxStatus::Skipped) => {
+                        let reason = self.ctx.log_store.get_skip_reason(tx_seq).await?;
+                        Ok(format!("Skipped: {}", reason.unwrap_or_default()))
+                    }
+                    _ => Ok("unknown".into()),
+                }
+            }
             _ => Err(error::internal_error("unexpected response type")),
         }
     }
@@ -156,6 +195,57 @@ impl RpcServer for RpcServerImpl {
         }
     }
 
+    async fn get_segment_coverage(&self, tx_seq: u64) -> RpcResult<Option<SegmentCoverage>> {
+        info!("admin_getSegmentCoverage({tx_seq})");
+
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::SegmentCoverage { tx_seq })
+            .await?;
+
+        match response {
+            SyncResponse::SegmentCoverage { coverage } => Ok(coverage),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+
+    async fn get_dead_letter_files(&self, tag: Option<String>) -> RpcResult<Vec<DeadLetterFile>> {
+        info!("admin_getDeadLetterFiles({:?})", tag);
+
+        let response = self.ctx.request_sync(SyncRequest::DeadLetterFiles).await?;
+
+        let mut result = match response {
+            SyncResponse::DeadLetterFiles { result } => result,
+            _ => return Err(error::internal_error("unexpected response type")),
+        };
+        if let Some(tag) = tag {
+            let tagged = self.ctx.log_store.get_tagged_txs(&tag).await?;
+            result.retain(|file| tagged.binary_search(&file.tx_seq).is_ok());
+        }
+
+        Ok(result)
+    }
+
+    async fn retry_dead_letter_file(&self, tx_seq: u64) -> RpcResult<bool> {
+        info!("admin_retryDeadLetterFile({tx_seq})");
+
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::RetryDeadLetterFile { tx_seq })
+            .await?;
+
+        match response {
+            SyncResponse::RetryDeadLetterFile { retried } => Ok(retried),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+
+    async fn get_worker_heartbeats(&self) -> RpcResult<BTreeMap<String, WorkerHeartbeat>> {
+        info!("admin_getWorkerHeartbeats()");
+
+        Ok(self.ctx.worker_watchdog.heartbeats())
+    }
+
     async fn get_network_info(&self) -> RpcResult<NetworkInfo> {
         info!("admin_getNetworkInfo()");
 
@@ -189,6 +279,123 @@ impl RpcServer for RpcServerImpl {
             .collect())
     }
 
+    async fn get_peer_state(&self, peer_id: String) -> RpcResult<Option<PeerState>> {
+        info!("admin_getPeerState({peer_id})");
+
+        let peer_id = parse_peer_id(&peer_id)?;
+        Ok(self
+            .ctx
+            .network_globals
+            .peers
+            .read()
+            .peer_info(&peer_id)
+            .map(PeerState::from))
+    }
+
+    async fn reset_peer_state(&self, peer_id: String) -> RpcResult<bool> {
+        info!("admin_resetPeerState({peer_id})");
+
+        let peer_id = parse_peer_id(&peer_id)?;
+        let state = match self.ctx.network_globals.peers.read().peer_info(&peer_id) {
+            Some(info) => PeerState::from(info),
+            None => return Ok(false),
+        };
+
+        info!(
+            %peer_id,
+            score = %state.score,
+            banned = %state.banned,
+            fault_counts = ?state.fault_counts,
+            "Reset peer state"
+        );
+        self.ctx
+            .send_network(NetworkMessage::ResetPeerState { peer_id })?;
+
+        Ok(true)
+    }
+
+    async fn get_discovery_health(&self) -> RpcResult<DiscoveryHealth> {
+        info!("admin_getDiscoveryHealth()");
+
+        Ok(self.ctx.network_globals.discovery_health())
+    }
+
+    async fn refresh_discovery(&self) -> RpcResult<()> {
+        info!("admin_refreshDiscovery()");
+
+        if !self
+            .ctx
+            .network_globals
+            .try_refresh_discovery(MIN_MANUAL_REFRESH_INTERVAL)
+        {
+            return Err(error::rate_limited());
+        }
+
+        self.ctx.send_network(NetworkMessage::RefreshDiscovery)
+    }
+
+    async fn get_peer_access_list(&self) -> RpcResult<PeerAccessListInfo> {
+        info!("admin_getPeerAccessList()");
+
+        Ok(self.ctx.network_globals.peer_access.read().info())
+    }
+
+    async fn blacklist_peer(&self, peer_id: String) -> RpcResult<bool> {
+        info!("admin_blacklistPeer({peer_id})");
+
+        let peer_id = parse_peer_id(&peer_id)?;
+        let added = self
+            .ctx
+            .network_globals
+            .peer_access
+            .write()
+            .blacklist(peer_id)
+            .map_err(|e| error::internal_error(format!("Failed to persist blacklist: {:?}", e)))?;
+
+        if self.ctx.network_globals.peers.read().is_connected(&peer_id) {
+            self.ctx
+                .send_network(NetworkMessage::DisconnectPeer { peer_id })?;
+        }
+
+        Ok(added)
+    }
+
+    async fn unblacklist_peer(&self, peer_id: String) -> RpcResult<bool> {
+        info!("admin_unblacklistPeer({peer_id})");
+
+        let peer_id = parse_peer_id(&peer_id)?;
+        self.ctx
+            .network_globals
+            .peer_access
+            .write()
+            .unblacklist(&peer_id)
+            .map_err(|e| error::internal_error(format!("Failed to persist blacklist: {:?}", e)))
+    }
+
+    async fn whitelist_peer(&self, peer_id: String) -> RpcResult<bool> {
+        info!("admin_whitelistPeer({peer_id})");
+
+        let peer_id = parse_peer_id(&peer_id)?;
+        self.ctx
+            .network_globals
+            .peer_access
+            .write()
+            .whitelist(peer_id)
+            .map_err(|e| error::internal_error(format!("Failed to persist whitelist: {:?}", e)))
+    }
+
+    async fn unwhitelist_peer(&self, peer_id: String) -> RpcResult<bool> {
+        info!("admin_unwhitelistPeer({peer_id})");
+
+        let peer_id = parse_peer_id(&peer_id)?;
+        self.ctx
+            .network_globals
+            .peer_access
+            .write()
+            .unwhitelist(&peer_id)
+            .map_err(|e| error::internal_error(format!("Failed to persist whitelist: {:?}", e)))
+    }
+
     async fn get_file_location(
         &self,
         tx_seq: u64,
@@ -275,4 +482,222 @@ impl RpcServer for RpcServerImpl {
 
         Ok(result)
     }
+
+    async fn pin_file(&self, tx_seq: u64) -> RpcResult<bool> {
+        info!("admin_pinFile({tx_seq})");
+
+        Ok(self.ctx.log_store.pin_tx(tx_seq).await?)
+    }
+
+    async fn unpin_file(&self, tx_seq: u64) -> RpcResult<bool> {
+        info!("admin_unpinFile({tx_seq})");
+
+        Ok(self.ctx.log_store.unpin_tx(tx_seq).await?)
+    }
+
+    async fn get_pinned_files(&self, tag: Option<String>) -> RpcResult<Vec<u64>> {
+        let mut pinned = self.ctx.log_store.get_pinned_txs().await?;
+        if let Some(tag) = tag {
+            let tagged = self.ctx.log_store.get_tagged_txs(&tag).await?;
+            pinned.retain(|seq| tagged.binary_search(seq).is_ok());
+        }
+        Ok(pinned)
+    }
+
+    async fn tag_file(&self, tx_seq: u64, tags: Vec<String>) -> RpcResult<()> {
+        info!("admin_tagFile({tx_seq}, {:?})", tags);
+
+        Ok(self.ctx.log_store.set_tx_tags(tx_seq, tags).await?)
+    }
+
+    async fn get_file_tags(&self, tx_seq: u64) -> RpcResult<Vec<String>> {
+        Ok(self.ctx.log_store.get_tx_tags(tx_seq).await?)
+    }
+
+    async fn get_tagged_files(&self, tag: String) -> RpcResult<Vec<u64>> {
+        Ok(self.ctx.log_store.get_tagged_txs(&tag).await?)
+    }
+
+    async fn prune_files(&self, tag: String) -> RpcResult<Vec<u64>> {
+        info!("admin_pruneFiles({tag})");
+
+        let store = self.ctx.log_store.get_store();
+        let mut pruned = vec![];
+        for tx_seq in self.ctx.log_store.get_tagged_txs(&tag).await? {
+            if matches!(store.get_tx_status(tx_seq)?, Some(TxStatus::Finalized))
+                && !store.check_tx_pinned(tx_seq)?
+            {
+                self.ctx.log_store.prune_tx(tx_seq).await?;
+                pruned.push(tx_seq);
+            }
+        }
+        info!(?pruned, %tag, "Pruned the tagged files");
+
+        Ok(pruned)
+    }
+
+    async fn get_auto_prune_state(&self) -> RpcResult<AutoPruneState> {
+        info!("admin_getAutoPruneState()");
+
+        Ok(self.ctx.auto_pruner.state())
+    }
+
+    async fn get_integrity_scan_state(&self) -> RpcResult<IntegrityScanState> {
+        info!("admin_getIntegrityScanState()");
+
+        Ok(self.ctx.integrity_scanner.state())
+    }
+
+    async fn export_state(&self, path: String) -> RpcResult<StateDumpInfo> {
+        info!("admin_exportState({path})");
+
+        Ok(self.ctx.log_store.export_state(path.into()).await?)
+    }
+
+    async fn download_file(&self, tx_seq: u64, path: String) -> RpcResult<FileDownloadInfo> {
+        info!("admin_downloadFile({tx_seq}, {path})");
+
+        Ok(self
+            .ctx
+            .log_store
+            .download_file(tx_seq, path.into())
+            .await?)
+    }
+
+    async fn verify_root(
+        &self,
+        tx_seq: u64,
+        expected_root: DataRoot,
+        repair: Option<bool>,
+    ) -> RpcResult<RootVerification> {
+        info!("admin_verifyRoot({tx_seq}, {expected_root:?}, {repair:?})");
+
+        if !self.ctx.log_store.check_tx_completed(tx_seq).await? {
+            return Err(error::file_not_finalized());
+        }
+
+        let computed_root = match self.ctx.log_store.compute_data_root(tx_seq).await? {
+            Some(root) => root,
+            None => return Err(error::internal_error("File data not fully stored")),
+        };
+
+        let mut verification = RootVerification::new(computed_root, expected_root);
+        if verification.matched {
+            return Ok(verification);
+        }
+
+        warn!(%tx_seq, ?computed_root, ?expected_root, "File root mismatch");
+
+        if repair == Some(true) {
+            let response = self
+                .ctx
+                .request_sync(SyncRequest::RepairFile { tx_seq })
+                .await?;
+
+            match response {
+                SyncResponse::SyncFile { err } if err.is_empty() => verification.repairing = true,
+                SyncResponse::SyncFile { err } => return Err(error::internal_error(err)),
+                _ => return Err(error::internal_error("unexpected response type")),
+            }
+        }
+
+        Ok(verification)
+    }
+
+    async fn gc_store(&self, dry_run: Option<bool>) -> RpcResult<GcReport> {
+        info!("admin_gcStore({dry_run:?})");
+
+        Ok(self
+            .ctx
+            .log_store
+            .gc_store(dry_run.unwrap_or(false))
+            .await?)
+    }
+
+    async fn get_replica_digest(
+        &self,
+        tx_seq: u64,
+        sample_indices: Vec<u64>,
+        recompute: Option<bool>,
+    ) -> RpcResult<Option<ReplicaDigest>> {
+        info!("admin_getReplicaDigest({tx_seq}, {sample_indices:?}, {recompute:?})");
+
+        Ok(self
+            .ctx
+            .log_store
+            .get_replica_digest(tx_seq, sample_indices, recompute.unwrap_or(false))
+            .await?)
+    }
+
+    async fn compare_replica(
+        &self,
+        tx_seq: u64,
+        peer_rpc_url: String,
+        num_samples: Option<usize>,
+        recompute: Option<bool>,
+    ) -> RpcResult<ReplicaComparison> {
+        info!("admin_compareReplica({tx_seq}, {peer_rpc_url}, {num_samples:?}, {recompute:?})");
+
+        let tx = match self.ctx.log_store.get_tx_by_seq_number(tx_seq).await? {
+            Some(tx) => tx,
+            None => return Err(error::invalid_params("tx_seq", "tx not found")),
+        };
+        let indices = sample_chunk_indices(tx.size, num_samples.unwrap_or(DEFAULT_REPLICA_SAMPLES));
+
+        let client = HttpClientBuilder::default()
+            .build(&peer_rpc_url)
+            .map_err(|e| error::invalid_params("peer_rpc_url", e.to_string()))?;
+        let remote = match RpcClient::get_replica_digest(
+            &client,
+            tx_seq,
+            indices.clone(),
+            recompute,
+        )
+        .await
+        {
+            Ok(Some(digest)) => digest,
+            Ok(None) => return Err(error::internal_error("tx not found by the peer")),
+            Err(e) => {
+                return Err(error::internal_error(format!(
+                    "Failed to get the digest from the peer: {}",
+                    e
+                )))
+            }
+        };
+        let local = match self
+            .ctx
+            .log_store
+            .get_replica_digest(tx_seq, indices, recompute.unwrap_or(false))
+            .await?
+        {
+            Some(digest) => digest,
+            None => return Err(error::invalid_params("tx_seq", "tx not found")),
+        };
+
+        let comparison = compare_replica_digests(&local, &remote)
+            .map_err(|e| error::internal_error(e.to_string()))?;
+        if !comparison.identical {
+            warn!(%tx_seq, %peer_rpc_url, ?comparison, "Replica diverged");
+        }
+        Ok(comparison)
+    }
+}
+
+impl RpcServerImpl {
+    async fn set_sync_paused(&self, paused: bool) -> RpcResult<()> {
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::SetPaused { paused })
+            .await?;
+
+        match response {
+            SyncResponse::SetPaused { err } if err.is_empty() => Ok(()),
+            SyncResponse::SetPaused { err } => Err(error::internal_error(err)),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+}
+
+fn parse_peer_id(peer_id: &str) -> RpcResult<PeerId> {
+    PeerId::from_str(peer_id).map_err(|e| error::invalid_params("peer_id", format!("{:?}", e)))
 }
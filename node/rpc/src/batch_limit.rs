@@ -0,0 +1,637 @@
+//! Limits the number of calls in a JSON-RPC batch request, so that a single HTTP request could
+//! not occupy the server with an unbounded number of calls, and the rate of the calls of each
+//! namespace.
+//!
+//! The JSON-RPC server does not limit the batch size, and its middleware cannot reject a call,
+//! so the HTTP and WebSocket servers are fronted by thin proxies, which reject an oversized or
+//! rate limited request as a whole before any call of it is processed, and forward the other
+//! requests to the JSON-RPC servers listening on local ports. The proxies forward the client
+//! address in the `X-Forwarded-For` header.
+
+use std::convert::Infallible;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use futures::io::{BufReader, BufWriter};
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::server::conn::{AddrIncoming, AddrStream};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Request, Response, Server, StatusCode, Uri};
+use jsonrpsee::types::error::ErrorCode;
+use metrics::{register_meter_with_group, Meter};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use serde_json::Value;
+use soketto::connection::{Error as WsError, Receiver, Sender};
+use soketto::handshake::client::{Header, ServerResponse};
+use soketto::handshake::{self, server::Response as HandshakeResponse};
+use soketto::Data;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+use crate::error::{RATE_LIMITED_CODE, RATE_LIMITED_MESSAGE};
+use crate::rate_limit::RateLimiter;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+type WsSocket = BufReader<BufWriter<Compat<TcpStream>>>;
+type WsResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Returns the number of calls if `body` is a batch request, or `None` otherwise.
+///
+/// A malformed batch is not counted, and is left to the JSON-RPC server to reject.
+pub fn batch_len(body: &[u8]) -> Option<usize> {
+    let first = body.iter().find(|b| !b.is_ascii_whitespace())?;
+    if *first != b'[' {
+        return None;
+    }
+
+    serde_json::from_slice::<Vec<IgnoredAny>>(body)
+        .ok()
+        .map(|calls| calls.len())
+}
+
+/// A call of a JSON-RPC request, of which only the fields to limit the rate are decoded.
+#[derive(Deserialize)]
+struct Call {
+    #[serde(default)]
+    method: String,
+    #[serde(default)]
+    id: Value,
+}
+
+/// Returns the calls of `body` and whether it is a batch request. A malformed request has no
+/// calls, and is left to the JSON-RPC server to reject.
+fn parse_calls(body: &[u8]) -> (Vec<Call>, bool) {
+    match body.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => (serde_json::from_slice(body).unwrap_or_default(), true),
+        Some(_) => match serde_json::from_slice(body) {
+            Ok(call) => (vec![call], false),
+            Err(_) => (vec![], false),
+        },
+        None => (vec![], false),
+    }
+}
+
+fn error_response(code: i32, message: &str, data: Option<String>, id: Value) -> String {
+    let mut error = serde_json::json!({
+        "code": code,
+        "message": message,
+    });
+    if let Some(data) = data {
+        error["data"] = data.into();
+    }
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": error,
+        "id": id,
+    })
+    .to_string()
+}
+
+pub struct BatchLimit {
+    /// Maximum number of calls in a batch, 0 for unlimited.
+    max_batch_size: usize,
+    max_request_body_size: u32,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    client: Client<HttpConnector>,
+    rejected: Arc<dyn Meter>,
+}
+
+impl BatchLimit {
+    pub fn new(max_batch_size: usize, max_request_body_size: u32) -> Self {
+        Self {
+            max_batch_size,
+            max_request_body_size,
+            rate_limiter: None,
+            client: Client::new(),
+            rejected: register_meter_with_group("rpc_batch_limit", "rejected"),
+        }
+    }
+
+    /// Limits the rate of the calls by the namespaces of their methods, e.g. `zgs` of
+    /// `zgs_getStatus`.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Returns the error response if `body` is a batch of more than `max_batch_size` calls, or
+    /// any call of it exceeds the rate limit of its namespace.
+    pub fn check(&self, body: &[u8]) -> Option<String> {
+        self.check_batch_size(body)
+            .or_else(|| self.check_rate_limit(body))
+    }
+
+    fn check_batch_size(&self, body: &[u8]) -> Option<String> {
+        let len = batch_len(body)?;
+        if self.max_batch_size == 0 || len <= self.max_batch_size {
+            return None;
+        }
+
+        self.rejected.mark(1);
+        debug!(%len, max_batch_size = %self.max_batch_size, "Batch request rejected");
+
+        Some(error_response(
+            ErrorCode::InvalidRequest.code(),
+            "Batch too large",
+            Some(format!(
+                "Batch of {} calls exceeds max_batch_size {}",
+                len, self.max_batch_size
+            )),
+            Value::Null,
+        ))
+    }
+
+    fn check_rate_limit(&self, body: &[u8]) -> Option<String> {
+        let rate_limiter = self.rate_limiter.as_ref()?;
+        let (calls, batch) = parse_calls(body);
+        let throttled = calls.iter().find(|call| {
+            let namespace = call.method.split('_').next().unwrap_or_default();
+            !rate_limiter.try_acquire(namespace)
+        })?;
+
+        debug!(method = %throttled.method, "Request rate limited");
+
+        // a batch is rejected as a whole
+        let id = if batch {
+            Value::Null
+        } else {
+            throttled.id.clone()
+        };
+        Some(error_response(
+            RATE_LIMITED_CODE,
+            RATE_LIMITED_MESSAGE,
+            None,
+            id,
+        ))
+    }
+
+    /// Serves HTTP on `listen_address` in background, and forwards the requests within the limit
+    /// to the JSON-RPC HTTP server on `upstream`. Returns the local address to serve on.
+    pub fn spawn(
+        self,
+        listen_address: SocketAddr,
+        upstream: SocketAddr,
+    ) -> Result<SocketAddr, Box<dyn Error>> {
+        let limit = Arc::new(self);
+        let make_service = make_service_fn(move |conn: &AddrStream| {
+            let limit = limit.clone();
+            let remote_address = conn.remote_addr();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let limit = limit.clone();
+                    async move {
+                        Ok::<_, Infallible>(limit.handle(request, remote_address, upstream).await)
+                    }
+                }))
+            }
+        });
+
+        let incoming = AddrIncoming::bind(&listen_address)?;
+        let local_address = incoming.local_addr();
+        let server = Server::builder(incoming).serve(make_service);
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!(%listen_address, ?e, "Batch limit proxy of RPC server stopped");
+            }
+        });
+
+        Ok(local_address)
+    }
+
+    /// Serves WebSocket on `listen_address` in background, and forwards the messages within the
+    /// limit to the JSON-RPC WebSocket server on `upstream`. Returns the local address to serve
+    /// on.
+    pub async fn spawn_ws(
+        self,
+        listen_address: SocketAddr,
+        upstream: SocketAddr,
+    ) -> Result<SocketAddr, Box<dyn Error>> {
+        let limit = Arc::new(self);
+        let listener = TcpListener::bind(listen_address).await?;
+        let local_address = listener.local_addr()?;
+        tokio::spawn(async move {
+            loop {
+                let (socket, remote_address) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!(%listen_address, ?e, "Failed to accept WebSocket connection");
+                        continue;
+                    }
+                };
+                let limit = limit.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = limit.proxy_ws(socket, remote_address, upstream).await {
+                        debug!(%remote_address, ?e, "WebSocket connection of RPC proxy closed");
+                    }
+                });
+            }
+        });
+
+        Ok(local_address)
+    }
+
+    async fn handle(
+        &self,
+        request: Request<Body>,
+        remote_address: SocketAddr,
+        upstream: SocketAddr,
+    ) -> Response<Body> {
+        let (mut parts, mut body) = request.into_parts();
+
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => return response(StatusCode::BAD_REQUEST, e.to_string()),
+            };
+            if data.len() + chunk.len() > self.max_request_body_size as usize {
+                return response(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large");
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        if let Some(rejected) = self.check(&data) {
+            let mut rejected = response(StatusCode::OK, rejected);
+            rejected.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/json; charset=utf-8"),
+            );
+            return rejected;
+        }
+
+        let forwarded_for = forwarded_for(
+            parts
+                .headers
+                .get(X_FORWARDED_FOR)
+                .and_then(|value| value.to_str().ok()),
+            remote_address,
+        );
+        match HeaderValue::from_str(&forwarded_for) {
+            Ok(value) => parts.headers.insert(X_FORWARDED_FOR, value),
+            Err(e) => return response(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+
+        let path = parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+        parts.uri = match format!("http://{}{}", upstream, path).parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(e) => return response(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+
+        match self
+            .client
+            .request(Request::from_parts(parts, Body::from(data)))
+            .await
+        {
+            Ok(forwarded) => forwarded,
+            Err(e) => response(StatusCode::BAD_GATEWAY, e.to_string()),
+        }
+    }
+
+    async fn proxy_ws(
+        &self,
+        socket: TcpStream,
+        remote_address: SocketAddr,
+        upstream: SocketAddr,
+    ) -> WsResult<()> {
+        let mut server = handshake::Server::new(BufReader::new(BufWriter::new(socket.compat())));
+        let request = server.receive_request().await?;
+        let path = request.path().to_string();
+        let key = request.key();
+
+        let forwarded_for = forwarded_for(None, remote_address);
+        let (mut upstream_sender, mut upstream_receiver) = match connect_ws(
+            upstream,
+            &path,
+            &[Header {
+                name: X_FORWARDED_FOR,
+                value: forwarded_for.as_bytes(),
+            }],
+            self.max_request_body_size,
+        )
+        .await
+        {
+            Ok(connected) => connected,
+            Err(e) => {
+                server
+                    .send_response(&HandshakeResponse::Reject { status_code: 502 })
+                    .await?;
+                return Err(e);
+            }
+        };
+
+        server
+            .send_response(&HandshakeResponse::Accept {
+                key,
+                protocol: None,
+            })
+            .await?;
+        let mut builder = server.into_builder();
+        builder.set_max_message_size(self.max_request_body_size as usize);
+        let (client_sender, mut client_receiver) = builder.finish();
+        let client_sender = Mutex::new(client_sender);
+
+        let result = tokio::select! {
+            result = self.forward_to_upstream(
+                &mut client_receiver,
+                &client_sender,
+                &mut upstream_sender,
+            ) => result,
+            result = forward_to_client(&mut upstream_receiver, &client_sender) => result,
+        };
+        match result {
+            Err(e) if matches!(e.downcast_ref::<WsError>(), Some(WsError::Closed)) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Forwards the messages within the limit from the client to the upstream, and answers the
+    /// oversized batches to the client directly.
+    async fn forward_to_upstream(
+        &self,
+        client_receiver: &mut Receiver<WsSocket>,
+        client_sender: &Mutex<Sender<WsSocket>>,
+        upstream_sender: &mut Sender<WsSocket>,
+    ) -> WsResult<()> {
+        let mut message = Vec::new();
+        loop {
+            message.clear();
+            let data = client_receiver.receive_data(&mut message).await?;
+            match self.check(&message) {
+                Some(rejected) => {
+                    let mut client_sender = client_sender.lock().await;
+                    send(&mut client_sender, Data::Text(0), rejected.as_bytes()).await?;
+                }
+                None => send(upstream_sender, data, &message).await?,
+            }
+        }
+    }
+}
+
+async fn forward_to_client(
+    upstream_receiver: &mut Receiver<WsSocket>,
+    client_sender: &Mutex<Sender<WsSocket>>,
+) -> WsResult<()> {
+    let mut message = Vec::new();
+    loop {
+        message.clear();
+        let data = upstream_receiver.receive_data(&mut message).await?;
+        send(&mut *client_sender.lock().await, data, &message).await?;
+    }
+}
+
+/// Appends `remote_address` to the value of the `X-Forwarded-For` header if any.
+fn forwarded_for(header: Option<&str>, remote_address: SocketAddr) -> String {
+    match header {
+        Some(header) if !header.trim().is_empty() => {
+            format!("{}, {}", header.trim(), remote_address.ip())
+        }
+        _ => remote_address.ip().to_string(),
+    }
+}
+
+/// Connects to the WebSocket server on `address`.
+async fn connect_ws(
+    address: SocketAddr,
+    path: &str,
+    headers: &[Header<'_>],
+    max_message_size: u32,
+) -> WsResult<(Sender<WsSocket>, Receiver<WsSocket>)> {
+    let socket = TcpStream::connect(address).await?;
+    let host = address.to_string();
+    let mut client =
+        handshake::Client::new(BufReader::new(BufWriter::new(socket.compat())), &host, path);
+    client.set_headers(headers);
+    match client.handshake().await? {
+        ServerResponse::Accepted { .. } => {}
+        ServerResponse::Redirect { status_code, .. } | ServerResponse::Rejected { status_code } => {
+            return Err(format!("WebSocket handshake rejected: status={}", status_code).into())
+        }
+    }
+
+    let mut builder = client.into_builder();
+    builder.set_max_message_size(max_message_size as usize);
+    Ok(builder.finish())
+}
+
+/// Sends a message of the same type as `data`, i.e. text or binary.
+async fn send(sender: &mut Sender<WsSocket>, data: Data, message: &[u8]) -> WsResult<()> {
+    match data {
+        Data::Text(_) => sender.send_text(std::str::from_utf8(message)?).await?,
+        Data::Binary(_) => sender.send_binary(message).await?,
+    }
+    sender.flush().await?;
+    Ok(())
+}
+
+fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use hyper::server::conn::AddrIncoming;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Client, Request, Response, Server};
+    use jsonrpsee::ws_server::WsServerBuilder;
+    use jsonrpsee::RpcModule;
+    use soketto::connection::{Receiver, Sender};
+    use soketto::Data;
+
+    use super::{batch_len, connect_ws, send, BatchLimit, WsSocket};
+    use crate::rate_limit::{RateLimitConfig, RateLimiter};
+
+    fn local_address() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+
+    fn batch(len: usize) -> String {
+        let calls: Vec<String> = (0..len)
+            .map(|id| {
+                format!(
+                    r#"{{"jsonrpc":"2.0","method":"zgs_getStatus","id":{}}}"#,
+                    id
+                )
+            })
+            .collect();
+        format!("[{}]", calls.join(","))
+    }
+
+    #[test]
+    fn test_batch_limit() {
+        assert_eq!(
+            batch_len(br#"{"jsonrpc":"2.0","method":"zgs_getStatus","id":0}"#),
+            None
+        );
+        assert_eq!(batch_len(b" [1, 2"), None);
+        assert_eq!(batch_len(b"\n[]"), Some(0));
+        assert_eq!(batch_len(batch(3).as_bytes()), Some(3));
+
+        let limit = BatchLimit::new(2, 1024);
+        assert_eq!(limit.check(batch(2).as_bytes()), None);
+        assert_eq!(limit.check(b"{}"), None);
+
+        // rejected as a whole
+        let rejected: serde_json::Value =
+            serde_json::from_str(&limit.check(batch(3).as_bytes()).unwrap()).unwrap();
+        assert_eq!(rejected["error"]["code"], -32600);
+        assert_eq!(rejected["error"]["message"], "Batch too large");
+        assert!(rejected["id"].is_null());
+        assert!(rejected.get("result").is_none());
+    }
+
+    /// Spawns an upstream which echoes the forwarded client address and the request body.
+    fn spawn_echo_upstream() -> SocketAddr {
+        let upstream = AddrIncoming::bind(&local_address()).unwrap();
+        let upstream_address = upstream.local_addr();
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                let forwarded_for = request.headers()["x-forwarded-for"]
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                let echo = format!("{} {}", forwarded_for, String::from_utf8_lossy(&body));
+                Ok::<_, Infallible>(Response::new(Body::from(echo)))
+            }))
+        });
+        tokio::spawn(Server::builder(upstream).serve(make_service));
+        upstream_address
+    }
+
+    async fn post(proxy: SocketAddr, body: String, forwarded_for: Option<&str>) -> String {
+        let mut request = Request::post(format!("http://{}/", proxy));
+        if let Some(forwarded_for) = forwarded_for {
+            request = request.header("x-forwarded-for", forwarded_for);
+        }
+        let response = Client::new()
+            .request(request.body(Body::from(body)).unwrap())
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_batch_limit_http_proxy() {
+        let proxy = BatchLimit::new(2, 1024)
+            .spawn(local_address(), spawn_echo_upstream())
+            .unwrap();
+
+        assert_eq!(
+            post(proxy, batch(2), None).await,
+            format!("127.0.0.1 {}", batch(2))
+        );
+        assert_eq!(
+            post(proxy, batch(1), Some("10.0.0.1")).await,
+            format!("10.0.0.1, 127.0.0.1 {}", batch(1))
+        );
+
+        // rejected without forwarding
+        let rejected: serde_json::Value =
+            serde_json::from_str(&post(proxy, batch(3), None).await).unwrap();
+        assert_eq!(rejected["error"]["message"], "Batch too large");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_http_proxy() {
+        let rate_limiter = RateLimiter::new(&[(
+            "zgs",
+            RateLimitConfig {
+                capacity: 3,
+                refill_per_sec: 1,
+            },
+        )]);
+        let proxy = BatchLimit::new(0, 1024)
+            .with_rate_limiter(Arc::new(rate_limiter))
+            .spawn(local_address(), spawn_echo_upstream())
+            .unwrap();
+        let call = |method: &str, id: u64| {
+            format!(r#"{{"jsonrpc":"2.0","method":"{}","id":{}}}"#, method, id)
+        };
+
+        // any batch size is allowed, and every call takes a token
+        assert_eq!(
+            post(proxy, batch(2), None).await,
+            format!("127.0.0.1 {}", batch(2))
+        );
+        assert_eq!(
+            post(proxy, call("zgs_getStatus", 7), None).await,
+            format!("127.0.0.1 {}", call("zgs_getStatus", 7))
+        );
+
+        // rejected without forwarding
+        let rejected: serde_json::Value =
+            serde_json::from_str(&post(proxy, call("zgs_getStatus", 8), None).await).unwrap();
+        assert_eq!(rejected["error"]["code"], -32005);
+        assert_eq!(rejected["error"]["message"], "Rate limited");
+        assert_eq!(rejected["id"], 8);
+        let rejected: serde_json::Value =
+            serde_json::from_str(&post(proxy, batch(1), None).await).unwrap();
+        assert_eq!(rejected["error"]["message"], "Rate limited");
+        assert!(rejected["id"].is_null());
+
+        // the other namespaces are not limited
+        assert_eq!(
+            post(proxy, call("admin_getStatus", 9), None).await,
+            format!("127.0.0.1 {}", call("admin_getStatus", 9))
+        );
+    }
+
+    async fn call(
+        sender: &mut Sender<WsSocket>,
+        receiver: &mut Receiver<WsSocket>,
+        body: String,
+    ) -> serde_json::Value {
+        send(sender, Data::Text(0), body.as_bytes()).await.unwrap();
+        let mut message = Vec::new();
+        receiver.receive_data(&mut message).await.unwrap();
+        serde_json::from_slice(&message).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_batch_limit_ws_proxy() {
+        let mut module = RpcModule::new(());
+        module
+            .register_method("zgs_getStatus", |_, _| Ok("ok"))
+            .unwrap();
+        let upstream = WsServerBuilder::default()
+            .build(local_address())
+            .await
+            .unwrap();
+        let upstream_address = upstream.local_addr().unwrap();
+        let _handle = upstream.start(module).unwrap();
+
+        let proxy = BatchLimit::new(2, 1024)
+            .spawn_ws(local_address(), upstream_address)
+            .await
+            .unwrap();
+        let (mut sender, mut receiver) = connect_ws(proxy, "/", &[], 1024).await.unwrap();
+        let response = call(&mut sender, &mut receiver, batch(2)).await;
+        assert_eq!(response.as_array().unwrap().len(), 2);
+        assert_eq!(response[0]["result"], "ok");
+
+        // rejected, and the connection is still served
+        let rejected = call(&mut sender, &mut receiver, batch(3)).await;
+        assert_eq!(rejected["error"]["message"], "Batch too large");
+        assert_eq!(
+            call(&mut sender, &mut receiver, batch(1)).await[0]["result"],
+            "ok"
+        );
+    }
+}
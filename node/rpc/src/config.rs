@@ -1,6 +1,9 @@
 use std::{net::SocketAddr, str::FromStr};
 
 use serde::{Deserialize, Serialize};
+use shared_types::CHUNK_SIZE;
+
+use crate::rate_limit::RateLimitConfig;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 #[serde(default)]
@@ -9,9 +12,27 @@ pub struct Config {
     pub listen_address: SocketAddr,
     pub listen_address_admin: SocketAddr,
     pub listen_address_grpc: SocketAddr,
+    /// WebSocket server address for subscriptions of the public RPC, disabled if `None`.
+    pub listen_address_ws: Option<SocketAddr>,
     pub chunks_per_segment: usize,
     pub max_request_body_size: u32,
+    /// Maximum number of calls in a batch request to the HTTP and WebSocket servers, 0 for
+    /// unlimited.
+    pub max_batch_size: usize,
     pub max_cache_file_size: usize,
+    /// Whether to serve segment proofs and sector proofs only for finalized files.
+    pub serve_proofs_only_for_finalized: bool,
+    /// Rate limits of requests per namespace.
+    pub zgs_rate_limit: RateLimitConfig,
+    pub admin_rate_limit: RateLimitConfig,
+    pub miner_rate_limit: RateLimitConfig,
+    pub net_rate_limit: RateLimitConfig,
+    /// Number of threads to hash for requests, e.g. to compute or validate segment proofs, 0 for
+    /// as many threads as CPUs.
+    pub hash_pool_threads: usize,
+    /// Number of segments to read ahead when a file is downloaded sequentially by segments with
+    /// proof, 0 to disable.
+    pub segment_prefetch: usize,
 }
 
 impl Default for Config {
@@ -21,9 +42,82 @@ impl Default for Config {
             listen_address: SocketAddr::from_str("0.0.0.0:5678").unwrap(),
             listen_address_admin: SocketAddr::from_str("127.0.0.1:5679").unwrap(),
             listen_address_grpc: SocketAddr::from_str("0.0.0.0:50051").unwrap(),
+            listen_address_ws: None,
             chunks_per_segment: 1024,
             max_request_body_size: 100 * 1024 * 1024, // 100MB
-            max_cache_file_size: 10 * 1024 * 1024,    // 10MB
+            max_batch_size: 0,
+            max_cache_file_size: 10 * 1024 * 1024, // 10MB
+            serve_proofs_only_for_finalized: false,
+            zgs_rate_limit: Default::default(),
+            admin_rate_limit: Default::default(),
+            miner_rate_limit: Default::default(),
+            net_rate_limit: Default::default(),
+            hash_pool_threads: 0,
+            segment_prefetch: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Validates the segment layout, since segments uploaded or downloaded with a mismatched
+    /// layout always fail the proof validation.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.chunks_per_segment.is_power_of_two() {
+            return Err(format!(
+                "Invalid chunks_per_segment {}, which should be a power of two",
+                self.chunks_per_segment
+            ));
+        }
+
+        // segment data is encoded in base64 in the upload request
+        let encoded_segment_size = (self.chunks_per_segment * CHUNK_SIZE + 2) / 3 * 4;
+        if encoded_segment_size > self.max_request_body_size as usize {
+            return Err(format!(
+                "Segment of {} chunks exceeds max_request_body_size {}",
+                self.chunks_per_segment, self.max_request_body_size
+            ));
+        }
+
+        for (namespace, limit) in self.rate_limits() {
+            if limit.refill_per_sec > 0 && limit.capacity == 0 {
+                return Err(format!(
+                    "Invalid rate limit of namespace {}, capacity should be positive",
+                    namespace
+                ));
+            }
         }
+
+        Ok(())
+    }
+
+    pub fn rate_limits(&self) -> [(&'static str, RateLimitConfig); 4] {
+        [
+            ("zgs", self.zgs_rate_limit),
+            ("admin", self.admin_rate_limit),
+            ("miner", self.miner_rate_limit),
+            ("net", self.net_rate_limit),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn test_validate() {
+        assert!(Config::default().validate().is_ok());
+
+        let config = Config {
+            chunks_per_segment: 1000,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Config {
+            max_request_body_size: 256 * 1024,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
     }
 }
@@ -28,3 +28,23 @@ pub fn invalid_params(param: &str, msg: impl std::convert::AsRef<str>) -> Error
         Some(msg.as_ref()),
     )))
 }
+
+/// The same code as `limit exceeded` in EIP-1474.
+pub const RATE_LIMITED_CODE: i32 = -32005;
+pub const RATE_LIMITED_MESSAGE: &str = "Rate limited";
+
+pub fn rate_limited() -> Error {
+    Error::Call(CallError::Custom(ErrorObject::borrowed(
+        RATE_LIMITED_CODE,
+        &RATE_LIMITED_MESSAGE,
+        None,
+    )))
+}
+
+pub fn file_not_finalized() -> Error {
+    Error::Call(CallError::Custom(ErrorObject::borrowed(
+        ErrorCode::InvalidRequest.code(),
+        &"File not finalized",
+        None,
+    )))
+}
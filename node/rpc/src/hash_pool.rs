@@ -0,0 +1,160 @@
+//! A dedicated thread pool for the CPU-bound hashing of RPC requests, e.g. to compute the proofs
+//! of downloaded segments and validate the proofs of uploaded segments, so that heavy hashing
+//! never blocks the threads of the async runtime, which also drive the networking.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use jsonrpsee::core::RpcResult;
+use metrics::{Gauge, GaugeUsize};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tokio::sync::oneshot;
+
+use crate::error;
+
+struct PoolMetrics {
+    threads: usize,
+    queued: AtomicUsize,
+    busy: AtomicUsize,
+    queue_depth: Arc<dyn Gauge<usize>>,
+    /// Percentage of the threads busy hashing.
+    utilization: Arc<dyn Gauge<usize>>,
+}
+
+impl PoolMetrics {
+    fn on_queued(&self) {
+        let queued = self.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        self.queue_depth.update(queued);
+    }
+
+    fn on_started(&self) {
+        let queued = self.queued.fetch_sub(1, Ordering::Relaxed) - 1;
+        self.queue_depth.update(queued);
+        let busy = self.busy.fetch_add(1, Ordering::Relaxed) + 1;
+        self.utilization.update(busy * 100 / self.threads);
+    }
+
+    fn on_completed(&self) {
+        let busy = self.busy.fetch_sub(1, Ordering::Relaxed) - 1;
+        self.utilization.update(busy * 100 / self.threads);
+    }
+}
+
+pub struct HashPool {
+    pool: ThreadPool,
+    metrics: Arc<PoolMetrics>,
+}
+
+impl HashPool {
+    /// Creates a pool of `num_threads` threads, or of as many threads as CPUs if 0.
+    pub fn new(num_threads: usize) -> Result<Self, String> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|index| format!("rpc_hash_{}", index))
+            .panic_handler(|_| error!("RPC hashing task panicked"))
+            .build()
+            .map_err(|e| format!("Failed to build RPC hash pool: {:?}", e))?;
+
+        let metrics = Arc::new(PoolMetrics {
+            threads: pool.current_num_threads(),
+            queued: AtomicUsize::new(0),
+            busy: AtomicUsize::new(0),
+            queue_depth: GaugeUsize::register_with_group("rpc_hash_pool", "queue_depth"),
+            utilization: GaugeUsize::register_with_group("rpc_hash_pool", "utilization"),
+        });
+
+        Ok(Self { pool, metrics })
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.metrics.threads
+    }
+
+    /// Runs `f` in the pool, and waits for the result without blocking the async runtime.
+    pub async fn run<T, F>(&self, f: F) -> RpcResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let metrics = self.metrics.clone();
+        metrics.on_queued();
+
+        self.pool.spawn(move || {
+            metrics.on_started();
+            // `metrics` is updated even if `f` panics
+            let completed = Completed(metrics);
+            let result = f();
+            drop(completed);
+            // the request may have been cancelled
+            let _ = sender.send(result);
+        });
+
+        receiver
+            .await
+            .map_err(|_| error::internal_error("Hashing task panicked"))
+    }
+}
+
+struct Completed(Arc<PoolMetrics>);
+
+impl Drop for Completed {
+    fn drop(&mut self) {
+        self.0.on_completed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashPool;
+    use merkle_light::merkle::MerkleTree;
+    use merkle_tree::RawLeafSha3Algorithm;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn heavy_hashing() -> [u8; 32] {
+        let leaves = (0..(1u32 << 16)).map(|i| {
+            let mut leaf = [0u8; 32];
+            leaf[..4].copy_from_slice(&i.to_be_bytes());
+            leaf
+        });
+        MerkleTree::<_, RawLeafSha3Algorithm>::new(leaves).root()
+    }
+
+    /// Returns the time for a lightweight request to be served on a single threaded runtime,
+    /// while a heavy hashing request is served either inline or in the pool.
+    fn probe_latency(pool: Option<Arc<HashPool>>) -> Duration {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async move {
+            let start = Instant::now();
+            let heavy = tokio::spawn(async move {
+                match pool {
+                    Some(pool) => pool.run(heavy_hashing).await.unwrap(),
+                    None => heavy_hashing(),
+                }
+            });
+            let probe = tokio::spawn(async move { start.elapsed() });
+
+            let latency = probe.await.unwrap();
+            heavy.await.unwrap();
+            latency
+        })
+    }
+
+    #[test]
+    fn test_hash_pool_latency() {
+        let pool = Arc::new(HashPool::new(0).unwrap());
+        assert!(pool.num_threads() > 0);
+
+        let inline = probe_latency(None);
+        let pooled = probe_latency(Some(pool.clone()));
+        assert!(pooled < inline, "pooled {:?}, inline {:?}", pooled, inline);
+
+        assert_eq!(pool.metrics.queued.load(Ordering::Relaxed), 0);
+        assert_eq!(pool.metrics.busy.load(Ordering::Relaxed), 0);
+    }
+}
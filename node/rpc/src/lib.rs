@@ -4,41 +4,59 @@ extern crate tracing;
 extern crate miner as zgs_miner;
 
 mod admin;
+mod batch_limit;
 mod config;
 mod error;
+mod hash_pool;
 mod middleware;
 mod miner;
+mod net;
+mod rate_limit;
 mod rpc_helper;
+mod segment_prefetch;
 pub mod types;
 mod zgs;
 mod zgs_grpc;
 
 use crate::miner::RpcServer as MinerRpcServer;
-use crate::types::SegmentWithProof;
+use crate::net::RpcServer as NetRpcServer;
+use crate::types::{SegmentWithProof, SyncMode};
 use crate::zgs_grpc::r#impl::ZgsGrpcServiceImpl;
 use crate::zgs_grpc_proto::zgs_grpc_service_server::ZgsGrpcServiceServer;
 use admin::RpcServer as AdminRpcServer;
+use batch_limit::BatchLimit;
 use chunk_pool::MemoryChunkPool;
 use file_location_cache::FileLocationCache;
 use futures::channel::mpsc::Sender;
+use jsonrpsee::core::server::rpc_module::Methods;
 use jsonrpsee::core::RpcResult;
 use jsonrpsee::http_server::{HttpServerBuilder, HttpServerHandle};
+use jsonrpsee::ws_server::{WsServerBuilder, WsServerHandle};
 use network::{NetworkGlobals, NetworkMessage, NetworkSender};
+use shared_types::ChunkArrayWithProof;
 use std::error::Error;
 use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use storage_async::Store;
+use sync::auto_prune::AutoPruner;
+use sync::integrity_scan::IntegrityScanner;
+use sync::worker_watchdog::WorkerWatchdog;
 use sync::{SyncRequest, SyncResponse, SyncSender};
 use task_executor::ShutdownReason;
 use tokio::sync::broadcast;
 use tonic::transport::Server;
 use tonic_reflection::server::Builder as ReflectionBuilder;
 use zgs::RpcServer as ZgsRpcServer;
-use zgs_miner::MinerMessage;
+use zgs_miner::{MinerMessage, MinerState};
 
 pub use admin::RpcClient as ZgsAdminRpcClient;
 pub use config::Config as RPCConfig;
+pub use hash_pool::HashPool;
 pub use miner::RpcClient as ZgsMinerRpcClient;
+pub use net::RpcClient as ZgsNetRpcClient;
+pub use rate_limit::{RateLimitConfig, RateLimiter};
+pub use segment_prefetch::SegmentPrefetcher;
 pub use zgs::RpcClient as ZgsRPCClient;
 
 pub mod zgs_grpc_proto {
@@ -57,10 +75,18 @@ pub struct Context {
     pub network_globals: Arc<NetworkGlobals>,
     pub network_send: NetworkSender,
     pub sync_send: SyncSender,
+    pub sync_mode: SyncMode,
+    pub worker_watchdog: Arc<WorkerWatchdog>,
+    pub auto_pruner: Arc<AutoPruner>,
+    pub integrity_scanner: Arc<IntegrityScanner>,
     pub chunk_pool: Arc<MemoryChunkPool>,
     pub log_store: Arc<Store>,
     pub shutdown_sender: Sender<ShutdownReason>,
     pub mine_service_sender: Option<broadcast::Sender<MinerMessage>>,
+    pub mine_state: Option<Arc<MinerState>>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub hash_pool: Arc<HashPool>,
+    pub segment_prefetcher: Arc<SegmentPrefetcher<ChunkArrayWithProof>>,
 }
 
 impl Context {
@@ -98,6 +124,43 @@ fn server_builder(ctx: Context) -> HttpServerBuilder<middleware::Metrics> {
         .set_middleware(middleware::Metrics::default())
 }
 
+/// Returns the limit of the requests to front the servers if the batch size or the request
+/// rate is limited.
+fn request_limit(ctx: &Context) -> Option<BatchLimit> {
+    if ctx.config.max_batch_size == 0 && !ctx.rate_limiter.is_enabled() {
+        return None;
+    }
+
+    Some(
+        BatchLimit::new(ctx.config.max_batch_size, ctx.config.max_request_body_size)
+            .with_rate_limiter(ctx.rate_limiter.clone()),
+    )
+}
+
+/// Starts an HTTP server of `methods` on `listen_address`, which is fronted by the batch and
+/// rate limits if configured.
+async fn start_http_server(
+    ctx: &Context,
+    listen_address: SocketAddr,
+    methods: impl Into<Methods>,
+) -> Result<HttpServerHandle, Box<dyn Error>> {
+    let limit = match request_limit(ctx) {
+        Some(limit) => limit,
+        None => {
+            return Ok(server_builder(ctx.clone())
+                .build(listen_address)
+                .await?
+                .start(methods)?)
+        }
+    };
+
+    let server = server_builder(ctx.clone())
+        .build(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await?;
+    limit.spawn(listen_address, server.local_addr()?)?;
+    Ok(server.start(methods)?)
+}
+
 /// Run a single RPC server for all namespace RPCs.
 async fn run_server_all(ctx: Context) -> Result<HttpServerHandle, Box<dyn Error>> {
     // public rpc
@@ -107,16 +170,17 @@ async fn run_server_all(ctx: Context) -> Result<HttpServerHandle, Box<dyn Error>
     let admin = (admin::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
     zgs.merge(admin)?;
 
+    // net rpc
+    let net = (net::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
+    zgs.merge(net)?;
+
     // mine rpc if configured
     if ctx.mine_service_sender.is_some() {
         let mine = (miner::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
         zgs.merge(mine)?;
     }
 
-    Ok(server_builder(ctx.clone())
-        .build(ctx.config.listen_address)
-        .await?
-        .start(zgs)?)
+    start_http_server(&ctx, ctx.config.listen_address, zgs).await
 }
 
 /// Run 2 RPC servers (public & private) for different namespace RPCs.
@@ -129,32 +193,54 @@ async fn run_server_public_private(
     // admin rpc
     let mut admin = (admin::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
 
+    // net rpc
+    let net = (net::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
+    admin.merge(net)?;
+
     // mine rpc if configured
     if ctx.mine_service_sender.is_some() {
         let mine = (miner::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
         admin.merge(mine)?;
     }
 
-    let handle_public = server_builder(ctx.clone())
-        .build(ctx.config.listen_address)
-        .await?
-        .start(zgs)?;
-
-    let handle_private = server_builder(ctx.clone())
-        .build(ctx.config.listen_address_admin)
-        .await?
-        .start(admin)?;
+    let handle_public = start_http_server(&ctx, ctx.config.listen_address, zgs).await?;
+    let handle_private = start_http_server(&ctx, ctx.config.listen_address_admin, admin).await?;
 
     Ok((handle_public, Some(handle_private)))
 }
 
+/// Run a WebSocket server for the public RPCs including subscriptions, if configured, which is
+/// fronted by the batch and rate limits if configured.
+pub async fn run_ws_server(ctx: Context) -> Result<Option<WsServerHandle>, Box<dyn Error>> {
+    let listen_address = match ctx.config.listen_address_ws {
+        Some(address) => address,
+        None => return Ok(None),
+    };
+    let zgs = (zgs::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
+
+    let builder =
+        WsServerBuilder::default().max_request_body_size(ctx.config.max_request_body_size);
+    let limit = match request_limit(&ctx) {
+        Some(limit) => limit,
+        None => return Ok(Some(builder.build(listen_address).await?.start(zgs)?)),
+    };
+
+    let server = builder.build(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+    limit.spawn_ws(listen_address, server.local_addr()?).await?;
+    Ok(Some(server.start(zgs)?))
+}
+
 pub async fn run_grpc_server(ctx: Context) -> Result<(), Box<dyn Error>> {
     let grpc_addr = ctx.config.listen_address_grpc;
     let reflection = ReflectionBuilder::configure()
         .register_encoded_file_descriptor_set(DESCRIPTOR_SET)
         .build()?;
 
-    let server = ZgsGrpcServiceServer::new(ZgsGrpcServiceImpl { ctx });
+    let rate_limiter = ctx.rate_limiter.clone();
+    let server = ZgsGrpcServiceServer::with_interceptor(
+        ZgsGrpcServiceImpl { ctx },
+        move |request: tonic::Request<()>| rate_limiter.check_grpc("zgs", request),
+    );
     Server::builder()
         .add_service(server)
         .add_service(reflection)
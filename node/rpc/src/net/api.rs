@@ -0,0 +1,21 @@
+use crate::types::LocalNodeInfo;
+use jsonrpsee::core::RpcResult;
+use jsonrpsee::proc_macros::rpc;
+use network::types::{AdvertisementStatus, SeedStatus};
+
+#[rpc(server, client, namespace = "net")]
+pub trait Rpc {
+    /// Returns the identity and addresses of the local node, e.g. to configure other nodes to
+    /// connect to it.
+    #[method(name = "getInfo")]
+    async fn get_info(&self) -> RpcResult<LocalNodeInfo>;
+
+    /// Returns the resolution status of the seed nodes configured by `network_libp2p_nodes`.
+    #[method(name = "getSeedStatus")]
+    async fn get_seed_status(&self) -> RpcResult<Vec<SeedStatus>>;
+
+    /// Returns what the node advertises to the peers about the files it holds, which includes
+    /// the available chunk ranges of the partial files if `router.advertise_partial_files`.
+    #[method(name = "getAdvertisement")]
+    async fn get_advertisement(&self) -> RpcResult<AdvertisementStatus>;
+}
@@ -0,0 +1,31 @@
+use super::api::RpcServer;
+use crate::types::LocalNodeInfo;
+use crate::Context;
+use jsonrpsee::core::async_trait;
+use jsonrpsee::core::RpcResult;
+use network::types::{AdvertisementStatus, SeedStatus};
+
+pub struct RpcServerImpl {
+    pub ctx: Context,
+}
+
+#[async_trait]
+impl RpcServer for RpcServerImpl {
+    async fn get_info(&self) -> RpcResult<LocalNodeInfo> {
+        info!("net_getInfo()");
+
+        Ok(LocalNodeInfo::from(self.ctx.network_globals.as_ref()))
+    }
+
+    async fn get_seed_status(&self) -> RpcResult<Vec<SeedStatus>> {
+        info!("net_getSeedStatus()");
+
+        Ok(self.ctx.network_globals.seed_status())
+    }
+
+    async fn get_advertisement(&self) -> RpcResult<AdvertisementStatus> {
+        info!("net_getAdvertisement()");
+
+        Ok(self.ctx.network_globals.advertisement())
+    }
+}
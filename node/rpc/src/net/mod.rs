@@ -0,0 +1,6 @@
+mod api;
+mod r#impl;
+
+pub use api::RpcClient;
+pub use api::RpcServer;
+pub use r#impl::RpcServerImpl;
@@ -0,0 +1,185 @@
+//! Token bucket rate limits of RPC requests per namespace, e.g. to allow more read requests in
+//! the `zgs` namespace than requests in the `admin` namespace.
+//!
+//! The limits are enforced in front of the handlers, i.e. by the proxies of the JSON-RPC servers
+//! in `batch_limit` and by the interceptor of the gRPC server, so that no handler could skip
+//! them. Requests are only limited per namespace, and the gRPC requests are of the `zgs`
+//! namespace.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use metrics::{register_meter_with_group, Meter};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tonic::{Request, Status};
+
+use crate::error::RATE_LIMITED_MESSAGE;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Maximum number of requests in a burst.
+    pub capacity: u64,
+    /// Number of requests allowed per second on average, 0 to disable the rate limit.
+    pub refill_per_sec: u64,
+}
+
+struct TokenBucket {
+    config: RateLimitConfig,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: RateLimitConfig, now: Instant) -> Self {
+        Self {
+            config,
+            tokens: config.capacity as f64,
+            last_refill: now,
+        }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.config.refill_per_sec as f64)
+            .min(self.config.capacity as f64);
+        self.last_refill = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+struct NamespaceLimit {
+    bucket: Mutex<TokenBucket>,
+    throttled: Arc<dyn Meter>,
+}
+
+pub struct RateLimiter {
+    namespaces: Vec<(&'static str, NamespaceLimit)>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &[(&'static str, RateLimitConfig)]) -> Self {
+        let now = Instant::now();
+        let namespaces = limits
+            .iter()
+            .filter(|(_, config)| config.refill_per_sec > 0)
+            .map(|(namespace, config)| {
+                let limit = NamespaceLimit {
+                    bucket: Mutex::new(TokenBucket::new(*config, now)),
+                    throttled: register_meter_with_group(
+                        format!("rpc_rate_limit_{}", namespace).as_str(),
+                        "throttled",
+                    ),
+                };
+                (*namespace, limit)
+            })
+            .collect();
+        Self { namespaces }
+    }
+
+    /// Returns whether the requests of any namespace are limited.
+    pub fn is_enabled(&self) -> bool {
+        !self.namespaces.is_empty()
+    }
+
+    /// Intercepts the gRPC requests of `namespace` to reject the throttled ones.
+    pub fn check_grpc(&self, namespace: &str, request: Request<()>) -> Result<Request<()>, Status> {
+        if self.try_acquire(namespace) {
+            Ok(request)
+        } else {
+            Err(Status::resource_exhausted(RATE_LIMITED_MESSAGE))
+        }
+    }
+
+    /// Returns `false` if the request of `namespace` should be throttled.
+    pub fn try_acquire(&self, namespace: &str) -> bool {
+        self.try_acquire_at(namespace, Instant::now())
+    }
+
+    fn try_acquire_at(&self, namespace: &str, now: Instant) -> bool {
+        let limit = match self.namespaces.iter().find(|(name, _)| *name == namespace) {
+            Some((_, limit)) => limit,
+            None => return true,
+        };
+
+        let acquired = limit.bucket.lock().try_acquire(now);
+        if !acquired {
+            limit.throttled.mark(1);
+        }
+        acquired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimitConfig, RateLimiter};
+    use std::time::{Duration, Instant};
+    use tonic::{Code, Request};
+
+    #[test]
+    fn test_rate_limit() {
+        let zgs = RateLimitConfig {
+            capacity: 10,
+            refill_per_sec: 5,
+        };
+        let admin = RateLimitConfig {
+            capacity: 2,
+            refill_per_sec: 1,
+        };
+        let limiter = RateLimiter::new(&[("zgs", zgs), ("admin", admin)]);
+        let now = Instant::now();
+
+        // requests beyond the burst are rejected
+        for _ in 0..10 {
+            assert!(limiter.try_acquire_at("zgs", now));
+        }
+        assert!(!limiter.try_acquire_at("zgs", now));
+        assert!(limiter.try_acquire_at("admin", now));
+        assert!(limiter.try_acquire_at("admin", now));
+        assert!(!limiter.try_acquire_at("admin", now));
+
+        // namespace without limit
+        assert!(limiter.try_acquire_at("miner", now));
+
+        // recover after refill
+        let now = now + Duration::from_millis(400);
+        assert!(limiter.try_acquire_at("zgs", now));
+        assert!(limiter.try_acquire_at("zgs", now));
+        assert!(!limiter.try_acquire_at("zgs", now));
+        assert!(!limiter.try_acquire_at("admin", now));
+
+        // refilled up to the capacity
+        let now = now + Duration::from_secs(60);
+        for _ in 0..2 {
+            assert!(limiter.try_acquire_at("admin", now));
+        }
+        assert!(!limiter.try_acquire_at("admin", now));
+    }
+
+    #[test]
+    fn test_rate_limit_grpc() {
+        let limiter = RateLimiter::new(&[(
+            "zgs",
+            RateLimitConfig {
+                capacity: 2,
+                refill_per_sec: 1,
+            },
+        )]);
+        assert!(limiter.is_enabled());
+        assert!(!RateLimiter::new(&[("zgs", Default::default())]).is_enabled());
+
+        for _ in 0..2 {
+            assert!(limiter.check_grpc("zgs", Request::new(())).is_ok());
+        }
+        let status = limiter.check_grpc("zgs", Request::new(())).unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+    }
+}
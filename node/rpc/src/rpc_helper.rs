@@ -39,7 +39,15 @@ pub async fn put_segment_with_maybe_tx(
         check_need_cache(ctx, &maybe_tx, segment.file_size).await?
     };
 
-    segment.validate(ctx.config.chunks_per_segment)?;
+    let chunks_per_segment = ctx.config.chunks_per_segment;
+    let (segment, validated) = ctx
+        .hash_pool
+        .run(move || {
+            let validated = segment.validate(chunks_per_segment);
+            (segment, validated)
+        })
+        .await?;
+    validated?;
 
     let seg_info = SegmentInfo {
         root: segment.root,
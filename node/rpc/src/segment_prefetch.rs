@@ -0,0 +1,297 @@
+//! Read-ahead of the segments of a file that a client downloads sequentially, e.g. to stream or
+//! export the file, so that the next segments are already read from the store when requested.
+//!
+//! A read is sequential if it's right after the previous read of the same file. Only sequential
+//! reads prefetch, so that random access never wastes the I/O for segments not requested. A read
+//! of a segment that is still being prefetched waits for the prefetch instead of loading it again.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use metrics::{register_meter_with_group, Gauge, GaugeUsize, Meter};
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+/// Maximum number of files read sequentially at the same time to track and prefetch for.
+const MAX_STREAMS: usize = 16;
+
+/// Segment key of `(tx_seq, index)`.
+type SegmentKey = (u64, usize);
+
+struct PrefetchMetrics {
+    hits: Arc<dyn Meter>,
+    misses: Arc<dyn Meter>,
+    num_hits: AtomicUsize,
+    num_reads: AtomicUsize,
+    /// Percentage of the reads served by the prefetched segments.
+    hit_rate: Arc<dyn Gauge<usize>>,
+}
+
+impl PrefetchMetrics {
+    fn on_read(&self, hit: bool) {
+        let num_hits = if hit {
+            self.hits.mark(1);
+            self.num_hits.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            self.misses.mark(1);
+            self.num_hits.load(Ordering::Relaxed)
+        };
+        let num_reads = self.num_reads.fetch_add(1, Ordering::Relaxed) + 1;
+        self.hit_rate.update(num_hits * 100 / num_reads);
+    }
+}
+
+struct PrefetchState<T> {
+    /// The last read segment of the recently read files, from the oldest to the latest.
+    streams: VecDeque<SegmentKey>,
+    segments: HashMap<SegmentKey, T>,
+    /// Keys of `segments` from the oldest to the latest, to evict the oldest ones.
+    order: VecDeque<SegmentKey>,
+    /// Segments being prefetched, along with the read waiting for it if any.
+    in_flight: HashMap<SegmentKey, Option<oneshot::Sender<T>>>,
+}
+
+impl<T> PrefetchState<T> {
+    fn insert(&mut self, key: SegmentKey, segment: T, max_segments: usize) {
+        if self.order.len() >= max_segments {
+            if let Some(oldest) = self.order.pop_front() {
+                self.segments.remove(&oldest);
+            }
+        }
+        self.segments.insert(key, segment);
+        self.order.push_back(key);
+    }
+}
+
+/// Where a read is served from.
+enum ReadSource<T> {
+    Prefetched(T),
+    InFlight(oneshot::Receiver<T>),
+    Load,
+}
+
+pub struct SegmentPrefetcher<T> {
+    /// Number of segments to read ahead, 0 if disabled.
+    read_ahead: usize,
+    state: Mutex<PrefetchState<T>>,
+    metrics: PrefetchMetrics,
+}
+
+impl<T: Send + 'static> SegmentPrefetcher<T> {
+    pub fn new(read_ahead: usize) -> Self {
+        Self {
+            read_ahead,
+            state: Mutex::new(PrefetchState {
+                streams: VecDeque::new(),
+                segments: HashMap::new(),
+                order: VecDeque::new(),
+                in_flight: HashMap::new(),
+            }),
+            metrics: PrefetchMetrics {
+                hits: register_meter_with_group("rpc_segment_prefetch", "hits"),
+                misses: register_meter_with_group("rpc_segment_prefetch", "misses"),
+                num_hits: AtomicUsize::new(0),
+                num_reads: AtomicUsize::new(0),
+                hit_rate: GaugeUsize::register_with_group("rpc_segment_prefetch", "hit_rate"),
+            },
+        }
+    }
+
+    /// Reads the segment at `index` of the file of `tx_seq` with `num_segments` segments, which
+    /// is served by the prefetched segment if any, and loaded by `load` otherwise. If the read
+    /// is sequential, the next segments are loaded by `load` in the background.
+    pub async fn read<F, Fut, E>(
+        self: &Arc<Self>,
+        tx_seq: u64,
+        index: usize,
+        num_segments: usize,
+        load: F,
+    ) -> Result<Option<T>, E>
+    where
+        F: Fn(usize) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<Option<T>, E>> + Send + 'static,
+    {
+        if self.read_ahead == 0 {
+            return load(index).await;
+        }
+
+        let key = (tx_seq, index);
+        let (source, sequential) = {
+            let mut state = self.state.lock();
+            let source = match state.segments.remove(&key) {
+                Some(segment) => {
+                    state.order.retain(|k| *k != key);
+                    ReadSource::Prefetched(segment)
+                }
+                None => match state.in_flight.get_mut(&key) {
+                    Some(waiter @ None) => {
+                        let (sender, receiver) = oneshot::channel();
+                        *waiter = Some(sender);
+                        ReadSource::InFlight(receiver)
+                    }
+                    _ => ReadSource::Load,
+                },
+            };
+            (source, Self::on_stream_read(&mut state, tx_seq, index))
+        };
+        self.metrics.on_read(!matches!(source, ReadSource::Load));
+
+        if sequential {
+            self.prefetch(
+                tx_seq,
+                index + 1..num_segments.min(index + 1 + self.read_ahead),
+                load.clone(),
+            );
+        }
+
+        match source {
+            ReadSource::Prefetched(segment) => Ok(Some(segment)),
+            // the prefetch failed or found nothing if the waiter is dropped
+            ReadSource::InFlight(receiver) => match receiver.await {
+                Ok(segment) => Ok(Some(segment)),
+                Err(_) => load(index).await,
+            },
+            ReadSource::Load => load(index).await,
+        }
+    }
+
+    /// Records the read of a file, and returns whether it's sequential.
+    fn on_stream_read(state: &mut PrefetchState<T>, tx_seq: u64, index: usize) -> bool {
+        let previous = state
+            .streams
+            .iter()
+            .position(|(seq, _)| *seq == tx_seq)
+            .and_then(|pos| state.streams.remove(pos));
+        if state.streams.len() >= MAX_STREAMS {
+            state.streams.pop_front();
+        }
+        state.streams.push_back((tx_seq, index));
+        previous.map_or(false, |(_, last)| last + 1 == index)
+    }
+
+    fn prefetch<F, Fut, E>(self: &Arc<Self>, tx_seq: u64, indices: std::ops::Range<usize>, load: F)
+    where
+        F: Fn(usize) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<Option<T>, E>> + Send + 'static,
+    {
+        for index in indices {
+            let key = (tx_seq, index);
+            {
+                let mut state = self.state.lock();
+                if state.segments.contains_key(&key) || state.in_flight.contains_key(&key) {
+                    continue;
+                }
+                state.in_flight.insert(key, None);
+            }
+
+            let prefetcher = self.clone();
+            let load = load.clone();
+            tokio::spawn(async move {
+                let segment = load(index).await;
+                let mut state = prefetcher.state.lock();
+                let waiter = state.in_flight.remove(&key).flatten();
+                if let Ok(Some(segment)) = segment {
+                    // cached if the waiting read is cancelled in the meantime
+                    let segment = match waiter {
+                        Some(waiter) => match waiter.send(segment) {
+                            Ok(()) => return,
+                            Err(segment) => segment,
+                        },
+                        None => segment,
+                    };
+                    state.insert(key, segment, prefetcher.read_ahead * MAX_STREAMS);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentPrefetcher;
+    use parking_lot::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    const LOAD_LATENCY: Duration = Duration::from_millis(20);
+
+    /// Reads `indices` of a file of 16 segments with a client that takes a while to consume
+    /// each segment, and returns the average read latency and the number of segments loaded.
+    async fn simulate_reads(read_ahead: usize, indices: &[usize]) -> (Duration, usize) {
+        let prefetcher = Arc::new(SegmentPrefetcher::new(read_ahead));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let load = {
+            let loads = loads.clone();
+            move |index: usize| {
+                let loads = loads.clone();
+                async move {
+                    loads.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(LOAD_LATENCY).await;
+                    Ok::<_, ()>(Some(index))
+                }
+            }
+        };
+
+        let mut total = Duration::ZERO;
+        for index in indices {
+            let start = Instant::now();
+            let segment = prefetcher.read(0, *index, 16, load.clone()).await;
+            total += start.elapsed();
+            assert_eq!(segment, Ok(Some(*index)));
+            tokio::time::sleep(2 * LOAD_LATENCY).await;
+        }
+
+        (total / indices.len() as u32, loads.load(Ordering::Relaxed))
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_sequential_reads() {
+        let indices: Vec<usize> = (0..10).collect();
+        let (without_prefetch, loads) = simulate_reads(0, &indices).await;
+        assert_eq!(loads, 10);
+        assert!(without_prefetch >= LOAD_LATENCY);
+
+        let (with_prefetch, loads) = simulate_reads(2, &indices).await;
+        assert!(
+            with_prefetch < without_prefetch / 2,
+            "with prefetch {:?}, without prefetch {:?}",
+            with_prefetch,
+            without_prefetch
+        );
+        // the segments right after the last read are also prefetched
+        assert_eq!(loads, 12);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_back_to_back_reads() {
+        let prefetcher = Arc::new(SegmentPrefetcher::new(2));
+        let loads = Arc::new(Mutex::new(vec![0; 16]));
+        let load = {
+            let loads = loads.clone();
+            move |index: usize| {
+                let loads = loads.clone();
+                async move {
+                    loads.lock()[index] += 1;
+                    tokio::time::sleep(LOAD_LATENCY).await;
+                    Ok::<_, ()>(Some(index))
+                }
+            }
+        };
+
+        // a client that reads faster than the prefetch waits for the in-flight prefetches
+        for index in 0..10 {
+            let segment = prefetcher.read(0, index, 16, load.clone()).await;
+            assert_eq!(segment, Ok(Some(index)));
+        }
+        assert_eq!(loads.lock()[..10], [1; 10]);
+    }
+
+    #[tokio::test]
+    async fn test_no_prefetch_for_random_reads() {
+        let (_, loads) = simulate_reads(4, &[5, 1, 9, 3, 12]).await;
+        assert_eq!(loads, 5);
+    }
+}
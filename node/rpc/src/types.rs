@@ -1,17 +1,17 @@
-use crate::{error, zgs_grpc_proto};
+use crate::{error, zgs_grpc_proto, RPCConfig};
 use append_merkle::ZERO_HASHES;
 use ethereum_types::H256 as EthH256;
 use jsonrpsee::core::RpcResult;
 use merkle_light::hash::Algorithm;
 use merkle_light::merkle::{log2_pow2, next_pow2, MerkleTree};
 use merkle_tree::RawLeafSha3Algorithm;
-use network::Multiaddr;
+use network::{EnrExt, Multiaddr, NetworkGlobals};
 use serde::{Deserialize, Serialize};
 use shared_types::{
     compute_padded_chunk_size, compute_segment_size, DataRoot, FileProof, NetworkIdentity,
     Transaction, CHUNK_SIZE,
 };
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryFrom;
 use std::hash::Hasher;
 use std::net::IpAddr;
@@ -34,6 +34,67 @@ pub struct Status {
     pub log_sync_block: H256,
     pub next_tx_seq: u64,
     pub network_identity: NetworkIdentity,
+    pub miner: MinerStatus,
+    /// Unix timestamp in seconds that the miner re-evaluated the finalized data last time.
+    pub miner_last_reevaluation: Option<u64>,
+    pub proof_serving_mode: ProofServingMode,
+    pub sync_mode: SyncMode,
+    /// Whether new segment downloads are paused by the operator.
+    pub sync_paused: bool,
+}
+
+/// Files that segment proofs are served for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProofServingMode {
+    /// Proofs are served for any available segments, including files being synced.
+    Partial,
+    /// Proofs are served only for finalized files.
+    FinalizedOnly,
+}
+
+impl ProofServingMode {
+    pub fn from_config(config: &RPCConfig) -> Self {
+        if config.serve_proofs_only_for_finalized {
+            ProofServingMode::FinalizedOnly
+        } else {
+            ProofServingMode::Partial
+        }
+    }
+
+    pub fn allows(&self, finalized: bool) -> bool {
+        match self {
+            ProofServingMode::Partial => true,
+            ProofServingMode::FinalizedOnly => finalized,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncMode {
+    /// Files are synced from peers and served.
+    Full,
+    /// Files are never synced, and only the stored files are served.
+    ServeOnly,
+}
+
+impl SyncMode {
+    pub fn from_config(config: &sync::Config) -> Self {
+        if config.serve_only {
+            SyncMode::ServeOnly
+        } else {
+            SyncMode::Full
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MinerStatus {
+    Enabled,
+    /// Miner is not configured or disabled by config.
+    Disabled,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -58,6 +119,9 @@ pub struct FileInfo {
     pub uploaded_seg_num: usize,
     /// Whether file is pruned, in which case `finalized` will be `false`.
     pub pruned: bool,
+    /// Whether all the nodes of the file merkle tree are known from the stored data, so the
+    /// whole file is reconstructable.
+    pub complete: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -79,6 +143,47 @@ pub struct SegmentWithProof {
     pub file_size: usize,
 }
 
+/// A segment of a chunked upload, which is uploaded without the proof.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkedSegment {
+    /// File merkle root declared by the client, which is verified once all the segments are
+    /// uploaded.
+    pub root: DataRoot,
+    #[serde(with = "base64")]
+    /// With fixed data size except the last segment.
+    pub data: Vec<u8>,
+    /// Segment index.
+    pub index: usize,
+    /// File size
+    pub file_size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkedUploadStatus {
+    pub root: DataRoot,
+    pub file_size: usize,
+    pub total_segments: usize,
+    /// Segments to upload to resume the upload.
+    pub missing_segments: Vec<usize>,
+    /// Whether all the segments are uploaded and the file root is verified, after which the
+    /// file is finalized once the log entry is retrieved from blockchain.
+    pub verified: bool,
+}
+
+impl From<chunk_pool::ChunkedUploadStatus> for ChunkedUploadStatus {
+    fn from(value: chunk_pool::ChunkedUploadStatus) -> Self {
+        Self {
+            root: value.root,
+            file_size: value.file_size,
+            total_segments: value.total_segments,
+            missing_segments: value.missing_segments,
+            verified: value.verified,
+        }
+    }
+}
+
 /// Convert the proto DataRoot → your app’s DataRoot
 impl TryFrom<zgs_grpc_proto::DataRoot> for DataRoot {
     type Error = GrpcStatus;
@@ -338,6 +443,32 @@ impl SegmentWithProof {
     }
 }
 
+/// Identity and addresses of the local node.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalNodeInfo {
+    pub peer_id: String,
+    /// Addresses that libp2p is listening on.
+    pub listen_addresses: Vec<Multiaddr>,
+    /// Addresses advertised in the ENR with the peer id, which are updated with the addresses
+    /// observed by other peers, e.g. to add as the boot nodes of other nodes.
+    pub external_addresses: Vec<Multiaddr>,
+    /// ENR for discovery in base64.
+    pub enr: String,
+}
+
+impl From<&NetworkGlobals> for LocalNodeInfo {
+    fn from(value: &NetworkGlobals) -> Self {
+        let enr = value.local_enr();
+        Self {
+            peer_id: value.local_peer_id().to_base58(),
+            listen_addresses: value.listen_multiaddrs(),
+            external_addresses: enr.multiaddr_p2p_tcp(),
+            enr: enr.to_base64(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PeerInfo {
@@ -348,6 +479,8 @@ pub struct PeerInfo {
     pub is_trusted: bool,
     pub connection_direction: Option<String>, // Incoming/Outgoing
     pub enr: Option<String>,
+    /// Round trip time in milliseconds of the last PING answered by the peer.
+    pub ping_rtt_ms: Option<u64>,
 }
 
 impl From<&network::PeerInfo> for PeerInfo {
@@ -363,6 +496,43 @@ impl From<&network::PeerInfo> for PeerInfo {
                 network::ConnectionDirection::Outgoing => "Outgoing".into(),
             }),
             enr: value.enr().map(|x| x.to_base64()),
+            ping_rtt_ms: value.ping_rtt().map(|rtt| rtt.as_millis() as u64),
+        }
+    }
+}
+
+/// Score and penalties of a peer, to debug whether the peer is wrongly penalized.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerState {
+    pub score: f64,
+    /// Whether the peer is banned until the score decays into a tolerable threshold.
+    pub banned: bool,
+    /// Seconds since the peer was banned, if banned.
+    pub banned_secs: Option<u64>,
+    /// Number of the reported actions by type, e.g. `low_tolerance_error`.
+    pub fault_counts: BTreeMap<String, u64>,
+    /// Seconds since the last reported action, if any.
+    pub last_fault_secs: Option<u64>,
+    pub is_trusted: bool,
+    pub connection_status: PeerConnectionStatus,
+}
+
+impl From<&network::PeerInfo> for PeerState {
+    fn from(value: &network::PeerInfo) -> Self {
+        let banned_secs = match value.connection_status() {
+            network::PeerConnectionStatus::Banned { since } => Some(since.elapsed().as_secs()),
+            _ => None,
+        };
+
+        Self {
+            score: value.score().score(),
+            banned: value.score_is_banned() || value.is_banned(),
+            banned_secs,
+            fault_counts: value.fault_counts().clone(),
+            last_fault_secs: value.last_fault().map(|x| x.elapsed().as_secs()),
+            is_trusted: value.is_trusted(),
+            connection_status: value.connection_status().clone().into(),
         }
     }
 }
@@ -374,6 +544,26 @@ pub struct LocationInfo {
     pub shard_config: ShardConfig,
 }
 
+/// Result of comparing the root recomputed from the stored data with the expected one.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootVerification {
+    pub matched: bool,
+    pub computed_root: DataRoot,
+    /// Whether the file is being synced again to repair the local data.
+    pub repairing: bool,
+}
+
+impl RootVerification {
+    pub fn new(computed_root: DataRoot, expected_root: DataRoot) -> Self {
+        Self {
+            matched: computed_root == expected_root,
+            computed_root,
+            repairing: false,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Client {
@@ -453,7 +643,12 @@ mod base64 {
 
 #[cfg(test)]
 mod tests {
-    use super::Segment;
+    use super::{LocalNodeInfo, ProofServingMode, RootVerification, Segment};
+    use crate::RPCConfig;
+    use network::discv5::enr::{CombinedKey, EnrBuilder};
+    use network::{CombinedKeyExt, Keypair, Multiaddr, NetworkGlobals, PeerId};
+    use std::net::Ipv4Addr;
+    use storage::H256;
 
     #[test]
     fn test_segment_serde() {
@@ -464,4 +659,66 @@ mod tests {
         let seg2: Segment = serde_json::from_str("\"aGVsbG8sIHdvcmxk\"").unwrap();
         assert_eq!(String::from_utf8(seg2.0).unwrap().as_str(), "hello, world");
     }
+
+    #[test]
+    fn test_proof_serving_mode() {
+        let mut config = RPCConfig::default();
+        let mode = ProofServingMode::from_config(&config);
+        assert_eq!(mode, ProofServingMode::Partial);
+        assert!(mode.allows(true));
+        assert!(mode.allows(false));
+
+        config.serve_proofs_only_for_finalized = true;
+        let mode = ProofServingMode::from_config(&config);
+        assert_eq!(mode, ProofServingMode::FinalizedOnly);
+        assert!(mode.allows(true));
+        assert!(!mode.allows(false));
+    }
+
+    #[test]
+    fn test_root_verification() {
+        let root = H256::repeat_byte(1);
+        let verification = RootVerification::new(root, root);
+        assert!(verification.matched);
+        assert_eq!(verification.computed_root, root);
+        assert!(!verification.repairing);
+
+        let verification = RootVerification::new(root, H256::repeat_byte(2));
+        assert!(!verification.matched);
+        assert_eq!(verification.computed_root, root);
+    }
+
+    #[test]
+    fn test_local_node_info() {
+        let keypair = Keypair::generate_secp256k1();
+        let enr_key = CombinedKey::from_libp2p(&keypair).unwrap();
+        let enr = EnrBuilder::new("v4")
+            .ip(Ipv4Addr::new(1, 2, 3, 4).into())
+            .tcp(1234)
+            .build(&enr_key)
+            .unwrap();
+        let globals = NetworkGlobals::new(
+            enr,
+            1234,
+            1234,
+            vec![],
+            Default::default(),
+            Default::default(),
+        );
+        let listen_address: Multiaddr = "/ip4/0.0.0.0/tcp/1234".parse().unwrap();
+        globals
+            .listen_multiaddrs
+            .write()
+            .push(listen_address.clone());
+
+        let info = LocalNodeInfo::from(&globals);
+        let peer_id = PeerId::from(keypair.public());
+        assert_eq!(info.peer_id, peer_id.to_base58());
+        assert_eq!(info.listen_addresses, vec![listen_address]);
+        let external_address: Multiaddr = format!("/ip4/1.2.3.4/tcp/1234/p2p/{}", peer_id)
+            .parse()
+            .unwrap();
+        assert_eq!(info.external_addresses, vec![external_address]);
+        assert!(info.enr.starts_with("enr:"));
+    }
 }
@@ -1,7 +1,10 @@
-use crate::types::{FileInfo, Segment, SegmentWithProof, Status};
+use crate::types::{
+    ChunkedSegment, ChunkedUploadStatus, FileInfo, Segment, SegmentWithProof, Status,
+};
 use jsonrpsee::core::RpcResult;
 use jsonrpsee::proc_macros::rpc;
 use shared_types::{DataRoot, FlowProof, TxSeqOrRoot};
+use storage::log_store::FinalizedFile;
 use storage::{config::ShardConfig, H256};
 
 #[rpc(server, client, namespace = "zgs")]
@@ -29,6 +32,24 @@ pub trait Rpc {
         tx_seq: u64,
     ) -> RpcResult<()>;
 
+    /// Uploads a segment of a file without the proof, which is resumable by querying the
+    /// missing segments with `getChunkedUploadStatus`. Once all the segments are uploaded, the
+    /// file root is verified against the declared root, and the upload is discarded on mismatch.
+    /// The received segments are staged in the store, so the upload resumes after a restart too.
+    #[method(name = "uploadChunkedSegment")]
+    async fn upload_chunked_segment(
+        &self,
+        segment: ChunkedSegment,
+    ) -> RpcResult<ChunkedUploadStatus>;
+
+    /// Returns `None` if there is no chunked upload of `root` in progress, e.g. expired or
+    /// finalized already.
+    #[method(name = "getChunkedUploadStatus")]
+    async fn get_chunked_upload_status(
+        &self,
+        root: DataRoot,
+    ) -> RpcResult<Option<ChunkedUploadStatus>>;
+
     #[method(name = "downloadSegment")]
     async fn download_segment(
         &self,
@@ -68,6 +89,18 @@ pub trait Rpc {
     #[method(name = "checkFileFinalized")]
     async fn check_file_finalized(&self, tx_seq_or_root: TxSeqOrRoot) -> RpcResult<Option<bool>>;
 
+    /// Returns the highest tx seq up to which the data of all files is held, or `None` if the
+    /// first file is not finalized yet. The gap from `nextTxSeq` of `getStatus` is the backlog
+    /// of files to download.
+    #[method(name = "getHighestFinalizedSeq")]
+    async fn get_highest_finalized_seq(&self) -> RpcResult<Option<u64>>;
+
+    /// Push the files finalized from now on, which is only served by the WebSocket server.
+    /// Finalized files from `start_tx_seq` are replayed first if specified, e.g. to catch up
+    /// after reconnecting, but only the latest 1024 files are checked for replay.
+    #[subscription(name = "subscribeFinalized", unsubscribe = "unsubscribeFinalized", item = FinalizedFile)]
+    fn subscribe_finalized(&self, start_tx_seq: Option<u64>);
+
     #[method(name = "getFileInfo")]
     async fn get_file_info(
         &self,
@@ -93,4 +126,23 @@ pub trait Rpc {
 
     #[method(name = "getFlowContext")]
     async fn get_flow_context(&self) -> RpcResult<(H256, u64)>;
+
+    #[method(name = "getFlowRootAt")]
+    async fn get_flow_root_at(&self, tx_seq: u64) -> RpcResult<Option<H256>>;
+
+    /// Returns the `(offset, len)` in bytes of the file data that a leaf (chunk) covers, or
+    /// `None` if the tx does not exist.
+    #[method(name = "getLeafByteRange")]
+    async fn get_leaf_byte_range(
+        &self,
+        tx_seq: u64,
+        leaf_index: u64,
+    ) -> RpcResult<Option<(u64, u64)>>;
+
+    /// Returns the `(depth, root)` of the subtrees that the file tree is composed of, from
+    /// the largest one, or `None` if the tx does not exist. The trailing subtrees of only
+    /// padding are not included, and the file root is bagged from the subtrees with the zero
+    /// hashes as the padding.
+    #[method(name = "getSubtreeList")]
+    async fn get_subtree_list(&self, tx_seq: u64) -> RpcResult<Option<Vec<(usize, DataRoot)>>>;
 }
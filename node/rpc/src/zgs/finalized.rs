@@ -0,0 +1,104 @@
+//! Finalized files pushed to the subscribers of `zgs_subscribeFinalized`.
+//!
+//! Files are finalized out of the tx seq order, so the replayed files are de-duplicated by tx
+//! seq against the files finalized after subscribing. A subscriber that falls behind misses
+//! the oldest files buffered for it instead of stalling the finalization, and could reconnect
+//! with a start tx seq to replay them.
+
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::HashSet;
+use std::pin::Pin;
+use storage::error::Result;
+use storage::log_store::FinalizedFile;
+use storage_async::Store;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Maximum number of the latest files to check for replay.
+const MAX_REPLAY_FILES: u64 = 1024;
+
+pub type FinalizedFileStream = Pin<Box<dyn Stream<Item = FinalizedFile> + Send>>;
+
+/// Returns the finalized files from `start_tx_seq`, which checks the latest
+/// `MAX_REPLAY_FILES` files at most.
+pub async fn replay(store: &Store, start_tx_seq: u64) -> Result<Vec<FinalizedFile>> {
+    let next_tx_seq = store.get_store().next_tx_seq();
+    let start_tx_seq = start_tx_seq.max(next_tx_seq.saturating_sub(MAX_REPLAY_FILES));
+
+    let mut files = vec![];
+    for tx_seq in start_tx_seq..next_tx_seq {
+        if !store.check_tx_completed(tx_seq).await? {
+            continue;
+        }
+        if let Some(tx) = store.get_tx_by_seq_number(tx_seq).await? {
+            files.push(FinalizedFile {
+                tx_seq,
+                data_root: tx.data_merkle_root,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Streams the `replayed` files, and then the files received from `receiver`, which should
+/// be subscribed before replaying so that no file is missed in between.
+pub fn finalized_stream(
+    replayed: Vec<FinalizedFile>,
+    receiver: broadcast::Receiver<FinalizedFile>,
+) -> FinalizedFileStream {
+    let replayed_seqs: HashSet<u64> = replayed.iter().map(|file| file.tx_seq).collect();
+    let live = stream::unfold(
+        (receiver, replayed_seqs),
+        |(mut receiver, replayed_seqs)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(file) if replayed_seqs.contains(&file.tx_seq) => {}
+                    Ok(file) => return Some((file, (receiver, replayed_seqs))),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(%skipped, "Subscriber of finalized files falls behind");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    stream::iter(replayed).chain(live).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::finalized_stream;
+    use futures::StreamExt;
+    use shared_types::DataRoot;
+    use storage::log_store::FinalizedFile;
+    use tokio::sync::broadcast;
+
+    fn file(tx_seq: u64) -> FinalizedFile {
+        FinalizedFile {
+            tx_seq,
+            data_root: DataRoot::from_low_u64_be(tx_seq),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finalized_stream() {
+        let (sender, receiver) = broadcast::channel(2);
+        let mut stream = finalized_stream(vec![file(0), file(2)], receiver);
+
+        // replayed files are not delivered twice
+        sender.send(file(2)).unwrap();
+        sender.send(file(1)).unwrap();
+        assert_eq!(stream.next().await, Some(file(0)));
+        assert_eq!(stream.next().await, Some(file(2)));
+        assert_eq!(stream.next().await, Some(file(1)));
+
+        // a slow subscriber misses the oldest files without blocking the sender
+        for tx_seq in 3..6 {
+            sender.send(file(tx_seq)).unwrap();
+        }
+        assert_eq!(stream.next().await, Some(file(4)));
+        assert_eq!(stream.next().await, Some(file(5)));
+
+        drop(sender);
+        assert_eq!(stream.next().await, None);
+    }
+}
@@ -1,8 +1,13 @@
 use super::api::RpcServer;
-use crate::types::{FileInfo, Segment, SegmentWithProof, Status};
+use super::finalized;
+use crate::types::{
+    ChunkedSegment, ChunkedUploadStatus, FileInfo, MinerStatus, ProofServingMode, Segment,
+    SegmentWithProof, Status,
+};
 use crate::Context;
 use crate::{error, rpc_helper, SegmentIndexArray};
 use jsonrpsee::core::async_trait;
+use jsonrpsee::core::server::rpc_module::SubscriptionSink;
 use jsonrpsee::core::RpcResult;
 use shared_types::{DataRoot, FlowProof, Transaction, TxSeqOrRoot, CHUNK_SIZE};
 use storage::config::ShardConfig;
@@ -32,6 +37,18 @@ impl RpcServer for RpcServerImpl {
             log_sync_block: sync_progress.1,
             next_tx_seq,
             network_identity: self.ctx.network_globals.network_id(),
+            miner: match self.ctx.mine_service_sender {
+                Some(_) => MinerStatus::Enabled,
+                None => MinerStatus::Disabled,
+            },
+            miner_last_reevaluation: self
+                .ctx
+                .mine_state
+                .as_ref()
+                .and_then(|state| state.last_reevaluation_timestamp()),
+            proof_serving_mode: ProofServingMode::from_config(&self.ctx.config),
+            sync_mode: self.ctx.sync_mode,
+            sync_paused: sync::pause::is_paused(&self.ctx.log_store).await?,
         })
     }
 
@@ -91,6 +108,49 @@ impl RpcServer for RpcServerImpl {
         Ok(())
     }
 
+    async fn upload_chunked_segment(
+        &self,
+        segment: ChunkedSegment,
+    ) -> RpcResult<ChunkedUploadStatus> {
+        info!(root = %segment.root, index = %segment.index, "zgs_uploadChunkedSegment");
+
+        let maybe_tx = self
+            .ctx
+            .log_store
+            .get_tx_by_data_root(&segment.root, false)
+            .await?;
+        // the segments are staged in the store, so the file size is not bounded by the cache
+        if maybe_tx.is_some() {
+            rpc_helper::check_need_cache(&self.ctx, &maybe_tx, segment.file_size).await?;
+        }
+
+        let status = self
+            .ctx
+            .chunk_pool
+            .upload_chunked_segment(
+                segment.root,
+                segment.file_size,
+                self.ctx.config.chunks_per_segment,
+                segment.index,
+                segment.data,
+            )
+            .await?;
+        Ok(status.into())
+    }
+
+    async fn get_chunked_upload_status(
+        &self,
+        root: DataRoot,
+    ) -> RpcResult<Option<ChunkedUploadStatus>> {
+        debug!(%root, "zgs_getChunkedUploadStatus");
+        Ok(self
+            .ctx
+            .chunk_pool
+            .get_chunked_upload_status(&root)
+            .await?
+            .map(Into::into))
+    }
+
     async fn download_segment(
         &self,
         data_root: DataRoot,
@@ -212,6 +272,38 @@ impl RpcServer for RpcServerImpl {
         }
     }
 
+    async fn get_highest_finalized_seq(&self) -> RpcResult<Option<u64>> {
+        debug!("zgs_getHighestFinalizedSeq");
+        Ok(self.ctx.log_store.get_highest_finalized_seq().await?)
+    }
+
+    fn subscribe_finalized(
+        &self,
+        mut sink: SubscriptionSink,
+        start_tx_seq: Option<u64>,
+    ) -> RpcResult<()> {
+        info!(?start_tx_seq, "zgs_subscribeFinalized");
+
+        // subscribe before replay so that no file is missed in between
+        let receiver = self.ctx.log_store.subscribe_finalized_files();
+        let store = self.ctx.log_store.clone();
+        tokio::spawn(async move {
+            let replayed = match start_tx_seq {
+                Some(tx_seq) => finalized::replay(&store, tx_seq).await.unwrap_or_else(|e| {
+                    warn!(%e, "Failed to replay finalized files");
+                    vec![]
+                }),
+                None => vec![],
+            };
+            let closed = sink
+                .pipe_from_stream(finalized::finalized_stream(replayed, receiver))
+                .await;
+            sink.close(closed);
+        });
+
+        Ok(())
+    }
+
     async fn get_file_info(
         &self,
         data_root: DataRoot,
@@ -248,6 +340,9 @@ impl RpcServer for RpcServerImpl {
         sector_index: u64,
         flow_root: Option<DataRoot>,
     ) -> RpcResult<FlowProof> {
+        let tx_seq = self.get_tx_seq_by_sector(sector_index).await?;
+        self.check_proof_serving_mode(tx_seq)?;
+
         let proof = self
             .ctx
             .log_store
@@ -270,6 +365,32 @@ impl RpcServer for RpcServerImpl {
     async fn get_flow_context(&self) -> RpcResult<(H256, u64)> {
         Ok(self.ctx.log_store.get_context().await?)
     }
+
+    async fn get_flow_root_at(&self, tx_seq: u64) -> RpcResult<Option<H256>> {
+        debug!(%tx_seq, "zgs_getFlowRootAt");
+        Ok(self.ctx.log_store.get_flow_root_at(tx_seq).await?)
+    }
+
+    async fn get_leaf_byte_range(
+        &self,
+        tx_seq: u64,
+        leaf_index: u64,
+    ) -> RpcResult<Option<(u64, u64)>> {
+        debug!(%tx_seq, %leaf_index, "zgs_getLeafByteRange");
+
+        let tx = try_option!(self.ctx.log_store.get_tx_by_seq_number(tx_seq).await?);
+        match tx.leaf_byte_range(leaf_index) {
+            Ok(range) => Ok(Some(range)),
+            Err(e) => Err(error::invalid_params("leaf_index", e.to_string())),
+        }
+    }
+
+    async fn get_subtree_list(&self, tx_seq: u64) -> RpcResult<Option<Vec<(usize, DataRoot)>>> {
+        debug!(%tx_seq, "zgs_getSubtreeList");
+
+        let tx = try_option!(self.ctx.log_store.get_tx_by_seq_number(tx_seq).await?);
+        Ok(Some(tx.data_subtree_list()))
+    }
 }
 
 impl RpcServerImpl {
@@ -316,7 +437,7 @@ impl RpcServerImpl {
         let (finalized, pruned) = match self.ctx.log_store.get_store().get_tx_status(tx.seq)? {
             Some(TxStatus::Finalized) => (true, false),
             Some(TxStatus::Pruned) => (false, true),
-            None => (false, false),
+            Some(TxStatus::Skipped) | None => (false, false),
         };
 
         let (uploaded_seg_num, is_cached) = match self
@@ -341,12 +462,15 @@ impl RpcServerImpl {
             ),
         };
 
+        let complete = self.ctx.log_store.check_tx_merkle_complete(tx.seq).await?;
+
         Ok(FileInfo {
             tx,
             finalized,
             is_cached,
             uploaded_seg_num,
             pruned,
+            complete,
         })
     }
 
@@ -444,6 +568,49 @@ impl RpcServerImpl {
         Ok(Some(Segment(segment.data)))
     }
 
+    /// Fails if the proofs of the file `tx_seq` are not served by the proof serving mode, where
+    /// `None` is for the sectors of no file.
+    fn check_proof_serving_mode(&self, tx_seq: Option<u64>) -> RpcResult<()> {
+        let mode = ProofServingMode::from_config(&self.ctx.config);
+        if mode == ProofServingMode::Partial {
+            return Ok(());
+        }
+
+        let finalized = match tx_seq {
+            Some(tx_seq) => matches!(
+                self.ctx.log_store.get_store().get_tx_status(tx_seq)?,
+                Some(TxStatus::Finalized)
+            ),
+            None => false,
+        };
+        if !mode.allows(finalized) {
+            return Err(error::file_not_finalized());
+        }
+        Ok(())
+    }
+
+    /// Returns the seq of the tx that the flow sector belongs to, or `None` for the padding
+    /// sectors between the txs.
+    async fn get_tx_seq_by_sector(&self, sector_index: u64) -> RpcResult<Option<u64>> {
+        // search for the last tx that starts at or before the sector
+        let (mut low, mut high) = (0, self.ctx.log_store.get_store().next_tx_seq());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let tx = try_option!(self.ctx.log_store.get_tx_by_seq_number(mid).await?);
+            if tx.start_entry_index <= sector_index {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if low == 0 {
+            return Ok(None);
+        }
+
+        let tx = try_option!(self.ctx.log_store.get_tx_by_seq_number(low - 1).await?);
+        Ok((sector_index < tx.start_entry_index + tx.num_entries() as u64).then_some(tx.seq))
+    }
+
     async fn get_segment_with_proof_by_tx(
         &self,
         tx: Transaction,
@@ -458,30 +625,192 @@ impl RpcServerImpl {
             return Err(error::invalid_params("index", "index out of bound"));
         }
 
-        // calculate chunk start and end index
-        let start_index = index * chunks_per_segment;
-        let end_index = if index == num_segments - 1 {
-            // last segment without padding chunks by flow
-            start_index + last_segment_size / CHUNK_SIZE
-        } else {
-            start_index + chunks_per_segment
+        self.check_proof_serving_mode(Some(tx.seq))?;
+
+        let (store, tx_seq) = (self.ctx.log_store.clone(), tx.seq);
+        let load = move |index: usize| {
+            let store = store.clone();
+            async move {
+                // calculate chunk start and end index
+                let start_index = index * chunks_per_segment;
+                let end_index = if index == num_segments - 1 {
+                    // last segment without padding chunks by flow
+                    start_index + last_segment_size / CHUNK_SIZE
+                } else {
+                    start_index + chunks_per_segment
+                };
+                store
+                    .get_chunks_with_proof_by_tx_and_index_range(
+                        tx_seq,
+                        start_index,
+                        end_index,
+                        None,
+                    )
+                    .await
+            }
         };
-
         let segment = try_option!(
             self.ctx
-                .log_store
-                .get_chunks_with_proof_by_tx_and_index_range(tx.seq, start_index, end_index, None)
+                .segment_prefetcher
+                .read(tx_seq, index, num_segments, load)
                 .await?
         );
 
-        let proof = tx.compute_segment_proof(&segment, chunks_per_segment)?;
+        let (root, file_size) = (tx.data_merkle_root, tx.size as usize);
+        let (segment, proof) = self
+            .ctx
+            .hash_pool
+            .run(move || {
+                let proof = tx.compute_segment_proof(&segment, chunks_per_segment);
+                (segment, proof)
+            })
+            .await?;
+        let proof = proof?;
 
         Ok(Some(SegmentWithProof {
-            root: tx.data_merkle_root,
+            root,
             data: segment.chunks.data,
             index,
             proof,
-            file_size: tx.size as usize,
+            file_size,
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::api::RpcServer;
+    use super::RpcServerImpl;
+    use crate::types::SyncMode;
+    use crate::{Context, HashPool, RPCConfig, RateLimiter, SegmentPrefetcher};
+    use file_location_cache::FileLocationCache;
+    use network::{new_network_channel, NetworkGlobals};
+    use std::sync::Arc;
+    use storage::LogManager;
+    use storage_async::{ShardConfig, Store};
+    use sync::auto_prune::AutoPruner;
+    use sync::disk_watchdog::DiskWatchdog;
+    use sync::integrity_scan::{IntegrityScanMode, IntegrityScanner};
+    use sync::test_util::create_2_store;
+    use sync::worker_watchdog::WorkerWatchdog;
+    use task_executor::test_utils::TestRuntime;
+
+    async fn create_server(
+        runtime: &TestRuntime,
+        config: RPCConfig,
+        store: Arc<LogManager>,
+    ) -> RpcServerImpl {
+        let executor = runtime.task_executor.clone();
+        let store = Store::new(store, executor.clone());
+        let (network_send, _) = new_network_channel();
+        let (sync_send, _) = channel::Channel::unbounded("test_sync");
+        let (chunk_pool, _) = chunk_pool::unbounded(
+            chunk_pool::Config {
+                write_window_size: 4,
+                max_cached_chunks_all: 1024,
+                max_writings: 4,
+                expiration_time_secs: 300,
+                shard_config: ShardConfig::default(),
+            },
+            Arc::new(store.clone()),
+            network_send.clone(),
+        );
+        let auto_pruner = AutoPruner::new(0, store.clone(), Arc::new(DiskWatchdog::disabled()))
+            .await
+            .unwrap();
+
+        RpcServerImpl {
+            ctx: Context {
+                file_location_cache: Arc::new(FileLocationCache::default()),
+                network_globals: Arc::new(NetworkGlobals::new_test_globals()),
+                network_send,
+                sync_send,
+                sync_mode: SyncMode::Full,
+                worker_watchdog: Arc::new(WorkerWatchdog::disabled()),
+                auto_pruner: Arc::new(auto_pruner),
+                integrity_scanner: Arc::new(IntegrityScanner::new(
+                    IntegrityScanMode::Off,
+                    0,
+                    1,
+                    store.clone(),
+                )),
+                chunk_pool,
+                log_store: Arc::new(store),
+                shutdown_sender: executor.shutdown_sender(),
+                mine_service_sender: None,
+                mine_state: None,
+                rate_limiter: Arc::new(RateLimiter::new(&config.rate_limits())),
+                hash_pool: Arc::new(HashPool::new(1).unwrap()),
+                segment_prefetcher: Arc::new(SegmentPrefetcher::new(config.segment_prefetch)),
+                config,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_tx_seq_by_sector() {
+        let runtime = TestRuntime::default();
+        // tx 0 covers the sectors [64, 164), and tx 1 covers [256, 456)
+        let (_, peer_store, _, _) = create_2_store(vec![100, 200]);
+        let server = create_server(&runtime, RPCConfig::default(), peer_store).await;
+
+        for (sector_index, tx_seq) in [
+            (0, None),
+            (64, Some(0)),
+            (163, Some(0)),
+            (164, None),
+            (256, Some(1)),
+            (455, Some(1)),
+            (456, None),
+        ] {
+            assert_eq!(
+                server.get_tx_seq_by_sector(sector_index).await.unwrap(),
+                tx_seq
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proof_serving_mode_rpc() {
+        let runtime = TestRuntime::default();
+        // the txs are finalized in the peer store, but not in the local store
+        let (store, peer_store, _, _) = create_2_store(vec![100]);
+        let is_not_finalized =
+            |err: jsonrpsee::core::Error| format!("{:?}", err).contains("File not finalized");
+
+        let partial = create_server(&runtime, RPCConfig::default(), peer_store.clone()).await;
+        assert!(partial.get_sector_proof(64, None).await.is_ok());
+        assert!(partial
+            .download_segment_with_proof_by_tx_seq(0, 0)
+            .await
+            .unwrap()
+            .is_some());
+
+        let config = RPCConfig {
+            serve_proofs_only_for_finalized: true,
+            ..Default::default()
+        };
+        let finalized = create_server(&runtime, config, peer_store).await;
+        assert!(finalized.get_sector_proof(64, None).await.is_ok());
+        assert!(finalized
+            .download_segment_with_proof_by_tx_seq(0, 0)
+            .await
+            .unwrap()
+            .is_some());
+        // the padding sectors belong to no finalized file
+        assert!(is_not_finalized(
+            finalized.get_sector_proof(0, None).await.unwrap_err()
+        ));
+
+        let not_finalized = create_server(&runtime, config, store).await;
+        assert!(is_not_finalized(
+            not_finalized.get_sector_proof(64, None).await.unwrap_err()
+        ));
+        assert!(is_not_finalized(
+            not_finalized
+                .download_segment_with_proof_by_tx_seq(0, 0)
+                .await
+                .unwrap_err()
+        ));
+    }
+}
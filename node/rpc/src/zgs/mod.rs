@@ -1,4 +1,5 @@
 mod api;
+mod finalized;
 mod r#impl;
 
 pub use api::RpcClient;
@@ -2,7 +2,8 @@ mod proof;
 
 use anyhow::{anyhow, bail, Error};
 use append_merkle::{
-    AppendMerkleTree, OptionalHash, Proof as RawProof, RangeProof as RawRangeProof, Sha3Algorithm,
+    Algorithm as MerkleAlgorithm, AppendMerkleTree, OptionalHash, Proof as RawProof,
+    RangeProof as RawRangeProof, Sha3Algorithm,
 };
 use ethereum_types::{Address, H256, U256};
 use merkle_light::merkle::MerkleTree;
@@ -45,6 +46,22 @@ pub fn bytes_to_chunks(size_bytes: usize) -> usize {
     }
 }
 
+/// Returns the `(offset, len)` in bytes of the file data that the leaf (chunk) at
+/// `leaf_index` covers, where the last leaf is shorter if the file size is not aligned with
+/// `CHUNK_SIZE`. The padding leaves beyond the file are rejected.
+pub fn leaf_byte_range(file_size: u64, leaf_index: u64) -> anyhow::Result<(u64, u64)> {
+    let num_leaves = bytes_to_chunks(file_size as usize) as u64;
+    if leaf_index >= num_leaves {
+        bail!(
+            "leaf index out of bound: leaf_index={} total_leaves={}",
+            leaf_index,
+            num_leaves
+        );
+    }
+    let offset = leaf_index * CHUNK_SIZE as u64;
+    Ok((offset, (file_size - offset).min(CHUNK_SIZE as u64)))
+}
+
 pub fn compute_padded_chunk_size(size_bytes: usize) -> (usize, usize) {
     let chunk_len = bytes_to_chunks(size_bytes);
     let chunks_next_pow2 = next_pow2(chunk_len);
@@ -140,6 +157,30 @@ impl Transaction {
     pub fn start_entry_index(&self) -> u64 {
         self.start_entry_index
     }
+
+    /// Returns the `(depth, root)` of the subtrees in `merkle_nodes` that cover the file data,
+    /// without the trailing subtrees of only padding chunks if any. The padding chunks are
+    /// zeros, so the file root is bagged from the returned subtrees with the zero hashes as
+    /// the padding of the file tree.
+    pub fn data_subtree_list(&self) -> Vec<(usize, DataRoot)> {
+        let num_chunks = bytes_to_chunks(self.size as usize);
+        let mut start_chunk = 0;
+        self.merkle_nodes
+            .iter()
+            .take_while(|&&(depth, _)| {
+                let covers_data = start_chunk < num_chunks;
+                start_chunk += Self::num_entries_of_node(depth);
+                covers_data
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the `(offset, len)` in bytes of the file data that the leaf at `leaf_index`
+    /// covers, see `leaf_byte_range`.
+    pub fn leaf_byte_range(&self, leaf_index: u64) -> anyhow::Result<(u64, u64)> {
+        leaf_byte_range(self.size, leaf_index)
+    }
 }
 
 pub struct ChunkWithProof {
@@ -154,6 +195,80 @@ pub struct ChunkArrayWithProof {
     pub proof: FlowRangeProof,
 }
 
+impl ChunkArrayWithProof {
+    /// Returns whether the chunks are the data of the file of `tx` at `chunks.start_index`,
+    /// checked against the subtree roots of the file rather than the flow root, so that the
+    /// data of another file is told apart from a malformed response.
+    ///
+    /// A subtree within the chunks is recomputed from the data, and a subtree that covers the
+    /// chunks partially is compared with the node on the path of the range proof, of which
+    /// the consistency with the data is validated first.
+    pub fn is_file_data(&self, tx: &Transaction) -> anyhow::Result<bool> {
+        if self.chunks.data.is_empty() || self.chunks.data.len() % CHUNK_SIZE != 0 {
+            bail!("Invalid chunk data length: {}", self.chunks.data.len());
+        }
+        let leaves: Vec<H256> = self
+            .chunks
+            .data
+            .chunks_exact(CHUNK_SIZE)
+            .map(<Sha3Algorithm as MerkleAlgorithm<H256>>::leaf)
+            .collect();
+        let start = (tx.start_entry_index + self.chunks.start_index) as usize;
+        let end = start + leaves.len();
+
+        let mut path_validated = false;
+        let mut subtree_start = tx.start_entry_index as usize;
+        for &(depth, root) in &tx.merkle_nodes {
+            let subtree_end = subtree_start + Transaction::num_entries_of_node(depth);
+            let (from, to) = (subtree_start, subtree_end);
+            subtree_start = subtree_end;
+            if to <= start {
+                continue;
+            }
+            if from >= end {
+                break;
+            }
+
+            let computed = if from >= start && to <= end {
+                subtree_root(&leaves[from - start..to - start])
+            } else {
+                if !path_validated {
+                    self.proof
+                        .validate::<Sha3Algorithm>(&leaves, self.proof.left_proof.position())?;
+                    path_validated = true;
+                }
+                let path = if from < start {
+                    &self.proof.left_proof
+                } else {
+                    &self.proof.right_proof
+                };
+                match path.derived_nodes::<Sha3Algorithm>().get(depth - 1) {
+                    Some(node) => *node,
+                    None => return Ok(false),
+                }
+            };
+
+            if computed != root {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Returns the root of the perfect tree of `leaves`, of which the number is a power of 2.
+fn subtree_root(leaves: &[H256]) -> H256 {
+    let mut layer = leaves.to_vec();
+    while layer.len() > 1 {
+        layer = layer
+            .chunks_exact(2)
+            .map(|pair| <Sha3Algorithm as MerkleAlgorithm<H256>>::parent(&pair[0], &pair[1]))
+            .collect();
+    }
+    layer[0]
+}
+
 #[derive(Clone, Eq, PartialEq, DeriveEncode, DeriveDecode)]
 pub struct ChunkArray {
     // The length is exactly a multiple of `CHUNK_SIZE`
@@ -430,6 +545,30 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_leaf_byte_range() {
+        let chunk = CHUNK_SIZE as u64;
+        assert_eq!(leaf_byte_range(1, 0).unwrap(), (0, 1));
+        assert!(leaf_byte_range(1, 1).is_err());
+
+        // aligned
+        assert_eq!(leaf_byte_range(chunk, 0).unwrap(), (0, chunk));
+        assert_eq!(leaf_byte_range(4 * chunk, 3).unwrap(), (3 * chunk, chunk));
+        assert!(leaf_byte_range(4 * chunk, 4).is_err());
+
+        // ragged last leaf
+        let size = 4 * chunk + 10;
+        assert_eq!(leaf_byte_range(size, 3).unwrap(), (3 * chunk, chunk));
+        assert_eq!(leaf_byte_range(size, 4).unwrap(), (4 * chunk, 10));
+        assert!(leaf_byte_range(size, 5).is_err());
+
+        let ranges: Vec<(u64, u64)> = (0..5).map(|i| leaf_byte_range(size, i).unwrap()).collect();
+        assert_eq!(ranges.iter().map(|(_, len)| len).sum::<u64>(), size);
+
+        // empty file
+        assert!(leaf_byte_range(0, 0).is_err());
+    }
+
     #[test]
     fn test_tx_seq_or_root_serde() {
         // serialize tx seq as number
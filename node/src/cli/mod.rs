@@ -1,4 +1,4 @@
-use clap::{arg, command, Command};
+use clap::{arg, command, value_parser, Command};
 
 pub fn cli_app() -> Command {
     command!()
@@ -10,6 +10,34 @@ pub fn cli_app() -> Command {
         )
         .arg(arg!(--"db-max-num-chunks" [NUM] "Sets the max number of chunks to store in db (Default: None)"))
         .arg(arg!(--"network-enr-address" [URL] "Sets the network ENR address (Default: None)"))
+        .subcommand(
+            Command::new("export-state")
+                .about("Exports the state of a stopped node to a portable file")
+                .arg(arg!(<FILE> "Path of the exported state file")),
+        )
+        .subcommand(
+            Command::new("import-state")
+                .about("Imports the exported state into the empty db of a node")
+                .arg(arg!(<FILE> "Path of the exported state file")),
+        )
+        .subcommand(
+            Command::new("download-file")
+                .about("Extracts a stored file of a stopped node to a local path")
+                .arg(arg!(--"tx-seq" <SEQ> "Tx seq of the file").value_parser(value_parser!(u64)))
+                .arg(arg!(--out <FILE> "Path of the extracted file")),
+        )
+        .subcommand(
+            Command::new("export-proof-manifest")
+                .about("Exports the proof manifest of a stored file of a stopped node")
+                .arg(arg!(--"tx-seq" <SEQ> "Tx seq of the file").value_parser(value_parser!(u64)))
+                .arg(arg!(--out <FILE> "Path of the manifest")),
+        )
+        .subcommand(
+            Command::new("verify-proof-manifest")
+                .about("Verifies a file against its proof manifest without a node")
+                .arg(arg!(--manifest <FILE> "Path of the manifest"))
+                .arg(arg!(--file <FILE> "Path of the file to verify")),
+        )
         .allow_external_subcommands(true)
         .version(zgs_version::VERSION)
 }
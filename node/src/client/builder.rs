@@ -2,19 +2,25 @@ use super::{Client, RuntimeContext};
 use chunk_pool::{Config as ChunkPoolConfig, MemoryChunkPool};
 use file_location_cache::FileLocationCache;
 use log_entry_sync::{LogSyncConfig, LogSyncEvent, LogSyncManager};
-use miner::{MineService, MinerConfig, MinerMessage, ShardConfig};
+use miner::{MineService, MinerConfig, MinerMessage, MinerState, ShardConfig};
 use network::{
     self, new_network_channel, Keypair, NetworkConfig, NetworkGlobals, NetworkReceiver,
-    NetworkSender, RequestId, Service as LibP2PService,
+    NetworkSender, PeerId, RequestId, Service as LibP2PService,
 };
 use pruner::{get_shard_config, Pruner, PrunerConfig, PrunerMessage};
 use router::RouterService;
 use rpc::RPCConfig;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::Arc;
 use storage::log_store::log_manager::LogConfig;
 use storage::log_store::Store;
 use storage::{LogManager, StorageConfig};
-use sync::{SyncSender, SyncService};
+use sync::auto_prune::AutoPruner;
+use sync::disk_watchdog::{DiskWatchdog, FsSpaceQuery};
+use sync::integrity_scan::IntegrityScanner;
+use sync::worker_watchdog::WorkerWatchdog;
+use sync::{DefaultPeerSelector, SyncSender, SyncService};
 use tokio::sync::{broadcast, mpsc, oneshot};
 
 macro_rules! require {
@@ -37,10 +43,15 @@ struct NetworkComponents {
 
 struct SyncComponents {
     send: SyncSender,
+    mode: rpc::types::SyncMode,
+    worker_watchdog: Arc<WorkerWatchdog>,
+    auto_pruner: Arc<AutoPruner>,
+    integrity_scanner: Arc<IntegrityScanner>,
 }
 
 struct MinerComponents {
     send: broadcast::Sender<MinerMessage>,
+    state: Arc<MinerState>,
 }
 
 struct LogSyncComponents {
@@ -169,7 +180,12 @@ impl ClientBuilder {
         Ok(self)
     }
 
-    pub async fn with_sync(mut self, config: sync::Config) -> Result<Self, String> {
+    pub async fn with_sync(
+        mut self,
+        config: sync::Config,
+        trusted_peers: HashSet<PeerId>,
+        db_dir: PathBuf,
+    ) -> Result<Self, String> {
         let executor = require!("sync", self, runtime_context).clone().executor;
         let store = require!("sync", self, store).clone();
         let file_location_cache = require!("sync", self, file_location_cache).clone();
@@ -182,6 +198,35 @@ impl ClientBuilder {
             .catch_up_end_recv
             .take()
             .ok_or("sync requires a catch_up_end_recv")?;
+        let disk_watchdog = Arc::new(DiskWatchdog::new(
+            config.min_free_disk_space_bytes,
+            Box::new(FsSpaceQuery::new(db_dir)),
+        ));
+        let worker_watchdog = Arc::new(WorkerWatchdog::new(
+            config.worker_stall_timeout,
+            config.max_worker_restarts,
+        ));
+        let async_store = require!("sync", self, async_store).as_ref().clone();
+        let integrity_scanner = Arc::new(IntegrityScanner::new(
+            config.integrity_scan_mode,
+            config.integrity_scan_sample_size,
+            config.integrity_scan_concurrency,
+            async_store.clone(),
+        ));
+        // Scan before syncing or serving files, and repair the corrupt files once the sync
+        // service is started.
+        let corrupt_tx_seqs = integrity_scanner
+            .scan()
+            .await
+            .map_err(|e| format!("Failed to scan the integrity of files: {:?}", e))?;
+        let auto_pruner = Arc::new(
+            AutoPruner::new(config.max_storage_bytes, async_store, disk_watchdog.clone())
+                .await
+                .map_err(|e| format!("Failed to load auto prune progress: {:?}", e))?,
+        );
+        auto_pruner
+            .clone()
+            .spawn(&executor, config.disk_space_check_interval);
 
         let send = SyncService::spawn_with_config(
             config,
@@ -189,12 +234,23 @@ impl ClientBuilder {
             network_send,
             store,
             file_location_cache,
+            disk_watchdog,
+            worker_watchdog.clone(),
+            trusted_peers,
+            Arc::new(DefaultPeerSelector),
             event_recv,
             catch_up_end_recv,
         )
         .await
         .map_err(|e| format!("Failed to start sync service: {:?}", e))?;
-        self.sync = Some(SyncComponents { send });
+        integrity_scanner.repair(&corrupt_tx_seqs, &send).await;
+        self.sync = Some(SyncComponents {
+            send,
+            mode: rpc::types::SyncMode::from_config(&config),
+            worker_watchdog,
+            auto_pruner,
+            integrity_scanner,
+        });
 
         Ok(self)
     }
@@ -205,8 +261,8 @@ impl ClientBuilder {
             let network_send = require!("miner", self, network).send.clone();
             let store = require!("miner", self, async_store).clone();
 
-            let send = MineService::spawn(executor, network_send, config, store).await?;
-            self.miner = Some(MinerComponents { send });
+            let (send, state) = MineService::spawn(executor, network_send, config, store).await?;
+            self.miner = Some(MinerComponents { send, state });
         }
 
         Ok(self)
@@ -279,10 +335,18 @@ impl ClientBuilder {
             return Ok(self);
         }
 
+        rpc_config.validate()?;
+        info!(
+            chunks_per_segment = rpc_config.chunks_per_segment,
+            max_request_body_size = rpc_config.max_request_body_size,
+            "Validated segment parameters"
+        );
+
         let executor = require!("rpc", self, runtime_context).clone().executor;
         let async_store = require!("rpc", self, async_store).clone();
         let network_send = require!("rpc", self, network).send.clone();
         let mine_send = self.miner.as_ref().map(|x| x.send.clone());
+        let mine_state = self.miner.as_ref().map(|x| x.state.clone());
         let file_location_cache = require!("rpc", self, file_location_cache).clone();
         let chunk_pool = require!("rpc", self, chunk_pool).chunk_pool.clone();
 
@@ -292,10 +356,18 @@ impl ClientBuilder {
             network_globals: require!("rpc", self, network).globals.clone(),
             network_send,
             sync_send: require!("rpc", self, sync).send.clone(),
+            sync_mode: require!("rpc", self, sync).mode,
+            worker_watchdog: require!("rpc", self, sync).worker_watchdog.clone(),
+            auto_pruner: require!("rpc", self, sync).auto_pruner.clone(),
+            integrity_scanner: require!("rpc", self, sync).integrity_scanner.clone(),
             log_store: async_store,
             chunk_pool,
             shutdown_sender: executor.shutdown_sender(),
             mine_service_sender: mine_send,
+            mine_state,
+            rate_limiter: Arc::new(rpc::RateLimiter::new(&rpc_config.rate_limits())),
+            hash_pool: Arc::new(rpc::HashPool::new(rpc_config.hash_pool_threads)?),
+            segment_prefetcher: Arc::new(rpc::SegmentPrefetcher::new(rpc_config.segment_prefetch)),
         };
 
         let (rpc_handle, maybe_admin_rpc_handle) = rpc::run_server(ctx.clone())
@@ -307,6 +379,13 @@ impl ClientBuilder {
             executor.spawn(admin_rpc_handle, "rpc_admin");
         }
 
+        let maybe_ws_rpc_handle = rpc::run_ws_server(ctx.clone())
+            .await
+            .map_err(|e| format!("Unable to start WebSocket RPC server: {:?}", e))?;
+        if let Some(ws_rpc_handle) = maybe_ws_rpc_handle {
+            executor.spawn(ws_rpc_handle, "rpc_ws");
+        }
+
         executor.spawn(
             async move {
                 rpc::run_grpc_server(ctx.clone())
@@ -342,12 +421,23 @@ impl ClientBuilder {
         Ok(self)
     }
 
-    pub async fn with_log_sync(mut self, config: LogSyncConfig) -> Result<Self, String> {
+    /// Syncs the log entries from the blockchain, unless disabled, e.g. in the serve-only mode,
+    /// in which case no log sync event is ever sent.
+    pub async fn with_log_sync(
+        mut self,
+        config: LogSyncConfig,
+        enabled: bool,
+    ) -> Result<Self, String> {
         let executor = require!("log_sync", self, runtime_context).clone().executor;
         let store = require!("log_sync", self, store).clone();
-        let (send, catch_up_end_recv) = LogSyncManager::spawn(config, executor, store)
-            .await
-            .map_err(|e| e.to_string())?;
+        let (send, catch_up_end_recv) = if enabled {
+            LogSyncManager::spawn(config, executor, store)
+                .await
+                .map_err(|e| e.to_string())?
+        } else {
+            info!("Log sync disabled");
+            (broadcast::channel(1).0, oneshot::channel().1)
+        };
 
         self.log_sync = Some(LogSyncComponents {
             send,
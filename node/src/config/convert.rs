@@ -1,13 +1,14 @@
 #![allow(clippy::field_reassign_with_default)]
 
 use crate::ZgsConfig;
-use ethereum_types::H256;
+use ethereum_types::{Address, H256};
 use ethers::prelude::{Http, Middleware, Provider};
 use log_entry_sync::{CacheConfig, ContractAddress, LogSyncConfig};
 use miner::MinerConfig;
-use network::{EnrExt, NetworkConfig};
+use network::{EnrExt, NetworkConfig, PeerId};
 use pruner::PrunerConfig;
 use shared_types::{NetworkIdentity, ProtocolVersion};
+use std::collections::HashSet;
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -101,6 +102,21 @@ impl ZgsConfig {
         network_config.discv5_config.enable_packet_filter = !self.discv5_disable_packet_filter;
         network_config.discv5_config.ip_limit = !self.discv5_disable_ip_limit;
 
+        network_config.blacklist_peers = self
+            .network_blacklist_peers
+            .iter()
+            .map(|peer_id| peer_id.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Unable to parse network_blacklist_peers: {:?}", e))?;
+
+        network_config.whitelist_peers = self
+            .network_whitelist_peers
+            .iter()
+            .map(|peer_id| peer_id.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Unable to parse network_whitelist_peers: {:?}", e))?;
+        network_config.whitelist_mode = self.network_whitelist_mode;
+
         network_config.target_peers = self.network_target_peers;
         network_config.private = self.network_private;
 
@@ -115,12 +131,28 @@ impl ZgsConfig {
     pub fn storage_config(&self) -> Result<StorageConfig, String> {
         let mut log_config = LogConfig::default();
         log_config.flow.merkle_node_cache_capacity = self.merkle_node_cache_capacity;
+        log_config.flow.merkle_pinned_layers = self.merkle_pinned_layers;
+        log_config.flow.merkle_flush_completed_subtrees = self.merkle_flush_completed_subtrees;
+        log_config.flow.merkle_wal = self.merkle_wal;
+        log_config.data_db_shards = self.db_data_shards;
+        log_config.data_db_shard_size = self.db_data_shard_size;
         Ok(StorageConfig {
             db_dir: self.db_dir.clone().into(),
             log_config,
         })
     }
 
+    pub fn sync_trusted_peers(&self) -> Result<HashSet<PeerId>, String> {
+        self.sync_trusted_bootstrap_peers
+            .iter()
+            .map(|peer_id| {
+                peer_id
+                    .parse::<PeerId>()
+                    .map_err(|e| format!("Unable to parse sync_trusted_bootstrap_peers: {:?}", e))
+            })
+            .collect()
+    }
+
     pub fn log_sync_config(&self) -> Result<LogSyncConfig, String> {
         let contract_address = self
             .log_contract_address
@@ -132,6 +164,21 @@ impl ZgsConfig {
             // This should be enough if we have about one Zgs tx per block.
             tx_seq_ttl: self.cache_tx_seq_ttl,
         };
+        let uploader_allowlist = self
+            .log_sync_uploader_allowlist
+            .iter()
+            .map(|address| {
+                address
+                    .parse::<Address>()
+                    .map_err(|e| format!("Unable to parse log_sync_uploader_allowlist: {:?}", e))
+            })
+            .collect::<Result<_, _>>()?;
+        if self.min_log_page_size == 0 || self.min_log_page_size > self.max_log_page_size {
+            return Err(format!(
+                "Invalid log page size range [{}, {}]",
+                self.min_log_page_size, self.max_log_page_size
+            ));
+        }
         Ok(LogSyncConfig::new(
             self.blockchain_rpc_endpoint.clone(),
             contract_address,
@@ -139,6 +186,9 @@ impl ZgsConfig {
             self.confirmation_block_count,
             cache_config,
             self.log_page_size,
+            self.min_log_page_size,
+            self.max_log_page_size,
+            Duration::from_millis(self.log_page_target_latency_ms),
             self.rate_limit_retries,
             self.timeout_retries,
             self.initial_backoff,
@@ -148,10 +198,18 @@ impl ZgsConfig {
             self.watch_loop_wait_time_ms,
             self.force_log_sync_from_start_block_number,
             Duration::from_secs(self.blockchain_rpc_timeout_secs),
+            uploader_allowlist,
+            self.log_sync_skip_empty_files,
+            self.log_sync_max_file_size,
         ))
     }
 
     pub fn mine_config(&self) -> Result<Option<MinerConfig>, String> {
+        if self.miner_disabled {
+            info!("Miner is disabled, run as a read-only replica");
+            return Ok(None);
+        }
+
         let flow_address = self
             .log_contract_address
             .parse::<ContractAddress>()
@@ -20,6 +20,9 @@ build_config! {
     (network_private, (bool), false)
     (network_disable_discovery, (bool), false)
     (network_find_chunks_enabled, (bool), false)
+    (network_blacklist_peers, (Vec<String>), vec![])
+    (network_whitelist_peers, (Vec<String>), vec![])
+    (network_whitelist_mode, (bool), false)
 
     // discv5
     (discv5_request_timeout_secs, (u64), 5)
@@ -38,6 +41,9 @@ build_config! {
     (force_log_sync_from_start_block_number, (bool), false)
     (confirmation_block_count, (u64), 3)
     (log_page_size, (u64), 999)
+    (min_log_page_size, (u64), 10)
+    (max_log_page_size, (u64), 999)
+    (log_page_target_latency_ms, (u64), 5000)
     (max_cache_data_size, (usize), 100 * 1024 * 1024) // 100 MB
     (cache_tx_seq_ttl, (usize), 500)
 
@@ -51,6 +57,12 @@ build_config! {
     (watch_loop_wait_time_ms, (u64), 500)
 
     (blockchain_rpc_timeout_secs, (u64), 120)
+    (log_sync_uploader_allowlist, (Vec<String>), vec![])
+    (log_sync_skip_empty_files, (bool), false)
+    (log_sync_max_file_size, (u64), 0)
+
+    // file sync
+    (sync_trusted_bootstrap_peers, (Vec<String>), vec![])
 
     // chunk pool
     (chunk_pool_write_window_size, (usize), 4)
@@ -65,15 +77,26 @@ build_config! {
     (prune_batch_size, (usize), 16 * 1024)
     (prune_batch_wait_time_ms, (u64), 1000)
     (merkle_node_cache_capacity, (usize), 32 * 1024 * 1024)
+    (merkle_pinned_layers, (usize), 10)
+    (merkle_flush_completed_subtrees, (bool), false)
+    (merkle_wal, (bool), false)
+    (db_data_shards, (usize), 0)
+    (db_data_shard_size, (u64), 1024)
 
     // misc
     (log_config_file, (String), "log_config".to_string())
     (log_directory, (String), "log".to_string())
 
+    // statsd, only available with the `statsd` feature
+    (statsd_address, (Option<String>), None)
+    (statsd_prefix, (String), "zgs".to_string())
+    (statsd_flush_interval_secs, (u64), 10)
+
     // mine
     (mine_contract_address, (String), "".to_string())
     (miner_id, (Option<String>), None)
     (miner_key, (Option<String>), None)
+    (miner_disabled, (bool), false)
     (miner_cpu_percentage, (u64), 100)
     (mine_iter_batch_size, (usize), 100)
     (reward_contract_address, (String), "".to_string())
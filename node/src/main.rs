@@ -5,10 +5,16 @@ mod cli;
 mod client;
 mod config;
 mod log;
+#[cfg(feature = "statsd")]
+mod statsd;
 
 use crate::config::ZgsConfig;
 use client::{Client, ClientBuilder, RuntimeContext};
 use std::error::Error;
+use std::path::Path;
+use storage::log_store::proof_manifest::{verify_proof_manifest, ProofManifest};
+use storage::log_store::LogStoreRead;
+use storage::LogManager;
 
 async fn start_node(context: RuntimeContext, config: ZgsConfig) -> Result<Client, String> {
     let network_config = config.network_config().await?;
@@ -19,20 +25,26 @@ async fn start_node(context: RuntimeContext, config: ZgsConfig) -> Result<Client
     let router_config = config.router_config(&network_config)?;
     let pruner_config = config.pruner_config()?;
     let shard_config = config.shard_config()?;
+    let sync_trusted_peers = config.sync_trusted_peers()?;
+    start_statsd_exporter(&config)?;
 
     ClientBuilder::default()
         .with_runtime_context(context)
         .with_rocksdb_store(&storage_config)?
         .with_shard(shard_config)
         .await?
-        .with_log_sync(log_sync_config)
+        .with_log_sync(log_sync_config, config.sync.log_sync_enabled())
         .await?
         .with_file_location_cache(config.file_location_cache)
         .with_network(network_config)
         .await?
         .with_chunk_pool(chunk_pool_config)
         .await?
-        .with_sync(config.sync)
+        .with_sync(
+            config.sync,
+            sync_trusted_peers,
+            storage_config.db_dir.clone(),
+        )
         .await?
         .with_miner(miner_config)
         .await?
@@ -44,6 +56,107 @@ async fn start_node(context: RuntimeContext, config: ZgsConfig) -> Result<Client
         .build()
 }
 
+#[cfg(feature = "statsd")]
+fn start_statsd_exporter(config: &ZgsConfig) -> Result<(), String> {
+    if let Some(address) = &config.statsd_address {
+        let exporter = statsd::StatsdExporter::new(address, &config.statsd_prefix)
+            .map_err(|e| format!("Failed to create statsd exporter: {:?}", e))?;
+        exporter.spawn(std::time::Duration::from_secs(
+            config.statsd_flush_interval_secs,
+        ));
+        info!(%address, "Statsd exporter started");
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "statsd"))]
+fn start_statsd_exporter(config: &ZgsConfig) -> Result<(), String> {
+    if config.statsd_address.is_some() {
+        warn!("statsd_address is ignored since the node is built without the statsd feature");
+    }
+    Ok(())
+}
+
+/// Extracts a stored file offline, since the db is locked by a running node.
+fn run_download_command(config: &ZgsConfig, tx_seq: u64, path: &str) -> Result<(), Box<dyn Error>> {
+    let storage_config = config.storage_config()?;
+    let flow_path = storage_config.db_dir.join("flow_db");
+    let data_path = storage_config.db_dir.join("data_db");
+
+    let info = LogManager::rocksdb(storage_config.log_config, flow_path, data_path)
+        .and_then(|store| store.download_file(tx_seq, Path::new(path)))
+        .map_err(|e| format!("Failed to download file: {:?}", e))?;
+
+    println!(
+        "download-file completed: tx_seq={} root={:?} size={}",
+        info.tx_seq, info.data_root, info.size_bytes
+    );
+
+    Ok(())
+}
+
+/// Exports the proof manifest of a stored file offline, since the db is locked by a running node.
+fn run_export_manifest_command(
+    config: &ZgsConfig,
+    tx_seq: u64,
+    path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let storage_config = config.storage_config()?;
+    let flow_path = storage_config.db_dir.join("flow_db");
+    let data_path = storage_config.db_dir.join("data_db");
+
+    let manifest = LogManager::rocksdb(storage_config.log_config, flow_path, data_path)
+        .and_then(|store| store.export_proof_manifest(tx_seq))
+        .map_err(|e| format!("Failed to export proof manifest: {:?}", e))?;
+    std::fs::write(path, serde_json::to_vec_pretty(&manifest)?)?;
+
+    println!(
+        "export-proof-manifest completed: tx_seq={} root={:?} segments={}",
+        manifest.tx_seq,
+        manifest.data_root,
+        manifest.segment_roots.len()
+    );
+
+    Ok(())
+}
+
+/// Verifies a file against its proof manifest, which requires neither the db nor the config.
+fn run_verify_manifest_command(manifest_path: &str, path: &str) -> Result<(), Box<dyn Error>> {
+    let manifest: ProofManifest = serde_json::from_slice(&std::fs::read(manifest_path)?)?;
+    verify_proof_manifest(&manifest, &std::fs::read(path)?)
+        .map_err(|e| format!("Failed to verify file: {:?}", e))?;
+
+    println!(
+        "verify-proof-manifest completed: root={:?} size={}",
+        manifest.data_root, manifest.size_bytes
+    );
+
+    Ok(())
+}
+
+/// Exports or imports the node state offline, since the db is locked by a running node.
+fn run_state_command(config: &ZgsConfig, command: &str, path: &str) -> Result<(), Box<dyn Error>> {
+    let storage_config = config.storage_config()?;
+    let flow_path = storage_config.db_dir.join("flow_db");
+    let data_path = storage_config.db_dir.join("data_db");
+
+    let info = match command {
+        "export-state" => LogManager::rocksdb(storage_config.log_config, flow_path, data_path)
+            .and_then(|store| store.export_state(Path::new(path)))
+            .map_err(|e| format!("Failed to export state: {:?}", e))?,
+        "import-state" => LogManager::import_rocksdb_state(flow_path, data_path, path)
+            .map_err(|e| format!("Failed to import state: {:?}", e))?,
+        _ => return Err(format!("Unknown command: {}", command).into()),
+    };
+
+    println!(
+        "{} completed: version={} records={} size={}",
+        command, info.version, info.num_records, info.size_bytes
+    );
+
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     // Only allow 64-bit targets for compilation, since there are many
     // type conversions between `usize` and `u64`, or even use `usize`
@@ -64,7 +177,28 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // CLI, config, and logs
     let matches = cli::cli_app().get_matches();
+    if let Some(("verify-proof-manifest", sub_matches)) = matches.subcommand() {
+        let manifest_path = sub_matches.get_one::<String>("manifest").expect("required");
+        let path = sub_matches.get_one::<String>("file").expect("required");
+        return run_verify_manifest_command(manifest_path, path);
+    }
     let config = ZgsConfig::parse(&matches)?;
+
+    if let Some(("download-file", sub_matches)) = matches.subcommand() {
+        let tx_seq = *sub_matches.get_one::<u64>("tx-seq").expect("required");
+        let path = sub_matches.get_one::<String>("out").expect("required");
+        return run_download_command(&config, tx_seq, path);
+    }
+    if let Some(("export-proof-manifest", sub_matches)) = matches.subcommand() {
+        let tx_seq = *sub_matches.get_one::<u64>("tx-seq").expect("required");
+        let path = sub_matches.get_one::<String>("out").expect("required");
+        return run_export_manifest_command(&config, tx_seq, path);
+    }
+    if let Some((command, sub_matches)) = matches.subcommand() {
+        if let Some(path) = sub_matches.try_get_one::<String>("FILE").ok().flatten() {
+            return run_state_command(&config, command, path);
+        }
+    }
     metrics::initialize(config.metrics.clone());
     log::configure(
         &config.log_config_file,
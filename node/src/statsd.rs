@@ -0,0 +1,200 @@
+//! Pushes the metrics of the default metrics registries to a StatsD server over UDP, i.e. the
+//! same metrics that are reported by `admin_getMetrics`, so that there is one definition of
+//! each metric.
+//!
+//! Metrics are mapped by their types as below, where `<prefix>` is configured and `<name>` is
+//! the registered name, or `<group>.<name>` for the metrics registered with a group:
+//!
+//! - `counter`: `<prefix>.<name>:<increment since last flush>|c`
+//! - `gauge`: `<prefix>.<name>:<value>|g`
+//! - Metrics of multiple fields, e.g. `meter`, `histogram` and `timer`: the `count` field as
+//! `<prefix>.<name>.count:<increment since last flush>|c`, and each of the other fields, e.g.
+//! `m1` or `p99`, as `<prefix>.<name>.<field>:<value>|g`.
+//!
+//! Metrics are only registered if `metrics.enabled` is set.
+
+use metrics::{DEFAULT_GROUPING_REGISTRY, DEFAULT_REGISTRY};
+use std::collections::HashMap;
+use std::io;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+
+/// Maximum payload size to avoid IP fragmentation on common networks.
+const MAX_PACKET_SIZE: usize = 1432;
+
+/// A metric read from the registries as `(name, type, value)`.
+type MetricValue = (String, String, String);
+
+pub struct StatsdExporter {
+    socket: UdpSocket,
+    prefix: String,
+    /// Counter values of the last flush, to compute the increments.
+    last_counters: HashMap<String, f64>,
+}
+
+impl StatsdExporter {
+    /// Creates an exporter to push metrics to `address`, e.g. `127.0.0.1:8125`.
+    pub fn new(address: &str, prefix: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.to_string(),
+            last_counters: HashMap::new(),
+        })
+    }
+
+    /// Pushes the metrics every `interval` in a background thread.
+    pub fn spawn(mut self, interval: Duration) -> thread::JoinHandle<()> {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            // Metrics are pushed in a best-effort manner, and the next flush covers
+            // the counter increments that failed to send.
+            let _ = self.flush();
+        })
+    }
+
+    /// Pushes the current metrics of the registries.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let lines = self.encode(&gather());
+
+        let mut packet = String::new();
+        for line in lines {
+            if !packet.is_empty() && packet.len() + line.len() + 1 > MAX_PACKET_SIZE {
+                self.socket.send(packet.as_bytes())?;
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(&line);
+        }
+        if !packet.is_empty() {
+            self.socket.send(packet.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the metrics into StatsD lines.
+    fn encode(&mut self, metrics: &[MetricValue]) -> Vec<String> {
+        let mut lines = vec![];
+
+        for (name, metric_type, value) in metrics {
+            let name = format!("{}.{}", self.prefix, name);
+            match (metric_type.as_str(), value.trim().parse::<f64>()) {
+                ("counter", Ok(value)) => lines.push(self.encode_counter(&name, value)),
+                (_, Ok(value)) => lines.push(format!("{}:{}|g", name, value)),
+                (_, Err(_)) => {
+                    for (field, value) in parse_fields(value) {
+                        let name = format!("{}.{}", name, field);
+                        if field == "count" {
+                            lines.push(self.encode_counter(&name, value));
+                        } else {
+                            lines.push(format!("{}:{}|g", name, value));
+                        }
+                    }
+                }
+            }
+        }
+
+        lines
+    }
+
+    fn encode_counter(&mut self, name: &str, value: f64) -> String {
+        let last = self
+            .last_counters
+            .insert(name.to_string(), value)
+            .unwrap_or_default();
+        // The counter is reset if it decreases.
+        let increment = if value >= last { value - last } else { value };
+        format!("{}:{}|c", name, increment)
+    }
+}
+
+/// Reads the metrics of `DEFAULT_REGISTRY` and `DEFAULT_GROUPING_REGISTRY` sorted by names.
+fn gather() -> Vec<MetricValue> {
+    let mut metrics = vec![];
+
+    for (name, metric) in DEFAULT_REGISTRY.read().get_all() {
+        metrics.push((
+            name.clone(),
+            metric.get_type().to_string(),
+            metric.get_value().to_string(),
+        ));
+    }
+
+    for (group_name, group) in DEFAULT_GROUPING_REGISTRY.read().get_all() {
+        for (metric_name, metric) in group.iter() {
+            metrics.push((
+                format!("{}.{}", group_name, metric_name),
+                metric.get_type().to_string(),
+                metric.get_value().to_string(),
+            ));
+        }
+    }
+
+    metrics.sort();
+    metrics
+}
+
+/// Parses the numeric fields of a value like `{count: 3, m1: 0.50}`, and skips the others.
+fn parse_fields(value: &str) -> Vec<(&str, f64)> {
+    value
+        .trim()
+        .trim_start_matches('{')
+        .trim_end_matches('}')
+        .split(',')
+        .filter_map(|field| {
+            let (name, value) = field.split_once(':')?;
+            Some((name.trim(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatsdExporter;
+
+    #[test]
+    fn test_encode() {
+        let metrics = |count: usize| {
+            vec![
+                ("peers".to_string(), "gauge".to_string(), "5".to_string()),
+                (
+                    "rpc.qps".to_string(),
+                    "meter".to_string(),
+                    format!("{{count: {}, m1: 0.50, mean: unknown}}", count),
+                ),
+                (
+                    "sync_requests".to_string(),
+                    "counter".to_string(),
+                    (count * 2).to_string(),
+                ),
+            ]
+        };
+
+        let mut exporter = StatsdExporter::new("127.0.0.1:8125", "zgs").unwrap();
+        assert_eq!(
+            exporter.encode(&metrics(3)),
+            vec![
+                "zgs.peers:5|g",
+                "zgs.rpc.qps.count:3|c",
+                "zgs.rpc.qps.m1:0.5|g",
+                "zgs.sync_requests:6|c"
+            ]
+        );
+
+        // counters are exported as increments
+        assert_eq!(
+            exporter.encode(&metrics(5)),
+            vec![
+                "zgs.peers:5|g",
+                "zgs.rpc.qps.count:2|c",
+                "zgs.rpc.qps.m1:0.5|g",
+                "zgs.sync_requests:4|c"
+            ]
+        );
+    }
+}
@@ -7,15 +7,23 @@ use shared_types::{
     Chunk, ChunkArray, ChunkArrayWithProof, DataRoot, FlowProof, FlowRangeProof, Transaction,
 };
 use ssz::{Decode, Encode};
+use std::path::PathBuf;
 use std::sync::Arc;
+use storage::log_store::file_download::FileDownloadInfo;
+use storage::log_store::gc::GcReport;
 use storage::log_store::load_chunk::EntryBatch;
+use storage::log_store::proof_manifest::ProofManifest;
+use storage::log_store::replica::ReplicaDigest;
+use storage::log_store::state_dump::StateDumpInfo;
+use storage::log_store::tx_store::TxStatus;
+use storage::log_store::upload_store::StagedUpload;
 use storage::{error, error::Result, log_store::Store as LogStore, H256};
 use task_executor::TaskExecutor;
-use tokio::sync::oneshot;
+use tokio::sync::{broadcast, oneshot, watch};
 
 pub use storage::config::ShardConfig;
 use storage::log_store::config::ConfigurableExt;
-use storage::log_store::{MineLoadChunk, SealAnswer, SealTask};
+use storage::log_store::{FinalizedFile, MineLoadChunk, RemovedFiles, SealAnswer, SealTask};
 
 /// The name of the worker tokio tasks.
 const WORKER_TASK_NAME: &str = "async_storage_worker";
@@ -48,6 +56,9 @@ impl Store {
 
     delegate!(fn check_tx_completed(tx_seq: u64) -> Result<bool>);
     delegate!(fn check_tx_pruned(tx_seq: u64) -> Result<bool>);
+    delegate!(fn compute_data_root(tx_seq: u64) -> Result<Option<DataRoot>>);
+    delegate!(fn check_tx_merkle_complete(tx_seq: u64) -> Result<bool>);
+    delegate!(fn get_tx_status(tx_seq: u64) -> Result<Option<TxStatus>>);
     delegate!(fn get_chunk_by_tx_and_index(tx_seq: u64, index: usize) -> Result<Option<Chunk>>);
     delegate!(fn get_chunks_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize) -> Result<Option<ChunkArray>>);
     delegate!(fn get_chunks_with_proof_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize, merkle_tx_seq: Option<u64>) -> Result<Option<ChunkArrayWithProof>>);
@@ -57,11 +68,21 @@ impl Store {
     delegate!(fn get_chunk_by_flow_index(index: u64, length: u64) -> Result<Option<ChunkArray>>);
     delegate!(fn finalize_tx(tx_seq: u64) -> Result<()>);
     delegate!(fn prune_tx(tx_seq: u64) -> Result<()>);
+    delegate!(fn pin_tx(tx_seq: u64) -> Result<bool>);
+    delegate!(fn unpin_tx(tx_seq: u64) -> Result<bool>);
+    delegate!(fn get_pinned_txs() -> Result<Vec<u64>>);
+    delegate!(fn set_tx_tags(tx_seq: u64, tags: Vec<String>) -> Result<()>);
+    delegate!(fn get_tx_tags(tx_seq: u64) -> Result<Vec<String>>);
+    delegate!(fn get_skip_reason(tx_seq: u64) -> Result<Option<String>>);
+    delegate!(fn get_highest_finalized_seq() -> Result<Option<u64>>);
+    delegate!(fn get_finalized_tx_ranges() -> Result<Vec<(u64, u64)>>);
     delegate!(fn finalize_tx_with_hash(tx_seq: u64, tx_hash: H256) -> Result<bool>);
+    delegate!(fn link_same_root_tx(tx_seq: u64) -> Result<bool>);
     delegate!(fn get_proof_at_root(root: Option<DataRoot>, index: u64, length: u64) -> Result<FlowRangeProof>);
     delegate!(fn get_node_hash_by_index(index: u64) -> Result<OptionalHash>);
     delegate!(fn get_data_by_node_index(index: u64) -> Result<Option<EntryBatch>>);
     delegate!(fn get_context() -> Result<(DataRoot, u64)>);
+    delegate!(fn get_flow_root_at(tx_seq: u64) -> Result<Option<DataRoot>>);
 
     pub async fn get_tx_seq_by_data_root(
         &self,
@@ -83,6 +104,11 @@ impl Store {
             .await
     }
 
+    pub async fn get_tagged_txs(&self, tag: &str) -> Result<Vec<u64>> {
+        let tag = tag.to_string();
+        self.spawn(move |store| store.get_tagged_txs(&tag)).await
+    }
+
     pub async fn get_config_decoded<K: AsRef<[u8]> + Send + Sync, T: Decode + Send + 'static>(
         &self,
         key: &K,
@@ -129,12 +155,89 @@ impl Store {
         self.spawn(move |store| store.get_num_entries()).await
     }
 
+    pub async fn get_storage_usage(&self) -> Result<u64> {
+        self.spawn(move |store| store.get_storage_usage()).await
+    }
+
     pub async fn remove_chunks_batch(&self, batch_list: &[u64]) -> Result<()> {
         let batch_list = batch_list.to_vec();
         self.spawn(move |store| store.remove_chunks_batch(&batch_list))
             .await
     }
 
+    pub async fn export_state(&self, path: PathBuf) -> Result<StateDumpInfo> {
+        self.spawn(move |store| store.export_state(&path)).await
+    }
+
+    pub async fn download_file(&self, tx_seq: u64, path: PathBuf) -> Result<FileDownloadInfo> {
+        self.spawn(move |store| store.download_file(tx_seq, &path))
+            .await
+    }
+
+    pub async fn export_proof_manifest(&self, tx_seq: u64) -> Result<ProofManifest> {
+        self.spawn(move |store| store.export_proof_manifest(tx_seq))
+            .await
+    }
+
+    pub async fn get_replica_digest(
+        &self,
+        tx_seq: u64,
+        sample_indices: Vec<u64>,
+        recompute: bool,
+    ) -> Result<Option<ReplicaDigest>> {
+        self.spawn(move |store| store.get_replica_digest(tx_seq, &sample_indices, recompute))
+            .await
+    }
+
+    pub async fn gc_store(&self, dry_run: bool) -> Result<GcReport> {
+        self.spawn(move |store| store.gc_store(dry_run)).await
+    }
+
+    pub async fn get_upload(&self, root: &DataRoot) -> Result<Option<StagedUpload>> {
+        let root = *root;
+        self.spawn(move |store| store.get_upload(&root)).await
+    }
+
+    pub async fn get_upload_segment(
+        &self,
+        root: &DataRoot,
+        index: usize,
+    ) -> Result<Option<Vec<u8>>> {
+        let root = *root;
+        self.spawn(move |store| store.get_upload_segment(&root, index))
+            .await
+    }
+
+    delegate!(fn get_uploads() -> Result<Vec<DataRoot>>);
+
+    pub async fn put_upload_segment(
+        &self,
+        root: &DataRoot,
+        file_size: usize,
+        chunks_per_segment: usize,
+        index: usize,
+        segment_root: H256,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let root = *root;
+        self.spawn(move |store| {
+            store.put_upload_segment(
+                &root,
+                file_size,
+                chunks_per_segment,
+                index,
+                segment_root,
+                &data,
+            )
+        })
+        .await
+    }
+
+    pub async fn remove_upload(&self, root: &DataRoot) -> Result<()> {
+        let root = *root;
+        self.spawn(move |store| store.remove_upload(&root)).await
+    }
+
     pub async fn update_shard_config(&self, shard_config: ShardConfig) {
         self.spawn(move |store| {
             store.update_shard_config(shard_config);
@@ -168,6 +271,18 @@ impl Store {
             .unwrap_or_else(|_| bail!(error::Error::Custom("Receiver error".to_string())))
     }
 
+    pub fn subscribe_finalized(&self) -> watch::Receiver<u64> {
+        self.store.subscribe_finalized()
+    }
+
+    pub fn subscribe_finalized_files(&self) -> broadcast::Receiver<FinalizedFile> {
+        self.store.subscribe_finalized_files()
+    }
+
+    pub fn subscribe_removed_files(&self) -> broadcast::Receiver<RemovedFiles> {
+        self.store.subscribe_removed_files()
+    }
+
     // FIXME(zz): Refactor the lock and async call here.
     pub fn get_store(&self) -> &dyn LogStore {
         self.store.as_ref()
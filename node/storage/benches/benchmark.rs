@@ -101,12 +101,38 @@ fn write_performance(c: &mut Criterion) {
 }
 
 fn read_performance(c: &mut Criterion) {
+    random_read(
+        c,
+        "read performance",
+        LogConfig::default(),
+        "db_flow_read",
+        "db_data_read",
+    );
+}
+
+fn sharded_read_performance(c: &mut Criterion) {
+    let config = LogConfig {
+        data_db_shards: 8,
+        data_db_shard_size: 16,
+        ..Default::default()
+    };
+    random_read(
+        c,
+        "sharded read performance",
+        config,
+        "db_flow_read_sharded",
+        "db_data_read_sharded",
+    );
+}
+
+/// Measures the latency of random reads with the data db layout in `config`.
+fn random_read(c: &mut Criterion, name: &str, config: LogConfig, flow_path: &str, data_path: &str) {
     if Path::new("db_read").exists() {
         fs::remove_dir_all("db_read").unwrap();
     }
 
     let store: Arc<RwLock<dyn Store>> = Arc::new(RwLock::new(
-        LogManager::rocksdb(LogConfig::default(), "db_flow_read", "db_data_read")
+        LogManager::rocksdb(config, flow_path, data_path)
             .map_err(|e| format!("Unable to start RocksDB store: {:?}", e))
             .unwrap(),
     ));
@@ -163,9 +189,9 @@ fn read_performance(c: &mut Criterion) {
 
     let mut rng = rand::thread_rng();
 
-    let mut group = c.benchmark_group("read performance");
+    let mut group = c.benchmark_group(name);
     group.sample_size(100);
-    group.bench_function("read performance", move |b| {
+    group.bench_function(name, move |b| {
         b.iter(|| {
             let tx_seq = rng.gen_range(0..tx_size);
             let index_start = rng.gen_range(0..=chunk_count);
@@ -180,5 +206,10 @@ fn read_performance(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, write_performance, read_performance);
+criterion_group!(
+    benches,
+    write_performance,
+    read_performance,
+    sharded_read_performance
+);
 criterion_main!(benches);
@@ -0,0 +1,114 @@
+//! Extracts the data of a stored file to a local path, e.g. for operators to restore a file
+//! from a node without a client.
+//!
+//! The file is read segment by segment in order and written to a temporary file first, which
+//! is moved to the path only if all the segments are stored and the data matches the root of
+//! the tx, so that an incomplete or corrupt file never leaves partial data at the path.
+
+use crate::log_store::log_manager::{data_to_merkle_leaves_h256, FileMerkleTree, PORA_CHUNK_SIZE};
+use crate::log_store::LogStoreRead;
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use shared_types::{bytes_to_chunks, DataRoot};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDownloadInfo {
+    pub tx_seq: u64,
+    pub data_root: DataRoot,
+    pub size_bytes: u64,
+}
+
+/// Writes the data of the file of `tx_seq` to `path`, of which the size is exactly the file
+/// size, i.e. the padding of the last chunk is trimmed.
+pub(crate) fn download_file(
+    store: &dyn LogStoreRead,
+    tx_seq: u64,
+    path: &Path,
+) -> Result<FileDownloadInfo> {
+    let partial_path = partial_path(path);
+    let result = write_file(store, tx_seq, &partial_path);
+    if result.is_err() {
+        let _ = fs::remove_file(&partial_path);
+    }
+    let info = result?;
+    fs::rename(&partial_path, path)?;
+    Ok(info)
+}
+
+/// Returns the path of the temporary file to write, i.e. `<name>.<ext>.partial`, which keeps
+/// the extension so that it never collides with the path itself.
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(".partial");
+    partial_path.into()
+}
+
+fn write_file(store: &dyn LogStoreRead, tx_seq: u64, path: &Path) -> Result<FileDownloadInfo> {
+    let tx = store
+        .get_tx_by_seq_number(tx_seq)?
+        .ok_or_else(|| anyhow!("Transaction {} not found", tx_seq))?;
+    let num_chunks = bytes_to_chunks(tx.size as usize);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut remaining = tx.size as usize;
+    let mut leaves = Vec::with_capacity(num_chunks);
+    let mut missing_segments = vec![];
+    for (segment_index, start_index) in (0..num_chunks).step_by(PORA_CHUNK_SIZE).enumerate() {
+        let end_index = (start_index + PORA_CHUNK_SIZE).min(num_chunks);
+        let chunks = match store.get_chunks_by_tx_and_index_range(tx_seq, start_index, end_index)? {
+            Some(chunks) => chunks,
+            None => {
+                missing_segments.push(segment_index);
+                continue;
+            }
+        };
+
+        // skip the rest segments once any segment is missing
+        if !missing_segments.is_empty() {
+            continue;
+        }
+
+        leaves.extend(
+            data_to_merkle_leaves_h256(&chunks.data)?
+                .into_iter()
+                .map(|h| h.0),
+        );
+        let len = chunks.data.len().min(remaining);
+        writer.write_all(&chunks.data[..len])?;
+        remaining -= len;
+    }
+
+    if !missing_segments.is_empty() {
+        bail!(
+            "File {} is not complete, missing segments {:?} of {}",
+            tx_seq,
+            missing_segments,
+            (num_chunks + PORA_CHUNK_SIZE - 1) / PORA_CHUNK_SIZE
+        );
+    }
+
+    let data_root: DataRoot = FileMerkleTree::new(leaves).root().into();
+    if data_root != tx.data_merkle_root {
+        bail!(
+            "File {} root mismatch, computed {:?}, expected {:?}",
+            tx_seq,
+            data_root,
+            tx.data_merkle_root
+        );
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+
+    Ok(FileDownloadInfo {
+        tx_seq,
+        data_root,
+        size_bytes: tx.size,
+    })
+}
@@ -78,16 +78,41 @@ impl FlowStore {
         AppendMerkleTree::convert_proof_to_h256(optional_proof)
     }
 
+    pub fn batch_size(&self) -> usize {
+        self.config.batch_size
+    }
+
     pub fn delete_batch_list(&self, batch_list: &[u64]) -> Result<()> {
         self.seal_manager.delete_batch_list(batch_list);
         self.data_db.delete_batch_list(batch_list)
     }
+
+    /// Corrupt the stored data of the entry at `index` in place.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn corrupt_entry(&self, index: u64) -> Result<()> {
+        let batch_size = self.config.batch_size as u64;
+        let batch_index = index / batch_size;
+        let mut batch = self
+            .data_db
+            .get_entry_batch(batch_index)?
+            .ok_or_else(|| anyhow!("batch missing, index={}", batch_index))?;
+        if !batch.corrupt_sector((index % batch_size) as usize) {
+            bail!("entry missing, index={}", index);
+        }
+        self.data_db.put_entry_raw(vec![(batch_index, batch)])
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct FlowConfig {
     pub batch_size: usize,
     pub merkle_node_cache_capacity: usize,
+    /// Number of the top layers of the merkle tree that are never evicted from memory.
+    pub merkle_pinned_layers: usize,
+    /// Whether to drop the merkle nodes of completed subtrees from memory once persisted.
+    pub merkle_flush_completed_subtrees: bool,
+    /// Whether to record the updates of the merkle tree in a write-ahead log before applied.
+    pub merkle_wal: bool,
     pub shard_config: Arc<RwLock<ShardConfig>>,
 }
 
@@ -97,6 +122,9 @@ impl Default for FlowConfig {
             batch_size: SECTORS_PER_LOAD,
             // Each node takes (8+8+32=)48 Bytes, so the default value is 1.5 GB memory size.
             merkle_node_cache_capacity: 32 * 1024 * 1024,
+            merkle_pinned_layers: 10,
+            merkle_flush_completed_subtrees: false,
+            merkle_wal: false,
             shard_config: Default::default(),
         }
     }
@@ -588,7 +616,7 @@ fn encode_mpt_node_key(layer_index: usize, position: usize) -> Vec<u8> {
     key
 }
 
-fn layer_size_key(layer: usize) -> Vec<u8> {
+pub(crate) fn layer_size_key(layer: usize) -> Vec<u8> {
     let mut key = "layer_size".as_bytes().to_vec();
     key.extend_from_slice(&layer.to_be_bytes());
     key
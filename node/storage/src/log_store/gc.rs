@@ -0,0 +1,218 @@
+//! Garbage collection of the orphaned records of the store, which nothing references anymore
+//! but could be left behind by reorgs, truncations and prunes, e.g. if the node crashes in the
+//! middle of reverting the txs of a reorg.
+//!
+//! A record is orphaned if it's out of the live range of the store:
+//! - a flow tree node out of the size of its layer, or the size of a layer above the top layer,
+//! - an entry batch after the end of the flow,
+//! - the status or padding of a tx not in the log,
+//! - a tx in the index of txs by data root, which deduplicates the data of the same root, but
+//!   not in the log.
+//!
+//! The columns are scanned without locking, and the orphans are reclaimed in small batches,
+//! each of which is checked again against the live range while holding the merkle lock. So the
+//! writes are only blocked for a batch at a time, and a record that becomes live in the meantime is
+//! never reclaimed.
+
+use crate::log_store::flow_store::layer_size_key;
+use crate::log_store::log_manager::{
+    COL_ENTRY_BATCH, COL_FLOW_MPT_NODES, COL_PAD_DATA_LIST, COL_TX_COMPLETED,
+    COL_TX_DATA_ROOT_INDEX,
+};
+use crate::log_store::metrics;
+use crate::ZgsKeyValueDB;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use ssz::{Decode, Encode};
+
+/// Maximum number of orphans to reclaim while holding the merkle lock.
+const GC_BATCH_SIZE: usize = 1024;
+
+/// Prefix of the keys of the layer sizes in `COL_FLOW_MPT_NODES`.
+const LAYER_SIZE_PREFIX: &[u8] = b"layer_size";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GcReport {
+    pub scanned_records: u64,
+    pub orphaned_records: u64,
+    /// Orphaned records deleted, or rewritten without the dangling references. It's 0 in a dry
+    /// run.
+    pub reclaimed_records: u64,
+    pub bytes_freed: u64,
+}
+
+/// The range of the records referenced by the store.
+pub(crate) struct LiveRange {
+    pub next_tx_seq: u64,
+    /// Index of the first entry batch after the end of the flow.
+    pub end_batch_index: u64,
+    /// Sizes of the layers of the flow tree from the bottom.
+    pub layer_sizes: Vec<usize>,
+}
+
+impl LiveRange {
+    /// Reads the layer sizes the same way as the flow tree is loaded at startup, which stops at
+    /// the first layer without a size.
+    pub fn read_layer_sizes(flow_db: &dyn ZgsKeyValueDB) -> Result<Vec<usize>> {
+        let mut layer_sizes = vec![];
+        while let Some(size) =
+            flow_db.get(COL_FLOW_MPT_NODES, &layer_size_key(layer_sizes.len()))?
+        {
+            layer_sizes.push(decode_usize(&size).unwrap_or_default());
+        }
+        Ok(layer_sizes)
+    }
+
+    /// Returns the new value of an orphaned record, or `None` if the record is live.
+    fn reclaim(&self, col: u32, key: &[u8], value: &[u8]) -> Option<Reclaim> {
+        let orphaned = match col {
+            COL_FLOW_MPT_NODES => {
+                if let Some(layer) = key.strip_prefix(LAYER_SIZE_PREFIX).and_then(decode_usize) {
+                    layer >= self.layer_sizes.len()
+                } else if key.len() == 16 {
+                    let layer = decode_usize(&key[..8])?;
+                    let pos = decode_usize(&key[8..])?;
+                    self.layer_sizes
+                        .get(layer)
+                        .map_or(true, |size| pos >= *size)
+                } else {
+                    false
+                }
+            }
+            COL_ENTRY_BATCH => decode_u64(key)? >= self.end_batch_index,
+            COL_TX_COMPLETED | COL_PAD_DATA_LIST => decode_u64(key)? >= self.next_tx_seq,
+            COL_TX_DATA_ROOT_INDEX => {
+                let tx_seq_list = Vec::<u64>::from_ssz_bytes(value).ok()?;
+                let live: Vec<u64> = tx_seq_list
+                    .iter()
+                    .copied()
+                    .filter(|seq| *seq < self.next_tx_seq)
+                    .collect();
+                if live.len() == tx_seq_list.len() {
+                    return None;
+                } else if !live.is_empty() {
+                    return Some(Reclaim::Rewrite(live.as_ssz_bytes()));
+                }
+                true
+            }
+            _ => false,
+        };
+
+        orphaned.then_some(Reclaim::Delete)
+    }
+}
+
+enum Reclaim {
+    Delete,
+    Rewrite(Vec<u8>),
+}
+
+struct Orphan {
+    col: u32,
+    key: Vec<u8>,
+}
+
+/// Scans the columns of `flow_db` and `data_db` for orphans, and reclaims them unless
+/// `dry_run`. `lock` blocks the writes to the store until the returned guard is dropped, and
+/// returns the live range at the moment.
+pub(crate) fn gc_store<G>(
+    flow_db: &dyn ZgsKeyValueDB,
+    data_db: &dyn ZgsKeyValueDB,
+    dry_run: bool,
+    lock: impl Fn() -> Result<(G, LiveRange)>,
+) -> Result<GcReport> {
+    let columns: [(&dyn ZgsKeyValueDB, u32); 5] = [
+        (flow_db, COL_FLOW_MPT_NODES),
+        (flow_db, COL_PAD_DATA_LIST),
+        (flow_db, COL_TX_DATA_ROOT_INDEX),
+        (data_db, COL_ENTRY_BATCH),
+        (data_db, COL_TX_COMPLETED),
+    ];
+
+    let mut report = GcReport::default();
+    let (guard, mut live) = lock()?;
+    drop(guard);
+
+    for (db, col) in columns {
+        let mut orphans = Vec::with_capacity(GC_BATCH_SIZE);
+        for item in db.iter(col) {
+            let (key, value) = item?;
+            report.scanned_records += 1;
+            if live.reclaim(col, &key, &value).is_some() {
+                orphans.push(Orphan {
+                    col,
+                    key: key.to_vec(),
+                });
+            }
+
+            if orphans.len() == GC_BATCH_SIZE {
+                live = reclaim_batch(db, &orphans, dry_run, &lock, &mut report)?;
+                orphans.clear();
+            }
+        }
+        if !orphans.is_empty() {
+            live = reclaim_batch(db, &orphans, dry_run, &lock, &mut report)?;
+        }
+    }
+
+    metrics::GC_ORPHANED_RECORDS.update(report.orphaned_records as usize);
+    if !dry_run {
+        metrics::GC_BYTES_FREED.inc(report.bytes_freed as usize);
+    }
+    Ok(report)
+}
+
+/// Reclaims the orphans that are still orphaned while holding the lock, and returns the live
+/// range at the moment.
+fn reclaim_batch<G>(
+    db: &dyn ZgsKeyValueDB,
+    orphans: &[Orphan],
+    dry_run: bool,
+    lock: &impl Fn() -> Result<(G, LiveRange)>,
+    report: &mut GcReport,
+) -> Result<LiveRange> {
+    let (guard, live) = lock()?;
+
+    let mut tx = db.transaction();
+    for orphan in orphans {
+        let value = match db.get(orphan.col, &orphan.key)? {
+            Some(value) => value,
+            None => continue,
+        };
+        let reclaim = match live.reclaim(orphan.col, &orphan.key, &value) {
+            Some(reclaim) => reclaim,
+            None => continue,
+        };
+
+        report.orphaned_records += 1;
+        if dry_run {
+            continue;
+        }
+        report.reclaimed_records += 1;
+        match reclaim {
+            Reclaim::Delete => {
+                tx.delete(orphan.col, &orphan.key);
+                report.bytes_freed += (orphan.key.len() + value.len()) as u64;
+            }
+            Reclaim::Rewrite(new_value) => {
+                tx.put(orphan.col, &orphan.key, &new_value);
+                report.bytes_freed += value.len().saturating_sub(new_value.len()) as u64;
+            }
+        }
+    }
+    if !tx.ops.is_empty() {
+        db.write(tx)?;
+    }
+
+    drop(guard);
+    Ok(live)
+}
+
+fn decode_usize(data: &[u8]) -> Option<usize> {
+    Some(usize::from_be_bytes(data.try_into().ok()?))
+}
+
+fn decode_u64(data: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(data.try_into().ok()?))
+}
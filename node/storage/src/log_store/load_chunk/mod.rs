@@ -76,6 +76,22 @@ impl EntryBatch {
     pub fn is_seal_sealed(&self, seal_index: u16) -> bool {
         self.seal.is_sealed(seal_index)
     }
+
+    /// Flip the first byte of the sector at `offset` to simulate a corruption of the stored
+    /// data. Return `false` if the sector is not stored.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn corrupt_sector(&mut self, offset: usize) -> bool {
+        match self
+            .data
+            .get_mut(offset * BYTES_PER_SECTOR, BYTES_PER_SECTOR)
+        {
+            Some(sector) => {
+                sector[0] ^= 0xff;
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 impl EntryBatch {
@@ -1,16 +1,26 @@
 use crate::config::ShardConfig;
+use crate::log_store::file_download::{self, FileDownloadInfo};
 use crate::log_store::flow_store::{
-    batch_iter_sharded, FlowConfig, FlowDBStore, FlowStore, PadPair,
+    batch_iter, batch_iter_sharded, FlowConfig, FlowDBStore, FlowStore, PadPair,
 };
+use crate::log_store::gc::{self, GcReport, LiveRange};
 use crate::log_store::load_chunk::EntryBatch;
+use crate::log_store::merkle_wal::DbWal;
+use crate::log_store::proof_manifest::{self, ProofManifest};
+use crate::log_store::replica::{self, ReplicaDigest};
+use crate::log_store::sharded_db::{self, ShardedDB};
+use crate::log_store::state_dump::{self, StateDumpInfo};
 use crate::log_store::tx_store::{BlockHashAndSubmissionIndex, TransactionStore, TxStatus};
+use crate::log_store::upload_store::{StagedUpload, UploadStore};
 use crate::log_store::{
-    FlowRead, FlowSeal, FlowWrite, LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead,
-    LogStoreWrite, MineLoadChunk, SealAnswer, SealTask,
+    FinalizedFile, FlowRead, FlowSeal, FlowWrite, LogStoreChunkRead, LogStoreChunkWrite,
+    LogStoreRead, LogStoreWrite, MineLoadChunk, RemovedFiles, SealAnswer, SealTask,
 };
 use crate::{try_option, ZgsKeyValueDB};
 use anyhow::{anyhow, bail, Result};
-use append_merkle::{Algorithm, AppendMerkleTree, MerkleTreeRead, OptionalHash, Sha3Algorithm};
+use append_merkle::{
+    Algorithm, AppendMerkleTree, AppendWal, MerkleTreeRead, OptionalHash, Sha3Algorithm, WalOp,
+};
 use ethereum_types::H256;
 use kvdb_rocksdb::{Database, DatabaseConfig};
 use merkle_light::merkle::{log2_pow2, MerkleTree};
@@ -25,9 +35,10 @@ use shared_types::{
 };
 use std::cmp::Ordering;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
 
 use tracing::{debug, error, info, instrument, trace, warn};
 
@@ -37,6 +48,8 @@ use crate::log_store::metrics;
 pub const ENTRY_SIZE: usize = 256;
 /// 1024 Entries.
 pub const PORA_CHUNK_SIZE: usize = 1024;
+/// Number of finalized files buffered for each subscriber that falls behind.
+const FINALIZED_FILES_CHANNEL_SIZE: usize = 1024;
 
 pub const COL_TX: u32 = 0; // flow db
 pub const COL_ENTRY_BATCH: u32 = 1; // data db
@@ -71,7 +84,14 @@ pub struct LogManager {
     pub(crate) data_db: Arc<dyn ZgsKeyValueDB>,
     tx_store: TransactionStore,
     flow_store: Arc<FlowStore>,
+    upload_store: UploadStore,
     merkle: RwLock<MerkleManager>,
+    /// Number of transactions finalized since startup.
+    finalized_sender: watch::Sender<u64>,
+    finalized_files_sender: broadcast::Sender<FinalizedFile>,
+    removed_files_sender: broadcast::Sender<RemovedFiles>,
+    /// Directories of the rocksdb files, empty if in memory.
+    db_paths: Vec<PathBuf>,
 }
 
 struct MerkleManager {
@@ -80,28 +100,58 @@ struct MerkleManager {
     /// The in-memory structure of the sub merkle tree of the last chunk.
     /// The size is always less than `PORA_CHUNK_SIZE`.
     last_chunk_merkle: Merkle,
+    /// The updates of `pora_chunks_merkle` in `put_tx` that are not applied yet, which are
+    /// applied as one batch so that they are recovered from the WAL together.
+    pora_batch: Vec<WalOp<OptionalHash>>,
 }
 
 impl MerkleManager {
+    /// Number of the leaves of `pora_chunks_merkle` including the pending batch.
+    fn pora_leaves(&self) -> usize {
+        self.pora_chunks_merkle.leaves() + self.pora_batch.iter().map(WalOp::leaves).sum::<usize>()
+    }
+
+    fn pora_append(&mut self, leaf: OptionalHash) {
+        match self.pora_batch.last_mut() {
+            Some(WalOp::AppendList(leaves)) => leaves.push(leaf),
+            _ => self.pora_batch.push(WalOp::AppendList(vec![leaf])),
+        }
+    }
+
+    fn pora_append_subtree(&mut self, depth: usize, root: OptionalHash) {
+        self.pora_batch.push(WalOp::AppendSubtree { depth, root });
+    }
+
+    fn pora_update_last(&mut self, leaf: OptionalHash) {
+        self.pora_batch.push(WalOp::UpdateLast(leaf));
+    }
+
+    fn apply_pora_batch(&mut self) -> Result<()> {
+        let batch = std::mem::take(&mut self.pora_batch);
+        self.pora_chunks_merkle.append_batch(batch)
+    }
+
     fn last_chunk_start_index(&self) -> u64 {
-        if self.pora_chunks_merkle.leaves() == 0 {
+        let pora_leaves = self.pora_leaves();
+        if pora_leaves == 0 {
             0
         } else {
             PORA_CHUNK_SIZE as u64
                 * if self.last_chunk_merkle.leaves() == 0 {
                     // The last chunk is empty and its root hash is not in `pora_chunk_merkle`,
                     // so all chunks in `pora_chunk_merkle` is complete.
-                    self.pora_chunks_merkle.leaves()
+                    pora_leaves
                 } else {
                     // The last chunk has data, so we need to exclude it from `pora_chunks_merkle`.
-                    self.pora_chunks_merkle.leaves() - 1
+                    pora_leaves - 1
                 } as u64
         }
     }
 
+    /// Commit the version of `tx_seq`, which also truncates the WAL of `pora_chunks_merkle`.
     #[instrument(skip(self))]
     fn commit_merkle(&mut self, tx_seq: u64) -> Result<()> {
-        self.pora_chunks_merkle.commit(Some(tx_seq));
+        self.pora_chunks_merkle.checkpoint(Some(tx_seq))?;
         self.last_chunk_merkle.commit(Some(tx_seq));
         Ok(())
     }
@@ -161,10 +211,15 @@ impl MerkleManager {
 #[derive(Clone, Default)]
 pub struct LogConfig {
     pub flow: FlowConfig,
+    /// Number of rocksdb shards to store chunk batches, 0 to store them in the data db.
+    pub data_db_shards: usize,
+    /// Number of consecutive chunk batches stored in the same shard.
+    pub data_db_shard_size: u64,
 }
 
 impl LogStoreChunkWrite for LogManager {
     fn put_chunks(&self, tx_seq: u64, chunks: ChunkArray) -> Result<()> {
+        let start_time = Instant::now();
         let mut merkle = self.merkle.write();
         let tx = self
             .tx_store
@@ -185,6 +240,7 @@ impl LogStoreChunkWrite for LogManager {
         let mut flow_entry_array = chunks;
         flow_entry_array.start_index += tx.start_entry_index;
         self.append_entries(flow_entry_array, &mut merkle)?;
+        metrics::SEGMENT_WRITE_LATENCY.update_since(start_time);
         Ok(())
     }
 
@@ -236,11 +292,32 @@ impl LogStoreChunkWrite for LogManager {
             )?;
         }
         metrics::PUT_CHUNKS.update_since(start_time);
+        metrics::SEGMENT_WRITE_LATENCY.update_since(start_time);
         Ok(true)
     }
 
     fn remove_chunks_batch(&self, batch_list: &[u64]) -> crate::error::Result<()> {
-        self.flow_store.delete_batch_list(batch_list)
+        let pinned_batches = self.pinned_batch_ranges()?;
+        if pinned_batches.is_empty() {
+            return self.flow_store.delete_batch_list(batch_list);
+        }
+
+        let to_delete: Vec<u64> = batch_list
+            .iter()
+            .copied()
+            .filter(|index| {
+                !pinned_batches
+                    .iter()
+                    .any(|(start, end)| start <= index && index < end)
+            })
+            .collect();
+        if to_delete.len() != batch_list.len() {
+            info!(
+                skipped = batch_list.len() - to_delete.len(),
+                "skip removing chunk batches of pinned txs"
+            );
+        }
+        self.flow_store.delete_batch_list(&to_delete)
     }
 }
 
@@ -280,7 +357,7 @@ impl LogStoreWrite for LogManager {
                 bail!("unexpected tx!");
             }
         }
-        let maybe_same_data_tx_seq = self.tx_store.put_tx(tx.clone())?.first().cloned();
+        self.tx_store.put_tx(tx.clone())?;
         // TODO(zz): Should we validate received tx?
         self.append_subtree_list(
             tx.seq,
@@ -289,19 +366,22 @@ impl LogStoreWrite for LogManager {
             &mut merkle,
         )?;
         merkle.commit_merkle(tx.seq)?;
-        debug!(
-            "commit flow root: root={:?}",
-            merkle.pora_chunks_merkle.root()
-        );
+        let flow_root = merkle
+            .pora_chunks_merkle
+            .root()
+            .0
+            .ok_or_else(|| anyhow!("put_tx with flow root missing: tx_seq={}", tx.seq))?;
+        debug!("commit flow root: root={:?}", flow_root);
+        // If the node crashes before the root is written, it's restored from the tree when the
+        // node restarts, see `LogManager::new`.
+        self.tx_store.put_flow_root(tx.seq, flow_root)?;
         // Drop the lock because `copy_tx_data` will lock again.
         drop(merkle);
 
-        if let Some(old_tx_seq) = maybe_same_data_tx_seq {
-            if self.check_tx_completed(old_tx_seq)? {
-                // copy and finalize once, then stop
-                self.copy_tx_and_finalize(old_tx_seq, vec![tx.seq])?;
-            }
-        }
+        // The tx is finalized only after all the data is copied, so if the node crashes in
+        // the middle, the tx is left unfinalized, and the sync service links it again before
+        // downloading the file, see `SyncService::on_start_sync_file`.
+        self.link_same_root_tx(tx.seq)?;
         metrics::PUT_TX.update_since(start_time);
         Ok(())
     }
@@ -348,8 +428,71 @@ impl LogStoreWrite for LogManager {
         Ok(true)
     }
 
+    fn link_same_root_tx(&self, tx_seq: u64) -> crate::error::Result<bool> {
+        if self.check_tx_completed(tx_seq)? {
+            return Ok(false);
+        }
+        let tx = self
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| anyhow!("link_same_root_tx with tx missing: tx_seq={}", tx_seq))?;
+        let num_shard = self.flow_store.get_shard_config().num_shard;
+
+        for old_tx_seq in self.get_tx_seq_list_by_data_root(&tx.data_merkle_root)? {
+            if old_tx_seq == tx_seq
+                || !self.check_tx_completed(old_tx_seq)?
+                || self.check_tx_pruned(old_tx_seq)?
+            {
+                continue;
+            }
+            let old_tx = self
+                .get_tx_by_seq_number(old_tx_seq)?
+                .ok_or_else(|| anyhow!("from tx missing"))?;
+            // The data of the old tx is stored in another shard.
+            if sector_to_segment(tx.start_entry_index) % num_shard
+                != sector_to_segment(old_tx.start_entry_index) % num_shard
+            {
+                continue;
+            }
+
+            self.copy_tx_and_finalize(old_tx_seq, vec![tx_seq])?;
+            debug!(%tx_seq, %old_tx_seq, "Linked tx to the data of the same root");
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
     fn prune_tx(&self, tx_seq: u64) -> crate::error::Result<()> {
-        self.tx_store.prune_tx(tx_seq)
+        // the index of txs by data root is updated, which must not interleave with the gc
+        let _merkle = self.merkle.read();
+        if !self.tx_store.prune_tx(tx_seq)? {
+            info!(tx_seq, "skip pruning pinned tx");
+            return Ok(());
+        }
+        self.notify_removed(tx_seq, tx_seq + 1);
+        Ok(())
+    }
+
+    fn skip_tx(&self, tx_seq: u64, reason: &str) -> crate::error::Result<()> {
+        self.tx_store.skip_tx(tx_seq, reason)
+    }
+
+    fn pin_tx(&self, tx_seq: u64) -> crate::error::Result<bool> {
+        if tx_seq >= self.tx_store.next_tx_seq() {
+            bail!("pin_tx with tx missing: tx_seq={}", tx_seq);
+        }
+        self.tx_store.pin_tx(tx_seq)
+    }
+
+    fn unpin_tx(&self, tx_seq: u64) -> crate::error::Result<bool> {
+        self.tx_store.unpin_tx(tx_seq)
+    }
+
+    fn set_tx_tags(&self, tx_seq: u64, tags: Vec<String>) -> crate::error::Result<()> {
+        if tx_seq >= self.tx_store.next_tx_seq() {
+            bail!("set_tx_tags with tx missing: tx_seq={}", tx_seq);
+        }
+        self.tx_store.set_tx_tags(tx_seq, tags)
     }
 
     fn put_sync_progress(&self, progress: (u64, H256, Option<Option<u64>>)) -> Result<()> {
@@ -377,7 +520,9 @@ impl LogStoreWrite for LogManager {
             + merkle.last_chunk_merkle.leaves() as u64;
         self.flow_store.truncate(start_index)?;
         let start = if tx_seq != u64::MAX { tx_seq + 1 } else { 0 };
-        self.tx_store.remove_tx_after(start)
+        let reverted = self.tx_store.remove_tx_after(start)?;
+        self.notify_removed(start, u64::MAX);
+        Ok(reverted)
     }
 
     fn validate_and_insert_range_proof(
@@ -443,6 +588,51 @@ impl LogStoreWrite for LogManager {
             "pad_tx",
         );
     }
+
+    fn gc_store(&self, dry_run: bool) -> crate::error::Result<GcReport> {
+        let report = gc::gc_store(
+            self.flow_db.as_ref(),
+            self.data_db.as_ref(),
+            dry_run,
+            || {
+                let merkle = self.merkle.write();
+                let flow_end = merkle.last_chunk_start_index() * PORA_CHUNK_SIZE as u64
+                    + merkle.last_chunk_merkle.leaves() as u64;
+                let batch_size = self.flow_store.batch_size() as u64;
+                let live = LiveRange {
+                    next_tx_seq: self.tx_store.next_tx_seq(),
+                    end_batch_index: (flow_end + batch_size - 1) / batch_size,
+                    layer_sizes: LiveRange::read_layer_sizes(self.flow_db.as_ref())?,
+                };
+                Ok((merkle, live))
+            },
+        )?;
+        info!(dry_run, ?report, "Store garbage collected");
+        Ok(report)
+    }
+
+    fn put_upload_segment(
+        &self,
+        root: &DataRoot,
+        file_size: usize,
+        chunks_per_segment: usize,
+        index: usize,
+        segment_root: H256,
+        data: &[u8],
+    ) -> crate::error::Result<()> {
+        Ok(self.upload_store.put_segment(
+            root,
+            file_size,
+            chunks_per_segment,
+            index,
+            segment_root,
+            data,
+        )?)
+    }
+
+    fn remove_upload(&self, root: &DataRoot) -> crate::error::Result<()> {
+        Ok(self.upload_store.remove(root)?)
+    }
 }
 
 impl LogStoreChunkRead for LogManager {
@@ -463,6 +653,7 @@ impl LogStoreChunkRead for LogManager {
         index_start: usize,
         index_end: usize,
     ) -> crate::error::Result<Option<ChunkArray>> {
+        let start_time = Instant::now();
         let tx = try_option!(self.get_tx_by_seq_number(tx_seq)?);
 
         if index_end as u64 > bytes_to_entries(tx.size) {
@@ -481,6 +672,7 @@ impl LogStoreChunkRead for LogManager {
             .flow_store
             .get_entries(start_flow_index, end_flow_index)?);
         tx_chunk.start_index -= tx.start_entry_index;
+        metrics::SEGMENT_READ_LATENCY.update_since(start_time);
         Ok(Some(tx_chunk))
     }
 
@@ -614,6 +806,78 @@ impl LogStoreRead for LogManager {
         self.tx_store.check_tx_completed(tx_seq)
     }
 
+    fn compute_data_root(&self, tx_seq: u64) -> crate::error::Result<Option<DataRoot>> {
+        let tx = try_option!(self.get_tx_by_seq_number(tx_seq)?);
+        if tx.size == 0 {
+            // The root of an empty file is defined as zero.
+            return Ok(Some(DataRoot::zero()));
+        }
+        let num_chunks = bytes_to_chunks(tx.size as usize);
+
+        // Read the chunks in batches so that only the leaves, instead of all the data, of the
+        // file are held in memory.
+        let mut leaves = Vec::with_capacity(num_chunks);
+        for start_index in (0..num_chunks).step_by(PORA_CHUNK_SIZE) {
+            let end_index = (start_index + PORA_CHUNK_SIZE).min(num_chunks);
+            let chunks = try_option!(self.get_chunks_by_tx_and_index_range(
+                tx_seq,
+                start_index,
+                end_index
+            )?);
+            leaves.extend(
+                data_to_merkle_leaves_h256(&chunks.data)?
+                    .into_iter()
+                    .map(|h| h.0),
+            );
+        }
+
+        Ok(Some(FileMerkleTree::new(leaves).root().into()))
+    }
+
+    fn check_tx_merkle_complete(&self, tx_seq: u64) -> crate::error::Result<bool> {
+        match self.get_tx_status(tx_seq)? {
+            Some(TxStatus::Finalized) => return Ok(true),
+            Some(TxStatus::Pruned) => return Ok(false),
+            Some(TxStatus::Skipped) | None => {}
+        }
+        let tx = match self.get_tx_by_seq_number(tx_seq)? {
+            Some(tx) => tx,
+            None => return Ok(false),
+        };
+
+        // The tx subtrees larger than a batch are appended to the flow tree with their roots
+        // only, so their batch roots stay null until the data of the batch is stored, and a
+        // null scan of the subtree nodes tells if all the data are stored. The other subtrees
+        // are within a batch, of which the root is known before the data is stored, so the
+        // data of the batch is checked instead.
+        let data_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
+        let mut start_index = tx.start_entry_index;
+        for (depth, _) in &tx.merkle_nodes {
+            let end_index = start_index + Transaction::num_entries_of_node(*depth) as u64;
+            let data_end = end_index.min(data_end_index);
+            if end_index - start_index > PORA_CHUNK_SIZE as u64 {
+                // The rear batches of the last subtree are only padded on finalization.
+                let full_batch_end = data_end / PORA_CHUNK_SIZE as u64 * PORA_CHUNK_SIZE as u64;
+                let start_batch = (start_index / PORA_CHUNK_SIZE as u64) as usize;
+                let end_batch = (full_batch_end / PORA_CHUNK_SIZE as u64) as usize;
+                if (start_batch < end_batch
+                    && !self
+                        .merkle
+                        .read_recursive()
+                        .pora_chunks_merkle
+                        .is_range_complete(start_batch, end_batch))
+                    || !self.has_entries(full_batch_end, data_end)?
+                {
+                    return Ok(false);
+                }
+            } else if !self.has_entries(start_index, data_end)? {
+                return Ok(false);
+            }
+            start_index = end_index;
+        }
+        Ok(true)
+    }
+
     fn validate_range_proof(&self, tx_seq: u64, data: &ChunkArrayWithProof) -> Result<bool> {
         let tx = self
             .get_tx_by_seq_number(tx_seq)?
@@ -650,6 +914,14 @@ impl LogStoreRead for LogManager {
         self.tx_store.next_tx_seq()
     }
 
+    fn get_highest_finalized_seq(&self) -> crate::error::Result<Option<u64>> {
+        self.tx_store.get_highest_finalized_seq()
+    }
+
+    fn get_finalized_tx_ranges(&self) -> crate::error::Result<Vec<(u64, u64)>> {
+        self.tx_store.get_finalized_tx_ranges()
+    }
+
     fn get_proof_at_root(
         &self,
         root: Option<DataRoot>,
@@ -664,6 +936,10 @@ impl LogStoreRead for LogManager {
         })
     }
 
+    fn get_flow_root_at(&self, tx_seq: u64) -> crate::error::Result<Option<DataRoot>> {
+        self.tx_store.get_flow_root(tx_seq)
+    }
+
     fn get_context(&self) -> crate::error::Result<(DataRoot, u64)> {
         let merkle = self.merkle.read_recursive();
         Ok((
@@ -676,6 +952,26 @@ impl LogStoreRead for LogManager {
         self.tx_store.check_tx_pruned(tx_seq)
     }
 
+    fn check_tx_pinned(&self, tx_seq: u64) -> crate::error::Result<bool> {
+        self.tx_store.check_tx_pinned(tx_seq)
+    }
+
+    fn get_pinned_txs(&self) -> crate::error::Result<Vec<u64>> {
+        self.tx_store.get_pinned_txs()
+    }
+
+    fn get_tx_tags(&self, tx_seq: u64) -> crate::error::Result<Vec<String>> {
+        self.tx_store.get_tx_tags(tx_seq)
+    }
+
+    fn get_tagged_txs(&self, tag: &str) -> crate::error::Result<Vec<u64>> {
+        self.tx_store.get_tagged_txs(tag)
+    }
+
+    fn get_skip_reason(&self, tx_seq: u64) -> crate::error::Result<Option<String>> {
+        self.tx_store.get_skip_reason(tx_seq)
+    }
+
     fn pull_seal_chunk(&self, seal_index_max: usize) -> Result<Option<Vec<SealTask>>> {
         self.flow_store.pull_seal_chunk(seal_index_max)
     }
@@ -684,6 +980,14 @@ impl LogStoreRead for LogManager {
         self.flow_store.get_num_entries()
     }
 
+    fn get_storage_usage(&self) -> Result<u64> {
+        if self.db_paths.is_empty() {
+            return Ok(live_data_size(&*self.flow_db)? + live_data_size(&*self.data_db)?);
+        }
+        // The shards of the data db are in the subdirectories.
+        self.db_paths.iter().map(|path| dir_size(path)).sum()
+    }
+
     fn load_sealed_data(&self, chunk_index: u64) -> Result<Option<MineLoadChunk>> {
         self.flow_store.load_sealed_data(chunk_index)
     }
@@ -695,6 +999,70 @@ impl LogStoreRead for LogManager {
     fn get_shard_config(&self) -> ShardConfig {
         self.flow_store.get_shard_config()
     }
+
+    fn subscribe_finalized(&self) -> watch::Receiver<u64> {
+        self.finalized_sender.subscribe()
+    }
+
+    fn subscribe_finalized_files(&self) -> broadcast::Receiver<FinalizedFile> {
+        self.finalized_files_sender.subscribe()
+    }
+
+    fn subscribe_removed_files(&self) -> broadcast::Receiver<RemovedFiles> {
+        self.removed_files_sender.subscribe()
+    }
+
+    fn export_state(&self, path: &Path) -> crate::error::Result<StateDumpInfo> {
+        // Hold the merkle lock to block writes while taking the db snapshots, and the dump is
+        // written without blocking writes.
+        let merkle = self.merkle.write();
+        let columns = state_dump::snapshot_columns(self.flow_db.as_ref(), self.data_db.as_ref());
+        drop(merkle);
+
+        let info = state_dump::export_state(columns, path)?;
+        info!(?path, ?info, "State exported");
+        Ok(info)
+    }
+
+    fn download_file(&self, tx_seq: u64, path: &Path) -> crate::error::Result<FileDownloadInfo> {
+        let info = file_download::download_file(self, tx_seq, path)?;
+        info!(?path, ?info, "File downloaded");
+        Ok(info)
+    }
+
+    fn export_proof_manifest(&self, tx_seq: u64) -> crate::error::Result<ProofManifest> {
+        Ok(proof_manifest::export_proof_manifest(self, tx_seq)?)
+    }
+
+    fn get_replica_digest(
+        &self,
+        tx_seq: u64,
+        sample_indices: &[u64],
+        recompute: bool,
+    ) -> crate::error::Result<Option<ReplicaDigest>> {
+        Ok(replica::replica_digest(
+            self,
+            tx_seq,
+            sample_indices,
+            recompute,
+        )?)
+    }
+
+    fn get_upload(&self, root: &DataRoot) -> crate::error::Result<Option<StagedUpload>> {
+        Ok(self.upload_store.get(root)?)
+    }
+
+    fn get_upload_segment(
+        &self,
+        root: &DataRoot,
+        index: usize,
+    ) -> crate::error::Result<Option<Vec<u8>>> {
+        Ok(self.upload_store.get_segment(root, index)?)
+    }
+
+    fn get_uploads(&self) -> crate::error::Result<Vec<DataRoot>> {
+        Ok(self.upload_store.get_roots()?)
+    }
 }
 
 impl LogManager {
@@ -705,9 +1073,37 @@ impl LogManager {
     ) -> Result<Self> {
         let mut db_config = DatabaseConfig::with_columns(COL_NUM);
         db_config.enable_statistics = true;
+        let flow_path = flow_path.as_ref();
         let flow_db_source = Arc::new(Database::open(&db_config, flow_path)?);
-        let data_db_source = Arc::new(Database::open(&db_config, data_path)?);
-        Self::new(flow_db_source, data_db_source, config)
+        let data_path = data_path.as_ref();
+        let mut data_db_source: Arc<dyn ZgsKeyValueDB> =
+            Arc::new(Database::open(&db_config, data_path)?);
+        if config.data_db_shards > 0 {
+            data_db_source = Arc::new(ShardedDB::open_rocksdb(
+                data_db_source,
+                &db_config,
+                data_path,
+                config.data_db_shards,
+                config.data_db_shard_size,
+            )?);
+        } else {
+            sharded_db::check_not_sharded(data_path)?;
+        }
+        let mut log_manager = Self::new(flow_db_source, data_db_source, config)?;
+        log_manager.db_paths = vec![flow_path.to_path_buf(), data_path.to_path_buf()];
+        Ok(log_manager)
+    }
+
+    /// Restores the state dump into the empty rocksdb at `flow_path` and `data_path`.
+    pub fn import_rocksdb_state(
+        flow_path: impl AsRef<Path>,
+        data_path: impl AsRef<Path>,
+        state_path: impl AsRef<Path>,
+    ) -> Result<StateDumpInfo> {
+        let db_config = DatabaseConfig::with_columns(COL_NUM);
+        let flow_db = Database::open(&db_config, flow_path)?;
+        let data_db = Database::open(&db_config, data_path)?;
+        state_dump::import_state(&flow_db, &data_db, state_path.as_ref())
     }
 
     pub fn memorydb(config: LogConfig) -> Result<Self> {
@@ -716,12 +1112,13 @@ impl LogManager {
         Self::new(flow_db, data_db, config)
     }
 
-    fn new(
+    pub(crate) fn new(
         flow_db_source: Arc<dyn ZgsKeyValueDB>,
         data_db_source: Arc<dyn ZgsKeyValueDB>,
         config: LogConfig,
     ) -> Result<Self> {
         let tx_store = TransactionStore::new(flow_db_source.clone(), data_db_source.clone())?;
+        let upload_store = UploadStore::new(data_db_source.clone());
         let flow_db = Arc::new(FlowDBStore::new(flow_db_source.clone()));
         let data_db = Arc::new(FlowDBStore::new(data_db_source.clone()));
         let flow_store = Arc::new(FlowStore::new(
@@ -738,12 +1135,32 @@ impl LogManager {
             None
         };
         let mut last_tx_to_insert = None;
+        let mut flow_root_to_restore = None;
 
         let mut pora_chunks_merkle = Merkle::new_with_subtrees(
             flow_db,
             config.flow.merkle_node_cache_capacity,
+            config.flow.merkle_pinned_layers,
             log2_pow2(PORA_CHUNK_SIZE),
         )?;
+        pora_chunks_merkle
+            .set_flush_completed_subtrees(config.flow.merkle_flush_completed_subtrees);
+        if config.flow.merkle_wal {
+            pora_chunks_merkle.set_wal(Arc::new(DbWal::new(flow_db_source.clone())?));
+            // Finish the updates of the last `put_tx` interrupted by a crash, so that it's not
+            // reverted below.
+            let replayed = pora_chunks_merkle.replay_wal()?;
+            if replayed != 0 {
+                info!("replayed merkle WAL: ops={}", replayed);
+            }
+        } else {
+            // Drop the records left when the WAL was enabled, which are not replayed.
+            DbWal::new(flow_db_source.clone())?.truncate()?;
+        }
+        debug!(
+            "merkle node cache footprint after loading: {:?}",
+            pora_chunks_merkle.node_cache_footprint()
+        );
         if let Some(last_tx_seq) = start_tx_seq {
             if !tx_store.check_tx_completed(last_tx_seq)? {
                 // Last tx not finalized, we need to check if its `put_tx` is completed.
@@ -766,7 +1183,13 @@ impl LogManager {
                             current_len
                         );
                     }
-                    Ordering::Equal => {}
+                    Ordering::Equal => {
+                        // The flow is updated, but the node may crash before the flow root is
+                        // written.
+                        if tx_store.get_flow_root(last_tx_seq)?.is_none() {
+                            flow_root_to_restore = Some(last_tx_seq);
+                        }
+                    }
                     Ordering::Greater => {
                         // Flow updates are not complete.
                         // For simplicity, we build the merkle tree for the previous tx and update
@@ -823,10 +1246,19 @@ impl LogManager {
             pora_chunks_merkle.update_last(last_chunk_merkle.root());
         }
         // update the merkle root
-        pora_chunks_merkle.commit(start_tx_seq);
+        pora_chunks_merkle.checkpoint(start_tx_seq)?;
+        if let Some(tx_seq) = flow_root_to_restore {
+            let flow_root = pora_chunks_merkle
+                .root()
+                .0
+                .ok_or_else(|| anyhow!("flow root missing: tx_seq={}", tx_seq))?;
+            info!(tx_seq, ?flow_root, "restore flow root");
+            tx_store.put_flow_root(tx_seq, flow_root)?;
+        }
         let merkle = RwLock::new(MerkleManager {
             pora_chunks_merkle,
             last_chunk_merkle,
+            pora_batch: vec![],
         });
 
         let log_manager = Self {
@@ -834,7 +1266,12 @@ impl LogManager {
             data_db: data_db_source,
             tx_store,
             flow_store,
+            upload_store,
             merkle,
+            finalized_sender: watch::channel(0).0,
+            finalized_files_sender: broadcast::channel(FINALIZED_FILES_CHANNEL_SIZE).0,
+            removed_files_sender: broadcast::channel(FINALIZED_FILES_CHANNEL_SIZE).0,
+            db_paths: vec![],
         };
 
         if let Some(tx) = last_tx_to_insert {
@@ -869,6 +1306,7 @@ impl LogManager {
         flow_index: u64,
         maybe_tx_seq: Option<u64>,
     ) -> Result<FlowProof> {
+        let start_time = Instant::now();
         let merkle = self.merkle.read_recursive();
         let seg_index = sector_to_segment(flow_index);
         let top_proof = match maybe_tx_seq {
@@ -910,7 +1348,9 @@ impl LogManager {
                 )?,
             }
         };
-        entry_proof(&top_proof, &sub_proof)
+        let proof = entry_proof(&top_proof, &sub_proof);
+        metrics::PROOF_GENERATION_LATENCY.update_since(start_time);
+        proof
     }
 
     #[instrument(skip(self, merkle))]
@@ -925,45 +1365,54 @@ impl LogManager {
             return Ok(());
         }
         let start_time = Instant::now();
+        // Drop the updates left by a failed batch.
+        merkle.pora_batch.clear();
 
         self.pad_tx(tx_seq, tx_start_index, &mut *merkle)?;
+        self.record_subtree_list(merkle_list, &mut *merkle)?;
+        // The WAL is written before the batch is applied, so a crash in the middle of the
+        // appends is recovered by replaying the WAL.
+        merkle.apply_pora_batch()?;
 
+        metrics::APPEND_SUBTREE_LIST.update_since(start_time);
+        metrics::TREE_APPEND_LATENCY.update_since(start_time);
+        Ok(())
+    }
+
+    /// Append the subtrees of a tx to `last_chunk_merkle`, and record the updates of
+    /// `pora_chunks_merkle` in its pending batch.
+    fn record_subtree_list(
+        &self,
+        merkle_list: Vec<(usize, DataRoot)>,
+        merkle: &mut MerkleManager,
+    ) -> Result<()> {
         for (subtree_depth, subtree_root) in merkle_list {
             let subtree_size = 1 << (subtree_depth - 1);
             if merkle.last_chunk_merkle.leaves() + subtree_size <= PORA_CHUNK_SIZE {
                 merkle
                     .last_chunk_merkle
                     .append_subtree(subtree_depth, OptionalHash::some(subtree_root))?;
+                let last_chunk_root = merkle.last_chunk_merkle.root();
                 if merkle.last_chunk_merkle.leaves() == subtree_size {
                     // `last_chunk_merkle` was empty, so this is a new leaf in the top_tree.
-
-                    merkle
-                        .pora_chunks_merkle
-                        .append_subtree(1, merkle.last_chunk_merkle.root())?;
+                    merkle.pora_append_subtree(1, last_chunk_root);
                 } else {
-                    merkle
-                        .pora_chunks_merkle
-                        .update_last(merkle.last_chunk_merkle.root());
+                    merkle.pora_update_last(last_chunk_root);
                 }
                 if merkle.last_chunk_merkle.leaves() == PORA_CHUNK_SIZE {
-                    self.complete_last_chunk_merkle(
-                        merkle.pora_chunks_merkle.leaves() - 1,
-                        &mut *merkle,
-                    )?;
+                    self.complete_last_chunk_merkle(merkle.pora_leaves() - 1, merkle)?;
                 }
             } else {
                 // `last_chunk_merkle` has been padded here, so a subtree should not be across
                 // the chunks boundary.
                 assert_eq!(merkle.last_chunk_merkle.leaves(), 0);
                 assert!(subtree_size >= PORA_CHUNK_SIZE);
-                merkle.pora_chunks_merkle.append_subtree(
+                merkle.pora_append_subtree(
                     subtree_depth - log2_pow2(PORA_CHUNK_SIZE),
                     OptionalHash::some(subtree_root),
-                )?;
+                );
             }
         }
-
-        metrics::APPEND_SUBTREE_LIST.update_since(start_time);
         Ok(())
     }
 
@@ -976,7 +1425,7 @@ impl LogManager {
         let pad_size = tx_start_index - tx_start_flow_index;
         trace!(
             "before pad_tx {} {}",
-            merkle.pora_chunks_merkle.leaves(),
+            merkle.pora_leaves(),
             merkle.last_chunk_merkle.leaves()
         );
         let mut pad_list = vec![];
@@ -996,25 +1445,23 @@ impl LogManager {
                     is_full_empty = false;
                     let pad_leaves = data_to_merkle_leaves(&pad_data)?;
                     merkle.last_chunk_merkle.append_list(pad_leaves);
-                    merkle
-                        .pora_chunks_merkle
-                        .update_last(merkle.last_chunk_merkle.root());
+                    let last_chunk_root = merkle.last_chunk_merkle.root();
+                    merkle.pora_update_last(last_chunk_root);
                 } else {
                     if last_chunk_pad != 0 {
                         is_full_empty = false;
                         // Pad the last chunk.
                         let last_chunk_leaves = data_to_merkle_leaves(&pad_data[..last_chunk_pad])?;
                         merkle.last_chunk_merkle.append_list(last_chunk_leaves);
-                        merkle
-                            .pora_chunks_merkle
-                            .update_last(merkle.last_chunk_merkle.root());
-                        completed_chunk_index = Some(merkle.pora_chunks_merkle.leaves() - 1);
+                        let last_chunk_root = merkle.last_chunk_merkle.root();
+                        merkle.pora_update_last(last_chunk_root);
+                        completed_chunk_index = Some(merkle.pora_leaves() - 1);
                     }
 
                     // Pad with more complete chunks.
                     let mut start_index = last_chunk_pad / ENTRY_SIZE;
                     while pad_data.len() >= (start_index + PORA_CHUNK_SIZE) * ENTRY_SIZE {
-                        merkle.pora_chunks_merkle.append(PAD_SEGMENT_ROOT.clone());
+                        merkle.pora_append(PAD_SEGMENT_ROOT.clone());
                         start_index += PORA_CHUNK_SIZE;
                     }
                     assert_eq!(pad_data.len(), start_index * ENTRY_SIZE);
@@ -1044,7 +1491,7 @@ impl LogManager {
         }
         trace!(
             "after pad_tx {} {}",
-            merkle.pora_chunks_merkle.leaves(),
+            merkle.pora_leaves(),
             merkle.last_chunk_merkle.leaves()
         );
 
@@ -1137,6 +1584,69 @@ impl LogManager {
         &self.flow_store
     }
 
+    /// Corrupt the stored data of the entry at the flow `index`, e.g. to test the detection of
+    /// the corrupt files.
+    #[cfg(feature = "test-utils")]
+    pub fn corrupt_entry(&self, index: u64) -> Result<()> {
+        self.flow_store.corrupt_entry(index)
+    }
+
+    /// Simulate a crash in `put_tx` after the merkle WAL of the tx is written but only the
+    /// first `applied` updates are applied.
+    #[cfg(test)]
+    pub fn put_tx_with_crash(&self, tx: Transaction, applied: usize) -> Result<()> {
+        use append_merkle::WalRecord;
+
+        let mut merkle = self.merkle.write();
+        self.tx_store.put_tx(tx.clone())?;
+        merkle.pora_batch.clear();
+        self.pad_tx(tx.seq, tx.start_entry_index, &mut *merkle)?;
+        self.record_subtree_list(tx.merkle_nodes, &mut *merkle)?;
+
+        let mut leaves_before = merkle.pora_chunks_merkle.leaves();
+        let records: Vec<_> = merkle
+            .pora_batch
+            .iter()
+            .map(|op| {
+                let record = WalRecord {
+                    leaves_before,
+                    op: op.clone(),
+                };
+                leaves_before += op.leaves();
+                record
+            })
+            .collect();
+        DbWal::new(self.flow_db.clone())?.write(&records)?;
+        for op in std::mem::take(&mut merkle.pora_batch)
+            .into_iter()
+            .take(applied)
+        {
+            let pora = &mut merkle.pora_chunks_merkle;
+            match op {
+                WalOp::AppendList(leaves) => pora.append_list(leaves),
+                WalOp::AppendSubtree { depth, root } => pora.append_subtree(depth, root)?,
+                WalOp::UpdateLast(leaf) => pora.update_last(leaf),
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the entry batch index ranges `[start, end)` that contain the data of pinned txs.
+    fn pinned_batch_ranges(&self) -> Result<Vec<(u64, u64)>> {
+        let mut ranges = Vec::new();
+        for tx_seq in self.tx_store.get_pinned_txs()? {
+            let tx = self
+                .tx_store
+                .get_tx_by_seq_number(tx_seq)?
+                .ok_or_else(|| anyhow!("pinned tx missing: tx_seq={}", tx_seq))?;
+            let start = tx.start_entry_index / PORA_CHUNK_SIZE as u64;
+            let end =
+                (tx.start_entry_index + tx.num_entries() as u64).div_ceil(PORA_CHUNK_SIZE as u64);
+            ranges.push((start, end));
+        }
+        Ok(ranges)
+    }
+
     fn padding_rear_data(&self, tx: &Transaction) -> Result<()> {
         let (chunks, _) = compute_padded_chunk_size(tx.size as usize);
         let (segments_for_proof, last_segment_size_for_proof) =
@@ -1204,6 +1714,17 @@ impl LogManager {
 
     fn finalize_tx_common(&self, tx: &Transaction) -> Result<()> {
         let tx_seq = tx.seq;
+        // A skipped tx, e.g. of a malformed size, is never downloaded or padded.
+        if let Some(reason) = self.tx_store.get_skip_reason(tx_seq)? {
+            bail!("tx skipped: {}", reason);
+        }
+        if tx.size == 0 {
+            // An empty file has no data to pad or check.
+            self.tx_store.finalize_tx(tx_seq)?;
+            self.finalized_sender.send_modify(|num| *num += 1);
+            self.notify_finalized(tx_seq, tx.data_merkle_root);
+            return Ok(());
+        }
         self.padding_rear_data(tx)?;
 
         let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
@@ -1226,12 +1747,29 @@ impl LogManager {
             }
 
             self.tx_store.finalize_tx(tx_seq)?;
+            self.finalized_sender.send_modify(|num| *num += 1);
+            self.notify_finalized(tx_seq, tx.data_merkle_root);
             Ok(())
         } else {
             bail!("data missing")
         }
     }
 
+    fn notify_finalized(&self, tx_seq: u64, data_root: DataRoot) {
+        // Sending fails only if there is no subscriber.
+        let _ = self
+            .finalized_files_sender
+            .send(FinalizedFile { tx_seq, data_root });
+    }
+
+    fn notify_removed(&self, start_tx_seq: u64, end_tx_seq: u64) {
+        // Sending fails only if there is no subscriber.
+        let _ = self.removed_files_sender.send(RemovedFiles {
+            start_tx_seq,
+            end_tx_seq,
+        });
+    }
+
     fn copy_tx_and_finalize(&self, from_tx_seq: u64, to_tx_seq_list: Vec<u64>) -> Result<()> {
         let start_time = Instant::now();
 
@@ -1281,8 +1819,11 @@ impl LogManager {
         }
         // num_entries() includes the rear padding data, so no need for more padding.
 
+        metrics::DEDUP_LINKED_TXS.inc(to_tx_offset_list.len());
         for (seq, _) in to_tx_offset_list {
             self.tx_store.finalize_tx(seq)?;
+            self.finalized_sender.send_modify(|num| *num += 1);
+            self.notify_finalized(seq, old_tx.data_merkle_root);
         }
 
         metrics::COPY_TX_AND_FINALIZE.update_since(start_time);
@@ -1309,6 +1850,23 @@ impl LogManager {
             .insert_subtree_list_for_batch(index, to_insert_subtrees)
     }
 
+    /// Return `true` if all the entries in `[start, end)` are stored locally.
+    fn has_entries(&self, start: u64, end: u64) -> Result<bool> {
+        if end <= start {
+            return Ok(true);
+        }
+        for (batch_start, batch_end) in batch_iter(start, end, PORA_CHUNK_SIZE) {
+            if self
+                .flow_store
+                .get_entries(batch_start, batch_end)?
+                .is_none()
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     fn check_data_completed(&self, start: u64, end: u64) -> Result<bool> {
         for (batch_start, batch_end) in batch_iter_sharded(
             start,
@@ -1394,6 +1952,33 @@ pub fn bytes_to_entries(size_bytes: u64) -> u64 {
     }
 }
 
+/// Returns the total size of the keys and values in `db`.
+fn live_data_size(db: &dyn ZgsKeyValueDB) -> Result<u64> {
+    let mut size = 0;
+    for col in 0..COL_NUM {
+        for item in db.iter(col) {
+            let (key, value) = item?;
+            size += (key.len() + value.len()) as u64;
+        }
+    }
+    Ok(size)
+}
+
+/// Returns the total size of the files in `path` and its subdirectories.
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
 fn entry_proof(top_proof: &FlowProof, sub_proof: &FlowProof) -> Result<FlowProof> {
     if top_proof.item() != sub_proof.root() {
         bail!(
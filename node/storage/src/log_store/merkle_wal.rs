@@ -0,0 +1,150 @@
+use crate::log_store::log_manager::COL_MISC;
+use crate::ZgsKeyValueDB;
+use anyhow::{anyhow, bail, Result};
+use append_merkle::{AppendWal, OptionalHash, WalOp, WalRecord};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Prefix of the key of a WAL record, followed by the record index.
+const MERKLE_WAL_KEY_PREFIX: &str = "merkle_wal_";
+/// Length of an encoded `OptionalHash`.
+const HASH_LEN: usize = 33;
+
+const OP_APPEND_LIST: u8 = 0;
+const OP_APPEND_SUBTREE: u8 = 1;
+const OP_UPDATE_LAST: u8 = 2;
+
+fn merkle_wal_key(index: u64) -> Vec<u8> {
+    [MERKLE_WAL_KEY_PREFIX.as_bytes(), &index.to_be_bytes()].concat()
+}
+
+/// The write-ahead log of the flow merkle tree in the db of its nodes, so that the records of
+/// a batch are written in one db transaction and survive a restart.
+pub struct DbWal {
+    db: Arc<dyn ZgsKeyValueDB>,
+    /// Index of the next record to write.
+    next_index: Mutex<u64>,
+}
+
+impl DbWal {
+    pub fn new(db: Arc<dyn ZgsKeyValueDB>) -> Result<Self> {
+        let next_index = match db
+            .iter_with_prefix(COL_MISC, MERKLE_WAL_KEY_PREFIX.as_bytes())
+            .last()
+        {
+            Some(item) => decode_index(&item?.0)? + 1,
+            None => 0,
+        };
+        Ok(Self {
+            db,
+            next_index: Mutex::new(next_index),
+        })
+    }
+}
+
+impl AppendWal<OptionalHash> for DbWal {
+    fn write(&self, records: &[WalRecord<OptionalHash>]) -> Result<()> {
+        let mut next_index = self.next_index.lock();
+        let mut tx = self.db.transaction();
+        for (i, record) in records.iter().enumerate() {
+            tx.put(
+                COL_MISC,
+                &merkle_wal_key(*next_index + i as u64),
+                &encode_record(record),
+            );
+        }
+        self.db.write(tx)?;
+        *next_index += records.len() as u64;
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Vec<WalRecord<OptionalHash>>> {
+        self.db
+            .iter_with_prefix(COL_MISC, MERKLE_WAL_KEY_PREFIX.as_bytes())
+            .map(|item| decode_record(&item?.1))
+            .collect()
+    }
+
+    fn truncate(&self) -> Result<()> {
+        let mut next_index = self.next_index.lock();
+        if *next_index == 0 {
+            return Ok(());
+        }
+        self.db
+            .delete_with_prefix(COL_MISC, MERKLE_WAL_KEY_PREFIX.as_bytes())?;
+        *next_index = 0;
+        Ok(())
+    }
+}
+
+fn decode_index(key: &[u8]) -> Result<u64> {
+    let index = key
+        .strip_prefix(MERKLE_WAL_KEY_PREFIX.as_bytes())
+        .and_then(|index| index.try_into().ok())
+        .ok_or_else(|| anyhow!("invalid merkle WAL key: {:?}", key))?;
+    Ok(u64::from_be_bytes(index))
+}
+
+fn encode_record(record: &WalRecord<OptionalHash>) -> Vec<u8> {
+    let mut bytes = (record.leaves_before as u64).to_be_bytes().to_vec();
+    match &record.op {
+        WalOp::AppendList(leaves) => {
+            bytes.push(OP_APPEND_LIST);
+            for leaf in leaves {
+                bytes.extend_from_slice(&leaf.as_bytes());
+            }
+        }
+        WalOp::AppendSubtree { depth, root } => {
+            bytes.push(OP_APPEND_SUBTREE);
+            bytes.extend_from_slice(&(*depth as u64).to_be_bytes());
+            bytes.extend_from_slice(&root.as_bytes());
+        }
+        WalOp::UpdateLast(leaf) => {
+            bytes.push(OP_UPDATE_LAST);
+            bytes.extend_from_slice(&leaf.as_bytes());
+        }
+    }
+    bytes
+}
+
+fn decode_record(bytes: &[u8]) -> Result<WalRecord<OptionalHash>> {
+    if bytes.len() < 9 {
+        bail!("merkle WAL record too short: len={}", bytes.len());
+    }
+    let leaves_before = u64::from_be_bytes(bytes[..8].try_into().unwrap()) as usize;
+    let payload = &bytes[9..];
+    let decode_hash = |bytes: &[u8]| -> Result<OptionalHash> {
+        OptionalHash::from_bytes(
+            bytes
+                .try_into()
+                .map_err(|_| anyhow!("invalid merkle WAL hash length: {}", bytes.len()))?,
+        )
+        .map_err(|e| anyhow!("invalid merkle WAL hash: {}", e))
+    };
+    let op = match bytes[8] {
+        OP_APPEND_LIST => {
+            if payload.len() % HASH_LEN != 0 {
+                bail!("invalid merkle WAL leaves length: {}", payload.len());
+            }
+            WalOp::AppendList(
+                payload
+                    .chunks_exact(HASH_LEN)
+                    .map(decode_hash)
+                    .collect::<Result<_>>()?,
+            )
+        }
+        OP_APPEND_SUBTREE => {
+            if payload.len() != 8 + HASH_LEN {
+                bail!("invalid merkle WAL subtree length: {}", payload.len());
+            }
+            WalOp::AppendSubtree {
+                depth: u64::from_be_bytes(payload[..8].try_into().unwrap()) as usize,
+                root: decode_hash(&payload[8..])?,
+            }
+        }
+        OP_UPDATE_LAST => WalOp::UpdateLast(decode_hash(payload)?),
+        op => bail!("unknown merkle WAL op: {}", op),
+    };
+    op.check(leaves_before)?;
+    Ok(WalRecord { leaves_before, op })
+}
@@ -1,6 +1,7 @@
 use std::sync::Arc;
+use std::time::Instant;
 
-use metrics::{register_timer, Gauge, GaugeUsize, Timer};
+use metrics::{register_timer, Counter, CounterUsize, Gauge, GaugeUsize, Timer};
 
 lazy_static::lazy_static! {
     pub static ref PUT_TX: Arc<dyn Timer> = register_timer("log_store_put_tx");
@@ -21,6 +22,10 @@ lazy_static::lazy_static! {
     pub static ref COPY_TX_AND_FINALIZE: Arc<dyn Timer> =
         register_timer("log_store_log_manager_copy_tx_and_finalize");
 
+    /// Number of txs finalized with the copied data of a tx of the same data root.
+    pub static ref DEDUP_LINKED_TXS: Arc<dyn Counter<usize>> =
+        CounterUsize::register("log_store_log_manager_dedup_linked_txs");
+
     pub static ref PAD_TX: Arc<dyn Timer> = register_timer("log_store_log_manager_pad_tx");
 
     pub static ref PUT_BATCH_ROOT_LIST: Arc<dyn Timer> = register_timer("log_store_flow_store_put_batch_root_list");
@@ -40,4 +45,76 @@ lazy_static::lazy_static! {
     pub static ref DATA_TO_MERKLE_LEAVES_SIZE: Arc<dyn Gauge<usize>> = GaugeUsize::register("log_store_data_to_merkle_leaves_size");
 
     pub static ref TX_BY_SEQ_NUMBER: Arc<dyn Timer> = register_timer("log_store_tx_store_get_tx_by_seq_number");
+
+    /// Number of the orphaned records found by the last garbage collection, including dry runs.
+    pub static ref GC_ORPHANED_RECORDS: Arc<dyn Gauge<usize>> = GaugeUsize::register("log_store_gc_orphaned_records");
+
+    pub static ref GC_BYTES_FREED: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_gc_bytes_freed");
+}
+
+/// Upper bounds (us) of the latency buckets, from sub-millisecond reads to multi-second
+/// appends of large files.
+const LATENCY_BUCKETS_US: [u64; 16] = [
+    50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 10_000_000,
+];
+
+/// A lightweight latency histogram of fixed buckets, of which the counters are registered in
+/// the metrics group of its name, so that they are exported as other metrics:
+///
+/// - `<group>.le_<bound>`: number of samples no more than `bound` us, for each of
+///   `LATENCY_BUCKETS_US`.
+/// - `<group>.le_inf`: number of all the samples.
+/// - `<group>.sum_us`: total latency of all the samples in us.
+pub struct LatencyHistogram {
+    buckets: Vec<(u64, Arc<dyn Counter<usize>>)>,
+    count: Arc<dyn Counter<usize>>,
+    sum_us: Arc<dyn Counter<usize>>,
+}
+
+impl LatencyHistogram {
+    fn register(group: &str) -> Self {
+        Self {
+            buckets: LATENCY_BUCKETS_US
+                .iter()
+                .map(|bound| {
+                    let name = format!("le_{}", bound);
+                    (*bound, CounterUsize::register_with_group(group, &name))
+                })
+                .collect(),
+            count: CounterUsize::register_with_group(group, "le_inf"),
+            sum_us: CounterUsize::register_with_group(group, "sum_us"),
+        }
+    }
+
+    /// Records the elapsed time since `start_time`.
+    pub fn update_since(&self, start_time: Instant) {
+        let latency_us = start_time.elapsed().as_micros() as u64;
+        for (bound, counter) in &self.buckets {
+            if latency_us <= *bound {
+                counter.inc(1);
+            }
+        }
+        self.count.inc(1);
+        self.sum_us.inc(latency_us as usize);
+    }
+}
+
+// Latency histograms, so that operators could check the tail latency of the storage.
+lazy_static::lazy_static! {
+    /// Latency (us) to generate a flow merkle proof for a single entry.
+    pub static ref PROOF_GENERATION_LATENCY: LatencyHistogram =
+        LatencyHistogram::register("log_store_proof_generation_latency");
+
+    /// Latency (us) to read a range of chunks of a tx, e.g. a segment.
+    pub static ref SEGMENT_READ_LATENCY: LatencyHistogram =
+        LatencyHistogram::register("log_store_segment_read_latency");
+
+    /// Latency (us) to write a range of chunks of a tx, including the merkle tree update.
+    pub static ref SEGMENT_WRITE_LATENCY: LatencyHistogram =
+        LatencyHistogram::register("log_store_segment_write_latency");
+
+    /// Latency (us) to append the subtree list of a new tx to the flow merkle tree.
+    pub static ref TREE_APPEND_LATENCY: LatencyHistogram =
+        LatencyHistogram::register("log_store_tree_append_latency");
 }
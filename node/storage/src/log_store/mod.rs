@@ -2,11 +2,20 @@ use crate::{config::ShardConfig, log_store::load_chunk::EntryBatch};
 
 use append_merkle::OptionalHash;
 use ethereum_types::H256;
+use file_download::FileDownloadInfo;
 use flow_store::PadPair;
+use gc::GcReport;
+use proof_manifest::ProofManifest;
+use replica::ReplicaDigest;
+use serde::{Deserialize, Serialize};
 use shared_types::{
     Chunk, ChunkArray, ChunkArrayWithProof, ChunkWithProof, DataRoot, FlowProof, FlowRangeProof,
     Transaction,
 };
+use state_dump::StateDumpInfo;
+use std::path::Path;
+use tokio::sync::{broadcast, watch};
+use upload_store::StagedUpload;
 use zgs_spec::{BYTES_PER_SEAL, SEALS_PER_LOAD};
 
 use crate::error::Result;
@@ -14,14 +23,22 @@ use crate::error::Result;
 use self::tx_store::{BlockHashAndSubmissionIndex, TxStatus};
 
 pub mod config;
+pub mod file_download;
 mod flow_store;
+pub mod gc;
 pub mod load_chunk;
 pub mod log_manager;
+mod merkle_wal;
 mod metrics;
+pub mod proof_manifest;
+pub mod replica;
 mod seal_task_manager;
+pub mod sharded_db;
+pub mod state_dump;
 #[cfg(test)]
 mod tests;
 pub mod tx_store;
+pub mod upload_store;
 
 /// The trait to read the transactions already appended to the log.
 ///
@@ -76,10 +93,39 @@ pub trait LogStoreRead: LogStoreChunkRead {
 
     fn check_tx_pruned(&self, tx_seq: u64) -> Result<bool>;
 
+    /// Recompute the data merkle root of a file from its stored chunks.
+    /// Return `None` if the transaction or any chunk of the file is missing.
+    fn compute_data_root(&self, tx_seq: u64) -> Result<Option<DataRoot>>;
+
+    /// Return `true` if all the nodes of the file merkle tree are known from the stored chunks,
+    /// so the whole file is reconstructable. A pruned or missing file is not complete.
+    fn check_tx_merkle_complete(&self, tx_seq: u64) -> Result<bool>;
+
     fn get_tx_status(&self, tx_seq: u64) -> Result<Option<TxStatus>>;
 
+    fn check_tx_pinned(&self, tx_seq: u64) -> Result<bool>;
+
+    /// Return the sorted list of pinned tx seqs.
+    fn get_pinned_txs(&self) -> Result<Vec<u64>>;
+
+    fn get_tx_tags(&self, tx_seq: u64) -> Result<Vec<String>>;
+
+    /// Return the sorted list of the tx seqs with `tag`.
+    fn get_tagged_txs(&self, tag: &str) -> Result<Vec<u64>>;
+
+    /// Return the reason why the tx is skipped, or `None` if it's not skipped.
+    fn get_skip_reason(&self, tx_seq: u64) -> Result<Option<String>>;
+
     fn next_tx_seq(&self) -> u64;
 
+    /// Return the highest tx seq up to which the data of all txs is finalized, which lags
+    /// `next_tx_seq` while the data is being downloaded. The pruned and skipped txs do not
+    /// hold it back.
+    fn get_highest_finalized_seq(&self) -> Result<Option<u64>>;
+
+    /// Return the ranges `[start, end)` of the finalized tx seqs in order.
+    fn get_finalized_tx_ranges(&self) -> Result<Vec<(u64, u64)>>;
+
     fn get_sync_progress(&self) -> Result<Option<(u64, H256)>>;
 
     fn get_log_latest_block_number(&self) -> Result<Option<u64>>;
@@ -97,6 +143,10 @@ pub trait LogStoreRead: LogStoreChunkRead {
         length: u64,
     ) -> Result<FlowRangeProof>;
 
+    /// Return the flow root right after the tx of `tx_seq` is appended, which is persisted
+    /// rather than recomputed, or `None` if the tx does not exist yet.
+    fn get_flow_root_at(&self, tx_seq: u64) -> Result<Option<DataRoot>>;
+
     /// Return flow root and length.
     fn get_context(&self) -> Result<(DataRoot, u64)>;
 
@@ -104,11 +154,73 @@ pub trait LogStoreRead: LogStoreChunkRead {
 
     fn get_num_entries(&self) -> Result<u64>;
 
+    /// Return the bytes of the database files on disk, or the bytes of the live data if the
+    /// database is in memory.
+    fn get_storage_usage(&self) -> Result<u64>;
+
     fn load_sealed_data(&self, chunk_index: u64) -> Result<Option<MineLoadChunk>>;
 
     fn get_data_by_node_index(&self, start_index: u64) -> Result<Option<EntryBatch>>;
 
     fn get_shard_config(&self) -> ShardConfig;
+
+    /// Subscribe to the number of transactions finalized since startup, which changes
+    /// whenever the finalized data grows.
+    fn subscribe_finalized(&self) -> watch::Receiver<u64>;
+
+    /// Subscribe to the files finalized from now on. A receiver that falls behind misses the
+    /// oldest files instead of blocking the finalization.
+    fn subscribe_finalized_files(&self) -> broadcast::Receiver<FinalizedFile>;
+
+    /// Subscribe to the finalized files removed from now on, i.e. pruned or reverted. A
+    /// receiver that falls behind misses the oldest removals.
+    fn subscribe_removed_files(&self) -> broadcast::Receiver<RemovedFiles>;
+
+    /// Write a consistent dump of the whole store to `path` without blocking writes for long.
+    fn export_state(&self, path: &Path) -> Result<StateDumpInfo>;
+
+    /// Write the data of a finalized or fully stored file to `path` after verifying it against
+    /// the tx root. Fail with the missing segments if the file is not complete.
+    fn download_file(&self, tx_seq: u64, path: &Path) -> Result<FileDownloadInfo>;
+
+    /// Build the manifest of the roots of all segments of a fully stored file, against which
+    /// the file bytes could be verified offline, see `proof_manifest::verify_proof_manifest`.
+    fn export_proof_manifest(&self, tx_seq: u64) -> Result<ProofManifest>;
+
+    /// Build the digest of the stored data of a tx with the leaves of the chunks at
+    /// `sample_indices`, to compare with the digest of another replica, see
+    /// `replica::compare_replica_digests`. The root is recomputed from all the stored chunks
+    /// only if `recompute`. Return `None` if the tx does not exist.
+    fn get_replica_digest(
+        &self,
+        tx_seq: u64,
+        sample_indices: &[u64],
+        recompute: bool,
+    ) -> Result<Option<ReplicaDigest>>;
+
+    /// Return the chunked upload of `root` staged in the store, or `None` if not started.
+    fn get_upload(&self, root: &DataRoot) -> Result<Option<StagedUpload>>;
+
+    fn get_upload_segment(&self, root: &DataRoot, index: usize) -> Result<Option<Vec<u8>>>;
+
+    /// Return the roots of all the chunked uploads staged in the store.
+    fn get_uploads(&self) -> Result<Vec<DataRoot>>;
+}
+
+/// A file of which all the data is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FinalizedFile {
+    pub tx_seq: u64,
+    pub data_root: DataRoot,
+}
+
+/// The files of the tx seqs in `[start_tx_seq, end_tx_seq)` of which the data is no longer
+/// stored, i.e. a pruned file, or all the files from the first reverted tx.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RemovedFiles {
+    pub start_tx_seq: u64,
+    pub end_tx_seq: u64,
 }
 
 pub trait LogStoreChunkRead {
@@ -155,9 +267,29 @@ pub trait LogStoreWrite: LogStoreChunkWrite {
     /// the caller is supposed to track chunk statuses and call this after storing all the chunks.
     fn finalize_tx(&self, tx_seq: u64) -> Result<()>;
     fn finalize_tx_with_hash(&self, tx_seq: u64, tx_hash: H256) -> Result<bool>;
+    /// Finalize a tx with the data copied from a finalized tx of the same data root, if any,
+    /// so that the data of a re-uploaded file is not downloaded again.
+    /// Return `false` if the tx is finalized already or no data of the same root is stored.
+    fn link_same_root_tx(&self, tx_seq: u64) -> Result<bool>;
     /// Mark the tx as pruned, meaning the data will not be stored.
+    /// This is a no-op for pinned txs.
     fn prune_tx(&self, tx_seq: u64) -> Result<()>;
 
+    /// Mark the tx as skipped, meaning the data will not be downloaded from peers, and record
+    /// the reason.
+    fn skip_tx(&self, tx_seq: u64, reason: &str) -> Result<()>;
+
+    /// Pin a tx so that its data is exempt from pruning. The pins are persisted.
+    /// Return `false` if the tx has been pinned already, or an error if the tx is pruned.
+    fn pin_tx(&self, tx_seq: u64) -> Result<bool>;
+
+    /// Return `false` if the tx is not pinned.
+    fn unpin_tx(&self, tx_seq: u64) -> Result<bool>;
+
+    /// Replace the operator-defined tags of a tx, or remove them if `tags` is empty. The tags
+    /// are persisted and dropped together with the reverted tx.
+    fn set_tx_tags(&self, tx_seq: u64, tags: Vec<String>) -> Result<()>;
+
     /// Store the progress of synced block number and its hash.
     fn put_sync_progress(&self, progress: (u64, H256, Option<Option<u64>>)) -> Result<()>;
 
@@ -184,6 +316,25 @@ pub trait LogStoreWrite: LogStoreChunkWrite {
     fn submit_seal_result(&self, answers: Vec<SealAnswer>) -> Result<()>;
 
     fn start_padding(&self, executor: &task_executor::TaskExecutor);
+
+    /// Reclaim the records that nothing references anymore, e.g. left behind by an interrupted
+    /// revert, without blocking writes for long. Only count them if `dry_run`.
+    fn gc_store(&self, dry_run: bool) -> Result<GcReport>;
+
+    /// Stage a segment of a chunked upload, which is not verified yet and hence not written
+    /// into the flow, see `upload_store`.
+    fn put_upload_segment(
+        &self,
+        root: &DataRoot,
+        file_size: usize,
+        chunks_per_segment: usize,
+        index: usize,
+        segment_root: H256,
+        data: &[u8],
+    ) -> Result<()>;
+
+    /// Remove all the staged segments of a chunked upload.
+    fn remove_upload(&self, root: &DataRoot) -> Result<()>;
 }
 
 pub trait LogStoreChunkWrite {
@@ -200,6 +351,7 @@ pub trait LogStoreChunkWrite {
 
     /// Delete a list of chunk batches from the db.
     /// `batch_list` is a `Vec` of entry batch index.
+    /// Batches that contain the data of pinned txs are skipped.
     fn remove_chunks_batch(&self, batch_list: &[u64]) -> Result<()>;
 }
 
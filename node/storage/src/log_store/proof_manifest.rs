@@ -0,0 +1,182 @@
+//! Exports a self-describing manifest that proves the integrity of a whole file, so that the
+//! bytes of an archived file can be verified offline against the file root without a node.
+//!
+//! The manifest lists the roots of all the segments of the file, which is a compact multi-proof
+//! of every leaf: the segment roots are bagged into the file root, and the leaves of each
+//! segment are hashed into its root. So a verifier only needs the manifest and the file bytes.
+//!
+//! The manifest is serialized in JSON. `format` and `version` tell the layout, and the version
+//! is bumped whenever the fields or how the roots are computed change.
+
+use crate::log_store::log_manager::{data_to_merkle_leaves_h256, FileMerkleTree, PORA_CHUNK_SIZE};
+use crate::log_store::LogStoreRead;
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use shared_types::{bytes_to_chunks, DataRoot, CHUNK_SIZE};
+
+pub const PROOF_MANIFEST_FORMAT: &str = "0g-storage-proof-manifest";
+pub const PROOF_MANIFEST_VERSION: u32 = 1;
+/// Hash of the leaves and nodes of the file merkle tree.
+pub const PROOF_MANIFEST_HASH: &str = "keccak256";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofManifest {
+    pub format: String,
+    pub version: u32,
+    pub hash: String,
+    pub tx_seq: u64,
+    pub data_root: DataRoot,
+    pub size_bytes: u64,
+    /// Number of the chunks of the file, of which the last one is padded with zeros.
+    pub leaf_count: u64,
+    pub leaves_per_segment: u64,
+    /// Roots of the segments in order, of which the last one is of the remaining leaves.
+    pub segment_roots: Vec<DataRoot>,
+}
+
+/// Builds the manifest of the file of `tx_seq` from the stored data, which must be complete.
+pub(crate) fn export_proof_manifest(
+    store: &dyn LogStoreRead,
+    tx_seq: u64,
+) -> Result<ProofManifest> {
+    let tx = store
+        .get_tx_by_seq_number(tx_seq)?
+        .ok_or_else(|| anyhow!("Transaction {} not found", tx_seq))?;
+    let num_chunks = bytes_to_chunks(tx.size as usize);
+
+    let mut segment_roots =
+        Vec::with_capacity((num_chunks + PORA_CHUNK_SIZE - 1) / PORA_CHUNK_SIZE);
+    let mut missing_segments = vec![];
+    for (segment_index, start_index) in (0..num_chunks).step_by(PORA_CHUNK_SIZE).enumerate() {
+        let end_index = (start_index + PORA_CHUNK_SIZE).min(num_chunks);
+        match store.get_chunks_by_tx_and_index_range(tx_seq, start_index, end_index)? {
+            Some(chunks) if missing_segments.is_empty() => {
+                segment_roots.push(segment_root(&chunks.data)?)
+            }
+            Some(_) => {}
+            None => missing_segments.push(segment_index),
+        }
+    }
+
+    if !missing_segments.is_empty() {
+        bail!(
+            "File {} is not complete, missing segments {:?} of {}",
+            tx_seq,
+            missing_segments,
+            (num_chunks + PORA_CHUNK_SIZE - 1) / PORA_CHUNK_SIZE
+        );
+    }
+
+    let manifest = ProofManifest {
+        format: PROOF_MANIFEST_FORMAT.to_string(),
+        version: PROOF_MANIFEST_VERSION,
+        hash: PROOF_MANIFEST_HASH.to_string(),
+        tx_seq,
+        data_root: tx.data_merkle_root,
+        size_bytes: tx.size,
+        leaf_count: num_chunks as u64,
+        leaves_per_segment: PORA_CHUNK_SIZE as u64,
+        segment_roots,
+    };
+
+    // do not export a manifest that would never verify, e.g. the stored data is corrupt
+    let data_root = manifest.bagged_root();
+    if data_root != tx.data_merkle_root {
+        bail!(
+            "File {} root mismatch, computed {:?}, expected {:?}",
+            tx_seq,
+            data_root,
+            tx.data_merkle_root
+        );
+    }
+
+    Ok(manifest)
+}
+
+/// Verifies the bytes of a file against the manifest without a node, and fails with the first
+/// mismatched segment if any.
+pub fn verify_proof_manifest(manifest: &ProofManifest, data: &[u8]) -> Result<()> {
+    if manifest.format != PROOF_MANIFEST_FORMAT {
+        bail!("Unknown manifest format {}", manifest.format);
+    }
+    if manifest.version != PROOF_MANIFEST_VERSION {
+        bail!(
+            "Unsupported manifest version {}, expected {}",
+            manifest.version,
+            PROOF_MANIFEST_VERSION
+        );
+    }
+    if manifest.hash != PROOF_MANIFEST_HASH {
+        bail!("Unsupported manifest hash {}", manifest.hash);
+    }
+    if data.len() as u64 != manifest.size_bytes {
+        bail!(
+            "File size mismatch, actual {}, expected {}",
+            data.len(),
+            manifest.size_bytes
+        );
+    }
+
+    let leaf_count = bytes_to_chunks(data.len()) as u64;
+    let segment_size = manifest.leaves_per_segment;
+    if manifest.leaf_count != leaf_count
+        || !segment_size.is_power_of_two()
+        || manifest.segment_roots.len() as u64 != (leaf_count + segment_size - 1) / segment_size
+    {
+        bail!(
+            "Manifest layout mismatch, leaves={} leaves_per_segment={} segments={}",
+            manifest.leaf_count,
+            manifest.leaves_per_segment,
+            manifest.segment_roots.len()
+        );
+    }
+
+    let data_root = manifest.bagged_root();
+    if data_root != manifest.data_root {
+        bail!(
+            "Manifest root mismatch, computed {:?}, expected {:?}",
+            data_root,
+            manifest.data_root
+        );
+    }
+
+    let segment_bytes = manifest.leaves_per_segment as usize * CHUNK_SIZE;
+    for (segment_index, (segment, expected)) in data
+        .chunks(segment_bytes)
+        .zip(&manifest.segment_roots)
+        .enumerate()
+    {
+        // the last chunk is padded with zeros as stored
+        let mut segment = segment.to_vec();
+        segment.resize(bytes_to_chunks(segment.len()) * CHUNK_SIZE, 0);
+        let root = segment_root(&segment)?;
+        if root != *expected {
+            bail!(
+                "Segment {} root mismatch, computed {:?}, expected {:?}",
+                segment_index,
+                root,
+                expected
+            );
+        }
+    }
+
+    Ok(())
+}
+
+impl ProofManifest {
+    /// Returns the file root bagged from the segment roots.
+    fn bagged_root(&self) -> DataRoot {
+        if self.segment_roots.is_empty() {
+            return DataRoot::zero();
+        }
+        FileMerkleTree::new(self.segment_roots.iter().map(|root| root.0))
+            .root()
+            .into()
+    }
+}
+
+fn segment_root(data: &[u8]) -> Result<DataRoot> {
+    let leaves = data_to_merkle_leaves_h256(data)?.into_iter().map(|h| h.0);
+    Ok(FileMerkleTree::new(leaves).root().into())
+}
@@ -0,0 +1,161 @@
+//! Digests of the data that a replica stores for a tx, so that two nodes can check that they
+//! hold identical data without transferring the file.
+//!
+//! A digest has the root of the stored data and the leaf hashes of the sampled chunks. The
+//! roots tell whether the replicas diverge, and which of them diverges from the root submitted
+//! on chain, while the samples locate some of the diverged chunks.
+//!
+//! The data of a finalized tx is verified against the tx root on finalization, so its root is
+//! the tx root without reading the data. Recomputing the root from all the stored chunks, e.g.
+//! to detect the data corrupted afterwards, is opt-in since it reads the whole file.
+
+use crate::log_store::log_manager::data_to_merkle_leaves_h256;
+use crate::log_store::tx_store::TxStatus;
+use crate::log_store::LogStoreRead;
+use anyhow::{bail, Result};
+use ethereum_types::H256;
+use rand::seq::index::sample;
+use serde::{Deserialize, Serialize};
+use shared_types::{bytes_to_chunks, DataRoot};
+
+/// Maximum number of the sampled chunks of a digest.
+pub const MAX_REPLICA_SAMPLES: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicaDigest {
+    pub tx_seq: u64,
+    /// The root of the tx submitted on chain.
+    pub data_root: DataRoot,
+    /// The root of the stored data, or `None` if the data is not finalized, or not fully
+    /// stored if recomputed.
+    pub stored_root: Option<DataRoot>,
+    /// `(index, leaf)` of the sampled chunks, where the leaf is `None` if not stored.
+    pub samples: Vec<(u64, Option<H256>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicaComparison {
+    pub tx_seq: u64,
+    /// Whether both replicas store the whole data and the data is identical.
+    pub identical: bool,
+    pub local_root: Option<DataRoot>,
+    pub remote_root: Option<DataRoot>,
+    /// Whether the local or the remote data matches the root submitted on chain.
+    pub local_valid: bool,
+    pub remote_valid: bool,
+    /// The indices of the sampled chunks of which the leaves differ.
+    pub mismatched_samples: Vec<u64>,
+}
+
+/// Returns up to `num_samples` random distinct chunk indices of a file of `size` bytes in
+/// ascending order.
+pub fn sample_chunk_indices(size: u64, num_samples: usize) -> Vec<u64> {
+    let num_chunks = bytes_to_chunks(size as usize);
+    let amount = num_samples.min(MAX_REPLICA_SAMPLES).min(num_chunks);
+    let mut indices: Vec<u64> = sample(&mut rand::thread_rng(), num_chunks, amount)
+        .into_iter()
+        .map(|index| index as u64)
+        .collect();
+    indices.sort_unstable();
+    indices
+}
+
+/// Builds the digest of the data of `tx_seq` with the leaves of the chunks at
+/// `sample_indices`, or returns `None` if the tx does not exist. The root is recomputed from
+/// the stored data only if `recompute`.
+pub(crate) fn replica_digest(
+    store: &dyn LogStoreRead,
+    tx_seq: u64,
+    sample_indices: &[u64],
+    recompute: bool,
+) -> Result<Option<ReplicaDigest>> {
+    let Some(tx) = store.get_tx_by_seq_number(tx_seq)? else {
+        return Ok(None);
+    };
+    if sample_indices.len() > MAX_REPLICA_SAMPLES {
+        bail!(
+            "Too many samples: {} > {}",
+            sample_indices.len(),
+            MAX_REPLICA_SAMPLES
+        );
+    }
+
+    let num_chunks = bytes_to_chunks(tx.size as usize) as u64;
+    let mut samples = Vec::with_capacity(sample_indices.len());
+    for &index in sample_indices {
+        if index >= num_chunks {
+            bail!(
+                "Sample index out of bound: index={} chunks={}",
+                index,
+                num_chunks
+            );
+        }
+        let leaf = match store.get_chunks_by_tx_and_index_range(
+            tx_seq,
+            index as usize,
+            index as usize + 1,
+        )? {
+            Some(chunk) => data_to_merkle_leaves_h256(&chunk.data)?.first().copied(),
+            None => None,
+        };
+        samples.push((index, leaf));
+    }
+
+    let stored_root = if recompute {
+        store.compute_data_root(tx_seq)?
+    } else if matches!(store.get_tx_status(tx_seq)?, Some(TxStatus::Finalized)) {
+        Some(tx.data_merkle_root)
+    } else {
+        None
+    };
+
+    Ok(Some(ReplicaDigest {
+        tx_seq,
+        data_root: tx.data_merkle_root,
+        stored_root,
+        samples,
+    }))
+}
+
+/// Compares the digests of the same tx and sample indices from two replicas.
+pub fn compare_replica_digests(
+    local: &ReplicaDigest,
+    remote: &ReplicaDigest,
+) -> Result<ReplicaComparison> {
+    if local.tx_seq != remote.tx_seq || local.data_root != remote.data_root {
+        bail!(
+            "Replicas disagree on the tx: local=({}, {:?}) remote=({}, {:?})",
+            local.tx_seq,
+            local.data_root,
+            remote.tx_seq,
+            remote.data_root
+        );
+    }
+
+    let mismatched_samples: Vec<u64> = local
+        .samples
+        .iter()
+        .filter(|(index, leaf)| {
+            remote
+                .samples
+                .iter()
+                .find(|(remote_index, _)| remote_index == index)
+                .map_or(true, |(_, remote_leaf)| remote_leaf != leaf)
+        })
+        .map(|(index, _)| *index)
+        .collect();
+
+    Ok(ReplicaComparison {
+        tx_seq: local.tx_seq,
+        identical: local.stored_root.is_some()
+            && local.stored_root == remote.stored_root
+            && mismatched_samples.is_empty(),
+        local_root: local.stored_root,
+        remote_root: remote.stored_root,
+        local_valid: local.stored_root == Some(local.data_root),
+        remote_valid: remote.stored_root == Some(remote.data_root),
+        mismatched_samples,
+    })
+}
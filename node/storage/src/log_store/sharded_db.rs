@@ -0,0 +1,372 @@
+//! Stores the chunk batches of the data db in a fixed number of sharded rocksdb instances, so
+//! that random reads of chunk batches hit smaller LSM trees with fewer levels.
+//!
+//! Chunk batches are grouped into ranges of `shard_size` consecutive batches, and the ranges are
+//! assigned to the shards round-robin. All the other columns are kept in the main data db.
+//! Existing chunk batches in the main data db are moved into the shards on startup.
+
+use crate::log_store::log_manager::{COL_ENTRY_BATCH, COL_MISC};
+use crate::ZgsKeyValueDB;
+use anyhow::{anyhow, bail, Result};
+use kvdb::{DBKeyValue, DBOp, DBTransaction, DBValue, KeyValueDB};
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use ssz::{Decode, Encode};
+use std::io;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+/// Key of the `(num_shards, shard_size)` layout in the main data db.
+const SHARD_LAYOUT_KEY: &[u8] = b"entry_batch_shard_layout";
+/// Number of chunk batches moved into the shards in a single write during migration.
+const MIGRATION_BATCH_SIZE: usize = 64;
+
+type KeyValueIter<'a> = Box<dyn Iterator<Item = io::Result<DBKeyValue>> + 'a>;
+
+pub struct ShardedDB {
+    main: Arc<dyn ZgsKeyValueDB>,
+    shards: Vec<Arc<dyn ZgsKeyValueDB>>,
+    /// Number of consecutive chunk batches stored in the same shard.
+    shard_size: u64,
+}
+
+impl ShardedDB {
+    /// Opens the shards next to the main data db at `data_path`.
+    pub fn open_rocksdb(
+        main: Arc<dyn ZgsKeyValueDB>,
+        db_config: &DatabaseConfig,
+        data_path: &Path,
+        num_shards: usize,
+        shard_size: u64,
+    ) -> Result<Self> {
+        let mut shards: Vec<Arc<dyn ZgsKeyValueDB>> = Vec::with_capacity(num_shards);
+        for shard_id in 0..num_shards {
+            shards.push(Arc::new(Database::open(
+                db_config,
+                shard_path(data_path, shard_id),
+            )?));
+        }
+        Self::new(main, shards, shard_size)
+    }
+
+    pub(crate) fn new(
+        main: Arc<dyn ZgsKeyValueDB>,
+        shards: Vec<Arc<dyn ZgsKeyValueDB>>,
+        shard_size: u64,
+    ) -> Result<Self> {
+        if shards.is_empty() || shard_size == 0 {
+            bail!(
+                "Invalid shard layout: num_shards={} shard_size={}",
+                shards.len(),
+                shard_size
+            );
+        }
+
+        let db = Self {
+            main,
+            shards,
+            shard_size,
+        };
+        let layout = (db.shards.len() as u64, db.shard_size);
+        if let Some(stored) = db.stored_layout()? {
+            // Chunk batches would be read from the wrong shards.
+            if stored != layout && db.has_sharded_batches()? {
+                bail!(
+                    "Shard layout changed from {:?} to {:?}, please keep the previous layout",
+                    stored,
+                    layout
+                );
+            }
+        }
+
+        db.migrate()?;
+        db.main
+            .put(COL_MISC, SHARD_LAYOUT_KEY, &layout.as_ssz_bytes())?;
+        Ok(db)
+    }
+
+    fn stored_layout(&self) -> Result<Option<(u64, u64)>> {
+        match self.main.get(COL_MISC, SHARD_LAYOUT_KEY)? {
+            Some(value) => Ok(Some(
+                <(u64, u64)>::from_ssz_bytes(&value)
+                    .map_err(|e| anyhow!("SSZ decode error: {:?}", e))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn has_sharded_batches(&self) -> Result<bool> {
+        for shard in &self.shards {
+            if shard.iter(COL_ENTRY_BATCH).next().transpose()?.is_some() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Moves the chunk batches stored in the main data db into the shards.
+    fn migrate(&self) -> Result<()> {
+        let mut migrated = 0;
+        loop {
+            let batches = self
+                .main
+                .iter(COL_ENTRY_BATCH)
+                .take(MIGRATION_BATCH_SIZE)
+                .collect::<io::Result<Vec<_>>>()?;
+            if batches.is_empty() {
+                break;
+            }
+
+            let mut shard_txs = self.shard_transactions();
+            let mut main_tx = self.main.transaction();
+            for (key, value) in &batches {
+                shard_txs[self.shard_index(key)].put(COL_ENTRY_BATCH, key, value);
+                main_tx.delete(COL_ENTRY_BATCH, key);
+            }
+            // Batches are only removed from the main data db once written into the shards.
+            self.write_shards(shard_txs)?;
+            self.main.write(main_tx)?;
+            migrated += batches.len();
+        }
+
+        if migrated > 0 {
+            info!(
+                migrated,
+                num_shards = self.shards.len(),
+                "Migrated chunk batches into shards"
+            );
+        }
+        Ok(())
+    }
+
+    fn shard_index(&self, key: &[u8]) -> usize {
+        let batch_index = match key.try_into() {
+            Ok(bytes) => u64::from_be_bytes(bytes),
+            Err(_) => 0,
+        };
+        ((batch_index / self.shard_size) % self.shards.len() as u64) as usize
+    }
+
+    fn shard_transactions(&self) -> Vec<DBTransaction> {
+        self.shards
+            .iter()
+            .map(|shard| shard.transaction())
+            .collect()
+    }
+
+    fn write_shards(&self, shard_txs: Vec<DBTransaction>) -> io::Result<()> {
+        for (shard, tx) in self.shards.iter().zip(shard_txs) {
+            if !tx.ops.is_empty() {
+                shard.write(tx)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge<'a>(&self, iters: Vec<KeyValueIter<'a>>) -> KeyValueIter<'a> {
+        Box::new(MergedIter {
+            iters: iters.into_iter().map(Iterator::peekable).collect(),
+        })
+    }
+}
+
+/// Returns the path of a shard, next to the main data db at `data_path`.
+pub fn shard_path(data_path: &Path, shard_id: usize) -> PathBuf {
+    let name = data_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    data_path.with_file_name(format!("{}_shard_{}", name, shard_id))
+}
+
+/// Checks that the data db at `data_path` is not sharded when opened without shards.
+pub fn check_not_sharded(data_path: &Path) -> Result<()> {
+    if shard_path(data_path, 0).exists() {
+        bail!(
+            "Chunk batches are stored in shards next to {:?}, please configure the shard layout",
+            data_path
+        );
+    }
+    Ok(())
+}
+
+impl KeyValueDB for ShardedDB {
+    fn get(&self, col: u32, key: &[u8]) -> io::Result<Option<DBValue>> {
+        if col == COL_ENTRY_BATCH {
+            self.shards[self.shard_index(key)].get(col, key)
+        } else {
+            self.main.get(col, key)
+        }
+    }
+
+    fn get_by_prefix(&self, col: u32, prefix: &[u8]) -> io::Result<Option<DBValue>> {
+        if col == COL_ENTRY_BATCH {
+            self.iter_with_prefix(col, prefix)
+                .next()
+                .transpose()
+                .map(|item| item.map(|(_, value)| value))
+        } else {
+            self.main.get_by_prefix(col, prefix)
+        }
+    }
+
+    fn write(&self, transaction: DBTransaction) -> io::Result<()> {
+        let mut shard_txs = self.shard_transactions();
+        let mut main_tx = self.main.transaction();
+        for op in transaction.ops {
+            match op {
+                DBOp::Insert { col, key, value } if col == COL_ENTRY_BATCH => {
+                    shard_txs[self.shard_index(&key)].put_vec(col, &key, value)
+                }
+                DBOp::Delete { col, key } if col == COL_ENTRY_BATCH => {
+                    shard_txs[self.shard_index(&key)].delete(col, &key)
+                }
+                DBOp::DeletePrefix { col, prefix } if col == COL_ENTRY_BATCH => {
+                    for tx in shard_txs.iter_mut() {
+                        tx.delete_prefix(col, &prefix);
+                    }
+                }
+                op => main_tx.ops.push(op),
+            }
+        }
+
+        // Write chunk batches first, so that the flow metadata in the main data db never refers
+        // to batches that are not written after a crash.
+        self.write_shards(shard_txs)?;
+        if main_tx.ops.is_empty() {
+            return Ok(());
+        }
+        self.main.write(main_tx)
+    }
+
+    fn iter<'a>(&'a self, col: u32) -> KeyValueIter<'a> {
+        if col == COL_ENTRY_BATCH {
+            self.merge(self.shards.iter().map(|shard| shard.iter(col)).collect())
+        } else {
+            self.main.iter(col)
+        }
+    }
+
+    fn iter_with_prefix<'a>(&'a self, col: u32, prefix: &'a [u8]) -> KeyValueIter<'a> {
+        if col == COL_ENTRY_BATCH {
+            self.merge(
+                self.shards
+                    .iter()
+                    .map(|shard| shard.iter_with_prefix(col, prefix))
+                    .collect(),
+            )
+        } else {
+            self.main.iter_with_prefix(col, prefix)
+        }
+    }
+}
+
+impl ZgsKeyValueDB for ShardedDB {
+    fn num_keys(&self, col: u32) -> io::Result<u64> {
+        if col == COL_ENTRY_BATCH {
+            let mut num_keys = 0;
+            for shard in &self.shards {
+                num_keys += shard.num_keys(col)?;
+            }
+            Ok(num_keys)
+        } else {
+            self.main.num_keys(col)
+        }
+    }
+}
+
+/// Merges the sorted iterators of all shards into a single sorted iterator.
+struct MergedIter<'a> {
+    iters: Vec<Peekable<KeyValueIter<'a>>>,
+}
+
+impl<'a> Iterator for MergedIter<'a> {
+    type Item = io::Result<DBKeyValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut min: Option<(usize, Vec<u8>)> = None;
+        for (index, iter) in self.iters.iter_mut().enumerate() {
+            match iter.peek() {
+                None => {}
+                Some(Err(_)) => return iter.next(),
+                Some(Ok((key, _))) => {
+                    if min
+                        .as_ref()
+                        .map_or(true, |(_, min_key)| key[..] < min_key[..])
+                    {
+                        min = Some((index, key.to_vec()));
+                    }
+                }
+            }
+        }
+        min.and_then(|(index, _)| self.iters[index].next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardedDB;
+    use crate::log_store::log_manager::{COL_ENTRY_BATCH, COL_MISC, COL_NUM, COL_TX_COMPLETED};
+    use crate::ZgsKeyValueDB;
+    use kvdb::KeyValueDB;
+    use std::sync::Arc;
+
+    fn key(batch_index: u64) -> [u8; 8] {
+        batch_index.to_be_bytes()
+    }
+
+    fn keys(db: &dyn ZgsKeyValueDB) -> Vec<[u8; 8]> {
+        db.iter(COL_ENTRY_BATCH)
+            .map(|item| item.unwrap().0[..].try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sharded_db() {
+        let main: Arc<dyn ZgsKeyValueDB> = Arc::new(kvdb_memorydb::create(COL_NUM));
+        let shards: Vec<Arc<dyn ZgsKeyValueDB>> = (0..3)
+            .map(|_| Arc::new(kvdb_memorydb::create(COL_NUM)) as Arc<dyn ZgsKeyValueDB>)
+            .collect();
+        for i in 0..10 {
+            main.put(COL_ENTRY_BATCH, &key(i), &[i as u8]).unwrap();
+        }
+        main.put(COL_TX_COMPLETED, &key(0), &[1]).unwrap();
+
+        // existing batches are migrated
+        let db = ShardedDB::new(main.clone(), shards.clone(), 2).unwrap();
+        assert!(keys(main.as_ref()).is_empty());
+        assert_eq!(
+            keys(shards[0].as_ref()),
+            vec![key(0), key(1), key(6), key(7)]
+        );
+        assert_eq!(
+            keys(shards[1].as_ref()),
+            vec![key(2), key(3), key(8), key(9)]
+        );
+        assert_eq!(keys(shards[2].as_ref()), vec![key(4), key(5)]);
+        assert_eq!(db.get(COL_TX_COMPLETED, &key(0)).unwrap(), Some(vec![1]));
+
+        // reads and writes are routed transparently
+        assert_eq!(keys(&db), (0..10).map(key).collect::<Vec<_>>());
+        assert_eq!(db.num_keys(COL_ENTRY_BATCH).unwrap(), 10);
+        assert_eq!(db.get(COL_ENTRY_BATCH, &key(7)).unwrap(), Some(vec![7]));
+        db.put(COL_ENTRY_BATCH, &key(12), &[12]).unwrap();
+        db.delete(COL_ENTRY_BATCH, &key(5)).unwrap();
+        assert_eq!(
+            shards[0].get(COL_ENTRY_BATCH, &key(12)).unwrap(),
+            Some(vec![12])
+        );
+        assert_eq!(db.get(COL_ENTRY_BATCH, &key(5)).unwrap(), None);
+        let last = db.iter(COL_ENTRY_BATCH).last().unwrap().unwrap();
+        assert_eq!(&last.0[..], &key(12));
+        assert!(main
+            .get(COL_MISC, super::SHARD_LAYOUT_KEY)
+            .unwrap()
+            .is_some());
+
+        // the layout cannot be changed once batches are sharded
+        assert!(ShardedDB::new(main.clone(), shards[..2].to_vec(), 2).is_err());
+        assert!(ShardedDB::new(main, shards, 2).is_ok());
+    }
+}
@@ -0,0 +1,188 @@
+//! A portable dump of the whole store, including the merkle trees, the chunk batches with their
+//! seal and completeness bitmaps, the transactions and the sync progress, to migrate a node
+//! between machines without copying the live databases.
+//!
+//! The dump file is laid out as below, where all integers are encoded in little endian:
+//!
+//! ```text
+//! magic (8 bytes "ZGSSTATE") | version (u32)
+//! record*: db (u8) | column (u32) | key length (u32) | key | value length (u32) | value
+//! end mark (u8 0xFF) | number of records (u64)
+//! ```
+//!
+//! `db` is 0 for the flow db and 1 for the data db. The version is bumped whenever the layout
+//! above or the layout of the db columns changes, and dumps of other versions are rejected.
+
+use crate::log_store::log_manager::COL_NUM;
+use crate::ZgsKeyValueDB;
+use anyhow::{bail, Result};
+use kvdb::DBKeyValue;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+pub const STATE_DUMP_MAGIC: &[u8; 8] = b"ZGSSTATE";
+pub const STATE_DUMP_VERSION: u32 = 1;
+
+const DB_FLOW: u8 = 0;
+const DB_DATA: u8 = 1;
+const END_MARK: u8 = 0xFF;
+
+/// Maximum number of records to write into db in a single transaction during import.
+const IMPORT_BATCH_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDumpInfo {
+    pub version: u32,
+    pub num_records: u64,
+    pub size_bytes: u64,
+}
+
+pub(crate) type ColumnIter<'a> = Box<dyn Iterator<Item = io::Result<DBKeyValue>> + 'a>;
+
+/// Snapshots of all columns of the flow db and data db, which should be created together
+/// without any write in between for a consistent dump.
+pub(crate) fn snapshot_columns<'a>(
+    flow_db: &'a dyn ZgsKeyValueDB,
+    data_db: &'a dyn ZgsKeyValueDB,
+) -> Vec<(u8, u32, ColumnIter<'a>)> {
+    let mut columns = Vec::with_capacity(2 * COL_NUM as usize);
+    for col in 0..COL_NUM {
+        columns.push((DB_FLOW, col, flow_db.iter(col)));
+        columns.push((DB_DATA, col, data_db.iter(col)));
+    }
+    columns
+}
+
+/// Writes the column snapshots to `path`. The dump is written to a temporary file first, so
+/// that an interrupted export never leaves a truncated dump at `path`.
+pub(crate) fn export_state(
+    columns: Vec<(u8, u32, ColumnIter<'_>)>,
+    path: &Path,
+) -> Result<StateDumpInfo> {
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+    writer.write_all(STATE_DUMP_MAGIC)?;
+    writer.write_all(&STATE_DUMP_VERSION.to_le_bytes())?;
+
+    let mut num_records = 0u64;
+    for (db, col, iter) in columns {
+        for item in iter {
+            let (key, value) = item?;
+            writer.write_all(&[db])?;
+            writer.write_all(&col.to_le_bytes())?;
+            writer.write_all(&(key.len() as u32).to_le_bytes())?;
+            writer.write_all(&key)?;
+            writer.write_all(&(value.len() as u32).to_le_bytes())?;
+            writer.write_all(&value)?;
+            num_records += 1;
+        }
+    }
+
+    writer.write_all(&[END_MARK])?;
+    writer.write_all(&num_records.to_le_bytes())?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    Ok(StateDumpInfo {
+        version: STATE_DUMP_VERSION,
+        num_records,
+        size_bytes: fs::metadata(path)?.len(),
+    })
+}
+
+/// Restores the dump at `path` into the empty flow db and data db.
+pub fn import_state(
+    flow_db: &dyn ZgsKeyValueDB,
+    data_db: &dyn ZgsKeyValueDB,
+    path: &Path,
+) -> Result<StateDumpInfo> {
+    for col in 0..COL_NUM {
+        if flow_db.iter(col).next().is_some() || data_db.iter(col).next().is_some() {
+            bail!("Failed to import state into non-empty db");
+        }
+    }
+
+    let size_bytes = fs::metadata(path)?.len();
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != STATE_DUMP_MAGIC {
+        bail!("Invalid state dump file");
+    }
+    let version = read_u32(&mut reader)?;
+    if version != STATE_DUMP_VERSION {
+        bail!(
+            "Unsupported state dump version {}, expected {}",
+            version,
+            STATE_DUMP_VERSION
+        );
+    }
+
+    let mut flow_tx = flow_db.transaction();
+    let mut data_tx = data_db.transaction();
+    let mut pending = 0;
+    let mut num_records = 0u64;
+    loop {
+        let mut db = [0u8; 1];
+        reader.read_exact(&mut db)?;
+        if db[0] == END_MARK {
+            break;
+        }
+
+        let col = read_u32(&mut reader)?;
+        if col >= COL_NUM {
+            bail!("Invalid column {} in state dump", col);
+        }
+        let key = read_bytes(&mut reader)?;
+        let value = read_bytes(&mut reader)?;
+        match db[0] {
+            DB_FLOW => flow_tx.put_vec(col, &key, value),
+            DB_DATA => data_tx.put_vec(col, &key, value),
+            _ => bail!("Invalid db {} in state dump", db[0]),
+        }
+        num_records += 1;
+
+        pending += 1;
+        if pending >= IMPORT_BATCH_SIZE {
+            flow_db.write(std::mem::take(&mut flow_tx))?;
+            data_db.write(std::mem::take(&mut data_tx))?;
+            pending = 0;
+        }
+    }
+
+    let mut expected_records = [0u8; 8];
+    reader.read_exact(&mut expected_records)?;
+    if u64::from_le_bytes(expected_records) != num_records {
+        bail!("Mismatched number of records in state dump");
+    }
+
+    flow_db.write(flow_tx)?;
+    data_db.write(data_tx)?;
+
+    Ok(StateDumpInfo {
+        version,
+        num_records,
+        size_bytes,
+    })
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)? as usize;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
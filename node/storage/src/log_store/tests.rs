@@ -1,13 +1,30 @@
+use crate::log_store::file_download::partial_path;
 use crate::log_store::log_manager::{
     data_to_merkle_leaves, data_to_merkle_leaves_h256, sub_merkle_tree,
     tx_subtree_root_list_padded, LogConfig, LogManager, PORA_CHUNK_SIZE,
 };
-use crate::log_store::{LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use crate::log_store::log_manager::{
+    COL_ENTRY_BATCH, COL_FLOW_MPT_NODES, COL_NUM, COL_PAD_DATA_LIST, COL_TX_COMPLETED,
+    COL_TX_DATA_ROOT_INDEX,
+};
+use crate::log_store::proof_manifest::{verify_proof_manifest, ProofManifest};
+use crate::log_store::replica::{
+    compare_replica_digests, sample_chunk_indices, MAX_REPLICA_SAMPLES,
+};
+use crate::log_store::state_dump::{import_state, STATE_DUMP_VERSION};
+use crate::log_store::{
+    FinalizedFile, LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite, RemovedFiles,
+};
+use crate::ZgsKeyValueDB;
 use append_merkle::{Algorithm, AppendMerkleTree, MerkleTreeRead, OptionalHash, Sha3Algorithm};
 use ethereum_types::H256;
 use rand::random;
-use shared_types::{compute_padded_chunk_size, ChunkArray, Transaction, CHUNK_SIZE};
+use shared_types::{
+    bytes_to_chunks, compute_padded_chunk_size, ChunkArray, Transaction, CHUNK_SIZE,
+};
 use std::cmp;
+use std::collections::BTreeSet;
+use std::sync::Arc;
 
 #[test]
 fn test_put_get() {
@@ -135,6 +152,48 @@ fn test_root() {
     }
 }
 
+#[test]
+fn test_data_subtree_list() {
+    for chunk_count in [
+        1,
+        2,
+        5,
+        16,
+        17,
+        33,
+        PORA_CHUNK_SIZE + 1,
+        2 * PORA_CHUNK_SIZE + 3,
+    ] {
+        let mut store = create_store();
+        let size = chunk_count * CHUNK_SIZE - 7;
+        let (tx, _) = put_tx_of_size_without_chunks(&mut store, size, 0);
+        let subtree_list = tx.data_subtree_list();
+        assert!(Transaction::num_entries_of_list(&subtree_list) >= chunk_count);
+
+        let mut merkle = AppendMerkleTree::<OptionalHash, Sha3Algorithm>::new(vec![], 0, None);
+        merkle
+            .append_subtree_list(
+                subtree_list
+                    .into_iter()
+                    .map(|(depth, root)| (depth, OptionalHash::some(root)))
+                    .collect(),
+            )
+            .unwrap();
+        assert_eq!(
+            merkle.root().unwrap(),
+            tx.data_merkle_root,
+            "chunks={}",
+            chunk_count
+        );
+    }
+
+    // the trailing subtrees of only padding are dropped
+    let mut store = create_store();
+    let (mut tx, _) = put_tx_without_chunks(&mut store, 4, 0);
+    tx.merkle_nodes.push((1, H256::zero()));
+    assert_eq!(tx.data_subtree_list().len(), 1);
+}
+
 #[test]
 fn test_multi_tx() {
     let mut store = create_store();
@@ -174,16 +233,725 @@ fn test_put_tx() {
     }
 }
 
+#[test]
+fn test_pinned_tx_not_pruned() {
+    let mut store = create_store();
+    for seq in 0..3 {
+        put_tx(&mut store, PORA_CHUNK_SIZE, seq);
+    }
+    assert!(store.pin_tx(1).unwrap());
+    assert!(!store.pin_tx(1).unwrap());
+    assert!(store.pin_tx(3).is_err());
+    assert_eq!(store.get_pinned_txs().unwrap(), vec![1]);
+
+    // Prune all the txs and their chunk batches.
+    let batch_list: Vec<u64> = (0..3)
+        .map(|seq| {
+            let tx = store.get_tx_by_seq_number(seq).unwrap().unwrap();
+            store.prune_tx(seq).unwrap();
+            tx.start_entry_index / PORA_CHUNK_SIZE as u64
+        })
+        .collect();
+    store.remove_chunks_batch(&batch_list).unwrap();
+
+    for seq in [0, 2] {
+        assert!(store.check_tx_pruned(seq).unwrap());
+        assert!(store
+            .get_chunks_by_tx_and_index_range(seq, 0, PORA_CHUNK_SIZE)
+            .unwrap()
+            .is_none());
+    }
+    assert!(store.check_tx_completed(1).unwrap());
+    assert!(store
+        .get_chunks_by_tx_and_index_range(1, 0, PORA_CHUNK_SIZE)
+        .unwrap()
+        .is_some());
+    assert!(store.pin_tx(0).is_err());
+
+    // Pins are dropped together with reverted txs.
+    assert!(store.unpin_tx(1).unwrap());
+    assert!(!store.unpin_tx(1).unwrap());
+    store.pin_tx(2).unwrap();
+    store.revert_to(1).unwrap();
+    assert!(store.get_pinned_txs().unwrap().is_empty());
+}
+
+#[test]
+fn test_tagged_txs() {
+    let mut store = create_store();
+    for seq in 0..4 {
+        put_tx(&mut store, 3, seq);
+    }
+    let tags = |tags: &[&str]| -> Vec<String> { tags.iter().map(|t| t.to_string()).collect() };
+    store.set_tx_tags(0, tags(&["backup", "team-a"])).unwrap();
+    store.set_tx_tags(1, tags(&["team-b"])).unwrap();
+    store.set_tx_tags(2, tags(&["team-a", "team-a"])).unwrap();
+    store.set_tx_tags(3, tags(&["backup", "team-a"])).unwrap();
+    assert!(store.set_tx_tags(4, tags(&["team-a"])).is_err());
+    assert!(store.set_tx_tags(1, tags(&[""])).is_err());
+    assert!(store.set_tx_tags(1, vec!["a".repeat(65)]).is_err());
+    assert!(store
+        .set_tx_tags(1, (0..17).map(|i| i.to_string()).collect())
+        .is_err());
+
+    assert_eq!(store.get_tx_tags(2).unwrap(), tags(&["team-a"]));
+    assert_eq!(store.get_tagged_txs("team-a").unwrap(), vec![0, 2, 3]);
+    assert_eq!(store.get_tagged_txs("backup").unwrap(), vec![0, 3]);
+    assert_eq!(store.get_tagged_txs("team-b").unwrap(), vec![1]);
+    assert!(store.get_tagged_txs("team-c").unwrap().is_empty());
+
+    // Replace the tags.
+    store.set_tx_tags(0, tags(&["team-b"])).unwrap();
+    assert_eq!(store.get_tagged_txs("team-a").unwrap(), vec![2, 3]);
+    assert_eq!(store.get_tagged_txs("backup").unwrap(), vec![3]);
+    assert_eq!(store.get_tagged_txs("team-b").unwrap(), vec![0, 1]);
+    store.set_tx_tags(1, vec![]).unwrap();
+    assert!(store.get_tx_tags(1).unwrap().is_empty());
+    assert_eq!(store.get_tagged_txs("team-b").unwrap(), vec![0]);
+
+    // The txs of a tag are not mixed up with the txs of the tags prefixed by it.
+    store.set_tx_tags(2, tags(&["team-a", "team"])).unwrap();
+    store
+        .set_tx_tags(3, tags(&["backup", "team-a", "team_b"]))
+        .unwrap();
+    assert_eq!(store.get_tagged_txs("team").unwrap(), vec![2]);
+    assert_eq!(store.get_tagged_txs("team_b").unwrap(), vec![3]);
+    assert_eq!(store.get_tx_tags(2).unwrap(), tags(&["team-a", "team"]));
+
+    // Tags are dropped together with reverted txs.
+    store.revert_to(1).unwrap();
+    assert!(store.get_tx_tags(3).unwrap().is_empty());
+    assert!(store.get_tagged_txs("team").unwrap().is_empty());
+    assert!(store.get_tagged_txs("team_b").unwrap().is_empty());
+    assert!(store.get_tagged_txs("team-a").unwrap().is_empty());
+    assert!(store.get_tagged_txs("backup").unwrap().is_empty());
+    assert_eq!(store.get_tagged_txs("team-b").unwrap(), vec![0]);
+}
+
+#[test]
+fn test_compute_data_root() {
+    let mut store = create_store();
+    put_tx(&mut store, 3, 0);
+    put_tx(&mut store, PORA_CHUNK_SIZE + 1, 1);
+    for seq in 0..2 {
+        let tx = store.get_tx_by_seq_number(seq).unwrap().unwrap();
+        assert_eq!(
+            store.compute_data_root(seq).unwrap(),
+            Some(tx.data_merkle_root)
+        );
+    }
+    assert_eq!(store.compute_data_root(2).unwrap(), None);
+
+    // The root cannot be computed once the data is pruned.
+    let tx = store.get_tx_by_seq_number(1).unwrap().unwrap();
+    store.prune_tx(1).unwrap();
+    store
+        .remove_chunks_batch(&[tx.start_entry_index / PORA_CHUNK_SIZE as u64])
+        .unwrap();
+    assert_eq!(store.compute_data_root(1).unwrap(), None);
+}
+
+#[test]
+fn test_compute_data_root_mismatch() {
+    let mut store = create_store();
+    put_tx(&mut store, 3, 0);
+    put_tx(&mut store, PORA_CHUNK_SIZE + 1, 1);
+
+    // Corrupt a chunk in the complete batch of tx 1.
+    let tx = store.get_tx_by_seq_number(1).unwrap().unwrap();
+    store
+        .flow_store()
+        .corrupt_entry(tx.start_entry_index + 5)
+        .unwrap();
+    let root = store.compute_data_root(1).unwrap().unwrap();
+    assert_ne!(root, tx.data_merkle_root);
+
+    // The other file is not affected.
+    let tx = store.get_tx_by_seq_number(0).unwrap().unwrap();
+    assert_eq!(
+        store.compute_data_root(0).unwrap(),
+        Some(tx.data_merkle_root)
+    );
+}
+
+#[test]
+fn test_check_tx_merkle_complete() {
+    let mut store = create_store();
+    put_tx(&mut store, 3, 0);
+    assert!(store.check_tx_merkle_complete(0).unwrap());
+
+    let chunk_count = PORA_CHUNK_SIZE + 3;
+    let (tx, data) = put_tx_without_chunks(&mut store, chunk_count, 1);
+    assert!(!store.check_tx_merkle_complete(1).unwrap());
+    // the last batch is missing
+    put_tx_chunks(&mut store, &tx, &data, 0, PORA_CHUNK_SIZE);
+    assert!(!store.check_tx_merkle_complete(1).unwrap());
+    put_tx_chunks(&mut store, &tx, &data, PORA_CHUNK_SIZE, chunk_count);
+    assert!(store.check_tx_merkle_complete(1).unwrap());
+    store.finalize_tx(1).unwrap();
+    assert!(store.check_tx_merkle_complete(1).unwrap());
+
+    // a gap in the batches of a subtree larger than a batch
+    let chunk_count = 2 * PORA_CHUNK_SIZE + 3;
+    let (tx, data) = put_tx_without_chunks(&mut store, chunk_count, 2);
+    put_tx_chunks(&mut store, &tx, &data, PORA_CHUNK_SIZE, chunk_count);
+    assert!(!store.check_tx_merkle_complete(2).unwrap());
+    put_tx_chunks(&mut store, &tx, &data, 0, PORA_CHUNK_SIZE);
+    assert!(store.check_tx_merkle_complete(2).unwrap());
+
+    assert!(!store.check_tx_merkle_complete(3).unwrap());
+    store.prune_tx(0).unwrap();
+    assert!(!store.check_tx_merkle_complete(0).unwrap());
+}
+
+#[test]
+fn test_link_same_root_tx() {
+    let mut store = create_store();
+    let finalized = store.subscribe_finalized();
+    let chunk_count = PORA_CHUNK_SIZE + 3;
+    let (tx, data) = put_tx_without_chunks(&mut store, chunk_count, 0);
+
+    // not linked before the data of the same root is stored
+    put_same_data_tx(&mut store, &tx, 1);
+    assert!(!store.link_same_root_tx(1).unwrap());
+    assert!(!store.check_tx_completed(1).unwrap());
+
+    // all the same-root txs are finalized once the data is stored
+    put_tx_chunks(&mut store, &tx, &data, 0, chunk_count);
+    store.finalize_tx(0).unwrap();
+    assert!(store.check_tx_completed(1).unwrap());
+    assert_eq!(*finalized.borrow(), 2);
+
+    // a re-upload is finalized once put
+    put_same_data_tx(&mut store, &tx, 2);
+    assert!(store.check_tx_completed(2).unwrap());
+    assert_eq!(*finalized.borrow(), 3);
+    assert!(!store.link_same_root_tx(2).unwrap());
+    for seq in 1..3 {
+        let chunks = store
+            .get_chunks_by_tx_and_index_range(seq, 0, chunk_count)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunks.data, data);
+    }
+}
+
+#[test]
+fn test_highest_finalized_seq() {
+    let mut store = create_store();
+    assert_eq!(store.get_highest_finalized_seq().unwrap(), None);
+
+    // the log is synced ahead of the data
+    let mut txs = vec![];
+    for seq in 0..4 {
+        txs.push(put_tx_without_chunks(&mut store, 3, seq));
+    }
+    assert_eq!(store.next_tx_seq(), 4);
+    assert_eq!(store.get_highest_finalized_seq().unwrap(), None);
+
+    for seq in [0, 2] {
+        let (tx, data) = &txs[seq];
+        put_tx_chunks(&mut store, tx, data, 0, 3);
+        store.finalize_tx(tx.seq).unwrap();
+    }
+    assert_eq!(store.get_highest_finalized_seq().unwrap(), Some(0));
+
+    // a skipped tx is not waiting for data
+    store.skip_tx(1, "uploader not allowlisted").unwrap();
+    assert_eq!(store.get_highest_finalized_seq().unwrap(), Some(2));
+    assert_eq!(
+        store.get_skip_reason(1).unwrap().as_deref(),
+        Some("uploader not allowlisted")
+    );
+    assert_eq!(store.get_skip_reason(2).unwrap(), None);
+
+    let (tx, data) = &txs[3];
+    put_tx_chunks(&mut store, tx, data, 0, 3);
+    store.finalize_tx(3).unwrap();
+    assert_eq!(store.get_highest_finalized_seq().unwrap(), Some(3));
+
+    // loaded at open
+    let reopened = LogManager::new(
+        store.flow_db.clone(),
+        store.data_db.clone(),
+        LogConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(reopened.get_highest_finalized_seq().unwrap(), Some(3));
+
+    store.revert_to(1).unwrap();
+    assert_eq!(store.get_highest_finalized_seq().unwrap(), Some(1));
+    assert_eq!(store.get_skip_reason(1).unwrap(), None);
+}
+
+#[test]
+fn test_subscribe_finalized_files() {
+    let mut store = create_store();
+    let mut receiver = store.subscribe_finalized_files();
+    put_tx(&mut store, 3, 0);
+    put_tx(&mut store, 5, 1);
+
+    for seq in 0..2 {
+        let tx = store.get_tx_by_seq_number(seq).unwrap().unwrap();
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            FinalizedFile {
+                tx_seq: seq,
+                data_root: tx.data_merkle_root,
+            }
+        );
+    }
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn test_finalized_tx_ranges() {
+    let mut store = create_store();
+    let mut receiver = store.subscribe_removed_files();
+    for seq in 0..3 {
+        put_tx(&mut store, 3, seq);
+    }
+    put_tx_without_chunks(&mut store, 3, 3);
+    put_tx(&mut store, 3, 4);
+    assert_eq!(
+        store.get_finalized_tx_ranges().unwrap(),
+        vec![(0, 3), (4, 5)]
+    );
+
+    store.prune_tx(1).unwrap();
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        RemovedFiles {
+            start_tx_seq: 1,
+            end_tx_seq: 2,
+        }
+    );
+    assert_eq!(
+        store.get_finalized_tx_ranges().unwrap(),
+        vec![(0, 1), (2, 3), (4, 5)]
+    );
+
+    store.revert_to(2).unwrap();
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        RemovedFiles {
+            start_tx_seq: 3,
+            end_tx_seq: u64::MAX,
+        }
+    );
+    assert_eq!(
+        store.get_finalized_tx_ranges().unwrap(),
+        vec![(0, 1), (2, 3)]
+    );
+}
+
+#[test]
+fn test_flow_root_at() {
+    let mut store = create_store();
+    let mut roots = vec![];
+    for (seq, chunk_count) in [3, PORA_CHUNK_SIZE + 1, 5].into_iter().enumerate() {
+        let (tx, data) = put_tx_without_chunks(&mut store, chunk_count, seq as u64);
+        roots.push(store.get_context().unwrap().0);
+        put_tx_chunks(&mut store, &tx, &data, 0, chunk_count);
+        store.finalize_tx(tx.seq).unwrap();
+    }
+    assert_ne!(roots[0], roots[1]);
+    assert_ne!(roots[1], roots[2]);
+
+    // the persisted roots are not affected by the later txs
+    for (seq, root) in roots.iter().enumerate() {
+        assert_eq!(store.get_flow_root_at(seq as u64).unwrap(), Some(*root));
+    }
+    assert_eq!(store.get_flow_root_at(3).unwrap(), None);
+
+    // the roots of the reverted txs are removed
+    store.revert_to(0).unwrap();
+    assert_eq!(store.get_flow_root_at(0).unwrap(), Some(roots[0]));
+    assert_eq!(store.get_flow_root_at(1).unwrap(), None);
+    put_tx(&mut store, 7, 1);
+    assert_ne!(store.get_flow_root_at(1).unwrap(), Some(roots[1]));
+}
+
+#[test]
+fn test_state_dump() {
+    let mut store = create_store();
+    put_tx(&mut store, 3, 0);
+    put_tx(&mut store, PORA_CHUNK_SIZE + 1, 1);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state");
+    let info = store.export_state(&path).unwrap();
+    assert_eq!(info.version, STATE_DUMP_VERSION);
+    assert!(info.num_records > 0);
+
+    let flow_db = Arc::new(kvdb_memorydb::create(COL_NUM));
+    let data_db = Arc::new(kvdb_memorydb::create(COL_NUM));
+    assert_eq!(
+        import_state(flow_db.as_ref(), data_db.as_ref(), &path).unwrap(),
+        info
+    );
+    // import into non-empty db is rejected
+    assert!(import_state(flow_db.as_ref(), data_db.as_ref(), &path).is_err());
+
+    let imported = LogManager::new(flow_db, data_db, LogConfig::default()).unwrap();
+    assert_eq!(imported.next_tx_seq(), store.next_tx_seq());
+    assert_eq!(
+        imported.get_context().unwrap(),
+        store.get_context().unwrap()
+    );
+    for seq in 0..2 {
+        let tx = store.get_tx_by_seq_number(seq).unwrap().unwrap();
+        assert_eq!(imported.get_tx_by_seq_number(seq).unwrap().unwrap(), tx);
+        assert!(imported.check_tx_completed(seq).unwrap());
+        assert_eq!(
+            imported.compute_data_root(seq).unwrap(),
+            Some(tx.data_merkle_root)
+        );
+    }
+}
+
+#[test]
+fn test_merkle_wal_recovery() {
+    let mut reference = create_store();
+    let (tx0, _) = put_tx_without_chunks(&mut reference, 3, 0);
+    // padded and across chunks, so that its merkle updates take more than one op
+    let (tx1, _) = put_tx_without_chunks(&mut reference, 2 * PORA_CHUNK_SIZE + 5, 1);
+    let expected = reference.get_context().unwrap();
+    let (tx2, _) = put_tx_without_chunks(&mut reference, 7, 2);
+
+    for (merkle_wal, applied) in [(true, 0), (true, 2), (false, 2)] {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = LogConfig::default();
+        config.flow.merkle_wal = merkle_wal;
+        let open = || {
+            LogManager::rocksdb(
+                config.clone(),
+                dir.path().join("flow"),
+                dir.path().join("data"),
+            )
+            .unwrap()
+        };
+
+        let store = open();
+        store.put_tx(tx0.clone()).unwrap();
+        store.put_tx_with_crash(tx1.clone(), applied).unwrap();
+        drop(store);
+
+        // the crashed updates are replayed with the WAL, or the tx is put again without it
+        let store = open();
+        assert_eq!(
+            store.get_context().unwrap(),
+            expected,
+            "merkle_wal={} applied={}",
+            merkle_wal,
+            applied
+        );
+        // the flow root of the crashed tx is restored as well
+        assert_eq!(store.get_flow_root_at(1).unwrap(), Some(expected.0));
+        store.put_tx(tx2.clone()).unwrap();
+        assert_eq!(
+            store.get_context().unwrap(),
+            reference.get_context().unwrap()
+        );
+        drop(store);
+
+        // the WAL is truncated after the recovery
+        let store = open();
+        assert_eq!(
+            store.get_context().unwrap(),
+            reference.get_context().unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_download_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    let mut store = create_store();
+
+    // the padding of the last ragged chunk is trimmed
+    let data_size = PORA_CHUNK_SIZE * CHUNK_SIZE + 5;
+    let (tx, data) = put_tx_of_size_without_chunks(&mut store, data_size, 0);
+    put_tx_chunks(&mut store, &tx, &data, 0, bytes_to_chunks(data_size));
+    let info = store.download_file(0, &path).unwrap();
+    assert_eq!(info.size_bytes, data_size as u64);
+    assert_eq!(info.data_root, tx.data_merkle_root);
+    assert_eq!(std::fs::read(&path).unwrap(), data[..data_size]);
+
+    // the temporary file keeps the extension of the path
+    let path = dir.path().join("file.tmp");
+    assert_eq!(partial_path(&path), dir.path().join("file.tmp.partial"));
+    store.download_file(0, &path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), data[..data_size]);
+    assert!(!partial_path(&path).exists());
+
+    // fail with the missing segments, and no partial file is left
+    let path = dir.path().join("incomplete");
+    let chunk_count = 2 * PORA_CHUNK_SIZE + 1;
+    let (tx, data) = put_tx_without_chunks(&mut store, chunk_count, 1);
+    put_tx_chunks(&mut store, &tx, &data, PORA_CHUNK_SIZE, 2 * PORA_CHUNK_SIZE);
+    let err = store.download_file(1, &path).unwrap_err().to_string();
+    assert!(err.contains("missing segments [0, 2] of 3"), "{}", err);
+    assert!(!path.exists());
+    assert!(!partial_path(&path).exists());
+
+    assert!(store.download_file(2, &path).is_err());
+}
+
+#[test]
+fn test_proof_manifest() {
+    let mut store = create_store();
+
+    for (seq, data_size) in [
+        5,
+        PORA_CHUNK_SIZE * CHUNK_SIZE,
+        (2 * PORA_CHUNK_SIZE + 300) * CHUNK_SIZE + 5,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let seq = seq as u64;
+        let (tx, data) = put_tx_of_size_without_chunks(&mut store, data_size, seq);
+        put_tx_chunks(&mut store, &tx, &data, 0, bytes_to_chunks(data_size));
+        let data = &data[..data_size];
+
+        let manifest = store.export_proof_manifest(seq).unwrap();
+        assert_eq!(manifest.data_root, tx.data_merkle_root);
+        assert_eq!(manifest.leaf_count, bytes_to_chunks(data_size) as u64);
+        assert_eq!(
+            manifest.segment_roots.len(),
+            (bytes_to_chunks(data_size) + PORA_CHUNK_SIZE - 1) / PORA_CHUNK_SIZE
+        );
+
+        // verified offline from the serialized manifest
+        let manifest: ProofManifest =
+            serde_json::from_slice(&serde_json::to_vec(&manifest).unwrap()).unwrap();
+        verify_proof_manifest(&manifest, data).unwrap();
+
+        // any modified byte or size is rejected
+        let mut modified = data.to_vec();
+        *modified.last_mut().unwrap() ^= 1;
+        assert!(verify_proof_manifest(&manifest, &modified).is_err());
+        assert!(verify_proof_manifest(&manifest, &data[..data_size - 1]).is_err());
+
+        let mut unsupported = manifest.clone();
+        unsupported.version += 1;
+        assert!(verify_proof_manifest(&unsupported, data).is_err());
+    }
+
+    // not exported for an incomplete file
+    let (tx, data) = put_tx_without_chunks(&mut store, 2 * PORA_CHUNK_SIZE, 3);
+    put_tx_chunks(&mut store, &tx, &data, 0, PORA_CHUNK_SIZE);
+    let err = store.export_proof_manifest(3).unwrap_err().to_string();
+    assert!(err.contains("missing segments [1] of 2"), "{}", err);
+}
+
+#[test]
+fn test_replica_digest() {
+    let chunk_count = PORA_CHUNK_SIZE + 3;
+    let mut local = create_store();
+    let mut remote = create_store();
+    put_tx(&mut local, chunk_count, 0);
+    put_tx(&mut remote, chunk_count, 0);
+    let tx = local.get_tx_by_seq_number(0).unwrap().unwrap();
+
+    // identical data
+    let indices = sample_chunk_indices(tx.size, 8);
+    assert_eq!(indices.len(), 8);
+    assert!(indices.windows(2).all(|w| w[0] < w[1]));
+    let local_digest = local
+        .get_replica_digest(0, &indices, false)
+        .unwrap()
+        .unwrap();
+    let remote_digest = remote
+        .get_replica_digest(0, &indices, false)
+        .unwrap()
+        .unwrap();
+    assert_eq!(local_digest, remote_digest);
+    let comparison = compare_replica_digests(&local_digest, &remote_digest).unwrap();
+    assert!(comparison.identical);
+    assert!(comparison.local_valid && comparison.remote_valid);
+    assert!(comparison.mismatched_samples.is_empty());
+
+    // differing data of the same tx
+    let mut data = local
+        .get_chunks_by_tx_and_index_range(0, 0, chunk_count)
+        .unwrap()
+        .unwrap()
+        .data;
+    data[5 * CHUNK_SIZE] ^= 1;
+    let diverged_tx = Transaction {
+        merkle_nodes: tx_subtree_root_list_padded(&data),
+        ..tx.clone()
+    };
+    let mut diverged = create_store();
+    diverged.put_tx(diverged_tx.clone()).unwrap();
+    put_tx_chunks(&mut diverged, &diverged_tx, &data, 0, chunk_count);
+
+    let indices = vec![0, 5, chunk_count as u64 - 1];
+    let local_digest = local
+        .get_replica_digest(0, &indices, false)
+        .unwrap()
+        .unwrap();
+    let diverged_digest = diverged
+        .get_replica_digest(0, &indices, false)
+        .unwrap()
+        .unwrap();
+    let comparison = compare_replica_digests(&local_digest, &diverged_digest).unwrap();
+    assert!(!comparison.identical);
+    assert!(comparison.local_valid);
+    assert!(!comparison.remote_valid);
+    // the diverged data is not finalized, so it has no root unless recomputed
+    assert_eq!(comparison.remote_root, None);
+    assert_eq!(comparison.mismatched_samples, vec![5]);
+
+    // recomputed from the stored data
+    let local_digest = local
+        .get_replica_digest(0, &indices, true)
+        .unwrap()
+        .unwrap();
+    assert_eq!(local_digest.stored_root, Some(tx.data_merkle_root));
+    let diverged_digest = diverged
+        .get_replica_digest(0, &indices, true)
+        .unwrap()
+        .unwrap();
+    let comparison = compare_replica_digests(&local_digest, &diverged_digest).unwrap();
+    assert!(!comparison.identical);
+    assert!(comparison.local_valid);
+    assert!(!comparison.remote_valid);
+    assert_ne!(comparison.local_root, comparison.remote_root);
+    assert!(comparison.remote_root.is_some());
+    assert_eq!(comparison.mismatched_samples, vec![5]);
+
+    // data not stored
+    let mut empty = create_store();
+    empty.put_tx(tx.clone()).unwrap();
+    let empty_digest = empty
+        .get_replica_digest(0, &indices, true)
+        .unwrap()
+        .unwrap();
+    assert_eq!(empty_digest.stored_root, None);
+    let comparison = compare_replica_digests(&local_digest, &empty_digest).unwrap();
+    assert!(!comparison.identical);
+    assert_eq!(comparison.mismatched_samples, indices);
+
+    // a different tx, or invalid samples
+    put_tx(&mut local, 3, 1);
+    let other_digest = local.get_replica_digest(1, &[0], false).unwrap().unwrap();
+    assert!(compare_replica_digests(&local_digest, &other_digest).is_err());
+    assert_eq!(local.get_replica_digest(2, &[0], false).unwrap(), None);
+    assert!(local.get_replica_digest(1, &[3], false).is_err());
+    let too_many: Vec<u64> = (0..=MAX_REPLICA_SAMPLES as u64).collect();
+    assert!(local.get_replica_digest(0, &too_many, false).is_err());
+}
+
+#[test]
+fn test_gc_store() {
+    let mut store = create_store();
+    put_tx(&mut store, 3, 0);
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE + 1, 1);
+    put_tx(&mut store, 5, 2);
+    // the live records are never reclaimed
+    assert_eq!(store.gc_store(false).unwrap().orphaned_records, 0);
+
+    let before_reorg = gc_records(&store);
+    store.revert_to(0).unwrap();
+    // the paddings of the reverted txs are left behind by the revert
+    let report = store.gc_store(false).unwrap();
+    assert_eq!(report.orphaned_records, 2);
+    assert_eq!(report.reclaimed_records, 2);
+    let after_reorg = gc_records(&store);
+
+    // simulate the records left behind if the node crashes in the middle of the revert, except
+    // the layer sizes, which would resurrect the reverted nodes at startup
+    let live_keys: BTreeSet<_> = after_reorg
+        .iter()
+        .map(|(db, col, key, _)| (*db, *col, key.clone()))
+        .collect();
+    let mut orphans = 0;
+    for (db, col, key, value) in &before_reorg {
+        if live_keys.contains(&(*db, *col, key.clone())) || key.starts_with(b"layer_size") {
+            continue;
+        }
+        let db = [&store.flow_db, &store.data_db][*db as usize];
+        db.put(*col, key, value).unwrap();
+        orphans += 1;
+    }
+    assert!(orphans > 2);
+
+    let report = store.gc_store(true).unwrap();
+    assert_eq!(report.orphaned_records, orphans);
+    assert_eq!(report.reclaimed_records, 0);
+    assert_eq!(
+        gc_records(&store).len(),
+        after_reorg.len() + orphans as usize
+    );
+
+    let report = store.gc_store(false).unwrap();
+    assert_eq!(report.orphaned_records, orphans);
+    assert_eq!(report.reclaimed_records, orphans);
+    assert!(report.bytes_freed > 0);
+    assert_eq!(gc_records(&store), after_reorg);
+    assert_eq!(store.gc_store(false).unwrap().orphaned_records, 0);
+
+    // the reverted txs could be put again
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE + 1, 1);
+    put_tx(&mut store, 5, 2);
+    assert_eq!(store.gc_store(false).unwrap().orphaned_records, 0);
+}
+
+/// Records of the columns in which the orphans are reclaimed, of the flow db (0) and data db (1).
+fn gc_records(store: &LogManager) -> BTreeSet<(u8, u32, Vec<u8>, Vec<u8>)> {
+    let mut records = BTreeSet::new();
+    for (index, db) in [&store.flow_db, &store.data_db].into_iter().enumerate() {
+        for col in [
+            COL_ENTRY_BATCH,
+            COL_FLOW_MPT_NODES,
+            COL_PAD_DATA_LIST,
+            COL_TX_COMPLETED,
+            COL_TX_DATA_ROOT_INDEX,
+        ] {
+            for item in db.iter(col) {
+                let (key, value) = item.unwrap();
+                records.insert((index as u8, col, key.to_vec(), value));
+            }
+        }
+    }
+    records
+}
+
 fn create_store() -> LogManager {
     let config = LogConfig::default();
     LogManager::memorydb(config).unwrap()
 }
 
 fn put_tx(store: &mut LogManager, chunk_count: usize, seq: u64) {
-    let data_size = CHUNK_SIZE * chunk_count;
-    let mut data = vec![0u8; data_size];
+    let (tx, data) = put_tx_without_chunks(store, chunk_count, seq);
+    put_tx_chunks(store, &tx, &data, 0, chunk_count);
+    store.finalize_tx(tx.seq).unwrap();
+}
+
+fn put_tx_without_chunks(
+    store: &mut LogManager,
+    chunk_count: usize,
+    seq: u64,
+) -> (Transaction, Vec<u8>) {
+    put_tx_of_size_without_chunks(store, CHUNK_SIZE * chunk_count, seq)
+}
+
+/// Puts a tx of `data_size` bytes, of which the returned data is padded to full chunks.
+fn put_tx_of_size_without_chunks(
+    store: &mut LogManager,
+    data_size: usize,
+    seq: u64,
+) -> (Transaction, Vec<u8>) {
+    let chunk_count = bytes_to_chunks(data_size);
+    let mut data = vec![0u8; CHUNK_SIZE * chunk_count];
     for i in 0..chunk_count {
-        data[i * CHUNK_SIZE..(i * CHUNK_SIZE + 8)].copy_from_slice(&(seq + 1).to_be_bytes());
+        let marker = (seq + 1).to_be_bytes();
+        let end = (i * CHUNK_SIZE + marker.len()).min(data_size);
+        data[i * CHUNK_SIZE..end].copy_from_slice(&marker[..end - i * CHUNK_SIZE]);
     }
     let tx_merkle = sub_merkle_tree(&data).unwrap();
     let merkle_nodes = tx_subtree_root_list_padded(&data);
@@ -201,13 +969,39 @@ fn put_tx(store: &mut LogManager, chunk_count: usize, seq: u64) {
         merkle_nodes,
     };
     store.put_tx(tx.clone()).unwrap();
-    for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
-        let end = cmp::min((start_index + PORA_CHUNK_SIZE) * CHUNK_SIZE, data.len());
+    (tx, data)
+}
+
+/// Puts a tx of the same data as `tx`, i.e. the file is uploaded again.
+fn put_same_data_tx(store: &mut LogManager, tx: &Transaction, seq: u64) -> Transaction {
+    let flow_len = store.get_context().unwrap().1;
+    let first_subtree_size = 1 << (tx.merkle_nodes.first().unwrap().0 - 1);
+    let start_entry_index = ((flow_len - 1) / first_subtree_size + 1) * first_subtree_size;
+    let tx = Transaction {
+        seq,
+        start_entry_index,
+        ..tx.clone()
+    };
+    store.put_tx(tx.clone()).unwrap();
+    tx
+}
+
+fn put_tx_chunks(
+    store: &mut LogManager,
+    tx: &Transaction,
+    data: &[u8],
+    start_chunk: usize,
+    end_chunk: usize,
+) {
+    for start_index in (start_chunk..end_chunk).step_by(PORA_CHUNK_SIZE) {
+        let end = cmp::min(
+            cmp::min(start_index + PORA_CHUNK_SIZE, end_chunk) * CHUNK_SIZE,
+            data.len(),
+        );
         let chunk_array = ChunkArray {
             data: data[start_index * CHUNK_SIZE..end].to_vec(),
             start_index: start_index as u64,
         };
         store.put_chunks(tx.seq, chunk_array.clone()).unwrap();
     }
-    store.finalize_tx(tx.seq).unwrap();
 }
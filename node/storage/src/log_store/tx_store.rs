@@ -5,15 +5,16 @@ use crate::log_store::log_manager::{
 };
 use crate::log_store::metrics;
 use crate::{try_option, LogManager, ZgsKeyValueDB};
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use append_merkle::{AppendMerkleTree, MerkleTreeRead, OptionalHash, Sha3Algorithm};
 use ethereum_types::H256;
 use merkle_light::merkle::log2_pow2;
+use parking_lot::{Mutex, RwLock};
 use shared_types::{DataRoot, Transaction};
 use ssz::{Decode, Encode};
 use std::cmp;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
@@ -22,11 +23,53 @@ use tracing::{error, instrument};
 const LOG_SYNC_PROGRESS_KEY: &str = "log_sync_progress";
 const NEXT_TX_KEY: &str = "next_tx_seq";
 const LOG_LATEST_BLOCK_NUMBER_KEY: &str = "log_latest_block_number_key";
+/// Prefix of the key of a pinned tx, followed by the tx seq.
+const PINNED_TX_KEY_PREFIX: &str = "pinned_";
+/// Prefix of the key of the flow root after a tx, followed by the tx seq.
+const FLOW_ROOT_KEY_PREFIX: &str = "flow_root_";
+/// Prefix of the key of the tags of a tx, followed by the tx seq.
+const TX_TAGS_KEY_PREFIX: &str = "tx_tags_";
+/// Prefix of the key of a tx with a tag, followed by the tag, `_` and the tx seq.
+const TAGGED_TX_KEY_PREFIX: &str = "tagged_";
+/// Prefix of the key of the reason why a tx is skipped, followed by the tx seq.
+const SKIP_REASON_KEY_PREFIX: &str = "skip_reason_";
+
+/// Maximum number of tags of a tx.
+pub const MAX_TAGS_PER_TX: usize = 16;
+/// Maximum length in bytes of a tag.
+pub const MAX_TAG_LEN: usize = 64;
+
+fn pinned_tx_key(tx_seq: u64) -> Vec<u8> {
+    [PINNED_TX_KEY_PREFIX.as_bytes(), &tx_seq.to_be_bytes()].concat()
+}
+
+fn flow_root_key(tx_seq: u64) -> Vec<u8> {
+    [FLOW_ROOT_KEY_PREFIX.as_bytes(), &tx_seq.to_be_bytes()].concat()
+}
+
+fn tx_tags_key(tx_seq: u64) -> Vec<u8> {
+    [TX_TAGS_KEY_PREFIX.as_bytes(), &tx_seq.to_be_bytes()].concat()
+}
+
+fn tagged_txs_prefix(tag: &str) -> Vec<u8> {
+    [TAGGED_TX_KEY_PREFIX.as_bytes(), tag.as_bytes(), b"_"].concat()
+}
+
+fn tagged_tx_key(tag: &str, tx_seq: u64) -> Vec<u8> {
+    [tagged_txs_prefix(tag), tx_seq.to_be_bytes().to_vec()].concat()
+}
+
+fn skip_reason_key(tx_seq: u64) -> Vec<u8> {
+    [SKIP_REASON_KEY_PREFIX.as_bytes(), &tx_seq.to_be_bytes()].concat()
+}
 
 #[derive(Debug)]
 pub enum TxStatus {
     Finalized,
     Pruned,
+    /// The data is not downloaded from peers, e.g. since the uploader is not allowlisted or
+    /// the tx is malformed. The reason is recorded along with the status.
+    Skipped,
 }
 
 impl From<TxStatus> for u8 {
@@ -34,6 +77,7 @@ impl From<TxStatus> for u8 {
         match value {
             TxStatus::Finalized => 0,
             TxStatus::Pruned => 1,
+            TxStatus::Skipped => 2,
         }
     }
 }
@@ -45,6 +89,7 @@ impl TryFrom<u8> for TxStatus {
         match value {
             0 => Ok(TxStatus::Finalized),
             1 => Ok(TxStatus::Pruned),
+            2 => Ok(TxStatus::Skipped),
             _ => Err(anyhow!("invalid value for tx status {}", value)),
         }
     }
@@ -61,6 +106,15 @@ pub struct TransactionStore {
     data_kvdb: Arc<dyn ZgsKeyValueDB>,
     /// This is always updated before writing the database to ensure no intermediate states.
     next_tx_seq: AtomicU64,
+    /// Number of txs from the start of the log that have a status, i.e. the next tx to check
+    /// for `get_highest_finalized_seq`. It's loaded at open, advanced on query and reset on
+    /// reverts, and the lock is held across a query so that a revert is not overwritten.
+    finalized_prefix: Mutex<u64>,
+    /// The pinned tx seqs in the database. The write lock is held while the pins are updated,
+    /// and the read lock while a tx is pruned, so that a tx is never pruned after pinned.
+    pinned: RwLock<BTreeSet<u64>>,
+    /// Held while the tags are updated.
+    tags_lock: Mutex<()>,
 }
 
 impl TransactionStore {
@@ -72,10 +126,18 @@ impl TransactionStore {
             .get(COL_TX, NEXT_TX_KEY.as_bytes())?
             .map(|a| decode_tx_seq(&a))
             .unwrap_or(Ok(0))?;
+        let pinned = data_kvdb
+            .iter_with_prefix(COL_MISC, PINNED_TX_KEY_PREFIX.as_bytes())
+            .map(|item| decode_pinned_tx_key(&item?.0))
+            .collect::<Result<_>>()?;
+        let finalized_prefix = load_finalized_prefix(data_kvdb.as_ref(), next_tx_seq)?;
         Ok(Self {
             flow_kvdb,
             data_kvdb,
             next_tx_seq: AtomicU64::new(next_tx_seq),
+            finalized_prefix: Mutex::new(finalized_prefix),
+            pinned: RwLock::new(pinned),
+            tags_lock: Mutex::new(()),
         })
     }
 
@@ -135,6 +197,8 @@ impl TransactionStore {
     }
 
     pub fn remove_tx_after(&self, min_seq: u64) -> Result<Vec<Transaction>> {
+        let mut pinned = self.pinned.write();
+        let _tags_lock = self.tags_lock.lock();
         let mut removed_txs = Vec::new();
         let max_seq = self.next_tx_seq();
         let mut flow_db_tx = self.flow_kvdb.transaction();
@@ -146,6 +210,7 @@ impl TransactionStore {
                 break;
             };
             flow_db_tx.delete(COL_TX, &seq.to_be_bytes());
+            flow_db_tx.delete(COL_MISC, &flow_root_key(seq));
             data_db_tx.delete(COL_TX_COMPLETED, &seq.to_be_bytes());
             // We only remove tx when the blockchain reorgs.
             // If a tx is reverted, all data after it will also be reverted, so we call remove
@@ -157,6 +222,12 @@ impl TransactionStore {
                 }
             };
             tx_seq_list.retain(|e| *e < seq);
+            // The reverted txs are not tagged anymore.
+            for tag in self.get_tx_tags(seq)? {
+                data_db_tx.delete(COL_MISC, &tagged_tx_key(&tag, seq));
+            }
+            data_db_tx.delete(COL_MISC, &tx_tags_key(seq));
+            data_db_tx.delete(COL_MISC, &skip_reason_key(seq));
             removed_txs.push(tx);
         }
         for (merkle_root, tx_seq_list) in modified_merkle_root_map {
@@ -170,9 +241,18 @@ impl TransactionStore {
                 );
             }
         }
+        // The reverted txs are not pinned anymore.
+        for seq in pinned.range(min_seq..) {
+            data_db_tx.delete(COL_MISC, &pinned_tx_key(*seq));
+        }
         flow_db_tx.put(COL_TX, NEXT_TX_KEY.as_bytes(), &min_seq.to_be_bytes());
         self.next_tx_seq.store(min_seq, Ordering::SeqCst);
+        {
+            let mut finalized_prefix = self.finalized_prefix.lock();
+            *finalized_prefix = cmp::min(*finalized_prefix, min_seq);
+        }
         self.data_kvdb.write(data_db_tx)?;
+        pinned.split_off(&min_seq);
         self.flow_kvdb.write(flow_db_tx)?;
         Ok(removed_txs)
     }
@@ -198,7 +278,29 @@ impl TransactionStore {
     }
 
     #[instrument(skip(self))]
-    pub fn prune_tx(&self, tx_seq: u64) -> Result<()> {
+    pub fn skip_tx(&self, tx_seq: u64, reason: &str) -> Result<()> {
+        let mut db_tx = self.data_kvdb.transaction();
+        db_tx.put(
+            COL_TX_COMPLETED,
+            &tx_seq.to_be_bytes(),
+            &[TxStatus::Skipped.into()],
+        );
+        db_tx.put(COL_MISC, &skip_reason_key(tx_seq), reason.as_bytes());
+        Ok(self.data_kvdb.write(db_tx)?)
+    }
+
+    pub fn get_skip_reason(&self, tx_seq: u64) -> Result<Option<String>> {
+        let value = try_option!(self.data_kvdb.get(COL_MISC, &skip_reason_key(tx_seq))?);
+        Ok(Some(String::from_utf8_lossy(&value).into_owned()))
+    }
+
+    #[instrument(skip(self))]
+    /// Return `false` if the tx is pinned and not pruned.
+    pub fn prune_tx(&self, tx_seq: u64) -> Result<bool> {
+        let pinned = self.pinned.read();
+        if pinned.contains(&tx_seq) {
+            return Ok(false);
+        }
         let tx = self
             .get_tx_by_seq_number(tx_seq)?
             .ok_or_else(|| anyhow!("prune_tx with tx missing: tx_seq={}", tx_seq))?;
@@ -225,7 +327,7 @@ impl TransactionStore {
 
         self.data_kvdb.write(data_db_tx)?;
         self.flow_kvdb.write(flow_db_tx)?;
-        Ok(())
+        Ok(true)
     }
 
     pub fn get_tx_status(&self, tx_seq: u64) -> Result<Option<TxStatus>> {
@@ -246,11 +348,168 @@ impl TransactionStore {
         Ok(matches!(status, Some(TxStatus::Finalized)))
     }
 
+    /// Return the highest tx seq of which the data of all the txs up to it is settled, or
+    /// `None` if the first tx is not. A tx is settled once finalized, and stays settled if
+    /// pruned or skipped since its data is never downloaded again, so only the txs waiting for
+    /// data hold it back.
+    pub fn get_highest_finalized_seq(&self) -> Result<Option<u64>> {
+        let mut finalized_prefix = self.finalized_prefix.lock();
+        let next_tx_seq = self.next_tx_seq();
+        while *finalized_prefix < next_tx_seq && self.get_tx_status(*finalized_prefix)?.is_some() {
+            *finalized_prefix += 1;
+        }
+        Ok(finalized_prefix.checked_sub(1))
+    }
+
+    pub fn get_finalized_tx_ranges(&self) -> Result<Vec<(u64, u64)>> {
+        let mut ranges: Vec<(u64, u64)> = vec![];
+        for item in self.data_kvdb.iter(COL_TX_COMPLETED) {
+            let (key, value) = item?;
+            if value.first() != Some(&TxStatus::Finalized.into()) {
+                continue;
+            }
+            let tx_seq = u64::from_be_bytes(
+                key.as_ref()
+                    .try_into()
+                    .map_err(|_| anyhow!("invalid tx status key: {:?}", key))?,
+            );
+            match ranges.last_mut() {
+                Some((_, end)) if *end == tx_seq => *end += 1,
+                _ => ranges.push((tx_seq, tx_seq + 1)),
+            }
+        }
+        Ok(ranges)
+    }
+
     pub fn check_tx_pruned(&self, tx_seq: u64) -> Result<bool> {
         let status = self.get_tx_status(tx_seq)?;
         Ok(matches!(status, Some(TxStatus::Pruned)))
     }
 
+    /// Return the sorted list of pinned tx seqs.
+    pub fn get_pinned_txs(&self) -> Result<Vec<u64>> {
+        Ok(self.pinned.read().iter().copied().collect())
+    }
+
+    /// Persists the flow root right after the tx of `tx_seq` is appended to the flow.
+    pub fn put_flow_root(&self, tx_seq: u64, root: DataRoot) -> Result<()> {
+        Ok(self
+            .flow_kvdb
+            .put(COL_MISC, &flow_root_key(tx_seq), root.as_bytes())?)
+    }
+
+    /// Returns the flow root persisted for the tx of `tx_seq`, or `None` if the tx does not
+    /// exist or was appended before the roots are persisted.
+    pub fn get_flow_root(&self, tx_seq: u64) -> Result<Option<DataRoot>> {
+        if tx_seq >= self.next_tx_seq() {
+            return Ok(None);
+        }
+        let value = try_option!(self.flow_kvdb.get(COL_MISC, &flow_root_key(tx_seq))?);
+        if value.len() != DataRoot::len_bytes() {
+            bail!("invalid flow root: tx_seq={} len={}", tx_seq, value.len());
+        }
+        Ok(Some(DataRoot::from_slice(&value)))
+    }
+
+    pub fn check_tx_pinned(&self, tx_seq: u64) -> Result<bool> {
+        Ok(self.pinned.read().contains(&tx_seq))
+    }
+
+    /// Pin a tx so that its data is never pruned.
+    /// Return `false` if the tx has been pinned already.
+    #[instrument(skip(self))]
+    pub fn pin_tx(&self, tx_seq: u64) -> Result<bool> {
+        let mut pinned = self.pinned.write();
+        if pinned.contains(&tx_seq) {
+            return Ok(false);
+        }
+        if self.check_tx_pruned(tx_seq)? {
+            bail!("pin_tx with tx pruned: tx_seq={}", tx_seq);
+        }
+        self.data_kvdb.put(COL_MISC, &pinned_tx_key(tx_seq), &[])?;
+        pinned.insert(tx_seq);
+        Ok(true)
+    }
+
+    /// Return `false` if the tx is not pinned.
+    #[instrument(skip(self))]
+    pub fn unpin_tx(&self, tx_seq: u64) -> Result<bool> {
+        let mut pinned = self.pinned.write();
+        if !pinned.contains(&tx_seq) {
+            return Ok(false);
+        }
+        self.data_kvdb.delete(COL_MISC, &pinned_tx_key(tx_seq))?;
+        pinned.remove(&tx_seq);
+        Ok(true)
+    }
+
+    /// Return the tags of a tx in the order they were set.
+    pub fn get_tx_tags(&self, tx_seq: u64) -> Result<Vec<String>> {
+        let value = match self.data_kvdb.get(COL_MISC, &tx_tags_key(tx_seq))? {
+            Some(v) => v,
+            None => return Ok(Vec::new()),
+        };
+        Vec::<Vec<u8>>::from_ssz_bytes(&value)
+            .map_err(Error::from)?
+            .into_iter()
+            .map(|tag| Ok(String::from_utf8(tag)?))
+            .collect()
+    }
+
+    /// Return the sorted list of the tx seqs with `tag`.
+    pub fn get_tagged_txs(&self, tag: &str) -> Result<Vec<u64>> {
+        let prefix = tagged_txs_prefix(tag);
+        let mut tagged = vec![];
+        for item in self.data_kvdb.iter_with_prefix(COL_MISC, &prefix) {
+            let key = item?.0;
+            // Skip the keys of the other tags that start with `<tag>_`.
+            if key.len() == prefix.len() + 8 {
+                tagged.push(decode_tx_seq(&key[prefix.len()..])?);
+            }
+        }
+        Ok(tagged)
+    }
+
+    /// Replace the tags of a tx, or remove them if `tags` is empty. Duplicated tags are only
+    /// kept once.
+    #[instrument(skip(self))]
+    pub fn set_tx_tags(&self, tx_seq: u64, tags: Vec<String>) -> Result<()> {
+        let mut new_tags: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            if tag.is_empty() || tag.len() > MAX_TAG_LEN {
+                bail!("invalid tag length: tag={:?} max_len={}", tag, MAX_TAG_LEN);
+            }
+            if !new_tags.contains(&tag) {
+                new_tags.push(tag);
+            }
+        }
+        if new_tags.len() > MAX_TAGS_PER_TX {
+            bail!(
+                "too many tags: tx_seq={} num={} max={}",
+                tx_seq,
+                new_tags.len(),
+                MAX_TAGS_PER_TX
+            );
+        }
+
+        let _tags_lock = self.tags_lock.lock();
+        let old_tags = self.get_tx_tags(tx_seq)?;
+        let mut db_tx = self.data_kvdb.transaction();
+        for tag in old_tags.iter().filter(|tag| !new_tags.contains(tag)) {
+            db_tx.delete(COL_MISC, &tagged_tx_key(tag, tx_seq));
+        }
+        for tag in new_tags.iter().filter(|tag| !old_tags.contains(tag)) {
+            db_tx.put(COL_MISC, &tagged_tx_key(tag, tx_seq), &[]);
+        }
+        if new_tags.is_empty() {
+            db_tx.delete(COL_MISC, &tx_tags_key(tx_seq));
+        } else {
+            let encoded: Vec<Vec<u8>> = new_tags.into_iter().map(String::into_bytes).collect();
+            db_tx.put(COL_MISC, &tx_tags_key(tx_seq), &encoded.as_ssz_bytes());
+        }
+        Ok(self.data_kvdb.write(db_tx)?)
+    }
+
     pub fn next_tx_seq(&self) -> u64 {
         self.next_tx_seq.load(Ordering::SeqCst)
     }
@@ -438,6 +697,26 @@ impl TransactionStore {
     }
 }
 
+fn decode_pinned_tx_key(key: &[u8]) -> Result<u64> {
+    decode_tx_seq(
+        key.strip_prefix(PINNED_TX_KEY_PREFIX.as_bytes())
+            .ok_or_else(|| anyhow!("invalid pinned tx key: {:?}", key))?,
+    )
+}
+
+/// Return the number of the txs from the start of the log that have a status.
+fn load_finalized_prefix(data_kvdb: &dyn ZgsKeyValueDB, next_tx_seq: u64) -> Result<u64> {
+    let mut prefix = 0;
+    for item in data_kvdb.iter(COL_TX_COMPLETED) {
+        let (key, _) = item?;
+        if prefix >= next_tx_seq || decode_tx_seq(&key)? != prefix {
+            break;
+        }
+        prefix += 1;
+    }
+    Ok(prefix)
+}
+
 fn decode_tx_seq(data: &[u8]) -> Result<u64> {
     Ok(u64::from_be_bytes(
         data.try_into().map_err(|e| anyhow!("{:?}", e))?,
@@ -0,0 +1,219 @@
+//! Stages the segments of the chunked uploads in the data db, so that an upload is not bounded
+//! by the memory and resumes after a restart.
+//!
+//! A segment is uploaded without the proof, so it cannot be written into the flow until the
+//! whole file is verified against the declared root. Hence the segments are kept apart from
+//! the flow together with their roots, and are moved into the flow once verified and the tx
+//! is retrieved.
+
+use crate::log_store::log_manager::COL_MISC;
+use crate::ZgsKeyValueDB;
+use anyhow::{anyhow, bail, Result};
+use ethereum_types::H256;
+use shared_types::DataRoot;
+use std::sync::Arc;
+
+/// Prefix of the key of the file info of an upload, followed by the file root.
+const UPLOAD_INFO_KEY_PREFIX: &str = "upload_info_";
+/// Prefix of the key of a segment root, followed by the file root and the segment index.
+const UPLOAD_ROOT_KEY_PREFIX: &str = "upload_root_";
+/// Prefix of the key of a segment data, followed by the file root and the segment index.
+const UPLOAD_DATA_KEY_PREFIX: &str = "upload_data_";
+
+fn upload_info_key(root: &DataRoot) -> Vec<u8> {
+    [UPLOAD_INFO_KEY_PREFIX.as_bytes(), root.as_bytes()].concat()
+}
+
+fn upload_root_prefix(root: &DataRoot) -> Vec<u8> {
+    [UPLOAD_ROOT_KEY_PREFIX.as_bytes(), root.as_bytes()].concat()
+}
+
+fn upload_data_prefix(root: &DataRoot) -> Vec<u8> {
+    [UPLOAD_DATA_KEY_PREFIX.as_bytes(), root.as_bytes()].concat()
+}
+
+fn segment_key(prefix: Vec<u8>, index: usize) -> Vec<u8> {
+    [prefix, (index as u64).to_be_bytes().to_vec()].concat()
+}
+
+/// A chunked upload staged in the store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StagedUpload {
+    pub file_size: usize,
+    pub chunks_per_segment: usize,
+    /// Indices and roots of the received segments in ascending order of the indices.
+    pub segment_roots: Vec<(usize, H256)>,
+}
+
+pub struct UploadStore {
+    db: Arc<dyn ZgsKeyValueDB>,
+}
+
+impl UploadStore {
+    pub fn new(db: Arc<dyn ZgsKeyValueDB>) -> Self {
+        Self { db }
+    }
+
+    /// Stores the segment `index` of the upload of `root` with its root, which fails if the
+    /// upload was started with another file size or segment size.
+    pub fn put_segment(
+        &self,
+        root: &DataRoot,
+        file_size: usize,
+        chunks_per_segment: usize,
+        index: usize,
+        segment_root: H256,
+        data: &[u8],
+    ) -> Result<()> {
+        if let Some((stored_size, stored_chunks)) = self.get_info(root)? {
+            if (stored_size, stored_chunks) != (file_size, chunks_per_segment) {
+                bail!(
+                    "(file size, chunks per segment) mismatch, ({}, {}) declared before, now ({}, {})",
+                    stored_size,
+                    stored_chunks,
+                    file_size,
+                    chunks_per_segment
+                );
+            }
+        }
+
+        let mut tx = self.db.transaction();
+        tx.put(
+            COL_MISC,
+            &upload_info_key(root),
+            &[
+                (file_size as u64).to_be_bytes(),
+                (chunks_per_segment as u64).to_be_bytes(),
+            ]
+            .concat(),
+        );
+        tx.put(
+            COL_MISC,
+            &segment_key(upload_root_prefix(root), index),
+            segment_root.as_bytes(),
+        );
+        tx.put(
+            COL_MISC,
+            &segment_key(upload_data_prefix(root), index),
+            data,
+        );
+        self.db.write(tx)?;
+        Ok(())
+    }
+
+    pub fn get(&self, root: &DataRoot) -> Result<Option<StagedUpload>> {
+        let (file_size, chunks_per_segment) = match self.get_info(root)? {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+
+        let prefix = upload_root_prefix(root);
+        let mut segment_roots = vec![];
+        for item in self.db.iter_with_prefix(COL_MISC, &prefix) {
+            let (key, value) = item?;
+            let index = key[prefix.len()..]
+                .try_into()
+                .map_err(|_| anyhow!("invalid upload segment key: {:?}", key))?;
+            if value.len() != 32 {
+                bail!("invalid upload segment root length: {}", value.len());
+            }
+            segment_roots.push((u64::from_be_bytes(index) as usize, H256::from_slice(&value)));
+        }
+
+        Ok(Some(StagedUpload {
+            file_size,
+            chunks_per_segment,
+            segment_roots,
+        }))
+    }
+
+    pub fn get_segment(&self, root: &DataRoot, index: usize) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .db
+            .get(COL_MISC, &segment_key(upload_data_prefix(root), index))?)
+    }
+
+    /// Returns the roots of all the staged uploads.
+    pub fn get_roots(&self) -> Result<Vec<DataRoot>> {
+        self.db
+            .iter_with_prefix(COL_MISC, UPLOAD_INFO_KEY_PREFIX.as_bytes())
+            .map(|item| {
+                let (key, _) = item?;
+                let root = &key[UPLOAD_INFO_KEY_PREFIX.len()..];
+                if root.len() != 32 {
+                    bail!("invalid upload info key: {:?}", key);
+                }
+                Ok(DataRoot::from_slice(root))
+            })
+            .collect()
+    }
+
+    pub fn remove(&self, root: &DataRoot) -> Result<()> {
+        let mut tx = self.db.transaction();
+        tx.delete(COL_MISC, &upload_info_key(root));
+        tx.delete_prefix(COL_MISC, &upload_root_prefix(root));
+        tx.delete_prefix(COL_MISC, &upload_data_prefix(root));
+        self.db.write(tx)?;
+        Ok(())
+    }
+
+    fn get_info(&self, root: &DataRoot) -> Result<Option<(usize, usize)>> {
+        let value = match self.db.get(COL_MISC, &upload_info_key(root))? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        if value.len() != 16 {
+            bail!("invalid upload info length: {}", value.len());
+        }
+        Ok(Some((
+            u64::from_be_bytes(value[..8].try_into().unwrap()) as usize,
+            u64::from_be_bytes(value[8..].try_into().unwrap()) as usize,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UploadStore;
+    use crate::log_store::log_manager::COL_NUM;
+    use ethereum_types::H256;
+    use shared_types::DataRoot;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_upload_store() {
+        let store = UploadStore::new(Arc::new(kvdb_memorydb::create(COL_NUM)));
+        let root = DataRoot::repeat_byte(1);
+        let other = DataRoot::repeat_byte(2);
+
+        store
+            .put_segment(&root, 1000, 4, 2, H256::repeat_byte(3), &[3; 8])
+            .unwrap();
+        store
+            .put_segment(&root, 1000, 4, 0, H256::repeat_byte(4), &[4; 8])
+            .unwrap();
+        store
+            .put_segment(&other, 10, 4, 0, H256::repeat_byte(5), &[5; 8])
+            .unwrap();
+        // the file info is fixed by the first segment
+        assert!(store
+            .put_segment(&root, 2000, 4, 1, H256::repeat_byte(6), &[6; 8])
+            .is_err());
+
+        let upload = store.get(&root).unwrap().unwrap();
+        assert_eq!((upload.file_size, upload.chunks_per_segment), (1000, 4));
+        assert_eq!(
+            upload.segment_roots,
+            vec![(0, H256::repeat_byte(4)), (2, H256::repeat_byte(3))]
+        );
+        assert_eq!(store.get_segment(&root, 2).unwrap(), Some(vec![3; 8]));
+        assert_eq!(store.get_segment(&root, 1).unwrap(), None);
+        assert_eq!(store.get_roots().unwrap(), vec![root, other]);
+
+        store.remove(&root).unwrap();
+        assert!(store.get(&root).unwrap().is_none());
+        assert!(store.get_segment(&root, 0).unwrap().is_none());
+        assert_eq!(store.get_roots().unwrap(), vec![other]);
+        assert!(store.get(&other).unwrap().is_some());
+    }
+}
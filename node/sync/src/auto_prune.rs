@@ -0,0 +1,310 @@
+//! Keeps the storage of a node within a fixed cap by pruning the oldest files.
+//!
+//! Once the storage exceeds the cap, new segment downloads are paused until the storage is under
+//! the cap again, and the finalized files that are not pinned are pruned from the oldest one.
+//! Pinned files are never pruned, so they may keep the storage over the cap.
+//!
+//! Files are pruned in the order of tx seq, and the files that are not finalized yet are
+//! skipped and pruned once finalized. The chunk batch at either end of a file may be shared
+//! with the adjacent file, and is only removed together with the file that is pruned later.
+//!
+//! The space of the pruned files is not reclaimed on disk until the db compaction, so the usage
+//! is reduced by the size of the pruned files until it's measured again in the next check.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use shared_types::CHUNK_SIZE;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use storage::log_store::log_manager::{DATA_DB_KEY, PORA_CHUNK_SIZE};
+use storage::log_store::tx_store::TxStatus;
+use storage_async::Store;
+use task_executor::TaskExecutor;
+
+use crate::disk_watchdog::DiskWatchdog;
+
+/// Key of the next tx seq to prune and the next chunk batch to remove.
+const KEY_PROGRESS: &str = "sync.auto_prune.progress";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoPruneAction {
+    /// Unix timestamp in seconds of the action.
+    pub timestamp: u64,
+    pub pruned_tx_seqs: Vec<u64>,
+    pub used_bytes_before: u64,
+    pub used_bytes_after: u64,
+    /// Whether the storage is still over the cap due to pinned files.
+    pub pinned_over_cap: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoPruneState {
+    /// Storage cap in bytes, 0 if the policy is disabled.
+    pub max_storage_bytes: u64,
+    /// Storage usage in bytes of the last check, or `None` if not checked yet.
+    pub used_bytes: Option<u64>,
+    pub next_tx_seq: u64,
+    pub last_action: Option<AutoPruneAction>,
+}
+
+pub struct AutoPruner {
+    /// Feature is disabled if 0.
+    max_storage_bytes: u64,
+    store: Store,
+    disk_watchdog: Arc<DiskWatchdog>,
+    /// The next tx seq to prune and the next chunk batch to remove.
+    progress: Mutex<(u64, u64)>,
+    state: Mutex<AutoPruneState>,
+}
+
+impl AutoPruner {
+    /// Loads the pruning progress persisted in db.
+    pub async fn new(
+        max_storage_bytes: u64,
+        store: Store,
+        disk_watchdog: Arc<DiskWatchdog>,
+    ) -> Result<Self> {
+        let progress: (u64, u64) = store
+            .get_config_decoded(&KEY_PROGRESS, DATA_DB_KEY)
+            .await?
+            .unwrap_or_default();
+
+        Ok(Self {
+            max_storage_bytes,
+            store,
+            disk_watchdog,
+            progress: Mutex::new(progress),
+            state: Mutex::new(AutoPruneState {
+                max_storage_bytes,
+                next_tx_seq: progress.0,
+                ..Default::default()
+            }),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_storage_bytes > 0
+    }
+
+    pub fn state(&self) -> AutoPruneState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Checks the storage usage, and prunes the oldest files if it exceeds the cap.
+    pub async fn check(&self) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let used_bytes = self.store.get_storage_usage().await?;
+        self.state.lock().unwrap().used_bytes = Some(used_bytes);
+        if used_bytes <= self.max_storage_bytes {
+            self.disk_watchdog.set_pruning(false);
+            return Ok(());
+        }
+
+        // pause new downloads until the storage is under the cap
+        self.disk_watchdog.set_pruning(true);
+        let used_bytes = self.prune_to_cap(used_bytes).await?;
+        self.disk_watchdog
+            .set_pruning(used_bytes > self.max_storage_bytes);
+        Ok(())
+    }
+
+    /// Returns the estimated storage usage after pruning.
+    async fn prune_to_cap(&self, used_bytes_before: u64) -> Result<u64> {
+        let (mut next_tx_seq, mut next_batch) = *self.progress.lock().unwrap();
+        let mut used_bytes = used_bytes_before;
+        let mut pruned_tx_seqs = vec![];
+        let mut pinned_over_cap = false;
+
+        // the next tx seq to check, which is ahead of `next_tx_seq` once a file is skipped
+        let mut scan_tx_seq = next_tx_seq;
+        let mut skipped = false;
+        while used_bytes > self.max_storage_bytes {
+            let tx = match self.store.get_tx_by_seq_number(scan_tx_seq).await? {
+                Some(tx) => tx,
+                None => break,
+            };
+            scan_tx_seq += 1;
+
+            match self.store.get_store().get_tx_status(tx.seq)? {
+                Some(TxStatus::Finalized) => {
+                    if self.store.get_store().check_tx_pinned(tx.seq)? {
+                        pinned_over_cap = true;
+                    } else {
+                        self.store.prune_tx(tx.seq).await?;
+                        pruned_tx_seqs.push(tx.seq);
+                        used_bytes =
+                            used_bytes.saturating_sub(tx.num_entries() as u64 * CHUNK_SIZE as u64);
+                    }
+                }
+                Some(TxStatus::Pruned) | Some(TxStatus::Skipped) => {}
+                None => {
+                    debug!(tx_seq = tx.seq, "File not finalized, skip auto pruning");
+                    skipped = true;
+                    continue;
+                }
+            }
+
+            // Batches of pinned files are kept by the store, and the first batch of a file
+            // after a skipped file may be shared with the skipped file.
+            let start_batch = if skipped {
+                let start_batch =
+                    (tx.start_entry_index + PORA_CHUNK_SIZE as u64 - 1) / PORA_CHUNK_SIZE as u64;
+                start_batch.max(next_batch)
+            } else {
+                next_batch
+            };
+            let end_batch =
+                (tx.start_entry_index + tx.num_entries() as u64) / PORA_CHUNK_SIZE as u64;
+            if end_batch > start_batch {
+                let batch_list: Vec<u64> = (start_batch..end_batch).collect();
+                self.store.remove_chunks_batch(&batch_list).await?;
+            }
+
+            // resume from the first skipped file in the next check
+            if !skipped {
+                next_tx_seq = scan_tx_seq;
+                next_batch = next_batch.max(end_batch);
+            }
+        }
+
+        self.store
+            .set_config_encoded(&KEY_PROGRESS, &(next_tx_seq, next_batch), DATA_DB_KEY)
+            .await?;
+        *self.progress.lock().unwrap() = (next_tx_seq, next_batch);
+
+        if pinned_over_cap && used_bytes > self.max_storage_bytes {
+            warn!(
+                used = %used_bytes, cap = %self.max_storage_bytes,
+                "Storage exceeds the cap due to pinned files"
+            );
+        }
+        info!(
+            ?pruned_tx_seqs, before = %used_bytes_before, after = %used_bytes,
+            "Auto pruned the oldest files"
+        );
+
+        let mut state = self.state.lock().unwrap();
+        state.used_bytes = Some(used_bytes);
+        state.next_tx_seq = next_tx_seq;
+        state.last_action = Some(AutoPruneAction {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            pruned_tx_seqs,
+            used_bytes_before,
+            used_bytes_after: used_bytes,
+            pinned_over_cap,
+        });
+
+        Ok(used_bytes)
+    }
+
+    pub fn spawn(self: Arc<Self>, executor: &TaskExecutor, interval: Duration) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        executor.spawn(
+            async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = self.check().await {
+                        warn!(%e, "Failed to auto prune files");
+                    }
+                }
+            },
+            "auto_prune",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AutoPruner;
+    use crate::disk_watchdog::DiskWatchdog;
+    use crate::test_util::{create_2_store, tests::TestStoreRuntime};
+    use std::sync::Arc;
+    use storage::log_store::{LogStoreChunkRead, LogStoreChunkWrite, LogStoreWrite};
+
+    #[tokio::test]
+    async fn test_prune_oldest_file() {
+        let (_, peer_store, _, _) = create_2_store(vec![1024, 1024, 1024]);
+        let runtime = TestStoreRuntime::new(peer_store);
+        let store = runtime.store.clone();
+        let used_bytes = store.get_storage_usage().await.unwrap();
+        let disk_watchdog = Arc::new(DiskWatchdog::disabled());
+
+        // exceeding the cap prunes the oldest file only
+        let pruner = AutoPruner::new(used_bytes - 1, store.clone(), disk_watchdog.clone())
+            .await
+            .unwrap();
+        pruner.check().await.unwrap();
+        assert!(!disk_watchdog.is_paused());
+        assert!(store.check_tx_pruned(0).await.unwrap());
+        assert!(store.check_tx_completed(1).await.unwrap());
+        assert!(store.check_tx_completed(2).await.unwrap());
+        let action = pruner.state().last_action.unwrap();
+        assert_eq!(action.pruned_tx_seqs, vec![0]);
+        assert!(action.used_bytes_after < used_bytes);
+        assert!(!action.pinned_over_cap);
+
+        // pinned files are kept over the cap, and the progress is resumed
+        store.pin_tx(1).await.unwrap();
+        let pruner = AutoPruner::new(1, store.clone(), disk_watchdog.clone())
+            .await
+            .unwrap();
+        assert_eq!(pruner.state().next_tx_seq, 1);
+        pruner.check().await.unwrap();
+        assert!(store.check_tx_completed(1).await.unwrap());
+        assert!(store
+            .get_chunks_by_tx_and_index_range(1, 0, 1024)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(store.check_tx_pruned(2).await.unwrap());
+        let action = pruner.state().last_action.unwrap();
+        assert_eq!(action.pruned_tx_seqs, vec![2]);
+        assert!(action.pinned_over_cap);
+
+        // downloads are paused while the storage is over the cap
+        assert!(disk_watchdog.is_paused());
+    }
+
+    #[tokio::test]
+    async fn test_skip_file_not_finalized() {
+        let (store, peer_store, txs, _) = create_2_store(vec![1024, 1024, 1024]);
+        // only the later files are downloaded and finalized
+        for tx in &txs[1..] {
+            let chunks = peer_store
+                .get_chunks_by_tx_and_index_range(tx.seq, 0, 1024)
+                .unwrap()
+                .unwrap();
+            store.put_chunks(tx.seq, chunks).unwrap();
+            store.finalize_tx(tx.seq).unwrap();
+        }
+        let runtime = TestStoreRuntime::new(store);
+        let store = runtime.store.clone();
+
+        let pruner = AutoPruner::new(1, store.clone(), Arc::new(DiskWatchdog::disabled()))
+            .await
+            .unwrap();
+        pruner.check().await.unwrap();
+        assert!(!store.check_tx_completed(0).await.unwrap());
+        assert!(store.check_tx_pruned(1).await.unwrap());
+        assert!(store.check_tx_pruned(2).await.unwrap());
+        assert_eq!(
+            pruner.state().last_action.unwrap().pruned_tx_seqs,
+            vec![1, 2]
+        );
+
+        // resume from the skipped file
+        assert_eq!(pruner.state().next_tx_seq, 0);
+    }
+}
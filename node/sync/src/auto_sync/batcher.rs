@@ -135,10 +135,10 @@ impl Batcher {
                 }
             }
 
-            // finding peers timeout
-            Some(SyncState::FindingPeers { origin, .. })
-                if origin.elapsed() > self.find_peer_timeout =>
-            {
+            // finding peers timeout, including the time the query is queued
+            Some(
+                SyncState::AwaitingQuery { origin, .. } | SyncState::FindingPeers { origin, .. },
+            ) if origin.elapsed() > self.find_peer_timeout => {
                 debug!(%tx_seq, "Terminate file sync due to finding peers timeout");
                 self.terminate_file_sync(tx_seq, false).await;
                 Ok(Some(SyncResult::Timeout))
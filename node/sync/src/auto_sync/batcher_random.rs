@@ -1,9 +1,11 @@
 use super::{batcher::Batcher, metrics::RandomBatcherMetrics, sync_store::SyncStore};
 use crate::{
-    auto_sync::{batcher::SyncResult, sync_store::Queue},
+    auto_sync::{batcher::SyncResult, sync_store::DeadLetterFile},
+    worker_watchdog::Heartbeat,
     Config, SyncSender,
 };
 use anyhow::Result;
+use log_dedup::{dedup_warn, LogDedup};
 use serde::{Deserialize, Serialize};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
@@ -20,6 +22,7 @@ pub struct RandomBatcherState {
     pub pending_txs: usize,
     pub ready_txs: usize,
     pub cached_ready_txs: usize,
+    pub dead_txs: usize,
 }
 
 #[derive(Clone)]
@@ -63,19 +66,32 @@ impl RandomBatcher {
             pending_txs,
             ready_txs,
             cached_ready_txs,
+            dead_txs: self.sync_store.dead_count().await?,
         })
     }
 
-    pub async fn start(mut self, catched_up: Arc<AtomicBool>) {
+    pub async fn dead_letters(&self) -> Result<Vec<DeadLetterFile>> {
+        self.sync_store.dead_letters().await
+    }
+
+    pub async fn retry_dead_letter(&self, tx_seq: u64) -> Result<bool> {
+        self.sync_store.retry_dead_letter(tx_seq).await
+    }
+
+    pub async fn start(mut self, catched_up: Arc<AtomicBool>, heartbeat: Heartbeat) {
         info!("Start to sync files, state = {:?}", self.get_state().await);
 
         // wait for log entry sync catched up
         while !catched_up.load(Ordering::Relaxed) {
             trace!("Cannot sync file in catch-up phase");
+            heartbeat.beat();
             sleep(self.config.auto_sync_idle_interval).await;
         }
 
+        let mut sync_errors = LogDedup::default();
         loop {
+            heartbeat.beat();
+
             if let Ok(state) = self.get_state().await {
                 self.metrics
                     .update_state(state.ready_txs, state.pending_txs);
@@ -91,7 +107,8 @@ impl RandomBatcher {
                     sleep(self.config.auto_sync_idle_interval).await;
                 }
                 Err(err) => {
-                    warn!(%err, "Failed to sync file once, state = {:?}", self.get_state().await);
+                    dedup_warn!(sync_errors, "Failed to sync file once: {:?}", err);
+                    debug!("Sync state on error = {:?}", self.get_state().await);
                     sleep(self.config.auto_sync_error_interval).await;
                 }
             }
@@ -114,8 +131,16 @@ impl RandomBatcher {
 
         if matches!(sync_result, SyncResult::Completed) {
             self.sync_store.remove(tx_seq).await?;
-        } else {
-            self.sync_store.insert(tx_seq, Queue::Pending).await?;
+        } else if self
+            .sync_store
+            .record_failure(
+                tx_seq,
+                &format!("{:?}", sync_result),
+                self.config.max_sync_file_attempts,
+            )
+            .await?
+        {
+            warn!(%tx_seq, ?sync_result, "Moved file to dead letter queue after too many sync failures");
         }
 
         Ok(true)
@@ -7,6 +7,7 @@ use crate::{
     Config, SyncSender,
 };
 use anyhow::Result;
+use log_dedup::{dedup_warn, LogDedup};
 use log_entry_sync::LogSyncEvent;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -126,6 +127,7 @@ impl SerialBatcher {
         catched_up: Arc<AtomicBool>,
     ) {
         info!("Start to sync files, state = {:?}", self.get_state().await);
+        let mut sync_errors = LogDedup::default();
 
         loop {
             // handle all pending file announcements
@@ -168,7 +170,8 @@ impl SerialBatcher {
                     sleep(self.config.auto_sync_idle_interval).await;
                 }
                 Err(err) => {
-                    warn!(%err, "Failed to sync file once, state = {:?}", self.get_state().await);
+                    dedup_warn!(sync_errors, "Failed to sync file once: {:?}", err);
+                    debug!("Sync state on error = {:?}", self.get_state().await);
                     sleep(self.config.auto_sync_error_interval).await;
                 }
             }
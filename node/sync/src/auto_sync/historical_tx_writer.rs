@@ -4,6 +4,7 @@ use std::sync::{
 };
 
 use anyhow::Result;
+use log_dedup::{dedup_warn, LogDedup};
 use serde::{Deserialize, Serialize};
 use storage::log_store::log_manager::DATA_DB_KEY;
 use storage_async::Store;
@@ -60,6 +61,7 @@ impl HistoricalTxWriter {
             self.get_state().await
         );
 
+        let mut write_errors = LogDedup::default();
         loop {
             match self.write_once().await {
                 Ok(true) => {}
@@ -71,7 +73,8 @@ impl HistoricalTxWriter {
                     sleep(self.config.auto_sync_idle_interval).await;
                 }
                 Err(err) => {
-                    warn!(%err, "Failed to write tx once, state = {:?}", self.get_state().await);
+                    dedup_warn!(write_errors, "Failed to write tx once: {:?}", err);
+                    debug!("Tx writer state on error = {:?}", self.get_state().await);
                     sleep(self.config.auto_sync_error_interval).await;
                 }
             }
@@ -13,7 +13,7 @@ use tokio::sync::{
     oneshot,
 };
 
-use crate::{Config, SyncSender};
+use crate::{worker_watchdog::WorkerWatchdog, Config, SyncSender};
 
 use super::{
     batcher_random::RandomBatcher,
@@ -39,6 +39,7 @@ impl AutoSyncManager {
         sync_send: SyncSender,
         log_sync_recv: broadcast::Receiver<LogSyncEvent>,
         catch_up_end_recv: oneshot::Receiver<()>,
+        worker_watchdog: &Arc<WorkerWatchdog>,
     ) -> Result<Self> {
         let (file_announcement_send, file_announcement_recv) = unbounded_channel();
         let (new_file_send, new_file_recv) = unbounded_channel();
@@ -49,6 +50,7 @@ impl AutoSyncManager {
                 config.ready_txs_cache_cap,
                 "pendingv2",
                 "readyv2",
+                "deadv2",
             ))
         } else {
             Arc::new(SyncStore::new(store.clone(), 0))
@@ -87,7 +89,11 @@ impl AutoSyncManager {
             sync_store,
             metrics::RANDOM_ANNOUNCED.clone(),
         );
-        executor.spawn(random.clone().start(catched_up.clone()), "auto_sync_random");
+        let worker = random.clone();
+        let worker_catched_up = catched_up.clone();
+        worker_watchdog.spawn_supervised(executor, "auto_sync_random", move |heartbeat| {
+            worker.clone().start(worker_catched_up.clone(), heartbeat)
+        });
 
         // handle on catched up notification
         executor.spawn(
@@ -102,6 +108,7 @@ impl AutoSyncManager {
                 0,
                 "pendingv2_historical",
                 "readyv2_historical",
+                "deadv2_historical",
             ));
 
             let writer =
@@ -117,9 +124,15 @@ impl AutoSyncManager {
                 historical_sync_store,
                 metrics::RANDOM_HISTORICAL.clone(),
             );
-            executor.spawn(
-                random_historical.start(catched_up.clone()),
+            let worker_catched_up = catched_up.clone();
+            worker_watchdog.spawn_supervised(
+                executor,
                 "auto_sync_random_historical",
+                move |heartbeat| {
+                    random_historical
+                        .clone()
+                        .start(worker_catched_up.clone(), heartbeat)
+                },
             );
         }
 
@@ -1,5 +1,6 @@
 use super::tx_store::{CachedTxStore, TxStore};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use storage::log_store::{
     config::{ConfigTx, ConfigurableExt},
@@ -23,6 +24,16 @@ pub enum InsertResult {
     AlreadyExists, // already exists in target queue
     Upgraded,      // upgraded from pending queue to ready queue
     Downgraded,    // downgraged from ready queue to pending queue
+    Dead,          // in dead letter queue, which is only synced on demand
+}
+
+/// A file that failed to sync too many times and will not be synced automatically.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetterFile {
+    pub tx_seq: u64,
+    pub attempts: usize,
+    pub last_error: String,
 }
 
 pub struct SyncStore {
@@ -34,11 +45,17 @@ pub struct SyncStore {
     /// Ready transactions to sync with high priority since announcement
     /// already received from other peers.
     ready_txs: CachedTxStore,
+
+    /// Transactions that exceed the retry budget, which are only synced on demand.
+    dead_txs: TxStore,
+
+    /// DB key prefix for the failed attempts and the last error of transactions.
+    dead: &'static str,
 }
 
 impl SyncStore {
     pub fn new(store: Store, ready_txs_cache_cap: usize) -> Self {
-        Self::new_with_name(store, ready_txs_cache_cap, "pending", "ready")
+        Self::new_with_name(store, ready_txs_cache_cap, "pending", "ready", "dead")
     }
 
     pub fn new_with_name(
@@ -46,14 +63,25 @@ impl SyncStore {
         ready_txs_cache_cap: usize,
         pending: &'static str,
         ready: &'static str,
+        dead: &'static str,
     ) -> Self {
         Self {
             store: Arc::new(RwLock::new(store)),
             pending_txs: TxStore::new(pending),
             ready_txs: CachedTxStore::new(ready, ready_txs_cache_cap),
+            dead_txs: TxStore::new(dead),
+            dead,
         }
     }
 
+    fn key_attempts(&self, tx_seq: u64) -> String {
+        format!("sync.manager.txs.{}.attempts.{}", self.dead, tx_seq)
+    }
+
+    fn key_last_error(&self, tx_seq: u64) -> String {
+        format!("sync.manager.txs.{}.error.{}", self.dead, tx_seq)
+    }
+
     /// Returns the number of pending txs and ready txs.
     pub async fn stat(&self) -> Result<(usize, usize, usize)> {
         let async_store = self.store.read().await;
@@ -105,10 +133,16 @@ impl SyncStore {
         Ok(None)
     }
 
+    /// Inserts the tx into the `queue`, unless it is in the dead letter queue, from which it
+    /// could only be retried on demand.
     pub async fn insert(&self, tx_seq: u64, queue: Queue) -> Result<InsertResult> {
         let async_store = self.store.write().await;
         let store = async_store.get_store();
 
+        if self.dead_txs.has(store, tx_seq)? {
+            return Ok(InsertResult::Dead);
+        }
+
         let mut tx = ConfigTx::default();
 
         match queue {
@@ -173,10 +207,100 @@ impl SyncStore {
         self.pending_txs.random(store)
     }
 
+    /// Records a failed attempt to sync the file and moves it back to the pending queue,
+    /// or to the dead letter queue if it has failed `max_attempts` times (0 for unlimited).
+    ///
+    /// Returns true if the file is moved to the dead letter queue.
+    pub async fn record_failure(
+        &self,
+        tx_seq: u64,
+        error: &str,
+        max_attempts: usize,
+    ) -> Result<bool> {
+        let async_store = self.store.write().await;
+        let store = async_store.get_store();
+
+        let attempts = store
+            .get_config_decoded::<_, usize>(&self.key_attempts(tx_seq), DATA_DB_KEY)?
+            .unwrap_or(0)
+            + 1;
+
+        let mut tx = ConfigTx::default();
+        tx.set_config(&self.key_attempts(tx_seq), &attempts);
+
+        let dead = max_attempts > 0 && attempts >= max_attempts;
+        if dead {
+            tx.set_config(&self.key_last_error(tx_seq), &error.as_bytes().to_vec());
+            self.pending_txs.remove(store, Some(&mut tx), tx_seq)?;
+            self.ready_txs.remove(store, Some(&mut tx), tx_seq).await?;
+            self.dead_txs.add(store, Some(&mut tx), tx_seq)?;
+        } else {
+            self.pending_txs.add(store, Some(&mut tx), tx_seq)?;
+            self.ready_txs.remove(store, Some(&mut tx), tx_seq).await?;
+        }
+
+        store.exec_configs(tx, DATA_DB_KEY)?;
+
+        Ok(dead)
+    }
+
+    pub async fn dead_count(&self) -> Result<usize> {
+        let async_store = self.store.read().await;
+        self.dead_txs.count(async_store.get_store())
+    }
+
+    pub async fn dead_letters(&self) -> Result<Vec<DeadLetterFile>> {
+        let async_store = self.store.read().await;
+        let store = async_store.get_store();
+
+        let mut files = vec![];
+        for tx_seq in self.dead_txs.list(store)? {
+            let attempts = store
+                .get_config_decoded(&self.key_attempts(tx_seq), DATA_DB_KEY)?
+                .unwrap_or(0);
+            let last_error: Vec<u8> = store
+                .get_config_decoded(&self.key_last_error(tx_seq), DATA_DB_KEY)?
+                .unwrap_or_default();
+            files.push(DeadLetterFile {
+                tx_seq,
+                attempts,
+                last_error: String::from_utf8_lossy(&last_error).into_owned(),
+            });
+        }
+        files.sort_by_key(|file| file.tx_seq);
+
+        Ok(files)
+    }
+
+    /// Moves the file from the dead letter queue to the ready queue with a new retry budget.
+    ///
+    /// Returns false if the file is not in the dead letter queue.
+    pub async fn retry_dead_letter(&self, tx_seq: u64) -> Result<bool> {
+        let async_store = self.store.write().await;
+        let store = async_store.get_store();
+
+        let mut tx = ConfigTx::default();
+
+        if !self.dead_txs.remove(store, Some(&mut tx), tx_seq)? {
+            return Ok(false);
+        }
+
+        tx.remove_config(&self.key_attempts(tx_seq));
+        tx.remove_config(&self.key_last_error(tx_seq));
+        self.ready_txs.add(store, Some(&mut tx), tx_seq).await?;
+
+        store.exec_configs(tx, DATA_DB_KEY)?;
+
+        Ok(true)
+    }
+
     pub async fn remove(&self, tx_seq: u64) -> Result<Option<Queue>> {
         let async_store = self.store.write().await;
         let store = async_store.get_store();
 
+        // reset the retry budget
+        store.remove_config_by_key(&self.key_attempts(tx_seq), DATA_DB_KEY)?;
+
         // removed in ready queue
         if self.ready_txs.remove(store, None, tx_seq).await? {
             return Ok(Some(Queue::Ready));
@@ -195,7 +319,7 @@ impl SyncStore {
 mod tests {
     use crate::test_util::tests::TestStoreRuntime;
 
-    use super::{InsertResult::*, Queue::*, SyncStore};
+    use super::{DeadLetterFile, InsertResult::*, Queue::*, SyncStore};
 
     #[tokio::test]
     async fn test_tx_seq_range() {
@@ -271,6 +395,48 @@ mod tests {
         assert_eq!(store.random().await.unwrap(), Some(2));
     }
 
+    #[tokio::test]
+    async fn test_dead_letter() {
+        let runtime = TestStoreRuntime::default();
+        let store = SyncStore::new(runtime.store.clone(), 0);
+
+        // unlimited attempts by default
+        assert_eq!(store.insert(1, Ready).await.unwrap(), NewAdded);
+        for _ in 0..10 {
+            assert!(!store.record_failure(1, "Failed", 0).await.unwrap());
+        }
+        assert_eq!(store.contains(1).await.unwrap(), Some(Pending));
+
+        // moved to dead letter queue once the budget is exhausted
+        assert_eq!(store.insert(2, Ready).await.unwrap(), NewAdded);
+        assert!(!store.record_failure(2, "Failed", 3).await.unwrap());
+        assert_eq!(store.contains(2).await.unwrap(), Some(Pending));
+        assert!(!store.record_failure(2, "Failed", 3).await.unwrap());
+        assert!(store.record_failure(2, "Timeout", 3).await.unwrap());
+        assert_eq!(store.contains(2).await.unwrap(), None);
+        assert_eq!(store.random().await.unwrap(), Some(1));
+
+        // not synced automatically any more
+        assert_eq!(store.insert(2, Ready).await.unwrap(), Dead);
+        assert_eq!(store.insert(2, Pending).await.unwrap(), Dead);
+        assert_eq!(store.contains(2).await.unwrap(), None);
+        assert_eq!(
+            store.dead_letters().await.unwrap(),
+            vec![DeadLetterFile {
+                tx_seq: 2,
+                attempts: 3,
+                last_error: "Timeout".into(),
+            }]
+        );
+
+        // retry manually with a new budget
+        assert!(!store.retry_dead_letter(1).await.unwrap());
+        assert!(store.retry_dead_letter(2).await.unwrap());
+        assert_eq!(store.contains(2).await.unwrap(), Some(Ready));
+        assert!(store.dead_letters().await.unwrap().is_empty());
+        assert!(!store.record_failure(2, "Failed", 3).await.unwrap());
+    }
+
     #[tokio::test]
     async fn test_remove() {
         let runtime = TestStoreRuntime::default();
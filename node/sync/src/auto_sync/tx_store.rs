@@ -81,6 +81,13 @@ impl TxStore {
         Ok(true)
     }
 
+    pub fn list(&self, store: &dyn Store) -> Result<Vec<u64>> {
+        let count = self.count(store)?;
+        (0..count)
+            .map(|index| Ok(self.at(store, index)?.expect("data corruption")))
+            .collect()
+    }
+
     pub fn random(&self, store: &dyn Store) -> Result<Option<u64>> {
         let count = self.count(store)?;
         if count == 0 {
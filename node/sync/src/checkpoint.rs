@@ -0,0 +1,444 @@
+//! Persists the download progress of syncing files periodically, so that a file sync is
+//! resumed from the last checkpoint after restart, and only the chunks after the checkpoint
+//! are scanned and downloaded again.
+//!
+//! Checkpoints are written in the background and only the files whose progress changed since
+//! the last checkpoint are written. Checkpoints of synced files are removed once the files are
+//! finalized or pruned.
+//!
+//! At startup, the files in sync are restored from their checkpoints by concurrent workers,
+//! which scan the data stored after the checkpoints to find where each file sync resumes, so
+//! that a node with many files in sync is ready sooner.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use shared_types::{bytes_to_chunks, TxID};
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use storage::log_store::config::{ConfigTx, ConfigurableExt};
+use storage::log_store::log_manager::DATA_DB_KEY;
+use storage_async::Store;
+use task_executor::TaskExecutor;
+
+use crate::striped::for_each_striped;
+use crate::SyncService;
+
+const KEY_TX_SEQS: &str = "sync.checkpoint.tx_seqs";
+
+/// Number of the restored files between two progress logs.
+const PROGRESS_LOG_INTERVAL: usize = 100;
+
+/// Encoded size of `TxID` and the next chunk index.
+const CHECKPOINT_SIZE: u64 = 48;
+
+fn key_tx_id(tx_seq: u64) -> String {
+    format!("sync.checkpoint.{}.tx_id", tx_seq)
+}
+
+fn key_next_chunk(tx_seq: u64) -> String {
+    format!("sync.checkpoint.{}.next_chunk", tx_seq)
+}
+
+/// Download progress of a file, where all chunks before `next_chunk` are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncCheckpoint {
+    pub tx_id: TxID,
+    pub next_chunk: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointInfo {
+    /// Unix timestamp in seconds of the last checkpoint, `None` if not taken yet.
+    pub last_checkpoint_timestamp: Option<u64>,
+    /// Number of files in the checkpoints.
+    pub num_files: usize,
+    /// Total size in bytes of the checkpoints.
+    pub size_bytes: u64,
+    /// Size in bytes written by the last checkpoint.
+    pub written_bytes: u64,
+}
+
+#[derive(Default)]
+struct CheckpointState {
+    /// Checkpoints persisted in db, of which the files in sync are advanced to the data stored
+    /// after the checkpoints at startup.
+    saved: HashMap<u64, SyncCheckpoint>,
+    info: CheckpointInfo,
+}
+
+#[derive(Clone)]
+pub struct SyncCheckpointer {
+    store: Store,
+    executor: TaskExecutor,
+    state: Arc<Mutex<CheckpointState>>,
+    /// Whether a checkpoint is being written.
+    writing: Arc<AtomicBool>,
+}
+
+impl SyncCheckpointer {
+    /// Loads the checkpoints persisted in db, and restores the files in sync from them with
+    /// `restore_concurrency` workers.
+    pub async fn new(
+        store: Store,
+        executor: TaskExecutor,
+        restore_concurrency: usize,
+    ) -> Result<Self> {
+        let started_at = Instant::now();
+        let checkpoints = Self::load(&store).await?;
+        let num_files = checkpoints.len();
+        let saved: HashMap<u64, SyncCheckpoint> =
+            Self::restore(&store, checkpoints, restore_concurrency)
+                .await?
+                .into_iter()
+                .map(|checkpoint| (checkpoint.tx_id.seq, checkpoint))
+                .collect();
+
+        if num_files > 0 {
+            info!(
+                %num_files,
+                elapsed = ?started_at.elapsed(),
+                "Restored files in sync from checkpoints"
+            );
+        }
+
+        let info = CheckpointInfo {
+            num_files: saved.len(),
+            size_bytes: saved.len() as u64 * CHECKPOINT_SIZE,
+            ..Default::default()
+        };
+
+        Ok(Self {
+            store,
+            executor,
+            state: Arc::new(Mutex::new(CheckpointState { saved, info })),
+            writing: Default::default(),
+        })
+    }
+
+    async fn load(store: &Store) -> Result<Vec<SyncCheckpoint>> {
+        let tx_seqs: Vec<u64> = store
+            .get_config_decoded(&KEY_TX_SEQS, DATA_DB_KEY)
+            .await?
+            .unwrap_or_default();
+
+        let mut checkpoints = Vec::with_capacity(tx_seqs.len());
+        for tx_seq in tx_seqs {
+            let tx_id = store
+                .get_config_decoded(&key_tx_id(tx_seq), DATA_DB_KEY)
+                .await?;
+            let next_chunk = store
+                .get_config_decoded(&key_next_chunk(tx_seq), DATA_DB_KEY)
+                .await?;
+            // ignore the partially written checkpoint
+            if let (Some(tx_id), Some(next_chunk)) = (tx_id, next_chunk) {
+                checkpoints.push(SyncCheckpoint { tx_id, next_chunk });
+            }
+        }
+        Ok(checkpoints)
+    }
+
+    /// Advances the checkpoints of the files in sync to the first chunk missing after them,
+    /// with `concurrency` workers.
+    async fn restore(
+        store: &Store,
+        checkpoints: Vec<SyncCheckpoint>,
+        concurrency: usize,
+    ) -> Result<Vec<SyncCheckpoint>> {
+        let total = checkpoints.len();
+        let restored = Arc::new(AtomicUsize::new(0));
+        let store = store.clone();
+
+        for_each_striped(checkpoints, concurrency, move |checkpoint| {
+            let store = store.clone();
+            let restored = restored.clone();
+            async move {
+                let checkpoint = Self::restore_file(&store, checkpoint).await?;
+                let restored = restored.fetch_add(1, Ordering::Relaxed) + 1;
+                if restored % PROGRESS_LOG_INTERVAL == 0 {
+                    info!(%restored, %total, "Restoring files in sync from checkpoints");
+                }
+                Ok(checkpoint)
+            }
+        })
+        .await
+    }
+
+    /// Returns the checkpoint as is if the file is not in sync anymore, e.g. reverted or
+    /// finalized, which is removed by the next checkpoint.
+    async fn restore_file(store: &Store, checkpoint: SyncCheckpoint) -> Result<SyncCheckpoint> {
+        let tx = match store.get_tx_by_seq_number(checkpoint.tx_id.seq).await? {
+            Some(tx) if tx.id() == checkpoint.tx_id => tx,
+            _ => return Ok(checkpoint),
+        };
+        if store.get_store().get_tx_status(tx.seq)?.is_some() {
+            return Ok(checkpoint);
+        }
+
+        let next_chunk =
+            match SyncService::tx_sync_start_index(store, &tx, checkpoint.next_chunk).await? {
+                Some(start) => start,
+                // all the data is stored
+                None => bytes_to_chunks(tx.size as usize) as u64,
+            };
+        Ok(SyncCheckpoint {
+            next_chunk,
+            ..checkpoint
+        })
+    }
+
+    pub fn info(&self) -> CheckpointInfo {
+        self.state.lock().unwrap().info
+    }
+
+    /// Returns the chunk index to resume the sync of specified file from. The checkpoint is
+    /// ignored if it is not taken for the same transaction, e.g. reverted.
+    pub fn resume_from(&self, tx_id: &TxID) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        let checkpoint = state.saved.get(&tx_id.seq)?;
+        (checkpoint.tx_id == *tx_id).then_some(checkpoint.next_chunk)
+    }
+
+    /// Writes the checkpoints of files in sync in the background, and returns `false` if the
+    /// last checkpoint is still being written.
+    pub fn checkpoint(&self, checkpoints: Vec<SyncCheckpoint>) -> bool {
+        if self.writing.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+
+        let store = self.store.clone();
+        let state = self.state.clone();
+        let writing = self.writing.clone();
+
+        self.executor.spawn_blocking(
+            move || {
+                if let Err(err) = Self::write(&store, &state, checkpoints) {
+                    warn!(%err, "Failed to write file sync checkpoints");
+                }
+                writing.store(false, Ordering::SeqCst);
+            },
+            "sync_checkpoint",
+        );
+
+        true
+    }
+
+    fn write(
+        store: &Store,
+        state: &Mutex<CheckpointState>,
+        checkpoints: Vec<SyncCheckpoint>,
+    ) -> Result<()> {
+        let store = store.get_store();
+        let mut saved = state.lock().unwrap().saved.clone();
+
+        let changed: Vec<SyncCheckpoint> = checkpoints
+            .iter()
+            .filter(|c| saved.get(&c.tx_id.seq) != Some(c))
+            .copied()
+            .collect();
+
+        // remove the checkpoints of files that are not in sync and not needed anymore
+        let mut removed = vec![];
+        for (tx_seq, checkpoint) in saved.iter() {
+            if checkpoints.iter().any(|c| c.tx_id.seq == *tx_seq) {
+                continue;
+            }
+
+            let stale = match store.get_tx_by_seq_number(*tx_seq)? {
+                Some(tx) => tx.id() != checkpoint.tx_id || store.get_tx_status(*tx_seq)?.is_some(),
+                None => true,
+            };
+            if stale {
+                removed.push(*tx_seq);
+            }
+        }
+
+        if changed.is_empty() && removed.is_empty() {
+            let mut state = state.lock().unwrap();
+            state.info.last_checkpoint_timestamp = Some(now_secs());
+            state.info.written_bytes = 0;
+            return Ok(());
+        }
+
+        let mut tx = ConfigTx::default();
+        for checkpoint in changed.iter() {
+            let tx_seq = checkpoint.tx_id.seq;
+            tx.set_config(&key_tx_id(tx_seq), &checkpoint.tx_id);
+            tx.set_config(&key_next_chunk(tx_seq), &checkpoint.next_chunk);
+            saved.insert(tx_seq, *checkpoint);
+        }
+        for tx_seq in removed.iter() {
+            tx.remove_config(&key_tx_id(*tx_seq));
+            tx.remove_config(&key_next_chunk(*tx_seq));
+            saved.remove(tx_seq);
+        }
+        let mut tx_seqs: Vec<u64> = saved.keys().copied().collect();
+        tx_seqs.sort_unstable();
+        tx.set_config(&KEY_TX_SEQS, &tx_seqs);
+
+        store.exec_configs(tx, DATA_DB_KEY)?;
+
+        debug!(
+            changed = changed.len(),
+            removed = removed.len(),
+            "File sync checkpoints written"
+        );
+
+        let mut state = state.lock().unwrap();
+        state.info = CheckpointInfo {
+            last_checkpoint_timestamp: Some(now_secs()),
+            num_files: saved.len(),
+            size_bytes: saved.len() as u64 * CHECKPOINT_SIZE,
+            written_bytes: changed.len() as u64 * CHECKPOINT_SIZE + tx_seqs.len() as u64 * 8,
+        };
+        state.saved = saved;
+
+        Ok(())
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SyncCheckpoint, SyncCheckpointer};
+    use crate::test_util::create_2_store;
+    use shared_types::{ChunkArray, TxID, CHUNK_SIZE};
+    use std::time::Duration;
+    use storage::log_store::{log_manager::PORA_CHUNK_SIZE, LogStoreChunkWrite};
+    use storage::H256;
+    use storage_async::Store;
+    use task_executor::test_utils::TestRuntime;
+
+    async fn wait_for_checkpoint(checkpointer: &SyncCheckpointer) {
+        for _ in 0..100 {
+            if !checkpointer
+                .writing
+                .load(std::sync::atomic::Ordering::SeqCst)
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("checkpoint not written");
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint() {
+        let runtime = TestRuntime::default();
+        let (store, _, txs, _) = create_2_store(vec![1024, 2048]);
+        let store = Store::new(store, runtime.task_executor.clone());
+
+        let checkpointer = SyncCheckpointer::new(store.clone(), runtime.task_executor.clone(), 1)
+            .await
+            .unwrap();
+        assert_eq!(checkpointer.info().last_checkpoint_timestamp, None);
+        assert_eq!(checkpointer.resume_from(&txs[0].id()), None);
+
+        let checkpoints = vec![
+            SyncCheckpoint {
+                tx_id: txs[0].id(),
+                next_chunk: 256,
+            },
+            SyncCheckpoint {
+                tx_id: txs[1].id(),
+                next_chunk: 1024,
+            },
+        ];
+        assert!(checkpointer.checkpoint(checkpoints.clone()));
+        wait_for_checkpoint(&checkpointer).await;
+
+        let info = checkpointer.info();
+        assert!(info.last_checkpoint_timestamp.is_some());
+        assert_eq!(info.num_files, 2);
+        assert_eq!(checkpointer.resume_from(&txs[0].id()), Some(256));
+
+        // only the changed checkpoint is written
+        let mut checkpoints = checkpoints;
+        checkpoints[1].next_chunk = 1536;
+        assert!(checkpointer.checkpoint(checkpoints));
+        wait_for_checkpoint(&checkpointer).await;
+        assert!(checkpointer.info().written_bytes < info.written_bytes);
+
+        // load checkpoints after restart
+        let checkpointer = SyncCheckpointer::new(store, runtime.task_executor.clone(), 4)
+            .await
+            .unwrap();
+        assert_eq!(checkpointer.info().num_files, 2);
+        assert_eq!(checkpointer.resume_from(&txs[0].id()), Some(256));
+        assert_eq!(checkpointer.resume_from(&txs[1].id()), Some(1536));
+
+        // checkpoint of another transaction is ignored, e.g. reverted
+        let reverted = TxID {
+            seq: txs[1].seq,
+            hash: H256::repeat_byte(1),
+        };
+        assert_eq!(checkpointer.resume_from(&reverted), None);
+    }
+
+    #[tokio::test]
+    async fn test_restore_files_concurrently() {
+        let runtime = TestRuntime::default();
+        let (store, _, txs, data) = create_2_store(vec![2048; 12]);
+
+        // files of which none, the first segment or all the data is stored
+        for tx in txs.iter() {
+            for segment in 0..(tx.seq % 3) as usize {
+                let start_index = segment * PORA_CHUNK_SIZE;
+                let chunks = ChunkArray {
+                    data: data[tx.seq as usize]
+                        [start_index * CHUNK_SIZE..(start_index + PORA_CHUNK_SIZE) * CHUNK_SIZE]
+                        .to_vec(),
+                    start_index: start_index as u64,
+                };
+                store.put_chunks(tx.seq, chunks).unwrap();
+            }
+        }
+        let store = Store::new(store, runtime.task_executor.clone());
+
+        let checkpointer = SyncCheckpointer::new(store.clone(), runtime.task_executor.clone(), 1)
+            .await
+            .unwrap();
+        let checkpoints: Vec<SyncCheckpoint> = txs
+            .iter()
+            .map(|tx| SyncCheckpoint {
+                tx_id: tx.id(),
+                next_chunk: 0,
+            })
+            .collect();
+        assert!(checkpointer.checkpoint(checkpoints));
+        wait_for_checkpoint(&checkpointer).await;
+
+        let restore = |concurrency| {
+            let store = store.clone();
+            let executor = runtime.task_executor.clone();
+            async move {
+                let checkpointer = SyncCheckpointer::new(store, executor, concurrency)
+                    .await
+                    .unwrap();
+                let saved = checkpointer.state.lock().unwrap().saved.clone();
+                saved
+            }
+        };
+        let serial = restore(1).await;
+        assert_eq!(serial.len(), txs.len());
+        for tx in txs.iter() {
+            assert_eq!(
+                serial[&tx.seq].next_chunk,
+                (tx.seq % 3) * PORA_CHUNK_SIZE as u64
+            );
+        }
+        for concurrency in [2, 4, 64] {
+            assert_eq!(restore(concurrency).await, serial);
+        }
+    }
+}
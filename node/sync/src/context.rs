@@ -1,12 +1,31 @@
 use network::{NetworkMessage, NetworkSender, PeerAction, PeerId, PubsubMessage, ReportSource};
+use std::collections::HashSet;
 
 pub struct SyncNetworkContext {
     network_send: NetworkSender,
+    /// Trusted bootstrap peers, of which file segments are accepted without proof verification.
+    trusted_peers: HashSet<PeerId>,
 }
 
 impl SyncNetworkContext {
     pub fn new(network_send: NetworkSender) -> Self {
-        Self { network_send }
+        Self {
+            network_send,
+            trusted_peers: Default::default(),
+        }
+    }
+
+    pub fn with_trusted_peers(mut self, trusted_peers: HashSet<PeerId>) -> Self {
+        self.trusted_peers = trusted_peers;
+        self
+    }
+
+    pub fn has_trusted_peers(&self) -> bool {
+        !self.trusted_peers.is_empty()
+    }
+
+    pub fn is_trusted(&self, peer_id: &PeerId) -> bool {
+        self.trusted_peers.contains(peer_id)
     }
 
     /// Sends an arbitrary network message.
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use network::PeerId;
+use storage::config::ShardConfig;
+
+use crate::controllers::metrics;
+
+/// Caches the files that peers answered to have, so that a file sync reuses the recent
+/// answers instead of asking the neighbors again on every attempt.
+///
+/// An entry expires after the TTL, and all the entries of a peer are dropped once the peer
+/// announces a new shard config or is disconnected. The expired entries are pruned on insert,
+/// and the oldest entries are evicted beyond the maximum number of entries.
+#[derive(Clone)]
+pub struct AvailabilityCache {
+    /// `Duration::ZERO` if disabled.
+    ttl: Duration,
+    /// 0 if unbounded.
+    max_entries: usize,
+    entries: Arc<Mutex<HashMap<PeerId, HashMap<u64, (ShardConfig, Instant)>>>>,
+    hits: Arc<AtomicUsize>,
+    misses: Arc<AtomicUsize>,
+}
+
+impl Default for AvailabilityCache {
+    fn default() -> Self {
+        Self::new(Duration::ZERO, 0)
+    }
+}
+
+impl AvailabilityCache {
+    /// Creates a cache of at most `max_entries` entries that expire after `ttl`, or a disabled
+    /// one if `ttl` is 0. The number of entries is unbounded if `max_entries` is 0.
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Default::default(),
+            hits: Default::default(),
+            misses: Default::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Records that `peer_id` has the file of `tx_seq` in the shard of `shard_config`.
+    pub fn insert(&self, peer_id: PeerId, tx_seq: u64, shard_config: ShardConfig) {
+        self.insert_at(peer_id, tx_seq, shard_config, Instant::now());
+    }
+
+    /// Drops all the entries of `peer_id`.
+    pub fn invalidate_peer(&self, peer_id: &PeerId) {
+        self.entries.lock().unwrap().remove(peer_id);
+    }
+
+    /// Returns the peers that have the file of `tx_seq` according to the unexpired entries.
+    pub fn get(&self, tx_seq: u64) -> Vec<(PeerId, ShardConfig)> {
+        self.get_at(tx_seq, Instant::now())
+    }
+
+    /// Records whether a query to find peers is suppressed by the cached entries.
+    pub fn record_lookup(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if hit {
+            metrics::SERIAL_SYNC_AVAILABILITY_CACHE_HITS.inc(1);
+        } else {
+            metrics::SERIAL_SYNC_AVAILABILITY_CACHE_MISSES.inc(1);
+        }
+        metrics::SERIAL_SYNC_AVAILABILITY_CACHE_HIT_RATE.update(self.hit_rate_percent());
+    }
+
+    /// Returns the percentage of the lookups that suppressed a query, or 0 if no lookup.
+    pub fn hit_rate_percent(&self) -> usize {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let total = hits + self.misses.load(Ordering::Relaxed);
+        if total == 0 {
+            0
+        } else {
+            hits * 100 / total
+        }
+    }
+
+    fn insert_at(&self, peer_id: PeerId, tx_seq: u64, shard_config: ShardConfig, now: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, files| {
+            files.retain(|_, (_, since)| now.duration_since(*since) < self.ttl);
+            !files.is_empty()
+        });
+        entries
+            .entry(peer_id)
+            .or_default()
+            .insert(tx_seq, (shard_config, now));
+
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut all: Vec<(Instant, PeerId, u64)> = entries
+            .iter()
+            .flat_map(|(peer_id, files)| {
+                files
+                    .iter()
+                    .map(move |(tx_seq, (_, since))| (*since, *peer_id, *tx_seq))
+            })
+            .collect();
+        if all.len() <= self.max_entries {
+            return;
+        }
+
+        // evict the oldest entries
+        let num_evicted = all.len() - self.max_entries;
+        all.select_nth_unstable_by_key(num_evicted - 1, |(since, _, _)| *since);
+        for (_, peer_id, tx_seq) in &all[..num_evicted] {
+            if let Some(files) = entries.get_mut(peer_id) {
+                files.remove(tx_seq);
+                if files.is_empty() {
+                    entries.remove(peer_id);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of cached entries, including the expired ones not pruned yet.
+    pub fn num_entries(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(|files| files.len())
+            .sum()
+    }
+
+    fn get_at(&self, tx_seq: u64, now: Instant) -> Vec<(PeerId, ShardConfig)> {
+        if !self.is_enabled() {
+            return vec![];
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let mut peers = vec![];
+        entries.retain(|peer_id, files| {
+            files.retain(|_, (_, since)| now.duration_since(*since) < self.ttl);
+            if let Some((shard_config, _)) = files.get(&tx_seq) {
+                peers.push((*peer_id, *shard_config));
+            }
+            !files.is_empty()
+        });
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AvailabilityCache;
+    use network::PeerId;
+    use std::time::Duration;
+    use storage::config::ShardConfig;
+    use tokio::time::Instant;
+
+    #[test]
+    fn test_availability_cache() {
+        let cache = AvailabilityCache::new(Duration::from_secs(10), 0);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let (peer1, peer2) = (PeerId::random(), PeerId::random());
+        let shard_config = ShardConfig {
+            num_shard: 2,
+            shard_id: 1,
+        };
+
+        cache.insert_at(peer1, 0, Default::default(), at(0));
+        cache.insert_at(peer2, 0, shard_config, at(5));
+        cache.insert_at(peer2, 1, shard_config, at(5));
+        assert_eq!(cache.get_at(0, at(9)).len(), 2);
+        assert_eq!(cache.get_at(1, at(9)), vec![(peer2, shard_config)]);
+        assert!(cache.get_at(2, at(9)).is_empty());
+
+        // expired entries
+        assert_eq!(cache.get_at(0, at(10)), vec![(peer2, shard_config)]);
+        assert!(cache.get_at(0, at(15)).is_empty());
+
+        // invalidated by peer
+        cache.insert_at(peer1, 0, Default::default(), at(20));
+        cache.insert_at(peer2, 0, shard_config, at(20));
+        cache.invalidate_peer(&peer2);
+        assert_eq!(cache.get_at(0, at(21)), vec![(peer1, Default::default())]);
+
+        // hit rate
+        cache.record_lookup(true);
+        cache.record_lookup(true);
+        cache.record_lookup(true);
+        cache.record_lookup(false);
+        assert_eq!(cache.hit_rate_percent(), 75);
+
+        // disabled
+        let cache = AvailabilityCache::default();
+        cache.insert(peer1, 0, Default::default());
+        assert!(cache.get(0).is_empty());
+    }
+
+    #[test]
+    fn test_availability_cache_bounded() {
+        let cache = AvailabilityCache::new(Duration::from_secs(10), 3);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let (peer1, peer2) = (PeerId::random(), PeerId::random());
+
+        // expired entries are pruned on insert without any lookup
+        for tx_seq in 0..3 {
+            cache.insert_at(peer1, tx_seq, Default::default(), at(0));
+        }
+        assert_eq!(cache.num_entries(), 3);
+        cache.insert_at(peer2, 10, Default::default(), at(10));
+        assert_eq!(cache.num_entries(), 1);
+
+        // the oldest entries are evicted beyond the cap
+        cache.insert_at(peer1, 11, Default::default(), at(11));
+        cache.insert_at(peer2, 12, Default::default(), at(12));
+        cache.insert_at(peer1, 13, Default::default(), at(13));
+        assert_eq!(cache.num_entries(), 3);
+        assert!(cache.get_at(10, at(13)).is_empty());
+        assert_eq!(cache.get_at(11, at(13)).len(), 1);
+        assert_eq!(cache.get_at(13, at(13)).len(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Window to measure the download rate of a file.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Paces the segment requests of a file to keep the download rate of the file under a cap,
+/// however many peers serve the file.
+///
+/// The download time of a segment at the cap is reserved before the segment is requested, and
+/// the request is sent once the reserved time ends, so the requests of a capped file slow
+/// down instead of stopping, and the bytes requested never exceed the cap since the first
+/// request.
+#[derive(Debug, Default)]
+pub struct FileBandwidth {
+    /// Maximum bytes per second to download, or 0 if unlimited.
+    max_bytes_per_sec: u64,
+    /// End of the time reserved for the requested bytes.
+    reserved_until: Option<Instant>,
+    /// Time to send the request of which the download time is reserved but not sent yet.
+    ready_at: Option<Instant>,
+    /// Bytes received in `RATE_WINDOW`.
+    received: VecDeque<(Instant, u64)>,
+    /// Time that the first bytes are received.
+    first_received_at: Option<Instant>,
+}
+
+impl FileBandwidth {
+    pub fn new(max_bytes_per_sec: u64) -> Self {
+        Self {
+            max_bytes_per_sec,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes_per_sec > 0
+    }
+
+    /// Returns the time to wait until to request `bytes`, or `None` if it could be requested at
+    /// once. The download time of `bytes` is reserved on the first call if not reserved yet.
+    pub fn poll_request(&mut self, bytes: u64, now: Instant) -> Option<Instant> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let ready_at = match self.ready_at {
+            Some(ready_at) => ready_at,
+            None => {
+                let from = self.reserved_until.map_or(now, |until| until.max(now));
+                let ready_at =
+                    from + Duration::from_secs_f64(bytes as f64 / self.max_bytes_per_sec as f64);
+                self.reserved_until = Some(ready_at);
+                ready_at
+            }
+        };
+
+        if ready_at <= now {
+            self.ready_at = None;
+            None
+        } else {
+            self.ready_at = Some(ready_at);
+            Some(ready_at)
+        }
+    }
+
+    pub fn on_received(&mut self, bytes: u64, now: Instant) {
+        self.first_received_at.get_or_insert(now);
+        self.received.push_back((now, bytes));
+        while let Some((at, _)) = self.received.front() {
+            if now.duration_since(*at) < RATE_WINDOW {
+                break;
+            }
+            self.received.pop_front();
+        }
+    }
+
+    /// Returns the average download rate in bytes per second in the recent window, or since the
+    /// first bytes are received if shorter, which is at least one second so that the rate does
+    /// not spike on the first segment.
+    pub fn rate(&self, now: Instant) -> u64 {
+        let first_received_at = match self.first_received_at {
+            Some(at) => at,
+            None => return 0,
+        };
+        let bytes: u64 = self
+            .received
+            .iter()
+            .filter(|(at, _)| now.duration_since(*at) < RATE_WINDOW)
+            .map(|(_, bytes)| bytes)
+            .sum();
+        let elapsed = now
+            .duration_since(first_received_at)
+            .clamp(Duration::from_secs(1), RATE_WINDOW);
+        bytes * 1000 / elapsed.as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileBandwidth;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn test_file_bandwidth() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        // unlimited
+        let mut bandwidth = FileBandwidth::new(0);
+        assert_eq!(bandwidth.poll_request(1000, start), None);
+
+        // the download time of 500 bytes at 1000 B/s is reserved
+        let mut bandwidth = FileBandwidth::new(1000);
+        assert_eq!(bandwidth.poll_request(500, at(0)), Some(at(500)));
+        assert_eq!(bandwidth.poll_request(500, at(200)), Some(at(500)));
+        assert_eq!(bandwidth.poll_request(500, at(500)), None);
+
+        // reserved after the last reservation
+        assert_eq!(bandwidth.poll_request(1000, at(600)), Some(at(1600)));
+        assert_eq!(bandwidth.poll_request(1000, at(1600)), None);
+
+        // not reserved for the idle time
+        assert_eq!(bandwidth.poll_request(100, at(5000)), Some(at(5100)));
+
+        // the rate is averaged since the first bytes are received in the first window
+        assert_eq!(bandwidth.rate(at(0)), 0);
+        bandwidth.on_received(5000, at(0));
+        assert_eq!(bandwidth.rate(at(0)), 5000);
+        assert_eq!(bandwidth.rate(at(500)), 5000);
+        assert_eq!(bandwidth.rate(at(2000)), 2500);
+        bandwidth.on_received(4000, at(9000));
+        assert_eq!(bandwidth.rate(at(9000)), 1000);
+        assert_eq!(bandwidth.rate(at(10000)), 400);
+        bandwidth.on_received(0, at(20000));
+        assert_eq!(bandwidth.received.len(), 1);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// A small set of peers whose union covers the missing segments of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCover<P> {
+    /// Peers to request segments from, in the order of selection.
+    pub peers: Vec<P>,
+    /// Missing segments that no known peer holds.
+    pub uncovered: BTreeSet<u64>,
+}
+
+/// Selects peers to cover the `missing` segments with the greedy set cover heuristic, which
+/// picks the peer holding the most uncovered segments at a time. The result is at most
+/// `ln(n) + 1` times the minimal set for `n` missing segments. Ties are broken by the order of
+/// `availability`.
+pub fn cover_segments<P: Clone>(
+    missing: &BTreeSet<u64>,
+    availability: &[(P, BTreeSet<u64>)],
+) -> PeerCover<P> {
+    let mut uncovered = missing.clone();
+    let mut selected = vec![false; availability.len()];
+    let mut peers = vec![];
+
+    while !uncovered.is_empty() {
+        let best = availability
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !selected[*index])
+            .map(|(index, (_, segments))| (index, segments.intersection(&uncovered).count()))
+            .filter(|(_, count)| *count > 0)
+            // `max_by_key` returns the last max element
+            .rev()
+            .max_by_key(|(_, count)| *count);
+
+        let index = match best {
+            Some((index, _)) => index,
+            None => break,
+        };
+
+        selected[index] = true;
+        let (peer, segments) = &availability[index];
+        uncovered.retain(|segment| !segments.contains(segment));
+        peers.push(peer.clone());
+    }
+
+    PeerCover { peers, uncovered }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentCoverage {
+    /// Number of segments not downloaded yet.
+    pub num_missing: usize,
+    /// Peers that cover all the missing segments except the uncovered ones.
+    pub peers: Vec<String>,
+    /// Segment indices in the file that no known peer holds.
+    pub uncovered_segments: Vec<u64>,
+    /// Chunk ranges `[start, end)` of the file that are downloaded and stored in db.
+    pub stored_chunk_ranges: Vec<(u64, u64)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cover_segments;
+    use std::collections::BTreeSet;
+
+    fn segments(range: impl IntoIterator<Item = u64>) -> BTreeSet<u64> {
+        range.into_iter().collect()
+    }
+
+    #[test]
+    fn test_cover_segments() {
+        let availability = vec![
+            ("a", segments(0..4)),
+            ("b", segments(2..6)),
+            ("c", segments(4..8)),
+            ("d", segments([1, 3, 5, 7])),
+            ("e", segments(0..8)),
+        ];
+
+        // the peer holding all segments is enough
+        let cover = cover_segments(&segments(0..8), &availability);
+        assert_eq!(cover.peers, vec!["e"]);
+        assert!(cover.uncovered.is_empty());
+
+        // overlapping peers
+        let cover = cover_segments(&segments(0..8), &availability[..4]);
+        assert_eq!(cover.peers, vec!["a", "c"]);
+        assert!(cover.uncovered.is_empty());
+
+        // ties are broken by order
+        let cover = cover_segments(&segments([3, 5]), &availability[..4]);
+        assert_eq!(cover.peers, vec!["b"]);
+
+        // segments held by no peer are reported
+        let cover = cover_segments(&segments(6..10), &availability[..4]);
+        assert_eq!(cover.peers, vec!["c"]);
+        assert_eq!(cover.uncovered, segments([8, 9]));
+
+        let cover = cover_segments(&segments(0..2), &[]);
+        assert!(cover.peers.is_empty());
+        assert_eq!(cover.uncovered, segments(0..2));
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use network::PeerId;
+
+/// Window to count the eager dials in.
+const DIAL_WINDOW: Duration = Duration::from_secs(60);
+
+/// Bounds the dials to the peers that advertised a file in sync but are not connected yet, so
+/// that bursts of announcements do not flood the network with dials.
+///
+/// At most `max_dials_per_min` peers are dialed within a minute, and a peer is not dialed
+/// again within a minute however many files it advertised.
+#[derive(Debug)]
+pub struct EagerDialLimiter {
+    /// 0 if disabled.
+    max_dials_per_min: usize,
+    /// Peers dialed in the current window.
+    dialed: HashMap<PeerId, Instant>,
+    window_start: Instant,
+    dials_in_window: usize,
+}
+
+impl EagerDialLimiter {
+    pub fn new(max_dials_per_min: usize) -> Self {
+        Self {
+            max_dials_per_min,
+            dialed: Default::default(),
+            window_start: Instant::now(),
+            dials_in_window: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_dials_per_min > 0
+    }
+
+    /// Returns whether `peer_id` could be dialed now, and counts the dial if so.
+    pub fn try_dial(&mut self, peer_id: PeerId) -> bool {
+        self.try_dial_at(peer_id, Instant::now())
+    }
+
+    fn try_dial_at(&mut self, peer_id: PeerId, now: Instant) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+
+        if now.duration_since(self.window_start) >= DIAL_WINDOW {
+            self.window_start = now;
+            self.dials_in_window = 0;
+        }
+        self.dialed
+            .retain(|_, since| now.duration_since(*since) < DIAL_WINDOW);
+
+        if self.dials_in_window >= self.max_dials_per_min || self.dialed.contains_key(&peer_id) {
+            return false;
+        }
+
+        self.dials_in_window += 1;
+        self.dialed.insert(peer_id, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EagerDialLimiter, DIAL_WINDOW};
+    use network::PeerId;
+    use std::time::Instant;
+
+    #[test]
+    fn test_eager_dial_limiter() {
+        let now = Instant::now();
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+
+        assert!(!EagerDialLimiter::new(0).try_dial_at(peers[0], now));
+
+        let mut limiter = EagerDialLimiter::new(2);
+        assert!(limiter.try_dial_at(peers[0], now));
+        // deduplicated
+        assert!(!limiter.try_dial_at(peers[0], now));
+        assert!(limiter.try_dial_at(peers[1], now));
+        // rate limited
+        assert!(!limiter.try_dial_at(peers[2], now));
+
+        let later = now + DIAL_WINDOW;
+        assert!(limiter.try_dial_at(peers[2], later));
+        assert!(limiter.try_dial_at(peers[0], later));
+        assert!(!limiter.try_dial_at(peers[1], later));
+    }
+}
@@ -1,7 +1,8 @@
 use std::sync::Arc;
 
 use metrics::{
-    register_meter, register_timer, Counter, CounterUsize, Histogram, Meter, Sample, Timer,
+    register_meter, register_timer, Counter, CounterUsize, Gauge, GaugeUsize, Histogram, Meter,
+    Sample, Timer,
 };
 
 lazy_static::lazy_static! {
@@ -10,6 +11,23 @@ lazy_static::lazy_static! {
 
     pub static ref SERIAL_SYNC_SEGMENT_BANDWIDTH: Arc<dyn Meter> = register_meter("sync_controllers_serial_sync_segment_bandwidth");
     pub static ref SERIAL_SYNC_SEGMENT_LATENCY: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register("sync_controllers_serial_sync_segment_latency", 1024);
+    pub static ref SERIAL_SYNC_FILE_BANDWIDTH_THROTTLED: Arc<dyn Counter<usize>> = CounterUsize::register("sync_controllers_serial_sync_file_bandwidth_throttled");
     pub static ref SERIAL_SYNC_SEGMENT_TIMEOUT: Arc<dyn Counter<usize>> = CounterUsize::register("sync_controllers_serial_sync_segment_timeout");
+    pub static ref SERIAL_SYNC_SEGMENT_PREEMPTED: Arc<dyn Counter<usize>> = CounterUsize::register("sync_controllers_serial_sync_segment_preempted");
+    pub static ref SERIAL_SYNC_WRITE_BUFFER_FILL: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_controllers_serial_sync_write_buffer_fill");
+    pub static ref SERIAL_SYNC_AVAILABILITY_CACHE_HITS: Arc<dyn Counter<usize>> = CounterUsize::register("sync_controllers_serial_sync_availability_cache_hits");
+    pub static ref SERIAL_SYNC_AVAILABILITY_CACHE_MISSES: Arc<dyn Counter<usize>> = CounterUsize::register("sync_controllers_serial_sync_availability_cache_misses");
+    pub static ref SERIAL_SYNC_AVAILABILITY_CACHE_HIT_RATE: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_controllers_serial_sync_availability_cache_hit_rate_percent");
+    pub static ref SERIAL_SYNC_AVAILABILITY_QUERIES_IN_FLIGHT: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_controllers_serial_sync_availability_queries_in_flight");
+    pub static ref SERIAL_SYNC_WRONG_FILE_DATA: Arc<dyn Counter<usize>> = CounterUsize::register("sync_controllers_serial_sync_wrong_file_data");
+    pub static ref SERIAL_SYNC_EAGER_DIALS: Arc<dyn Counter<usize>> = CounterUsize::register("sync_controllers_serial_sync_eager_dials");
     pub static ref SERIAL_SYNC_UNEXPECTED_ERRORS: Arc<dyn Counter<usize>> = CounterUsize::register("sync_controllers_serial_sync_unexpected_errors");
 }
+
+/// Registers the download rate in bytes per second of the file `tx_seq`, grouped by the tx seq.
+pub fn file_download_rate(tx_seq: u64) -> Arc<dyn Gauge<usize>> {
+    GaugeUsize::register_with_group(
+        "sync_controllers_serial_sync_file_download_rate",
+        &tx_seq.to_string(),
+    )
+}
@@ -0,0 +1,574 @@
+//! A scripted network to sync files end to end in tests without sockets.
+//!
+//! The controller talks to the network by messages only, so the mock network receives the
+//! messages sent by the controller, and calls back the controller as the scripted peers would
+//! do, e.g. answers the file queries in the shards of the peers, and serves the segments
+//! after a latency, fails the requests or never responds.
+//!
+//! A run is deterministic: the network advances the paused tokio clock, i.e. the tests are
+//! `#[tokio::test(start_paused = true)]`, and the peer ids, the latencies and the peers that
+//! the controller picks are drawn from the seed of the network.
+
+use crate::context::SyncNetworkContext;
+use crate::controllers::{
+    CandidatePeer, DefaultPeerSelector, FileSyncGoal, PeerSelector, SerialSyncController,
+    SyncState, WriteBuffer,
+};
+use crate::disk_watchdog::DiskWatchdog;
+use crate::Config;
+use file_location_cache::FileLocationCache;
+use libp2p::identity;
+use libp2p::swarm::DialError;
+use network::{
+    new_network_channel, rpc::GetChunksRequest, NetworkMessage, NetworkReceiver, PeerAction,
+    PeerId, PubsubMessage, Request,
+};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use shared_types::{bytes_to_chunks, ChunkArrayWithProof, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use storage::log_store::LogStoreRead;
+use storage::LogManager;
+use storage_async::{ShardConfig, Store};
+use task_executor::TaskExecutor;
+use tokio::time::Instant;
+
+/// Interval to advance the sync of a file.
+const TICK: Duration = Duration::from_millis(5);
+
+/// How a peer handles a segment request.
+pub enum Transfer {
+    /// Responds after `delay`, of which `None` fails the request.
+    Respond {
+        delay: Duration,
+        response: Option<ChunkArrayWithProof>,
+    },
+    /// Never responds.
+    Silent,
+}
+
+/// A peer of the mock network, of which the availability and the transfers are scripted.
+pub trait ScriptedPeer {
+    /// Returns the shard of the flow that the peer announces for the file, or `None` if the
+    /// peer does not answer the file queries.
+    fn announce(&self) -> Option<ShardConfig>;
+
+    /// Returns whether a dial to the peer succeeds.
+    fn accept_dial(&self) -> bool {
+        true
+    }
+
+    /// Handles a segment request, and draws any randomness from `rng` to keep the run
+    /// deterministic.
+    fn transfer(&self, request: &GetChunksRequest, rng: &mut StdRng) -> Transfer;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerBehavior {
+    /// Serves the requested segments from the store of the peer.
+    Serve,
+    /// Announces the file, but fails all the segment requests, e.g. the data is pruned.
+    FailRequests,
+    /// Announces the file, but never responds to the segment requests.
+    Silent,
+    /// Announces the file, but serves the segments of the file of the tx seq instead.
+    ServeFile(u64),
+}
+
+pub struct MockPeer {
+    pub shard_config: ShardConfig,
+    pub behavior: PeerBehavior,
+    /// Delay to respond to a segment request.
+    pub latency: Duration,
+    /// Maximum random delay added to `latency`.
+    pub jitter: Duration,
+    store: Arc<LogManager>,
+}
+
+impl MockPeer {
+    pub fn new(store: Arc<LogManager>, behavior: PeerBehavior) -> Self {
+        Self {
+            shard_config: Default::default(),
+            behavior,
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            store,
+        }
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_shard_config(mut self, shard_config: ShardConfig) -> Self {
+        self.shard_config = shard_config;
+        self
+    }
+
+    /// Returns the response to a segment request, or `None` if the request fails.
+    fn respond(&self, request: &GetChunksRequest) -> Option<ChunkArrayWithProof> {
+        let tx_seq = match self.behavior {
+            PeerBehavior::Serve => request.tx_id.seq,
+            PeerBehavior::ServeFile(tx_seq) => tx_seq,
+            _ => return None,
+        };
+
+        self.store
+            .get_chunks_with_proof_by_tx_and_index_range(
+                tx_seq,
+                request.index_start as usize,
+                request.index_end as usize,
+                None,
+            )
+            .ok()
+            .flatten()
+    }
+}
+
+impl ScriptedPeer for MockPeer {
+    fn announce(&self) -> Option<ShardConfig> {
+        Some(self.shard_config)
+    }
+
+    fn transfer(&self, request: &GetChunksRequest, rng: &mut StdRng) -> Transfer {
+        if self.behavior == PeerBehavior::Silent {
+            return Transfer::Silent;
+        }
+
+        Transfer::Respond {
+            delay: self.latency + rng.gen_range(Duration::ZERO..=self.jitter),
+            response: self.respond(request),
+        }
+    }
+}
+
+/// Selects the same peers as `DefaultPeerSelector`, but in a seeded random order.
+struct SeededPeerSelector(Mutex<StdRng>);
+
+impl PeerSelector for SeededPeerSelector {
+    fn select(
+        &self,
+        missing_segments: Range<u64>,
+        candidates: &[CandidatePeer],
+        max_active_peers: usize,
+    ) -> Vec<PeerId> {
+        let mut peers = DefaultPeerSelector.select(missing_segments, candidates, max_active_peers);
+        peers.sort_unstable();
+        peers.shuffle(&mut *self.0.lock().unwrap());
+        peers
+    }
+}
+
+pub struct MockNetwork {
+    peers: Vec<(PeerId, Box<dyn ScriptedPeer>)>,
+    rng: StdRng,
+    network_recv: NetworkReceiver,
+    ctx: Arc<SyncNetworkContext>,
+    /// Responses to deliver once due, of which `None` fails the request.
+    pending: Vec<(Instant, PeerId, Option<ChunkArrayWithProof>)>,
+    /// Peers banned by the controller, which are disconnected and do not answer any more.
+    pub banned: HashSet<PeerId>,
+    /// Peers reported by the controller to serve the data of a wrong file, which are banned.
+    pub wrong_file_peers: HashSet<PeerId>,
+    /// Number of the segment requests sent to each peer.
+    pub requests: HashMap<PeerId, usize>,
+    /// Whether the local node announced the file once finalized.
+    pub announced: bool,
+}
+
+impl MockNetwork {
+    pub fn new(seed: u64) -> Self {
+        let (network_send, network_recv) = new_network_channel();
+        Self {
+            peers: vec![],
+            rng: StdRng::seed_from_u64(seed),
+            network_recv,
+            ctx: Arc::new(SyncNetworkContext::new(network_send)),
+            pending: vec![],
+            banned: Default::default(),
+            wrong_file_peers: Default::default(),
+            requests: Default::default(),
+            announced: false,
+        }
+    }
+
+    /// Adds a peer with an id drawn from the seed, and returns the id.
+    pub fn add_peer(&mut self, peer: impl ScriptedPeer + 'static) -> PeerId {
+        let secret = identity::ed25519::SecretKey::from_bytes(self.rng.gen::<[u8; 32]>())
+            .expect("32 bytes secret key");
+        let peer_id = identity::Keypair::Ed25519(secret.into())
+            .public()
+            .to_peer_id();
+        self.peers.push((peer_id, Box::new(peer)));
+        peer_id
+    }
+
+    /// Creates a controller to sync the whole file of `tx` into `store` over this network,
+    /// which picks the peers by the seed of the network.
+    pub fn create_controller(
+        &mut self,
+        config: Config,
+        tx: &Transaction,
+        store: Arc<LogManager>,
+        task_executor: TaskExecutor,
+    ) -> SerialSyncController {
+        let num_chunks = bytes_to_chunks(tx.size as usize) as u64;
+        let store = Store::new(store, task_executor.clone());
+        SerialSyncController::new(
+            config,
+            tx.id(),
+            tx.start_entry_index(),
+            FileSyncGoal::new_file(num_chunks),
+            self.ctx.clone(),
+            store.clone(),
+            Arc::new(FileLocationCache::default()),
+            Arc::new(DiskWatchdog::disabled()),
+            WriteBuffer::spawn(config.max_pending_write_segments, store, &task_executor),
+        )
+        .with_rng_seed(self.rng.gen())
+        .with_peer_selector(Arc::new(SeededPeerSelector(Mutex::new(
+            StdRng::seed_from_u64(self.rng.gen()),
+        ))))
+    }
+
+    /// Drives the controller until the file is completed or failed, or `timeout` elapsed on
+    /// the tokio clock, and returns the final state.
+    ///
+    /// Panics if the tokio clock is not paused.
+    pub async fn run(
+        &mut self,
+        controller: &mut SerialSyncController,
+        timeout: Duration,
+    ) -> SyncState {
+        let deadline = Instant::now() + timeout;
+        while !controller.is_completed_or_failed() && Instant::now() < deadline {
+            controller.transition();
+            self.handle_messages(controller);
+            self.deliver_responses(controller).await;
+            tokio::time::advance(TICK).await;
+        }
+        self.handle_messages(controller);
+
+        controller.get_status().clone()
+    }
+
+    fn handle_messages(&mut self, controller: &mut SerialSyncController) {
+        while let Ok(msg) = self.network_recv.try_recv() {
+            match msg {
+                NetworkMessage::Publish { messages } => {
+                    for msg in messages {
+                        if matches!(
+                            msg,
+                            PubsubMessage::AskFile(_)
+                                | PubsubMessage::FindFile(_)
+                                | PubsubMessage::FindChunks(_)
+                        ) {
+                            self.answer_file(controller);
+                        }
+                    }
+                }
+                NetworkMessage::DialPeer { peer_id, .. } => {
+                    let accepted = !self.banned.contains(&peer_id)
+                        && self
+                            .peers
+                            .iter()
+                            .any(|(id, peer)| *id == peer_id && peer.accept_dial());
+                    if accepted {
+                        controller.on_peer_connected(peer_id);
+                    } else {
+                        controller.on_dial_failed(peer_id, &DialError::NoAddresses);
+                    }
+                }
+                NetworkMessage::SendRequest {
+                    peer_id,
+                    request: Request::GetChunks(request),
+                    ..
+                } => {
+                    *self.requests.entry(peer_id).or_default() += 1;
+                    let peer = match self.peers.iter().find(|(id, _)| *id == peer_id) {
+                        Some((_, peer)) => peer,
+                        None => continue,
+                    };
+                    if let Transfer::Respond { delay, response } =
+                        peer.transfer(&request, &mut self.rng)
+                    {
+                        self.pending
+                            .push((Instant::now() + delay, peer_id, response));
+                    }
+                }
+                NetworkMessage::ReportPeer {
+                    peer_id,
+                    action: action @ (PeerAction::Fatal | PeerAction::WrongFileData),
+                    ..
+                } => {
+                    if matches!(action, PeerAction::WrongFileData) {
+                        self.wrong_file_peers.insert(peer_id);
+                    }
+                    self.banned.insert(peer_id);
+                    self.pending.retain(|(_, id, _)| *id != peer_id);
+                    controller.on_peer_disconnected(peer_id);
+                }
+                NetworkMessage::AnnounceLocalFile { .. } => self.announced = true,
+                _ => {}
+            }
+        }
+    }
+
+    /// Answers the query of the file by all the peers that are not banned, in the order they
+    /// were added.
+    fn answer_file(&self, controller: &mut SerialSyncController) {
+        for (peer_id, peer) in &self.peers {
+            if self.banned.contains(peer_id) {
+                continue;
+            }
+            if let Some(shard_config) = peer.announce() {
+                controller.on_peer_announced(*peer_id, shard_config);
+            }
+        }
+    }
+
+    async fn deliver_responses(&mut self, controller: &mut SerialSyncController) {
+        let now = Instant::now();
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|(at, _, _)| *at <= now);
+        self.pending = pending;
+
+        for (_, peer_id, response) in due {
+            match response {
+                Some(response) => controller.on_response(peer_id, response).await,
+                None => controller.on_request_failed(peer_id),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockNetwork, MockPeer, PeerBehavior};
+    use crate::controllers::{FailureReason, SyncState};
+    use crate::test_util::create_2_store;
+    use crate::Config;
+    use shared_types::CHUNK_SIZE;
+    use std::time::Duration;
+    use storage::log_store::LogStoreRead;
+    use storage_async::ShardConfig;
+    use task_executor::test_utils::TestRuntime;
+    use tokio::time::Instant;
+
+    const SEED: u64 = 170;
+    const RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn config() -> Config {
+        Config {
+            peer_find_timeout: Duration::from_millis(100),
+            peer_chunks_download_timeout: Duration::from_millis(50),
+            peer_next_chunks_request_wait_timeout: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_network_download() {
+        // 3 segments
+        let (store, peer_store, txs, _) = create_2_store(vec![2500]);
+        let runtime = TestRuntime::default();
+        let half = |shard_id| ShardConfig {
+            num_shard: 2,
+            shard_id,
+        };
+
+        // each peer stores half of the segments
+        let mut network = MockNetwork::new(SEED);
+        network.add_peer(
+            MockPeer::new(peer_store.clone(), PeerBehavior::Serve).with_shard_config(half(0)),
+        );
+        network.add_peer(
+            MockPeer::new(peer_store, PeerBehavior::Serve)
+                .with_shard_config(half(1))
+                .with_latency(Duration::from_millis(20))
+                .with_jitter(Duration::from_millis(20)),
+        );
+        let mut controller = network.create_controller(
+            config(),
+            &txs[0],
+            store.clone(),
+            runtime.task_executor.clone(),
+        );
+
+        assert_eq!(
+            network.run(&mut controller, RUN_TIMEOUT).await,
+            SyncState::Completed
+        );
+        assert!(store.check_tx_completed(0).unwrap());
+        assert_eq!(network.requests.len(), 2);
+        assert_eq!(network.requests.values().sum::<usize>(), 3);
+        assert!(network.banned.is_empty());
+        assert!(network.announced);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_network_replay() {
+        let runtime = TestRuntime::default();
+
+        let run = |seed| {
+            let task_executor = runtime.task_executor.clone();
+            async move {
+                // 5 segments
+                let (store, peer_store, txs, _) = create_2_store(vec![4500]);
+                let mut network = MockNetwork::new(seed);
+                for latency in [10, 20, 30] {
+                    network.add_peer(
+                        MockPeer::new(peer_store.clone(), PeerBehavior::Serve)
+                            .with_latency(Duration::from_millis(latency))
+                            .with_jitter(Duration::from_millis(30)),
+                    );
+                }
+                let mut controller =
+                    network.create_controller(config(), &txs[0], store, task_executor);
+
+                let start = Instant::now();
+                let state = network.run(&mut controller, RUN_TIMEOUT).await;
+                (state, start.elapsed(), network.requests)
+            }
+        };
+
+        // the same seed replays the same requests in the same time
+        let (state, elapsed, requests) = run(SEED).await;
+        assert_eq!(state, SyncState::Completed);
+        assert_eq!(run(SEED).await, (state, elapsed, requests));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_network_file_bandwidth() {
+        // 3 segments
+        let (store, peer_store, txs, _) = create_2_store(vec![2500]);
+        let runtime = TestRuntime::default();
+        let max_file_bandwidth_bytes = 2 * 1024 * 1024;
+
+        let mut network = MockNetwork::new(SEED);
+        network.add_peer(MockPeer::new(peer_store.clone(), PeerBehavior::Serve));
+        network.add_peer(MockPeer::new(peer_store, PeerBehavior::Serve));
+        let mut controller = network.create_controller(
+            Config {
+                max_file_bandwidth_bytes,
+                ..config()
+            },
+            &txs[0],
+            store.clone(),
+            runtime.task_executor.clone(),
+        );
+
+        let start = Instant::now();
+        assert_eq!(
+            network.run(&mut controller, RUN_TIMEOUT).await,
+            SyncState::Completed
+        );
+
+        // the requests are paced, however many peers serve the file
+        let bytes = 2500 * CHUNK_SIZE as u64;
+        assert!(start.elapsed().as_secs_f64() >= bytes as f64 / max_file_bandwidth_bytes as f64);
+        assert!(store.check_tx_completed(0).unwrap());
+        assert!(network.banned.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_network_fallback_to_peer_with_data() {
+        let (store, peer_store, txs, _) = create_2_store(vec![2500]);
+        let runtime = TestRuntime::default();
+
+        let mut network = MockNetwork::new(SEED);
+        let failing_peer_id = network.add_peer(MockPeer::new(
+            peer_store.clone(),
+            PeerBehavior::FailRequests,
+        ));
+        network.add_peer(MockPeer::new(peer_store, PeerBehavior::Serve));
+        let mut controller = network.create_controller(
+            config(),
+            &txs[0],
+            store.clone(),
+            runtime.task_executor.clone(),
+        );
+
+        assert_eq!(
+            network.run(&mut controller, RUN_TIMEOUT).await,
+            SyncState::Completed
+        );
+        assert!(store.check_tx_completed(0).unwrap());
+        assert!(network
+            .banned
+            .iter()
+            .all(|peer_id| *peer_id == failing_peer_id));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_network_peer_serves_wrong_file() {
+        let (store, peer_store, txs, _) = create_2_store(vec![2500, 2500]);
+        let runtime = TestRuntime::default();
+
+        // serves the segments of another file with valid proofs
+        let mut network = MockNetwork::new(SEED);
+        let wrong_peer_id = network.add_peer(MockPeer::new(peer_store, PeerBehavior::ServeFile(1)));
+        let mut controller = network.create_controller(
+            config(),
+            &txs[0],
+            store.clone(),
+            runtime.task_executor.clone(),
+        );
+
+        assert_eq!(
+            network.run(&mut controller, RUN_TIMEOUT).await,
+            SyncState::Failed {
+                reason: FailureReason::TimeoutFindFile
+            }
+        );
+        assert_eq!(network.requests.get(&wrong_peer_id), Some(&1));
+        assert!(network.wrong_file_peers.contains(&wrong_peer_id));
+        assert!(network.banned.contains(&wrong_peer_id));
+        assert_eq!(controller.get_sync_info().failed_segments, 1);
+        assert!(store
+            .get_chunks_by_tx_and_index_range(0, 0, 1024)
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_network_connected_peers_without_data() {
+        let (store, peer_store, txs, _) = create_2_store(vec![123]);
+        let runtime = TestRuntime::default();
+
+        // both peers announce the file and get connected, but never serve any segment
+        let mut network = MockNetwork::new(SEED);
+        network.add_peer(MockPeer::new(
+            peer_store.clone(),
+            PeerBehavior::FailRequests,
+        ));
+        network.add_peer(MockPeer::new(peer_store, PeerBehavior::Silent));
+        let mut controller = network.create_controller(
+            config(),
+            &txs[0],
+            store.clone(),
+            runtime.task_executor.clone(),
+        );
+
+        assert_eq!(
+            network.run(&mut controller, RUN_TIMEOUT).await,
+            SyncState::Failed {
+                reason: FailureReason::TimeoutFindFile
+            }
+        );
+        assert!(!store.check_tx_completed(0).unwrap());
+        assert_eq!(network.banned.len(), 2);
+        assert!(!network.announced);
+    }
+}
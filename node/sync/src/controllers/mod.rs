@@ -1,13 +1,28 @@
+mod availability;
+mod bandwidth;
+mod coverage;
+mod eager_dial;
 mod metrics;
+#[cfg(test)]
+mod mock_network;
+mod peer_selection;
 mod peers;
+mod query_limit;
 mod serial;
+mod write_buffer;
 
 use std::collections::HashMap;
 
 use peers::PeerState;
 use serde::{Deserialize, Serialize};
 
+pub use availability::AvailabilityCache;
+pub use coverage::{cover_segments, SegmentCoverage};
+pub use eager_dial::EagerDialLimiter;
+pub use peer_selection::{CandidatePeer, DefaultPeerSelector, PeerSelector};
+pub use query_limit::QueryLimiter;
 pub use serial::{FailureReason, SerialSyncController, SyncState};
+pub use write_buffer::WriteBuffer;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -51,6 +66,14 @@ pub struct FileSyncInfo {
     pub elapsed_secs: u64,
     pub peers: HashMap<PeerState, u64>,
     pub goal: FileSyncGoal,
+    /// Number of peers that segments are requested from.
+    pub active_peers: usize,
     pub next_chunks: u64,
+    /// Number of segments whose proofs are verified and written into db.
+    pub verified_segments: u64,
+    /// Number of segments that failed the proof verification and are re-requested.
+    pub failed_segments: u64,
+    /// Average download rate of the file in bytes per second recently.
+    pub download_rate_bytes: u64,
     pub state: String,
 }
@@ -0,0 +1,148 @@
+//! Strategies to select the peer to request the next segments of a file from, which could be
+//! injected into the sync service to experiment with other policies without forking it.
+
+use network::PeerId;
+use rand::seq::SliceRandom;
+use std::ops::Range;
+use storage_async::ShardConfig;
+
+/// A connected peer that could be requested for the segments of a file.
+#[derive(Clone, Debug)]
+pub struct CandidatePeer {
+    pub peer_id: PeerId,
+    /// Shard of the flow that the peer stores.
+    pub shard_config: ShardConfig,
+    /// Delivery rate in bytes per second of the segments served by the peer, or `None` if the
+    /// peer has not served any segment of the file yet.
+    pub delivery_rate: Option<f64>,
+    /// Whether a request to the peer was preempted since the peer was slow.
+    pub preempted: bool,
+    /// Whether segments of the file are requested from the peer.
+    pub active: bool,
+}
+
+impl CandidatePeer {
+    /// Returns `true` if the peer stores the flow segment of `segment_index`.
+    pub fn has_segment(&self, segment_index: u64) -> bool {
+        self.shard_config.in_range(segment_index)
+    }
+}
+
+/// Selects the peers to request the missing segments of a file from.
+///
+/// - `missing_segments` are the flow segment indices not downloaded yet, of which the first
+///   one is requested next.
+/// - `candidates` are all the connected peers of the file in no particular order.
+/// - `max_active_peers` is the limit of `max_active_peers_per_file`, 0 for unlimited. Once the
+///   limit is reached, the selection should keep to the active peers unless none of them
+///   stores the next segment.
+///
+/// Returns the peers to request the next segment from in the order of preference, of which the
+/// first one is requested. Peers that do not store the next segment are ignored, and an empty
+/// selection pauses the download until more peers are found.
+///
+/// The selection is called for every request, so it should be cheap.
+pub trait PeerSelector: Send + Sync {
+    fn select(
+        &self,
+        missing_segments: Range<u64>,
+        candidates: &[CandidatePeer],
+        max_active_peers: usize,
+    ) -> Vec<PeerId>;
+}
+
+/// The default strategy, which spreads the requests randomly over the peers that store the
+/// next segment, and prefers the peers that are not preempted as slow.
+#[derive(Debug, Default)]
+pub struct DefaultPeerSelector;
+
+impl PeerSelector for DefaultPeerSelector {
+    fn select(
+        &self,
+        missing_segments: Range<u64>,
+        candidates: &[CandidatePeer],
+        max_active_peers: usize,
+    ) -> Vec<PeerId> {
+        if missing_segments.is_empty() {
+            return vec![];
+        }
+
+        let mut peers: Vec<&CandidatePeer> = candidates
+            .iter()
+            .filter(|peer| peer.has_segment(missing_segments.start))
+            .collect();
+
+        // avoid slow peers if possible
+        if peers.iter().any(|peer| !peer.preempted) {
+            peers.retain(|peer| !peer.preempted);
+        }
+
+        // keep to the active peers if the limit is reached, unless none of them has the segment
+        let num_active = candidates.iter().filter(|peer| peer.active).count();
+        if max_active_peers > 0
+            && num_active >= max_active_peers
+            && peers.iter().any(|peer| peer.active)
+        {
+            peers.retain(|peer| peer.active);
+        }
+
+        peers.shuffle(&mut rand::thread_rng());
+        peers.into_iter().map(|peer| peer.peer_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CandidatePeer, DefaultPeerSelector, PeerSelector};
+    use network::PeerId;
+    use std::collections::HashSet;
+    use storage_async::ShardConfig;
+
+    fn candidate(shard_config: ShardConfig, preempted: bool, active: bool) -> CandidatePeer {
+        CandidatePeer {
+            peer_id: PeerId::random(),
+            shard_config,
+            delivery_rate: None,
+            preempted,
+            active,
+        }
+    }
+
+    #[test]
+    fn test_default_peer_selector() {
+        let half = |shard_id| ShardConfig {
+            num_shard: 2,
+            shard_id,
+        };
+        let candidates = vec![
+            candidate(Default::default(), false, false),
+            candidate(half(0), true, false),
+            candidate(half(0), false, true),
+            candidate(half(1), false, false),
+        ];
+        let selected = |segments, max_active_peers| -> HashSet<PeerId> {
+            DefaultPeerSelector
+                .select(segments, &candidates, max_active_peers)
+                .into_iter()
+                .collect()
+        };
+
+        // peers without the segment or preempted are not selected
+        assert_eq!(
+            selected(0..4, 0),
+            HashSet::from([candidates[0].peer_id, candidates[2].peer_id])
+        );
+        assert_eq!(
+            selected(1..4, 0),
+            HashSet::from([candidates[0].peer_id, candidates[3].peer_id])
+        );
+
+        // keep to the active peers
+        assert_eq!(selected(0..4, 1), HashSet::from([candidates[2].peer_id]));
+        assert_eq!(
+            selected(1..4, 1),
+            HashSet::from([candidates[0].peer_id, candidates[3].peer_id])
+        );
+        assert!(selected(4..4, 0).is_empty());
+    }
+}
@@ -1,15 +1,16 @@
 use file_location_cache::FileLocationCache;
 use network::{Multiaddr, PeerAction, PeerId};
-use rand::seq::IteratorRandom;
+use rand::seq::SliceRandom;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use shared_types::TxID;
 
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
-use std::time::Instant;
 use std::vec;
 use storage::config::{all_shards_available, ShardConfig};
+use tokio::time::Instant;
 
 use crate::context::SyncNetworkContext;
 use crate::{Config, InstantWrapper};
@@ -135,16 +136,24 @@ impl SyncPeers {
         self.peers.get(peer_id).map(|info| info.state)
     }
 
+    pub fn peer_addr(&self, peer_id: &PeerId) -> Option<Multiaddr> {
+        self.peers.get(peer_id).map(|info| info.addr.clone())
+    }
+
     pub fn shard_config(&self, peer_id: &PeerId) -> Option<ShardConfig> {
         self.peers.get(peer_id).map(|info| info.shard_config)
     }
 
-    pub fn random_peer(&self, state: PeerState) -> Option<(PeerId, Multiaddr)> {
-        self.peers
+    pub fn random_peer(&self, state: PeerState, rng: &mut impl Rng) -> Option<(PeerId, Multiaddr)> {
+        let mut peers: Vec<_> = self
+            .peers
             .iter()
             .filter(|(_, info)| info.state == state)
             .map(|(peer_id, info)| (*peer_id, info.addr.clone()))
-            .choose(&mut rand::thread_rng())
+            .collect();
+        // sorted so that the same seed picks the same peer
+        peers.sort_unstable_by_key(|(peer_id, _)| *peer_id);
+        peers.choose(rng).cloned()
     }
 
     pub fn filter_peers(&self, state: Vec<PeerState>) -> Vec<PeerId> {
@@ -325,14 +334,19 @@ mod tests {
         }
 
         // random pick
+        let mut rng = rand::thread_rng();
         for _ in 0..30 {
-            let peer = sync_peers.random_peer(PeerState::Found).unwrap();
+            let peer = sync_peers.random_peer(PeerState::Found, &mut rng).unwrap();
             assert!(peers_found.contains(&peer.0));
             assert_eq!(peer.1, addr);
-            let peer = sync_peers.random_peer(PeerState::Connecting).unwrap();
+            let peer = sync_peers
+                .random_peer(PeerState::Connecting, &mut rng)
+                .unwrap();
             assert!(peers_connecting.contains(&peer.0));
             assert_eq!(peer.1, addr);
-            assert!(sync_peers.random_peer(PeerState::Disconnected).is_none());
+            assert!(sync_peers
+                .random_peer(PeerState::Disconnected, &mut rng)
+                .is_none());
         }
     }
 
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::controllers::metrics;
+
+#[derive(Default)]
+struct LimiterState {
+    in_flight: usize,
+    next_id: u64,
+    /// Queued queries in the order they're queued.
+    queued: VecDeque<u64>,
+}
+
+/// Bounds the availability queries, i.e. `FindFile` or `FindChunks`, that are published but
+/// not answered or timed out yet, so that the query fan-out of many file syncs does not
+/// overwhelm the node and its neighbors.
+///
+/// The queries beyond the limit are queued, and started in the order they're queued once the
+/// in-flight queries are released.
+#[derive(Clone, Default)]
+pub struct QueryLimiter {
+    /// 0 if unbounded.
+    max_in_flight: usize,
+    state: Arc<Mutex<LimiterState>>,
+}
+
+impl QueryLimiter {
+    /// Creates a limiter of `max_in_flight` queries, or an unbounded one if 0.
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            state: Default::default(),
+        }
+    }
+
+    /// Queues a query, which is started by `QueryTicket::try_start`.
+    pub fn enqueue(&self) -> QueryTicket {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.queued.push_back(id);
+        QueryTicket {
+            id,
+            started: false,
+            limiter: self.clone(),
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().queued.len()
+    }
+}
+
+/// A query of `QueryLimiter` that is queued or in flight, and is released when dropped.
+pub struct QueryTicket {
+    id: u64,
+    started: bool,
+    limiter: QueryLimiter,
+}
+
+impl QueryTicket {
+    /// Starts the query if it's among the earliest queued ones that fit in the available
+    /// slots, and returns whether the query is in flight.
+    pub fn try_start(&mut self) -> bool {
+        if self.started {
+            return true;
+        }
+
+        let mut state = self.limiter.state.lock().unwrap();
+        let available = match self.limiter.max_in_flight {
+            0 => usize::MAX,
+            max => max.saturating_sub(state.in_flight),
+        };
+        match state.queued.iter().position(|id| *id == self.id) {
+            Some(pos) if pos < available => {
+                state.queued.remove(pos);
+            }
+            _ => return false,
+        }
+
+        state.in_flight += 1;
+        metrics::SERIAL_SYNC_AVAILABILITY_QUERIES_IN_FLIGHT.update(state.in_flight);
+        self.started = true;
+        true
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+}
+
+impl Drop for QueryTicket {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        if self.started {
+            state.in_flight -= 1;
+            metrics::SERIAL_SYNC_AVAILABILITY_QUERIES_IN_FLIGHT.update(state.in_flight);
+        } else {
+            state.queued.retain(|id| *id != self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryLimiter;
+
+    #[test]
+    fn test_query_limiter() {
+        let limiter = QueryLimiter::new(2);
+        let mut tickets: Vec<_> = (0..4).map(|_| limiter.enqueue()).collect();
+
+        // the later queued query cannot skip the queue
+        assert!(!tickets[2].try_start());
+        assert!(tickets[0].try_start());
+        assert!(tickets[1].try_start());
+        assert!(!tickets[3].try_start());
+        assert_eq!(limiter.in_flight(), 2);
+        assert_eq!(limiter.queued(), 2);
+
+        // started in order once released
+        drop(tickets.remove(0));
+        assert!(!tickets[2].try_start());
+        assert!(tickets[1].try_start());
+        assert_eq!(limiter.in_flight(), 2);
+
+        // cancelled query leaves the queue
+        drop(tickets.remove(2));
+        assert_eq!(limiter.queued(), 0);
+        drop(tickets);
+        assert_eq!(limiter.in_flight(), 0);
+
+        let unbounded = QueryLimiter::new(0);
+        let mut tickets: Vec<_> = (0..100).map(|_| unbounded.enqueue()).collect();
+        assert!(tickets.iter_mut().all(|ticket| ticket.try_start()));
+        assert_eq!(unbounded.in_flight(), 100);
+    }
+}
@@ -1,7 +1,17 @@
+use crate::checkpoint::SyncCheckpoint;
 use crate::context::SyncNetworkContext;
+use crate::controllers::availability::AvailabilityCache;
+use crate::controllers::bandwidth::FileBandwidth;
+use crate::controllers::coverage::PeerCover;
+use crate::controllers::peer_selection::{CandidatePeer, DefaultPeerSelector, PeerSelector};
 use crate::controllers::peers::{PeerState, SyncPeers};
-use crate::controllers::{metrics, FileSyncGoal, FileSyncInfo};
+use crate::controllers::query_limit::{QueryLimiter, QueryTicket};
+use crate::controllers::write_buffer::{write_segment, SegmentWrite, WriteBuffer, WriteSlot};
+use crate::controllers::{cover_segments, metrics, FileSyncGoal, FileSyncInfo, SegmentCoverage};
+use crate::disk_watchdog::DiskWatchdog;
 use crate::{Config, InstantWrapper};
+use ::metrics::Gauge;
+use anyhow::anyhow;
 use file_location_cache::FileLocationCache;
 use libp2p::swarm::DialError;
 use network::types::FindChunks;
@@ -9,23 +19,39 @@ use network::{
     multiaddr::Protocol, rpc::GetChunksRequest, types::FindFile, Multiaddr, NetworkMessage,
     PeerAction, PeerId, PubsubMessage, SyncId as RequestId,
 };
-use rand::Rng;
-use shared_types::{ChunkArrayWithProof, ShardedFile, TxID, CHUNK_SIZE};
+use rand::{rngs::StdRng, SeedableRng};
+use shared_types::{ChunkArray, ChunkArrayWithProof, DataRoot, ShardedFile, TxID, CHUNK_SIZE};
 use ssz::Encode;
-use std::{sync::Arc, time::Instant};
-use storage::log_store::log_manager::{sector_to_segment, segment_to_sector, PORA_CHUNK_SIZE};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::{sync::Arc, time::Duration};
+use storage::log_store::log_manager::{
+    data_to_merkle_leaves_h256, sector_to_segment, segment_to_sector, FileMerkleTree,
+    PORA_CHUNK_SIZE,
+};
 use storage_async::{ShardConfig, Store};
+use tokio::time::Instant;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum FailureReason {
     DBError(String),
     TxReverted(TxID),
     TimeoutFindFile,
+    /// Segments accepted from trusted peers without verification mismatch the file root.
+    TrustedDataMismatch,
 }
 
+/// Maximum size of a file of which the segments from trusted peers are accepted without proof
+/// verification, since they're held in memory until the whole file is verified.
+const MAX_UNVERIFIED_FILE_BYTES: u64 = 256 * 1024 * 1024;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SyncState {
     Idle,
+    /// The query for peers is queued since too many queries are in flight.
+    AwaitingQuery {
+        origin: InstantWrapper,
+        since: InstantWrapper,
+    },
     FindingPeers {
         origin: InstantWrapper,
         since: InstantWrapper,
@@ -53,6 +79,19 @@ pub enum SyncState {
     },
 }
 
+/// Delivery rate of a peer that served segments of the file.
+#[derive(Debug, Default)]
+struct DeliveryStats {
+    bytes: u64,
+    elapsed: Duration,
+}
+
+impl DeliveryStats {
+    fn rate(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+}
+
 pub struct SerialSyncController {
     config: Config,
 
@@ -75,6 +114,23 @@ pub struct SerialSyncController {
     /// Continuous RPC failures to request chunks.
     failures: usize,
 
+    /// Segments verified against the file root and written into db.
+    verified_segments: u64,
+
+    /// Segments failed to verify, whose peers are banned.
+    failed_segments: u64,
+
+    /// Trusted peers that served segments accepted without proof verification.
+    unverified_peers: HashSet<PeerId>,
+
+    /// Segments accepted from trusted peers without proof verification, which are held in
+    /// memory until the whole file is verified against the file root, so that unverified data
+    /// is never written into the flow.
+    unverified_segments: Vec<ChunkArray>,
+
+    /// Verify all the segments, e.g. the segments from trusted peers mismatched the file root.
+    verify_all: bool,
+
     /// Current state of this request.
     state: SyncState,
 
@@ -89,9 +145,62 @@ pub struct SerialSyncController {
 
     /// Cache for storing and serving gossip messages.
     file_location_cache: Arc<FileLocationCache>,
+
+    /// Pauses downloading file segments when the disk space is low.
+    disk_watchdog: Arc<DiskWatchdog>,
+
+    /// Bounds the segments that are downloading or not written into db yet.
+    write_buffer: WriteBuffer,
+
+    /// Slot of the segment in downloading or writing.
+    write_slot: Option<WriteSlot>,
+
+    /// Delivery rate of peers that served segments of this file.
+    delivery_stats: HashMap<PeerId, DeliveryStats>,
+
+    /// The first chunk and the start time of the preempted requests by peer, which are not
+    /// answered or timeout yet. The late responses of the preempted requests are dropped
+    /// without penalty, and the peers are not selected if other peers are available.
+    preempted_requests: HashMap<PeerId, (u64, InstantWrapper)>,
+
+    /// Peers that segments are requested from, bounded by `max_active_peers_per_file`.
+    active_peers: HashSet<PeerId>,
+
+    /// Strategy to select the peer to request the next segment from.
+    peer_selector: Arc<dyn PeerSelector>,
+
+    /// Recent answers of peers that have the file, which are reused to find peers.
+    availability_cache: AvailabilityCache,
+
+    /// Paces the segment requests under `max_file_bandwidth_bytes`.
+    bandwidth: FileBandwidth,
+
+    /// Download rate of the file in metrics, which is registered once any segment is received
+    /// and reset once the sync of the file ends.
+    download_rate: Option<Arc<dyn Gauge<usize>>>,
+
+    /// Picks the peers to dial, which is seeded to replay a sync in tests.
+    rng: StdRng,
+
+    /// Bounds the queries for peers that are in flight.
+    query_limiter: QueryLimiter,
+
+    /// The query for peers that is queued or in flight.
+    query: Option<QueryTicket>,
+}
+
+/// Outcome of the query for peers that have the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Query {
+    /// The cached answers of peers cover all the shards.
+    Skipped,
+    Published,
+    /// Queued since too many queries are in flight.
+    Queued,
 }
 
 impl SerialSyncController {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Config,
         tx_id: TxID,
@@ -100,6 +209,8 @@ impl SerialSyncController {
         ctx: Arc<SyncNetworkContext>,
         store: Store,
         file_location_cache: Arc<FileLocationCache>,
+        disk_watchdog: Arc<DiskWatchdog>,
+        write_buffer: WriteBuffer,
     ) -> Self {
         SerialSyncController {
             config,
@@ -110,24 +221,167 @@ impl SerialSyncController {
             goal,
             next_chunk: goal.index_start,
             failures: 0,
+            verified_segments: 0,
+            failed_segments: 0,
+            unverified_peers: Default::default(),
+            unverified_segments: Default::default(),
+            verify_all: false,
             state: SyncState::Idle,
             peers: SyncPeers::new(config, ctx.clone(), tx_id, file_location_cache.clone()),
             ctx,
             store,
             file_location_cache,
+            disk_watchdog,
+            write_buffer,
+            write_slot: None,
+            delivery_stats: Default::default(),
+            preempted_requests: Default::default(),
+            active_peers: Default::default(),
+            peer_selector: Arc::new(DefaultPeerSelector),
+            availability_cache: Default::default(),
+            bandwidth: FileBandwidth::new(config.max_file_bandwidth_bytes),
+            download_rate: None,
+            query_limiter: Default::default(),
+            query: None,
+            rng: StdRng::from_entropy(),
         }
     }
 
+    /// Seeds the random choice of the peers to dial, e.g. to replay a sync in tests.
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Replaces the default strategy to select peers.
+    pub fn with_peer_selector(mut self, peer_selector: Arc<dyn PeerSelector>) -> Self {
+        self.peer_selector = peer_selector;
+        self
+    }
+
+    /// Shares the recent answers of peers with other file syncs.
+    pub fn with_availability_cache(mut self, availability_cache: AvailabilityCache) -> Self {
+        self.availability_cache = availability_cache;
+        self
+    }
+
+    /// Shares the bound of the in-flight queries for peers with other file syncs.
+    pub fn with_query_limiter(mut self, query_limiter: QueryLimiter) -> Self {
+        self.query_limiter = query_limiter;
+        self
+    }
+
     pub fn get_sync_info(&self) -> FileSyncInfo {
         FileSyncInfo {
             elapsed_secs: self.since.elapsed().as_secs(),
             peers: self.peers.states(),
             goal: self.goal,
+            active_peers: self.active_peers.len(),
             next_chunks: self.next_chunk,
+            verified_segments: self.verified_segments,
+            failed_segments: self.failed_segments,
+            download_rate_bytes: self.bandwidth.rate(Instant::now()),
             state: format!("{:?}", self.state),
         }
     }
 
+    /// Returns the peers that cover the segments not downloaded yet, and the segments that no
+    /// known peer holds according to the announced shard configs.
+    pub fn get_segment_coverage(&self) -> SegmentCoverage {
+        let (missing, cover) = self.cover_missing_segments(vec![
+            PeerState::Found,
+            PeerState::Connecting,
+            PeerState::Connected,
+        ]);
+        SegmentCoverage {
+            num_missing: missing.len(),
+            peers: cover
+                .peers
+                .iter()
+                .map(|peer_id| peer_id.to_base58())
+                .collect(),
+            uncovered_segments: cover.uncovered.into_iter().collect(),
+            stored_chunk_ranges: self.stored_chunk_ranges(),
+        }
+    }
+
+    /// Returns whether `shard_config` holds the segment of `segment_index` in the file.
+    fn shard_has_segment(&self, shard_config: &ShardConfig, segment_index: u64) -> bool {
+        let chunk_in_flow = segment_index * PORA_CHUNK_SIZE as u64 + self.tx_start_chunk_in_flow;
+        shard_config.in_range(sector_to_segment(chunk_in_flow) as u64)
+    }
+
+    /// Returns the segment indices in the file that are not downloaded yet and held by the
+    /// local shard, i.e. the segments to request from now on.
+    fn missing_segments(&self) -> BTreeSet<u64> {
+        let segment_size = PORA_CHUNK_SIZE as u64;
+        let local_shard_config = self.store.get_store().get_shard_config();
+        (self.next_chunk / segment_size..(self.goal.index_end + segment_size - 1) / segment_size)
+            .filter(|segment| self.shard_has_segment(&local_shard_config, *segment))
+            .collect()
+    }
+
+    /// Returns the missing segments, and the peers in `states` that cover them according to
+    /// the announced shard configs.
+    fn cover_missing_segments(&self, states: Vec<PeerState>) -> (BTreeSet<u64>, PeerCover<PeerId>) {
+        let missing = self.missing_segments();
+        let availability: Vec<(PeerId, BTreeSet<u64>)> = self
+            .peers
+            .filter_peers(states)
+            .into_iter()
+            .filter_map(|peer_id| {
+                let shard_config = self.peers.shard_config(&peer_id)?;
+                let segments = missing
+                    .iter()
+                    .copied()
+                    .filter(|segment| self.shard_has_segment(&shard_config, *segment))
+                    .collect();
+                Some((peer_id, segments))
+            })
+            .collect();
+
+        let cover = cover_segments(&missing, &availability);
+        (missing, cover)
+    }
+
+    /// Returns the chunk ranges `[start, end)` of the file in the local shard that have been
+    /// downloaded and stored, excluding the unverified segments held in memory.
+    fn stored_chunk_ranges(&self) -> Vec<(u64, u64)> {
+        let segment_size = PORA_CHUNK_SIZE as u64;
+        let stored_end = self
+            .unverified_segments
+            .iter()
+            .map(|segment| segment.start_index)
+            .fold(self.next_chunk, u64::min);
+        let shard_config = self.store.get_store().get_shard_config();
+
+        let mut ranges: Vec<(u64, u64)> = vec![];
+        for segment in
+            self.goal.index_start / segment_size..(stored_end + segment_size - 1) / segment_size
+        {
+            if !self.shard_has_segment(&shard_config, segment) {
+                continue;
+            }
+
+            let start = (segment * segment_size).max(self.goal.index_start);
+            let end = ((segment + 1) * segment_size).min(stored_end);
+            match ranges.last_mut() {
+                Some(last) if last.1 == start => last.1 = end,
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        ranges
+    }
+
+    /// Returns the download progress of file sync, or `None` for chunks sync.
+    pub fn checkpoint(&self) -> Option<SyncCheckpoint> {
+        self.goal.is_all_chunks().then_some(SyncCheckpoint {
+            tx_id: self.tx_id,
+            next_chunk: self.next_chunk,
+        })
+    }
+
     pub fn get_status(&self) -> &SyncState {
         &self.state
     }
@@ -143,6 +397,7 @@ impl SerialSyncController {
             // It's up to client to avoid duplicated chunks sync.
             self.goal = FileSyncGoal::new(self.goal.num_chunks, start, end, false);
             self.next_chunk = start;
+            self.unverified_segments.clear();
         } else if self.goal.is_all_chunks() {
             // retry the failed file sync at break point
             debug!(%self.tx_seq, %self.next_chunk, "Continue to sync failed file");
@@ -150,27 +405,41 @@ impl SerialSyncController {
             // Ignore the failed chunks sync, and change to file sync.
             self.goal = FileSyncGoal::new_file(self.goal.num_chunks);
             self.next_chunk = 0;
+            self.unverified_segments.clear();
         }
 
         self.failures = 0;
         self.state = SyncState::Idle;
+        self.preempted_requests.clear();
         // remove disconnected peers
         self.peers.transition();
     }
 
     /// Find more peers to sync chunks. Return whether `FindFile` pubsub message published,
     fn try_find_peers(&mut self) {
-        let (published, num_new_peers) = if !self.goal.is_all_chunks() {
-            self.publish_find_chunks();
-            (true, 0)
+        let (query, num_new_peers) = if !self.goal.is_all_chunks() {
+            (self.publish_query(), 0)
         } else if self.config.neighbors_only {
-            self.do_publish_find_file();
-            (true, 0)
+            self.ask_file()
         } else {
             self.publish_find_file()
         };
 
-        info!(%self.tx_seq, %published, %num_new_peers, "Finding peers");
+        info!(%self.tx_seq, ?query, %num_new_peers, "Finding peers");
+
+        match query {
+            Query::Queued => {
+                if !matches!(self.state, SyncState::AwaitingQuery { .. }) {
+                    self.state = SyncState::AwaitingQuery {
+                        origin: self.since,
+                        since: Instant::now().into(),
+                    };
+                }
+                return;
+            }
+            Query::Skipped => self.query = None,
+            Query::Published => {}
+        }
 
         self.state = SyncState::FindingPeers {
             origin: self.since,
@@ -178,7 +447,23 @@ impl SerialSyncController {
         };
     }
 
-    fn publish_find_file(&mut self) -> (bool, usize) {
+    /// Publishes the query for peers, unless too many queries are in flight.
+    fn publish_query(&mut self) -> Query {
+        let query_limiter = &self.query_limiter;
+        let query = self.query.get_or_insert_with(|| query_limiter.enqueue());
+        if !query.try_start() {
+            return Query::Queued;
+        }
+
+        if self.goal.is_all_chunks() {
+            self.do_publish_find_file();
+        } else {
+            self.publish_find_chunks();
+        }
+        Query::Published
+    }
+
+    fn publish_find_file(&mut self) -> (Query, usize) {
         // try from cache
         let mut num_new_peers = 0;
 
@@ -200,12 +485,38 @@ impl SerialSyncController {
                 PeerState::Connected,
             ])
         {
-            return (false, num_new_peers);
+            return (Query::Skipped, num_new_peers);
+        }
+
+        (self.publish_query(), num_new_peers)
+    }
+
+    /// Asks the neighbors for the file, unless the peers that answered recently cover all the
+    /// shards.
+    fn ask_file(&mut self) -> (Query, usize) {
+        if !self.availability_cache.is_enabled() {
+            return (self.publish_query(), 0);
+        }
+
+        let mut num_new_peers = 0;
+        for (peer_id, shard_config) in self.availability_cache.get(self.tx_seq) {
+            if self.peers.peer_state(&peer_id).is_none() {
+                self.on_peer_announced(peer_id, shard_config);
+                num_new_peers += 1;
+            }
         }
 
-        self.do_publish_find_file();
+        let hit = self.peers.all_shards_available(vec![
+            PeerState::Found,
+            PeerState::Connecting,
+            PeerState::Connected,
+        ]);
+        self.availability_cache.record_lookup(hit);
+        if hit {
+            return (Query::Skipped, num_new_peers);
+        }
 
-        (true, num_new_peers)
+        (self.publish_query(), num_new_peers)
     }
 
     fn do_publish_find_file(&self) {
@@ -245,6 +556,9 @@ impl SerialSyncController {
 
     /// Dial to peers in `Found` state, so that `Connecting` or `Connected` peers cover
     /// data in all shards.
+    ///
+    /// Peers are dialed only until all shards are covered, so the peers required to cover
+    /// the shards are dialed even beyond `max_active_peers_per_file`.
     fn try_connect(&mut self) {
         let mut num_peers_dialed = 0;
 
@@ -253,7 +567,7 @@ impl SerialSyncController {
             .peers
             .all_shards_available(vec![PeerState::Connecting, PeerState::Connected])
         {
-            let (peer_id, address) = match self.peers.random_peer(PeerState::Found) {
+            let (peer_id, address) = match self.peers.random_peer(PeerState::Found, &mut self.rng) {
                 Some((peer_id, address)) => (peer_id, address),
                 None => {
                     // peer may be disconnected by remote node and need to find peers again
@@ -283,6 +597,28 @@ impl SerialSyncController {
 
     /// Randomly select a peer to sync the next segment.
     fn try_request_next(&mut self) {
+        // stop downloading if the disk space is low
+        if self.disk_watchdog.is_paused() {
+            self.state = SyncState::AwaitingDownload {
+                since: (Instant::now() + self.config.disk_space_check_interval).into(),
+            };
+            return;
+        }
+
+        // wait for the pending segments to be written into db
+        self.write_slot = None;
+        let write_slot = match self.write_buffer.try_acquire() {
+            Some(slot) => slot,
+            None => {
+                debug!(%self.tx_seq, "Write buffer is full, wait to request chunks");
+                self.state = SyncState::AwaitingDownload {
+                    since: (Instant::now() + self.config.peer_next_chunks_request_wait_timeout)
+                        .into(),
+                };
+                return;
+            }
+        };
+
         // limits network bandwidth if configured
         if self.config.max_bandwidth_bytes > 0 {
             let m1 = metrics::SERIAL_SYNC_SEGMENT_BANDWIDTH.rate1() as u64;
@@ -294,11 +630,34 @@ impl SerialSyncController {
             }
         }
 
-        // request next chunk array
-        let from_chunk = self.next_chunk;
+        // request the next missing segment, unless no connected peer holds it
+        let (missing, cover) = self.cover_missing_segments(vec![PeerState::Connected]);
+        let from_chunk = match missing.first() {
+            Some(segment) if cover.uncovered.contains(segment) => {
+                warn!(%self.tx_seq, %segment, "No connected peers hold the next segment");
+                self.state = SyncState::Idle;
+                return;
+            }
+            Some(segment) => (segment * PORA_CHUNK_SIZE as u64).max(self.next_chunk),
+            None => self.next_chunk,
+        };
         let to_chunk = std::cmp::min(from_chunk + PORA_CHUNK_SIZE as u64, self.goal.index_end);
-        let request_id =
-            network::RequestId::Sync(Instant::now(), RequestId::SerialSync { tx_id: self.tx_id });
+
+        // limits the bandwidth of this file if configured
+        let bytes = (to_chunk - from_chunk) * CHUNK_SIZE as u64;
+        if let Some(ready_at) = self.bandwidth.poll_request(bytes, Instant::now()) {
+            metrics::SERIAL_SYNC_FILE_BANDWIDTH_THROTTLED.inc(1);
+            self.state = SyncState::AwaitingDownload {
+                since: ready_at.into(),
+            };
+            return;
+        }
+
+        // request next chunk array
+        let request_id = network::RequestId::Sync(
+            std::time::Instant::now(),
+            RequestId::SerialSync { tx_id: self.tx_id },
+        );
         // TODO: It's possible that we read it while `nex_tx_seq - 1` is still being committed.
         // We can wait for its commitment, but this will slow down this state machine.
         // Or we can use `next_tx_seq - 2`, but for a restarted node without receiving new
@@ -311,6 +670,11 @@ impl SerialSyncController {
             merkle_tx_seq: committed_tx_seq,
         };
 
+        // rotate reserved peers in for the failed active peers
+        let peers = &self.peers;
+        self.active_peers
+            .retain(|peer_id| peers.peer_state(peer_id) == Some(PeerState::Connected));
+
         // select a random peer
         let peer_id = match self.select_peer_for_request(&request) {
             Some(peer_id) => peer_id,
@@ -320,6 +684,7 @@ impl SerialSyncController {
                 return;
             }
         };
+        self.active_peers.insert(peer_id);
 
         self.ctx.send(NetworkMessage::SendRequest {
             peer_id,
@@ -328,6 +693,7 @@ impl SerialSyncController {
         });
 
         info!(%self.tx_seq, %from_chunk, %to_chunk, %peer_id, "Sent request to get chunks");
+        self.write_slot = Some(write_slot);
 
         self.state = SyncState::Downloading {
             peer_id,
@@ -337,6 +703,17 @@ impl SerialSyncController {
         };
     }
 
+    /// Returns whether the chunks are the data of this file, see
+    /// `ChunkArrayWithProof::is_file_data`.
+    fn is_file_data(&self, response: &ChunkArrayWithProof) -> anyhow::Result<bool> {
+        let tx = self
+            .store
+            .get_store()
+            .get_tx_by_seq_number(self.tx_seq)?
+            .ok_or_else(|| anyhow!("tx missing"))?;
+        response.is_file_data(&tx)
+    }
+
     fn ban_peer(&mut self, peer_id: PeerId, reason: &'static str) {
         debug!(%self.tx_seq, %peer_id, %reason, "Ban peer");
         self.ctx.ban_peer(peer_id, reason);
@@ -365,13 +742,74 @@ impl SerialSyncController {
     }
 
     /// Triggered when any peer (TCP connected) announced file via RPC message.
+    ///
+    /// The peer is held in reserve as `Found` if the file is already connected to
+    /// `max_active_peers_per_file` peers.
     pub fn on_peer_announced(&mut self, peer_id: PeerId, shard_config: ShardConfig) {
         self.peers
             .add_new_peer_with_config(peer_id, Multiaddr::empty(), shard_config);
+        if self.peers.peer_state(&peer_id) == Some(PeerState::Found)
+            && self.is_connection_limit_reached()
+        {
+            debug!(%self.tx_seq, %peer_id, "Announced peer held in reserve");
+            return;
+        }
         self.peers
             .update_state_force(&peer_id, PeerState::Connected);
     }
 
+    /// Returns whether the `Connecting` or `Connected` peers reach `max_active_peers_per_file`,
+    /// in which case the other found peers are held in reserve until any of them fails.
+    fn is_connection_limit_reached(&self) -> bool {
+        let max_peers = self.config.max_active_peers_per_file;
+        max_peers > 0
+            && self
+                .peers
+                .count(&[PeerState::Connecting, PeerState::Connected])
+                >= max_peers
+    }
+
+    /// Returns whether the found peer holds any segment not downloaded yet, so that it's worth
+    /// dialing at once, e.g. once the peer advertised the file, unless the file is connected to
+    /// `max_active_peers_per_file` peers already.
+    pub fn needs_found_peer(&self, peer_id: &PeerId) -> bool {
+        if self.is_completed_or_failed()
+            || self.peers.peer_state(peer_id) != Some(PeerState::Found)
+            || self.is_connection_limit_reached()
+        {
+            return false;
+        }
+
+        let shard_config = match self.peers.shard_config(peer_id) {
+            Some(shard_config) => shard_config,
+            None => return false,
+        };
+        self.missing_segments()
+            .into_iter()
+            .any(|segment| self.shard_has_segment(&shard_config, segment))
+    }
+
+    /// Dials the found peer at once instead of waiting for the next round to connect peers.
+    pub fn dial_found_peer(&mut self, peer_id: PeerId) {
+        if self.is_connection_limit_reached() {
+            return;
+        }
+
+        let address = match self.peers.peer_addr(&peer_id) {
+            Some(address) => address,
+            None => return,
+        };
+
+        if let Some(true) =
+            self.peers
+                .update_state(&peer_id, PeerState::Found, PeerState::Connecting)
+        {
+            debug!(%self.tx_seq, %peer_id, %address, "Dialing advertised peer");
+            self.ctx.send(NetworkMessage::DialPeer { address, peer_id });
+            metrics::SERIAL_SYNC_EAGER_DIALS.inc(1);
+        }
+    }
+
     pub fn on_dial_failed(&mut self, peer_id: PeerId, err: &DialError) {
         match err {
             DialError::ConnectionLimit(_) => {
@@ -407,6 +845,17 @@ impl SerialSyncController {
         }
     }
 
+    /// Requests the next segment at once if waiting to download, e.g. once the paused
+    /// downloads are resumed, instead of waiting for the next retry.
+    pub fn on_download_resumed(&mut self) {
+        if matches!(self.state, SyncState::AwaitingDownload { .. }) {
+            self.state = SyncState::AwaitingDownload {
+                since: Instant::now().into(),
+            };
+            self.transition();
+        }
+    }
+
     pub fn on_peer_disconnected(&mut self, peer_id: PeerId) {
         match self
             .peers
@@ -421,9 +870,26 @@ impl SerialSyncController {
     }
 
     /// Handle the case that got an unexpected response:
-    /// 1. not in `Downloading` sync state.
-    /// 2. from unexpected peer.
-    fn handle_on_response_mismatch(&self, from_peer_id: PeerId) -> bool {
+    /// 1. the late response of a preempted request.
+    /// 2. not in `Downloading` sync state.
+    /// 3. from unexpected peer.
+    fn handle_on_response_mismatch(&mut self, from_peer_id: PeerId, start_index: u64) -> bool {
+        if self
+            .preempted_requests
+            .get(&from_peer_id)
+            .is_some_and(|(from_chunk, _)| *from_chunk == start_index)
+        {
+            debug!(%self.tx_seq, %from_peer_id, %start_index, "Drop the late response of preempted request");
+            self.preempted_requests.remove(&from_peer_id);
+            return true;
+        }
+
+        self.handle_on_state_mismatch(from_peer_id)
+    }
+
+    /// Returns `true` if not downloading from `from_peer_id`, in which case the peer is
+    /// reported.
+    fn handle_on_state_mismatch(&mut self, from_peer_id: PeerId) -> bool {
         match self.state {
             SyncState::Downloading { peer_id, .. } => {
                 if from_peer_id == peer_id {
@@ -455,11 +921,20 @@ impl SerialSyncController {
 
     pub async fn on_response(&mut self, from_peer_id: PeerId, response: ChunkArrayWithProof) {
         metrics::SERIAL_SYNC_SEGMENT_BANDWIDTH.mark(response.ssz_bytes_len());
-
-        if self.handle_on_response_mismatch(from_peer_id) {
+        let now = Instant::now();
+        self.bandwidth
+            .on_received(response.chunks.data.len() as u64, now);
+        self.download_rate
+            .get_or_insert_with(|| metrics::file_download_rate(self.tx_seq))
+            .update(self.bandwidth.rate(now) as usize);
+
+        if self.handle_on_response_mismatch(from_peer_id, response.chunks.start_index) {
             return;
         }
 
+        // the chunks are written through the slot, which is released otherwise
+        let write_slot = self.write_slot.take();
+
         let (from_chunk, to_chunk, since) = match self.state {
             SyncState::Downloading {
                 from_chunk,
@@ -497,11 +972,43 @@ impl SerialSyncController {
             return;
         }
 
-        // validate Merkle proofs
-        let validation_result = self
-            .store
-            .get_store()
-            .validate_and_insert_range_proof(self.tx_seq, &response);
+        // validate Merkle proofs, except for the segments from trusted bootstrap peers
+        let shard_config = self.store.get_store().get_shard_config();
+        let trusted = self.ctx.is_trusted(&from_peer_id)
+            && !self.verify_all
+            && self.goal.is_all_chunks()
+            && shard_config.num_shard == 1
+            && self.goal.num_chunks * CHUNK_SIZE as u64 <= MAX_UNVERIFIED_FILE_BYTES;
+        let validation_result = if trusted {
+            debug!(%self.tx_seq, %from_peer_id, "Accept segment from trusted peer without verification");
+            self.unverified_peers.insert(from_peer_id);
+            Ok(true)
+        } else {
+            match self.is_file_data(&response) {
+                Ok(true) => self
+                    .store
+                    .get_store()
+                    .validate_and_insert_range_proof(self.tx_seq, &response),
+                Ok(false) => {
+                    warn!(%self.tx_seq, %from_peer_id, %from_chunk, %to_chunk, "Got chunks that are not the data of the file");
+                    metrics::SERIAL_SYNC_WRONG_FILE_DATA.inc(1);
+                    self.failed_segments += 1;
+                    self.ctx.report_peer(
+                        from_peer_id,
+                        PeerAction::WrongFileData,
+                        "Chunks not the data of the file",
+                    );
+                    self.peers.update_state(
+                        &from_peer_id,
+                        PeerState::Connected,
+                        PeerState::Disconnecting,
+                    );
+                    self.state = SyncState::Idle;
+                    return;
+                }
+                Err(err) => Err(err),
+            }
+        };
 
         match validation_result {
             Ok(true) => {}
@@ -517,6 +1024,8 @@ impl SerialSyncController {
             Err(err) => {
                 warn!(%err, %self.tx_seq, "Failed to validate chunks response");
                 metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+                // the banned peer will not be selected to re-request the segment
+                self.failed_segments += 1;
                 self.ban_peer(from_peer_id, "Chunk array validation failed");
                 self.state = SyncState::Idle;
                 return;
@@ -525,20 +1034,37 @@ impl SerialSyncController {
 
         self.failures = 0;
 
-        metrics::SERIAL_SYNC_SEGMENT_LATENCY.update_since(since.0);
+        metrics::SERIAL_SYNC_SEGMENT_LATENCY.update_since(since.0.into_std());
 
-        let shard_config = self.store.get_store().get_shard_config();
         let next_chunk = segment_to_sector(shard_config.next_segment_index(
             sector_to_segment(from_chunk),
             sector_to_segment(self.tx_start_chunk_in_flow),
         ));
-        // store in db
-        match self
-            .store
-            .put_chunks_with_tx_hash(self.tx_id.seq, self.tx_id.hash, response.chunks, None)
-            .await
-        {
-            Ok(true) => self.next_chunk = next_chunk as u64,
+        // store in db, or hold the unverified segment until the whole file is downloaded
+        let stored = if trusted {
+            self.unverified_segments.push(response.chunks);
+            Ok(true)
+        } else {
+            let segment = SegmentWrite {
+                tx_id: self.tx_id,
+                chunks: response.chunks,
+            };
+            match write_slot {
+                Some(slot) => slot.write(&self.store, segment).await,
+                None => write_segment(&self.store, segment).await,
+            }
+        };
+        match stored {
+            Ok(true) => {
+                self.next_chunk = next_chunk as u64;
+                if !trusted {
+                    self.verified_segments += 1;
+                }
+                network::metrics::SERVED_SEGMENTS_PEERS.mark(from_peer_id);
+                let stats = self.delivery_stats.entry(from_peer_id).or_default();
+                stats.bytes += data_len as u64;
+                stats.elapsed += since.elapsed();
+            }
             Ok(false) => {
                 warn!(%self.tx_seq, ?self.tx_id, "Transaction reverted while storing chunks");
                 metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
@@ -566,7 +1092,11 @@ impl SerialSyncController {
         // completed to download chunks
         if !self.goal.is_all_chunks() {
             self.state = SyncState::Completed;
-            metrics::SERIAL_SYNC_CHUNKS_COMPLETED.update_since(self.since.0);
+            metrics::SERIAL_SYNC_CHUNKS_COMPLETED.update_since(self.since.0.into_std());
+            return;
+        }
+
+        if !self.write_unverified_segments().await {
             return;
         }
 
@@ -579,7 +1109,7 @@ impl SerialSyncController {
             Ok(true) => {
                 info!(%self.tx_seq, "Succeeded to finalize file");
                 self.state = SyncState::Completed;
-                metrics::SERIAL_SYNC_FILE_COMPLETED.update_since(self.since.0);
+                metrics::SERIAL_SYNC_FILE_COMPLETED.update_since(self.since.0.into_std());
                 // notify neighbor nodes about new file completed to sync
                 self.ctx
                     .send(NetworkMessage::AnnounceLocalFile { tx_id: self.tx_id });
@@ -601,8 +1131,120 @@ impl SerialSyncController {
         }
     }
 
+    /// Verifies the segments accepted from trusted peers with a single check of the file root,
+    /// and writes them into db if matched. Returns `false` if the file sync failed.
+    ///
+    /// On a mismatch, the segments are dropped and downloaded again with proof verification.
+    async fn write_unverified_segments(&mut self) -> bool {
+        if self.unverified_segments.is_empty() {
+            return true;
+        }
+
+        let mut segments = std::mem::take(&mut self.unverified_segments);
+        segments.sort_by_key(|segment| segment.start_index);
+        let computed_root = self.compute_file_root(&segments).await;
+        let tx = self.store.get_tx_by_seq_number(self.tx_seq).await;
+        match (computed_root, tx) {
+            (Ok(Some(root)), Ok(Some(tx))) if root == tx.data_merkle_root => {
+                info!(%self.tx_seq, peers = ?self.unverified_peers, "Verified segments from trusted peers");
+                self.unverified_peers.clear();
+            }
+            (Ok(_), Ok(_)) => {
+                error!(%self.tx_seq, peers = ?self.unverified_peers, "Segments from trusted peers mismatch the file root");
+                metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+                self.failed_segments += segments.len() as u64;
+                self.next_chunk = segments[0].start_index;
+                self.verify_all = true;
+                self.state = SyncState::Failed {
+                    reason: FailureReason::TrustedDataMismatch,
+                };
+                return false;
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                error!(%err, %self.tx_seq, "Unexpected DB error while verifying the file root");
+                metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+                self.next_chunk = segments[0].start_index;
+                self.state = SyncState::Failed {
+                    reason: FailureReason::DBError(err.to_string()),
+                };
+                return false;
+            }
+        }
+
+        for segment in segments {
+            match self
+                .store
+                .put_chunks_with_tx_hash(self.tx_id.seq, self.tx_id.hash, segment, None)
+                .await
+            {
+                Ok(true) => self.verified_segments += 1,
+                Ok(false) => {
+                    warn!(%self.tx_seq, ?self.tx_id, "Transaction reverted while storing chunks");
+                    metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+                    self.state = SyncState::Failed {
+                        reason: FailureReason::TxReverted(self.tx_id),
+                    };
+                    return false;
+                }
+                Err(err) => {
+                    error!(%err, %self.tx_seq, "Unexpected DB error while storing chunks");
+                    metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+                    self.state = SyncState::Failed {
+                        reason: FailureReason::DBError(err.to_string()),
+                    };
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Computes the file root with the unverified `segments` in the order of chunk index, and
+    /// the other chunks stored in db. Returns `None` if any chunk is missing.
+    async fn compute_file_root(&self, segments: &[ChunkArray]) -> anyhow::Result<Option<DataRoot>> {
+        let num_chunks = self.goal.num_chunks;
+        let mut leaves = Vec::with_capacity(num_chunks as usize);
+        let mut segments = segments.iter().peekable();
+        let mut index = 0;
+        while index < num_chunks {
+            if let Some(segment) = segments.next_if(|segment| segment.start_index == index) {
+                leaves.extend(data_to_merkle_leaves_h256(&segment.data)?);
+                index += (segment.data.len() / CHUNK_SIZE) as u64;
+                continue;
+            }
+
+            let end_index = segments
+                .peek()
+                .map_or(num_chunks, |segment| segment.start_index)
+                .min(index + PORA_CHUNK_SIZE as u64);
+            match self
+                .store
+                .get_chunks_by_tx_and_index_range(self.tx_seq, index as usize, end_index as usize)
+                .await?
+            {
+                Some(chunks) => leaves.extend(data_to_merkle_leaves_h256(&chunks.data)?),
+                None => return Ok(None),
+            }
+            index = end_index;
+        }
+
+        let leaves: Vec<[u8; 32]> = leaves.into_iter().map(|leaf| leaf.0).collect();
+        Ok(Some(FileMerkleTree::new(leaves).root().into()))
+    }
+
     pub fn on_request_failed(&mut self, peer_id: PeerId) {
-        if self.handle_on_response_mismatch(peer_id) {
+        // The failure does not tell which request failed, so the preempted request of the peer
+        // is forgotten, and the failure is dropped without penalty unless the peer is
+        // downloading again.
+        if self.preempted_requests.remove(&peer_id).is_some()
+            && !matches!(self.state, SyncState::Downloading { peer_id: id, .. } if id == peer_id)
+        {
+            debug!(%self.tx_seq, %peer_id, "Drop the failure of preempted request");
+            return;
+        }
+
+        if self.handle_on_state_mismatch(peer_id) {
             return;
         }
 
@@ -628,21 +1270,69 @@ impl SerialSyncController {
 
     /// Randomly select a `Connected` peer to sync chunks.
     fn select_peer_for_request(&self, request: &GetChunksRequest) -> Option<PeerId> {
-        let segment_index = sector_to_segment(request.index_start + self.tx_start_chunk_in_flow);
-        let mut peers = self.peers.filter_peers(vec![PeerState::Connected]);
+        let start_segment =
+            sector_to_segment(request.index_start + self.tx_start_chunk_in_flow) as u64;
+        let end_segment =
+            sector_to_segment(self.goal.index_end - 1 + self.tx_start_chunk_in_flow) as u64 + 1;
 
-        peers.retain(|peer_id| match self.peers.shard_config(peer_id) {
-            Some(v) => v.in_range(segment_index as u64),
-            None => false,
-        });
+        let candidates: Vec<CandidatePeer> = self
+            .peers
+            .filter_peers(vec![PeerState::Connected])
+            .into_iter()
+            .filter_map(|peer_id| {
+                Some(CandidatePeer {
+                    peer_id,
+                    shard_config: self.peers.shard_config(&peer_id)?,
+                    delivery_rate: self.delivery_stats.get(&peer_id).map(|stats| stats.rate()),
+                    preempted: self.preempted_requests.contains_key(&peer_id),
+                    active: self.active_peers.contains(&peer_id),
+                })
+            })
+            .collect();
+
+        let selected = self.peer_selector.select(
+            start_segment..end_segment.max(start_segment + 1),
+            &candidates,
+            self.config.max_active_peers_per_file,
+        );
+
+        // do not trust the strategy to request a peer without the segment
+        selected.into_iter().find(|peer_id| {
+            candidates
+                .iter()
+                .any(|peer| peer.peer_id == *peer_id && peer.has_segment(start_segment))
+        })
+    }
+
+    /// Returns true if the request to `peer_id` takes much longer than the expected time
+    /// at the median delivery rate of other peers, and another peer is available.
+    fn is_slow_peer(&self, peer_id: &PeerId, since: InstantWrapper, num_chunks: u64) -> bool {
+        let ratio = self.config.slow_peer_preemption_ratio;
+        if ratio <= 0.0 {
+            return false;
+        }
+
+        let mut rates: Vec<f64> = self
+            .delivery_stats
+            .iter()
+            .filter(|(id, _)| *id != peer_id)
+            .map(|(_, stats)| stats.rate())
+            .collect();
+        if rates.is_empty() {
+            return false;
+        }
+        rates.sort_by(|a, b| a.partial_cmp(b).expect("rate is not NaN"));
+        let median_rate = rates[rates.len() / 2];
 
-        let len = peers.len();
-        if len == 0 {
-            return None;
+        let expected = num_chunks as f64 * CHUNK_SIZE as f64 / median_rate;
+        if since.elapsed().as_secs_f64() <= expected * ratio {
+            return false;
         }
 
-        let index = rand::thread_rng().gen_range(0..len);
-        Some(peers[index])
+        self.peers
+            .filter_peers(vec![PeerState::Connected])
+            .iter()
+            .any(|id| id != peer_id && !self.preempted_requests.contains_key(id))
     }
 
     pub fn transition(&mut self) {
@@ -653,6 +1343,24 @@ impl SerialSyncController {
         // update peer connection states
         self.peers.transition();
 
+        // release the slot of timeout or failed request
+        if !matches!(self.state, SyncState::Downloading { .. }) {
+            self.write_slot = None;
+        }
+
+        // no more late response of the timeout preempted requests
+        let timeout = self.config.peer_chunks_download_timeout;
+        self.preempted_requests
+            .retain(|_, (_, since)| since.elapsed() < timeout);
+
+        // release the query of which the peers are found or timeout
+        if !matches!(
+            self.state,
+            SyncState::AwaitingQuery { .. } | SyncState::FindingPeers { .. }
+        ) {
+            self.query = None;
+        }
+
         let mut completed = false;
 
         while !completed {
@@ -668,15 +1376,32 @@ impl SerialSyncController {
                     }
                 }
 
+                SyncState::AwaitingQuery { .. } => {
+                    if self
+                        .peers
+                        .all_shards_available(vec![Found, Connecting, Connected])
+                    {
+                        self.query = None;
+                        self.state = SyncState::FoundPeers;
+                    } else if self.query.as_mut().map_or(true, QueryTicket::try_start) {
+                        self.try_find_peers();
+                    } else {
+                        completed = true;
+                    }
+                }
+
                 SyncState::FindingPeers { since, .. } => {
                     if self
                         .peers
                         .all_shards_available(vec![Found, Connecting, Connected])
                     {
+                        self.query = None;
                         self.state = SyncState::FoundPeers;
                     } else {
                         // FindFile timeout
                         if since.elapsed() >= self.config.peer_find_timeout {
+                            // the query is answered or lost, and a new one queues behind others
+                            self.query = None;
                             if self.goal.is_all_chunks() && self.config.neighbors_only {
                                 self.state = SyncState::Failed {
                                     reason: FailureReason::TimeoutFindFile,
@@ -737,7 +1462,12 @@ impl SerialSyncController {
                     }
                 }
 
-                SyncState::Downloading { peer_id, since, .. } => {
+                SyncState::Downloading {
+                    peer_id,
+                    from_chunk,
+                    to_chunk,
+                    since,
+                } => {
                     if !matches!(self.peers.peer_state(&peer_id), Some(PeerState::Connected)) {
                         // e.g. peer disconnected by remote node
                         debug!(%self.tx_seq, "No peer to continue downloading and try to find other peers to download");
@@ -745,6 +1475,13 @@ impl SerialSyncController {
                     } else if since.elapsed() >= self.config.peer_chunks_download_timeout {
                         metrics::SERIAL_SYNC_SEGMENT_TIMEOUT.inc(1);
                         self.handle_response_failure(peer_id, "RPC timeout");
+                    } else if self.is_slow_peer(&peer_id, since, to_chunk - from_chunk) {
+                        info!(%self.tx_seq, %peer_id, %from_chunk, %to_chunk, "Preempt the request to slow peer");
+                        metrics::SERIAL_SYNC_SEGMENT_PREEMPTED.inc(1);
+                        self.preempted_requests.insert(peer_id, (from_chunk, since));
+                        self.state = SyncState::AwaitingDownload {
+                            since: Instant::now().into(),
+                        };
                     } else {
                         completed = true;
                     }
@@ -758,9 +1495,17 @@ impl SerialSyncController {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl Drop for SerialSyncController {
+    fn drop(&mut self) {
+        if let Some(download_rate) = &self.download_rate {
+            download_rate.update(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
     use crate::test_util::create_2_store;
     use crate::test_util::tests::create_file_location_cache;
     use libp2p::identity;
@@ -826,6 +1571,102 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_find_peers_from_availability_cache() {
+        let runtime = TestRuntime::default();
+        let cache = AvailabilityCache::new(Duration::from_secs(60), 0);
+        let create = |cache: &AvailabilityCache| {
+            let (controller, network_recv) =
+                create_default_controller(runtime.task_executor.clone(), None);
+            let mut controller = controller.with_availability_cache(cache.clone());
+            controller.config.neighbors_only = true;
+            (controller, network_recv)
+        };
+        let is_ask_file = |network_recv: &mut NetworkReceiver| {
+            matches!(
+                network_recv.try_recv(),
+                Ok(NetworkMessage::Publish { messages })
+                    if matches!(messages[..], [PubsubMessage::AskFile(_)])
+            )
+        };
+
+        // ask neighbors if not cached
+        let (mut controller, mut network_recv) = create(&cache);
+        controller.try_find_peers();
+        assert!(is_ask_file(&mut network_recv));
+
+        // the recent answer suppresses the query of the same file
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        cache.insert(peer_id, controller.tx_seq, Default::default());
+        for _ in 0..3 {
+            let (mut controller, mut network_recv) = create(&cache);
+            controller.try_find_peers();
+            assert!(network_recv.try_recv().is_err());
+            assert_eq!(
+                controller.peers.peer_state(&peer_id),
+                Some(PeerState::Connected)
+            );
+        }
+        assert_eq!(cache.hit_rate_percent(), 75);
+
+        // ask again once the answer is invalidated
+        cache.invalidate_peer(&peer_id);
+        let (mut controller, mut network_recv) = create(&cache);
+        controller.try_find_peers();
+        assert!(is_ask_file(&mut network_recv));
+    }
+
+    #[tokio::test]
+    async fn test_find_peers_in_flight_limit() {
+        let runtime = TestRuntime::default();
+        let query_limiter = QueryLimiter::new(2);
+        let mut controllers: Vec<_> = (0..6)
+            .map(|_| {
+                let (controller, network_recv) =
+                    create_default_controller(runtime.task_executor.clone(), None);
+                let mut controller = controller.with_query_limiter(query_limiter.clone());
+                controller.config.neighbors_only = true;
+                (controller, network_recv)
+            })
+            .collect();
+        let published = |controllers: &mut Vec<(SerialSyncController, NetworkReceiver)>| {
+            controllers
+                .iter_mut()
+                .map(|(_, network_recv)| network_recv.try_recv().is_ok())
+                .collect::<Vec<_>>()
+        };
+
+        // burst of scheduling ticks
+        for _ in 0..10 {
+            for (controller, _) in controllers.iter_mut() {
+                controller.transition();
+                assert!(query_limiter.in_flight() <= 2);
+            }
+        }
+        assert_eq!(query_limiter.in_flight(), 2);
+        assert_eq!(query_limiter.queued(), 4);
+        assert_eq!(
+            published(&mut controllers),
+            [true, true, false, false, false, false]
+        );
+        assert!(controllers[2..].iter().all(|(controller, _)| matches!(
+            controller.get_status(),
+            SyncState::AwaitingQuery { .. }
+        )));
+
+        // the earliest queued query is published once a query is released
+        controllers.remove(0);
+        for (controller, _) in controllers.iter_mut() {
+            controller.transition();
+        }
+        assert_eq!(query_limiter.in_flight(), 2);
+        assert_eq!(query_limiter.queued(), 3);
+        assert_eq!(
+            published(&mut controllers),
+            [false, true, false, false, false]
+        );
+    }
+
     #[tokio::test]
     async fn test_find_peers_not_in_file_cache() {
         let runtime = TestRuntime::default();
@@ -902,6 +1743,54 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_dial_found_peer() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+        controller.state = SyncState::Downloading {
+            peer_id: identity::Keypair::generate_ed25519().public().to_peer_id(),
+            from_chunk: 0,
+            to_chunk: 123,
+            since: Instant::now().into(),
+        };
+
+        let new_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        assert!(!controller.needs_found_peer(&new_peer_id));
+
+        // the advertised peer is dialed at once while downloading
+        controller.peers.add_new_peer(new_peer_id, addr.clone());
+        assert!(controller.needs_found_peer(&new_peer_id));
+        controller.dial_found_peer(new_peer_id);
+        match network_recv.try_recv() {
+            Ok(NetworkMessage::DialPeer { address, peer_id }) => {
+                assert_eq!(address, addr);
+                assert_eq!(peer_id, new_peer_id);
+            }
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+        assert_eq!(
+            controller.peers.peer_state(&new_peer_id),
+            Some(PeerState::Connecting)
+        );
+        assert!(matches!(
+            controller.get_status(),
+            SyncState::Downloading { .. }
+        ));
+
+        // not dialed again
+        assert!(!controller.needs_found_peer(&new_peer_id));
+        controller.dial_found_peer(new_peer_id);
+        assert!(network_recv.try_recv().is_err());
+
+        controller.on_peer_connected(new_peer_id);
+        assert_eq!(
+            controller.peers.peer_state(&new_peer_id),
+            Some(PeerState::Connected)
+        );
+    }
+
     #[tokio::test]
     async fn test_request_chunks() {
         let runtime = TestRuntime::default();
@@ -965,6 +1854,355 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_request_missing_segments() {
+        let runtime = TestRuntime::default();
+        let store = Arc::new(LogManager::memorydb(LogConfig::default()).unwrap());
+        let tx_id = TxID {
+            seq: 0,
+            hash: H256::random(),
+        };
+        // 3 segments
+        let (mut controller, mut network_recv) =
+            create_controller(runtime.task_executor.clone(), None, store, tx_id, 3 * 1024);
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        let half = |shard_id| ShardConfig {
+            num_shard: 2,
+            shard_id,
+        };
+        let odd_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        controller
+            .peers
+            .add_new_peer_with_config(odd_peer_id, addr.clone(), half(1));
+        controller
+            .peers
+            .update_state_force(&odd_peer_id, PeerState::Connected);
+
+        // the next segment is not requested if no connected peer holds it
+        controller.try_request_next();
+        assert_eq!(controller.state, SyncState::Idle);
+        assert!(network_recv.try_recv().is_err());
+        let coverage = controller.get_segment_coverage();
+        assert_eq!(coverage.num_missing, 3);
+        assert_eq!(coverage.uncovered_segments, vec![0, 2]);
+
+        let even_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        controller
+            .peers
+            .add_new_peer_with_config(even_peer_id, addr, half(0));
+        controller
+            .peers
+            .update_state_force(&even_peer_id, PeerState::Connected);
+        assert!(controller
+            .get_segment_coverage()
+            .uncovered_segments
+            .is_empty());
+
+        for (next_chunk, expected_peer_id) in [(0, even_peer_id), (1024, odd_peer_id)] {
+            controller.next_chunk = next_chunk;
+            controller.state = SyncState::Idle;
+            controller.try_request_next();
+            match network_recv.try_recv() {
+                Ok(NetworkMessage::SendRequest {
+                    peer_id,
+                    request: Request::GetChunks(request),
+                    ..
+                }) => {
+                    assert_eq!(peer_id, expected_peer_id);
+                    assert_eq!(request.index_start, next_chunk);
+                    assert_eq!(request.index_end, next_chunk + 1024);
+                }
+                _ => panic!("Not expected message: NetworkMessage::SendRequest"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_chunks_write_buffer_full() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+        controller.write_buffer =
+            WriteBuffer::spawn(1, controller.store.clone(), &runtime.task_executor);
+
+        let new_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        controller.peers.add_new_peer(new_peer_id, addr);
+        controller
+            .peers
+            .update_state_force(&new_peer_id, PeerState::Connected);
+
+        // a slow writer of another file holds the only slot
+        let slow_write = controller.write_buffer.try_acquire().unwrap();
+        controller.try_request_next();
+        assert!(matches!(
+            controller.state,
+            SyncState::AwaitingDownload { .. }
+        ));
+        assert!(network_recv.try_recv().is_err());
+
+        // resume once the write completes
+        drop(slow_write);
+        controller.try_request_next();
+        assert!(matches!(controller.state, SyncState::Downloading { .. }));
+        assert!(matches!(
+            network_recv.try_recv(),
+            Ok(NetworkMessage::SendRequest { .. })
+        ));
+        assert_eq!(controller.write_buffer.fill_level(), 1);
+
+        // release the slot if the sync failed
+        controller.state = SyncState::Failed {
+            reason: FailureReason::TimeoutFindFile,
+        };
+        controller.transition();
+        assert_eq!(controller.write_buffer.fill_level(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_preempt_slow_peer() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+        controller.config.slow_peer_preemption_ratio = 2.0;
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        let slow_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let fast_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        for peer_id in [slow_peer_id, fast_peer_id] {
+            controller.peers.add_new_peer(peer_id, addr.clone());
+            controller
+                .peers
+                .update_state_force(&peer_id, PeerState::Connected);
+        }
+        controller.delivery_stats.insert(
+            fast_peer_id,
+            DeliveryStats {
+                bytes: 1024 * 1024,
+                elapsed: Duration::from_secs(1),
+            },
+        );
+
+        // within the expected time
+        controller.state = SyncState::Downloading {
+            peer_id: slow_peer_id,
+            from_chunk: 0,
+            to_chunk: 123,
+            since: Instant::now().into(),
+        };
+        controller.transition();
+        assert!(matches!(
+            controller.state,
+            SyncState::Downloading { peer_id, .. } if peer_id == slow_peer_id
+        ));
+
+        // reassign to the fast peer
+        controller.state = SyncState::Downloading {
+            peer_id: slow_peer_id,
+            from_chunk: 0,
+            to_chunk: 123,
+            since: (Instant::now() - Duration::from_secs(1)).into(),
+        };
+        controller.transition();
+        assert!(matches!(
+            controller.state,
+            SyncState::Downloading { peer_id, .. } if peer_id == fast_peer_id
+        ));
+        match network_recv.try_recv() {
+            Ok(NetworkMessage::SendRequest { peer_id, .. }) => assert_eq!(peer_id, fast_peer_id),
+            _ => panic!("Not expected message: NetworkMessage::SendRequest"),
+        }
+
+        // the late response of the preempted request is dropped without penalty
+        assert!(controller.preempted_requests.contains_key(&slow_peer_id));
+        assert!(controller.handle_on_response_mismatch(slow_peer_id, 0));
+        assert!(network_recv.try_recv().is_err());
+        // and only once
+        assert!(!controller.preempted_requests.contains_key(&slow_peer_id));
+        assert!(controller.handle_on_response_mismatch(slow_peer_id, 0));
+        assert!(matches!(
+            network_recv.try_recv(),
+            Ok(NetworkMessage::ReportPeer { peer_id, .. }) if peer_id == slow_peer_id
+        ));
+
+        // the failure of a preempted request is dropped without penalty as well
+        controller
+            .preempted_requests
+            .insert(slow_peer_id, (0, Instant::now().into()));
+        let failures = controller.failures;
+        controller.on_request_failed(slow_peer_id);
+        assert!(controller.preempted_requests.is_empty());
+        assert_eq!(controller.failures, failures);
+        assert!(network_recv.try_recv().is_err());
+
+        // timeout preempted requests are forgotten, and all of them on reset
+        let timeout = controller.config.peer_chunks_download_timeout;
+        controller
+            .preempted_requests
+            .insert(slow_peer_id, (0, (Instant::now() - timeout).into()));
+        controller.transition();
+        assert!(controller.preempted_requests.is_empty());
+        controller
+            .preempted_requests
+            .insert(slow_peer_id, (0, Instant::now().into()));
+        controller.reset(None);
+        assert!(controller.preempted_requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_active_peers_per_file() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+        controller.config.max_active_peers_per_file = 2;
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        let peer_ids: Vec<PeerId> = (0..4)
+            .map(|_| identity::Keypair::generate_ed25519().public().to_peer_id())
+            .collect();
+        for peer_id in peer_ids.iter() {
+            controller.peers.add_new_peer(*peer_id, addr.clone());
+            controller
+                .peers
+                .update_state_force(peer_id, PeerState::Connected);
+        }
+
+        let mut request_next = |controller: &mut SerialSyncController| {
+            controller.state = SyncState::Idle;
+            controller.try_request_next();
+            match network_recv.try_recv() {
+                Ok(NetworkMessage::SendRequest { peer_id, .. }) => peer_id,
+                _ => panic!("Not expected message: NetworkMessage::SendRequest"),
+            }
+        };
+
+        let mut requested = HashSet::new();
+        for _ in 0..50 {
+            requested.insert(request_next(&mut controller));
+        }
+        assert_eq!(requested.len(), 2);
+        assert_eq!(controller.get_sync_info().active_peers, 2);
+
+        // a reserved peer replaces the failed active peer
+        let failed_peer_id = *requested.iter().next().unwrap();
+        controller
+            .peers
+            .update_state_force(&failed_peer_id, PeerState::Disconnected);
+        let mut requested = HashSet::new();
+        for _ in 0..50 {
+            requested.insert(request_next(&mut controller));
+        }
+        assert_eq!(requested.len(), 2);
+        assert!(!requested.contains(&failed_peer_id));
+        assert_eq!(controller.get_sync_info().active_peers, 2);
+    }
+
+    #[tokio::test]
+    async fn test_max_active_peers_per_file_connections() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+        controller.config.max_active_peers_per_file = 2;
+
+        let peer_ids: Vec<PeerId> = (0..4)
+            .map(|_| identity::Keypair::generate_ed25519().public().to_peer_id())
+            .collect();
+        for peer_id in peer_ids.iter() {
+            controller.on_peer_announced(*peer_id, ShardConfig::default());
+        }
+
+        // announced peers beyond the limit are held in reserve
+        assert_eq!(controller.peers.count(&[PeerState::Connected]), 2);
+        assert_eq!(controller.peers.count(&[PeerState::Found]), 2);
+        let reserved_peer_id = peer_ids[2];
+        assert_eq!(
+            controller.peers.peer_state(&reserved_peer_id),
+            Some(PeerState::Found)
+        );
+        assert!(!controller.needs_found_peer(&reserved_peer_id));
+        controller.dial_found_peer(reserved_peer_id);
+        assert!(network_recv.try_recv().is_err());
+
+        // a reserved peer is dialed once an active peer is disconnected
+        controller.on_peer_disconnected(peer_ids[0]);
+        assert!(controller.needs_found_peer(&reserved_peer_id));
+        controller.dial_found_peer(reserved_peer_id);
+        match network_recv.try_recv() {
+            Ok(NetworkMessage::DialPeer { peer_id, .. }) => assert_eq!(peer_id, reserved_peer_id),
+            _ => panic!("Not expected message: NetworkMessage::DialPeer"),
+        }
+        assert_eq!(
+            controller
+                .peers
+                .count(&[PeerState::Connecting, PeerState::Connected]),
+            2
+        );
+        assert!(!controller.needs_found_peer(&peer_ids[3]));
+    }
+
+    /// Always selects the peer with the lowest id, and then an unknown peer.
+    struct LowestPeerSelector(PeerId);
+
+    impl PeerSelector for LowestPeerSelector {
+        fn select(
+            &self,
+            _missing_segments: std::ops::Range<u64>,
+            candidates: &[CandidatePeer],
+            _max_active_peers: usize,
+        ) -> Vec<PeerId> {
+            let mut peers: Vec<PeerId> = candidates.iter().map(|peer| peer.peer_id).collect();
+            peers.sort();
+            peers.truncate(1);
+            peers.push(self.0);
+            peers
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_peer_selector() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (controller, mut network_recv) = create_default_controller(task_executor, None);
+        let unknown_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let mut controller =
+            controller.with_peer_selector(Arc::new(LowestPeerSelector(unknown_peer_id)));
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        let peer_ids: Vec<PeerId> = (0..4)
+            .map(|_| identity::Keypair::generate_ed25519().public().to_peer_id())
+            .collect();
+        for peer_id in peer_ids.iter() {
+            controller.peers.add_new_peer(*peer_id, addr.clone());
+            controller
+                .peers
+                .update_state_force(peer_id, PeerState::Connected);
+        }
+        let lowest_peer_id = *peer_ids.iter().min().unwrap();
+
+        for _ in 0..10 {
+            controller.state = SyncState::Idle;
+            controller.try_request_next();
+            match network_recv.try_recv() {
+                Ok(NetworkMessage::SendRequest { peer_id, .. }) => {
+                    assert_eq!(peer_id, lowest_peer_id)
+                }
+                _ => panic!("Not expected message: NetworkMessage::SendRequest"),
+            }
+        }
+
+        // the selected peers that are not connected are ignored
+        for peer_id in peer_ids.iter() {
+            controller
+                .peers
+                .update_state_force(peer_id, PeerState::Disconnected);
+        }
+        controller.state = SyncState::Idle;
+        controller.try_request_next();
+        assert_eq!(controller.state, SyncState::Idle);
+        assert!(network_recv.try_recv().is_err());
+    }
+
     #[tokio::test]
     async fn test_ban_peer() {
         let runtime = TestRuntime::default();
@@ -1118,7 +2356,7 @@ mod tests {
         let (controller, mut network_recv) =
             create_default_controller(task_executor, Some(init_peer_id));
 
-        assert!(controller.handle_on_response_mismatch(init_peer_id));
+        assert!(controller.handle_on_response_mismatch(init_peer_id, 0));
 
         if let Some(msg) = network_recv.recv().await {
             match msg {
@@ -1168,7 +2406,7 @@ mod tests {
             to_chunk: 1,
             since: Instant::now().into(),
         };
-        assert!(controller.handle_on_response_mismatch(peer_id_1));
+        assert!(controller.handle_on_response_mismatch(peer_id_1, 0));
         if let Some(msg) = network_recv.recv().await {
             match msg {
                 NetworkMessage::ReportPeer {
@@ -1268,6 +2506,8 @@ mod tests {
         chunks.chunks.data = Vec::new();
         controller.on_response(peer_id, chunks).await;
         assert_eq!(*controller.get_status(), SyncState::Idle);
+        assert_eq!(controller.get_sync_info().verified_segments, 0);
+        assert_eq!(controller.get_sync_info().failed_segments, 1);
         if let Some(msg) = network_recv.recv().await {
             match msg {
                 NetworkMessage::ReportPeer {
@@ -1435,6 +2675,150 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_trusted_peer_skip_verification() {
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let chunk_count = 123;
+
+        for (trusted, corrupted) in [(false, false), (true, false), (true, true)] {
+            // the proof of another file fails to verify
+            let (store, peer_store, txs, _) = create_2_store(vec![chunk_count, chunk_count]);
+            let mut chunks = peer_store
+                .get_chunks_with_proof_by_tx_and_index_range(0, 0, chunk_count, None)
+                .unwrap()
+                .unwrap();
+            chunks.proof = peer_store
+                .get_chunks_with_proof_by_tx_and_index_range(1, 0, chunk_count, None)
+                .unwrap()
+                .unwrap()
+                .proof;
+            if corrupted {
+                chunks.chunks.data[0] ^= 1;
+            }
+
+            let runtime = TestRuntime::default();
+            let (mut controller, _network_recv) = create_controller(
+                runtime.task_executor.clone(),
+                Some(peer_id),
+                store.clone(),
+                txs[0].id(),
+                chunk_count,
+            );
+            let (network_send, _trusted_network_recv) = new_network_channel();
+            if trusted {
+                controller.ctx = Arc::new(
+                    SyncNetworkContext::new(network_send)
+                        .with_trusted_peers(HashSet::from([peer_id])),
+                );
+            }
+            controller.state = SyncState::Downloading {
+                peer_id,
+                from_chunk: 0,
+                to_chunk: chunk_count as u64,
+                since: Instant::now().into(),
+            };
+            controller.on_response(peer_id, chunks).await;
+
+            match (trusted, corrupted) {
+                (false, _) => {
+                    assert_eq!(*controller.get_status(), SyncState::Idle);
+                    assert_eq!(controller.get_sync_info().failed_segments, 1);
+                    assert!(!store.check_tx_completed(0).unwrap());
+                }
+                // verified against the file root once downloaded
+                (true, false) => {
+                    assert_eq!(*controller.get_status(), SyncState::Completed);
+                    assert_eq!(controller.get_sync_info().failed_segments, 0);
+                    assert!(store.check_tx_completed(0).unwrap());
+                }
+                (true, true) => {
+                    assert_eq!(
+                        *controller.get_status(),
+                        SyncState::Failed {
+                            reason: FailureReason::TrustedDataMismatch
+                        }
+                    );
+                    assert!(!store.check_tx_completed(0).unwrap());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trusted_peer_corrupt_then_retry() {
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        // more than one batch of the flow
+        let chunk_count = PORA_CHUNK_SIZE * 2 + 123;
+        let (store, peer_store, txs, _) = create_2_store(vec![chunk_count]);
+
+        let runtime = TestRuntime::default();
+        let (mut controller, _network_recv) = create_controller(
+            runtime.task_executor.clone(),
+            Some(peer_id),
+            store.clone(),
+            txs[0].id(),
+            chunk_count,
+        );
+        let (network_send, _trusted_network_recv) = new_network_channel();
+        controller.ctx = Arc::new(
+            SyncNetworkContext::new(network_send).with_trusted_peers(HashSet::from([peer_id])),
+        );
+
+        for corrupted in [true, false] {
+            for from_chunk in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
+                let to_chunk = (from_chunk + PORA_CHUNK_SIZE).min(chunk_count);
+                let mut chunks = peer_store
+                    .get_chunks_with_proof_by_tx_and_index_range(0, from_chunk, to_chunk, None)
+                    .unwrap()
+                    .unwrap();
+                if corrupted && from_chunk == PORA_CHUNK_SIZE {
+                    chunks.chunks.data[0] ^= 1;
+                }
+
+                controller.state = SyncState::Downloading {
+                    peer_id,
+                    from_chunk: from_chunk as u64,
+                    to_chunk: to_chunk as u64,
+                    since: Instant::now().into(),
+                };
+                controller.on_response(peer_id, chunks).await;
+
+                // only the verified segments are reported as stored
+                if from_chunk == 0 {
+                    let stored = controller.get_segment_coverage().stored_chunk_ranges;
+                    if corrupted {
+                        assert!(stored.is_empty());
+                    } else {
+                        assert_eq!(stored, vec![(0, PORA_CHUNK_SIZE as u64)]);
+                    }
+                }
+            }
+
+            if corrupted {
+                // nothing unverified is written into the flow
+                assert_eq!(
+                    *controller.get_status(),
+                    SyncState::Failed {
+                        reason: FailureReason::TrustedDataMismatch
+                    }
+                );
+                assert!(!store.check_tx_completed(0).unwrap());
+                assert!(store
+                    .get_chunks_by_tx_and_index_range(0, 0, PORA_CHUNK_SIZE)
+                    .unwrap()
+                    .is_none());
+
+                // retry from the first segment with proof verification
+                controller.reset(None);
+                assert_eq!(controller.next_chunk, 0);
+            } else {
+                assert_eq!(*controller.get_status(), SyncState::Completed);
+                assert_eq!(controller.get_sync_info().verified_segments, 3);
+                assert!(store.check_tx_completed(0).unwrap());
+            }
+        }
+    }
+
     // FIXME(zz): enable.
     // #[tokio::test]
     #[allow(unused)]
@@ -1558,6 +2942,8 @@ mod tests {
 
         controller.on_response(peer_id, chunks).await;
         assert_eq!(*controller.get_status(), SyncState::Completed);
+        assert_eq!(controller.get_sync_info().verified_segments, 1);
+        assert_eq!(controller.get_sync_info().failed_segments, 0);
         assert!(matches!(
             network_recv.try_recv().unwrap(),
             NetworkMessage::AnnounceLocalFile { .. }
@@ -1704,6 +3090,8 @@ mod tests {
             ctx,
             Store::new(store, task_executor),
             file_location_cache,
+            Arc::new(DiskWatchdog::disabled()),
+            WriteBuffer::unbounded(),
         );
 
         (controller, network_recv)
@@ -0,0 +1,216 @@
+use std::future::Future;
+
+use anyhow::{anyhow, Result};
+use shared_types::{ChunkArray, TxID};
+use storage_async::Store;
+use task_executor::TaskExecutor;
+use tokio::sync::mpsc::{self, OwnedPermit};
+use tokio::sync::oneshot;
+
+use crate::controllers::metrics;
+
+/// A downloaded segment of a file to write into db.
+pub struct SegmentWrite {
+    pub tx_id: TxID,
+    pub chunks: ChunkArray,
+}
+
+struct WriteTask {
+    segment: SegmentWrite,
+    result_sender: oneshot::Sender<Result<bool>>,
+}
+
+/// Bounded channel of the segments from the downloads to the db writer.
+///
+/// A slot of the channel is reserved before requesting a segment, and the segment is sent
+/// through the slot once downloaded, so new requests pause when the db writes lag behind the
+/// downloads.
+#[derive(Clone)]
+pub struct WriteBuffer {
+    /// `None` if unbounded, in which case the segments are written by the downloads.
+    sender: Option<mpsc::Sender<WriteTask>>,
+    capacity: usize,
+}
+
+impl WriteBuffer {
+    /// Creates an unbounded buffer.
+    pub fn unbounded() -> Self {
+        Self {
+            sender: None,
+            capacity: 0,
+        }
+    }
+
+    /// Spawns the writer of the segments into `store` behind a buffer of `capacity` segments,
+    /// or creates an unbounded buffer if `capacity` is 0.
+    pub fn spawn(capacity: usize, store: Store, executor: &TaskExecutor) -> Self {
+        Self::spawn_with_writer(capacity, executor, move |segment| {
+            let store = store.clone();
+            async move { write_segment(&store, segment).await }
+        })
+    }
+
+    fn spawn_with_writer<F, Fut>(capacity: usize, executor: &TaskExecutor, write: F) -> Self
+    where
+        F: Fn(SegmentWrite) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<bool>> + Send,
+    {
+        if capacity == 0 {
+            return Self::unbounded();
+        }
+
+        let (sender, mut receiver) = mpsc::channel::<WriteTask>(capacity);
+        executor.spawn(
+            async move {
+                while let Some(task) = receiver.recv().await {
+                    // the sync of the file may be terminated in the meantime
+                    let _ = task.result_sender.send(write(task.segment).await);
+                }
+            },
+            "sync_write_buffer",
+        );
+
+        Self {
+            sender: Some(sender),
+            capacity,
+        }
+    }
+
+    /// Returns `None` if the buffer is full.
+    pub fn try_acquire(&self) -> Option<WriteSlot> {
+        let permit = match &self.sender {
+            Some(sender) => Some(sender.clone().try_reserve_owned().ok()?),
+            None => None,
+        };
+        metrics::SERIAL_SYNC_WRITE_BUFFER_FILL.update(self.fill_level());
+        Some(WriteSlot {
+            permit,
+            buffer: self.clone(),
+        })
+    }
+
+    /// Returns the number of reserved or queued segments, which is always 0 if unbounded.
+    pub fn fill_level(&self) -> usize {
+        match &self.sender {
+            Some(sender) => self.capacity - sender.capacity(),
+            None => 0,
+        }
+    }
+}
+
+/// A slot of `WriteBuffer` that is released when dropped, or once its segment is dequeued by
+/// the writer.
+pub struct WriteSlot {
+    permit: Option<OwnedPermit<WriteTask>>,
+    buffer: WriteBuffer,
+}
+
+impl WriteSlot {
+    /// Writes the segment through the buffer, or into `store` directly if unbounded.
+    ///
+    /// Returns `false` if the tx has been reverted.
+    pub async fn write(mut self, store: &Store, segment: SegmentWrite) -> Result<bool> {
+        let permit = match self.permit.take() {
+            Some(permit) => permit,
+            None => return write_segment(store, segment).await,
+        };
+
+        let (result_sender, result_receiver) = oneshot::channel();
+        permit.send(WriteTask {
+            segment,
+            result_sender,
+        });
+        result_receiver
+            .await
+            .map_err(|_| anyhow!("Segment writer terminated"))?
+    }
+}
+
+impl Drop for WriteSlot {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+        metrics::SERIAL_SYNC_WRITE_BUFFER_FILL.update(self.buffer.fill_level());
+    }
+}
+
+/// Writes the segment into `store` directly, e.g. if no slot is reserved for it.
+pub async fn write_segment(store: &Store, segment: SegmentWrite) -> Result<bool> {
+    store
+        .put_chunks_with_tx_hash(segment.tx_id.seq, segment.tx_id.hash, segment.chunks, None)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use shared_types::{ChunkArray, TxID};
+    use storage::{log_store::log_manager::LogConfig, LogManager};
+    use storage_async::Store;
+    use task_executor::test_utils::TestRuntime;
+    use tokio::sync::{mpsc, Semaphore};
+
+    use super::{SegmentWrite, WriteBuffer};
+
+    fn segment(tx_seq: u64) -> SegmentWrite {
+        SegmentWrite {
+            tx_id: TxID::random_hash(tx_seq),
+            chunks: ChunkArray {
+                data: vec![0; 256],
+                start_index: 0,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_buffer_slow_writer() {
+        let runtime = TestRuntime::default();
+        let store = Store::new(
+            Arc::new(LogManager::memorydb(LogConfig::default()).unwrap()),
+            runtime.task_executor.clone(),
+        );
+
+        // the writer completes a write only when allowed
+        let (started_send, mut started_recv) = mpsc::unbounded_channel();
+        let allowed = Arc::new(Semaphore::new(0));
+        let writer_allowed = allowed.clone();
+        let buffer = WriteBuffer::spawn_with_writer(1, &runtime.task_executor, move |segment| {
+            let started_send = started_send.clone();
+            let allowed = writer_allowed.clone();
+            async move {
+                started_send.send(segment.tx_id.seq).unwrap();
+                allowed.acquire().await.unwrap().forget();
+                Ok(true)
+            }
+        });
+
+        let slot = buffer.try_acquire().unwrap();
+        assert_eq!(buffer.fill_level(), 1);
+        let write_store = store.clone();
+        let write1 = tokio::spawn(async move { slot.write(&write_store, segment(0)).await });
+        assert_eq!(started_recv.recv().await, Some(0));
+
+        // one segment is queued while the writer is busy, and then the buffer is full
+        let slot = buffer.try_acquire().unwrap();
+        assert!(buffer.try_acquire().is_none());
+        let write_store = store.clone();
+        let write2 = tokio::spawn(async move { slot.write(&write_store, segment(1)).await });
+
+        // the queued segment is dequeued once the slow write completes
+        allowed.add_permits(1);
+        assert!(write1.await.unwrap().unwrap());
+        assert_eq!(started_recv.recv().await, Some(1));
+        drop(buffer.try_acquire().unwrap());
+
+        allowed.add_permits(1);
+        assert!(write2.await.unwrap().unwrap());
+        assert_eq!(buffer.fill_level(), 0);
+    }
+
+    #[test]
+    fn test_write_buffer_unbounded() {
+        let unbounded = WriteBuffer::unbounded();
+        let _slots: Vec<_> = (0..100).map(|_| unbounded.try_acquire().unwrap()).collect();
+        assert_eq!(unbounded.fill_level(), 0);
+    }
+}
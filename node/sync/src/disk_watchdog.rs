@@ -0,0 +1,224 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use metrics::{Gauge, GaugeUsize};
+use serde::{Deserialize, Serialize};
+use task_executor::TaskExecutor;
+
+lazy_static::lazy_static! {
+    pub static ref DISK_FREE_SPACE_BYTES: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_disk_watchdog_free_space_bytes");
+    pub static ref DISK_DOWNLOAD_PAUSED: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_disk_watchdog_download_paused");
+}
+
+/// Queries the free space of the filesystem that stores the data.
+pub trait SpaceQuery: Send + Sync {
+    fn available_space(&self) -> io::Result<u64>;
+}
+
+pub struct FsSpaceQuery {
+    path: PathBuf,
+}
+
+impl FsSpaceQuery {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl SpaceQuery for FsSpaceQuery {
+    fn available_space(&self) -> io::Result<u64> {
+        fs2::available_space(&self.path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskSpaceState {
+    /// Free space in bytes of the last check, or `None` if not checked yet.
+    pub available_bytes: Option<u64>,
+    pub min_free_bytes: u64,
+    pub download_paused: bool,
+}
+
+/// Pauses new segment downloads when the free disk space is below a threshold,
+/// and resumes them once the space is freed, e.g. by the pruner. Downloads are also paused
+/// while the auto pruner prunes files to keep the storage under the cap, or by the operator.
+///
+/// Only downloads are paused, so the node still serves the data it already has.
+pub struct DiskWatchdog {
+    /// Feature is disabled if 0.
+    min_free_bytes: u64,
+    query: Option<Box<dyn SpaceQuery>>,
+    checked: AtomicBool,
+    available_bytes: AtomicU64,
+    paused: AtomicBool,
+    pruning: AtomicBool,
+    paused_by_operator: AtomicBool,
+}
+
+impl DiskWatchdog {
+    pub fn new(min_free_bytes: u64, query: Box<dyn SpaceQuery>) -> Self {
+        Self {
+            min_free_bytes,
+            query: Some(query),
+            checked: AtomicBool::new(false),
+            available_bytes: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            pruning: AtomicBool::new(false),
+            paused_by_operator: AtomicBool::new(false),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            min_free_bytes: 0,
+            query: None,
+            checked: AtomicBool::new(false),
+            available_bytes: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            pruning: AtomicBool::new(false),
+            paused_by_operator: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.min_free_bytes > 0 && self.query.is_some()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+            || self.pruning.load(Ordering::Relaxed)
+            || self.paused_by_operator.load(Ordering::Relaxed)
+    }
+
+    pub fn is_paused_by_operator(&self) -> bool {
+        self.paused_by_operator.load(Ordering::Relaxed)
+    }
+
+    /// Pauses downloads until resumed by the operator, and returns whether the state changed.
+    pub fn set_paused_by_operator(&self, paused: bool) -> bool {
+        self.paused_by_operator.swap(paused, Ordering::Relaxed) != paused
+    }
+
+    /// Pauses downloads while the storage exceeds the cap of the auto pruner.
+    pub fn set_pruning(&self, pruning: bool) {
+        self.pruning.store(pruning, Ordering::Relaxed);
+    }
+
+    pub fn state(&self) -> DiskSpaceState {
+        DiskSpaceState {
+            available_bytes: if self.checked.load(Ordering::Relaxed) {
+                Some(self.available_bytes.load(Ordering::Relaxed))
+            } else {
+                None
+            },
+            min_free_bytes: self.min_free_bytes,
+            download_paused: self.is_paused(),
+        }
+    }
+
+    /// Queries the free space and updates the paused state.
+    ///
+    /// The paused state is kept unchanged if the query fails.
+    pub fn check(&self) {
+        let query = match &self.query {
+            Some(query) if self.min_free_bytes > 0 => query,
+            _ => return,
+        };
+
+        let available = match query.available_space() {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(%e, "Failed to query the available disk space");
+                return;
+            }
+        };
+
+        self.available_bytes.store(available, Ordering::Relaxed);
+        self.checked.store(true, Ordering::Relaxed);
+        DISK_FREE_SPACE_BYTES.update(available as usize);
+
+        let paused = available < self.min_free_bytes;
+        if self.paused.swap(paused, Ordering::Relaxed) != paused {
+            if paused {
+                warn!(
+                    %available, min_free = %self.min_free_bytes,
+                    "Disk space is low, pause downloading file segments"
+                );
+            } else {
+                info!(%available, "Disk space is freed, resume downloading file segments");
+            }
+        }
+        DISK_DOWNLOAD_PAUSED.update(paused as usize);
+    }
+
+    pub fn spawn(self: Arc<Self>, executor: &TaskExecutor, interval: Duration) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        executor.spawn(
+            async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    interval.tick().await;
+                    self.check();
+                }
+            },
+            "disk_watchdog",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use super::{DiskWatchdog, SpaceQuery};
+
+    struct MockSpaceQuery(Arc<AtomicU64>);
+
+    impl SpaceQuery for MockSpaceQuery {
+        fn available_space(&self) -> io::Result<u64> {
+            Ok(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    #[test]
+    fn test_pause_on_low_space() {
+        let space = Arc::new(AtomicU64::new(1000));
+        let watchdog = DiskWatchdog::new(100, Box::new(MockSpaceQuery(space.clone())));
+        assert_eq!(watchdog.state().available_bytes, None);
+
+        watchdog.check();
+        assert!(!watchdog.is_paused());
+        assert_eq!(watchdog.state().available_bytes, Some(1000));
+
+        space.store(99, Ordering::Relaxed);
+        watchdog.check();
+        assert!(watchdog.is_paused());
+        assert!(watchdog.state().download_paused);
+
+        // resume once the space is freed
+        space.store(100, Ordering::Relaxed);
+        watchdog.check();
+        assert!(!watchdog.is_paused());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let watchdog = DiskWatchdog::new(0, Box::new(MockSpaceQuery(Arc::new(AtomicU64::new(0)))));
+        watchdog.check();
+        assert!(!watchdog.is_paused());
+        assert!(!watchdog.is_enabled());
+
+        let watchdog = DiskWatchdog::disabled();
+        watchdog.check();
+        assert!(!watchdog.is_paused());
+    }
+}
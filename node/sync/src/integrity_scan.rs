@@ -0,0 +1,260 @@
+//! Verifies the data of the finalized files against the roots of their txs at startup, e.g.
+//! after an unclean shutdown, and syncs the corrupt files again.
+//!
+//! The `full` mode verifies all the finalized files, while the `sampled` mode verifies a random
+//! sample of them, which keeps the startup fast on a large node and still catches a widespread
+//! corruption. Files that are not fully stored, e.g. by a shard of the data only, are skipped.
+
+use anyhow::Result;
+use metrics::{Gauge, GaugeUsize};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use storage_async::Store;
+
+use crate::striped::for_each_striped;
+use crate::{SyncRequest, SyncResponse, SyncSender};
+
+lazy_static::lazy_static! {
+    pub static ref INTEGRITY_SCAN_SCANNED: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_integrity_scan_scanned");
+    pub static ref INTEGRITY_SCAN_CORRUPT: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_integrity_scan_corrupt");
+}
+
+/// Number of the verified files between two progress logs.
+const PROGRESS_LOG_INTERVAL: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityScanMode {
+    #[default]
+    Off,
+    Full,
+    Sampled,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityScanState {
+    pub mode: IntegrityScanMode,
+    /// Number of the files to verify.
+    pub total: usize,
+    /// Number of the verified files, including the skipped ones.
+    pub scanned: usize,
+    /// Number of the files skipped since the data is not fully stored.
+    pub skipped: usize,
+    /// Files of which the data does not match the tx root.
+    pub corrupt_tx_seqs: Vec<u64>,
+    /// Corrupt files that are being synced again.
+    pub repairing_tx_seqs: Vec<u64>,
+    /// Unix timestamp in seconds when the scan started, or `None` if not started.
+    pub started_at: Option<u64>,
+    /// Unix timestamp in seconds when the scan finished, or `None` if not finished.
+    pub finished_at: Option<u64>,
+}
+
+pub struct IntegrityScanner {
+    mode: IntegrityScanMode,
+    /// Number of the random files to verify in the `sampled` mode.
+    sample_size: usize,
+    /// Number of the files to verify concurrently.
+    concurrency: usize,
+    store: Store,
+    scanned: AtomicUsize,
+    skipped: AtomicUsize,
+    state: Mutex<IntegrityScanState>,
+}
+
+impl IntegrityScanner {
+    pub fn new(
+        mode: IntegrityScanMode,
+        sample_size: usize,
+        concurrency: usize,
+        store: Store,
+    ) -> Self {
+        Self {
+            mode,
+            sample_size,
+            concurrency: concurrency.max(1),
+            store,
+            scanned: AtomicUsize::new(0),
+            skipped: AtomicUsize::new(0),
+            state: Mutex::new(IntegrityScanState {
+                mode,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != IntegrityScanMode::Off
+    }
+
+    pub fn state(&self) -> IntegrityScanState {
+        let mut state = self.state.lock().unwrap().clone();
+        state.scanned = self.scanned.load(Ordering::Relaxed);
+        state.skipped = self.skipped.load(Ordering::Relaxed);
+        state
+    }
+
+    /// Verifies the finalized files in the configured mode, and returns the corrupt ones.
+    pub async fn scan(self: &Arc<Self>) -> Result<Vec<u64>> {
+        if !self.is_enabled() {
+            return Ok(vec![]);
+        }
+
+        let tx_seqs = self.files_to_scan().await?;
+        let total = tx_seqs.len();
+        {
+            let mut state = self.state.lock().unwrap();
+            state.total = total;
+            state.started_at = Some(now_secs());
+        }
+        info!(mode = ?self.mode, %total, "Start to scan the integrity of files");
+
+        let scanner = self.clone();
+        let verified = for_each_striped(tx_seqs.clone(), self.concurrency, move |tx_seq| {
+            let scanner = scanner.clone();
+            async move {
+                let verified = scanner.verify(tx_seq).await?;
+                let scanned = scanner.scanned.fetch_add(1, Ordering::Relaxed) + 1;
+                INTEGRITY_SCAN_SCANNED.update(scanned);
+                if scanned % PROGRESS_LOG_INTERVAL == 0 {
+                    info!(%scanned, %total, "Integrity scan in progress");
+                }
+                Ok(verified)
+            }
+        })
+        .await?;
+
+        let corrupt: Vec<u64> = tx_seqs
+            .into_iter()
+            .zip(verified)
+            .filter_map(|(tx_seq, verified)| (!verified).then_some(tx_seq))
+            .collect();
+        INTEGRITY_SCAN_CORRUPT.update(corrupt.len());
+
+        if corrupt.is_empty() {
+            info!(%total, "Integrity scan completed, no corrupt file found");
+        } else {
+            warn!(%total, ?corrupt, "Integrity scan completed with corrupt files");
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.corrupt_tx_seqs = corrupt.clone();
+        state.finished_at = Some(now_secs());
+
+        Ok(corrupt)
+    }
+
+    /// Syncs the corrupt files again to overwrite the local data.
+    pub async fn repair(&self, corrupt: &[u64], sync_send: &SyncSender) {
+        for &tx_seq in corrupt {
+            match sync_send.request(SyncRequest::RepairFile { tx_seq }).await {
+                Ok(SyncResponse::SyncFile { err }) if err.is_empty() => {
+                    self.state.lock().unwrap().repairing_tx_seqs.push(tx_seq);
+                }
+                Ok(response) => warn!(%tx_seq, ?response, "Failed to repair corrupt file"),
+                Err(e) => warn!(%tx_seq, ?e, "Failed to send repair request"),
+            }
+        }
+    }
+
+    /// Returns the finalized files to verify in the configured mode, ordered by tx seq.
+    async fn files_to_scan(&self) -> Result<Vec<u64>> {
+        let mut tx_seqs = vec![];
+        for tx_seq in 0..self.store.get_store().next_tx_seq() {
+            if self.store.check_tx_completed(tx_seq).await? {
+                tx_seqs.push(tx_seq);
+            }
+        }
+
+        if self.mode == IntegrityScanMode::Sampled && tx_seqs.len() > self.sample_size {
+            let mut indices =
+                rand::seq::index::sample(&mut rand::thread_rng(), tx_seqs.len(), self.sample_size)
+                    .into_vec();
+            indices.sort_unstable();
+            tx_seqs = indices.into_iter().map(|index| tx_seqs[index]).collect();
+        }
+
+        Ok(tx_seqs)
+    }
+
+    /// Returns `false` if the data of the file does not match the tx root.
+    async fn verify(&self, tx_seq: u64) -> Result<bool> {
+        let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+            Some(tx) => tx,
+            // reverted during the scan
+            None => return Ok(true),
+        };
+
+        match self.store.compute_data_root(tx_seq).await? {
+            Some(root) if root == tx.data_merkle_root => Ok(true),
+            Some(root) => {
+                warn!(%tx_seq, computed = ?root, expected = ?tx.data_merkle_root, "File root mismatch");
+                Ok(false)
+            }
+            None => {
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+                Ok(true)
+            }
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IntegrityScanMode, IntegrityScanner};
+    use crate::test_util::tests::{create_store_with_corrupt_files, TestStoreRuntime};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_integrity_scan() {
+        let store = create_store_with_corrupt_files(vec![3, 1024, 5, 2048, 7], &[1, 3]);
+        let runtime = TestStoreRuntime::new(store);
+
+        // all the files are verified in parallel
+        let scanner = Arc::new(IntegrityScanner::new(
+            IntegrityScanMode::Full,
+            0,
+            2,
+            runtime.store.clone(),
+        ));
+        assert_eq!(scanner.scan().await.unwrap(), vec![1, 3]);
+        let state = scanner.state();
+        assert_eq!(state.total, 5);
+        assert_eq!(state.scanned, 5);
+        assert_eq!(state.skipped, 0);
+        assert_eq!(state.corrupt_tx_seqs, vec![1, 3]);
+        assert!(state.finished_at.is_some());
+
+        // a sample of the files only
+        let scanner = Arc::new(IntegrityScanner::new(
+            IntegrityScanMode::Sampled,
+            2,
+            4,
+            runtime.store.clone(),
+        ));
+        let corrupt = scanner.scan().await.unwrap();
+        assert!(corrupt.iter().all(|tx_seq| [1, 3].contains(tx_seq)));
+        assert_eq!(scanner.state().total, 2);
+        assert_eq!(scanner.state().scanned, 2);
+
+        // disabled
+        let scanner = Arc::new(IntegrityScanner::new(
+            IntegrityScanMode::Off,
+            2,
+            4,
+            runtime.store.clone(),
+        ));
+        assert!(scanner.scan().await.unwrap().is_empty());
+        assert_eq!(scanner.state().started_at, None);
+    }
+}
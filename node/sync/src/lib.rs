@@ -1,21 +1,32 @@
 #[macro_use]
 extern crate tracing;
 
+pub mod auto_prune;
 pub mod auto_sync;
+mod checkpoint;
 mod context;
 mod controllers;
+pub mod disk_watchdog;
+pub mod integrity_scan;
+pub mod pause;
 mod service;
+mod striped;
 pub mod test_util;
+pub mod worker_watchdog;
 
+pub use auto_sync::sync_store::DeadLetterFile;
 use auto_sync::{batcher_random::RandomBatcherState, batcher_serial::SerialBatcherState};
-pub use controllers::FileSyncInfo;
+pub use checkpoint::CheckpointInfo;
+pub use controllers::{
+    CandidatePeer, DefaultPeerSelector, FileSyncInfo, PeerSelector, SegmentCoverage,
+};
+use disk_watchdog::DiskSpaceState;
 use duration_str::deserialize_duration;
+use integrity_scan::IntegrityScanMode;
 use serde::{Deserialize, Serialize};
 pub use service::{SyncMessage, SyncReceiver, SyncRequest, SyncResponse, SyncSender, SyncService};
-use std::{
-    fmt::Debug,
-    time::{Duration, Instant},
-};
+use std::{fmt::Debug, time::Duration};
+use tokio::time::Instant;
 
 #[derive(Clone, Copy, Debug, Deserialize)]
 #[serde(default)]
@@ -31,6 +42,13 @@ pub struct Config {
     pub max_sync_files: usize,
     pub sync_file_by_rpc_enabled: bool,
     pub sync_file_on_announcement_enabled: bool,
+    /// Only serve the stored files and never sync any file, e.g. for dedicated serving replicas
+    /// seeded from a snapshot. The RPC serving, proof generation and file announcements are
+    /// not affected.
+    pub serve_only: bool,
+    /// Keep syncing the log entries in the serve-only mode, so that `logSyncHeight` is still
+    /// tracked.
+    pub serve_only_log_sync_enabled: bool,
 
     // serial sync config
     pub max_chunks_to_request: u64,
@@ -50,6 +68,48 @@ pub struct Config {
     pub max_bandwidth_bytes: u64,
     #[serde(deserialize_with = "deserialize_duration")]
     pub bandwidth_wait_timeout: Duration,
+    /// Maximum download bandwidth (B/s) of each file, 0 for unlimited. The segment requests of
+    /// a file are paced under it, however many peers serve the file.
+    pub max_file_bandwidth_bytes: u64,
+    /// Maximum number of segments that are requested but not written into db yet,
+    /// 0 for unlimited. New requests pause when the db writes lag behind.
+    pub max_pending_write_segments: usize,
+    /// Preempt the segment request to a peer and reassign it to another peer, if the request
+    /// takes longer than this ratio of the expected time at the median delivery rate of other
+    /// peers that served the file. 0 to disable.
+    pub slow_peer_preemption_ratio: f64,
+    /// Maximum number of peers of a file to connect to and request segments from, 0 for
+    /// unlimited. Other peers are held in reserve and connected once the active peers are
+    /// disconnected or banned, except that the peers required to cover all shards are always
+    /// connected.
+    pub max_active_peers_per_file: usize,
+    /// Interval to persist the download progress of files in sync, so that the file sync is
+    /// resumed from the last checkpoint after restart. 0 to disable.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub checkpoint_interval: Duration,
+    /// Number of the workers to restore the files in sync from the checkpoints at startup.
+    pub checkpoint_load_concurrency: usize,
+    /// Pause downloading file segments if the free disk space is below this value, 0 to disable.
+    pub min_free_disk_space_bytes: u64,
+    /// Prune the oldest finalized files that are not pinned once the storage exceeds this size
+    /// in bytes, 0 to disable.
+    pub max_storage_bytes: u64,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub disk_space_check_interval: Duration,
+    /// Reuse the recent answers of peers that have a file to sync it again within this
+    /// duration, instead of asking the neighbors again. 0 to disable.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub availability_cache_ttl: Duration,
+    /// Maximum number of the cached answers of peers, beyond which the oldest ones are
+    /// evicted. 0 for unlimited.
+    pub max_availability_cache_entries: usize,
+    /// Maximum number of the peers to dial per minute once they advertised a file in sync but
+    /// are not connected yet, instead of waiting for the file sync to connect peers. Each
+    /// peer is dialed at most once a minute. 0 to disable.
+    pub max_eager_dials_per_min: usize,
+    /// Maximum number of the queries for peers that have files, i.e. `FindFile` or
+    /// `FindChunks`, in flight at the same time. Excess queries are queued. 0 for unlimited.
+    pub max_in_flight_availability_queries: usize,
 
     // auto sync config
     #[serde(deserialize_with = "deserialize_duration")]
@@ -63,6 +123,26 @@ pub struct Config {
     #[serde(deserialize_with = "deserialize_duration")]
     pub random_find_peer_timeout: Duration,
     pub ready_txs_cache_cap: usize,
+    /// Maximum number of failed attempts to sync a file randomly, after which the file is
+    /// moved to the dead letter queue until retried by admin RPC. 0 for unlimited.
+    pub max_sync_file_attempts: usize,
+
+    // worker watchdog config
+    /// Restart the auto sync workers if they make no progress for this duration, and log a
+    /// fatal error if the sync service stalls. 0 to disable.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub worker_stall_timeout: Duration,
+    /// Maximum number of restarts of a stalled worker, after which a fatal error is logged.
+    pub max_worker_restarts: usize,
+
+    // integrity scan config
+    /// Verify the data of the finalized files against the tx roots at startup, and sync the
+    /// corrupt files again. `full` to verify all the files, `sampled` to verify a random sample
+    /// of `integrity_scan_sample_size` files, or `off` to disable.
+    pub integrity_scan_mode: IntegrityScanMode,
+    pub integrity_scan_sample_size: usize,
+    /// Number of the files to verify concurrently.
+    pub integrity_scan_concurrency: usize,
 }
 
 impl Default for Config {
@@ -75,6 +155,8 @@ impl Default for Config {
             max_sync_files: 16,
             sync_file_by_rpc_enabled: true,
             sync_file_on_announcement_enabled: false,
+            serve_only: false,
+            serve_only_log_sync_enabled: true,
 
             // serial sync config
             max_chunks_to_request: 2 * 1024,
@@ -87,6 +169,19 @@ impl Default for Config {
             peer_next_chunks_request_wait_timeout: Duration::from_secs(3),
             max_bandwidth_bytes: 0,
             bandwidth_wait_timeout: Duration::from_secs(5),
+            max_file_bandwidth_bytes: 0,
+            max_pending_write_segments: 0,
+            slow_peer_preemption_ratio: 0.0,
+            max_active_peers_per_file: 0,
+            checkpoint_interval: Duration::from_secs(60),
+            checkpoint_load_concurrency: 4,
+            min_free_disk_space_bytes: 0,
+            max_storage_bytes: 0,
+            disk_space_check_interval: Duration::from_secs(30),
+            availability_cache_ttl: Duration::ZERO,
+            max_availability_cache_entries: 100_000,
+            max_eager_dials_per_min: 0,
+            max_in_flight_availability_queries: 0,
 
             // auto sync config
             auto_sync_idle_interval: Duration::from_secs(3),
@@ -96,10 +191,27 @@ impl Default for Config {
             sequential_find_peer_timeout: Duration::from_secs(5),
             random_find_peer_timeout: Duration::from_secs(5),
             ready_txs_cache_cap: 1_000_000,
+            max_sync_file_attempts: 0,
+
+            // worker watchdog config
+            worker_stall_timeout: Duration::ZERO,
+            max_worker_restarts: 3,
+
+            // integrity scan config
+            integrity_scan_mode: IntegrityScanMode::Off,
+            integrity_scan_sample_size: 100,
+            integrity_scan_concurrency: 4,
         }
     }
 }
 
+impl Config {
+    pub fn log_sync_enabled(&self) -> bool {
+        !self.serve_only || self.serve_only_log_sync_enabled
+    }
+}
+
+/// An instant of the tokio clock, so that the sync states follow the paused clock in tests.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct InstantWrapper(Instant);
 
@@ -124,8 +236,14 @@ impl InstantWrapper {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SyncServiceState {
+    /// Whether the file sync is disabled to only serve the stored files.
+    pub serve_only: bool,
+    /// Whether new segment downloads are paused by the operator.
+    pub paused: bool,
     pub num_syncing: usize,
     pub catched_up: Option<bool>,
     pub auto_sync_serial: Option<SerialBatcherState>,
     pub auto_sync_random: Option<RandomBatcherState>,
+    pub disk_space: DiskSpaceState,
+    pub checkpoint: CheckpointInfo,
 }
@@ -0,0 +1,33 @@
+//! Persists whether the file sync is paused by the operator, so that a node restarted while
+//! paused stays paused until it's resumed explicitly.
+//!
+//! While paused, no new segment is requested, but the requests in flight are completed and the
+//! node still serves the data it already has.
+
+use anyhow::Result;
+use metrics::{Gauge, GaugeUsize};
+use std::sync::Arc;
+use storage::log_store::log_manager::DATA_DB_KEY;
+use storage_async::Store;
+
+lazy_static::lazy_static! {
+    pub static ref SYNC_PAUSED: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_paused");
+}
+
+const KEY_PAUSED: &str = "sync.paused";
+
+/// Returns whether the file sync is paused, which is `false` if never paused.
+pub async fn is_paused(store: &Store) -> Result<bool> {
+    Ok(store
+        .get_config_decoded(&KEY_PAUSED, DATA_DB_KEY)
+        .await?
+        .unwrap_or(false))
+}
+
+pub async fn set_paused(store: &Store, paused: bool) -> Result<()> {
+    store
+        .set_config_encoded(&KEY_PAUSED, &paused, DATA_DB_KEY)
+        .await?;
+    SYNC_PAUSED.update(paused as usize);
+    Ok(())
+}
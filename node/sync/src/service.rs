@@ -1,13 +1,20 @@
 use crate::auto_sync::manager::AutoSyncManager;
+use crate::checkpoint::SyncCheckpointer;
 use crate::context::SyncNetworkContext;
 use crate::controllers::{
-    FailureReason, FileSyncGoal, FileSyncInfo, SerialSyncController, SyncState,
+    AvailabilityCache, DefaultPeerSelector, EagerDialLimiter, FailureReason, FileSyncGoal,
+    FileSyncInfo, PeerSelector, QueryLimiter, SegmentCoverage, SerialSyncController, SyncState,
+    WriteBuffer,
 };
-use crate::{Config, SyncServiceState};
+use crate::disk_watchdog::DiskWatchdog;
+use crate::pause;
+use crate::worker_watchdog::{Heartbeat, WorkerWatchdog};
+use crate::{Config, DeadLetterFile, SyncServiceState};
 use anyhow::{anyhow, bail, Result};
 use file_location_cache::FileLocationCache;
 use libp2p::swarm::DialError;
 use log_entry_sync::LogSyncEvent;
+use metrics::{Gauge, GaugeUsize};
 use network::types::{AnnounceChunks, FindFile};
 use network::{
     rpc::GetChunksRequest, rpc::RPCResponseErrorCode, Multiaddr, NetworkMessage, NetworkSender,
@@ -17,16 +24,22 @@ use shared_types::{bytes_to_chunks, ChunkArrayWithProof, ShardedFile, Transactio
 use std::sync::atomic::Ordering;
 use std::{
     cmp,
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     sync::Arc,
+    time::Instant,
 };
 use storage::config::ShardConfig;
 use storage::error::Result as StorageResult;
 use storage::log_store::log_manager::{sector_to_segment, segment_to_sector, PORA_CHUNK_SIZE};
+use storage::log_store::tx_store::TxStatus;
 use storage::log_store::Store as LogStore;
 use storage_async::Store;
 use tokio::sync::{broadcast, oneshot};
 
+lazy_static::lazy_static! {
+    pub static ref SYNC_TIME_TO_READY_MS: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_time_to_ready_ms");
+}
+
 pub type SyncSender = channel::Sender<SyncMessage, SyncRequest, SyncResponse>;
 pub type SyncReceiver = channel::Receiver<SyncMessage, SyncRequest, SyncResponse>;
 
@@ -102,6 +115,21 @@ pub enum SyncRequest {
         tx_seq: u64,
         is_reverted: bool,
     },
+    DeadLetterFiles,
+    RetryDeadLetterFile {
+        tx_seq: u64,
+    },
+    /// Download all chunks of a finalized file again to overwrite the local data.
+    RepairFile {
+        tx_seq: u64,
+    },
+    SegmentCoverage {
+        tx_seq: u64,
+    },
+    /// Pause or resume the segment downloads of all the files.
+    SetPaused {
+        paused: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -112,6 +140,10 @@ pub enum SyncResponse {
     FileSyncInfo { result: HashMap<u64, FileSyncInfo> },
     FindFile { err: String },
     TerminateFileSync { count: usize },
+    DeadLetterFiles { result: Vec<DeadLetterFile> },
+    RetryDeadLetterFile { retried: bool },
+    SegmentCoverage { coverage: Option<SegmentCoverage> },
+    SetPaused { err: String },
 }
 
 pub struct SyncService {
@@ -132,6 +164,30 @@ pub struct SyncService {
     /// A collection of file sync controllers.
     controllers: HashMap<u64, SerialSyncController>,
 
+    /// Pauses downloading file segments when the disk space is low.
+    disk_watchdog: Arc<DiskWatchdog>,
+
+    /// Bounded channel of the segments that are downloading or not written into db yet.
+    write_buffer: WriteBuffer,
+
+    /// Strategy to select the peers to request file segments from.
+    peer_selector: Arc<dyn PeerSelector>,
+
+    /// Recent answers of peers that have files, shared by all the file syncs.
+    availability_cache: AvailabilityCache,
+
+    /// Bounds the dials to the peers that advertised the files in sync.
+    eager_dials: EagerDialLimiter,
+
+    /// Bounds the in-flight queries for peers, shared by all the file syncs.
+    query_limiter: QueryLimiter,
+
+    /// Persists the download progress of files in sync.
+    checkpointer: SyncCheckpointer,
+
+    /// Reports the progress of the main loop to the worker watchdog.
+    heartbeat: Heartbeat,
+
     auto_sync_manager: Option<AutoSyncManager>,
 }
 
@@ -150,26 +206,56 @@ impl SyncService {
             network_send,
             store,
             file_location_cache,
+            Arc::new(DiskWatchdog::disabled()),
+            Arc::new(WorkerWatchdog::disabled()),
+            Default::default(),
+            Arc::new(DefaultPeerSelector),
             event_recv,
             catch_up_end_recv,
         )
         .await
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn spawn_with_config(
         config: Config,
         executor: task_executor::TaskExecutor,
         network_send: NetworkSender,
         store: Arc<dyn LogStore>,
         file_location_cache: Arc<FileLocationCache>,
+        disk_watchdog: Arc<DiskWatchdog>,
+        worker_watchdog: Arc<WorkerWatchdog>,
+        trusted_peers: HashSet<PeerId>,
+        peer_selector: Arc<dyn PeerSelector>,
         event_recv: broadcast::Receiver<LogSyncEvent>,
         catch_up_end_recv: oneshot::Receiver<()>,
     ) -> Result<SyncSender> {
+        let started_at = Instant::now();
         let (sync_send, sync_recv) = channel::Channel::unbounded("sync");
+        if !trusted_peers.is_empty() {
+            info!(
+                ?trusted_peers,
+                "Accept file segments from trusted bootstrap peers without proof verification"
+            );
+        }
         let store = Store::new(store, executor.clone());
 
+        let paused = pause::is_paused(&store).await?;
+        if paused {
+            info!("File sync is paused by the operator");
+        }
+        disk_watchdog.set_paused_by_operator(paused);
+        pause::SYNC_PAUSED.update(paused as usize);
+
+        if config.serve_only {
+            info!(
+                log_sync_enabled = %config.log_sync_enabled(),
+                "Serve-only mode, file sync disabled"
+            );
+        }
+
         // init auto sync
-        let auto_sync_manager = if config.auto_sync_enabled {
+        let auto_sync_manager = if config.auto_sync_enabled && !config.serve_only {
             Some(
                 AutoSyncManager::spawn(
                     config,
@@ -178,6 +264,7 @@ impl SyncService {
                     sync_send.clone(),
                     event_recv,
                     catch_up_end_recv,
+                    &worker_watchdog,
                 )
                 .await?,
             )
@@ -185,17 +272,43 @@ impl SyncService {
             None
         };
 
+        let checkpointer = SyncCheckpointer::new(
+            store.clone(),
+            executor.clone(),
+            config.checkpoint_load_concurrency,
+        )
+        .await?;
+
+        let write_buffer =
+            WriteBuffer::spawn(config.max_pending_write_segments, store.clone(), &executor);
+
         let mut sync = SyncService {
             config,
             msg_recv: sync_recv,
-            ctx: Arc::new(SyncNetworkContext::new(network_send)),
+            ctx: Arc::new(SyncNetworkContext::new(network_send).with_trusted_peers(trusted_peers)),
             store,
             file_location_cache,
             controllers: Default::default(),
             auto_sync_manager,
+            disk_watchdog: disk_watchdog.clone(),
+            write_buffer,
+            peer_selector,
+            availability_cache: AvailabilityCache::new(
+                config.availability_cache_ttl,
+                config.max_availability_cache_entries,
+            ),
+            eager_dials: EagerDialLimiter::new(config.max_eager_dials_per_min),
+            query_limiter: QueryLimiter::new(config.max_in_flight_availability_queries),
+            checkpointer,
+            heartbeat: worker_watchdog.monitor(&executor, "sync"),
         };
 
-        info!("Starting sync service");
+        disk_watchdog.spawn(&executor, config.disk_space_check_interval);
+
+        // ready to serve once the files in sync are restored
+        let time_to_ready = started_at.elapsed();
+        SYNC_TIME_TO_READY_MS.update(time_to_ready.as_millis() as usize);
+        info!(?time_to_ready, "Starting sync service");
         executor.spawn(async move { Box::pin(sync.main()).await }, "sync");
 
         Ok(sync_send)
@@ -203,6 +316,12 @@ impl SyncService {
 
     async fn main(&mut self) {
         let mut heartbeat = tokio::time::interval(self.config.heartbeat_interval);
+        let checkpoint_enabled = !self.config.checkpoint_interval.is_zero();
+        let mut checkpoint = tokio::time::interval(if checkpoint_enabled {
+            self.config.checkpoint_interval
+        } else {
+            self.config.heartbeat_interval
+        });
 
         loop {
             tokio::select! {
@@ -216,6 +335,9 @@ impl SyncService {
 
                 // heartbeat
                 _ = heartbeat.tick() => self.on_heartbeat(),
+
+                // persist the download progress
+                _ = checkpoint.tick(), if checkpoint_enabled => self.on_checkpoint(),
             }
         }
     }
@@ -268,8 +390,9 @@ impl SyncService {
             }
 
             SyncMessage::AnnounceChunksGossip { msg } => self.on_announce_chunks_gossip(msg).await,
-            SyncMessage::AnnounceShardConfig { .. } => {
+            SyncMessage::AnnounceShardConfig { peer_id, .. } => {
                 // FIXME: Check if controllers need to be reset?
+                self.availability_cache.invalidate_peer(&peer_id);
             }
             SyncMessage::NewFile { from, file } => self.on_new_file_gossip(from, file).await,
             SyncMessage::AnswerFile { peer_id, file } => self.on_answer_file(peer_id, file).await,
@@ -285,6 +408,8 @@ impl SyncService {
             SyncRequest::SyncState => {
                 let state = match &self.auto_sync_manager {
                     Some(manager) => SyncServiceState {
+                        serve_only: self.config.serve_only,
+                        paused: self.disk_watchdog.is_paused_by_operator(),
                         num_syncing: self.controllers.len(),
                         catched_up: Some(manager.catched_up.load(Ordering::Relaxed)),
                         auto_sync_serial: match &manager.serial {
@@ -292,12 +417,18 @@ impl SyncService {
                             None => None,
                         },
                         auto_sync_random: manager.random.get_state().await.ok(),
+                        disk_space: self.disk_watchdog.state(),
+                        checkpoint: self.checkpointer.info(),
                     },
                     None => SyncServiceState {
+                        serve_only: self.config.serve_only,
+                        paused: self.disk_watchdog.is_paused_by_operator(),
                         num_syncing: self.controllers.len(),
                         catched_up: None,
                         auto_sync_serial: None,
                         auto_sync_random: None,
+                        disk_space: self.disk_watchdog.state(),
+                        checkpoint: self.checkpointer.info(),
                     },
                 };
 
@@ -348,6 +479,14 @@ impl SyncService {
                 let _ = sender.send(SyncResponse::FileSyncInfo { result });
             }
 
+            SyncRequest::SegmentCoverage { tx_seq } => {
+                let coverage = self
+                    .controllers
+                    .get(&tx_seq)
+                    .map(|controller| controller.get_segment_coverage());
+                let _ = sender.send(SyncResponse::SegmentCoverage { coverage });
+            }
+
             SyncRequest::TerminateFileSync {
                 tx_seq,
                 is_reverted,
@@ -359,6 +498,48 @@ impl SyncService {
                 let result = self.on_find_file_request(tx_seq).await;
                 let _ = sender.send(SyncResponse::FindFile { err: result });
             }
+
+            SyncRequest::DeadLetterFiles => {
+                let result = match &self.auto_sync_manager {
+                    Some(manager) => manager.random.dead_letters().await.unwrap_or_else(|err| {
+                        warn!(%err, "Failed to get dead letter files");
+                        vec![]
+                    }),
+                    None => vec![],
+                };
+                let _ = sender.send(SyncResponse::DeadLetterFiles { result });
+            }
+
+            SyncRequest::RetryDeadLetterFile { tx_seq } => {
+                let retried = match &self.auto_sync_manager {
+                    Some(manager) => manager
+                        .random
+                        .retry_dead_letter(tx_seq)
+                        .await
+                        .unwrap_or_else(|err| {
+                            warn!(%err, %tx_seq, "Failed to retry dead letter file");
+                            false
+                        }),
+                    None => false,
+                };
+                let _ = sender.send(SyncResponse::RetryDeadLetterFile { retried });
+            }
+
+            SyncRequest::RepairFile { tx_seq } => {
+                let result = match self.on_repair_file(tx_seq).await {
+                    Ok(()) => "".into(),
+                    Err(e) => e.to_string(),
+                };
+                let _ = sender.send(SyncResponse::SyncFile { err: result });
+            }
+
+            SyncRequest::SetPaused { paused } => {
+                let result = match self.on_set_paused(paused).await {
+                    Ok(()) => "".into(),
+                    Err(e) => e.to_string(),
+                };
+                let _ = sender.send(SyncResponse::SetPaused { err: result });
+            }
         }
     }
 
@@ -382,6 +563,7 @@ impl SyncService {
 
     fn on_peer_disconnected(&mut self, peer_id: PeerId) {
         info!(%peer_id, "Peer disconnected");
+        self.availability_cache.invalidate_peer(&peer_id);
 
         for controller in self.controllers.values_mut() {
             controller.on_peer_disconnected(peer_id);
@@ -550,6 +732,10 @@ impl SyncService {
         tx_seq: u64,
         maybe_range: Option<(u64, u64)>,
     ) -> String {
+        if self.config.serve_only {
+            return "Disabled to sync file in serve-only mode".into();
+        }
+
         if maybe_range.is_none() && !self.config.sync_file_by_rpc_enabled {
             return "Disabled to sync file".into();
         }
@@ -602,6 +788,10 @@ impl SyncService {
         maybe_range: Option<(u64, u64)>,
         maybe_peer: Option<(PeerId, Multiaddr)>,
     ) -> Result<()> {
+        if self.config.serve_only {
+            bail!("Disabled to sync file in serve-only mode");
+        }
+
         info!(%tx_seq, ?maybe_range, ?maybe_peer, "Start to sync file");
 
         // remove failed entry if caused by tx reverted, so as to re-sync
@@ -640,15 +830,29 @@ impl SyncService {
                     }
                 };
 
-                // file already exists
-                if let Some(status) = self.store.get_store().get_tx_status(tx_seq)? {
-                    bail!("File already exists [{:?}]", status);
+                // file already exists or skipped
+                match self.store.get_store().get_tx_status(tx_seq)? {
+                    Some(TxStatus::Skipped) => {
+                        let reason = self.store.get_store().get_skip_reason(tx_seq)?;
+                        bail!("File skipped: {}", reason.unwrap_or_default())
+                    }
+                    Some(status) => bail!("File already exists [{:?}]", status),
+                    None => {}
+                }
+
+                // re-uploaded file of which the data is stored already
+                if maybe_range.is_none() && self.store.link_same_root_tx(tx_seq).await? {
+                    info!(%tx_seq, "File finalized with the stored data of the same root");
+                    self.ctx
+                        .send(NetworkMessage::AnnounceLocalFile { tx_id: tx.id() });
+                    return Ok(());
                 }
 
                 let (index_start, index_end, all_chunks) = match maybe_range {
                     Some((start, end)) => (start, end, false),
                     None => {
-                        let start = match Self::tx_sync_start_index(&self.store, &tx).await? {
+                        let from = self.checkpointer.resume_from(&tx.id()).unwrap_or_default();
+                        let start = match Self::tx_sync_start_index(&self.store, &tx, from).await? {
                             Some(s) => s,
                             None => {
                                 debug!(%tx.seq, "No more data needed");
@@ -667,15 +871,22 @@ impl SyncService {
                     bail!("Invalid chunk range");
                 }
 
-                entry.insert(SerialSyncController::new(
-                    self.config,
-                    tx.id(),
-                    tx.start_entry_index(),
-                    FileSyncGoal::new(num_chunks, index_start, index_end, all_chunks),
-                    self.ctx.clone(),
-                    self.store.clone(),
-                    self.file_location_cache.clone(),
-                ))
+                entry.insert(
+                    SerialSyncController::new(
+                        self.config,
+                        tx.id(),
+                        tx.start_entry_index(),
+                        FileSyncGoal::new(num_chunks, index_start, index_end, all_chunks),
+                        self.ctx.clone(),
+                        self.store.clone(),
+                        self.file_location_cache.clone(),
+                        self.disk_watchdog.clone(),
+                        self.write_buffer.clone(),
+                    )
+                    .with_peer_selector(self.peer_selector.clone())
+                    .with_availability_cache(self.availability_cache.clone())
+                    .with_query_limiter(self.query_limiter.clone()),
+                )
             }
         };
 
@@ -694,6 +905,79 @@ impl SyncService {
         Ok(())
     }
 
+    /// Pauses or resumes the segment downloads, of which the state is persisted first so that
+    /// the state is kept after restart.
+    async fn on_set_paused(&mut self, paused: bool) -> Result<()> {
+        pause::set_paused(&self.store, paused).await?;
+        if !self.disk_watchdog.set_paused_by_operator(paused) {
+            return Ok(());
+        }
+
+        if paused {
+            info!(num_syncing = %self.controllers.len(), "File sync paused by the operator");
+        } else {
+            info!(num_syncing = %self.controllers.len(), "File sync resumed by the operator");
+            for controller in self.controllers.values_mut() {
+                controller.on_download_resumed();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Syncs all chunks of a finalized file again, e.g. the local data is corrupted. Unlike file
+    /// sync, the file is not finalized again after all chunks are downloaded.
+    async fn on_repair_file(&mut self, tx_seq: u64) -> Result<()> {
+        if self.config.serve_only {
+            bail!("Disabled to repair file in serve-only mode");
+        }
+
+        info!(%tx_seq, "Start to repair file");
+
+        if let Some(controller) = self.controllers.get(&tx_seq) {
+            if !controller.is_completed_or_failed() {
+                bail!("File already in sync");
+            }
+        }
+
+        let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+            Some(tx) => tx,
+            None => bail!("Transaction not found"),
+        };
+
+        if !self.store.check_tx_completed(tx_seq).await? {
+            bail!("File not finalized");
+        }
+
+        if tx.size == 0 {
+            bail!("Empty file has no data to repair");
+        }
+
+        let num_chunks = match usize::try_from(tx.size) {
+            Ok(size) => bytes_to_chunks(size) as u64,
+            Err(_) => bail!("Unexpected transaction size"),
+        };
+
+        let mut controller = SerialSyncController::new(
+            self.config,
+            tx.id(),
+            tx.start_entry_index(),
+            FileSyncGoal::new(num_chunks, 0, num_chunks, false),
+            self.ctx.clone(),
+            self.store.clone(),
+            self.file_location_cache.clone(),
+            self.disk_watchdog.clone(),
+            self.write_buffer.clone(),
+        )
+        .with_peer_selector(self.peer_selector.clone())
+        .with_availability_cache(self.availability_cache.clone())
+        .with_query_limiter(self.query_limiter.clone());
+        controller.transition();
+        self.controllers.insert(tx_seq, controller);
+
+        Ok(())
+    }
+
     async fn on_announce_file_gossip(&mut self, tx_id: TxID, peer_id: PeerId, addr: Multiaddr) {
         let tx_seq = tx_id.seq;
         trace!(%tx_seq, %peer_id, %addr, "Received AnnounceFile gossip");
@@ -707,13 +991,14 @@ impl SyncService {
             let info = controller.get_sync_info();
             if info.goal.is_all_chunks() {
                 controller.on_peer_found(peer_id, addr);
+                Self::dial_advertised_peer(&mut self.eager_dials, controller, peer_id);
                 controller.transition();
             }
 
             return;
         }
 
-        if !self.config.sync_file_on_announcement_enabled {
+        if !self.config.sync_file_on_announcement_enabled || self.config.serve_only {
             return;
         }
 
@@ -747,15 +1032,39 @@ impl SyncService {
                 && info.goal.index_end == msg.index_end
             {
                 controller.on_peer_found(msg.peer_id.into(), msg.at.into());
+                Self::dial_advertised_peer(&mut self.eager_dials, controller, msg.peer_id.into());
                 controller.transition();
             }
         }
     }
 
+    /// Dials the peer that advertised the file in sync at once if the peer is not connected but
+    /// holds the segments not downloaded yet, bounded by `max_eager_dials_per_min`.
+    fn dial_advertised_peer(
+        eager_dials: &mut EagerDialLimiter,
+        controller: &mut SerialSyncController,
+        peer_id: PeerId,
+    ) {
+        if !eager_dials.is_enabled() || !controller.needs_found_peer(&peer_id) {
+            return;
+        }
+
+        if eager_dials.try_dial(peer_id) {
+            controller.dial_found_peer(peer_id);
+        } else {
+            debug!(%peer_id, "Eager dials rate limited");
+        }
+    }
+
     /// Handle on `NewFile` gossip message received.
     async fn on_new_file_gossip(&mut self, from: PeerId, file: ShardedFile) {
         debug!(%from, ?file, "Received NewFile gossip");
 
+        if let Ok(shard_config) = ShardConfig::try_from(file.shard_config) {
+            self.availability_cache
+                .insert(from, file.tx_id.seq, shard_config);
+        }
+
         if let Some(controller) = self.controllers.get_mut(&file.tx_id.seq) {
             // Notify new peer announced if file already in sync
             if let Ok(shard_config) = ShardConfig::try_from(file.shard_config) {
@@ -769,6 +1078,11 @@ impl SyncService {
 
     /// Handle on `AnswerFile` RPC message received.
     async fn on_answer_file(&mut self, from: PeerId, file: ShardedFile) {
+        if let Ok(shard_config) = ShardConfig::try_from(file.shard_config) {
+            self.availability_cache
+                .insert(from, file.tx_id.seq, shard_config);
+        }
+
         // Notify new peer announced if file already in sync
         if let Some(controller) = self.controllers.get_mut(&file.tx_id.seq) {
             if let Ok(shard_config) = ShardConfig::try_from(file.shard_config) {
@@ -811,6 +1125,8 @@ impl SyncService {
     }
 
     fn on_heartbeat(&mut self) {
+        self.heartbeat.beat();
+
         let mut completed = vec![];
         let mut incompleted = vec![];
 
@@ -836,7 +1152,26 @@ impl SyncService {
         }
     }
 
-    async fn tx_sync_start_index(store: &Store, tx: &Transaction) -> Result<Option<u64>> {
+    fn on_checkpoint(&mut self) {
+        let checkpoints = self
+            .controllers
+            .values()
+            .filter(|controller| !matches!(controller.get_status(), SyncState::Completed))
+            .filter_map(|controller| controller.checkpoint())
+            .collect();
+
+        if !self.checkpointer.checkpoint(checkpoints) {
+            debug!("Skip checkpoint since the last one is still being written");
+        }
+    }
+
+    /// Returns the first chunk to sync from, where the stored chunks are scanned from the
+    /// checkpoint `from`.
+    pub(crate) async fn tx_sync_start_index(
+        store: &Store,
+        tx: &Transaction,
+        from: u64,
+    ) -> Result<Option<u64>> {
         let shard_config = store.get_store().get_shard_config();
         let start_segment = sector_to_segment(tx.start_entry_index());
         let end =
@@ -846,6 +1181,9 @@ impl SyncService {
         } else {
             segment_to_sector(shard_config.next_segment_index(0, start_segment))
         };
+        if from as usize <= end {
+            start = cmp::max(start, from as usize);
+        }
         while start < end {
             if store
                 .get_chunks_by_tx_and_index_range(
@@ -885,6 +1223,7 @@ mod tests {
     use storage::log_store::log_manager::LogConfig;
     use storage::log_store::log_manager::LogManager;
     use storage::log_store::LogStoreRead;
+    use storage::log_store::LogStoreWrite;
     use storage::H256;
     use task_executor::test_utils::TestRuntime;
 
@@ -968,6 +1307,10 @@ mod tests {
                 self.network_send.clone(),
                 store,
                 self.file_location_cache.clone(),
+                Arc::new(DiskWatchdog::disabled()),
+                Arc::new(WorkerWatchdog::disabled()),
+                Default::default(),
+                Arc::new(DefaultPeerSelector),
                 self.event_send.subscribe(),
                 self.catch_up_end_recv.take().unwrap(),
             )
@@ -991,6 +1334,10 @@ mod tests {
         let (network_send, mut network_recv) = new_network_channel();
         let (_, sync_recv) = channel::Channel::unbounded("test");
 
+        let checkpointer = SyncCheckpointer::new(store.clone(), runtime.task_executor.clone(), 1)
+            .await
+            .unwrap();
+
         let mut sync = SyncService {
             config: Config::default(),
             msg_recv: sync_recv,
@@ -998,6 +1345,14 @@ mod tests {
             store,
             file_location_cache,
             controllers: Default::default(),
+            disk_watchdog: Arc::new(DiskWatchdog::disabled()),
+            write_buffer: WriteBuffer::unbounded(),
+            peer_selector: Arc::new(DefaultPeerSelector),
+            availability_cache: Default::default(),
+            eager_dials: EagerDialLimiter::new(0),
+            query_limiter: Default::default(),
+            checkpointer,
+            heartbeat: Default::default(),
             auto_sync_manager: None,
         };
 
@@ -1020,6 +1375,10 @@ mod tests {
         let (network_send, mut network_recv) = new_network_channel();
         let (_, sync_recv) = channel::Channel::unbounded("test");
 
+        let checkpointer = SyncCheckpointer::new(store.clone(), runtime.task_executor.clone(), 1)
+            .await
+            .unwrap();
+
         let mut sync = SyncService {
             config: Config::default(),
             msg_recv: sync_recv,
@@ -1027,6 +1386,14 @@ mod tests {
             store,
             file_location_cache,
             controllers: Default::default(),
+            disk_watchdog: Arc::new(DiskWatchdog::disabled()),
+            write_buffer: WriteBuffer::unbounded(),
+            peer_selector: Arc::new(DefaultPeerSelector),
+            availability_cache: Default::default(),
+            eager_dials: EagerDialLimiter::new(0),
+            query_limiter: Default::default(),
+            checkpointer,
+            heartbeat: Default::default(),
             auto_sync_manager: None,
         };
 
@@ -1345,6 +1712,10 @@ mod tests {
             network_send,
             store.clone(),
             file_location_cache,
+            Arc::new(DiskWatchdog::disabled()),
+            Arc::new(WorkerWatchdog::disabled()),
+            Default::default(),
+            Arc::new(DefaultPeerSelector),
             event_recv,
             catch_up_end_recv,
         )
@@ -1378,6 +1749,41 @@ mod tests {
         assert!(runtime.network_recv.try_recv().is_err());
     }
 
+    #[tokio::test]
+    async fn test_sync_file_uploader_not_allowlisted() {
+        let mut runtime = TestSyncRuntime::new(vec![1023, 1023], 2);
+        // tx 1 is submitted by an uploader not allowlisted during log sync
+        runtime
+            .store
+            .skip_tx(1, "uploader not allowlisted")
+            .unwrap();
+        let sync_send = runtime.spawn_sync_service(false).await;
+
+        for (tx_seq, allowlisted) in [(0u64, true), (1, false)] {
+            let err = match sync_send
+                .request(SyncRequest::SyncFile { tx_seq })
+                .await
+                .unwrap()
+            {
+                SyncResponse::SyncFile { err } => err,
+                _ => panic!("Invalid sync response type"),
+            };
+            assert_eq!(err.is_empty(), allowlisted);
+
+            let status = match sync_send
+                .request(SyncRequest::SyncStatus { tx_seq })
+                .await
+                .unwrap()
+            {
+                SyncResponse::SyncStatus { status } => status,
+                _ => panic!("Invalid sync response type"),
+            };
+            assert_eq!(status.is_some(), allowlisted);
+        }
+
+        assert!(!runtime.store.check_tx_completed(1).unwrap());
+    }
+
     async fn wait_for_tx_finalized(store: Arc<LogManager>, tx_seq: u64) {
         let deadline = Instant::now() + Duration::from_millis(5000);
         while !store.check_tx_completed(tx_seq).unwrap() {
@@ -1636,6 +2042,115 @@ mod tests {
         wait_for_tx_finalized(runtime.store, tx_seq).await;
     }
 
+    #[tokio::test]
+    async fn test_serve_only() {
+        let mut runtime = TestSyncRuntime::new(vec![1023], 0);
+        let config = Config {
+            serve_only: true,
+            sync_file_on_announcement_enabled: true,
+            ..Default::default()
+        };
+        let sync_send = runtime.spawn_sync_service_with_config(false, config).await;
+
+        let tx_seq = 0u64;
+        match sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap()
+        {
+            SyncResponse::SyncFile { err } => {
+                assert_eq!(err, "Disabled to sync file in serve-only mode")
+            }
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        match sync_send
+            .request(SyncRequest::RepairFile { tx_seq })
+            .await
+            .unwrap()
+        {
+            SyncResponse::SyncFile { err } => assert!(!err.is_empty()),
+            response => panic!("Unexpected response: {:?}", response),
+        }
+
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        sync_send
+            .notify(SyncMessage::AnnounceFileGossip {
+                tx_id: runtime.txs[tx_seq as usize].id(),
+                peer_id: runtime.init_peer_id,
+                addr: address,
+            })
+            .unwrap();
+
+        match sync_send.request(SyncRequest::SyncState).await.unwrap() {
+            SyncResponse::SyncState { state } => {
+                assert!(state.serve_only);
+                assert_eq!(state.num_syncing, 0);
+            }
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        assert!(runtime.network_recv.try_recv().is_err());
+        assert!(!runtime.store.check_tx_completed(tx_seq).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_pause_sync() {
+        let mut runtime = TestSyncRuntime::default();
+        let sync_send = runtime.spawn_sync_service(false).await;
+        let set_paused = |paused| {
+            let sync_send = sync_send.clone();
+            async move {
+                match sync_send
+                    .request(SyncRequest::SetPaused { paused })
+                    .await
+                    .unwrap()
+                {
+                    SyncResponse::SetPaused { err } => assert_eq!(err, ""),
+                    response => panic!("Unexpected response: {:?}", response),
+                }
+            }
+        };
+
+        set_paused(true).await;
+        let store = Store::new(runtime.store.clone(), runtime.runtime.task_executor.clone());
+        assert!(pause::is_paused(&store).await.unwrap());
+
+        let tx_seq = 0u64;
+        sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap();
+        receive_dial(&mut runtime, &sync_send).await;
+
+        // no segment is requested while paused
+        match sync_send.request(SyncRequest::SyncState).await.unwrap() {
+            SyncResponse::SyncState { state } => {
+                assert!(state.paused);
+                assert!(state.disk_space.download_paused);
+                assert_eq!(state.num_syncing, 1);
+            }
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(runtime.network_recv.try_recv().is_err());
+        assert!(!runtime.store.check_tx_completed(tx_seq).unwrap());
+
+        // downloads are resumed at once
+        set_paused(false).await;
+        assert!(!pause::is_paused(&store).await.unwrap());
+        receive_chunk_request(
+            &mut runtime.network_recv,
+            &sync_send,
+            runtime.peer_store.clone(),
+            runtime.init_peer_id,
+            tx_seq,
+            0,
+            runtime.chunk_count as u64,
+        )
+        .await;
+
+        wait_for_tx_finalized(runtime.store, tx_seq).await;
+    }
+
     #[tokio::test]
     async fn test_announce_file_in_sync() {
         let mut runtime = TestSyncRuntime::default();
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use std::future::Future;
+use tokio::task::JoinSet;
+
+/// Runs `f` on each of `items` with `concurrency` workers, where each worker handles every
+/// `concurrency`-th item in turn, e.g. to load or verify the files at startup on multiple
+/// cores.
+///
+/// Returns the results in the order of `items`, or the first error.
+pub async fn for_each_striped<T, R, F, Fut>(
+    items: Vec<T>,
+    concurrency: usize,
+    f: F,
+) -> Result<Vec<R>>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<R>> + Send + 'static,
+{
+    let total = items.len();
+    let concurrency = concurrency.clamp(1, total.max(1));
+
+    let mut stripes: Vec<Vec<(usize, T)>> = (0..concurrency).map(|_| vec![]).collect();
+    for (index, item) in items.into_iter().enumerate() {
+        stripes[index % concurrency].push((index, item));
+    }
+
+    let mut tasks = JoinSet::new();
+    for stripe in stripes {
+        let f = f.clone();
+        tasks.spawn(async move {
+            let mut results = Vec::with_capacity(stripe.len());
+            for (index, item) in stripe {
+                results.push((index, f(item).await?));
+            }
+            Ok::<_, anyhow::Error>(results)
+        });
+    }
+
+    let mut results = Vec::with_capacity(total);
+    while let Some(result) = tasks.join_next().await {
+        results.extend(result??);
+    }
+    results.sort_unstable_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::for_each_striped;
+    use anyhow::bail;
+
+    #[tokio::test]
+    async fn test_for_each_striped() {
+        for concurrency in [0, 1, 3, 100] {
+            let squares =
+                for_each_striped(
+                    (0..10u64).collect(),
+                    concurrency,
+                    |i| async move { Ok(i * i) },
+                )
+                .await
+                .unwrap();
+            assert_eq!(squares, (0..10u64).map(|i| i * i).collect::<Vec<_>>());
+        }
+
+        assert!(for_each_striped(vec![], 4, |i: u64| async move { Ok(i) })
+            .await
+            .unwrap()
+            .is_empty());
+
+        let failed = for_each_striped((0..10u64).collect(), 3, |i| async move {
+            if i == 7 {
+                bail!("failed at {}", i);
+            }
+            Ok(i)
+        })
+        .await;
+        assert!(failed.is_err());
+    }
+}
@@ -89,6 +89,7 @@ fn generate_data(
 
 #[cfg(test)]
 pub mod tests {
+    use super::create_2_store;
     use file_location_cache::{test_util::AnnounceFileBuilder, FileLocationCache};
     use libp2p::PeerId;
     use shared_types::TxID;
@@ -101,6 +102,19 @@ pub mod tests {
     use storage_async::Store;
     use task_executor::test_utils::TestRuntime;
 
+    /// Creates a store with finalized transactions of specified chunk count, of which the first
+    /// chunk of the transactions in `corrupt` is corrupted in the stored data.
+    pub fn create_store_with_corrupt_files(
+        chunk_count: Vec<usize>,
+        corrupt: &[u64],
+    ) -> Arc<LogManager> {
+        let (_, peer_store, txs, _) = create_2_store(chunk_count);
+        for tx in txs.iter().filter(|tx| corrupt.contains(&tx.seq)) {
+            peer_store.corrupt_entry(tx.start_entry_index).unwrap();
+        }
+        peer_store
+    }
+
     pub struct TestStoreRuntime {
         pub runtime: TestRuntime,
         pub store: Store,
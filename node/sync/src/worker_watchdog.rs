@@ -0,0 +1,267 @@
+//! Detects long-lived worker tasks that stop making progress, e.g. panicked or deadlocked,
+//! while the node still appears alive.
+//!
+//! Every worker reports a heartbeat whenever it makes progress. A supervised worker is aborted
+//! and restarted if no heartbeat is reported within the stall timeout, or once it panics or
+//! exits, and a fatal error is logged after too many restarts. Other workers, which cannot be
+//! restarted in place, are only monitored and a fatal error is logged once stalled.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use task_executor::TaskExecutor;
+
+/// Reported by a worker whenever it makes progress.
+#[derive(Clone, Debug, Default)]
+pub struct Heartbeat(Arc<AtomicU64>);
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        self.0.store(now_millis(), Ordering::Relaxed);
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::from_millis(now_millis().saturating_sub(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerHeartbeat {
+    /// Unix timestamp in milliseconds of the last heartbeat.
+    pub last_heartbeat: u64,
+    pub restarts: usize,
+    pub supervised: bool,
+    /// Whether the worker is stalled and gave up.
+    pub stalled: bool,
+}
+
+struct Worker {
+    heartbeat: Heartbeat,
+    restarts: usize,
+    supervised: bool,
+    stalled: bool,
+}
+
+pub struct WorkerWatchdog {
+    /// Feature is disabled if 0.
+    stall_timeout: Duration,
+    max_restarts: usize,
+    workers: Mutex<BTreeMap<&'static str, Worker>>,
+}
+
+impl WorkerWatchdog {
+    pub fn new(stall_timeout: Duration, max_restarts: usize) -> Self {
+        Self {
+            stall_timeout,
+            max_restarts,
+            workers: Default::default(),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(Duration::ZERO, 0)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.stall_timeout.is_zero()
+    }
+
+    pub fn heartbeats(&self) -> BTreeMap<String, WorkerHeartbeat> {
+        self.workers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, worker)| {
+                let heartbeat = WorkerHeartbeat {
+                    last_heartbeat: worker.heartbeat.0.load(Ordering::Relaxed),
+                    restarts: worker.restarts,
+                    supervised: worker.supervised,
+                    stalled: worker.stalled,
+                };
+                (name.to_string(), heartbeat)
+            })
+            .collect()
+    }
+
+    /// Monitors a worker that cannot be restarted, and returns the heartbeat to report.
+    pub fn monitor(self: &Arc<Self>, executor: &TaskExecutor, name: &'static str) -> Heartbeat {
+        let heartbeat = self.add_worker(name, false);
+        if !self.is_enabled() {
+            return heartbeat;
+        }
+
+        let watchdog = self.clone();
+        let worker_heartbeat = heartbeat.clone();
+        executor.spawn(
+            async move {
+                let mut check = tokio::time::interval(watchdog.check_interval());
+                loop {
+                    check.tick().await;
+                    if worker_heartbeat.elapsed() > watchdog.stall_timeout {
+                        watchdog.set_stalled(name);
+                        error!(
+                            worker = name,
+                            timeout = ?watchdog.stall_timeout,
+                            "Worker stalled without progress, please restart the node"
+                        );
+                        return;
+                    }
+                }
+            },
+            "worker_watchdog",
+        );
+
+        heartbeat
+    }
+
+    /// Spawns a worker created by `factory`, and restarts it if it stalls, panics or exits.
+    pub fn spawn_supervised<F, Fut>(
+        self: &Arc<Self>,
+        executor: &TaskExecutor,
+        name: &'static str,
+        factory: F,
+    ) where
+        F: Fn(Heartbeat) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let heartbeat = self.add_worker(name, true);
+        if !self.is_enabled() {
+            executor.spawn(factory(heartbeat), name);
+            return;
+        }
+
+        let watchdog = self.clone();
+        let worker_executor = executor.clone();
+        executor.spawn(
+            async move {
+                let mut check = tokio::time::interval(watchdog.check_interval());
+                loop {
+                    heartbeat.beat();
+                    let mut handle =
+                        match worker_executor.spawn_handle(factory(heartbeat.clone()), name) {
+                            Some(handle) => handle,
+                            None => return,
+                        };
+
+                    let reason = loop {
+                        tokio::select! {
+                            result = &mut handle => match result {
+                                // shutdown
+                                Ok(None) => return,
+                                Ok(Some(())) => break "exited",
+                                Err(e) if e.is_panic() => break "panicked",
+                                Err(_) => return,
+                            },
+                            _ = check.tick() => {
+                                if heartbeat.elapsed() > watchdog.stall_timeout {
+                                    handle.abort();
+                                    break "stalled";
+                                }
+                            }
+                        }
+                    };
+
+                    if !watchdog.on_restart(name) {
+                        error!(
+                            worker = name,
+                            reason,
+                            max_restarts = watchdog.max_restarts,
+                            "Worker restarted too many times, please restart the node"
+                        );
+                        return;
+                    }
+                    warn!(worker = name, reason, "Restart worker");
+                }
+            },
+            "worker_watchdog",
+        );
+    }
+
+    fn check_interval(&self) -> Duration {
+        (self.stall_timeout / 4).max(Duration::from_millis(10))
+    }
+
+    fn add_worker(&self, name: &'static str, supervised: bool) -> Heartbeat {
+        let heartbeat = Heartbeat::default();
+        heartbeat.beat();
+        self.workers.lock().unwrap().insert(
+            name,
+            Worker {
+                heartbeat: heartbeat.clone(),
+                restarts: 0,
+                supervised,
+                stalled: false,
+            },
+        );
+        heartbeat
+    }
+
+    fn set_stalled(&self, name: &'static str) {
+        if let Some(worker) = self.workers.lock().unwrap().get_mut(name) {
+            worker.stalled = true;
+        }
+    }
+
+    /// Returns `false` if the worker should not be restarted anymore.
+    fn on_restart(&self, name: &'static str) -> bool {
+        let mut workers = self.workers.lock().unwrap();
+        let worker = match workers.get_mut(name) {
+            Some(worker) => worker,
+            None => return false,
+        };
+        if worker.restarts >= self.max_restarts {
+            worker.stalled = true;
+            return false;
+        }
+        worker.restarts += 1;
+        true
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorkerWatchdog;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use task_executor::test_utils::TestRuntime;
+
+    #[tokio::test]
+    async fn test_restart_stalled_worker() {
+        let runtime = TestRuntime::default();
+        let watchdog = Arc::new(WorkerWatchdog::new(Duration::from_millis(100), 2));
+        let starts = Arc::new(AtomicUsize::new(0));
+
+        let worker_starts = starts.clone();
+        watchdog.spawn_supervised(&runtime.task_executor, "stalled", move |_| {
+            worker_starts.fetch_add(1, Ordering::SeqCst);
+            // never reports heartbeat
+            std::future::pending()
+        });
+        let beating = watchdog.monitor(&runtime.task_executor, "beating");
+
+        for _ in 0..50 {
+            beating.beat();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        // restarts are bounded
+        assert_eq!(starts.load(Ordering::SeqCst), 3);
+        let heartbeats = watchdog.heartbeats();
+        assert_eq!(heartbeats["stalled"].restarts, 2);
+        assert!(heartbeats["stalled"].stalled);
+        assert!(!heartbeats["beating"].stalled);
+    }
+}